@@ -0,0 +1,139 @@
+//
+// Benchmarks of the work done for every block on the consensus path, for
+// blocks of 1k, 10k and 100k transactions. Run them with
+//
+//   cargo bench --bench consensus
+//
+// and compare against a saved baseline with --save-baseline and --baseline
+// to see whether a change makes these paths slower.
+//
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use saito_rust::block::{Block, BlockType};
+use saito_rust::crypto::HashAlgorithm;
+use saito_rust::test_utilities::benchmarks::{
+    create_block_with_transactions, BENCHMARK_TRANSACTION_COUNTS,
+};
+
+fn bench_consensus(c: &mut Criterion) {
+    let blocks: Vec<Block> = BENCHMARK_TRANSACTION_COUNTS
+        .iter()
+        .map(|transaction_count| create_block_with_transactions(*transaction_count))
+        .collect();
+
+    let mut group = c.benchmark_group("block");
+    group.sample_size(10);
+    for block in blocks.iter() {
+        let transaction_count = block.get_transactions().len();
+        group.throughput(Throughput::Elements(transaction_count as u64));
+
+        group.bench_with_input(
+            BenchmarkId::new("serialize_for_net", transaction_count),
+            block,
+            |b, block| b.iter(|| block.serialize_for_net(BlockType::Full)),
+        );
+
+        let serialized_block = block.serialize_for_net(BlockType::Full);
+        group.bench_with_input(
+            BenchmarkId::new("deserialize_for_net", transaction_count),
+            &serialized_block,
+            |b, serialized_block| b.iter(|| Block::deserialize_for_net(serialized_block)),
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("generate_merkle_root", transaction_count),
+            block,
+            |b, block| b.iter(|| block.generate_merkle_root()),
+        );
+
+        //
+        // the hashes for signature and fees of the transactions, which
+        // validation checks the block against
+        //
+        group.bench_with_input(
+            BenchmarkId::new("generate_metadata", transaction_count),
+            block,
+            |b, block| {
+                b.iter_batched(
+                    || block.clone(),
+                    |mut block| block.generate_metadata(),
+                    criterion::BatchSize::LargeInput,
+                )
+            },
+        );
+
+        // the signature of the creator and those of all transactions
+        group.bench_with_input(
+            BenchmarkId::new("prevalidate", transaction_count),
+            block,
+            |b, block| {
+                b.iter_batched(
+                    || block.clone(),
+                    |mut block| block.prevalidate(),
+                    criterion::BatchSize::LargeInput,
+                )
+            },
+        );
+    }
+    group.finish();
+}
+
+//
+// The hashing a block producer does with the algorithm of the block, with
+// each algorithm, to tell how much of block production is down to the hash
+// function. Test networks can run SHA-256 end to end with the
+// experimental-sha256-blocks feature.
+//
+fn bench_hash_algorithms(c: &mut Criterion) {
+    let blocks: Vec<Block> = BENCHMARK_TRANSACTION_COUNTS
+        .iter()
+        .map(|transaction_count| create_block_with_transactions(*transaction_count))
+        .collect();
+
+    let mut group = c.benchmark_group("hash_algorithm");
+    group.sample_size(10);
+    for block in blocks.iter() {
+        let transaction_count = block.get_transactions().len();
+        group.throughput(Throughput::Elements(transaction_count as u64));
+
+        for hash_algorithm in [HashAlgorithm::Blake3, HashAlgorithm::Sha256] {
+            let mut block = block.clone();
+            block.set_hash_algorithm(hash_algorithm);
+
+            group.bench_with_input(
+                BenchmarkId::new(
+                    format!("generate_merkle_root/{:?}", hash_algorithm),
+                    transaction_count,
+                ),
+                &block,
+                |b, block| b.iter(|| block.generate_merkle_root()),
+            );
+
+            // as if every transaction of the block were rebroadcast
+            group.bench_with_input(
+                BenchmarkId::new(
+                    format!("chain_rebroadcast_hash/{:?}", hash_algorithm),
+                    transaction_count,
+                ),
+                &block,
+                |b, block| {
+                    b.iter(|| {
+                        block.get_transactions().iter().fold(
+                            [0; 32],
+                            |rebroadcast_hash, transaction| {
+                                Block::chain_rebroadcast_hash(
+                                    hash_algorithm,
+                                    rebroadcast_hash,
+                                    transaction,
+                                )
+                            },
+                        )
+                    })
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_consensus, bench_hash_algorithms);
+criterion_main!(benches);
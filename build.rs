@@ -0,0 +1,8 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    //
+    // the gRPC service is generated from proto/saito.proto, see networking::grpc
+    //
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/saito.proto")?;
+    Ok(())
+}
@@ -37,6 +37,81 @@ pub fn try_from_byte(input: TokenStream) -> TokenStream {
     output.into()
 }
 ///
+/// A derive macro for an enum of APIMessage names. Each variant gives its 8 byte
+/// name on the wire in a message_name attribute, and its doc comment is kept as
+/// its description so that the protocol documentation can be generated from it.
+///
+/// Implements ALL, as_bytes(), from_bytes() and get_description() for the enum.
+///
+/// usage:
+/// ```rust
+/// #[derive(MessageNames)]
+/// pub enum MessageName {
+///     /// A response to a request.
+///     #[message_name = "RESULT__"]
+///     Result,
+/// }
+/// ```
+///
+#[proc_macro_derive(MessageNames, attributes(message_name))]
+pub fn message_names(input: TokenStream) -> TokenStream {
+    let DeriveInput { ident, data, .. } = parse_macro_input!(input);
+    let variants = match data {
+        syn::Data::Enum(enum_item) => enum_item.variants,
+        _ => panic!("MessageNames only works on Enums"),
+    };
+    let mut paths = vec![];
+    let mut names = vec![];
+    let mut descriptions = vec![];
+    for variant in variants {
+        let mut name = None;
+        let mut description: Vec<String> = vec![];
+        for attr in &variant.attrs {
+            if let Ok(syn::Meta::NameValue(meta)) = attr.parse_meta() {
+                if let syn::Lit::Str(lit) = meta.lit {
+                    if meta.path.is_ident("message_name") {
+                        name = Some(lit.value());
+                    } else if meta.path.is_ident("doc") {
+                        description.push(lit.value().trim().to_string());
+                    }
+                }
+            }
+        }
+        let variant_ident = variant.ident;
+        let name = name
+            .unwrap_or_else(|| panic!("{} needs a message_name attribute", variant_ident));
+        if name.len() != 8 {
+            panic!("message name {} is not 8 bytes", name);
+        }
+        paths.push(quote! { #ident::#variant_ident });
+        names.push(syn::LitByteStr::new(
+            name.as_bytes(),
+            proc_macro2::Span::call_site(),
+        ));
+        descriptions.push(description.join(" "));
+    }
+    let output = quote! {
+        impl #ident {
+            pub const ALL: &'static [#ident] = &[#(#paths),*];
+            pub fn as_bytes(&self) -> &'static [u8; 8] {
+                match self {
+                    #(#paths => #names),*
+                }
+            }
+            pub fn from_bytes(bytes: &[u8; 8]) -> Option<#ident> {
+                #(if bytes == #names { return Some(#paths); })*
+                None
+            }
+            pub fn get_description(&self) -> &'static str {
+                match self {
+                    #(#paths => #descriptions),*
+                }
+            }
+        }
+    };
+    output.into()
+}
+///
 /// A derive macro which implments Persistable save/load functions.
 ///
 /// optional attribute persist_with_name can be added to specify a
@@ -0,0 +1,148 @@
+use crate::crypto::SaitoPublicKey;
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+
+//
+// Publickeys are 33 bytes, which nobody remembers or reads. The address
+// book of a wallet gives the keys it pays and is paid by labels of the
+// user's choosing, e.g. "alice" or "exchange", which are shown in place of
+// the keys in the history of the wallet. Every key has at most one label,
+// so that the history shows the same label for it every time.
+//
+
+/// Labels are at most this many bytes of UTF-8.
+pub const MAX_LABEL_SIZE: usize = 255;
+
+/// The labels of publickeys, saved in the wallet.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AddressBook {
+    entries: BTreeMap<String, SaitoPublicKey>,
+}
+
+impl AddressBook {
+    pub fn new() -> Self {
+        AddressBook::default()
+    }
+
+    /// Labels the publickey, replacing what the label was given to before. Fails if the label
+    /// is empty or too long, or if the publickey has another label.
+    pub fn add(&mut self, label: &str, publickey: SaitoPublicKey) -> crate::Result<()> {
+        if label.is_empty() || label.len() > MAX_LABEL_SIZE {
+            return Err(format!(
+                "labels are 1 to {} bytes long, {} is {}",
+                MAX_LABEL_SIZE,
+                label,
+                label.len()
+            )
+            .into());
+        }
+        if let Some(other_label) = self.get_label(&publickey) {
+            if other_label != label {
+                return Err(format!("the publickey is labelled {} already", other_label).into());
+            }
+        }
+        self.entries.insert(label.to_string(), publickey);
+        Ok(())
+    }
+
+    /// Removes the label, returning the publickey it was given to.
+    pub fn remove(&mut self, label: &str) -> Option<SaitoPublicKey> {
+        self.entries.remove(label)
+    }
+
+    pub fn get_publickey(&self, label: &str) -> Option<SaitoPublicKey> {
+        self.entries.get(label).copied()
+    }
+
+    pub fn get_label(&self, publickey: &SaitoPublicKey) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(_, entry_publickey)| *entry_publickey == publickey)
+            .map(|(label, _)| label.as_str())
+    }
+
+    /// The labels and their publickeys, ordered by label.
+    pub fn get_entries(&self) -> Vec<(&str, SaitoPublicKey)> {
+        self.entries
+            .iter()
+            .map(|(label, publickey)| (label.as_str(), *publickey))
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// [entries count - 4 bytes]
+    /// for every entry:
+    /// [label size - 1 byte]
+    /// [label - label size bytes]
+    /// [publickey - 33 bytes]
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut vbytes: Vec<u8> = vec![];
+        vbytes.extend(&(self.entries.len() as u32).to_be_bytes());
+        for (label, publickey) in self.entries.iter() {
+            vbytes.push(label.len() as u8);
+            vbytes.extend(label.as_bytes());
+            vbytes.extend(publickey);
+        }
+        vbytes
+    }
+
+    /// None if the bytes are not an address book.
+    pub fn deserialize(bytes: &[u8]) -> Option<Self> {
+        let entries_count = u32::from_be_bytes(bytes.get(0..4)?.try_into().ok()?) as usize;
+        let mut address_book = AddressBook::new();
+        let mut start = 4;
+        for _ in 0..entries_count {
+            let label_size = *bytes.get(start)? as usize;
+            let label = std::str::from_utf8(bytes.get(start + 1..start + 1 + label_size)?).ok()?;
+            start += 1 + label_size;
+            let publickey: SaitoPublicKey = bytes.get(start..start + 33)?.try_into().ok()?;
+            start += 33;
+            address_book.add(label, publickey).ok()?;
+        }
+        if start != bytes.len() {
+            return None;
+        }
+        Some(address_book)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn address_book_test() {
+        let mut address_book = AddressBook::new();
+        address_book.add("bob", [2; 33]).unwrap();
+        address_book.add("alice", [1; 33]).unwrap();
+        assert_eq!(address_book.get_publickey("alice"), Some([1; 33]));
+        assert_eq!(address_book.get_label(&[2; 33]), Some("bob"));
+        assert_eq!(address_book.get_label(&[3; 33]), None);
+        assert_eq!(
+            address_book.get_entries(),
+            vec![("alice", [1; 33]), ("bob", [2; 33])]
+        );
+
+        // a key has one label, and a label is given to one key
+        assert!(address_book.add("robert", [2; 33]).is_err());
+        address_book.add("bob", [3; 33]).unwrap();
+        assert_eq!(address_book.get_label(&[2; 33]), None);
+        assert!(address_book.add("", [4; 33]).is_err());
+        assert!(address_book.add(&"a".repeat(256), [4; 33]).is_err());
+
+        let bytes = address_book.serialize();
+        assert_eq!(AddressBook::deserialize(&bytes), Some(address_book.clone()));
+        assert_eq!(AddressBook::deserialize(&bytes[..bytes.len() - 1]), None);
+
+        assert_eq!(address_book.remove("alice"), Some([1; 33]));
+        assert_eq!(address_book.remove("alice"), None);
+        assert_eq!(address_book.len(), 1);
+    }
+}
@@ -0,0 +1,186 @@
+use crate::configuration::AdmissionSettings;
+use crate::crypto::SaitoPublicKey;
+use crate::transaction::Transaction;
+use ahash::AHashMap;
+use std::fmt::Debug;
+
+//
+// Every transaction a routing node takes into its mempool costs it memory,
+// bandwidth to relay it and space in the blocks it produces, and is paid
+// for only if the transaction ends up in a block. Operators decide what
+// traffic they carry with admission policies, which the mempool consults
+// before taking a transaction. Golden tickets pay no fees but are needed
+// to produce blocks, so the mempool admits them without asking.
+//
+
+/// Decides whether the mempool takes a transaction. Applications filter the transactions they
+/// carry by implementing this for their own policies, and adding them to the mempool.
+pub trait AdmissionPolicy: Debug + Send + Sync {
+    /// Ok if the transaction may be added to the mempool at current_timestamp, or else why
+    /// not. The metadata of the transaction has been generated.
+    fn admit(&mut self, transaction: &Transaction, current_timestamp: u64) -> Result<(), String>;
+}
+
+/// Rejects transactions paying fewer nolan per byte than the fee rate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MinimumFeeRate {
+    fee_rate: u64,
+}
+
+impl MinimumFeeRate {
+    pub fn new(fee_rate: u64) -> Self {
+        MinimumFeeRate { fee_rate }
+    }
+}
+
+impl AdmissionPolicy for MinimumFeeRate {
+    fn admit(&mut self, transaction: &Transaction, _current_timestamp: u64) -> Result<(), String> {
+        if transaction.get_fee_rate() < self.fee_rate {
+            return Err(format!(
+                "fee rate {} is below {}",
+                transaction.get_fee_rate(),
+                self.fee_rate
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Rejects transactions whose message is longer than the size, in bytes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaximumMessageSize {
+    size: usize,
+}
+
+impl MaximumMessageSize {
+    pub fn new(size: usize) -> Self {
+        MaximumMessageSize { size }
+    }
+}
+
+impl AdmissionPolicy for MaximumMessageSize {
+    fn admit(&mut self, transaction: &Transaction, _current_timestamp: u64) -> Result<(), String> {
+        if transaction.get_message().len() > self.size {
+            return Err(format!(
+                "message of {} bytes is larger than {}",
+                transaction.get_message().len(),
+                self.size
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Admits at most max_transactions from every address in each window of the given number of
+/// milliseconds. The address of a transaction is the publickey of its first input, and
+/// transactions without inputs are not limited.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AddressRateLimit {
+    max_transactions: u64,
+    window: u64,
+    // the window the counts are for, counted from the epoch
+    current_window: u64,
+    transaction_counts: AHashMap<SaitoPublicKey, u64>,
+}
+
+impl AddressRateLimit {
+    pub fn new(max_transactions: u64, window: u64) -> Self {
+        AddressRateLimit {
+            max_transactions,
+            window: window.max(1),
+            current_window: 0,
+            transaction_counts: AHashMap::new(),
+        }
+    }
+}
+
+impl AdmissionPolicy for AddressRateLimit {
+    fn admit(&mut self, transaction: &Transaction, current_timestamp: u64) -> Result<(), String> {
+        let publickey = match transaction.get_sender() {
+            Some(publickey) => publickey,
+            None => return Ok(()),
+        };
+        let window = current_timestamp / self.window;
+        if window != self.current_window {
+            self.current_window = window;
+            self.transaction_counts.clear();
+        }
+        let transaction_count = self.transaction_counts.entry(publickey).or_insert(0);
+        if *transaction_count >= self.max_transactions {
+            return Err(format!(
+                "address sent {} transactions in the last {} ms",
+                transaction_count, self.window
+            ));
+        }
+        *transaction_count += 1;
+        Ok(())
+    }
+}
+
+/// The policies turned on in the settings. The rate limit comes last, so that it only counts
+/// the transactions the other policies admit.
+pub fn admission_policies_from_settings(
+    admission_settings: &AdmissionSettings,
+) -> Vec<Box<dyn AdmissionPolicy>> {
+    let mut admission_policies: Vec<Box<dyn AdmissionPolicy>> = vec![];
+    if admission_settings.min_fee_rate > 0 {
+        admission_policies.push(Box::new(MinimumFeeRate::new(
+            admission_settings.min_fee_rate,
+        )));
+    }
+    if admission_settings.max_message_size > 0 {
+        admission_policies.push(Box::new(MaximumMessageSize::new(
+            admission_settings.max_message_size,
+        )));
+    }
+    if admission_settings.max_transactions_per_address > 0 {
+        admission_policies.push(Box::new(AddressRateLimit::new(
+            admission_settings.max_transactions_per_address,
+            admission_settings.rate_limit_window,
+        )));
+    }
+    admission_policies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::slip::Slip;
+
+    #[test]
+    fn admission_policy_test() {
+        let mut transaction = Transaction::new();
+        let mut input = Slip::new();
+        input.set_publickey([1; 33]);
+        transaction.add_input(input);
+        transaction.set_message(vec![0; 100]);
+
+        let mut maximum_message_size = MaximumMessageSize::new(100);
+        assert!(maximum_message_size.admit(&transaction, 0).is_ok());
+        let mut maximum_message_size = MaximumMessageSize::new(99);
+        assert!(maximum_message_size.admit(&transaction, 0).is_err());
+
+        // a transaction without fees is below any fee rate
+        assert!(MinimumFeeRate::new(0).admit(&transaction, 0).is_ok());
+        assert!(MinimumFeeRate::new(1).admit(&transaction, 0).is_err());
+
+        let mut address_rate_limit = AddressRateLimit::new(2, 1000);
+        assert!(address_rate_limit.admit(&transaction, 1000).is_ok());
+        assert!(address_rate_limit.admit(&transaction, 1500).is_ok());
+        assert!(address_rate_limit.admit(&transaction, 1999).is_err());
+        // other addresses have their own limit
+        let mut other_transaction = transaction.clone();
+        other_transaction.inputs[0].set_publickey([2; 33]);
+        assert!(address_rate_limit.admit(&other_transaction, 1999).is_ok());
+        // and the limit starts over with the next window
+        assert!(address_rate_limit.admit(&transaction, 2000).is_ok());
+
+        let admission_policies = admission_policies_from_settings(&AdmissionSettings {
+            min_fee_rate: 0,
+            max_message_size: 1000,
+            max_transactions_per_address: 10,
+            rate_limit_window: 1000,
+        });
+        assert_eq!(admission_policies.len(), 2);
+    }
+}
@@ -0,0 +1,201 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::iter::Sum;
+use std::str::FromStr;
+
+/// the number of nolan in one SAITO
+pub const NOLAN_PER_SAITO: u64 = 100_000_000;
+/// the number of decimal places in a SAITO amount
+pub const SAITO_DECIMALS: usize = 8;
+
+/// An amount of SAITO, counted in nolan. Fees, payouts and the treasury are summed with
+/// the checked operations here so that an overflow is caught instead of silently wrapping.
+///
+/// Amounts are displayed in SAITO, e.g. "1.5 SAITO", and parse from either SAITO or nolan,
+/// e.g. "1.5", "1.5 SAITO" or "150000000 nolan".
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+pub struct Amount(u64);
+
+impl Amount {
+    pub const ZERO: Amount = Amount(0);
+    pub const MAX: Amount = Amount(u64::MAX);
+
+    pub fn from_nolan(nolan: u64) -> Amount {
+        Amount(nolan)
+    }
+
+    pub fn from_saito(saito: u64) -> Option<Amount> {
+        saito.checked_mul(NOLAN_PER_SAITO).map(Amount)
+    }
+
+    pub fn as_nolan(&self) -> u64 {
+        self.0
+    }
+
+    pub fn checked_add(self, other: Amount) -> Option<Amount> {
+        self.0.checked_add(other.0).map(Amount)
+    }
+
+    pub fn checked_sub(self, other: Amount) -> Option<Amount> {
+        self.0.checked_sub(other.0).map(Amount)
+    }
+
+    pub fn checked_mul(self, factor: u64) -> Option<Amount> {
+        self.0.checked_mul(factor).map(Amount)
+    }
+
+    pub fn checked_div(self, divisor: u64) -> Option<Amount> {
+        self.0.checked_div(divisor).map(Amount)
+    }
+
+    pub fn saturating_add(self, other: Amount) -> Amount {
+        Amount(self.0.saturating_add(other.0))
+    }
+
+    pub fn saturating_sub(self, other: Amount) -> Amount {
+        Amount(self.0.saturating_sub(other.0))
+    }
+
+    /// Adds up the amounts, returning None if the total does not fit in a u64.
+    pub fn checked_sum<I: IntoIterator<Item = Amount>>(amounts: I) -> Option<Amount> {
+        amounts
+            .into_iter()
+            .try_fold(Amount::ZERO, |total, amount| total.checked_add(amount))
+    }
+
+    /// Splits the amount in two, the first half rounded down and the second holding the rest.
+    pub fn split_in_half(self) -> (Amount, Amount) {
+        let first_half = Amount(self.0 / 2);
+        (first_half, Amount(self.0 - first_half.0))
+    }
+
+    /// Formats the amount in SAITO without the unit, e.g. "1.5", which parses back.
+    pub fn to_saito_string(&self) -> String {
+        let saito = self.0 / NOLAN_PER_SAITO;
+        let nolan = self.0 % NOLAN_PER_SAITO;
+        if nolan == 0 {
+            format!("{}", saito)
+        } else {
+            let decimals = format!("{:0width$}", nolan, width = SAITO_DECIMALS);
+            format!("{}.{}", saito, decimals.trim_end_matches('0'))
+        }
+    }
+
+    /// Formats the amount in nolan, e.g. "150000000 nolan".
+    pub fn to_nolan_string(&self) -> String {
+        format!("{} nolan", self.0)
+    }
+}
+
+impl From<u64> for Amount {
+    fn from(nolan: u64) -> Self {
+        Amount(nolan)
+    }
+}
+
+impl From<Amount> for u64 {
+    fn from(amount: Amount) -> Self {
+        amount.0
+    }
+}
+
+/// Sums saturate at Amount::MAX. Use Amount::checked_sum where an overflow must be caught.
+impl Sum for Amount {
+    fn sum<I: Iterator<Item = Amount>>(amounts: I) -> Self {
+        amounts.fold(Amount::ZERO, |total, amount| total.saturating_add(amount))
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} SAITO", self.to_saito_string())
+    }
+}
+
+impl FromStr for Amount {
+    type Err = crate::Error;
+
+    fn from_str(amount: &str) -> crate::Result<Amount> {
+        let amount = amount.trim();
+        if let Some(nolan) = amount.strip_suffix("nolan") {
+            return Ok(Amount(nolan.trim().parse::<u64>()?));
+        }
+        let saito = amount.strip_suffix("SAITO").unwrap_or(amount).trim();
+        let (whole, decimals) = saito.split_once('.').unwrap_or((saito, ""));
+        if decimals.len() > SAITO_DECIMALS || !decimals.chars().all(|c| c.is_ascii_digit()) {
+            return Err(format!("invalid SAITO amount {}", amount).into());
+        }
+        let nolan = if decimals.is_empty() {
+            0
+        } else {
+            format!("{:0<width$}", decimals, width = SAITO_DECIMALS).parse::<u64>()?
+        };
+        Amount::from_saito(whole.parse::<u64>()?)
+            .and_then(|whole| whole.checked_add(Amount(nolan)))
+            .ok_or_else(|| format!("SAITO amount {} is too large", amount).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn amount_checked_arithmetic_test() {
+        let amount = Amount::from_nolan(u64::MAX - 1);
+        assert_eq!(amount.checked_add(Amount::from_nolan(1)), Some(Amount::MAX));
+        assert_eq!(amount.checked_add(Amount::from_nolan(2)), None);
+        assert_eq!(Amount::ZERO.checked_sub(Amount::from_nolan(1)), None);
+        assert_eq!(amount.checked_mul(2), None);
+        assert_eq!(Amount::from_saito(u64::MAX), None);
+        assert_eq!(
+            Amount::checked_sum(vec![Amount::from_nolan(1), Amount::from_nolan(2)]),
+            Some(Amount::from_nolan(3))
+        );
+        assert_eq!(Amount::checked_sum(vec![amount, amount]), None);
+        assert_eq!(
+            vec![amount, amount].into_iter().sum::<Amount>(),
+            Amount::MAX
+        );
+        assert_eq!(
+            Amount::from_nolan(5).split_in_half(),
+            (Amount::from_nolan(2), Amount::from_nolan(3))
+        );
+    }
+
+    #[test]
+    fn amount_display_and_parse_test() {
+        assert_eq!(Amount::from_nolan(150_000_000).to_string(), "1.5 SAITO");
+        assert_eq!(Amount::from_nolan(200_000_000).to_string(), "2 SAITO");
+        assert_eq!(Amount::from_nolan(1).to_string(), "0.00000001 SAITO");
+        assert_eq!(Amount::from_nolan(1).to_nolan_string(), "1 nolan");
+
+        assert_eq!(
+            "1.5".parse::<Amount>().unwrap(),
+            Amount::from_nolan(150_000_000)
+        );
+        assert_eq!(
+            "1.5 SAITO".parse::<Amount>().unwrap(),
+            Amount::from_nolan(150_000_000)
+        );
+        assert_eq!(
+            "0.00000001".parse::<Amount>().unwrap(),
+            Amount::from_nolan(1)
+        );
+        assert_eq!(
+            "150 nolan".parse::<Amount>().unwrap(),
+            Amount::from_nolan(150)
+        );
+        assert!("0.000000001".parse::<Amount>().is_err());
+        assert!("1.-5".parse::<Amount>().is_err());
+        assert!("abc".parse::<Amount>().is_err());
+        assert!("184467440738".parse::<Amount>().is_err());
+
+        for nolan in [0, 1, 99_999_999, 100_000_000, 123_456_789_012] {
+            let amount = Amount::from_nolan(nolan);
+            assert_eq!(amount.to_string().parse::<Amount>().unwrap(), amount);
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use crate::consensus::{recv_message, SaitoMessage, Subsystem};
+use crate::mempool::Mempool;
+use crate::wallet::Wallet;
+use base58::ToBase58;
+use log::info;
+use std::sync::Arc;
+use tokio::sync::{broadcast, RwLock};
+
+//
+// An undelegated stake compounds by itself, as the StakerOutput paying its
+// staker carries the stake and the return on it back into the staking
+// table. The return on a delegated stake is paid out as a spendable
+// StakerPayout instead. A wallet with an auto-compound threshold stakes
+// those payouts again: whenever the longest chain moves, the task below
+// asks the wallet for a StakerDeposit of the payouts confirmed to it, which
+// it only creates once they add up to more than the threshold.
+//
+
+/// Deposits the payouts of the wallet once they are above its threshold, and adds the
+/// deposit to the mempool. Returns whether a deposit was made.
+pub async fn compound(
+    wallet_lock: &Arc<RwLock<Wallet>>,
+    mempool_lock: &Arc<RwLock<Mempool>>,
+) -> bool {
+    let transaction = {
+        let mut wallet = wallet_lock.write().await;
+        match wallet.create_auto_compound_transaction() {
+            Some(transaction) => {
+                info!(
+                    "staking {} of payouts again for {}",
+                    transaction.get_outputs()[0].get_amount().to_nolan_string(),
+                    wallet.get_publickey().to_base58()
+                );
+                transaction
+            }
+            None => return false,
+        }
+    };
+    mempool_lock
+        .write()
+        .await
+        .add_wallet_transaction(transaction)
+        .await;
+    true
+}
+
+pub async fn run(
+    wallet_lock: Arc<RwLock<Wallet>>,
+    mempool_lock: Arc<RwLock<Mempool>>,
+    mut broadcast_channel_receiver: broadcast::Receiver<SaitoMessage>,
+) -> crate::Result<()> {
+    while let Some(message) =
+        recv_message(&mut broadcast_channel_receiver, Subsystem::AutoCompound).await
+    {
+        if let SaitoMessage::BlockchainNewLongestChainBlock { .. } = message {
+            compound(&wallet_lock, &mempool_lock).await;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::Amount;
+    use crate::block::Block;
+    use crate::slip::{Slip, SlipType};
+    use crate::transaction::{Transaction, TransactionType};
+
+    #[tokio::test]
+    async fn compound_staking_payouts_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let mempool_lock = Arc::new(RwLock::new(Mempool::new(wallet_lock.clone())));
+        let publickey = wallet_lock.read().await.get_publickey();
+
+        let mut fee_transaction = Transaction::new();
+        fee_transaction.set_transaction_type(TransactionType::Fee);
+        let mut output = Slip::new();
+        output.set_publickey(publickey);
+        output.set_amount(Amount::from_nolan(1000));
+        output.set_slip_type(SlipType::StakerPayout);
+        fee_transaction.add_output(output);
+        fee_transaction.generate_output_uuids();
+        let mut block = Block::new();
+        block.set_transactions(&mut vec![fee_transaction]);
+
+        // compounding is off without a threshold
+        wallet_lock
+            .write()
+            .await
+            .on_chain_reorganization(&block, true);
+        assert_eq!(wallet_lock.read().await.get_uncompounded_payouts(), 1000);
+        assert!(!compound(&wallet_lock, &mempool_lock).await);
+
+        wallet_lock.write().await.set_auto_compound_threshold(1000);
+        assert!(!compound(&wallet_lock, &mempool_lock).await);
+
+        wallet_lock.write().await.set_auto_compound_threshold(999);
+        assert!(compound(&wallet_lock, &mempool_lock).await);
+        assert_eq!(wallet_lock.read().await.get_uncompounded_payouts(), 0);
+        let mempool = mempool_lock.read().await;
+        assert_eq!(mempool.transactions.len(), 1);
+        let deposit = &mempool.transactions[0];
+        assert_eq!(
+            deposit.get_transaction_type(),
+            TransactionType::StakerDeposit
+        );
+        assert_eq!(
+            deposit.get_outputs()[0].get_slip_type(),
+            SlipType::StakerDeposit
+        );
+        assert_eq!(
+            deposit.get_outputs()[0].get_amount(),
+            Amount::from_nolan(1000)
+        );
+    }
+}
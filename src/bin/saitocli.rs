@@ -19,10 +19,50 @@ prints keys from wallet
 
 create a sign a transaction
 
+**pay**
+
+create and sign one transaction paying every recipient listed in a file
+
 **create_tx**
 
 create vip tx for modelling test network only
 
+**audit-supply**
+
+replays the blocks on disk and checks that no nolan are created or lost
+
+**reindex**
+
+rebuilds the utxoset, staking tables and wallet slips from the blocks on disk
+
+**replay**
+
+replays a message log recorded by a node into a new node in memory
+
+**export-chain**
+
+writes the longest chain on disk to a bootstrap file
+
+**import-chain**
+
+adds the blocks in a bootstrap file to the blocks on disk, validating every block
+
+**addressbook**
+
+adds, lists and removes the labels of publickeys in the address book of the wallet
+
+**payment-request**
+
+creates saito: payment request URIs, optionally drawn as QR codes, and parses them
+
+**history**
+
+prints the transactions of the wallet in the longest chain on disk, labelled from the address book
+
+**sendrawtransaction**
+
+sends a transaction written by tx, or hex encoded, to a running node
+
 ## Example
 
 ```bash
@@ -46,6 +86,10 @@ cargo run --bin saitocli -- tx -a 1 -t gYsu1fVHjP6Z8CHCzti9K9xb5JPqpEL7zi7arvLiV
 ```
 or
 ```
+cargo run --bin saitocli -- pay payroll.txt --keyfile test/testwallet --password asdf --fee 1000 -f tx.out
+```
+or
+```
 cargo run --bin saitocli -- print --keyfile test/testwallet --password asdf
 ```
 or
@@ -58,27 +102,78 @@ cargo run --bin saitocli -- blocks
 ```
 or
 ```
+cargo run --bin saitocli -- audit-supply --verbose
+```
+or
+```
+cargo run --bin saitocli -- reindex --keyfile test/testwallet --password asdf
+```
+or
+```
+cargo run --bin saitocli -- addressbook --keyfile test/testwallet --password asdf add alice gYsu1fVHjP6Z8CHCzti9K9xb5JPqpEL7zi7arvLiVANm
+```
+or
+```
+cargo run --bin saitocli -- history --keyfile test/testwallet --password asdf
+```
+or
+```
+cargo run --bin saitocli -- payment-request create --keyfile test/testwallet --password asdf --amount 1.5 --message "order 42" --qr
+```
+or
+```
+cargo run --bin saitocli -- payment-request parse "saito:gYsu1fVHjP6Z8CHCzti9K9xb5JPqpEL7zi7arvLiVANm?amount=1.5"
+```
+or
+```
+cargo run --bin saitocli -- export-chain saito.chain
+```
+or
+```
+cargo run --bin saitocli -- replay data/messages.log
+```
+or
+```
+cargo run --bin saitocli -- import-chain saito.chain
+```
+or
+```
+cargo run --bin saitocli -- sendrawtransaction tx.out --url http://127.0.0.1:3000
+```
+or
+```
 cargo run --bin saitocli -- create_tx -a 1 -t gYsu1fVHjP6Z8CHCzti9K9xb5JPqpEL7zi7arvLiVANm  --keyfile test/testwallet --password asdf -o 0 -f data/test/out1.tx
 ```
 
 */
 
-use base58::FromBase58;
+use base58::{FromBase58, ToBase58};
 use clap::{App, Arg};
 use saito_rust::{
-    block::Block,
-    crypto::{hash, SaitoHash},
+    amount::Amount,
+    blockchain::Blockchain,
+    bootstrap::{export_chain, import_chain},
+    configuration::get_configuration,
+    crypto::{hash, publickey_from_base58, SaitoHash, SaitoPublicKey},
+    mempool::Mempool,
+    message_log::{read_message_log, MessageReplay},
+    reindex::Reindex,
     slip::Slip,
-    storage::{Storage, BLOCKS_DIR_PATH},
+    storage::{FileBlockStorage, MemoryBlockStorage, Storage, BLOCKS_DIR_PATH},
+    supply_audit::SupplyAudit,
     transaction::{Transaction, TransactionType},
-    wallet::Wallet,
+    vip_policy::VipPolicy,
+    wallet::{FeePayer, PaymentRequest, Wallet, WalletTransactionDirection},
+    wallet_manager::WalletManager,
 };
 use secp256k1::PublicKey;
 use std::{
     convert::TryInto,
     fs::{self, File},
     io::{Read, Write},
+    sync::Arc,
 };
+use tokio::sync::RwLock;
 
 // TODO Combine this into the main binary?
 #[tokio::main]
@@ -148,6 +243,68 @@ pub async fn main() -> saito_rust::Result<()> {
                         .help("output file"),
                 ),
         )
+        .subcommand(
+            App::new("pay")
+                .about("creates one transaction paying every recipient listed in a file")
+                .arg(
+                    Arg::with_name("recipients")
+                        .index(1)
+                        .required(true)
+                        .help("file with a recipient and an amount in SAITO on each line, e.g. alice 1.5, the recipient being a label in the address book or a base58 publickey"),
+                )
+                .arg(
+                    Arg::with_name("keyfile")
+                        .short("k")
+                        .long("keyfile")
+                        .required(true)
+                        .takes_value(true)
+                        .help("path to keyfile"),
+                )
+                .arg(
+                    Arg::with_name("password")
+                        .short("p")
+                        .long("password")
+                        .required(true)
+                        .takes_value(true)
+                        .help("password of keyfile"),
+                )
+                .arg(
+                    Arg::with_name("fee")
+                        .long("fee")
+                        .takes_value(true)
+                        .default_value("0")
+                        .help("fee in nolan"),
+                )
+                .arg(
+                    Arg::with_name("fee-from-recipients")
+                        .long("fee-from-recipients")
+                        .help("split the fee between the recipients instead of paying it on top"),
+                )
+                .arg(
+                    Arg::with_name("filename")
+                        .short("f")
+                        .long("filename")
+                        .takes_value(true)
+                        .help("output file"),
+                ),
+        )
+        .subcommand(
+            App::new("sendrawtransaction")
+                .about("send a serialized transaction to a running node")
+                .arg(
+                    Arg::with_name("filename")
+                        .index(1)
+                        .required(true)
+                        .help("transaction file, as written by tx or hex encoded"),
+                )
+                .arg(
+                    Arg::with_name("url")
+                        .short("u")
+                        .long("url")
+                        .takes_value(true)
+                        .help("url of the node, defaults to the port in ./configuration"),
+                ),
+        )
         .subcommand(
             App::new("block")
                 .about("print info about a block file")
@@ -171,6 +328,203 @@ pub async fn main() -> saito_rust::Result<()> {
                         .help("path to blocks directory"),
                 ),
         )
+        .subcommand(
+            App::new("audit-supply")
+                .about("replay the chain and check the supply is conserved at every block")
+                .arg(
+                    Arg::with_name("path")
+                        .short("p")
+                        .long("path")
+                        .takes_value(true)
+                        .help("path to blocks directory"),
+                )
+                .arg(
+                    Arg::with_name("verbose")
+                        .short("v")
+                        .long("verbose")
+                        .help("print the supply at every block"),
+                ),
+        )
+        .subcommand(
+            App::new("reindex")
+                .about("rebuild the state derived from the blocks on disk, checking every block")
+                .arg(
+                    Arg::with_name("path")
+                        .short("p")
+                        .long("path")
+                        .takes_value(true)
+                        .help("path to blocks directory"),
+                )
+                .arg(
+                    Arg::with_name("keyfile")
+                        .short("k")
+                        .long("keyfile")
+                        .takes_value(true)
+                        .requires("password")
+                        .help("path to a keyfile whose slips are rebuilt"),
+                )
+                .arg(
+                    Arg::with_name("password")
+                        .long("password")
+                        .takes_value(true)
+                        .help("password of keyfile"),
+                ),
+        )
+        .subcommand(
+            App::new("addressbook")
+                .about("manage the labels of publickeys in the address book of the wallet")
+                .arg(
+                    Arg::with_name("keyfile")
+                        .short("k")
+                        .long("keyfile")
+                        .required(true)
+                        .takes_value(true)
+                        .help("path to keyfile"),
+                )
+                .arg(
+                    Arg::with_name("password")
+                        .short("p")
+                        .long("password")
+                        .required(true)
+                        .takes_value(true)
+                        .help("password of keyfile"),
+                )
+                .subcommand(
+                    App::new("add")
+                        .about("label a publickey")
+                        .arg(Arg::with_name("label").index(1).required(true))
+                        .arg(
+                            Arg::with_name("publickey")
+                                .index(2)
+                                .required(true)
+                                .help("base58 encoded publickey"),
+                        ),
+                )
+                .subcommand(App::new("list").about("print the labels and their publickeys"))
+                .subcommand(
+                    App::new("remove")
+                        .about("remove a label")
+                        .arg(Arg::with_name("label").index(1).required(true)),
+                ),
+        )
+        .subcommand(
+            App::new("payment-request")
+                .about("create and parse saito: payment request URIs")
+                .subcommand(
+                    App::new("create")
+                        .about("print a URI requesting a payment to the wallet or publickey")
+                        .arg(
+                            Arg::with_name("keyfile")
+                                .short("k")
+                                .long("keyfile")
+                                .takes_value(true)
+                                .requires("password")
+                                .required_unless("publickey")
+                                .help("path to the keyfile of the wallet to be paid"),
+                        )
+                        .arg(
+                            Arg::with_name("password")
+                                .short("p")
+                                .long("password")
+                                .takes_value(true)
+                                .help("password of keyfile"),
+                        )
+                        .arg(
+                            Arg::with_name("publickey")
+                                .long("publickey")
+                                .takes_value(true)
+                                .conflicts_with("keyfile")
+                                .help("base58 encoded publickey to be paid"),
+                        )
+                        .arg(
+                            Arg::with_name("amount")
+                                .short("a")
+                                .long("amount")
+                                .takes_value(true)
+                                .help("amount requested, in SAITO"),
+                        )
+                        .arg(
+                            Arg::with_name("message")
+                                .short("m")
+                                .long("message")
+                                .takes_value(true)
+                                .help("message to the payer, e.g. an order number"),
+                        )
+                        .arg(
+                            Arg::with_name("qr")
+                                .long("qr")
+                                .help("also draw the URI as a QR code"),
+                        ),
+                )
+                .subcommand(
+                    App::new("parse")
+                        .about("print the publickey, amount and message of a URI")
+                        .arg(Arg::with_name("uri").index(1).required(true)),
+                ),
+        )
+        .subcommand(
+            App::new("history")
+                .about("print the transactions of the wallet in the longest chain on disk")
+                .arg(
+                    Arg::with_name("keyfile")
+                        .short("k")
+                        .long("keyfile")
+                        .required(true)
+                        .takes_value(true)
+                        .help("path to keyfile"),
+                )
+                .arg(
+                    Arg::with_name("password")
+                        .short("p")
+                        .long("password")
+                        .required(true)
+                        .takes_value(true)
+                        .help("password of keyfile"),
+                )
+                .arg(
+                    Arg::with_name("path")
+                        .long("path")
+                        .takes_value(true)
+                        .help("path to blocks directory"),
+                ),
+        )
+        .subcommand(
+            App::new("export-chain")
+                .about("write the longest chain on disk to a bootstrap file")
+                .arg(
+                    Arg::with_name("filename")
+                        .index(1)
+                        .required(true)
+                        .help("bootstrap file to write"),
+                )
+                .arg(
+                    Arg::with_name("path")
+                        .short("p")
+                        .long("path")
+                        .takes_value(true)
+                        .help("path to blocks directory"),
+                ),
+        )
+        .subcommand(
+            App::new("import-chain")
+                .about("add the blocks in a bootstrap file to the blocks on disk")
+                .arg(
+                    Arg::with_name("filename")
+                        .index(1)
+                        .required(true)
+                        .help("bootstrap file to read"),
+                ),
+        )
+        .subcommand(
+            App::new("replay")
+                .about("replay the messages recorded in a message log into a new node")
+                .arg(
+                    Arg::with_name("filename")
+                        .index(1)
+                        .required(true)
+                        .help("message log to read"),
+                ),
+        )
         .subcommand(
             App::new("create_tx")
                 .about("create VIP transaction")
@@ -229,13 +583,40 @@ pub async fn main() -> saito_rust::Result<()> {
         )
         .get_matches();
 
+    if let Some(matches) = command_matches.subcommand_matches("sendrawtransaction") {
+        let filename = matches.value_of("filename").unwrap();
+        let contents = fs::read(filename).unwrap_or_else(|error| {
+            println!("could not read {}: {}", filename, error);
+            std::process::exit(1);
+        });
+        let transaction_bytes = decode_raw_transaction(contents);
+
+        //
+        // without a url we send to the node configured in this directory
+        //
+        let url = match matches.value_of("url") {
+            Some(url) => String::from(url.trim_end_matches('/')),
+            None => format!("http://127.0.0.1:{}", get_configuration()?.network.port),
+        };
+
+        let response = reqwest::Client::new()
+            .post(format!("{}/sendtransaction", url))
+            .body(transaction_bytes)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            println!("the node rejected the transaction: {}", response.status());
+            std::process::exit(1);
+        }
+        println!("{}", response.text().await?);
+    }
     if let Some(matches) = command_matches.subcommand_matches("print") {
         let key_file = matches.value_of("keyfile").unwrap();
         let password = matches.value_of("password");
 
         let mut wallet = Wallet::new();
         wallet.save();
-        wallet.load_wallet(key_file, password);
+        wallet.load_wallet(key_file, password)?;
 
         println!("public key : {}", hex::encode(wallet.get_publickey()));
         println!("private key : {}", hex::encode(wallet.get_privatekey()));
@@ -276,7 +657,13 @@ pub async fn main() -> saito_rust::Result<()> {
                 let mut f = File::open(path.path()).unwrap();
                 let mut encoded = Vec::<u8>::new();
                 f.read_to_end(&mut encoded).unwrap();
-                let mut block = Block::deserialize_for_net(&encoded);
+                let mut block = match Storage::deserialize_block_from_disk(&encoded) {
+                    Ok(block) => block,
+                    Err(err) => {
+                        println!("cannot read {:?}: {}", path.path(), err);
+                        continue;
+                    }
+                };
                 println!("--------------------------------------------------------------");
                 println!("filename: {:?}", path);
                 println!("hash on disk  : {:?}", &hex::encode(&block.get_hash()));
@@ -289,12 +676,325 @@ pub async fn main() -> saito_rust::Result<()> {
             }
         }
     }
+    if let Some(matches) = command_matches.subcommand_matches("reindex") {
+        let blocks_dir = match matches.value_of("path") {
+            Some(path) => String::from(path),
+            None => BLOCKS_DIR_PATH.clone(),
+        };
+        let settings = get_configuration()?;
+
+        //
+        // the blocks are validated under the node's configuration, and the
+        // wallets it is configured with are rebuilt along with the keyfile. the
+        // blocks are kept in memory so that the blocks on disk are left alone
+        //
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        if let Some(key_file) = matches.value_of("keyfile") {
+            wallet_lock
+                .write()
+                .await
+                .load_wallet(key_file, matches.value_of("password"))?;
+        }
+        let mut wallet_manager = WalletManager::new(wallet_lock.clone());
+        for wallet_setting in settings.wallets.iter() {
+            let mut wallet = Wallet::new();
+            wallet.load_wallet(&wallet_setting.filename, Some(&wallet_setting.password))?;
+            wallet_manager.add_wallet(Arc::new(RwLock::new(wallet)), &wallet_setting.roles)?;
+        }
+        let mut blockchain = Blockchain::new(wallet_lock.clone());
+        blockchain.set_block_storage(Arc::new(MemoryBlockStorage::new()));
+        blockchain.set_wallet_manager(wallet_manager);
+        blockchain.set_vip_policy(VipPolicy::from_settings(&settings.vip_policy)?);
+
+        let reindex = Reindex::reindex_blocks_dir(&mut blockchain, &blocks_dir).await?;
+        println!(
+            "replayed {} blocks from {}, the longest chain is at block {} {}",
+            reindex.get_blocks_replayed(),
+            blocks_dir,
+            blockchain.get_latest_block_id(),
+            hex::encode(blockchain.get_latest_block_hash())
+        );
+        for block_hash in reindex.get_blocks_off_longest_chain() {
+            println!("not in the longest chain: {}", hex::encode(block_hash));
+        }
+        for wallet_lock in blockchain.get_wallet_manager().get_wallet_locks() {
+            let wallet = wallet_lock.read().await;
+            println!(
+                "wallet {} balance {}",
+                wallet.get_publickey().to_base58(),
+                wallet.get_available_balance()
+            );
+        }
+        if !reindex.get_corrupt_block_files().is_empty() {
+            for path in reindex.get_corrupt_block_files() {
+                println!("corrupt block file: {:?}", path);
+            }
+            std::process::exit(1);
+        }
+    }
+    if let Some(matches) = command_matches.subcommand_matches("addressbook") {
+        let mut wallet = Wallet::new();
+        wallet.load_wallet(
+            matches.value_of("keyfile").unwrap(),
+            matches.value_of("password"),
+        )?;
+
+        if let Some(matches) = matches.subcommand_matches("add") {
+            let label = matches.value_of("label").unwrap();
+            let publickey = parse_publickey(matches.value_of("publickey").unwrap());
+            if let Err(err) = wallet.add_address_book_entry(label, publickey) {
+                println!("cannot add {}: {}", label, err);
+                std::process::exit(1);
+            }
+        } else if let Some(matches) = matches.subcommand_matches("remove") {
+            let label = matches.value_of("label").unwrap();
+            if wallet.remove_address_book_entry(label).is_none() {
+                println!("{} is not in the address book", label);
+                std::process::exit(1);
+            }
+        } else {
+            for (label, publickey) in wallet.get_address_book().get_entries() {
+                println!("{} {}", label, publickey.to_base58());
+            }
+        }
+    }
+    if let Some(matches) = command_matches.subcommand_matches("payment-request") {
+        if let Some(matches) = matches.subcommand_matches("create") {
+            let publickey = match matches.value_of("publickey") {
+                Some(publickey) => parse_publickey(publickey),
+                None => {
+                    let mut wallet = Wallet::new();
+                    wallet.load_wallet(
+                        matches.value_of("keyfile").unwrap(),
+                        matches.value_of("password"),
+                    )?;
+                    wallet.get_publickey()
+                }
+            };
+            let mut payment_request = PaymentRequest::new(publickey);
+            if let Some(amount) = matches.value_of("amount") {
+                let amount: Amount = amount.parse().unwrap_or_else(|_| {
+                    println!("invalid amount {}", amount);
+                    std::process::exit(1);
+                });
+                payment_request.set_amount(Some(amount));
+            }
+            payment_request.set_message(matches.value_of("message").map(String::from));
+
+            println!("{}", payment_request.to_uri());
+            if matches.is_present("qr") {
+                println!("{}", payment_request.to_qr_code()?);
+            }
+        } else if let Some(matches) = matches.subcommand_matches("parse") {
+            let payment_request = PaymentRequest::from_uri(matches.value_of("uri").unwrap())
+                .unwrap_or_else(|err| {
+                    println!("invalid payment request: {}", err);
+                    std::process::exit(1);
+                });
+            println!(
+                "publickey : {}",
+                payment_request.get_publickey().to_base58()
+            );
+            if let Some(amount) = payment_request.get_amount() {
+                println!("amount : {}", amount);
+            }
+            if let Some(message) = payment_request.get_message() {
+                println!("message : {}", message);
+            }
+        }
+    }
+    if let Some(matches) = command_matches.subcommand_matches("history") {
+        let blocks_dir = match matches.value_of("path") {
+            Some(path) => String::from(path),
+            None => BLOCKS_DIR_PATH.clone(),
+        };
+        let settings = get_configuration()?;
+
+        //
+        // the blocks are replayed into a blockchain of our own, as reindex
+        // does, so that the history holds only the longest chain, and are kept
+        // in memory so that the blocks on disk are left alone
+        //
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        wallet_lock.write().await.load_wallet(
+            matches.value_of("keyfile").unwrap(),
+            matches.value_of("password"),
+        )?;
+        let mut blockchain = Blockchain::new(wallet_lock.clone());
+        blockchain.set_block_storage(Arc::new(MemoryBlockStorage::new()));
+        blockchain.set_vip_policy(VipPolicy::from_settings(&settings.vip_policy)?);
+        Reindex::reindex_blocks_dir(&mut blockchain, &blocks_dir).await?;
+
+        let wallet = wallet_lock.read().await;
+        for wallet_transaction in wallet.get_history(&blockchain)? {
+            let counterparty = match wallet_transaction.get_counterparty() {
+                Some(publickey) => match wallet.get_address_book().get_label(&publickey) {
+                    Some(label) => String::from(label),
+                    None => publickey.to_base58(),
+                },
+                None => String::from("-"),
+            };
+            let (direction, preposition) = match wallet_transaction.get_direction() {
+                WalletTransactionDirection::Incoming => ("in ", "from"),
+                WalletTransactionDirection::Outgoing => ("out", "to"),
+            };
+            println!(
+                "block {} {} {} {} {} {}",
+                wallet_transaction.get_block_id(),
+                wallet_transaction.get_timestamp(),
+                direction,
+                wallet_transaction.get_amount(),
+                preposition,
+                counterparty
+            );
+        }
+    }
+    if let Some(matches) = command_matches.subcommand_matches("export-chain") {
+        let blocks_dir = match matches.value_of("path") {
+            Some(path) => String::from(path),
+            None => BLOCKS_DIR_PATH.clone(),
+        };
+        let filename = matches.value_of("filename").unwrap();
+        let blocks_exported = export_chain(&FileBlockStorage::new(&blocks_dir), filename)?;
+        println!(
+            "exported {} blocks from {} to {}",
+            blocks_exported, blocks_dir, filename
+        );
+    }
+    if let Some(matches) = command_matches.subcommand_matches("import-chain") {
+        let settings = get_configuration()?;
+
+        //
+        // the blocks already on disk are loaded first, so that the imported
+        // blocks extend them and are written next to them
+        //
+        fs::create_dir_all(BLOCKS_DIR_PATH.as_str())?;
+        let mut blockchain = Blockchain::new(Arc::new(RwLock::new(Wallet::new())));
+        blockchain.set_vip_policy(VipPolicy::from_settings(&settings.vip_policy)?);
+        let blockchain_lock = Arc::new(RwLock::new(blockchain));
+        Storage::load_blocks_from_disk(blockchain_lock.clone()).await;
+        let mut blockchain = blockchain_lock.write().await;
+        let filename = matches.value_of("filename").unwrap();
+        let result = import_chain(&mut blockchain, filename).await;
+        println!(
+            "the longest chain is at block {} {}",
+            blockchain.get_latest_block_id(),
+            hex::encode(blockchain.get_latest_block_hash())
+        );
+        match result {
+            Ok(chain_import) => println!(
+                "imported {} blocks from {}, {} were on disk already",
+                chain_import.blocks_imported, filename, chain_import.blocks_skipped
+            ),
+            Err(err) => {
+                println!("cannot import {}: {}", filename, err);
+                std::process::exit(1);
+            }
+        }
+    }
+    if let Some(matches) = command_matches.subcommand_matches("replay") {
+        let settings = get_configuration()?;
+        let filename = matches.value_of("filename").unwrap();
+        let message_records = match read_message_log(filename) {
+            Ok(message_records) => message_records,
+            Err(err) => {
+                println!("cannot read {}: {}", filename, err);
+                std::process::exit(1);
+            }
+        };
+
+        //
+        // the node is replayed under the policies of our configuration, and
+        // keeps its blocks in memory so that the blocks on disk are left alone
+        //
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let mut mempool = Mempool::new(wallet_lock.clone());
+        mempool.apply_settings(&settings.mempool);
+        let mut blockchain = Blockchain::new(wallet_lock.clone());
+        blockchain.set_block_storage(Arc::new(MemoryBlockStorage::new()));
+        blockchain.set_vip_policy(VipPolicy::from_settings(&settings.vip_policy)?);
+        let blockchain_lock = Arc::new(RwLock::new(blockchain));
+        let publickey = wallet_lock.read().await.get_publickey();
+        let message_replay = MessageReplay::replay(
+            Arc::new(RwLock::new(mempool)),
+            blockchain_lock.clone(),
+            publickey,
+            message_records,
+        )
+        .await;
+        let blockchain = blockchain_lock.read().await;
+        println!(
+            "replayed {} messages from {}: {} handshakes, {} blocks, {} transactions added and {} rejected",
+            message_replay.messages_replayed,
+            filename,
+            message_replay.handshakes,
+            message_replay.blocks_added,
+            message_replay.transactions_added,
+            message_replay.transactions_rejected
+        );
+        println!(
+            "the longest chain is at block {} {}",
+            blockchain.get_latest_block_id(),
+            hex::encode(blockchain.get_latest_block_hash())
+        );
+    }
+    if let Some(matches) = command_matches.subcommand_matches("audit-supply") {
+        let blocks_dir = match matches.value_of("path") {
+            Some(path) => String::from(path),
+            None => BLOCKS_DIR_PATH.clone(),
+        };
+        //
+        // the blocks are kept in memory so that the blocks on disk are left alone
+        //
+        let mut blockchain = Blockchain::new(Arc::new(RwLock::new(Wallet::new())));
+        blockchain.set_block_storage(Arc::new(MemoryBlockStorage::new()));
+        let supply_audit = SupplyAudit::audit_blocks_dir(&mut blockchain, &blocks_dir).await;
+
+        if matches.is_present("verbose") {
+            for entry in supply_audit.get_entries() {
+                println!(
+                    "block {} {} : utxo {} treasury {} staking treasury {} unsettled fees {} issued {}",
+                    entry.get_block_id(),
+                    hex::encode(entry.get_block_hash()),
+                    entry.get_utxo_total().as_nolan(),
+                    entry.get_treasury().as_nolan(),
+                    entry.get_staking_treasury().as_nolan(),
+                    entry.get_unsettled_fees().as_nolan(),
+                    entry.get_issued().as_nolan(),
+                );
+            }
+        }
+        println!(
+            "audited {} blocks in {}",
+            supply_audit.get_entries().len(),
+            blocks_dir
+        );
+        match supply_audit.get_first_break() {
+            Some(entry) => {
+                println!(
+                    "supply is not conserved from block {} {}",
+                    entry.get_block_id(),
+                    hex::encode(entry.get_block_hash())
+                );
+                match entry.get_accounted() {
+                    Some(accounted) => println!(
+                        "issued {} but found {}",
+                        entry.get_issued().to_nolan_string(),
+                        accounted.to_nolan_string()
+                    ),
+                    None => println!("the supply found overflows"),
+                }
+                std::process::exit(1);
+            }
+            None => println!("supply is conserved"),
+        }
+    }
     if let Some(matches) = command_matches.subcommand_matches("tx") {
         let key_file = matches.value_of("keyfile").unwrap();
         let password = matches.value_of("password");
 
         let mut wallet = Wallet::new();
-        wallet.load_wallet(key_file, password);
+        wallet.load_wallet(key_file, password)?;
 
         let filename: String = match matches.value_of("filename") {
             Some(filename) => String::from(filename),
@@ -322,12 +1022,12 @@ pub async fn main() -> saito_rust::Result<()> {
         // get inputs from the wallet and use the amount specified
         let mut input1 = Slip::new();
         input1.set_publickey(wallet.get_publickey());
-        input1.set_amount(amount);
+        input1.set_amount(amount.into());
         input1.set_uuid([0; 32]);
 
         let mut output1 = Slip::new();
         output1.set_publickey(to_pubkey.serialize());
-        output1.set_amount(amount);
+        output1.set_amount(amount.into());
         output1.set_uuid([0; 32]);
 
         transaction.add_input(input1);
@@ -348,6 +1048,66 @@ pub async fn main() -> saito_rust::Result<()> {
         buffer.write_all(&output[..]).unwrap();
         buffer.flush()?;
     }
+    if let Some(matches) = command_matches.subcommand_matches("pay") {
+        let mut wallet = Wallet::new();
+        wallet.load_wallet(
+            matches.value_of("keyfile").unwrap(),
+            matches.value_of("password"),
+        )?;
+
+        let mut payments = vec![];
+        for line in fs::read_to_string(matches.value_of("recipients").unwrap())?.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (recipient, amount) = match line.split_once(char::is_whitespace) {
+                Some((recipient, amount)) => (recipient, amount.trim()),
+                None => {
+                    println!("expected a recipient and an amount, got {}", line);
+                    std::process::exit(1);
+                }
+            };
+            let publickey = match wallet.get_address_book().get_publickey(recipient) {
+                Some(publickey) => publickey,
+                None => parse_publickey(recipient),
+            };
+            let amount: Amount = amount.parse().unwrap_or_else(|_| {
+                println!("invalid amount {}", amount);
+                std::process::exit(1);
+            });
+            payments.push((publickey, amount.as_nolan()));
+        }
+        let fee: u64 = matches
+            .value_of("fee")
+            .unwrap()
+            .parse()
+            .unwrap_or_else(|_| {
+                println!("fee must be a number of nolan");
+                std::process::exit(1);
+            });
+        let fee_payer = if matches.is_present("fee-from-recipients") {
+            FeePayer::Recipients
+        } else {
+            FeePayer::Sender
+        };
+
+        let transaction = wallet
+            .create_payment_transaction(&payments, fee, fee_payer)
+            .unwrap_or_else(|err| {
+                println!("cannot create the payment: {}", err);
+                std::process::exit(1);
+            });
+
+        let filename = matches.value_of("filename").unwrap_or("transaction.out");
+        println!("Writing transaction");
+        println!("Recipients: {}", payments.len());
+        println!("====> {}", filename);
+
+        let mut buffer = File::create(filename)?;
+        buffer.write_all(&transaction.serialize_for_net())?;
+        buffer.flush()?;
+    }
     if let Some(matches) = command_matches.subcommand_matches("create_tx") {
         let key_file = matches.value_of("keyfile").unwrap();
         let password = matches.value_of("password");
@@ -356,7 +1116,7 @@ pub async fn main() -> saito_rust::Result<()> {
         // let log_level = matches.value_of("log-level");
 
         let mut wallet = Wallet::new();
-        wallet.load_wallet(key_file, password);
+        wallet.load_wallet(key_file, password)?;
 
         let out_file: String = match matches.value_of("filename") {
             Some(out_file) => String::from(out_file),
@@ -403,7 +1163,7 @@ pub async fn main() -> saito_rust::Result<()> {
 
         let mut slip_outp = Slip::new();
         slip_outp.set_publickey(to_pubkey.serialize());
-        slip_outp.set_amount(amount);
+        slip_outp.set_amount(amount.into());
         slip_outp.set_uuid([0; 32]);
 
         transaction.add_input(slip_inp);
@@ -426,3 +1186,21 @@ pub async fn main() -> saito_rust::Result<()> {
     }
     Ok(())
 }
+
+//
+// transaction files hold the transaction serialized for the network, either as
+// raw bytes or as hex.
+//
+fn parse_publickey(publickey: &str) -> SaitoPublicKey {
+    publickey_from_base58(publickey).unwrap_or_else(|err| {
+        println!("{}", err);
+        std::process::exit(1);
+    })
+}
+
+fn decode_raw_transaction(contents: Vec<u8>) -> Vec<u8> {
+    match std::str::from_utf8(&contents) {
+        Ok(text) => hex::decode(text.trim()).unwrap_or(contents),
+        Err(_) => contents,
+    }
+}
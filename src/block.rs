@@ -1,18 +1,21 @@
 use crate::{
-    blockchain::{Blockchain, GENESIS_PERIOD, MAX_STAKER_RECURSION},
+    amount::Amount,
+    blockchain::{Blockchain, ATR_FEE, GENESIS_PERIOD, MAX_STAKER_RECURSION},
     burnfee::BurnFee,
+    consensus_params::ConsensusParams,
     crypto::{
-        hash, sign, verify, SaitoHash, SaitoPrivateKey, SaitoPublicKey, SaitoSignature,
-        SaitoUTXOSetKey,
+        hash, hash_with, sign, verify, HashAlgorithm, SaitoHash, SaitoPrivateKey, SaitoPublicKey,
+        SaitoSignature, SaitoUTXOSetKey,
     },
     golden_ticket::GoldenTicket,
-    hop::HOP_SIZE,
+    layout::TRANSACTION_LENGTH_PREFIX_SIZE,
     merkle::MerkleTreeLayer,
-    slip::{Slip, SlipType, SLIP_SIZE},
+    random::HashChain,
+    slip::{Slip, SlipType},
     staking::Staking,
-    storage::Storage,
-    time::create_timestamp,
-    transaction::{Transaction, TransactionType, TRANSACTION_SIZE},
+    storage::BlockStorage,
+    time::{create_timestamp, MAX_FUTURE_BLOCK_TIME},
+    transaction::{Transaction, TransactionType},
     wallet::Wallet,
 };
 use ahash::AHashMap;
@@ -21,11 +24,247 @@ use log::{error, info, trace};
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use std::convert::TryInto;
-use std::{mem, sync::Arc};
+use std::{fmt, mem, sync::Arc};
 use tokio::sync::RwLock;
 use tracing::{span, Level};
 
-pub const BLOCK_HEADER_SIZE: usize = 213;
+pub use crate::layout::BLOCK_HEADER_SIZE;
+
+/// The place of the golden ticket among the transactions of a block which has one. The fee
+/// transaction, which pays out the lottery the ticket solves, is always the last transaction.
+pub const GOLDEN_TICKET_IDX: usize = 0;
+
+lazy_static::lazy_static! {
+    //
+    // blocks received from peers are prevalidated on their own threads so that they
+    // do not compete with the hashing and signing done on the global rayon pool.
+    //
+    static ref BLOCK_VALIDATION_POOL: rayon::ThreadPool = rayon::ThreadPoolBuilder::new()
+        .thread_name(|i| format!("block-validation-{}", i))
+        .build()
+        .expect("failed to build the block validation pool");
+}
+
+/// The reason a block failed validation, in the order the checks are made.
+#[derive(PartialEq, Debug, Clone)]
+pub enum BlockValidationError {
+    InvalidSignature,
+    InvalidHashAlgorithm {
+        expected: HashAlgorithm,
+        found: HashAlgorithm,
+    },
+    TimestampInFuture {
+        timestamp: u64,
+        adjusted_timestamp: u64,
+    },
+    TimestampBeforeParent {
+        timestamp: u64,
+        parent_timestamp: u64,
+    },
+    InvalidBurnFee {
+        expected: u64,
+        found: u64,
+    },
+    NoTransactions,
+    TooLarge {
+        size: usize,
+        max: usize,
+    },
+    InsufficientRoutingWork {
+        needed: u64,
+        found: u64,
+    },
+    InvalidGoldenTicket,
+    IssuanceAfterFirstBlock,
+    VipPolicy,
+    FeesOverflow,
+    PayoutOverflow,
+    TreasuryOverflow,
+    InvalidTreasury {
+        expected: u64,
+        found: u64,
+    },
+    StakingTreasuryOverflow,
+    InvalidStakingTreasury {
+        expected: u64,
+        found: u64,
+    },
+    InvalidDifficulty {
+        expected: u64,
+        found: u64,
+    },
+    FeeTransactionWithoutGoldenTicket,
+    InvalidFeeTransaction {
+        block_id: u64,
+    },
+    InvalidRebroadcastSlips,
+    InvalidRebroadcastNolan,
+    InvalidRebroadcastHash,
+    InvalidMerkleRoot,
+    InvalidTransactions,
+    MisplacedGoldenTicket {
+        idx: usize,
+    },
+    MisplacedFeeTransaction {
+        idx: usize,
+    },
+    MultipleGoldenTickets {
+        count: usize,
+    },
+    MultipleFeeTransactions {
+        count: usize,
+    },
+    InvalidFeeTransactionSignature,
+    InvalidFeeTransactionInputs,
+    ConflictingDelegations,
+}
+
+impl fmt::Display for BlockValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockValidationError::InvalidSignature => write!(
+                f,
+                "ERROR 582039: block is not signed by creator or signature does not validate"
+            ),
+            BlockValidationError::InvalidHashAlgorithm { expected, found } => write!(
+                f,
+                "ERROR 582042: block is hashed with {:?} but blocks at its id are hashed with {:?}",
+                found, expected
+            ),
+            BlockValidationError::TimestampInFuture {
+                timestamp,
+                adjusted_timestamp,
+            } => write!(
+                f,
+                "ERROR 720431: block timestamp {} is too far ahead of network-adjusted time {}",
+                timestamp, adjusted_timestamp
+            ),
+            BlockValidationError::TimestampBeforeParent {
+                timestamp,
+                parent_timestamp,
+            } => write!(
+                f,
+                "ERROR 720432: block timestamp {} is not after its parent's timestamp {}",
+                timestamp, parent_timestamp
+            ),
+            BlockValidationError::InvalidBurnFee { expected, found } => write!(
+                f,
+                "ERROR 720433: burn fee does not validate: {} expected versus {} found",
+                expected, found
+            ),
+            BlockValidationError::NoTransactions => write!(
+                f,
+                "ERROR 424342: block does not validate as it has no transactions"
+            ),
+            BlockValidationError::TooLarge { size, max } => write!(
+                f,
+                "ERROR 830394: block is {} bytes, more than the {} allowed",
+                size, max
+            ),
+            BlockValidationError::InsufficientRoutingWork { needed, found } => write!(
+                f,
+                "ERROR 510293: block lacking adequate routing work from creator: {} needed versus {} found",
+                needed, found
+            ),
+            BlockValidationError::InvalidGoldenTicket => write!(
+                f,
+                "ERROR 801923: golden ticket solution does not validate against previous block hash and difficulty"
+            ),
+            BlockValidationError::IssuanceAfterFirstBlock => write!(
+                f,
+                "ERROR 801924: blockchain contains issuance after block 1 in chain"
+            ),
+            BlockValidationError::VipPolicy => {
+                write!(f, "ERROR 801925: block violates the VIP policy")
+            }
+            BlockValidationError::FeesOverflow => {
+                write!(f, "ERROR 582043: fees overflow, block invalid")
+            }
+            BlockValidationError::PayoutOverflow => {
+                write!(f, "ERROR 582044: staker payout overflows, block invalid")
+            }
+            BlockValidationError::TreasuryOverflow => {
+                write!(f, "ERROR 582040: treasury overflows, block invalid")
+            }
+            BlockValidationError::InvalidTreasury { expected, found } => write!(
+                f,
+                "ERROR 801926: treasury does not validate: {} expected versus {} found",
+                expected, found
+            ),
+            BlockValidationError::StakingTreasuryOverflow => {
+                write!(f, "ERROR 582041: staking treasury overflows, block invalid")
+            }
+            BlockValidationError::InvalidStakingTreasury { expected, found } => write!(
+                f,
+                "ERROR 801927: staking treasury does not validate: {} expected versus {} found",
+                expected, found
+            ),
+            BlockValidationError::InvalidDifficulty { expected, found } => write!(
+                f,
+                "ERROR 801928: difficulty does not validate: {} expected versus {} found",
+                expected, found
+            ),
+            BlockValidationError::FeeTransactionWithoutGoldenTicket => write!(
+                f,
+                "ERROR 48203: block appears to have fee transaction without golden ticket"
+            ),
+            BlockValidationError::InvalidFeeTransaction { block_id } => write!(
+                f,
+                "ERROR 627428: block {} fee transaction doesn't match cv fee transaction",
+                block_id
+            ),
+            BlockValidationError::InvalidRebroadcastSlips => {
+                write!(f, "ERROR 624442: rebroadcast slips total incorrect")
+            }
+            BlockValidationError::InvalidRebroadcastNolan => {
+                write!(f, "ERROR 294018: rebroadcast nolan amount incorrect")
+            }
+            BlockValidationError::InvalidRebroadcastHash => {
+                write!(f, "ERROR 123422: hash of rebroadcast transactions incorrect")
+            }
+            BlockValidationError::InvalidMerkleRoot => {
+                write!(f, "ERROR 801929: merkle root is unset or is invalid")
+            }
+            BlockValidationError::InvalidTransactions => {
+                write!(f, "ERROR 801930: block contains invalid transactions")
+            }
+            BlockValidationError::MisplacedGoldenTicket { idx } => write!(
+                f,
+                "ERROR 801931: golden ticket is transaction {} rather than the first",
+                idx
+            ),
+            BlockValidationError::MisplacedFeeTransaction { idx } => write!(
+                f,
+                "ERROR 801932: fee transaction is transaction {} rather than the last",
+                idx
+            ),
+            BlockValidationError::MultipleGoldenTickets { count } => write!(
+                f,
+                "ERROR 801933: block has {} golden tickets, at most one is allowed",
+                count
+            ),
+            BlockValidationError::MultipleFeeTransactions { count } => write!(
+                f,
+                "ERROR 801934: block has {} fee transactions, at most one is allowed",
+                count
+            ),
+            BlockValidationError::InvalidFeeTransactionSignature => write!(
+                f,
+                "ERROR 801935: fee transaction is not signed by the block creator"
+            ),
+            BlockValidationError::InvalidFeeTransactionInputs => write!(
+                f,
+                "ERROR 801936: fee transaction spends a slip which is not a staker paid out by it"
+            ),
+            BlockValidationError::ConflictingDelegations => write!(
+                f,
+                "ERROR 801937: block delegates a staker key to more than one key"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BlockValidationError {}
 
 //
 // object used when generating and validation transactions, containing the
@@ -106,6 +345,7 @@ pub struct BlockPayout {
     pub miner: SaitoPublicKey,
     pub router: SaitoPublicKey,
     pub staker: SaitoPublicKey,
+    pub staker_delegate: SaitoPublicKey,
     pub miner_payout: u64,
     pub router_payout: u64,
     pub staker_payout: u64,
@@ -120,6 +360,7 @@ impl BlockPayout {
             miner: [0; 33],
             router: [0; 33],
             staker: [0; 33],
+            staker_delegate: [0; 33],
             miner_payout: 0,
             router_payout: 0,
             staker_payout: 0,
@@ -128,6 +369,83 @@ impl BlockPayout {
             random_number: [0; 32],
         }
     }
+
+    /// A readable view of the payout, with publickeys hex encoded and null for a share
+    /// which nobody won.
+    pub fn to_json(&self) -> serde_json::Value {
+        let winner =
+            |publickey: SaitoPublicKey| (publickey != [0; 33]).then(|| hex::encode(publickey));
+        serde_json::json!({
+            "miner": winner(self.miner),
+            "miner_payout": self.miner_payout,
+            "router": winner(self.router),
+            "router_payout": self.router_payout,
+            "staker": winner(self.staker),
+            "staker_delegate": winner(self.staker_delegate),
+            "staker_payout": self.staker_payout,
+            "staking_treasury": self.staking_treasury,
+        })
+    }
+}
+
+/// A payment made by the fee transaction of a block, with the publickey hex encoded.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PayoutRecipient {
+    pub publickey: String,
+    pub amount: u64,
+}
+
+/// Who the fee transaction of a block pays for which work, read from the types of its
+/// outputs. A block without a golden ticket pays nobody.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct PayoutBreakdown {
+    pub miners: Vec<PayoutRecipient>,
+    pub routers: Vec<PayoutRecipient>,
+    pub stakers: Vec<PayoutRecipient>,
+}
+
+impl PayoutBreakdown {
+    pub fn get_total_payout(&self) -> u64 {
+        self.miners
+            .iter()
+            .chain(self.routers.iter())
+            .chain(self.stakers.iter())
+            .map(|recipient| recipient.amount)
+            .sum()
+    }
+}
+
+/// The automatic transaction rebroadcasts of a block. Hashes are hex encoded.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct AtrStats {
+    pub rebroadcast_slips: u64,
+    pub rebroadcast_nolan: u64,
+    pub rebroadcast_fees_nolan: u64,
+    pub rebroadcast_hash: String,
+}
+
+/// A transaction of a block as listed by GET /explorer/block/<hash>, without its slips.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct TransactionSummary {
+    pub hash: Option<String>,
+    pub transaction_type: String,
+    pub timestamp: u64,
+    pub input_count: usize,
+    pub output_count: usize,
+    pub total_in: u64,
+    pub total_out: u64,
+    pub total_fees: u64,
+}
+
+/// Everything a block explorer shows for a block, as replied to GET /explorer/block/<hash>.
+/// The payouts and rebroadcasts are those the block was validated with, so explorers need
+/// not reimplement the consensus rules to show them.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ExplorerBlock {
+    pub header: serde_json::Value,
+    pub transactions: Vec<TransactionSummary>,
+    pub payouts: PayoutBreakdown,
+    pub atr: AtrStats,
 }
 
 ///
@@ -165,6 +483,8 @@ pub struct Block {
     burnfee: u64,
     difficulty: u64,
     staking_treasury: u64,
+    /// The algorithm of the block hashes and merkle root
+    hash_algorithm: HashAlgorithm,
     /// Transactions
     transactions: Vec<Transaction>,
     /// Self-Calculated / Validated
@@ -204,6 +524,9 @@ pub struct Block {
     created_hashmap_of_slips_spent_this_block: bool,
     // the peer's connection ID who sent us this block
     source_connection_id: Option<SaitoHash>,
+    // the signatures were checked by prevalidate() and are not checked again by validate()
+    #[serde(skip)]
+    signatures_verified: bool,
 }
 
 impl Block {
@@ -220,6 +543,7 @@ impl Block {
             burnfee: 0,
             difficulty: 0,
             staking_treasury: 0,
+            hash_algorithm: HashAlgorithm::default(),
             transactions: vec![],
             pre_hash: None,
             hash: None,
@@ -242,6 +566,7 @@ impl Block {
             slips_spent_this_block: AHashMap::new(),
             created_hashmap_of_slips_spent_this_block: false,
             source_connection_id: None,
+            signatures_verified: false,
         }
     }
 
@@ -251,7 +576,7 @@ impl Block {
 
     pub fn get_hash(&self) -> SaitoHash {
         self.hash
-            .unwrap_or_else(|| hash(&self.serialize_for_hash()))
+            .unwrap_or_else(|| hash_with(self.hash_algorithm, &self.serialize_for_hash()))
     }
 
     pub fn get_lc(&self) -> bool {
@@ -302,6 +627,10 @@ impl Block {
         self.difficulty
     }
 
+    pub fn get_hash_algorithm(&self) -> HashAlgorithm {
+        self.hash_algorithm
+    }
+
     pub fn get_has_golden_ticket(&self) -> bool {
         self.has_golden_ticket
     }
@@ -326,23 +655,161 @@ impl Block {
         self.fee_transaction_idx
     }
 
+    /// The golden ticket transaction, if the block starts with one.
+    pub fn get_golden_ticket_transaction(&self) -> Option<&Transaction> {
+        self.transactions
+            .get(GOLDEN_TICKET_IDX)
+            .filter(|transaction| transaction.is_golden_ticket())
+    }
+
+    /// The fee transaction, if the block ends with one.
+    pub fn get_fee_transaction(&self) -> Option<&Transaction> {
+        self.transactions
+            .last()
+            .filter(|transaction| transaction.is_fee_transaction())
+    }
+
     pub fn get_pre_hash(&self) -> SaitoHash {
         self.pre_hash
-            .unwrap_or_else(|| hash(&self.serialize_for_signature()))
+            .unwrap_or_else(|| hash_with(self.hash_algorithm, &self.serialize_for_signature()))
     }
 
     pub fn get_total_fees(&self) -> u64 {
         self.total_fees
     }
 
+    /// The bytes counted against ConsensusParams::MAX_BLOCK_SIZE: the header and every
+    /// transaction except the ATR and fee transactions, with their length prefixes.
+    pub fn get_limited_size(&self) -> usize {
+        BLOCK_HEADER_SIZE
+            + self
+                .transactions
+                .iter()
+                .filter(|transaction| {
+                    !transaction.is_atr_transaction() && !transaction.is_fee_transaction()
+                })
+                .map(|transaction| {
+                    TRANSACTION_LENGTH_PREFIX_SIZE + transaction.get_serialized_size()
+                })
+                .sum::<usize>()
+    }
+
+    /// The length of the whole block as serialized for the network, counted against
+    /// ConsensusParams::MAX_SERIALIZED_BLOCK_SIZE.
+    pub fn get_serialized_size(&self) -> usize {
+        BLOCK_HEADER_SIZE
+            + self
+                .transactions
+                .iter()
+                .map(|transaction| {
+                    TRANSACTION_LENGTH_PREFIX_SIZE + transaction.get_serialized_size()
+                })
+                .sum::<usize>()
+    }
+
     pub fn get_routing_work_for_creator(&self) -> u64 {
         self.routing_work_for_creator
     }
 
+    pub fn get_signatures_verified(&self) -> bool {
+        self.signatures_verified
+    }
+
     pub fn get_source_connection_id(&self) -> Option<SaitoHash> {
         self.source_connection_id
     }
 
+    /// A readable view of the block for the REST API. Hashes and keys are hex encoded and the
+    /// transactions are nested, see Transaction::to_json.
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut json = self.header_to_json();
+        json["transactions"] = self
+            .transactions
+            .iter()
+            .map(Transaction::to_json)
+            .collect::<Vec<_>>()
+            .into();
+        json
+    }
+
+    /// The readable view of the block without its transactions.
+    pub fn header_to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "hash": self.hash.map(hex::encode),
+            "id": self.id,
+            "timestamp": self.timestamp,
+            "previous_block_hash": hex::encode(self.previous_block_hash),
+            "creator": hex::encode(self.creator),
+            "merkle_root": hex::encode(self.merkle_root),
+            "signature": hex::encode(self.signature),
+            "treasury": self.treasury,
+            "staking_treasury": self.staking_treasury,
+            "burnfee": self.burnfee,
+            "difficulty": self.difficulty,
+            "hash_algorithm": format!("{:?}", self.hash_algorithm),
+            "total_fees": self.total_fees,
+            "block_type": format!("{:?}", self.block_type),
+        })
+    }
+
+    //
+    // the fee transaction has been checked against the payouts the consensus
+    // values expect when the block was validated. recomputing the payouts
+    // later would draw the staker from the staking table as it is now rather
+    // than as it was, so the fee transaction is the record of who was paid.
+    //
+    pub fn get_payout_breakdown(&self) -> PayoutBreakdown {
+        let mut payout_breakdown = PayoutBreakdown::default();
+        if let Some(fee_transaction) = self.get_fee_transaction() {
+            for output in fee_transaction.get_outputs() {
+                let recipient = PayoutRecipient {
+                    publickey: hex::encode(output.get_publickey()),
+                    amount: output.get_amount().into(),
+                };
+                match output.get_slip_type() {
+                    SlipType::MinerOutput => payout_breakdown.miners.push(recipient),
+                    SlipType::RouterOutput => payout_breakdown.routers.push(recipient),
+                    SlipType::StakerOutput | SlipType::StakerPayout => {
+                        payout_breakdown.stakers.push(recipient)
+                    }
+                    _ => {}
+                }
+            }
+        }
+        payout_breakdown
+    }
+
+    pub fn get_atr_stats(&self) -> AtrStats {
+        AtrStats {
+            rebroadcast_slips: self.total_rebroadcast_slips,
+            rebroadcast_nolan: self.total_rebroadcast_nolan,
+            rebroadcast_fees_nolan: self.total_rebroadcast_slips * ATR_FEE,
+            rebroadcast_hash: hex::encode(self.rebroadcast_hash),
+        }
+    }
+
+    pub fn get_explorer_block(&self) -> ExplorerBlock {
+        ExplorerBlock {
+            header: self.header_to_json(),
+            transactions: self
+                .transactions
+                .iter()
+                .map(|transaction| TransactionSummary {
+                    hash: transaction.get_hash_for_signature().map(hex::encode),
+                    transaction_type: format!("{:?}", transaction.get_transaction_type()),
+                    timestamp: transaction.get_timestamp(),
+                    input_count: transaction.get_inputs().len(),
+                    output_count: transaction.get_outputs().len(),
+                    total_in: transaction.get_total_in(),
+                    total_out: transaction.get_total_out(),
+                    total_fees: transaction.get_total_fees(),
+                })
+                .collect(),
+            payouts: self.get_payout_breakdown(),
+            atr: self.get_atr_stats(),
+        }
+    }
+
     pub fn set_routing_work_for_creator(&mut self, routing_work_for_creator: u64) {
         self.routing_work_for_creator = routing_work_for_creator;
     }
@@ -387,6 +854,7 @@ impl Block {
     pub fn set_transactions(&mut self, transactions: &mut Vec<Transaction>) {
         self.transactions = transactions.to_vec();
         self.hash = None;
+        self.signatures_verified = false;
     }
 
     pub fn set_block_type(&mut self, block_type: BlockType) {
@@ -451,6 +919,10 @@ impl Block {
         self.hash = None;
     }
 
+    pub fn set_hash_algorithm(&mut self, hash_algorithm: HashAlgorithm) {
+        self.hash_algorithm = hash_algorithm;
+    }
+
     pub fn set_difficulty(&mut self, difficulty: u64) {
         self.difficulty = difficulty;
         self.pre_hash = None;
@@ -463,6 +935,8 @@ impl Block {
 
     pub fn add_transaction(&mut self, tx: Transaction) {
         self.transactions.push(tx);
+        self.hash = None;
+        self.signatures_verified = false;
     }
 
     //
@@ -470,7 +944,11 @@ impl Block {
     // data that is necessary for blocks of that type if possible. if this is
     // not possible, return false. if it is possible, return true once upgraded.
     //
-    pub async fn upgrade_block_to_block_type(&mut self, block_type: BlockType) -> bool {
+    pub async fn upgrade_block_to_block_type(
+        &mut self,
+        block_type: BlockType,
+        block_storage: &dyn BlockStorage,
+    ) -> bool {
         let _span = span!(Level::TRACE, "UPGRADE BLOCK");
         trace!("UPGRADE_BLOCK_TO_BLOCK_TYPE {:?}", self.block_type);
         if self.block_type == block_type {
@@ -487,8 +965,18 @@ impl Block {
         // load the block if it exists on disk.
         //
         if block_type == BlockType::Full {
-            let mut new_block =
-                Storage::load_block_from_disk(Storage::generate_block_filename(&self)).await;
+            let block_hash = self.get_hash();
+            let mut new_block = match block_storage.read_block(self.timestamp, &block_hash) {
+                Ok(block) => block,
+                Err(err) => {
+                    error!(
+                        "ERROR 290314: cannot load block {}: {}",
+                        hex::encode(block_hash),
+                        err
+                    );
+                    return false;
+                }
+            };
 
             new_block.generate_hashes();
 
@@ -496,6 +984,7 @@ impl Block {
             // in-memory swap copying txs in block from mempool
             //
             mem::swap(&mut new_block.transactions, &mut self.transactions);
+            self.signatures_verified = false;
             //
             // transactions need hashes
             //
@@ -534,6 +1023,29 @@ impl Block {
         false
     }
 
+    /// Moves a block bundled ahead of time to the timestamp it is produced at, setting the
+    /// burnfee for that timestamp and signing the block again. Nothing else in a block depends
+    /// on its timestamp.
+    pub fn restamp(
+        &mut self,
+        current_timestamp: u64,
+        previous_block_burnfee: u64,
+        previous_block_timestamp: u64,
+        publickey: SaitoPublicKey,
+        privatekey: SaitoPrivateKey,
+    ) {
+        self.set_timestamp(current_timestamp);
+        self.set_burnfee(
+            BurnFee::return_burnfee_for_block_produced_at_current_timestamp_in_nolan(
+                previous_block_burnfee,
+                current_timestamp,
+                previous_block_timestamp,
+            ),
+        );
+        self.hash = None;
+        self.sign(publickey, privatekey);
+    }
+
     pub fn sign(&mut self, publickey: SaitoPublicKey, privatekey: SaitoPrivateKey) {
         //
         // we set final data
@@ -588,6 +1100,7 @@ impl Block {
         vbytes.extend(&self.staking_treasury.to_be_bytes());
         vbytes.extend(&self.burnfee.to_be_bytes());
         vbytes.extend(&self.difficulty.to_be_bytes());
+        vbytes.extend(&(self.hash_algorithm as u8).to_be_bytes());
         vbytes
     }
 
@@ -603,7 +1116,8 @@ impl Block {
     /// [staking_treasury - 8 bytes - u64]
     /// [burnfee - 8 bytes - u64]
     /// [difficulty - 8 bytes - u64]
-    /// [transaction][transaction][transaction]...
+    /// [hash algorithm - 1 byte]
+    /// [len of transaction - 4 bytes - u32][transaction]...
     pub fn serialize_for_net(&self, block_type: BlockType) -> Vec<u8> {
         let mut vbytes: Vec<u8> = vec![];
 
@@ -624,13 +1138,16 @@ impl Block {
         vbytes.extend(&self.staking_treasury.to_be_bytes());
         vbytes.extend(&self.burnfee.to_be_bytes());
         vbytes.extend(&self.difficulty.to_be_bytes());
+        vbytes.extend(&(self.hash_algorithm as u8).to_be_bytes());
 
         let mut serialized_txs = vec![];
 
         // block headers do not get tx data
         if block_type != BlockType::Header {
             self.transactions.iter().for_each(|transaction| {
-                serialized_txs.extend(transaction.serialize_for_net());
+                let serialized_tx = transaction.serialize_for_net();
+                serialized_txs.extend(&(serialized_tx.len() as u32).to_be_bytes());
+                serialized_txs.extend(serialized_tx);
             });
             vbytes.extend(serialized_txs);
         }
@@ -638,7 +1155,37 @@ impl Block {
         vbytes
     }
 
-    /// Deserialize from bytes to a Block.
+    /// Like deserialize_for_net, but for bytes from outside the node: None unless the bytes are
+    /// exactly one block, with a known hash algorithm and transactions which
+    /// Transaction::try_deserialize_from_net accepts.
+    pub fn try_deserialize_for_net(bytes: &[u8]) -> Option<Block> {
+        if bytes.len() < BLOCK_HEADER_SIZE || HashAlgorithm::try_from(bytes[213]).is_err() {
+            return None;
+        }
+        let transactions_len = u32::from_be_bytes(bytes[0..4].try_into().unwrap());
+        let mut start_of_transaction = BLOCK_HEADER_SIZE;
+        for _ in 0..transactions_len {
+            let start_of_transaction_data = start_of_transaction + TRANSACTION_LENGTH_PREFIX_SIZE;
+            let transaction_len = u32::from_be_bytes(
+                bytes
+                    .get(start_of_transaction..start_of_transaction_data)?
+                    .try_into()
+                    .unwrap(),
+            ) as usize;
+            let end_of_transaction_data = start_of_transaction_data + transaction_len;
+            Transaction::try_deserialize_from_net(
+                bytes.get(start_of_transaction_data..end_of_transaction_data)?,
+            )?;
+            start_of_transaction = end_of_transaction_data;
+        }
+        if start_of_transaction != bytes.len() {
+            return None;
+        }
+        Some(Block::deserialize_for_net(bytes))
+    }
+
+    /// Deserialize from bytes to a Block. Panics on bytes which are not a block, so it is only
+    /// for bytes the node wrote itself, see try_deserialize_for_net for anything else.
     /// [len of transactions - 4 bytes - u32]
     /// [id - 8 bytes - u64]
     /// [timestamp - 8 bytes - u64]
@@ -650,8 +1197,12 @@ impl Block {
     /// [staking_treasury - 8 bytes - u64]
     /// [burnfee - 8 bytes - u64]
     /// [difficulty - 8 bytes - u64]
-    /// [transaction][transaction][transaction]...
-    pub fn deserialize_for_net(bytes: &Vec<u8>) -> Block {
+    /// [hash algorithm - 1 byte]
+    /// [len of transaction - 4 bytes - u32][transaction]...
+    ///
+    /// Panics if the length of a transaction does not match the lengths of the slips, message
+    /// and hops it says it has.
+    pub fn deserialize_for_net(bytes: &[u8]) -> Block {
         let transactions_len: u32 = u32::from_be_bytes(bytes[0..4].try_into().unwrap());
         let id: u64 = u64::from_be_bytes(bytes[4..12].try_into().unwrap());
         let timestamp: u64 = u64::from_be_bytes(bytes[12..20].try_into().unwrap());
@@ -665,9 +1216,22 @@ impl Block {
 
         let burnfee: u64 = u64::from_be_bytes(bytes[197..205].try_into().unwrap());
         let difficulty: u64 = u64::from_be_bytes(bytes[205..213].try_into().unwrap());
-        let mut transactions = vec![];
+        let hash_algorithm = HashAlgorithm::try_from(bytes[213]).unwrap();
+
+        //
+        // the transactions are found with a first pass over their lengths, so
+        // that they can then be deserialized in parallel
+        //
+        let mut transaction_offsets = vec![];
         let mut start_of_transaction_data = BLOCK_HEADER_SIZE;
-        for _n in 0..transactions_len {
+        for n in 0..transactions_len {
+            let transaction_len = u32::from_be_bytes(
+                bytes[start_of_transaction_data
+                    ..start_of_transaction_data + TRANSACTION_LENGTH_PREFIX_SIZE]
+                    .try_into()
+                    .unwrap(),
+            ) as usize;
+            start_of_transaction_data += TRANSACTION_LENGTH_PREFIX_SIZE;
             let inputs_len: u32 = u32::from_be_bytes(
                 bytes[start_of_transaction_data..start_of_transaction_data + 4]
                     .try_into()
@@ -688,17 +1252,30 @@ impl Block {
                     .try_into()
                     .unwrap(),
             ) as usize;
-            let end_of_transaction_data = start_of_transaction_data
-                + TRANSACTION_SIZE
-                + ((inputs_len + outputs_len) as usize * SLIP_SIZE)
-                + message_len
-                + path_len as usize * HOP_SIZE;
-            let transaction = Transaction::deserialize_from_net(
-                bytes[start_of_transaction_data..end_of_transaction_data].to_vec(),
+            let calculated_transaction_len = Transaction::calculate_serialized_size(
+                inputs_len as usize,
+                outputs_len as usize,
+                message_len,
+                path_len,
             );
-            transactions.push(transaction);
+            if calculated_transaction_len != transaction_len {
+                panic!(
+                    "transaction {} of block {} is framed as {} bytes but is laid out as {}",
+                    n, id, transaction_len, calculated_transaction_len
+                );
+            }
+            let end_of_transaction_data = start_of_transaction_data + transaction_len;
+            transaction_offsets.push((start_of_transaction_data, end_of_transaction_data));
             start_of_transaction_data = end_of_transaction_data;
         }
+        let mut transactions: Vec<Transaction> = transaction_offsets
+            .par_iter()
+            .map(|(start_of_transaction_data, end_of_transaction_data)| {
+                Transaction::deserialize_from_net(
+                    bytes[*start_of_transaction_data..*end_of_transaction_data].to_vec(),
+                )
+            })
+            .collect();
 
         let mut block = Block::new();
         block.set_id(id);
@@ -710,6 +1287,7 @@ impl Block {
         block.set_treasury(treasury);
         block.set_burnfee(burnfee);
         block.set_difficulty(difficulty);
+        block.set_hash_algorithm(hash_algorithm);
         block.set_staking_treasury(staking_treasury);
         block.set_transactions(&mut transactions);
         if transactions_len == 0 {
@@ -719,6 +1297,19 @@ impl Block {
         block
     }
 
+    /// The rebroadcast hash after the ATR transaction, which hashes all ATR transactions of
+    /// the block together in order.
+    pub fn chain_rebroadcast_hash(
+        hash_algorithm: HashAlgorithm,
+        rebroadcast_hash: SaitoHash,
+        transaction: &Transaction,
+    ) -> SaitoHash {
+        let mut vbytes: Vec<u8> = vec![];
+        vbytes.extend(&rebroadcast_hash);
+        vbytes.extend(&transaction.serialize_for_signature());
+        hash_with(hash_algorithm, &vbytes)
+    }
+
     //
     // TODO - this logic should probably be in the merkle-root class
     //
@@ -764,7 +1355,7 @@ impl Block {
             // hash the parent in parallel
             mrv[start_point..stop_point]
                 .par_iter_mut()
-                .all(|leaf| leaf.hash());
+                .all(|leaf| leaf.hash(self.hash_algorithm));
 
             let start_point_old = start_point;
             start_point = mrv.len();
@@ -792,39 +1383,49 @@ impl Block {
         //
         // hash the final leaf
         //
-        mrv[start_point].hash();
+        mrv[start_point].hash(self.hash_algorithm);
         mrv[start_point].get_hash()
     }
 
     //
     // generate hashes and payouts and fee calculations
     //
-    pub async fn generate_consensus_values(&self, blockchain: &Blockchain) -> ConsensusValues {
+    pub async fn generate_consensus_values(
+        &self,
+        blockchain: &Blockchain,
+    ) -> Result<ConsensusValues, BlockValidationError> {
         let mut cv = ConsensusValues::new();
 
         //
         // calculate total fees
         //
-        // calculate fee and golden ticket and issuance (block 1) indices
+        // count the fee, golden ticket and issuance (block 1) transactions. the
+        // golden ticket and fee transaction have places of their own, so only
+        // the issuance transaction needs its index noted.
         //
-        let mut idx: usize = 0;
-        for transaction in &self.transactions {
+        for (idx, transaction) in self.transactions.iter().enumerate() {
             if !transaction.is_fee_transaction() {
-                cv.total_fees += transaction.get_total_fees();
+                cv.total_fees = Amount::from(cv.total_fees)
+                    .checked_add(transaction.get_total_fees().into())
+                    .ok_or(BlockValidationError::FeesOverflow)?
+                    .into();
             } else {
                 cv.ft_num += 1;
-                cv.ft_idx = Some(idx);
             }
             if transaction.is_golden_ticket() {
                 cv.gt_num += 1;
-                cv.gt_idx = Some(idx);
             }
             if transaction.is_issuance_transaction() {
                 cv.it_num += 1;
                 cv.it_idx = Some(idx);
             }
-            idx += 1;
         }
+        cv.gt_idx = self
+            .get_golden_ticket_transaction()
+            .map(|_| GOLDEN_TICKET_IDX);
+        cv.ft_idx = self
+            .get_fee_transaction()
+            .map(|_| self.transactions.len() - 1);
 
         //
         // calculate expected burn-fee
@@ -861,6 +1462,8 @@ impl Block {
             // ATR rebroadcasts calculated.
             //
             if let Some(pruned_block) = blockchain.blocks.get(&pruned_block_hash) {
+                let mut rebroadcast_size = 0;
+
                 //
                 // identify all unspent transactions
                 //
@@ -870,30 +1473,50 @@ impl Block {
                         // valid means spendable and non-zero
                         //
                         if output.validate(&blockchain.utxoset) {
-                            if output.get_amount() > 200_000_000 {
-                                cv.total_rebroadcast_nolan += output.get_amount();
-                                cv.total_rebroadcast_fees_nolan += 200_000_000;
-                                cv.total_rebroadcast_slips += 1;
+                            //
+                            // create rebroadcast transaction, unless the ATR transactions,
+                            // which copy the transactions they rebroadcast, would no longer
+                            // fit in the block
+                            //
+                            // TODO - floating fee based on previous block average
+                            //
+                            let mut rebroadcast_transaction = None;
+                            if output.get_amount() > Amount::from(ATR_FEE) {
+                                let transaction = Transaction::generate_rebroadcast_transaction(
+                                    &transaction,
+                                    output,
+                                    ATR_FEE,
+                                );
+                                let size = TRANSACTION_LENGTH_PREFIX_SIZE
+                                    + transaction.get_serialized_size();
+                                if rebroadcast_size + size <= ConsensusParams::MAX_REBROADCAST_SIZE
+                                {
+                                    rebroadcast_size += size;
+                                    rebroadcast_transaction = Some(transaction);
+                                }
+                            }
 
-                                //
-                                // create rebroadcast transaction
-                                //
-                                // TODO - floating fee based on previous block average
-                                //
-                                let rebroadcast_transaction =
-                                    Transaction::generate_rebroadcast_transaction(
-                                        &transaction,
-                                        output,
-                                        200_000_000,
-                                    );
+                            if let Some(rebroadcast_transaction) = rebroadcast_transaction {
+                                cv.total_rebroadcast_nolan =
+                                    Amount::from(cv.total_rebroadcast_nolan)
+                                        .checked_add(output.get_amount())
+                                        .ok_or(BlockValidationError::FeesOverflow)?
+                                        .into();
+                                cv.total_rebroadcast_fees_nolan =
+                                    Amount::from(cv.total_rebroadcast_fees_nolan)
+                                        .checked_add(ATR_FEE.into())
+                                        .ok_or(BlockValidationError::FeesOverflow)?
+                                        .into();
+                                cv.total_rebroadcast_slips += 1;
 
                                 //
                                 // update cryptographic hash of all ATRs
                                 //
-                                let mut vbytes: Vec<u8> = vec![];
-                                vbytes.extend(&cv.rebroadcast_hash);
-                                vbytes.extend(&rebroadcast_transaction.serialize_for_signature());
-                                cv.rebroadcast_hash = hash(&vbytes);
+                                cv.rebroadcast_hash = Block::chain_rebroadcast_hash(
+                                    self.hash_algorithm,
+                                    cv.rebroadcast_hash,
+                                    &rebroadcast_transaction,
+                                );
 
                                 cv.rebroadcasts.push(rebroadcast_transaction);
                             } else {
@@ -902,9 +1525,14 @@ impl Block {
                                 // distributed as a fee for the next block producer. for now
                                 // we will simply distribute it as a fee. we may need to
                                 // change this if the DUST becomes a significant enough amount
-                                // each block to reduce consensus security.
+                                // each block to reduce consensus security. slips past the cap
+                                // on ATR transactions are collected the same way.
                                 //
-                                cv.total_rebroadcast_fees_nolan += output.get_amount();
+                                cv.total_rebroadcast_fees_nolan =
+                                    Amount::from(cv.total_rebroadcast_fees_nolan)
+                                        .checked_add(output.get_amount())
+                                        .ok_or(BlockValidationError::FeesOverflow)?
+                                        .into();
                             }
                         }
                     }
@@ -920,24 +1548,26 @@ impl Block {
                 self.transactions[gt_idx].get_message().to_vec(),
             );
             // generate input hash for router
-            let mut next_random_number = hash(&golden_ticket.get_random().to_vec());
+            let mut random_numbers = HashChain::new(golden_ticket.get_random());
             let _miner_publickey = golden_ticket.get_publickey();
 
             //
             // miner payout is fees from previous block, no staking treasury
             //
             if let Some(previous_block) = blockchain.blocks.get(&self.get_previous_block_hash()) {
-                let miner_payment = previous_block.get_total_fees() / 2;
-                let router_payment = previous_block.get_total_fees() - miner_payment;
+                let (miner_payment, router_payment) =
+                    Amount::from(previous_block.get_total_fees()).split_in_half();
+                let (miner_payment, router_payment): (u64, u64) =
+                    (miner_payment.into(), router_payment.into());
 
                 //
                 // calculate miner and router payments
                 //
-                let router_publickey = previous_block.find_winning_router(next_random_number);
+                let router_publickey =
+                    previous_block.find_winning_router(random_numbers.get_random_number());
 
                 // these two from find_winning_router - 3, 4
-                next_random_number = hash(&next_random_number.to_vec());
-                next_random_number = hash(&next_random_number.to_vec());
+                random_numbers.advance(2);
 
                 let mut payout = BlockPayout::new();
                 payout.miner = golden_ticket.get_publickey();
@@ -988,21 +1618,23 @@ impl Block {
                                 // be withheld for the staker treasury, which is what previous_staker_
                                 // payment is measuring.
                                 //
-                                let sp = staking_block.get_total_fees() / 2;
-                                let rp = staking_block.get_total_fees() - sp;
+                                let (sp, rp) =
+                                    Amount::from(staking_block.get_total_fees()).split_in_half();
+                                let (sp, rp): (u64, u64) = (sp.into(), rp.into());
 
                                 let mut payout = BlockPayout::new();
-                                payout.router =
-                                    staking_block.find_winning_router(next_random_number);
+                                payout.router = staking_block
+                                    .find_winning_router(random_numbers.get_random_number());
                                 payout.router_payout = rp;
-                                payout.staking_treasury = sp as i64;
+                                payout.staking_treasury = i64::try_from(sp)
+                                    .map_err(|_| BlockValidationError::StakingTreasuryOverflow)?;
 
                                 // router consumes 2 hashes
-                                next_random_number = hash(&next_random_number.to_vec());
-                                next_random_number = hash(&next_random_number.to_vec());
+                                random_numbers.advance(2);
 
-                                let staker_slip_option =
-                                    blockchain.staking.find_winning_staker(next_random_number);
+                                let staker_slip_option = blockchain
+                                    .staking
+                                    .find_winning_staker(random_numbers.get_random_number());
                                 if let Some(staker_slip) = staker_slip_option {
                                     let mut slip_was_spent = 0;
 
@@ -1036,12 +1668,21 @@ impl Block {
                                     //
                                     if slip_was_spent == 0 {
                                         payout.staker = staker_slip.get_publickey();
-                                        payout.staker_payout =
-                                            staker_slip.get_amount() + staker_slip.get_payout();
+                                        payout.staker_payout = staker_slip
+                                            .get_amount()
+                                            .checked_add(staker_slip.get_payout().into())
+                                            .ok_or(BlockValidationError::PayoutOverflow)?
+                                            .into();
                                         payout.staker_slip = staker_slip.clone();
+                                        if let Some(delegate) = blockchain
+                                            .staking
+                                            .get_delegate(&staker_slip.get_publickey())
+                                        {
+                                            payout.staker_delegate = delegate;
+                                        }
                                     }
 
-                                    next_random_number = hash(&next_random_number.to_vec());
+                                    random_numbers.advance(1);
 
                                     cv.block_payout.push(payout);
                                 }
@@ -1062,7 +1703,7 @@ impl Block {
                 if cv.block_payout[i].miner != [0; 33] {
                     let mut output = Slip::new();
                     output.set_publickey(cv.block_payout[i].miner);
-                    output.set_amount(cv.block_payout[i].miner_payout);
+                    output.set_amount(cv.block_payout[i].miner_payout.into());
                     output.set_slip_type(SlipType::MinerOutput);
                     output.set_slip_ordinal(slip_ordinal);
                     transaction.add_output(output.clone());
@@ -1071,7 +1712,7 @@ impl Block {
                 if cv.block_payout[i].router != [0; 33] {
                     let mut output = Slip::new();
                     output.set_publickey(cv.block_payout[i].router);
-                    output.set_amount(cv.block_payout[i].router_payout);
+                    output.set_amount(cv.block_payout[i].router_payout.into());
                     output.set_slip_type(SlipType::RouterOutput);
                     output.set_slip_ordinal(slip_ordinal);
                     transaction.add_output(output.clone());
@@ -1080,18 +1721,50 @@ impl Block {
                 if cv.block_payout[i].staker != [0; 33] {
                     transaction.add_input(cv.block_payout[i].staker_slip.clone());
 
+                    //
+                    // a delegated stake keeps staking with its staker key while
+                    // the return on it is paid out to the delegated key
+                    //
+                    let mut staker_amount = Amount::from(cv.block_payout[i].staker_payout);
+                    if cv.block_payout[i].staker_delegate != [0; 33] {
+                        staker_amount = cv.block_payout[i].staker_slip.get_amount();
+                    }
+
                     let mut output = Slip::new();
                     output.set_publickey(cv.block_payout[i].staker);
-                    output.set_amount(cv.block_payout[i].staker_payout);
+                    output.set_amount(staker_amount);
                     output.set_slip_type(SlipType::StakerOutput);
                     output.set_slip_ordinal(slip_ordinal);
                     transaction.add_output(output);
                     slip_ordinal += 1;
-                    cv.staking_treasury += cv.block_payout[i].staking_treasury;
-                    cv.staking_treasury -= cv.block_payout[i].staker_payout as i64;
+
+                    let delegate_amount = Amount::from(cv.block_payout[i].staker_payout)
+                        .checked_sub(staker_amount)
+                        .ok_or(BlockValidationError::PayoutOverflow)?;
+                    if delegate_amount > Amount::ZERO {
+                        let mut output = Slip::new();
+                        output.set_publickey(cv.block_payout[i].staker_delegate);
+                        output.set_amount(delegate_amount);
+                        output.set_slip_type(SlipType::StakerPayout);
+                        output.set_slip_ordinal(slip_ordinal);
+                        transaction.add_output(output);
+                        slip_ordinal += 1;
+                    }
+                    let staker_payout = i64::try_from(cv.block_payout[i].staker_payout)
+                        .map_err(|_| BlockValidationError::StakingTreasuryOverflow)?;
+                    cv.staking_treasury = cv
+                        .staking_treasury
+                        .checked_add(cv.block_payout[i].staking_treasury)
+                        .and_then(|staking_treasury| staking_treasury.checked_sub(staker_payout))
+                        .ok_or(BlockValidationError::StakingTreasuryOverflow)?;
                 }
             }
 
+            //
+            // the UUIDs are set here so that the creator signs, and the validator
+            // compares, the same finished outputs
+            //
+            transaction.generate_output_uuids();
             cv.fee_transaction = Some(transaction);
         }
 
@@ -1132,7 +1805,7 @@ impl Block {
             }
         }
 
-        cv
+        Ok(cv)
     }
 
     // consumes two hashes every time
@@ -1244,12 +1917,17 @@ impl Block {
         let mut cumulative_fees = 0;
         let mut cumulative_work = 0;
 
-        let mut has_golden_ticket = false;
-        let mut has_fee_transaction = false;
         let mut has_issuance_transaction = false;
         let mut issuance_transaction_idx = 0;
-        let mut golden_ticket_idx = 0;
-        let mut fee_transaction_idx = 0;
+
+        //
+        // metadata is generated more than once for a block, e.g. by
+        // Block::generate and again when the block is added, so the ATR
+        // figures are counted afresh rather than added to
+        //
+        self.total_rebroadcast_slips = 0;
+        self.total_rebroadcast_nolan = 0;
+        self.rebroadcast_hash = [0; 32];
 
         //
         // we have to do a single sweep through all of the transactions in
@@ -1264,7 +1942,13 @@ impl Block {
         for i in 0..self.transactions.len() {
             let transaction = &mut self.transactions[i];
 
-            cumulative_fees = transaction.generate_metadata_cumulative_fees(cumulative_fees);
+            cumulative_fees = match transaction.generate_metadata_cumulative_fees(cumulative_fees) {
+                Some(cumulative_fees) => cumulative_fees,
+                None => {
+                    error!("ERROR 582045: fees overflow in block {}", self.id);
+                    return false;
+                }
+            };
             cumulative_work = transaction.generate_metadata_cumulative_work(cumulative_work);
 
             //
@@ -1300,33 +1984,51 @@ impl Block {
                     has_issuance_transaction = true;
                     issuance_transaction_idx = i as u64;
                 }
-                TransactionType::Fee => {
-                    has_fee_transaction = true;
-                    fee_transaction_idx = i as u64;
-                }
-                TransactionType::GoldenTicket => {
-                    has_golden_ticket = true;
-                    golden_ticket_idx = i as u64;
-                }
                 TransactionType::ATR => {
-                    let mut vbytes: Vec<u8> = vec![];
-                    vbytes.extend(&self.rebroadcast_hash);
-                    vbytes.extend(&transaction.serialize_for_signature());
-                    self.rebroadcast_hash = hash(&vbytes);
+                    self.rebroadcast_hash = Block::chain_rebroadcast_hash(
+                        self.hash_algorithm,
+                        self.rebroadcast_hash,
+                        transaction,
+                    );
 
-                    for input in transaction.get_inputs() {
-                        self.total_rebroadcast_slips += 1;
-                        self.total_rebroadcast_nolan += input.get_amount();
+                    //
+                    // ATR transactions have no inputs, and pay out the slip
+                    // they rebroadcast less the ATR fee
+                    //
+                    for output in transaction.get_outputs() {
+                        let total_rebroadcast_nolan = output
+                            .get_amount()
+                            .checked_add(ATR_FEE.into())
+                            .and_then(|nolan| {
+                                nolan.checked_add(self.total_rebroadcast_nolan.into())
+                            });
+                        match total_rebroadcast_nolan {
+                            Some(total_rebroadcast_nolan) => {
+                                self.total_rebroadcast_slips += 1;
+                                self.total_rebroadcast_nolan = total_rebroadcast_nolan.into();
+                            }
+                            None => {
+                                error!(
+                                    "ERROR 582046: rebroadcast nolan overflows in block {}",
+                                    self.id
+                                );
+                                return false;
+                            }
+                        }
                     }
                 }
                 _ => {}
             };
         }
-        self.set_has_fee_transaction(has_fee_transaction);
-        self.set_has_golden_ticket(has_golden_ticket);
+        self.set_has_fee_transaction(self.get_fee_transaction().is_some());
+        self.set_has_golden_ticket(self.get_golden_ticket_transaction().is_some());
         self.set_has_issuance_transaction(has_issuance_transaction);
-        self.set_fee_transaction_idx(fee_transaction_idx);
-        self.set_golden_ticket_idx(golden_ticket_idx);
+        self.set_fee_transaction_idx(if self.has_fee_transaction {
+            self.transactions.len() as u64 - 1
+        } else {
+            0
+        });
+        self.set_golden_ticket_idx(GOLDEN_TICKET_IDX as u64);
         self.set_issuance_transaction_idx(issuance_transaction_idx);
 
         //
@@ -1344,37 +2046,101 @@ impl Block {
         true
     }
 
+    /// Checks what can be checked without the blockchain: the signatures of the creator and
+    /// of the transactions. Signature checks are the most expensive part of validation, so
+    /// received blocks are prevalidated before the blockchain lock is taken, and validate()
+    /// does not check the signatures of a prevalidated block again.
+    pub fn prevalidate(&mut self) -> Result<(), BlockValidationError> {
+        self.generate_metadata();
+
+        if !verify(
+            &self.get_pre_hash(),
+            self.get_signature(),
+            self.get_creator(),
+        ) {
+            return Err(BlockValidationError::InvalidSignature);
+        }
+        if !self
+            .transactions
+            .par_iter()
+            .all(|transaction| transaction.validate_signatures())
+        {
+            return Err(BlockValidationError::InvalidTransactions);
+        }
+
+        self.signatures_verified = true;
+        Ok(())
+    }
+
+    /// Prevalidates the blocks concurrently on the block validation pool, dropping those
+    /// that do not validate. Blocks from different peers are prevalidated at the same time
+    /// without holding any lock, and the pool steals work between the transactions of
+    /// large and small blocks.
+    pub async fn prevalidate_blocks(blocks: Vec<Block>) -> Vec<Block> {
+        if blocks.is_empty() {
+            return blocks;
+        }
+        tokio::task::spawn_blocking(move || {
+            BLOCK_VALIDATION_POOL.install(|| {
+                blocks
+                    .into_par_iter()
+                    .filter_map(|mut block| match block.prevalidate() {
+                        Ok(()) => Some(block),
+                        Err(validation_error) => {
+                            error!(
+                                "block {} dropped: {}",
+                                hex::encode(block.get_hash()),
+                                validation_error
+                            );
+                            None
+                        }
+                    })
+                    .collect()
+            })
+        })
+        .await
+        .expect("block validation pool panicked")
+    }
+
+    /// Validates the block, logging the reason if it does not validate.
     pub async fn validate(
         &self,
         blockchain: &Blockchain,
         utxoset: &AHashMap<SaitoUTXOSetKey, u64>,
         staking: &Staking,
     ) -> bool {
-        //
-        // no transactions? no thank you
-        //
-        if self.transactions.is_empty() {
-            error!("ERROR 424342: block does not validate as it has no transactions",);
-            return false;
+        match self.validate_stages(blockchain, utxoset, staking).await {
+            Ok(_) => true,
+            Err(validation_error) => {
+                error!("{}", validation_error);
+                false
+            }
         }
+    }
 
-        trace!(
-            " ... block.validate: (burn fee)  {:?}",
-            create_timestamp(),
-            // tracing_tracker.time_since_last();
-        );
-
+    /// Validates the block in stages ordered from the cheapest to the most expensive, so
+    /// that invalid blocks are rejected with as little work as possible. The first failing
+    /// check is returned, otherwise the consensus values the block was checked against.
+    pub async fn validate_stages(
+        &self,
+        blockchain: &Blockchain,
+        utxoset: &AHashMap<SaitoUTXOSetKey, u64>,
+        staking: &Staking,
+    ) -> Result<ConsensusValues, BlockValidationError> {
         //
-        // verify signed by creator
+        // Previous Block
         //
-        if !verify(
-            &self.get_pre_hash(),
-            self.get_signature(),
-            self.get_creator(),
-        ) {
-            error!("ERROR 582039: block is not signed by creator or signature does not validate",);
-            return false;
-        }
+        // many kinds of validation like the burn fee and the golden ticket solution
+        // require the existence of the previous block in order to validate. if no
+        // previous block exists, we are valid only in a limited number of
+        // circumstances, such as this being the first block we are adding to our chain.
+        //
+        let previous_block = blockchain.get_block_sync(&self.get_previous_block_hash());
+
+        self.validate_header(blockchain)?;
+        self.validate_body(previous_block)?;
+        self.validate_golden_ticket(previous_block)?;
+        self.validate_fee_transaction()?;
 
         //
         // Consensus Values
@@ -1383,76 +2149,71 @@ impl Block {
         // on its relationship to other blocks in the chain -- things like the burn
         // fee, the ATR transactions, the golden ticket solution and more.
         //
-        // the first step in validating our block is asking our software to calculate
+        // the next step in validating our block is asking our software to calculate
         // what it thinks this data should be. this same function should have been
         // used by the block creator to create this block, so consensus rules allow us
         // to validate it by checking the variables we can see in our block with what
         // they should be given this function.
         //
-        let cv = self.generate_consensus_values(&blockchain).await;
+        let cv = self.generate_consensus_values(blockchain).await?;
+
+        self.validate_consensus_values(blockchain, previous_block, &cv)?;
+        self.validate_atr(&cv)?;
+        self.validate_transactions(utxoset, staking)?;
+        Ok(cv)
+    }
 
+    /// Validates the fields of the block header: the creator's signature, the timestamp and
+    /// the burn fee. Only the header is needed, so blocks can be checked before their
+    /// transactions are downloaded.
+    pub fn validate_header(&self, blockchain: &Blockchain) -> Result<(), BlockValidationError> {
         //
-        // only block #1 can have an issuance transaction
+        // the hashes, and with them the signature, are only meaningful with the
+        // algorithm scheduled for the block id
         //
-        if cv.it_num > 0 && self.get_id() > 1 {
-            error!("ERROR: blockchain contains issuance after block 1 in chain",);
-            return false;
+        let expected_hash_algorithm = HashAlgorithm::for_block_id(self.get_id());
+        if self.get_hash_algorithm() != expected_hash_algorithm {
+            return Err(BlockValidationError::InvalidHashAlgorithm {
+                expected: expected_hash_algorithm,
+                found: self.get_hash_algorithm(),
+            });
         }
 
         //
-        // Previous Block
+        // verify signed by creator
         //
-        // many kinds of validation like the burn fee and the golden ticket solution
-        // require the existence of the previous block in order to validate. we put all
-        // of these validation steps below so they will have access to the previous block
+        if !verify(
+            &self.get_pre_hash(),
+            self.get_signature(),
+            self.get_creator(),
+        ) {
+            return Err(BlockValidationError::InvalidSignature);
+        }
+
         //
-        // if no previous block exists, we are valid only in a limited number of
-        // circumstances, such as this being the first block we are adding to our chain.
+        // blocks from the future could lower the burn fee they need to pay
         //
+        let adjusted_timestamp = blockchain.get_network_time().get_adjusted_timestamp();
+        if self.get_timestamp() > adjusted_timestamp.saturating_add(MAX_FUTURE_BLOCK_TIME) {
+            return Err(BlockValidationError::TimestampInFuture {
+                timestamp: self.get_timestamp(),
+                adjusted_timestamp,
+            });
+        }
+
         if let Some(previous_block) = blockchain.blocks.get(&self.get_previous_block_hash()) {
             //
-            // validate treasury
+            // validate timestamp
             //
-            if self.get_treasury() != previous_block.get_treasury() + cv.nolan_falling_off_chain {
-                error!(
-                    "ERROR: treasury does not validate: {} expected versus {} found",
-                    (previous_block.get_treasury() + cv.nolan_falling_off_chain),
-                    self.get_treasury(),
-                    // tracing_tracker.time_since_last();
-                );
-                return false;
+            if self.get_timestamp() <= previous_block.get_timestamp() {
+                return Err(BlockValidationError::TimestampBeforeParent {
+                    timestamp: self.get_timestamp(),
+                    parent_timestamp: previous_block.get_timestamp(),
+                });
             }
 
             //
-            // validate staking treasury
-            //
-            let mut adjusted_staking_treasury = previous_block.get_staking_treasury();
-            if cv.staking_treasury < 0 {
-                let x = cv.staking_treasury * -1;
-                if adjusted_staking_treasury > x as u64 {
-                    adjusted_staking_treasury -= x as u64;
-                } else {
-                    adjusted_staking_treasury = 0;
-                }
-            } else {
-                let x: u64 = cv.staking_treasury as u64;
-                adjusted_staking_treasury += x;
-            }
-
-            if self.get_staking_treasury() != adjusted_staking_treasury {
-                error!(
-                    "ERROR: staking treasury does not validate: {} expected versus {} found",
-                    adjusted_staking_treasury,
-                    self.get_staking_treasury(),
-                );
-                //     "ERROR: staking treasury does not validate: {} expected versus {} found",
-                //     adjusted_staking_treasury,
-                //     self.get_staking_treasury(),
-                return false;
-            }
-
-            //
-            // validate burn fee
+            // validate burn fee
             //
             let new_burnfee: u64 =
                 BurnFee::return_burnfee_for_block_produced_at_current_timestamp_in_nolan(
@@ -1461,21 +2222,92 @@ impl Block {
                     previous_block.get_timestamp(),
                 );
             if new_burnfee != self.get_burnfee() {
-                error!(
-                    "ERROR: burn fee does not validate, expected: {}",
-                    new_burnfee
-                );
-                return false;
+                return Err(BlockValidationError::InvalidBurnFee {
+                    expected: new_burnfee,
+                    found: self.get_burnfee(),
+                });
             }
 
             trace!(" ... burn fee in blk validated:  {:?}", create_timestamp());
+        }
 
-            //
-            // validate routing work required
-            //
-            // this checks the total amount of fees that need to be burned in this
-            // block to be considered valid according to consensus criteria.
-            //
+        Ok(())
+    }
+
+    fn validate_body(&self, previous_block: Option<&Block>) -> Result<(), BlockValidationError> {
+        //
+        // no transactions? no thank you
+        //
+        if self.transactions.is_empty() {
+            return Err(BlockValidationError::NoTransactions);
+        }
+
+        //
+        // a block solves the lottery of the previous block at most once, and
+        // pays it out only if it does
+        //
+        let golden_ticket_count = self
+            .transactions
+            .iter()
+            .filter(|transaction| transaction.is_golden_ticket())
+            .count();
+        let fee_transaction_count = self
+            .transactions
+            .iter()
+            .filter(|transaction| transaction.is_fee_transaction())
+            .count();
+        if golden_ticket_count > 1 {
+            return Err(BlockValidationError::MultipleGoldenTickets {
+                count: golden_ticket_count,
+            });
+        }
+        if fee_transaction_count > 1 {
+            return Err(BlockValidationError::MultipleFeeTransactions {
+                count: fee_transaction_count,
+            });
+        }
+        if fee_transaction_count > 0 && golden_ticket_count == 0 {
+            return Err(BlockValidationError::FeeTransactionWithoutGoldenTicket);
+        }
+
+        //
+        // the golden ticket goes first and the fee transaction last, so a
+        // block carrying either anywhere else is refused before any of its
+        // transactions are looked at more closely
+        //
+        let last_idx = self.transactions.len() - 1;
+        for (idx, transaction) in self.transactions.iter().enumerate() {
+            if transaction.is_golden_ticket() && idx != GOLDEN_TICKET_IDX {
+                return Err(BlockValidationError::MisplacedGoldenTicket { idx });
+            }
+            if transaction.is_fee_transaction() && idx != last_idx {
+                return Err(BlockValidationError::MisplacedFeeTransaction { idx });
+            }
+        }
+
+        //
+        // oversized blocks take too long to propagate and validate
+        //
+        if self.get_limited_size() > ConsensusParams::MAX_BLOCK_SIZE {
+            return Err(BlockValidationError::TooLarge {
+                size: self.get_limited_size(),
+                max: ConsensusParams::MAX_BLOCK_SIZE,
+            });
+        }
+        if self.get_serialized_size() > ConsensusParams::MAX_SERIALIZED_BLOCK_SIZE {
+            return Err(BlockValidationError::TooLarge {
+                size: self.get_serialized_size(),
+                max: ConsensusParams::MAX_SERIALIZED_BLOCK_SIZE,
+            });
+        }
+
+        //
+        // validate routing work required
+        //
+        // this checks the total amount of fees that need to be burned in this
+        // block to be considered valid according to consensus criteria.
+        //
+        if let Some(previous_block) = previous_block {
             let amount_of_routing_work_needed: u64 =
                 BurnFee::return_routing_work_needed_to_produce_block_in_nolan(
                     previous_block.get_burnfee(),
@@ -1483,117 +2315,139 @@ impl Block {
                     previous_block.get_timestamp(),
                 );
             if self.routing_work_for_creator < amount_of_routing_work_needed {
-                error!("Error 510293: block lacking adequate routing work from creator");
-                return false;
+                return Err(BlockValidationError::InsufficientRoutingWork {
+                    needed: amount_of_routing_work_needed,
+                    found: self.routing_work_for_creator,
+                });
             }
 
             trace!(" ... done routing work required: {:?}", create_timestamp());
+        }
 
-            //
-            // validate golden ticket
-            //
-            // the golden ticket is a special kind of transaction that stores the
-            // solution to the network-payment lottery in the transaction message
-            // field. it targets the hash of the previous block, which is why we
-            // tackle it's validation logic here.
-            //
-            // first we reconstruct the ticket, then calculate that the solution
-            // meets our consensus difficulty criteria. note that by this point in
-            // the validation process we have already examined the fee transaction
-            // which was generated using this solution. If the solution is invalid
-            // we find that out now, and it invalidates the block.
-            //
-            if let Some(gt_idx) = cv.gt_idx {
-                let golden_ticket: GoldenTicket = GoldenTicket::deserialize_for_transaction(
-                    self.get_transactions()[gt_idx].get_message().to_vec(),
-                );
-                let solution = GoldenTicket::generate_solution(
-                    previous_block.get_hash(),
-                    golden_ticket.get_random(),
-                    golden_ticket.get_publickey(),
-                );
-                if !GoldenTicket::is_valid_solution(solution, previous_block.get_difficulty()) {
-                    error!(
-                        "ERROR: Golden Ticket solution does not validate against previous block hash and difficulty"
-                    );
-                    return false;
-                }
+        Ok(())
+    }
+
+    //
+    // the golden ticket is a special kind of transaction that stores the
+    // solution to the network-payment lottery in the transaction message
+    // field. it targets the hash of the previous block, which is why it
+    // can only be validated when we have the previous block.
+    //
+    // we reconstruct the ticket, then calculate that the solution meets our
+    // consensus difficulty criteria. this happens before the fee transaction
+    // generated using this solution is examined, as an invalid solution
+    // invalidates the block.
+    //
+    fn validate_golden_ticket(
+        &self,
+        previous_block: Option<&Block>,
+    ) -> Result<(), BlockValidationError> {
+        let previous_block = match previous_block {
+            Some(previous_block) => previous_block,
+            None => return Ok(()),
+        };
+        if let Some(golden_ticket_transaction) = self.get_golden_ticket_transaction() {
+            let golden_ticket: GoldenTicket = GoldenTicket::deserialize_for_transaction(
+                golden_ticket_transaction.get_message().to_vec(),
+            );
+            let solution = GoldenTicket::generate_solution(
+                previous_block.get_hash(),
+                golden_ticket.get_random(),
+                golden_ticket.get_publickey(),
+            );
+            if !GoldenTicket::is_valid_solution(solution, previous_block.get_difficulty()) {
+                return Err(BlockValidationError::InvalidGoldenTicket);
             }
-            trace!(" ... golden ticket: (validated)  {:?}", create_timestamp());
         }
+        trace!(" ... golden ticket: (validated)  {:?}", create_timestamp());
 
-        trace!(" ... block.validate: (merkle rt) {:?}", create_timestamp());
+        Ok(())
+    }
 
-        //
-        // validate atr
-        //
-        // Automatic Transaction Rebroadcasts are removed programmatically from
-        // an earlier block in the blockchain and rebroadcast into the latest
-        // block, with a fee being deducted to keep the data on-chain. In order
-        // to validate ATR we need to make sure we have the correct number of
-        // transactions (and ONLY those transactions!) included in our block.
-        //
-        // we do this by comparing the total number of ATR slips and nolan
-        // which we counted in the generate_metadata() function, with the
-        // expected number given the consensus values we calculated earlier.
-        //
-        if cv.total_rebroadcast_slips != self.total_rebroadcast_slips {
-            error!("ERROR 624442: rebroadcast slips total incorrect");
-            return false;
-        }
-        if cv.total_rebroadcast_nolan != self.total_rebroadcast_nolan {
-            error!("ERROR 294018: rebroadcast nolan amount incorrect");
-            return false;
+    //
+    // the fee transaction is made by the block creator, who signs it like
+    // the block. its only inputs are the staker slips it pays out, each of
+    // which must be matched by a staker output to the same key. these rules
+    // are checked before the fee transaction is compared with the one the
+    // consensus values say the block should have.
+    //
+    fn validate_fee_transaction(&self) -> Result<(), BlockValidationError> {
+        let fee_transaction = match self.get_fee_transaction() {
+            Some(fee_transaction) => fee_transaction,
+            None => return Ok(()),
+        };
+        if !verify(
+            &fee_transaction.generate_hash_for_signature(),
+            fee_transaction.get_signature(),
+            self.get_creator(),
+        ) {
+            return Err(BlockValidationError::InvalidFeeTransactionSignature);
         }
-        if cv.rebroadcast_hash != self.rebroadcast_hash {
-            error!("ERROR 123422: hash of rebroadcast transactions incorrect");
-            return false;
+        let is_paid_staker = |input: &Slip| {
+            matches!(
+                input.get_slip_type(),
+                SlipType::StakerDeposit | SlipType::StakerOutput
+            ) && fee_transaction.get_outputs().iter().any(|output| {
+                output.get_slip_type() == SlipType::StakerOutput
+                    && output.get_publickey() == input.get_publickey()
+            })
+        };
+        if !fee_transaction.get_inputs().iter().all(is_paid_staker) {
+            return Err(BlockValidationError::InvalidFeeTransactionInputs);
         }
+        trace!(
+            " ... fee transaction: (validated)  {:?}",
+            create_timestamp()
+        );
+
+        Ok(())
+    }
 
+    fn validate_consensus_values(
+        &self,
+        blockchain: &Blockchain,
+        previous_block: Option<&Block>,
+        cv: &ConsensusValues,
+    ) -> Result<(), BlockValidationError> {
         //
-        // validate merkle root
+        // only block #1 can have an issuance transaction
         //
-        if self.get_merkle_root() == [0; 32]
-            && self.get_merkle_root() != self.generate_merkle_root()
-        {
-            error!("merkle root is unset or is invalid false 1");
-            return false;
+        if cv.it_num > 0 && self.get_id() > 1 {
+            return Err(BlockValidationError::IssuanceAfterFirstBlock);
         }
 
-        trace!(" ... block.validate: (cv-data)   {:?}", create_timestamp());
-
-        //
-        // validate fee transactions
         //
-        // if this block contains a golden ticket, we have to use the random
-        // number associated with the golden ticket to create a fee-transaction
-        // that stretches back into previous blocks and finds the winning nodes
-        // that should collect payment.
+        // VIP transactions only in the bootstrap window and from authorized keys
         //
-        if let (Some(ft_idx), Some(mut fee_transaction)) = (cv.ft_idx, cv.fee_transaction) {
+        if !blockchain.get_vip_policy().validate_block(self) {
+            return Err(BlockValidationError::VipPolicy);
+        }
+
+        if let Some(previous_block) = previous_block {
             //
-            // no golden ticket? invalid
+            // validate treasury
             //
-            if cv.gt_idx.is_none() {
-                error!("ERROR 48203: block appears to have fee transaction without golden ticket");
-                return false;
+            let expected_treasury = Amount::from(previous_block.get_treasury())
+                .checked_add(cv.nolan_falling_off_chain.into())
+                .ok_or(BlockValidationError::TreasuryOverflow)?;
+            if self.get_treasury() != u64::from(expected_treasury) {
+                return Err(BlockValidationError::InvalidTreasury {
+                    expected: expected_treasury.into(),
+                    found: self.get_treasury(),
+                });
             }
 
             //
-            // the fee transaction we receive from the CV needs to be updated with
-            // block-specific data in the same way that all of the transactions in
-            // the block have been. we must do this prior to comparing them.
+            // validate staking treasury
             //
-            fee_transaction.generate_metadata(self.get_creator());
-
-            let hash1 = hash(&fee_transaction.serialize_for_signature());
-            let hash2 = hash(&self.transactions[ft_idx].serialize_for_signature());
-            if hash1 != hash2 {
-                error!(
-                    "ERROR 627428: block {} fee transaction doesn't match cv fee transaction",
-                    self.get_id()
-                );
-                return false;
+            let adjusted_staking_treasury =
+                adjust_staking_treasury(previous_block.get_staking_treasury(), cv.staking_treasury)
+                    .ok_or(BlockValidationError::StakingTreasuryOverflow)?;
+            if self.get_staking_treasury() != adjusted_staking_treasury {
+                return Err(BlockValidationError::InvalidStakingTreasury {
+                    expected: adjusted_staking_treasury,
+                    found: self.get_staking_treasury(),
+                });
             }
         }
 
@@ -1611,40 +2465,137 @@ impl Block {
         // distinction is in practice less clean.
         //
         if cv.expected_difficulty != self.get_difficulty() {
-            error!(
-                "difficulty is false {} vs {}",
-                cv.expected_difficulty,
-                self.get_difficulty()
-            );
-            return false;
+            return Err(BlockValidationError::InvalidDifficulty {
+                expected: cv.expected_difficulty,
+                found: self.get_difficulty(),
+            });
         }
 
-        trace!(" ... block.validate: (txs valid) {:?}", create_timestamp());
+        //
+        // validate fee transactions
+        //
+        // if this block contains a golden ticket, we have to use the random
+        // number associated with the golden ticket to create a fee-transaction
+        // that stretches back into previous blocks and finds the winning nodes
+        // that should collect payment.
+        //
+        if let (Some(ft_idx), Some(fee_transaction)) = (cv.ft_idx, &cv.fee_transaction) {
+            //
+            // no golden ticket? invalid
+            //
+            if cv.gt_idx.is_none() {
+                return Err(BlockValidationError::FeeTransactionWithoutGoldenTicket);
+            }
+
+            //
+            // the UUIDs are not covered by the signature, so we compare them as well
+            //
+            let hash1 = fee_transaction.generate_hash_for_signature();
+            let hash2 = self.transactions[ft_idx].generate_hash_for_signature();
+            let uuids_match = fee_transaction
+                .get_outputs()
+                .iter()
+                .map(|output| output.get_uuid())
+                .eq(self.transactions[ft_idx]
+                    .get_outputs()
+                    .iter()
+                    .map(|output| output.get_uuid()));
+            if hash1 != hash2 || !uuids_match {
+                return Err(BlockValidationError::InvalidFeeTransaction {
+                    block_id: self.get_id(),
+                });
+            }
+        }
+
+        trace!(" ... block.validate: (cv-data)   {:?}", create_timestamp());
+
+        Ok(())
+    }
+
+    //
+    // Automatic Transaction Rebroadcasts are removed programmatically from
+    // an earlier block in the blockchain and rebroadcast into the latest
+    // block, with a fee being deducted to keep the data on-chain. In order
+    // to validate ATR we need to make sure we have the correct number of
+    // transactions (and ONLY those transactions!) included in our block.
+    //
+    // we do this by comparing the total number of ATR slips and nolan
+    // which we counted in the generate_metadata() function, with the
+    // expected number given the consensus values we calculated earlier.
+    //
+    fn validate_atr(&self, cv: &ConsensusValues) -> Result<(), BlockValidationError> {
+        if cv.total_rebroadcast_slips != self.total_rebroadcast_slips {
+            return Err(BlockValidationError::InvalidRebroadcastSlips);
+        }
+        if cv.total_rebroadcast_nolan != self.total_rebroadcast_nolan {
+            return Err(BlockValidationError::InvalidRebroadcastNolan);
+        }
+        if cv.rebroadcast_hash != self.rebroadcast_hash {
+            return Err(BlockValidationError::InvalidRebroadcastHash);
+        }
 
+        Ok(())
+    }
+
+    //
+    // validating transactions requires checking that the signatures are valid,
+    // the routing paths are valid, and all of the input slips are pointing
+    // to spendable tokens that exist in our UTXOSET. this logic is separate
+    // from the validation of block-level variables, so is handled in the
+    // transaction objects.
+    //
+    // this is one of the most computationally intensive parts of processing a
+    // block which is why we handle it in parallel and last. the exact logic
+    // needed to examine a transaction may depend on the transaction itself, as
+    // we have some specific types (Fee / ATR / etc.) that are generated
+    // automatically and may have different requirements.
+    //
+    // the validation logic for transactions is contained in the transaction
+    // class, and the validation logic for slips is contained in the slips
+    // class. Note that we are passing in a read-only copy of our UTXOSet so
+    // as to determine spendability.
+    //
+    fn validate_transactions(
+        &self,
+        utxoset: &AHashMap<SaitoUTXOSetKey, u64>,
+        staking: &Staking,
+    ) -> Result<(), BlockValidationError> {
         //
-        // validate transactions
+        // validate merkle root
         //
-        // validating transactions requires checking that the signatures are valid,
-        // the routing paths are valid, and all of the input slips are pointing
-        // to spendable tokens that exist in our UTXOSET. this logic is separate
-        // from the validation of block-level variables, so is handled in the
-        // transaction objects.
+        if self.get_merkle_root() == [0; 32]
+            && self.get_merkle_root() != self.generate_merkle_root()
+        {
+            return Err(BlockValidationError::InvalidMerkleRoot);
+        }
+
         //
-        // this is one of the most computationally intensive parts of processing a
-        // block which is why we handle it in parallel. the exact logic needed to
-        // examine a transaction may depend on the transaction itself, as we have
-        // some specific types (Fee / ATR / etc.) that are generated automatically
-        // and may have different requirements.
+        // each transaction is validated against the delegations confirmed before
+        // the block, so the delegations of the block must agree among themselves
         //
-        // the validation logic for transactions is contained in the transaction
-        // class, and the validation logic for slips is contained in the slips
-        // class. Note that we are passing in a read-only copy of our UTXOSet so
-        // as to determine spendability.
+        let mut block_delegations = AHashMap::new();
+        if !self
+            .transactions
+            .iter()
+            .all(|tx| Staking::add_block_delegations(&mut block_delegations, tx))
+        {
+            return Err(BlockValidationError::ConflictingDelegations);
+        }
+
+        trace!(" ... block.validate: (txs valid) {:?}", create_timestamp());
+
+        let validate_transaction = |tx: &Transaction| {
+            if self.signatures_verified {
+                tx.validate_against_utxoset(utxoset, staking)
+            } else {
+                tx.validate(utxoset, staking)
+            }
+        };
 
         if cfg!(debug_assertions) {
             // validate serially when we are not in release mode for easier debugging
             for i in 0..self.transactions.len() {
-                let transactions_valid2 = self.transactions[i].validate(utxoset, staking);
+                let transactions_valid2 = validate_transaction(&self.transactions[i]);
                 if !transactions_valid2 {
                     info!("Type: {:?}", self.transactions[i].get_transaction_type());
                     info!("Data {:?}", self.transactions[i]);
@@ -1652,12 +2603,12 @@ impl Block {
             }
         }
 
-        let transactions_valid = self
-            .transactions
-            .par_iter()
-            .all(|tx| tx.validate(utxoset, staking));
+        let transactions_valid = self.transactions.par_iter().all(validate_transaction);
+        if !transactions_valid {
+            return Err(BlockValidationError::InvalidTransactions);
+        }
 
-        transactions_valid
+        Ok(())
     }
 
     pub async fn generate(
@@ -1666,7 +2617,7 @@ impl Block {
         wallet_lock: Arc<RwLock<Wallet>>,
         blockchain_lock: Arc<RwLock<Blockchain>>,
         current_timestamp: u64,
-    ) -> Block {
+    ) -> Result<Block, BlockValidationError> {
         let blockchain = blockchain_lock.read().await;
         let wallet = wallet_lock.read().await;
         let publickey = wallet.get_publickey();
@@ -1697,6 +2648,7 @@ impl Block {
             );
 
         block.set_id(previous_block_id + 1);
+        block.set_hash_algorithm(HashAlgorithm::for_block_id(previous_block_id + 1));
         block.set_previous_block_hash(previous_block_hash);
         block.set_burnfee(current_burnfee);
         block.set_timestamp(current_timestamp);
@@ -1707,6 +2659,20 @@ impl Block {
         //
         mem::swap(&mut block.transactions, transactions);
 
+        //
+        // the golden ticket, if we have one, takes its place at the front
+        //
+        if let Some(idx) = block
+            .transactions
+            .iter()
+            .position(|transaction| transaction.is_golden_ticket())
+        {
+            let golden_ticket_transaction = block.transactions.remove(idx);
+            block
+                .transactions
+                .insert(GOLDEN_TICKET_IDX, golden_ticket_transaction);
+        }
+
         //
         // update slips_spent_this_block so that we have a record of
         // how many times input slips are spent in this block. we will
@@ -1738,7 +2704,7 @@ impl Block {
         //
         // contextual values
         //
-        let mut cv: ConsensusValues = block.generate_consensus_values(&blockchain).await;
+        let mut cv: ConsensusValues = block.generate_consensus_values(&blockchain).await?;
 
         //
         // ATR transactions
@@ -1765,8 +2731,6 @@ impl Block {
             // creator signs fee transaction
             //
             let mut fee_tx = cv.fee_transaction.unwrap();
-            let hash_for_signature: SaitoHash = hash(&fee_tx.serialize_for_signature());
-            fee_tx.set_hash_for_signature(hash_for_signature);
             fee_tx.sign(wallet.get_privatekey());
 
             //
@@ -1804,24 +2768,19 @@ impl Block {
         // set treasury
         //
         if cv.nolan_falling_off_chain != 0 {
-            block.set_treasury(previous_block_treasury + cv.nolan_falling_off_chain);
+            let treasury = Amount::from(previous_block_treasury)
+                .checked_add(cv.nolan_falling_off_chain.into())
+                .ok_or(BlockValidationError::TreasuryOverflow)?;
+            block.set_treasury(treasury.into());
         }
 
         //
         // set staking treasury
         //
         if cv.staking_treasury != 0 {
-            let mut adjusted_staking_treasury = previous_block_staking_treasury;
-            if cv.staking_treasury < 0 {
-                let x = cv.staking_treasury * -1;
-                if adjusted_staking_treasury > x as u64 {
-                    adjusted_staking_treasury -= x as u64;
-                } else {
-                    adjusted_staking_treasury = 0;
-                }
-            } else {
-                adjusted_staking_treasury += cv.staking_treasury as u64;
-            }
+            let adjusted_staking_treasury =
+                adjust_staking_treasury(previous_block_staking_treasury, cv.staking_treasury)
+                    .ok_or(BlockValidationError::StakingTreasuryOverflow)?;
             // info!(
             //     "adjusted staking treasury written into block {}",
             //     adjusted_staking_treasury
@@ -1837,7 +2796,7 @@ impl Block {
 
         block.sign(wallet.get_publickey(), wallet.get_privatekey());
 
-        block
+        Ok(block)
     }
 
     pub async fn delete(&self, utxoset: &mut AHashMap<SaitoUTXOSetKey, u64>) -> bool {
@@ -1848,12 +2807,27 @@ impl Block {
     }
 }
 
+//
+// the staking treasury cannot go below zero, so withdrawals larger than it
+// empty it. returns None if a deposit overflows it.
+//
+fn adjust_staking_treasury(staking_treasury: u64, change: i64) -> Option<u64> {
+    let staking_treasury = Amount::from(staking_treasury);
+    let change_amount = Amount::from(change.unsigned_abs());
+    if change < 0 {
+        Some(staking_treasury.saturating_sub(change_amount).into())
+    } else {
+        staking_treasury.checked_add(change_amount).map(u64::from)
+    }
+}
+
 #[cfg(test)]
 
 mod tests {
     use super::*;
     use crate::{
         slip::Slip,
+        storage::MemoryBlockStorage,
         test_utilities::test_manager::TestManager,
         time::create_timestamp,
         transaction::{Transaction, TransactionType},
@@ -1861,6 +2835,15 @@ mod tests {
     };
     use hex::FromHex;
 
+    #[test]
+    fn adjust_staking_treasury_test() {
+        assert_eq!(adjust_staking_treasury(100, 50), Some(150));
+        assert_eq!(adjust_staking_treasury(100, -50), Some(50));
+        assert_eq!(adjust_staking_treasury(100, -150), Some(0));
+        assert_eq!(adjust_staking_treasury(100, i64::MIN), Some(0));
+        assert_eq!(adjust_staking_treasury(u64::MAX, 1), None);
+    }
+
     #[test]
     fn block_new_test() {
         let block = Block::new();
@@ -1924,7 +2907,7 @@ mod tests {
     fn block_serialize_for_signature_hash() {
         let block = Block::new();
         let serialized_body = block.serialize_for_signature();
-        assert_eq!(serialized_body.len(), 145);
+        assert_eq!(serialized_body.len(), 146);
         TestManager::check_block_consistency(&block);
     }
 
@@ -1953,7 +2936,7 @@ mod tests {
         block.signature = <[u8; 64]>::from_hex("c9a6c2d0bf884be6933878577171a3c8094c2bf6e0bc1b4ec3535a4a55224d186d4d891e254736cae6c0d2002c8dfc0ddfc7fcdbe4bc583f96fa5b273b9d63f4").unwrap();
 
         let serialized_body = block.serialize_for_signature();
-        assert_eq!(serialized_body.len(), 145);
+        assert_eq!(serialized_body.len(), 146);
         TestManager::check_block_consistency(&block);
 
         block.sign(
@@ -1970,10 +2953,10 @@ mod tests {
         assert_eq!(
             block.signature,
             [
-                235, 76, 9, 192, 163, 53, 245, 3, 41, 25, 140, 231, 66, 149, 218, 232, 130, 66,
-                162, 91, 12, 246, 188, 58, 41, 42, 69, 192, 250, 81, 161, 87, 94, 145, 108, 115,
-                200, 186, 242, 204, 221, 100, 154, 75, 35, 162, 39, 224, 116, 76, 252, 208, 27, 32,
-                110, 80, 199, 139, 141, 134, 125, 246, 6, 155
+                160, 227, 101, 118, 46, 251, 236, 164, 32, 234, 54, 160, 135, 154, 222, 90, 27,
+                194, 251, 147, 49, 45, 43, 92, 190, 240, 72, 121, 97, 27, 71, 239, 25, 125, 146,
+                167, 197, 77, 157, 57, 249, 13, 22, 156, 201, 186, 249, 181, 43, 130, 87, 113, 82,
+                131, 77, 194, 37, 188, 150, 228, 182, 246, 62, 29
             ]
         )
     }
@@ -2019,6 +3002,24 @@ mod tests {
         let serialized_block_header = block.serialize_for_net(BlockType::Header);
         let deserialized_block_header = Block::deserialize_for_net(&serialized_block_header);
 
+        assert_eq!(
+            Block::try_deserialize_for_net(&serialized_block)
+                .map(|block| block.serialize_for_net(BlockType::Full)),
+            Some(serialized_block.clone())
+        );
+        assert!(Block::try_deserialize_for_net(&serialized_block_header).is_some());
+        // bytes which are not exactly one block are rejected
+        assert!(
+            Block::try_deserialize_for_net(&serialized_block[..serialized_block.len() - 1])
+                .is_none()
+        );
+        assert!(
+            Block::try_deserialize_for_net(&serialized_block[..BLOCK_HEADER_SIZE - 1]).is_none()
+        );
+        let mut trailing_bytes = serialized_block.clone();
+        trailing_bytes.push(0);
+        assert!(Block::try_deserialize_for_net(&trailing_bytes).is_none());
+
         assert_eq!(
             block.serialize_for_net(BlockType::Full),
             deserialized_block.serialize_for_net(BlockType::Full)
@@ -2076,6 +3077,58 @@ mod tests {
         TestManager::check_block_consistency(&block);
     }
 
+    #[test]
+    fn block_deserialize_for_net_keeps_transaction_order_test() {
+        let mut block = Block::new();
+        let mut transactions: Vec<Transaction> = (0..64)
+            .map(|i| {
+                let mut transaction = Transaction::new();
+                transaction.set_timestamp(i);
+                transaction.set_message(vec![i as u8; i as usize]);
+                transaction
+            })
+            .collect();
+        block.set_transactions(&mut transactions);
+
+        let deserialized_block =
+            Block::deserialize_for_net(&block.serialize_for_net(BlockType::Full));
+        assert_eq!(deserialized_block.get_transactions().len(), 64);
+        for (i, transaction) in deserialized_block.get_transactions().iter().enumerate() {
+            assert_eq!(transaction.get_timestamp(), i as u64);
+            assert_eq!(transaction.get_message().len(), i);
+        }
+    }
+
+    #[test]
+    fn block_limited_size_test() {
+        let mut block = Block::new();
+        let mut transaction = Transaction::new();
+        transaction.set_message(vec![0; 1000]);
+        block.set_transactions(&mut vec![transaction.clone()]);
+        assert_eq!(
+            block.get_limited_size(),
+            block.serialize_for_net(BlockType::Full).len()
+        );
+
+        //
+        // ATR transactions are not counted
+        //
+        let limited_size = block.get_limited_size();
+        transaction.set_transaction_type(TransactionType::ATR);
+        transaction.set_message(vec![0; ConsensusParams::MAX_BLOCK_SIZE]);
+        block.add_transaction(transaction);
+        assert_eq!(block.get_limited_size(), limited_size);
+
+        //
+        // but count against the size of the whole block
+        //
+        assert_eq!(
+            block.get_serialized_size(),
+            block.serialize_for_net(BlockType::Full).len()
+        );
+        assert!(block.get_serialized_size() > ConsensusParams::MAX_BLOCK_SIZE);
+    }
+
     #[tokio::test]
     #[serial_test::serial]
     // downgrade and upgrade a block with transactions
@@ -2092,7 +3145,8 @@ mod tests {
             .collect();
         block.set_transactions(&mut transactions);
 
-        Storage::write_block_to_disk(&mut block);
+        let block_storage = MemoryBlockStorage::new();
+        block_storage.write_block(&block).unwrap();
 
         assert_eq!(block.transactions.len(), 5);
         assert_eq!(block.get_block_type(), BlockType::Full);
@@ -2104,7 +3158,9 @@ mod tests {
         assert_eq!(block.transactions.len(), 0);
         assert_eq!(block.get_block_type(), BlockType::Pruned);
 
-        block.upgrade_block_to_block_type(BlockType::Full).await;
+        block
+            .upgrade_block_to_block_type(BlockType::Full, &block_storage)
+            .await;
 
         assert_eq!(block.get_block_type(), BlockType::Full);
         assert_eq!(
@@ -2115,6 +3171,361 @@ mod tests {
         TestManager::check_block_consistency(&block);
     }
 
+    #[tokio::test]
+    #[serial_test::serial]
+    // the fee transaction the creator signs is the one a validator derives
+    async fn fee_transaction_consensus_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        let latest_block_hash = test_manager.generate_blockchain(3, [0; 32]).await;
+
+        let timestamp = blockchain_lock
+            .read()
+            .await
+            .get_latest_block()
+            .unwrap()
+            .get_timestamp()
+            + 120000;
+        let block = test_manager
+            .generate_block_and_metadata(latest_block_hash, timestamp, 0, 0, true, vec![])
+            .await;
+        assert!(block.get_has_fee_transaction());
+        let creator_fee_transaction = &block.transactions[block.get_fee_transaction_idx() as usize];
+
+        let mut received_block =
+            Block::deserialize_for_net(&block.serialize_for_net(BlockType::Full));
+        received_block.generate_metadata();
+        let blockchain = blockchain_lock.read().await;
+        let cv = received_block
+            .generate_consensus_values(&blockchain)
+            .await
+            .unwrap();
+        let validator_fee_transaction = cv.fee_transaction.unwrap();
+
+        assert_eq!(
+            validator_fee_transaction.get_hash_for_signature(),
+            creator_fee_transaction.get_hash_for_signature()
+        );
+        assert!(!validator_fee_transaction.get_outputs().is_empty());
+        for (validator_output, creator_output) in validator_fee_transaction
+            .get_outputs()
+            .iter()
+            .zip(creator_fee_transaction.get_outputs())
+        {
+            assert_eq!(
+                validator_output.serialize_for_net(),
+                creator_output.serialize_for_net()
+            );
+            assert_eq!(
+                Some(validator_output.get_uuid()),
+                creator_fee_transaction.get_hash_for_signature()
+            );
+        }
+        assert!(
+            received_block
+                .validate(&blockchain, &blockchain.utxoset, &blockchain.staking)
+                .await
+        );
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn block_validate_stages_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        let latest_block_hash = test_manager.generate_blockchain(3, [0; 32]).await;
+
+        let timestamp = blockchain_lock
+            .read()
+            .await
+            .get_latest_block()
+            .unwrap()
+            .get_timestamp()
+            + 120000;
+        let block = test_manager
+            .generate_block_and_metadata(latest_block_hash, timestamp, 0, 0, true, vec![])
+            .await;
+        let (publickey, privatekey) = {
+            let wallet = wallet_lock.read().await;
+            (wallet.get_publickey(), wallet.get_privatekey())
+        };
+        let blockchain = blockchain_lock.read().await;
+        assert_eq!(
+            block
+                .validate_stages(&blockchain, &blockchain.utxoset, &blockchain.staking)
+                .await
+                .map(|_| ()),
+            Ok(())
+        );
+
+        // the header is enough to validate the header
+        let header = Block::deserialize_for_net(&block.serialize_for_net(BlockType::Header));
+        assert!(header.get_transactions().is_empty());
+        assert_eq!(header.validate_header(&blockchain), Ok(()));
+
+        let mut tampered_block = header.clone();
+        tampered_block.set_burnfee(block.get_burnfee() + 1);
+        assert_eq!(
+            tampered_block.validate_header(&blockchain),
+            Err(BlockValidationError::InvalidSignature)
+        );
+        tampered_block.sign(publickey, privatekey);
+        assert_eq!(
+            tampered_block.validate_header(&blockchain),
+            Err(BlockValidationError::InvalidBurnFee {
+                expected: block.get_burnfee(),
+                found: block.get_burnfee() + 1,
+            })
+        );
+
+        // a block hashed with another algorithm than the one scheduled is refused,
+        // even though its hashes and signature are consistent
+        let other_hash_algorithm = match header.get_hash_algorithm() {
+            HashAlgorithm::Blake3 => HashAlgorithm::Sha256,
+            HashAlgorithm::Sha256 => HashAlgorithm::Blake3,
+        };
+        let mut rehashed_block = header.clone();
+        rehashed_block.set_hash_algorithm(other_hash_algorithm);
+        rehashed_block.sign(publickey, privatekey);
+        assert_ne!(rehashed_block.get_hash(), header.get_hash());
+        assert_eq!(
+            Block::deserialize_for_net(&rehashed_block.serialize_for_net(BlockType::Header))
+                .get_hash(),
+            rehashed_block.get_hash()
+        );
+        assert_eq!(
+            rehashed_block.validate_header(&blockchain),
+            Err(BlockValidationError::InvalidHashAlgorithm {
+                expected: header.get_hash_algorithm(),
+                found: other_hash_algorithm,
+            })
+        );
+
+        // the transactions are not covered by the signature of the header
+        assert_eq!(
+            header
+                .validate_stages(&blockchain, &blockchain.utxoset, &blockchain.staking)
+                .await,
+            Err(BlockValidationError::NoTransactions)
+        );
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn block_prevalidate_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        let latest_block_hash = test_manager.generate_blockchain(3, [0; 32]).await;
+
+        let timestamp = blockchain_lock
+            .read()
+            .await
+            .get_latest_block()
+            .unwrap()
+            .get_timestamp()
+            + 120000;
+        let block = test_manager
+            .generate_block_and_metadata(latest_block_hash, timestamp, 0, 0, true, vec![])
+            .await;
+        let mut received_block =
+            Block::deserialize_for_net(&block.serialize_for_net(BlockType::Full));
+        assert!(!received_block.get_signatures_verified());
+
+        let mut forged_block = received_block.clone();
+        let gt_idx = forged_block
+            .transactions
+            .iter()
+            .position(|transaction| transaction.is_golden_ticket())
+            .unwrap();
+        forged_block.transactions[gt_idx].set_signature([1; 64]);
+        assert_eq!(
+            forged_block.clone().prevalidate(),
+            Err(BlockValidationError::InvalidTransactions)
+        );
+
+        let blocks = Block::prevalidate_blocks(vec![forged_block, received_block.clone()]).await;
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].get_hash(), block.get_hash());
+        assert!(blocks[0].get_signatures_verified());
+
+        assert_eq!(received_block.prevalidate(), Ok(()));
+        assert!(received_block.get_signatures_verified());
+        let blockchain = blockchain_lock.read().await;
+        assert!(
+            received_block
+                .validate(&blockchain, &blockchain.utxoset, &blockchain.staking)
+                .await
+        );
+
+        // a transaction added later has not been verified
+        received_block.add_transaction(Transaction::new());
+        assert!(!received_block.get_signatures_verified());
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn block_transaction_placement_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        let latest_block_hash = test_manager.generate_blockchain(3, [0; 32]).await;
+
+        let timestamp = blockchain_lock
+            .read()
+            .await
+            .get_latest_block()
+            .unwrap()
+            .get_timestamp()
+            + 120000;
+        let block = test_manager
+            .generate_block_and_metadata(latest_block_hash, timestamp, 3, 0, true, vec![])
+            .await;
+        let last_idx = block.get_transactions().len() - 1;
+        assert!(block.get_has_golden_ticket());
+        assert!(block.get_has_fee_transaction());
+        assert!(block.get_transactions()[GOLDEN_TICKET_IDX].is_golden_ticket());
+        assert!(block.get_transactions()[last_idx].is_fee_transaction());
+        assert_eq!(block.get_golden_ticket_idx(), GOLDEN_TICKET_IDX as u64);
+        assert_eq!(block.get_fee_transaction_idx(), last_idx as u64);
+
+        let blockchain = blockchain_lock.read().await;
+        let mut misplaced_block = block.clone();
+        misplaced_block.transactions.swap(GOLDEN_TICKET_IDX, 1);
+        assert!(misplaced_block.get_golden_ticket_transaction().is_none());
+        assert_eq!(
+            misplaced_block
+                .validate_stages(&blockchain, &blockchain.utxoset, &blockchain.staking)
+                .await,
+            Err(BlockValidationError::MisplacedGoldenTicket { idx: 1 })
+        );
+
+        let mut misplaced_block = block.clone();
+        misplaced_block.transactions.swap(last_idx - 1, last_idx);
+        assert!(misplaced_block.get_fee_transaction().is_none());
+        assert_eq!(
+            misplaced_block
+                .validate_stages(&blockchain, &blockchain.utxoset, &blockchain.staking)
+                .await,
+            Err(BlockValidationError::MisplacedFeeTransaction { idx: last_idx - 1 })
+        );
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn block_golden_ticket_and_fee_transaction_count_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        let latest_block_hash = test_manager.generate_blockchain(3, [0; 32]).await;
+
+        let timestamp = blockchain_lock
+            .read()
+            .await
+            .get_latest_block()
+            .unwrap()
+            .get_timestamp()
+            + 120000;
+        let block = test_manager
+            .generate_block_and_metadata(latest_block_hash, timestamp, 3, 0, true, vec![])
+            .await;
+        let golden_ticket_transaction = block.get_golden_ticket_transaction().unwrap().clone();
+        let fee_transaction = block.get_fee_transaction().unwrap().clone();
+        let blockchain = blockchain_lock.read().await;
+
+        let mut two_golden_tickets_block = block.clone();
+        two_golden_tickets_block
+            .transactions
+            .insert(GOLDEN_TICKET_IDX, golden_ticket_transaction);
+        assert_eq!(
+            two_golden_tickets_block
+                .validate_stages(&blockchain, &blockchain.utxoset, &blockchain.staking)
+                .await,
+            Err(BlockValidationError::MultipleGoldenTickets { count: 2 })
+        );
+
+        let mut two_fee_transactions_block = block.clone();
+        two_fee_transactions_block
+            .transactions
+            .push(fee_transaction.clone());
+        assert_eq!(
+            two_fee_transactions_block
+                .validate_stages(&blockchain, &blockchain.utxoset, &blockchain.staking)
+                .await,
+            Err(BlockValidationError::MultipleFeeTransactions { count: 2 })
+        );
+
+        let mut fee_transaction_only_block = block.clone();
+        fee_transaction_only_block
+            .transactions
+            .remove(GOLDEN_TICKET_IDX);
+        assert_eq!(
+            fee_transaction_only_block
+                .validate_stages(&blockchain, &blockchain.utxoset, &blockchain.staking)
+                .await,
+            Err(BlockValidationError::FeeTransactionWithoutGoldenTicket)
+        );
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn block_fee_transaction_rules_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        let latest_block_hash = test_manager.generate_blockchain(3, [0; 32]).await;
+
+        let timestamp = blockchain_lock
+            .read()
+            .await
+            .get_latest_block()
+            .unwrap()
+            .get_timestamp()
+            + 120000;
+        let block = test_manager
+            .generate_block_and_metadata(latest_block_hash, timestamp, 3, 0, true, vec![])
+            .await;
+        let privatekey = wallet_lock.read().await.get_privatekey();
+        let last_idx = block.get_transactions().len() - 1;
+        assert_eq!(block.validate_fee_transaction(), Ok(()));
+
+        // signed by someone other than the creator
+        let mut tampered_block = block.clone();
+        tampered_block.transactions[last_idx].sign(Wallet::new().get_privatekey());
+        assert_eq!(
+            tampered_block.validate_fee_transaction(),
+            Err(BlockValidationError::InvalidFeeTransactionSignature)
+        );
+
+        // spending a slip of the creator which is not a staker paid out
+        let mut input = Slip::new();
+        input.set_publickey(block.get_creator());
+        input.set_amount(Amount::from_nolan(10));
+        for slip_type in [SlipType::Normal, SlipType::StakerDeposit] {
+            input.set_slip_type(slip_type);
+            let mut tampered_block = block.clone();
+            tampered_block.transactions[last_idx].add_input(input.clone());
+            tampered_block.transactions[last_idx].sign(privatekey);
+            assert_eq!(
+                tampered_block.validate_fee_transaction(),
+                Err(BlockValidationError::InvalidFeeTransactionInputs)
+            );
+        }
+
+        // unless the staker is paid by the fee transaction
+        let mut output = Slip::new();
+        output.set_publickey(block.get_creator());
+        output.set_amount(Amount::from_nolan(10));
+        output.set_slip_type(SlipType::StakerOutput);
+        let mut staking_block = block.clone();
+        staking_block.transactions[last_idx].add_input(input);
+        staking_block.transactions[last_idx].add_output(output);
+        staking_block.transactions[last_idx].sign(privatekey);
+        assert_eq!(staking_block.validate_fee_transaction(), Ok(()));
+    }
+
     // TODO It is not obvious that calling sign() would have the side effect of setting the hash.
     //      The API of block.sign() and block.set_hash() should be clearer.
     #[ignore]
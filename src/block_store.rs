@@ -0,0 +1,77 @@
+use crate::block::Block;
+use crate::crypto::SaitoHash;
+use ahash::AHashMap;
+use std::sync::{Arc, RwLock};
+
+/// The blocks held by the Blockchain, shared with the parts of the node which only read
+/// blocks, such as serving them to peers and answering RPC queries. The store has its own
+/// lock, so readers do not wait on the blockchain lock while blocks are being added.
+///
+/// The Blockchain publishes a block to the store once it is done changing it, and readers
+/// get the last published version of each block. Blocks are shared with the Blockchain
+/// rather than copied, and a published block which the Blockchain later changes (e.g. when
+/// pruning it) is copied on write.
+///
+/// The lock is only taken inside these methods and never held across an await.
+#[derive(Debug, Clone, Default)]
+pub struct BlockStore {
+    blocks: Arc<RwLock<AHashMap<SaitoHash, Arc<Block>>>>,
+}
+
+impl BlockStore {
+    pub fn new() -> Self {
+        BlockStore::default()
+    }
+
+    pub fn get_block(&self, block_hash: &SaitoHash) -> Option<Arc<Block>> {
+        self.blocks.read().unwrap().get(block_hash).cloned()
+    }
+
+    pub fn contains_block_hash(&self, block_hash: &SaitoHash) -> bool {
+        self.blocks.read().unwrap().contains_key(block_hash)
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub(crate) fn publish_block(&self, block_hash: SaitoHash, block: Arc<Block>) {
+        self.blocks.write().unwrap().insert(block_hash, block);
+    }
+
+    pub(crate) fn remove_block(&self, block_hash: &SaitoHash) {
+        self.blocks.write().unwrap().remove(block_hash);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn block_store_test() {
+        let block_store = BlockStore::new();
+        assert!(block_store.is_empty());
+
+        let mut block = Block::new();
+        block.set_id(1);
+        let block_hash = block.generate_hash();
+        let block = Arc::new(block);
+        block_store.publish_block(block_hash, block.clone());
+        assert!(block_store.contains_block_hash(&block_hash));
+        assert!(Arc::ptr_eq(
+            &block_store.get_block(&block_hash).unwrap(),
+            &block
+        ));
+
+        // clones share the blocks
+        let other_block_store = block_store.clone();
+        other_block_store.remove_block(&block_hash);
+        assert!(block_store.get_block(&block_hash).is_none());
+        assert_eq!(block_store.len(), 0);
+    }
+}
@@ -10,23 +10,39 @@ pub const MAX_TOKEN_SUPPLY: u64 = 1_000_000_000_000_000_000;
 pub const MIN_GOLDEN_TICKETS_NUMERATOR: u64 = 2;
 // minimum golden tickets required ( number of tickets / NUMBER_OF_PRECEDING_BLOCKS )
 pub const MIN_GOLDEN_TICKETS_DENOMINATOR: u64 = 6;
-
-use crate::block::{Block, BlockType};
-use crate::blockring::BlockRing;
-use crate::consensus::SaitoMessage;
+// fee for rebroadcasting an unspent slip -- slips of no more are dust and are not rebroadcast
+pub const ATR_FEE: u64 = 200_000_000;
+// latest blocks listed one by one in a block locator, before it starts skipping blocks
+pub const BLOCK_LOCATOR_DENSE_LENGTH: u64 = 10;
+
+use crate::amount::Amount;
+use crate::block::{Block, BlockPayout, BlockType};
+use crate::block_store::BlockStore;
+use crate::blockring::{BlockRing, RING_BUFFER_LENGTH};
+use crate::consensus::{recv_message, send_message, SaitoMessage, Subsystem};
 use crate::crypto::{SaitoHash, SaitoUTXOSetKey};
+use crate::disk_space::DiskSpaceStatus;
+use crate::memo_index::{MemoIndex, MemoSearchResult};
+use crate::paranoid::{assert_paranoid_check, check_longest_chain, check_utxoset_changes};
+use crate::reorg_log::{ReorgEvent, ReorgLog};
+use crate::slip::iter_unspent_slips;
 use crate::staking::Staking;
-use crate::storage::Storage;
-use crate::time::create_timestamp;
+use crate::storage::{default_block_storage, BlockStorage};
+use crate::sync::{SyncStatus, TipComparison};
+use crate::time::{create_timestamp, NetworkTime};
 use crate::transaction::TransactionType;
+use crate::vip_policy::VipPolicy;
 use crate::wallet::Wallet;
-use log::{error, info, trace, warn};
+use crate::wallet_manager::WalletManager;
+use crate::webhooks::Webhooks;
+use log::{debug, error, info, trace, warn};
 
 use async_recursion::async_recursion;
 
-use ahash::AHashMap;
+use ahash::{AHashMap, AHashSet};
+use serde::Serialize;
 use std::sync::Arc;
-use tokio::sync::{broadcast, mpsc, RwLock};
+use tokio::sync::{broadcast, mpsc, oneshot, RwLock};
 
 pub fn bit_pack(top: u32, bottom: u32) -> u64 {
     ((top as u64) << 32) + (bottom as u64)
@@ -38,18 +54,142 @@ pub fn bit_unpack(packed: u64) -> (u32, u32) {
     (top, bottom)
 }
 
-pub type UtxoSet = AHashMap<SaitoUTXOSetKey, u64>;
+pub use crate::slip::UtxoSet;
+
+/// A block on the longest chain, as listed by GET /blocks. The hash is hex encoded. Pruned
+/// blocks no longer hold their transactions, so their transaction count is 0.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct BlockSummary {
+    pub hash: String,
+    pub id: u64,
+    pub timestamp: u64,
+    pub transaction_count: usize,
+    pub total_fees: u64,
+}
+
+/// The header of a block and the figures of its transactions which the chain statistics are
+/// computed from, kept when the transactions of the block are pruned.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CachedBlockHeader {
+    pub id: u64,
+    pub timestamp: u64,
+    pub previous_block_hash: SaitoHash,
+    pub difficulty: u64,
+    pub transaction_count: usize,
+    pub total_fees: u64,
+    pub has_golden_ticket: bool,
+    /// the burn fees of the chain summed up to this block, from the earliest block we held
+    /// when this one was added
+    pub chain_work: u64,
+}
+
+impl CachedBlockHeader {
+    pub fn new(block: &Block, chain_work: u64) -> Self {
+        CachedBlockHeader {
+            id: block.get_id(),
+            timestamp: block.get_timestamp(),
+            previous_block_hash: block.get_previous_block_hash(),
+            difficulty: block.get_difficulty(),
+            transaction_count: block.get_transactions().len(),
+            total_fees: block.get_total_fees(),
+            has_golden_ticket: block.get_has_golden_ticket(),
+            chain_work,
+        }
+    }
+}
+
+/// Fee and throughput statistics over the last blocks of the longest chain, as reported by
+/// GET /chain/stats. The block interval is in milliseconds, and the difficulty trajectory
+/// lists the difficulty of every block counted, oldest first.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ChainStats {
+    pub blocks: u64,
+    pub from_block_id: u64,
+    pub to_block_id: u64,
+    pub average_block_interval: u64,
+    pub total_fees: u64,
+    pub average_fees: u64,
+    pub transactions: u64,
+    pub transactions_per_second: f64,
+    pub golden_tickets: u64,
+    pub golden_ticket_frequency: f64,
+    pub difficulty: Vec<u64>,
+}
+
+/// The state of the utxoset, as reported by GET /utxoset. Entries include the spent slips
+/// still kept for reorganizations, dust slips are unspent slips worth no more than ATR_FEE,
+/// and the memory size is an estimate of the table alone.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct UtxoSetInfo {
+    pub latest_block_id: u64,
+    pub entries: usize,
+    pub unspent_slips: usize,
+    pub total_nolan: u64,
+    pub dust_slips: usize,
+    pub dust_nolan: u64,
+    pub memory_bytes: usize,
+}
+
+//
+// Lock Ordering
+//
+// the locks of the node are taken in this order, and a task holding one of them
+// never waits on a lock which comes before it:
+//
+//   mempool -> blockchain -> wallet -> block store
+//
+// blocks are only added by the blockchain task, which the mempool sends them to
+// over a BlockchainMessage channel, so the mempool and blockchain write locks
+// are never held together.
+//
+// the block store lock is only held inside BlockStore and never across an await,
+// so readers of blocks (peers requesting blocks, RPC queries) only ever wait on
+// the short publication of blocks, not on the validation of a block being added.
+//
+/// Commands handled by the blockchain task. Each command carries a channel for the reply.
+#[derive(Debug)]
+pub enum BlockchainMessage {
+    // adds the blocks in order, replying with the id of the latest block
+    AddBlocks {
+        blocks: Vec<Block>,
+        reply: oneshot::Sender<u64>,
+    },
+}
 
 #[derive(Debug)]
 pub struct Blockchain {
     pub staking: Staking,
     pub utxoset: UtxoSet,
     pub blockring: BlockRing,
-    pub blocks: AHashMap<SaitoHash, Block>,
-    pub wallet_lock: Arc<RwLock<Wallet>>,
+    pub blocks: AHashMap<SaitoHash, Arc<Block>>,
+    // blocks shared with readers which do not take the blockchain lock
+    block_store: BlockStore,
+    // where blocks are written once added, and read back when their transactions were pruned
+    block_storage: Arc<dyn BlockStorage>,
+    // blocks added, changed or removed since they were last published to the block store
+    unpublished_block_hashes: AHashSet<SaitoHash>,
+    wallet_manager: WalletManager,
     broadcast_channel_sender: Option<broadcast::Sender<SaitoMessage>>,
     genesis_block_id: u64,
     fork_id: SaitoHash,
+    vip_policy: VipPolicy,
+    network_time: NetworkTime,
+    sync_status: SyncStatus,
+    // check the utxoset and blockring after every block wound or unwound
+    paranoid: bool,
+    webhooks: Webhooks,
+    memo_index: Option<MemoIndex>,
+    // the winners of the payout lottery of each block, as decided when the block was validated
+    block_payouts: AHashMap<SaitoHash, Vec<BlockPayout>>,
+    // the headers of the blocks we hold, see get_chain_stats
+    block_headers: AHashMap<SaitoHash, CachedBlockHeader>,
+    reorg_log: ReorgLog,
+    // blocks whose stored copy was corrupt, to be fetched again from our peers
+    quarantined_block_hashes: AHashSet<SaitoHash>,
+    // while the disk is critically full blocks are kept in memory only
+    disk_space_status: DiskSpaceStatus,
+    // blocks not written to the block storage yet, which must not be pruned
+    unstored_block_hashes: AHashSet<SaitoHash>,
 }
 
 impl Blockchain {
@@ -60,17 +200,329 @@ impl Blockchain {
             utxoset: AHashMap::new(),
             blockring: BlockRing::new(),
             blocks: AHashMap::new(),
-            wallet_lock,
+            block_store: BlockStore::new(),
+            block_storage: default_block_storage(),
+            unpublished_block_hashes: AHashSet::new(),
+            wallet_manager: WalletManager::new(wallet_lock),
             broadcast_channel_sender: None,
             genesis_block_id: 0,
             fork_id: [0; 32],
+            vip_policy: VipPolicy::default(),
+            network_time: NetworkTime::new(),
+            sync_status: SyncStatus::new(),
+            paranoid: false,
+            webhooks: Webhooks::new(),
+            memo_index: None,
+            block_payouts: AHashMap::new(),
+            block_headers: AHashMap::new(),
+            reorg_log: ReorgLog::default(),
+            quarantined_block_hashes: AHashSet::new(),
+            disk_space_status: DiskSpaceStatus::default(),
+            unstored_block_hashes: AHashSet::new(),
+        }
+    }
+
+    /// Replaces the single wallet given to new(). Every wallet in the manager is kept up to
+    /// date with the slips it owns as blocks are added to and removed from the longest chain.
+    pub fn set_wallet_manager(&mut self, wallet_manager: WalletManager) {
+        self.wallet_manager = wallet_manager;
+    }
+
+    /// The store of the blocks held by the blockchain, for readers which should not take
+    /// the blockchain lock.
+    pub fn get_block_store(&self) -> BlockStore {
+        self.block_store.clone()
+    }
+
+    /// Publishes the blocks added, changed or removed since the last publication to the
+    /// block store.
+    fn publish_blocks(&mut self) {
+        for block_hash in self.unpublished_block_hashes.drain() {
+            match self.blocks.get(&block_hash) {
+                Some(block) => self.block_store.publish_block(block_hash, block.clone()),
+                None => self.block_store.remove_block(&block_hash),
+            }
+        }
+    }
+
+    pub fn get_wallet_manager(&self) -> &WalletManager {
+        &self.wallet_manager
+    }
+
+    pub fn set_vip_policy(&mut self, vip_policy: VipPolicy) {
+        self.vip_policy = vip_policy;
+    }
+
+    pub fn get_vip_policy(&self) -> &VipPolicy {
+        &self.vip_policy
+    }
+
+    /// In paranoid mode every block wound or unwound is checked against the utxoset and the
+    /// blockring, and the node stops at the first divergence. This is slow and meant for
+    /// development.
+    pub fn set_paranoid(&mut self, paranoid: bool) {
+        self.paranoid = paranoid;
+    }
+
+    pub fn get_paranoid(&self) -> bool {
+        self.paranoid
+    }
+
+    /// The webhooks are told of every block wound or unwound, after the wallets.
+    pub fn set_webhooks(&mut self, webhooks: Webhooks) {
+        self.webhooks = webhooks;
+    }
+
+    /// Indexes the transactions of the longest chain by the tag of their message from now
+    /// on, so it should be set before the blocks are loaded.
+    pub fn set_memo_index(&mut self, memo_index: Option<MemoIndex>) {
+        self.memo_index = memo_index;
+    }
+
+    /// The transactions of the longest chain whose message starts with the tag, see
+    /// MemoIndex::search.
+    pub fn search_transactions(&self, tag: &[u8]) -> Result<Vec<MemoSearchResult>, String> {
+        match self.memo_index.as_ref() {
+            Some(memo_index) => memo_index.search(tag),
+            None => Err(String::from("transactions are not indexed")),
+        }
+    }
+
+    pub fn get_block_storage(&self) -> Arc<dyn BlockStorage> {
+        self.block_storage.clone()
+    }
+
+    /// Blocks are written to the block storage once they are added to the longest chain, and
+    /// the blocks in it are loaded when the node starts, see Storage::load_blocks_from_disk.
+    pub fn set_block_storage(&mut self, block_storage: Arc<dyn BlockStorage>) {
+        self.block_storage = block_storage;
+    }
+
+    /// Notes a block whose stored copy was corrupt, so that it is requested from the block
+    /// sources we connect to until one of them sends it.
+    pub fn add_quarantined_block_hash(&mut self, block_hash: SaitoHash) {
+        self.quarantined_block_hashes.insert(block_hash);
+    }
+
+    pub fn get_quarantined_block_hashes(&self) -> Vec<SaitoHash> {
+        self.quarantined_block_hashes.iter().cloned().collect()
+    }
+
+    pub fn get_disk_space_status(&self) -> DiskSpaceStatus {
+        self.disk_space_status
+    }
+
+    /// Set by the disk_space::DiskSpaceMonitor. Blocks added while the status is critical are
+    /// not stored or pruned, and are stored as soon as it no longer is.
+    pub fn set_disk_space_status(&mut self, disk_space_status: DiskSpaceStatus) {
+        self.disk_space_status = disk_space_status;
+        if disk_space_status != DiskSpaceStatus::Critical {
+            let unstored_block_hashes: Vec<SaitoHash> =
+                self.unstored_block_hashes.drain().collect();
+            for block_hash in unstored_block_hashes {
+                self.store_block(block_hash);
+            }
+        }
+    }
+
+    /// Blocks kept in memory only, as the disk was too full to store them.
+    pub fn get_unstored_block_hashes(&self) -> &AHashSet<SaitoHash> {
+        &self.unstored_block_hashes
+    }
+
+    /// Writes the block to the block storage, unless the disk is critically full, in which
+    /// case the block is noted to be stored later.
+    fn store_block(&mut self, block_hash: SaitoHash) {
+        let block = match self.blocks.get(&block_hash) {
+            Some(block) => block,
+            None => return,
+        };
+        if block.get_block_type() == BlockType::Header {
+            return;
+        }
+        if self.disk_space_status == DiskSpaceStatus::Critical {
+            self.unstored_block_hashes.insert(block_hash);
+            return;
+        }
+        if let Err(err) = self.block_storage.write_block(block) {
+            error!(
+                "ERROR 290315: cannot save block {}: {}",
+                hex::encode(block_hash),
+                err
+            );
+        }
+    }
+
+    /// The last reorganizations of the longest chain.
+    pub fn get_reorg_log(&self) -> &ReorgLog {
+        &self.reorg_log
+    }
+
+    pub fn set_reorg_log(&mut self, reorg_log: ReorgLog) {
+        self.reorg_log = reorg_log;
+    }
+
+    /// Our clock adjusted by those of our peers, used to reject blocks from the future.
+    pub fn get_network_time(&self) -> &NetworkTime {
+        &self.network_time
+    }
+
+    pub fn get_mut_network_time(&mut self) -> &mut NetworkTime {
+        &mut self.network_time
+    }
+
+    /// How far we are behind the tips announced by our peers.
+    pub fn get_sync_status(&self) -> &SyncStatus {
+        &self.sync_status
+    }
+
+    pub fn get_mut_sync_status(&mut self) -> &mut SyncStatus {
+        &mut self.sync_status
+    }
+
+    /// Counts the entries and nolan of the utxoset, so that its growth can be followed.
+    pub fn get_utxoset_info(&self) -> UtxoSetInfo {
+        let mut utxoset_info = UtxoSetInfo {
+            latest_block_id: self.get_latest_block_id(),
+            entries: self.utxoset.len(),
+            unspent_slips: 0,
+            total_nolan: 0,
+            dust_slips: 0,
+            dust_nolan: 0,
+            //
+            // hashbrown keeps a control byte for every bucket
+            //
+            memory_bytes: self.utxoset.capacity()
+                * (std::mem::size_of::<SaitoUTXOSetKey>() + std::mem::size_of::<u64>() + 1),
+        };
+        for slip in iter_unspent_slips(&self.utxoset, None) {
+            utxoset_info.unspent_slips += 1;
+            utxoset_info.total_nolan = utxoset_info
+                .total_nolan
+                .saturating_add(slip.get_amount().into());
+            if slip.get_amount() <= Amount::from(ATR_FEE) {
+                utxoset_info.dust_slips += 1;
+                utxoset_info.dust_nolan += u64::from(slip.get_amount());
+            }
+        }
+        utxoset_info
+    }
+
+    /// Summarizes the blocks on the longest chain from from_id to to_id inclusive, skipping
+    /// any ids we no longer hold.
+    pub fn get_block_summaries(&self, from_id: u64, to_id: u64) -> Vec<BlockSummary> {
+        let mut block_summaries = vec![];
+        for block_id in from_id..=to_id.min(self.get_latest_block_id()) {
+            let block_hash = self
+                .blockring
+                .get_longest_chain_block_hash_by_block_id(block_id);
+            if let Some(block) = self.get_block_sync(&block_hash) {
+                //
+                // the blockring is a ring buffer, so its slot may hold a later block
+                //
+                if block.get_id() == block_id {
+                    block_summaries.push(BlockSummary {
+                        hash: hex::encode(block_hash),
+                        id: block_id,
+                        timestamp: block.get_timestamp(),
+                        transaction_count: block.get_transactions().len(),
+                        total_fees: block.get_total_fees(),
+                    });
+                }
+            }
+        }
+        block_summaries
+    }
+
+    /// Computes fee and throughput statistics over the last n_blocks blocks of the longest
+    /// chain, or as many of them as we hold. They are computed from the header cache, so the
+    /// blocks whose transactions have been pruned are still counted.
+    pub fn get_chain_stats(&self, n_blocks: u64) -> ChainStats {
+        let mut headers = vec![];
+        let mut block_hash = self.get_latest_block_hash();
+        while (headers.len() as u64) < n_blocks {
+            match self.block_headers.get(&block_hash) {
+                Some(header) => {
+                    block_hash = header.previous_block_hash;
+                    headers.push(header);
+                }
+                None => break,
+            }
+        }
+        headers.reverse();
+
+        let blocks = headers.len() as u64;
+        let total_fees = headers.iter().fold(0u64, |total, header| {
+            total.saturating_add(header.total_fees)
+        });
+        let transactions: u64 = headers
+            .iter()
+            .map(|header| header.transaction_count as u64)
+            .sum();
+        let golden_tickets = headers
+            .iter()
+            .filter(|header| header.has_golden_ticket)
+            .count() as u64;
+
+        //
+        // the transactions of the first block counted were made before the
+        // first interval, so the throughput is that of the blocks after it
+        //
+        let mut average_block_interval = 0;
+        let mut transactions_per_second = 0.0;
+        if let (Some(first), Some(last)) = (headers.first(), headers.last()) {
+            let elapsed = last.timestamp.saturating_sub(first.timestamp);
+            if blocks > 1 {
+                average_block_interval = elapsed / (blocks - 1);
+            }
+            if elapsed > 0 {
+                transactions_per_second = (transactions - first.transaction_count as u64) as f64
+                    * 1000.0
+                    / elapsed as f64;
+            }
+        }
+
+        ChainStats {
+            blocks,
+            from_block_id: headers.first().map_or(0, |header| header.id),
+            to_block_id: headers.last().map_or(0, |header| header.id),
+            average_block_interval,
+            total_fees,
+            average_fees: total_fees.checked_div(blocks).unwrap_or(0),
+            transactions,
+            transactions_per_second,
+            golden_tickets,
+            golden_ticket_frequency: if blocks > 0 {
+                golden_tickets as f64 / blocks as f64
+            } else {
+                0.0
+            },
+            difficulty: headers.iter().map(|header| header.difficulty).collect(),
         }
     }
 
+    /// Rebuilds the slips of every wallet from the longest chain, see Wallet::rescan.
+    pub async fn rescan_wallets(&self, from_block_id: u64) -> crate::Result<()> {
+        for wallet_lock in self.wallet_manager.get_wallet_locks() {
+            wallet_lock
+                .write()
+                .await
+                .rescan(self, from_block_id)
+                .await?;
+        }
+        Ok(())
+    }
+
     pub fn set_broadcast_channel_sender(&mut self, bcs: broadcast::Sender<SaitoMessage>) {
         self.broadcast_channel_sender = Some(bcs);
     }
 
+    fn broadcast_message(&self, message: SaitoMessage) {
+        if let Some(broadcast_channel_sender) = self.broadcast_channel_sender.as_ref() {
+            send_message(broadcast_channel_sender, message);
+        }
+    }
+
     pub fn set_fork_id(&mut self, fork_id: SaitoHash) {
         self.fork_id = fork_id;
     }
@@ -93,7 +545,7 @@ impl Blockchain {
                 .blockring
                 .get_longest_chain_block_hash_by_block_id(earliest_block_id);
             trace!("earliest_block_hash {:?}", earliest_block_hash);
-            let earliest_block = self.get_mut_block(&earliest_block_hash).await;
+            let earliest_block = self.get_block_sync(&earliest_block_hash).unwrap();
 
             if block.get_timestamp() > earliest_block.get_timestamp() {
                 if self
@@ -102,17 +554,11 @@ impl Blockchain {
                     .is_none()
                 {
                     if block.get_id() > earliest_block_id {
-                        if block.get_source_connection_id().is_some()
-                            && self.broadcast_channel_sender.is_some()
-                        {
-                            self.broadcast_channel_sender
-                                .as_ref()
-                                .unwrap()
-                                .send(SaitoMessage::MissingBlock {
-                                    peer_id: block.get_source_connection_id().unwrap(),
-                                    hash: block.get_previous_block_hash(),
-                                })
-                                .expect("error: MissingBlock message failed to send");
+                        if let Some(peer_id) = block.get_source_connection_id() {
+                            self.broadcast_message(SaitoMessage::MissingBlock {
+                                peer_id,
+                                hash: block.get_previous_block_hash(),
+                            });
                         }
                     }
                 }
@@ -140,6 +586,7 @@ impl Blockchain {
         let block_hash = block.get_hash();
         let block_id = block.get_id();
         let previous_block_hash = self.blockring.get_latest_block_hash();
+        self.quarantined_block_hashes.remove(&block_hash);
 
         //
         // sanity checks
@@ -197,7 +644,14 @@ impl Blockchain {
         // arrival if they do not exist.
         //
         if !self.blocks.contains_key(&block_hash) {
-            self.blocks.insert(block_hash, block);
+            let chain_work = self
+                .get_chain_work(&block.get_previous_block_hash())
+                .unwrap_or(0)
+                .saturating_add(block.get_burnfee());
+            self.block_headers
+                .insert(block_hash, CachedBlockHeader::new(&block, chain_work));
+            self.blocks.insert(block_hash, Arc::new(block));
+            self.unpublished_block_hashes.insert(block_hash);
         } else {
             error!(
                 "BLOCK IS ALREADY IN THE BLOCKCHAIN, WHY ARE WE ADDING IT????? {:?}",
@@ -304,8 +758,25 @@ impl Blockchain {
         // viable.
         //
         if am_i_the_longest_chain {
+            let displaced_block_hashes = old_chain.clone();
             let does_new_chain_validate = self.validate(new_chain, old_chain).await;
             if does_new_chain_validate {
+                if !displaced_block_hashes.is_empty() {
+                    let old_tip_id = self
+                        .get_block_sync(&previous_block_hash)
+                        .map_or(0, |block| block.get_id());
+                    let reorg_event = ReorgEvent::new(
+                        create_timestamp(),
+                        (previous_block_hash, old_tip_id),
+                        (block_hash, block_id),
+                        &displaced_block_hashes,
+                    );
+                    warn!(
+                        "reorganization of depth {} to block {} at id {}",
+                        reorg_event.depth, reorg_event.new_tip, block_id
+                    );
+                    self.reorg_log.record(reorg_event);
+                }
                 self.add_block_success(block_hash).await;
 
                 //
@@ -316,50 +787,53 @@ impl Blockchain {
                 // this trick. we did this check before validating.
                 //
                 {
-                    self.blocks.get_mut(&block_hash).unwrap().set_lc(true);
+                    self.get_mut_block(&block_hash).await.set_lc(true);
                 }
 
-                if self.broadcast_channel_sender.is_some() {
-                    self.broadcast_channel_sender
-                        .as_ref()
-                        .unwrap()
-                        .send(SaitoMessage::BlockchainAddBlockSuccess { hash: block_hash })
-                        .expect("error: BlockchainAddBlockSuccess message failed to send");
+                self.broadcast_message(SaitoMessage::BlockchainAddBlockSuccess {
+                    hash: block_hash,
+                });
 
-                    let difficulty = self.blocks.get(&block_hash).unwrap().get_difficulty();
+                let difficulty = self.blocks.get(&block_hash).unwrap().get_difficulty();
 
-                    self.broadcast_channel_sender
-                        .as_ref()
-                        .unwrap()
-                        .send(SaitoMessage::BlockchainNewLongestChainBlock {
-                            hash: block_hash,
-                            difficulty,
-                        })
-                        .expect("error: BlockchainNewLongestChainBlock message failed to send");
+                self.broadcast_message(SaitoMessage::BlockchainNewLongestChainBlock {
+                    hash: block_hash,
+                    difficulty,
+                });
+
+                //
+                // a block sent to us by a peer is at or below its tip
+                //
+                let block = self.blocks.get(&block_hash).unwrap();
+                if let Some(connection_id) = block.get_source_connection_id() {
+                    let block_id = block.get_id();
+                    self.sync_status.on_peer_tip(connection_id, block_id);
                 }
             } else {
                 self.add_block_failure().await;
 
-                if self.broadcast_channel_sender.is_some() {
-                    self.broadcast_channel_sender
-                        .as_ref()
-                        .unwrap()
-                        .send(SaitoMessage::BlockchainAddBlockFailure { hash: block_hash })
-                        .expect("error: BlockchainAddBlockFailure message failed to send");
-                }
+                self.broadcast_message(SaitoMessage::BlockchainAddBlockFailure {
+                    hash: block_hash,
+                });
             }
         } else {
             self.add_block_failure().await;
 
-            if self.broadcast_channel_sender.is_some() {
-                self.broadcast_channel_sender
-                    .as_ref()
-                    .unwrap()
-                    .send(SaitoMessage::BlockchainAddBlockFailure { hash: block_hash })
-                    .expect("error: BlockchainAddBlockFailure message failed to send");
-            }
+            self.broadcast_message(SaitoMessage::BlockchainAddBlockFailure { hash: block_hash });
         }
+
+        self.sync_status.on_latest_block(self.get_latest_block_id());
+        self.publish_blocks();
     }
+    /// Adds the blocks in order and returns the id of the latest block.
+    pub async fn add_blocks(blockchain_lock: Arc<RwLock<Blockchain>>, blocks: Vec<Block>) -> u64 {
+        let mut blockchain = blockchain_lock.write().await;
+        for block in blocks {
+            blockchain.add_block(block).await;
+        }
+        blockchain.get_latest_block_id()
+    }
+
     pub async fn add_block_to_blockchain(blockchain_lock: Arc<RwLock<Blockchain>>, block: Block) {
         let mut blockchain = blockchain_lock.write().await;
         let res = blockchain.add_block(block).await;
@@ -377,12 +851,7 @@ impl Blockchain {
         //
         // save to disk
         //
-        {
-            let block = self.get_mut_block(&block_hash).await;
-            if block.get_block_type() != BlockType::Header {
-                Storage::write_block_to_disk(block);
-            }
-        }
+        self.store_block(block_hash);
 
         //
         // TODO: clean up mempool - I think we shouldn't cleanup mempool here.
@@ -397,13 +866,7 @@ impl Blockchain {
         //
         // propagate block to network
         //
-        if self.broadcast_channel_sender.is_some() {
-            self.broadcast_channel_sender
-                .as_ref()
-                .unwrap()
-                .send(SaitoMessage::BlockchainSavedBlock { hash: block_hash })
-                .expect("error: BlockchainSavedBlock message failed to send");
-        }
+        self.broadcast_message(SaitoMessage::BlockchainSavedBlock { hash: block_hash });
         trace!(" ... block save done:            {:?}", create_timestamp());
 
         //
@@ -434,8 +897,11 @@ impl Blockchain {
             // to use to check the utxoset.
             //
             {
+                let block_storage = self.block_storage.clone();
                 let pblock = self.get_mut_block(&pruned_block_hash).await;
-                pblock.upgrade_block_to_block_type(BlockType::Full).await;
+                pblock
+                    .upgrade_block_to_block_type(BlockType::Full, block_storage.as_ref())
+                    .await;
             }
         }
     }
@@ -734,7 +1200,7 @@ impl Blockchain {
 
     pub fn get_latest_block(&self) -> Option<&Block> {
         let block_hash = self.blockring.get_latest_block_hash();
-        self.blocks.get(&block_hash)
+        self.get_block_sync(&block_hash)
     }
 
     pub fn get_latest_block_hash(&self) -> SaitoHash {
@@ -745,16 +1211,25 @@ impl Blockchain {
         self.blockring.get_latest_block_id()
     }
 
+    /// The winners of the payout lottery of the block, i.e. the miner, router and staker
+    /// paid by the block after it, as decided when the block was validated. None if the
+    /// block has not been added to the longest chain.
+    pub fn get_block_payouts(&self, block_hash: &SaitoHash) -> Option<&Vec<BlockPayout>> {
+        self.block_payouts.get(block_hash)
+    }
+
     pub fn get_block_sync(&self, block_hash: &SaitoHash) -> Option<&Block> {
-        self.blocks.get(block_hash)
+        self.blocks.get(block_hash).map(|block| block.as_ref())
     }
     pub async fn get_block(&self, block_hash: &SaitoHash) -> Option<&Block> {
-        self.blocks.get(block_hash)
+        self.get_block_sync(block_hash)
     }
 
+    /// Blocks must only be changed through here, so that the changes are published to
+    /// the block store.
     pub async fn get_mut_block(&mut self, block_hash: &SaitoHash) -> &mut Block {
-        let block = self.blocks.get_mut(block_hash).unwrap();
-        block
+        self.unpublished_block_hashes.insert(*block_hash);
+        Arc::make_mut(self.blocks.get_mut(block_hash).unwrap())
     }
 
     pub fn is_block_indexed(&self, block_hash: SaitoHash) -> bool {
@@ -769,6 +1244,98 @@ impl Blockchain {
             .contains_block_hash_at_block_id(block_id, block_hash)
     }
 
+    /// Whether the block is on our longest chain.
+    pub fn is_block_hash_in_longest_chain(&self, block_hash: &SaitoHash) -> bool {
+        match self.get_block_sync(block_hash) {
+            Some(block) => {
+                block.get_id() + RING_BUFFER_LENGTH > self.get_latest_block_id()
+                    && self
+                        .blockring
+                        .get_longest_chain_block_hash_by_block_id(block.get_id())
+                        == *block_hash
+            }
+            None => false,
+        }
+    }
+
+    /// A sparse list of the hashes of our longest chain, newest first, for a peer to find
+    /// where its chain forks from ours. The latest BLOCK_LOCATOR_DENSE_LENGTH blocks are
+    /// listed one by one, then the gaps double up to the earliest block in the blockring,
+    /// so a long chain needs only a few dozen hashes.
+    pub fn generate_block_locator(&self) -> Vec<SaitoHash> {
+        let latest_block_id = self.get_latest_block_id();
+        if latest_block_id == 0 {
+            return vec![];
+        }
+        let earliest_block_id = latest_block_id
+            .saturating_sub(RING_BUFFER_LENGTH - 1)
+            .max(1);
+
+        let mut block_locator = vec![];
+        let mut block_id = latest_block_id;
+        let mut step = 1;
+        loop {
+            let block_hash = self
+                .blockring
+                .get_longest_chain_block_hash_by_block_id(block_id);
+            if block_hash != [0; 32] {
+                block_locator.push(block_hash);
+            }
+            if block_id == earliest_block_id {
+                break;
+            }
+            if block_locator.len() as u64 >= BLOCK_LOCATOR_DENSE_LENGTH {
+                step *= 2;
+            }
+            block_id = block_id.saturating_sub(step).max(earliest_block_id);
+        }
+        block_locator
+    }
+
+    /// The latest hash of the block locator which is on our longest chain, i.e. where the
+    /// chain of the peer which sent it forks from ours.
+    pub fn find_fork_point(&self, block_locator: &[SaitoHash]) -> Option<SaitoHash> {
+        block_locator
+            .iter()
+            .find(|block_hash| self.is_block_hash_in_longest_chain(block_hash))
+            .copied()
+    }
+
+    pub fn get_cached_block_header(&self, block_hash: &SaitoHash) -> Option<&CachedBlockHeader> {
+        self.block_headers.get(block_hash)
+    }
+
+    /// The burn fees of the chain summed up to a block we hold. Chains are compared by their
+    /// work when a peer announces its tip, see compare_tip.
+    pub fn get_chain_work(&self, block_hash: &SaitoHash) -> Option<u64> {
+        self.get_cached_block_header(block_hash)
+            .map(|header| header.chain_work)
+    }
+
+    /// Compares the tip a peer announced with ours, see TipComparison. As each node sums the
+    /// work of its chain from the earliest block it held, a block we do not hold with a higher
+    /// id than our tip is Ahead as well, however little work its chain has.
+    pub fn compare_tip(
+        &self,
+        block_hash: &SaitoHash,
+        block_id: u64,
+        chain_work: u64,
+    ) -> TipComparison {
+        let latest_block_hash = self.get_latest_block_hash();
+        if *block_hash == latest_block_hash {
+            TipComparison::Same
+        } else if self.is_block_hash_in_longest_chain(block_hash) {
+            TipComparison::Behind
+        } else if !self.blocks.contains_key(block_hash)
+            && (block_id > self.get_latest_block_id()
+                || chain_work > self.get_chain_work(&latest_block_hash).unwrap_or(0))
+        {
+            TipComparison::Ahead
+        } else {
+            TipComparison::Fork
+        }
+    }
+
     pub fn is_new_chain_the_longest_chain(
         &mut self,
         new_chain: &Vec<[u8; 32]>,
@@ -946,15 +1513,28 @@ impl Blockchain {
                 let previous_block_hash =
                     self.blockring.get_longest_chain_block_hash_by_block_id(bid);
                 if self.is_block_indexed(previous_block_hash) {
+                    let block_storage = self.block_storage.clone();
                     block = self.get_mut_block(&previous_block_hash).await;
-                    block.upgrade_block_to_block_type(BlockType::Full).await;
+                    block
+                        .upgrade_block_to_block_type(BlockType::Full, block_storage.as_ref())
+                        .await;
                 }
             }
         }
 
         let block = self.blocks.get(&new_chain[current_wind_index]).unwrap();
         trace!(" ... before block.validate:      {:?}", create_timestamp());
-        let does_block_validate = block.validate(&self, &self.utxoset, &self.staking).await;
+        let block_payout = match block
+            .validate_stages(self, &self.utxoset, &self.staking)
+            .await
+        {
+            Ok(consensus_values) => Some(consensus_values.block_payout),
+            Err(validation_error) => {
+                error!("{}", validation_error);
+                None
+            }
+        };
+        let does_block_validate = block_payout.is_some();
 
         trace!(
             " ... after block.validate:       {:?} {}",
@@ -966,13 +1546,30 @@ impl Blockchain {
             trace!(" ... before block ocr            {:?}", create_timestamp());
 
             // utxoset update
+            let utxoset_before = self.paranoid.then(|| self.utxoset.clone());
             block.on_chain_reorganization(&mut self.utxoset, true);
+            if let Some(utxoset_before) = utxoset_before {
+                assert_paranoid_check(check_utxoset_changes(
+                    &utxoset_before,
+                    &self.utxoset,
+                    block,
+                    true,
+                ));
+            }
 
             trace!(" ... before blockring ocr:       {:?}", create_timestamp());
 
             // blockring update
             self.blockring
                 .on_chain_reorganization(block.get_id(), block.get_hash(), true);
+            if self.paranoid {
+                assert_paranoid_check(check_longest_chain(
+                    self,
+                    block.get_id(),
+                    block.get_hash(),
+                    true,
+                ));
+            }
 
             // staking tables update
             let (res_spend, res_unspend, res_delete) =
@@ -986,10 +1583,19 @@ impl Blockchain {
             //
             {
                 trace!(" ... wallet processing start:    {}", create_timestamp());
-                let mut wallet = self.wallet_lock.write().await;
-                wallet.on_chain_reorganization(&block, true);
+                for wallet_lock in self.wallet_manager.get_wallet_locks() {
+                    let mut wallet = wallet_lock.write().await;
+                    wallet.on_chain_reorganization(block, true);
+                }
                 trace!(" ... wallet processing stop:     {}", create_timestamp());
             }
+            self.webhooks.on_chain_reorganization(block, true);
+            if let Some(memo_index) = self.memo_index.as_mut() {
+                memo_index.on_chain_reorganization(block, true);
+            }
+            if let Some(block_payout) = block_payout {
+                self.block_payouts.insert(block.get_hash(), block_payout);
+            }
 
             let block_id = block.get_id();
             self.on_chain_reorganization(block_id, true).await;
@@ -1130,20 +1736,41 @@ impl Blockchain {
         let block = &self.blocks[&old_chain[current_unwind_index]];
 
         // utxoset update
+        let utxoset_before = self.paranoid.then(|| self.utxoset.clone());
         block.on_chain_reorganization(&mut self.utxoset, false);
+        if let Some(utxoset_before) = utxoset_before {
+            assert_paranoid_check(check_utxoset_changes(
+                &utxoset_before,
+                &self.utxoset,
+                block,
+                false,
+            ));
+        }
 
         // blockring update
         self.blockring
             .on_chain_reorganization(block.get_id(), block.get_hash(), false);
+        if self.paranoid {
+            assert_paranoid_check(check_longest_chain(
+                self,
+                block.get_id(),
+                block.get_hash(),
+                false,
+            ));
+        }
 
         // staking tables
         let (res_spend, res_unspend, res_delete) =
             self.staking.on_chain_reorganization(block, false);
 
         // wallet update
-        {
-            let mut wallet = self.wallet_lock.write().await;
-            wallet.on_chain_reorganization(&block, false);
+        for wallet_lock in self.wallet_manager.get_wallet_locks() {
+            let mut wallet = wallet_lock.write().await;
+            wallet.on_chain_reorganization(block, false);
+        }
+        self.webhooks.on_chain_reorganization(block, false);
+        if let Some(memo_index) = self.memo_index.as_mut() {
+            memo_index.on_chain_reorganization(block, false);
         }
 
         //
@@ -1286,13 +1913,14 @@ impl Blockchain {
         //
         {
             let pblock = self.blocks.get(&delete_block_hash).unwrap();
-            let pblock_filename = Storage::generate_block_filename(pblock);
 
             //
             // remove slips from wallet
             //
-            let mut wallet = self.wallet_lock.write().await;
-            wallet.delete_block(pblock);
+            for wallet_lock in self.wallet_manager.get_wallet_locks() {
+                let mut wallet = wallet_lock.write().await;
+                wallet.delete_block(pblock);
+            }
 
             //
             // removes utxoset data
@@ -1302,7 +1930,16 @@ impl Blockchain {
             //
             // deletes block from disk
             //
-            Storage::delete_block_from_disk(pblock_filename).await;
+            if let Err(err) = self
+                .block_storage
+                .delete_block(pblock.get_timestamp(), &delete_block_hash)
+            {
+                error!(
+                    "ERROR 290316: cannot delete block {}: {}",
+                    hex::encode(delete_block_hash),
+                    err
+                );
+            }
         }
 
         //
@@ -1316,7 +1953,11 @@ impl Blockchain {
         //
         if self.blocks.contains_key(&delete_block_hash) {
             self.blocks.remove_entry(&delete_block_hash);
+            self.unpublished_block_hashes.insert(delete_block_hash);
         }
+        self.block_payouts.remove(&delete_block_hash);
+        self.block_headers.remove(&delete_block_hash);
+        self.unstored_block_hashes.remove(&delete_block_hash);
     }
 
     pub async fn downgrade_blockchain_data(&mut self) {
@@ -1340,6 +1981,13 @@ impl Blockchain {
         }
 
         for hash in block_hashes_copy {
+            //
+            // the transactions of a block we could not store exist nowhere else
+            //
+            if self.unstored_block_hashes.contains(&hash) {
+                continue;
+            }
+
             //
             // ask the block to remove its transactions
             //
@@ -1350,6 +1998,8 @@ impl Blockchain {
                     .await;
             }
         }
+
+        self.publish_blocks();
     }
 }
 
@@ -1357,14 +2007,10 @@ impl Blockchain {
 // and receiving channels for asynchronous loops or message checks
 pub async fn run(
     blockchain_lock: Arc<RwLock<Blockchain>>,
+    mut blockchain_channel_receiver: mpsc::Receiver<BlockchainMessage>,
     broadcast_channel_sender: broadcast::Sender<SaitoMessage>,
     mut broadcast_channel_receiver: broadcast::Receiver<SaitoMessage>,
 ) -> crate::Result<()> {
-    let (_blockchain_channel_sender, mut blockchain_channel_receiver): (
-        tokio::sync::mpsc::Sender<SaitoMessage>,
-        tokio::sync::mpsc::Receiver<SaitoMessage>,
-    ) = mpsc::channel(4);
-
     //
     // blockchain takes global broadcast channel
     //
@@ -1384,17 +2030,23 @@ pub async fn run(
         //
             Some(message) = blockchain_channel_receiver.recv() => {
                 match message {
-                    _ => {},
+                    BlockchainMessage::AddBlocks { blocks, reply } => {
+                        let latest_block_id = Blockchain::add_blocks(blockchain_lock.clone(), blocks).await;
+                        //
+                        // the sender may have stopped waiting
+                        //
+                        let _ = reply.send(latest_block_id);
+                    },
                 }
             }
 
         //
         // global broadcast messages
         //
-            Ok(message) = broadcast_channel_receiver.recv() => {
+            Some(message) = recv_message(&mut broadcast_channel_receiver, Subsystem::Blockchain) => {
                 match message {
                     SaitoMessage::BlockchainSavedBlock { hash: _hash } => {
-                        println!("Blockchain aware network has received new block! -- we might use for this congestion tracking");
+                        debug!("Blockchain aware network has received new block! -- we might use for this congestion tracking");
                     },
                     _ => {},
                 }
@@ -1407,7 +2059,9 @@ pub async fn run(
 
 mod tests {
     use super::*;
+    use crate::storage::Storage;
     use crate::test_utilities::test_manager::TestManager;
+    use crate::time::{MAX_CLOCK_ADJUSTMENT, MAX_FUTURE_BLOCK_TIME};
 
     #[test]
     //
@@ -1482,6 +2136,59 @@ mod tests {
         test_manager.check_token_supply().await;
     }
 
+    #[tokio::test]
+    #[serial_test::serial]
+    //
+    // test blocks which are not after their parent or are from the future are rejected
+    //
+    async fn block_timestamp_validation_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+
+        let current_timestamp = create_timestamp();
+        let block1_hash = test_manager
+            .add_block(current_timestamp, 3, 0, false, vec![])
+            .await;
+
+        test_manager
+            .add_block_on_hash(current_timestamp, 0, 1, false, vec![], block1_hash)
+            .await;
+        assert_eq!(1, blockchain_lock.read().await.get_latest_block_id());
+
+        test_manager
+            .add_block_on_hash(
+                current_timestamp + MAX_FUTURE_BLOCK_TIME + 600000,
+                0,
+                1,
+                false,
+                vec![],
+                block1_hash,
+            )
+            .await;
+        assert_eq!(1, blockchain_lock.read().await.get_latest_block_id());
+
+        //
+        // our peers' clocks being ahead lets the block in
+        //
+        blockchain_lock
+            .write()
+            .await
+            .get_mut_network_time()
+            .add_clock_offset([1; 33], MAX_CLOCK_ADJUSTMENT);
+        test_manager
+            .add_block_on_hash(
+                current_timestamp + MAX_FUTURE_BLOCK_TIME + 600000,
+                0,
+                1,
+                false,
+                vec![],
+                block1_hash,
+            )
+            .await;
+        assert_eq!(2, blockchain_lock.read().await.get_latest_block_id());
+    }
+
     #[tokio::test]
     #[serial_test::serial]
     //
@@ -1783,6 +2490,19 @@ mod tests {
             let blockchain = blockchain_lock.read().await;
             assert_eq!(10, blockchain.get_latest_block_id());
             assert_eq!(block10_2_hash, blockchain.get_latest_block_hash());
+
+            // the reorganization displaced blocks 9 back to 6
+            let reorg_events = blockchain.get_reorg_log().get_events();
+            assert_eq!(reorg_events.len(), 1);
+            assert_eq!(reorg_events[0].old_tip, hex::encode(block9_hash));
+            assert_eq!(reorg_events[0].old_tip_id, 9);
+            assert_eq!(reorg_events[0].new_tip, hex::encode(block10_2_hash));
+            assert_eq!(reorg_events[0].new_tip_id, 10);
+            assert_eq!(reorg_events[0].depth, 4);
+            assert_eq!(
+                reorg_events[0].displaced_block_hashes[0],
+                hex::encode(block9_hash)
+            );
         }
 
         test_manager.check_utxoset().await;
@@ -1845,6 +2565,224 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn block_locator_fork_point_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        assert!(blockchain_lock
+            .read()
+            .await
+            .generate_block_locator()
+            .is_empty());
+
+        let block5_hash = test_manager.generate_blockchain(5, [0; 32]).await;
+        test_manager.generate_blockchain(5, block5_hash).await;
+        let old_block_locator = blockchain_lock.read().await.generate_block_locator();
+        assert_eq!(old_block_locator.len(), 10);
+
+        //
+        // the old chain forks from the new longest chain after block 5. The test
+        // manager builds the same blocks on the same parent, so the fork starts
+        // with an earlier block, whose higher burn fee makes the fork win
+        //
+        let block5_timestamp = blockchain_lock
+            .read()
+            .await
+            .get_block_sync(&block5_hash)
+            .unwrap()
+            .get_timestamp();
+        let block6_hash = test_manager
+            .add_block_on_hash(block5_timestamp + 110000, 0, 0, true, vec![], block5_hash)
+            .await;
+        let block15_hash = test_manager.generate_blockchain(9, block6_hash).await;
+        let blockchain = blockchain_lock.read().await;
+        assert_eq!(
+            blockchain.find_fork_point(&old_block_locator),
+            Some(block5_hash)
+        );
+        assert_eq!(blockchain.find_fork_point(&[[1; 32]]), None);
+
+        // blocks 15 to 6 one by one, then 4 and 1
+        let block_locator = blockchain.generate_block_locator();
+        assert_eq!(block_locator.len(), 12);
+        assert_eq!(block_locator[0], block15_hash);
+        assert_eq!(
+            block_locator[10],
+            blockchain
+                .blockring
+                .get_longest_chain_block_hash_by_block_id(4)
+        );
+        assert_eq!(
+            block_locator[11],
+            blockchain
+                .blockring
+                .get_longest_chain_block_hash_by_block_id(1)
+        );
+        assert_eq!(
+            blockchain.find_fork_point(&block_locator),
+            Some(block15_hash)
+        );
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn block_store_publication_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        let block_store = blockchain_lock.read().await.get_block_store();
+
+        let block5_hash = test_manager.generate_blockchain(5, [0; 32]).await;
+        test_manager.generate_blockchain(5, block5_hash).await;
+        let block15_hash = test_manager.generate_blockchain(10, block5_hash).await;
+        {
+            let blockchain = blockchain_lock.read().await;
+            assert_eq!(block_store.len(), blockchain.blocks.len());
+            for (block_hash, block) in blockchain.blocks.iter() {
+                assert!(Arc::ptr_eq(
+                    &block_store.get_block(block_hash).unwrap(),
+                    block
+                ));
+            }
+            assert!(block_store.get_block(&block15_hash).unwrap().get_lc());
+        }
+
+        // readers of the store do not wait on the blockchain lock
+        let _blockchain = blockchain_lock.write().await;
+        assert_eq!(
+            block_store.get_block(&block15_hash).unwrap().get_hash(),
+            block15_hash
+        );
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn chain_stats_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+
+        assert_eq!(blockchain_lock.read().await.get_chain_stats(10).blocks, 0);
+
+        let block5_hash = test_manager.generate_blockchain(5, [0; 32]).await;
+        test_manager.generate_blockchain(5, block5_hash).await;
+        test_manager.generate_blockchain(10, block5_hash).await;
+
+        let mut blockchain = blockchain_lock.write().await;
+        let blocks: Vec<Block> = (6..=15)
+            .map(|block_id| {
+                let block_hash = blockchain
+                    .blockring
+                    .get_longest_chain_block_hash_by_block_id(block_id);
+                blockchain.get_block_sync(&block_hash).unwrap().clone()
+            })
+            .collect();
+        let chain_stats = blockchain.get_chain_stats(10);
+        assert_eq!(chain_stats.blocks, 10);
+        assert_eq!(chain_stats.from_block_id, 6);
+        assert_eq!(chain_stats.to_block_id, 15);
+        assert_eq!(
+            chain_stats.average_block_interval,
+            (blocks[9].get_timestamp() - blocks[0].get_timestamp()) / 9
+        );
+        assert_eq!(
+            chain_stats.transactions,
+            blocks
+                .iter()
+                .map(|block| block.get_transactions().len() as u64)
+                .sum::<u64>()
+        );
+        assert_eq!(
+            chain_stats.total_fees,
+            blocks
+                .iter()
+                .map(|block| block.get_total_fees())
+                .sum::<u64>()
+        );
+        assert_eq!(
+            chain_stats.golden_tickets,
+            blocks
+                .iter()
+                .filter(|block| block.get_has_golden_ticket())
+                .count() as u64
+        );
+        assert_eq!(
+            chain_stats.difficulty,
+            blocks
+                .iter()
+                .map(|block| block.get_difficulty())
+                .collect::<Vec<u64>>()
+        );
+
+        // asking for more blocks than we hold counts the whole chain
+        assert_eq!(blockchain.get_chain_stats(100).blocks, 15);
+
+        // pruned blocks are still counted
+        let block_hash = blocks[0].get_hash();
+        blockchain
+            .get_mut_block(&block_hash)
+            .await
+            .downgrade_block_to_block_type(BlockType::Pruned)
+            .await;
+        assert!(blockchain
+            .get_block_sync(&block_hash)
+            .unwrap()
+            .get_transactions()
+            .is_empty());
+        assert_eq!(blockchain.get_chain_stats(10), chain_stats);
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn chain_work_compare_tip_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+
+        let block5_hash = test_manager.generate_blockchain(5, [0; 32]).await;
+        let block10_hash = test_manager.generate_blockchain(5, block5_hash).await;
+
+        let blockchain = blockchain_lock.read().await;
+        let chain_work: u64 = (1..=10)
+            .map(|block_id| {
+                let block_hash = blockchain
+                    .blockring
+                    .get_longest_chain_block_hash_by_block_id(block_id);
+                blockchain
+                    .get_block_sync(&block_hash)
+                    .unwrap()
+                    .get_burnfee()
+            })
+            .sum();
+        assert!(chain_work > 0);
+        assert_eq!(blockchain.get_chain_work(&block10_hash), Some(chain_work));
+        assert_eq!(blockchain.get_chain_work(&[1; 32]), None);
+
+        assert_eq!(
+            blockchain.compare_tip(&block10_hash, 10, chain_work),
+            TipComparison::Same
+        );
+        assert_eq!(
+            blockchain.compare_tip(&block5_hash, 5, 0),
+            TipComparison::Behind
+        );
+        // a block we do not hold is ahead if its chain is longer or heavier
+        assert_eq!(
+            blockchain.compare_tip(&[1; 32], 11, 0),
+            TipComparison::Ahead
+        );
+        assert_eq!(
+            blockchain.compare_tip(&[1; 32], 8, chain_work + 1),
+            TipComparison::Ahead
+        );
+        assert_eq!(
+            blockchain.compare_tip(&[1; 32], 10, chain_work),
+            TipComparison::Fork
+        );
+    }
+
     /// Loading blocks into a blockchain which was were created from another blockchain instance
     #[tokio::test]
     #[serial_test::serial]
@@ -1866,6 +2804,13 @@ mod tests {
         let blockchain_lock2 = Arc::new(RwLock::new(Blockchain::new(wallet_lock2.clone())));
         let _test_manager2 = TestManager::new(blockchain_lock2.clone(), wallet_lock2.clone());
 
+        {
+            let block_storage = blockchain_lock1.read().await.get_block_storage();
+            blockchain_lock2
+                .write()
+                .await
+                .set_block_storage(block_storage);
+        }
         Storage::load_blocks_from_disk(blockchain_lock2.clone()).await;
 
         {
@@ -0,0 +1,272 @@
+use crate::block::{Block, BlockType};
+use crate::blockchain::Blockchain;
+use crate::consensus_params::ConsensusParams;
+use crate::crypto::{hash, SaitoHash};
+use crate::storage::{BlockStorage, BLOCK_FILE_VERSION};
+use ahash::AHashMap;
+use log::{info, warn};
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+//
+// A bootstrap file holds the longest chain as one stream of blocks, so that
+// a new node can be filled from a file downloaded once, or dumped by a node
+// it trusts, instead of fetching every block from its peers. The blocks are
+// written in the order they were added to the chain, each framed by its
+// length and the hash of its bytes. Every block is validated again as it is
+// imported, so a bootstrap file needs to be trusted only to be complete.
+//
+
+/// Bootstrap files start with these bytes and the version of the block file format, as the
+/// blocks in them are serialized for the network like those in block files.
+pub const BOOTSTRAP_FILE_MAGIC: [u8; 4] = *b"SAIC";
+pub const BOOTSTRAP_FILE_HEADER_SIZE: usize = 8;
+/// Every block in the file is preceded by its length, a u32, and the hash of its bytes.
+pub const BOOTSTRAP_RECORD_HEADER_SIZE: usize = 36;
+
+/// Writes the longest chain of the blocks in the storage to the file, returning the number of
+/// blocks written. The longest chain ends in the block with the highest id, and is followed
+/// back for as long as the storage has the parent of the block.
+pub fn export_chain(block_storage: &dyn BlockStorage, filename: &str) -> crate::Result<u64> {
+    //
+    // only the header of every block is kept while looking for the chain,
+    // the blocks are read again one by one while writing them out
+    //
+    let mut headers: AHashMap<SaitoHash, (u64, u64, SaitoHash)> = AHashMap::new();
+    for block in block_storage.iterate() {
+        match block {
+            Ok(block) => {
+                headers.insert(
+                    block.get_hash(),
+                    (
+                        block.get_timestamp(),
+                        block.get_id(),
+                        block.get_previous_block_hash(),
+                    ),
+                );
+            }
+            Err(corrupt_block) => warn!("not exporting block: {}", corrupt_block.error),
+        }
+    }
+    let mut chain = vec![];
+    let mut block_hash = headers
+        .iter()
+        .max_by_key(|(_, (timestamp, id, _))| (*id, std::cmp::Reverse(*timestamp)))
+        .map(|(block_hash, _)| *block_hash);
+    while let Some((timestamp, _, previous_block_hash)) =
+        block_hash.and_then(|block_hash| headers.get(&block_hash))
+    {
+        chain.push((*timestamp, block_hash.unwrap()));
+        block_hash = Some(*previous_block_hash);
+    }
+    chain.reverse();
+
+    let mut writer = BufWriter::new(File::create(filename)?);
+    writer.write_all(&BOOTSTRAP_FILE_MAGIC)?;
+    writer.write_all(&BLOCK_FILE_VERSION.to_be_bytes())?;
+    for (timestamp, block_hash) in chain.iter() {
+        let block = block_storage.read_block(*timestamp, block_hash)?;
+        write_block_record(&mut writer, &block.serialize_for_net(BlockType::Full))?;
+    }
+    writer.flush()?;
+    info!("exported {} blocks to {}", chain.len(), filename);
+    Ok(chain.len() as u64)
+}
+
+/// What was read from a bootstrap file.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ChainImport {
+    pub blocks_imported: u64,
+    pub blocks_skipped: u64,
+}
+
+/// Adds the blocks in the file to the blockchain, which stores them like any block it is
+/// given. Blocks the blockchain already has are skipped. The import stops at the first block
+/// whose bytes do not match their hash, keeping the blocks before it.
+pub async fn import_chain(
+    blockchain: &mut Blockchain,
+    filename: &str,
+) -> crate::Result<ChainImport> {
+    let mut reader = BufReader::new(File::open(filename)?);
+    let mut header = [0; BOOTSTRAP_FILE_HEADER_SIZE];
+    reader.read_exact(&mut header)?;
+    if header[0..4] != BOOTSTRAP_FILE_MAGIC {
+        return Err(format!("{} is not a bootstrap file", filename).into());
+    }
+    let version = u32::from_be_bytes(header[4..8].try_into().unwrap());
+    if version != BLOCK_FILE_VERSION {
+        return Err(format!(
+            "{} has blocks of version {}, expected {}",
+            filename, version, BLOCK_FILE_VERSION
+        )
+        .into());
+    }
+
+    let mut chain_import = ChainImport::default();
+    loop {
+        let bytes = match read_block_record(&mut reader) {
+            Ok(Some(bytes)) => bytes,
+            Ok(None) => break,
+            Err(err) => {
+                return Err(format!(
+                    "block {} of {} {}",
+                    chain_import.blocks_imported + chain_import.blocks_skipped + 1,
+                    filename,
+                    err
+                )
+                .into())
+            }
+        };
+
+        let mut block = Block::try_deserialize_for_net(&bytes).ok_or_else(|| {
+            format!(
+                "block {} of {} is not a block",
+                chain_import.blocks_imported + chain_import.blocks_skipped + 1,
+                filename
+            )
+        })?;
+        block.generate_metadata();
+        if blockchain.get_block_sync(&block.get_hash()).is_some() {
+            chain_import.blocks_skipped += 1;
+            continue;
+        }
+        blockchain.add_block(block).await;
+        chain_import.blocks_imported += 1;
+    }
+    info!(
+        "imported {} blocks from {}, the longest chain is at block {}",
+        chain_import.blocks_imported,
+        filename,
+        blockchain.get_latest_block_id()
+    );
+    Ok(chain_import)
+}
+
+/// Writes the bytes of a block as a record of a bootstrap file: its length, a u32, the hash of
+/// the bytes and the bytes.
+pub fn write_block_record(writer: &mut impl Write, bytes: &[u8]) -> io::Result<()> {
+    writer.write_all(&(bytes.len() as u32).to_be_bytes())?;
+    writer.write_all(&hash(bytes))?;
+    writer.write_all(bytes)
+}
+
+/// Reads the bytes of the next block record, or None at the end of the reader. Fails if the
+/// record is cut short or its bytes do not match their hash.
+pub fn read_block_record(reader: &mut impl Read) -> crate::Result<Option<Vec<u8>>> {
+    let mut record_header = [0; BOOTSTRAP_RECORD_HEADER_SIZE];
+    if !read_exact_or_eof(reader, &mut record_header)
+        .map_err(|err| format!("is truncated: {}", err))?
+    {
+        return Ok(None);
+    }
+    let length = u32::from_be_bytes(record_header[0..4].try_into().unwrap()) as usize;
+    if length > ConsensusParams::MAX_SERIALIZED_BLOCK_SIZE {
+        return Err(format!("is {} bytes, larger than a block may be", length).into());
+    }
+    let checksum: SaitoHash = record_header[4..36].try_into().unwrap();
+    let mut bytes = vec![0; length];
+    reader
+        .read_exact(&mut bytes)
+        .map_err(|err| format!("is truncated: {}", err))?;
+    if hash(&bytes) != checksum {
+        return Err("does not match its checksum".into());
+    }
+    Ok(Some(bytes))
+}
+
+/// Fills the buffer, or returns false if the reader is at its end.
+fn read_exact_or_eof(reader: &mut impl Read, buffer: &mut [u8]) -> io::Result<bool> {
+    let mut read = 0;
+    while read < buffer.len() {
+        match reader.read(&mut buffer[read..])? {
+            0 if read == 0 => return Ok(false),
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            n => read += n,
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utilities::test_manager::TestManager;
+    use crate::wallet::Wallet;
+    use std::fs;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    const BOOTSTRAP_TEST_FILE_PATH: &str = "./data/test/bootstrap.chain";
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn export_import_chain_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        test_manager.generate_blockchain(5, [0; 32]).await;
+        let blockchain = blockchain_lock.read().await;
+
+        fs::create_dir_all("./data/test/").unwrap();
+        let exported =
+            export_chain(&*blockchain.get_block_storage(), BOOTSTRAP_TEST_FILE_PATH).unwrap();
+        assert_eq!(exported, 5);
+
+        let mut imported_blockchain = Blockchain::new(Arc::new(RwLock::new(Wallet::new())));
+        let chain_import = import_chain(&mut imported_blockchain, BOOTSTRAP_TEST_FILE_PATH)
+            .await
+            .unwrap();
+        assert_eq!(chain_import.blocks_imported, 5);
+        assert_eq!(
+            imported_blockchain.get_latest_block_hash(),
+            blockchain.get_latest_block_hash()
+        );
+
+        // importing again adds nothing
+        let chain_import = import_chain(&mut imported_blockchain, BOOTSTRAP_TEST_FILE_PATH)
+            .await
+            .unwrap();
+        assert_eq!(chain_import.blocks_imported, 0);
+        assert_eq!(chain_import.blocks_skipped, 5);
+
+        // the blocks before a damaged one are imported
+        let mut bytes = fs::read(BOOTSTRAP_TEST_FILE_PATH).unwrap();
+        let last_byte = bytes.len() - 1;
+        bytes[last_byte] ^= 1;
+        fs::write(BOOTSTRAP_TEST_FILE_PATH, &bytes).unwrap();
+        let mut imported_blockchain = Blockchain::new(Arc::new(RwLock::new(Wallet::new())));
+        let result = import_chain(&mut imported_blockchain, BOOTSTRAP_TEST_FILE_PATH).await;
+        assert!(result.unwrap_err().to_string().contains("checksum"));
+        assert_eq!(imported_blockchain.get_latest_block_id(), 4);
+
+        // as are those before a truncated one
+        fs::write(BOOTSTRAP_TEST_FILE_PATH, &bytes[..last_byte]).unwrap();
+        let mut imported_blockchain = Blockchain::new(Arc::new(RwLock::new(Wallet::new())));
+        let result = import_chain(&mut imported_blockchain, BOOTSTRAP_TEST_FILE_PATH).await;
+        assert!(result.unwrap_err().to_string().contains("truncated"));
+        assert_eq!(imported_blockchain.get_latest_block_id(), 4);
+
+        fs::remove_file(BOOTSTRAP_TEST_FILE_PATH).unwrap();
+    }
+
+    #[test]
+    fn read_block_record_size_test() {
+        // a block may be larger than MAX_BLOCK_SIZE with its ATR and fee transactions
+        let bytes = vec![0; ConsensusParams::MAX_BLOCK_SIZE + 1];
+        let mut record = vec![];
+        write_block_record(&mut record, &bytes).unwrap();
+        assert_eq!(read_block_record(&mut &record[..]).unwrap(), Some(bytes));
+
+        // but not larger than a whole block may be
+        let mut record = [0; BOOTSTRAP_RECORD_HEADER_SIZE];
+        record[0..4].copy_from_slice(
+            &(ConsensusParams::MAX_SERIALIZED_BLOCK_SIZE as u32 + 1).to_be_bytes(),
+        );
+        let result = read_block_record(&mut &record[..]);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("larger than a block"));
+    }
+}
@@ -42,6 +42,28 @@ impl BurnFee {
         (work_needed_float * 100_000_000.0).round() as u64
     }
 
+    /// Returns the earliest timestamp at which the routing work available suffices to
+    /// produce a block on the previous block. The work needed falls as time passes and is
+    /// 0 once 2 * HEARTBEAT has elapsed, so a block can always be produced by then.
+    ///
+    /// * `burn_fee_previous_block` - burn fee value (y-axis) for curve determination
+    /// * `routing_work_available` - routing work of the candidate transactions
+    /// * `previous_block_timestamp` - timestamp of previous block
+    pub fn return_timestamp_when_routing_work_suffices(
+        burn_fee_previous_block: u64,
+        routing_work_available: u64,
+        previous_block_timestamp: u64,
+    ) -> u64 {
+        //
+        // the work needed is rounded, so it suffices once burn_fee / elapsed_time is
+        // below routing_work_available + 0.5
+        //
+        let elapsed_time =
+            2 * burn_fee_previous_block as u128 / (2 * routing_work_available as u128 + 1) + 1;
+        let elapsed_time = elapsed_time.min(2 * HEARTBEAT as u128) as u64;
+        previous_block_timestamp.saturating_add(elapsed_time)
+    }
+
     /// Returns an adjusted burnfee based on the start value provided
     /// and the difference between the current block timestamp and the
     /// previous block timestamp
@@ -99,6 +121,43 @@ mod tests {
         );
     }
 
+    #[test]
+    fn burnfee_return_timestamp_when_routing_work_suffices_test() {
+        let previous_block_timestamp = 1_000_000;
+        let burn_fee = 100_000_000;
+        for routing_work_available in [0, 1, 5_000, 33_333, 100_000_000, 200_000_000] {
+            let timestamp = BurnFee::return_timestamp_when_routing_work_suffices(
+                burn_fee,
+                routing_work_available,
+                previous_block_timestamp,
+            );
+            assert!(timestamp > previous_block_timestamp);
+            assert!(timestamp <= previous_block_timestamp + 2 * HEARTBEAT);
+            assert!(
+                BurnFee::return_routing_work_needed_to_produce_block_in_nolan(
+                    burn_fee,
+                    timestamp,
+                    previous_block_timestamp
+                ) <= routing_work_available
+            );
+        }
+
+        // a millisecond earlier is not enough
+        let timestamp = BurnFee::return_timestamp_when_routing_work_suffices(
+            burn_fee,
+            5_000,
+            previous_block_timestamp,
+        );
+        assert_eq!(timestamp, previous_block_timestamp + 19_999);
+        assert!(
+            BurnFee::return_routing_work_needed_to_produce_block_in_nolan(
+                burn_fee,
+                timestamp - 1,
+                previous_block_timestamp
+            ) > 5_000
+        );
+    }
+
     #[test]
     fn burnfee_burn_fee_adjustment_test() {
         // if the difference in timestamps is equal to HEARTBEAT, our start value should not change
@@ -1,24 +1,446 @@
+use crate::blockchain::ATR_FEE;
+use crate::disk_space::{
+    DISK_SPACE_CHECK_INTERVAL, DISK_SPACE_CRITICAL_FREE_BYTES, DISK_SPACE_WARN_FREE_BYTES,
+};
+use crate::logging::{LOG_FILES_KEPT, LOG_FILE_MAX_SIZE, LOG_FILE_ROTATION_INTERVAL};
+use crate::memo_index::MEMO_TAG_LENGTH;
+use crate::mempool::MEMPOOL_TRANSACTION_TTL;
+use crate::network::{PING_INTERVAL, TIP_ANNOUNCEMENT_INTERVAL};
+use crate::networking::peer_connection::PeerTransport;
+use crate::networking::peer_host::{deserialize_ip_address, PeerHost};
+use crate::networking::peer_role::PeerRole;
+use crate::networking::peer_services::PeerServices;
+use crate::wallet_manager::WalletRole;
 use serde_aux::field_attributes::deserialize_number_from_string;
 use std::convert::{TryFrom, TryInto};
+use std::net::IpAddr;
 
 #[derive(serde::Deserialize, Clone)]
 pub struct Settings {
     pub network: NetworkSettings,
+    /// wallets loaded in addition to the one given on the command line
+    #[serde(default)]
+    pub wallets: Vec<WalletSetting>,
+    /// a JSON or TOML genesis::GenesisSpec used to create the first block of a new chain
+    #[serde(default)]
+    pub genesis_file: Option<String>,
+    #[serde(default)]
+    pub vip_policy: VipPolicySettings,
+    #[serde(default)]
+    pub mempool: MempoolSettings,
+    #[serde(default)]
+    pub webhooks: Vec<WebhookSetting>,
+    /// a file every reorganization of the longest chain is appended to as a line of JSON
+    #[serde(default)]
+    pub reorg_log_file: Option<String>,
+    #[serde(default)]
+    pub disk_space: DiskSpaceSettings,
+    #[serde(default)]
+    pub memo_index: MemoIndexSettings,
+    #[serde(default)]
+    pub mode: NodeMode,
+    #[serde(default)]
+    pub miner: MinerSettings,
+    #[serde(default)]
+    pub staking: StakingSettings,
+    /// the level of our logs, e.g. info or debug, instead of RUST_LOG
+    #[serde(default)]
+    pub log_level: Option<String>,
+    #[serde(default)]
+    pub logging: LoggingSettings,
 }
 
-/// Settings for remote Peers
+/// What the node does besides following the chain.
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeMode {
+    /// the node bundles blocks and mines golden tickets
+    #[default]
+    Full,
+    /// the node validates blocks and collects and relays transactions, but neither bundles
+    /// blocks nor mines, e.g. for relay nodes at the edge of the network
+    TransactionRelay,
+}
+
+#[derive(serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct MempoolSettings {
+    /// the number of blocks a transaction may wait in the mempool before it is dropped
+    #[serde(default = "default_mempool_transaction_ttl")]
+    pub transaction_ttl: u64,
+    /// our wallets sweep their dust into one slip once it adds up to more than this many
+    /// nolan, 0 turns the sweeping off
+    #[serde(default = "default_dust_consolidation_threshold")]
+    pub dust_consolidation_threshold: u64,
+    /// how many milliseconds before the routing work suffices we bundle the next block, so that
+    /// only its timestamp is left to set when it is produced, 0 turns this off
+    #[serde(default)]
+    pub block_lead_time: u64,
+    /// which transactions the mempool takes
+    #[serde(default)]
+    pub admission: AdmissionSettings,
+    /// how many bytes of transactions from one address the mempool holds before throttling
+    /// the address, 0 turns throttling off
+    #[serde(default)]
+    pub address_byte_budget: u64,
+    /// what happens to the transactions of an address beyond its budget
+    #[serde(default)]
+    pub throttle_action: ThrottleAction,
+}
+
+/// How the mempool throttles an address sending more than its budget of bytes.
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ThrottleAction {
+    /// the transactions beyond the budget are taken, but go into blocks after those of every
+    /// other address
+    #[default]
+    Deprioritize,
+    /// the transactions beyond the budget are rejected
+    Reject,
+}
+
+/// The admission policies of the mempool, see admission_policy. A limit of 0 turns its policy
+/// off, and all are off by default.
+#[derive(serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct AdmissionSettings {
+    /// the lowest fee rate, in nolan per byte, of the transactions taken
+    #[serde(default)]
+    pub min_fee_rate: u64,
+    /// the largest message, in bytes, of the transactions taken
+    #[serde(default)]
+    pub max_message_size: usize,
+    /// how many transactions from one address are taken per rate_limit_window
+    #[serde(default)]
+    pub max_transactions_per_address: u64,
+    /// in milliseconds
+    #[serde(default = "default_rate_limit_window")]
+    pub rate_limit_window: u64,
+}
+
+fn default_rate_limit_window() -> u64 {
+    1000
+}
+
+impl Default for AdmissionSettings {
+    fn default() -> Self {
+        AdmissionSettings {
+            min_fee_rate: 0,
+            max_message_size: 0,
+            max_transactions_per_address: 0,
+            rate_limit_window: default_rate_limit_window(),
+        }
+    }
+}
+
+fn default_mempool_transaction_ttl() -> u64 {
+    MEMPOOL_TRANSACTION_TTL
+}
+
+fn default_dust_consolidation_threshold() -> u64 {
+    ATR_FEE
+}
+
+impl Default for MempoolSettings {
+    fn default() -> Self {
+        MempoolSettings {
+            transaction_ttl: default_mempool_transaction_ttl(),
+            dust_consolidation_threshold: default_dust_consolidation_threshold(),
+            block_lead_time: 0,
+            admission: AdmissionSettings::default(),
+            address_byte_budget: 0,
+            throttle_action: ThrottleAction::default(),
+        }
+    }
+}
+
+/// How hard the miner works on golden tickets.
+#[derive(serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct MinerSettings {
+    /// how many solutions the miner tries every 100 milliseconds
+    #[serde(default = "default_miner_intensity")]
+    pub intensity: u64,
+}
+
+fn default_miner_intensity() -> u64 {
+    1
+}
+
+impl Default for MinerSettings {
+    fn default() -> Self {
+        MinerSettings {
+            intensity: default_miner_intensity(),
+        }
+    }
+}
+
+/// What the wallet holding the staking role does with its staking payouts.
+#[derive(serde::Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct StakingSettings {
+    /// the wallet stakes the StakerPayout nolan paid to it again once they add up to more
+    /// than this, 0 turns this off
+    #[serde(default)]
+    pub auto_compound_threshold: u64,
+}
+
+/// Where the node writes its logs. A log file is rotated once it grows larger than
+/// max_file_size or older than rotation_interval, see logging::RotatingLogFile.
+#[derive(serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct LoggingSettings {
+    #[serde(default = "default_logging_stdout")]
+    pub stdout: bool,
+    /// the file logs are appended to, e.g. data/logs/saito.log
+    #[serde(default)]
+    pub file: Option<String>,
+    /// in bytes, 0 rotates by time only
+    #[serde(default = "default_logging_max_file_size")]
+    pub max_file_size: u64,
+    /// in seconds, 0 rotates by size only
+    #[serde(default = "default_logging_rotation_interval")]
+    pub rotation_interval: u64,
+    /// how many rotated files are kept, the oldest are deleted
+    #[serde(default = "default_logging_max_files")]
+    pub max_files: usize,
+    /// whether rotated files are gzipped
+    #[serde(default = "default_logging_compress")]
+    pub compress: bool,
+}
+
+fn default_logging_stdout() -> bool {
+    true
+}
+
+fn default_logging_max_file_size() -> u64 {
+    LOG_FILE_MAX_SIZE
+}
+
+fn default_logging_rotation_interval() -> u64 {
+    LOG_FILE_ROTATION_INTERVAL
+}
+
+fn default_logging_max_files() -> usize {
+    LOG_FILES_KEPT
+}
+
+fn default_logging_compress() -> bool {
+    true
+}
+
+impl Default for LoggingSettings {
+    fn default() -> Self {
+        LoggingSettings {
+            stdout: default_logging_stdout(),
+            file: None,
+            max_file_size: default_logging_max_file_size(),
+            rotation_interval: default_logging_rotation_interval(),
+            max_files: default_logging_max_files(),
+            compress: default_logging_compress(),
+        }
+    }
+}
+
+/// The free space of the data directory below which the node warns, and below which it stops
+/// storing and pruning blocks, see disk_space::DiskSpaceMonitor.
+#[derive(serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct DiskSpaceSettings {
+    /// how often the free space is checked, in seconds
+    #[serde(default = "default_disk_space_check_interval")]
+    pub check_interval: u64,
+    #[serde(default = "default_disk_space_warn_free_bytes")]
+    pub warn_free_bytes: u64,
+    #[serde(default = "default_disk_space_critical_free_bytes")]
+    pub critical_free_bytes: u64,
+}
+
+fn default_disk_space_check_interval() -> u64 {
+    DISK_SPACE_CHECK_INTERVAL
+}
+
+fn default_disk_space_warn_free_bytes() -> u64 {
+    DISK_SPACE_WARN_FREE_BYTES
+}
+
+fn default_disk_space_critical_free_bytes() -> u64 {
+    DISK_SPACE_CRITICAL_FREE_BYTES
+}
+
+impl Default for DiskSpaceSettings {
+    fn default() -> Self {
+        DiskSpaceSettings {
+            check_interval: default_disk_space_check_interval(),
+            warn_free_bytes: default_disk_space_warn_free_bytes(),
+            critical_free_bytes: default_disk_space_critical_free_bytes(),
+        }
+    }
+}
+
+/// Whether the transactions of the longest chain are indexed by the tag their message starts
+/// with, see memo_index::MemoIndex.
+#[derive(serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct MemoIndexSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    /// how many bytes of a message are its tag
+    #[serde(default = "default_memo_index_tag_length")]
+    pub tag_length: usize,
+}
+
+fn default_memo_index_tag_length() -> usize {
+    MEMO_TAG_LENGTH
+}
+
+impl Default for MemoIndexSettings {
+    fn default() -> Self {
+        MemoIndexSettings {
+            enabled: false,
+            tag_length: default_memo_index_tag_length(),
+        }
+    }
+}
+
+/// A url the node POSTs a JSON webhooks::WebhookEvent to whenever a transaction paying or
+/// spending from one of the base58 addresses is confirmed or reorganized out of the chain.
+#[derive(serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct WebhookSetting {
+    pub url: String,
+    pub addresses: Vec<String>,
+}
+
+/// An additional wallet for the node, loaded from data/wallets. The wallet takes over the
+/// listed roles from the command line wallet.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct WalletSetting {
+    pub filename: String,
+    pub password: String,
+    #[serde(default)]
+    pub roles: Vec<WalletRole>,
+}
+
+/// Settings for remote Peers. The host may be an IPv4 or IPv6 address or a hostname. A peer
+/// listed without roles is used for everything, see networking::peer_role::PeerRole.
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 pub struct PeerSetting {
-    pub host: [u8; 4],
+    pub host: PeerHost,
     pub port: u16,
+    #[serde(default)]
+    pub transport: PeerTransport,
+    #[serde(default)]
+    pub roles: Vec<PeerRole>,
+}
+
+/// Allow and deny lists for peers. Publickeys are base58 encoded and ip ranges are written in
+/// CIDR notation, e.g. 10.0.0.0/8. See networking::peer_filter::PeerFilter.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct PeerFilterSettings {
+    #[serde(default)]
+    pub allowed_publickeys: Vec<String>,
+    #[serde(default)]
+    pub denied_publickeys: Vec<String>,
+    #[serde(default)]
+    pub allowed_ip_ranges: Vec<String>,
+    #[serde(default)]
+    pub denied_ip_ranges: Vec<String>,
+}
+
+/// Where VIP transactions may appear on the network. Publickeys are base58 encoded; if none
+/// are listed, VIP transactions must be signed by the creator of their block. See
+/// vip_policy::VipPolicy.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct VipPolicySettings {
+    /// the last block id which may contain VIP transactions
+    #[serde(default = "default_vip_bootstrap_window")]
+    pub bootstrap_window: u64,
+    #[serde(default)]
+    pub authorized_publickeys: Vec<String>,
+}
+
+fn default_vip_bootstrap_window() -> u64 {
+    1
+}
+
+impl Default for VipPolicySettings {
+    fn default() -> Self {
+        VipPolicySettings {
+            bootstrap_window: default_vip_bootstrap_window(),
+            authorized_publickeys: vec![],
+        }
+    }
 }
 
 #[derive(serde::Deserialize, Clone)]
 pub struct NetworkSettings {
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub port: u16,
-    pub host: [u8; 4],
+    /// the IPv4 or IPv6 address we listen on
+    #[serde(deserialize_with = "deserialize_ip_address")]
+    pub host: IpAddr,
     pub peers: Option<Vec<PeerSetting>>,
+    /// if set, we also accept QUIC connections from peers on this port
+    pub quic_port: Option<u16>,
+    /// if set, and the node is built with the grpc feature, we serve gRPC on this port
+    pub grpc_port: Option<u16>,
+    /// if set, we serve the routes which manage the node, e.g. the peer filter, on this port
+    /// of localhost only
+    #[serde(default)]
+    pub admin_port: Option<u16>,
+    #[serde(default)]
+    pub peer_filter: PeerFilterSettings,
+    /// if set, all our outbound connections to peers go through this proxy
+    #[serde(default)]
+    pub socks5_proxy: Option<Socks5ProxySettings>,
+    /// what we serve our peers, and advertise to them in the handshake
+    #[serde(default)]
+    pub services: PeerServices,
+    /// how often, in seconds, we announce our latest block again while no new block arrives,
+    /// 0 turns the announcements off
+    #[serde(default = "default_tip_announcement_interval")]
+    pub tip_announcement_interval: u64,
+    /// how often, in seconds, we ping our peers to measure their latency and clock offset
+    /// again, 0 turns the pings off
+    #[serde(default = "default_ping_interval")]
+    pub ping_interval: u64,
+    /// what we publish about ourselves in our node metadata record
+    #[serde(default)]
+    pub identity: NodeIdentitySettings,
+    /// if set, the consensus messages our peers send us are appended to this file, to be
+    /// replayed with saitocli replay
+    #[serde(default)]
+    pub message_log: Option<String>,
+    /// if set, the stats of the peers we connect to are kept in this file across restarts,
+    /// and we reconnect to the peers with the best stats first
+    #[serde(default)]
+    pub peer_stats_file: Option<String>,
+}
+
+/// The alias and endpoints of our node metadata record, see networking::node_metadata. The
+/// record also carries our services and the lowest fee rate our mempool takes.
+#[derive(serde::Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct NodeIdentitySettings {
+    #[serde(default)]
+    pub alias: String,
+    /// where peers can reach us, e.g. wss://node.example:12101
+    #[serde(default)]
+    pub endpoints: Vec<String>,
+}
+
+fn default_tip_announcement_interval() -> u64 {
+    TIP_ANNOUNCEMENT_INTERVAL
+}
+
+fn default_ping_interval() -> u64 {
+    PING_INTERVAL
+}
+
+/// A SOCKS5 proxy for outbound connections, e.g. a local Tor client on port 9050. Peers are
+/// reached through the proxy by name, so .onion hosts can be used as peers. Tor gives each
+/// username and password its own circuits.
+#[derive(serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct Socks5ProxySettings {
+    pub host: PeerHost,
+    pub port: u16,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -1,17 +1,37 @@
-use crate::configuration::get_configuration;
+use crate::configuration::{get_configuration, NodeMode, Settings};
 use crate::crypto::SaitoHash;
+use crate::disk_space::DiskSpaceMonitor;
+use crate::genesis::GenesisSpec;
 use crate::golden_ticket::GoldenTicket;
+use crate::health::{check_clock, check_directory_writable, check_wallet_unlockable};
+use crate::logging::{parse_log_level, set_log_level};
+use crate::memo_index::MemoIndex;
 use crate::miner::Miner;
 use crate::network::Network;
-use crate::storage::Storage;
+use crate::networking::peer_filter::PeerFilter;
+use crate::reorg_log::ReorgLog;
+use crate::storage::{Storage, BLOCKS_DIR_PATH};
+use crate::supervisor::Supervisor;
 use crate::test_utilities::test_manager::TestManager;
-use crate::wallet::Wallet;
+use crate::time::create_timestamp;
+use crate::vip_policy::VipPolicy;
+use crate::wallet::{Wallet, WALLETS_DIR_PATH};
+use crate::wallet_manager::{WalletManager, WalletRole};
+use crate::webhooks::Webhooks;
 use crate::{blockchain::Blockchain, mempool::Mempool, transaction::Transaction};
 use clap::{App, Arg};
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use tokio::signal;
+use tokio::sync::broadcast::error::RecvError;
 use tokio::sync::RwLock;
 use tokio::sync::{broadcast, mpsc};
+use tracing::{error, info, warn};
+
+/// How many messages each subsystem may fall behind on the broadcast channel before it
+/// starts missing them.
+pub const BROADCAST_CHANNEL_CAPACITY: usize = 1024;
 
 ///
 /// Saito has the following system-wide messages which may be sent and received
@@ -36,6 +56,112 @@ pub enum SaitoMessage {
     WalletNewTransaction { transaction: Transaction },
 }
 
+/// The subsystems listening on the broadcast channel. Each has its own receiver, so a slow
+/// subsystem only misses messages itself and does not hold up the others.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Subsystem {
+    Mempool,
+    Blockchain,
+    Miner,
+    Network,
+    Grpc,
+    AutoCompound,
+}
+
+/// Counts the messages of the broadcast channel which were lost, either because nobody was
+/// listening when they were sent or because a subsystem fell more than
+/// BROADCAST_CHANNEL_CAPACITY messages behind.
+#[derive(Debug)]
+pub struct BroadcastMetrics {
+    unreceived_messages: AtomicU64,
+    lagged_messages: [AtomicU64; 6],
+}
+
+/// The lost messages of the broadcast channel, as reported by GET /broadcastmetrics.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct BroadcastMetricsReport {
+    pub unreceived_messages: u64,
+    pub mempool_lagged_messages: u64,
+    pub blockchain_lagged_messages: u64,
+    pub miner_lagged_messages: u64,
+    pub network_lagged_messages: u64,
+    pub grpc_lagged_messages: u64,
+    pub auto_compound_lagged_messages: u64,
+}
+
+impl BroadcastMetrics {
+    const fn new() -> Self {
+        BroadcastMetrics {
+            unreceived_messages: AtomicU64::new(0),
+            lagged_messages: [
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+            ],
+        }
+    }
+
+    pub fn get_unreceived_messages(&self) -> u64 {
+        self.unreceived_messages.load(Ordering::Relaxed)
+    }
+
+    pub fn get_lagged_messages(&self, subsystem: Subsystem) -> u64 {
+        self.lagged_messages[subsystem as usize].load(Ordering::Relaxed)
+    }
+
+    pub fn get_report(&self) -> BroadcastMetricsReport {
+        BroadcastMetricsReport {
+            unreceived_messages: self.get_unreceived_messages(),
+            mempool_lagged_messages: self.get_lagged_messages(Subsystem::Mempool),
+            blockchain_lagged_messages: self.get_lagged_messages(Subsystem::Blockchain),
+            miner_lagged_messages: self.get_lagged_messages(Subsystem::Miner),
+            network_lagged_messages: self.get_lagged_messages(Subsystem::Network),
+            grpc_lagged_messages: self.get_lagged_messages(Subsystem::Grpc),
+            auto_compound_lagged_messages: self.get_lagged_messages(Subsystem::AutoCompound),
+        }
+    }
+}
+
+pub static BROADCAST_METRICS: BroadcastMetrics = BroadcastMetrics::new();
+
+/// Sends a message over the broadcast channel. A message nobody is listening for is counted
+/// rather than treated as an error, so that the sender is never brought down by its
+/// listeners.
+pub fn send_message(sender: &broadcast::Sender<SaitoMessage>, message: SaitoMessage) {
+    if let Err(error) = sender.send(message) {
+        BROADCAST_METRICS
+            .unreceived_messages
+            .fetch_add(1, Ordering::Relaxed);
+        warn!("broadcast message has no receivers: {:?}", error.0);
+    }
+}
+
+/// Receives the next message of the broadcast channel for the subsystem. Messages missed
+/// because the subsystem fell behind are counted and skipped. Returns None once the channel
+/// is closed.
+pub async fn recv_message(
+    receiver: &mut broadcast::Receiver<SaitoMessage>,
+    subsystem: Subsystem,
+) -> Option<SaitoMessage> {
+    loop {
+        match receiver.recv().await {
+            Ok(message) => return Some(message),
+            Err(RecvError::Lagged(missed_messages)) => {
+                BROADCAST_METRICS.lagged_messages[subsystem as usize]
+                    .fetch_add(missed_messages, Ordering::Relaxed);
+                warn!(
+                    "{:?} fell behind and missed {} broadcast messages",
+                    subsystem, missed_messages
+                );
+            }
+            Err(RecvError::Closed) => return None,
+        }
+    }
+}
+
 ///
 /// The entry point to the Saito consensus runtime
 ///
@@ -57,17 +183,94 @@ pub async fn run() -> crate::Result<()> {
     tokio::select! {
         res = consensus.run() => {
             if let Err(err) = res {
-                eprintln!("{:?}", err);
+                error!("{}", err);
             }
         },
         _ = signal::ctrl_c() => {
-            println!("Shutting down!")
+            info!("Shutting down!")
         }
     }
 
     Ok(())
 }
 
+/// Applies the settings which can be changed while the node runs: the log level, the peer list
+/// and filter, the limits of the mempool and the intensity of the miner. The other settings
+/// take effect when the node is restarted. Settings with mistakes change nothing.
+pub async fn apply_changeable_settings(
+    settings: &Settings,
+    mempool_lock: Arc<RwLock<Mempool>>,
+    miner_lock: Arc<RwLock<Miner>>,
+    network_lock: Arc<RwLock<Network>>,
+) -> crate::Result<()> {
+    let log_level = settings
+        .log_level
+        .as_deref()
+        .map(parse_log_level)
+        .transpose()?;
+    PeerFilter::from_settings(&settings.network.peer_filter)?;
+
+    if let Some(log_level) = log_level {
+        set_log_level(log_level)?;
+    }
+    let network = network_lock.read().await;
+    Network::apply_peer_filter_settings(
+        &network.get_network_context(),
+        &settings.network.peer_filter,
+    )
+    .await?;
+    network
+        .apply_peer_settings(settings.network.peers.as_deref().unwrap_or_default())
+        .await;
+    drop(network);
+    mempool_lock.write().await.apply_settings(&settings.mempool);
+    miner_lock
+        .write()
+        .await
+        .set_intensity(settings.miner.intensity);
+    Ok(())
+}
+
+//
+// Restarting a node drops its peers and the transactions in its mempool,
+// so a SIGHUP reads the configuration again and applies what can be
+// changed while the node runs instead. A configuration which cannot be
+// read keeps the settings we run with.
+//
+#[cfg(unix)]
+fn reload_configuration_on_hangup(
+    mempool_lock: Arc<RwLock<Mempool>>,
+    miner_lock: Arc<RwLock<Miner>>,
+    network_lock: Arc<RwLock<Network>>,
+) {
+    tokio::spawn(async move {
+        let mut hangup_signal = signal::unix::signal(signal::unix::SignalKind::hangup())
+            .expect("Error setting up hangup signal");
+        while hangup_signal.recv().await.is_some() {
+            info!("reloading the configuration");
+            let result = match get_configuration() {
+                Ok(settings) => {
+                    apply_changeable_settings(
+                        &settings,
+                        mempool_lock.clone(),
+                        miner_lock.clone(),
+                        network_lock.clone(),
+                    )
+                    .await
+                }
+                Err(err) => Err(err.into()),
+            };
+            match result {
+                Ok(()) => info!("reloaded the configuration"),
+                Err(err) => error!(
+                    "ERROR 370101: cannot reload the configuration, keeping the current settings: {}",
+                    err
+                ),
+            }
+        }
+    });
+}
+
 //
 // The consensus state exposes a run method that main
 // calls to initialize Saito state and prepare for
@@ -91,7 +294,14 @@ impl Consensus {
         // channel, and can communicate by sending the events listed in the
         // SaitoMessage list above.
         //
-        let (broadcast_channel_sender, broadcast_channel_receiver) = broadcast::channel(32);
+        let (broadcast_channel_sender, broadcast_channel_receiver) =
+            broadcast::channel(BROADCAST_CHANNEL_CAPACITY);
+
+        //
+        // the mempool sends blocks to be added to the blockchain task over its
+        // own channel
+        //
+        let (blockchain_channel_sender, blockchain_channel_receiver) = mpsc::channel(32);
 
         //
         // handle command-line arguments
@@ -120,6 +330,17 @@ impl Consensus {
                     .long("spammer")
                     .help("enable tx spamming"),
             )
+            .arg(
+                Arg::with_name("rescan")
+                    .long("rescan")
+                    .takes_value(true)
+                    .help("Rebuild wallet balances from this block id"),
+            )
+            .arg(
+                Arg::with_name("paranoid")
+                    .long("paranoid")
+                    .help("Check the utxoset and blockring after every block (slow)"),
+            )
             .get_matches();
 
         //TODO: spammer just served for testing app
@@ -134,6 +355,25 @@ impl Consensus {
 
         // Load configurations based on env
         let settings = get_configuration().expect("Failed to read configuration.");
+        if let Some(log_level) = settings.log_level.as_deref() {
+            set_log_level(parse_log_level(log_level)?)?;
+        }
+
+        //
+        // the startup self-test stops the node with an error saying what to
+        // fix, rather than letting it panic once it is running
+        //
+        check_directory_writable(&BLOCKS_DIR_PATH)?;
+        check_directory_writable(WALLETS_DIR_PATH)?;
+        if matches.value_of("wallet").unwrap() != "none" {
+            check_wallet_unlockable(
+                matches.value_of("wallet").unwrap(),
+                matches.value_of("password").unwrap(),
+            )?;
+        }
+        for wallet_setting in settings.wallets.iter() {
+            check_wallet_unlockable(&wallet_setting.filename, &wallet_setting.password)?;
+        }
 
         //
         // generate core system components
@@ -164,19 +404,98 @@ impl Consensus {
                 let mut wallet = wallet_lock.write().await;
                 wallet.set_filename(walletname.to_string());
                 wallet.set_password(password.to_string());
-                wallet.load();
+                wallet.load()?;
             } else {
                 let mut wallet = wallet_lock.write().await;
                 wallet.save();
             }
         }
 
+        //
+        // additional wallets from the configuration take over roles from
+        // the wallet above, e.g. so that blocks are signed by one key while
+        // staking payouts are made to another.
+        //
+        let mut wallet_manager = WalletManager::new(wallet_lock.clone());
+        for wallet_setting in settings.wallets.iter() {
+            let mut wallet = Wallet::new();
+            wallet.load_wallet(&wallet_setting.filename, Some(&wallet_setting.password))?;
+            info!(
+                "loaded wallet {} for {:?}",
+                wallet_setting.filename, wallet_setting.roles
+            );
+            wallet_manager.add_wallet(Arc::new(RwLock::new(wallet)), &wallet_setting.roles)?;
+        }
+
         let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        blockchain_lock
+            .write()
+            .await
+            .set_wallet_manager(wallet_manager.clone());
+        blockchain_lock
+            .write()
+            .await
+            .set_vip_policy(VipPolicy::from_settings(&settings.vip_policy)?);
+        blockchain_lock
+            .write()
+            .await
+            .set_paranoid(matches.is_present("paranoid"));
+        let mut webhooks = Webhooks::from_settings(&settings.webhooks)?;
+        webhooks.start();
+        blockchain_lock.write().await.set_webhooks(webhooks);
+        blockchain_lock
+            .write()
+            .await
+            .set_memo_index(MemoIndex::from_settings(&settings.memo_index));
+        blockchain_lock
+            .write()
+            .await
+            .set_reorg_log(ReorgLog::new(settings.reorg_log_file.clone()));
+        let disk_space_monitor =
+            DiskSpaceMonitor::from_settings(&BLOCKS_DIR_PATH, &settings.disk_space)?;
+        disk_space_monitor.check(&mut *blockchain_lock.write().await);
+        disk_space_monitor.start(blockchain_lock.clone());
+        wallet_manager.start_saving();
 
         //
         // load blocks from disk and check chain
         //
         Storage::load_blocks_from_disk(blockchain_lock.clone()).await;
+        check_clock(
+            create_timestamp(),
+            blockchain_lock
+                .read()
+                .await
+                .get_latest_block()
+                .map_or(0, |block| block.get_timestamp()),
+        )?;
+
+        //
+        // a new chain starts from the genesis spec if one is configured
+        //
+        if let Some(genesis_file) = settings.genesis_file.as_ref() {
+            if blockchain_lock.read().await.get_latest_block_id() == 0 {
+                let genesis_block = GenesisSpec::load(genesis_file)?
+                    .generate_block(wallet_lock.clone(), blockchain_lock.clone())
+                    .await?;
+                info!("created genesis block from {}", genesis_file);
+                blockchain_lock.write().await.add_block(genesis_block).await;
+            }
+        }
+
+        //
+        // rebuild the wallets from the blocks we have just loaded if asked to
+        //
+        if let Some(from_block_id) = matches.value_of("rescan") {
+            let from_block_id = from_block_id
+                .parse::<u64>()
+                .map_err(|_| format!("invalid rescan block id {}", from_block_id))?;
+            blockchain_lock
+                .read()
+                .await
+                .rescan_wallets(from_block_id)
+                .await?;
+        }
 
         //
         // instantiate core classes
@@ -187,14 +506,40 @@ impl Consensus {
         // require direct access when initializing the object below.
         //
         let mempool_lock = Arc::new(RwLock::new(Mempool::new(wallet_lock.clone())));
-        let miner_lock = Arc::new(RwLock::new(Miner::new(wallet_lock.clone())));
+        {
+            let mut mempool = mempool_lock.write().await;
+            mempool.set_wallet_manager(wallet_manager.clone());
+            mempool.set_bundling_enabled(settings.mode == NodeMode::Full);
+            mempool.apply_settings(&settings.mempool);
+            mempool.set_latest_block_id(blockchain_lock.read().await.get_latest_block_id());
+        }
+        let node_mode = settings.mode;
+        let staking_wallet_lock = wallet_manager.get_wallet_lock(WalletRole::Staking);
+        let auto_compound_threshold = settings.staking.auto_compound_threshold;
+        staking_wallet_lock
+            .write()
+            .await
+            .set_auto_compound_threshold(auto_compound_threshold);
+        let miner_lock = Arc::new(RwLock::new(Miner::new(
+            wallet_manager.get_wallet_lock(WalletRole::Mining),
+        )));
+        miner_lock
+            .write()
+            .await
+            .set_intensity(settings.miner.intensity);
         let network_lock = Arc::new(RwLock::new(Network::new(
             settings,
             blockchain_lock.clone(),
             mempool_lock.clone(),
-            wallet_lock.clone(),
+            wallet_manager.get_wallet_lock(WalletRole::Routing),
             broadcast_channel_sender.clone(),
         )));
+        #[cfg(unix)]
+        reload_configuration_on_hangup(
+            mempool_lock.clone(),
+            miner_lock.clone(),
+            network_lock.clone(),
+        );
 
         //
         // the configuration file should be used to update the network so that
@@ -221,7 +566,10 @@ impl Consensus {
         // start test_manager spammer
         //
         if is_spammer_enabled {
-            let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+            let mut test_manager = TestManager::new(
+                blockchain_lock.clone(),
+                wallet_manager.get_wallet_lock(WalletRole::BlockProducer),
+            );
             test_manager.spam_mempool(mempool_lock.clone());
         }
 
@@ -236,68 +584,212 @@ impl Consensus {
         // The SaitoMessage ENUM above contains a list of all cross-
         // system notifications.
         //
+        // the tasks are supervised, so that a panic restarts the task or shuts
+        // the node down instead of leaving it running without the task.
+        //
+        let mut broadcast_channel_receiver = Some(broadcast_channel_receiver);
         tokio::select! {
 
         //
         // Mempool
         //
-            res = crate::mempool::run(
+            res = Supervisor::new("mempool").run_restarting(|| crate::mempool::run(
                 mempool_lock.clone(),
                 blockchain_lock.clone(),
+                blockchain_channel_sender.clone(),
                 broadcast_channel_sender.clone(),
-                broadcast_channel_receiver,
-            ) => {
+                // the first run takes the messages sent while we started
+                broadcast_channel_receiver
+                    .take()
+                    .unwrap_or_else(|| broadcast_channel_sender.subscribe()),
+            )) => {
                 if let Err(err) = res {
-                    eprintln!("mempool err {:?}", err)
+                    error!("mempool err {:?}", err)
                 }
             },
 
         //
-        // Blockchain
+        // Blockchain, which stores our blocks and cannot be restarted with its channel
         //
-            res = crate::blockchain::run(
+            res = Supervisor::new("blockchain").run(crate::blockchain::run(
                 blockchain_lock.clone(),
+                blockchain_channel_receiver,
                 broadcast_channel_sender.clone(),
                 broadcast_channel_sender.subscribe()
-            ) => {
+            )) => {
                 if let Err(err) = res {
-                    eprintln!("blockchain err {:?}", err)
+                    error!("blockchain err {:?}", err)
                 }
             },
 
         //
-        // Miner
+        // Miner, unless we only relay transactions
         //
-            res = crate::miner::run(
+            res = Supervisor::new("miner").run_restarting(|| crate::miner::run(
                 miner_lock.clone(),
                 broadcast_channel_sender.clone(),
                 broadcast_channel_sender.subscribe()
-            ) => {
+            )), if node_mode == NodeMode::Full => {
                 if let Err(err) = res {
-                    eprintln!("miner err {:?}", err)
+                    error!("miner err {:?}", err)
                 }
             },
 
         //
-        // Network
+        // Network, which cannot be restarted while its servers hold their ports
         //
-            res = crate::network::run(
+            res = Supervisor::new("network").run(crate::network::run(
                 network_lock.clone(),
                 broadcast_channel_sender.clone(),
                 broadcast_channel_sender.subscribe()
-            ) => {
+            )) => {
                 if let Err(err) = res {
-                    eprintln!("miner err {:?}", err)
+                    error!("network err {:?}", err)
                 }
             },
         //
+        // Auto-compounding of staking payouts, if a threshold is set
+        //
+            res = Supervisor::new("auto compound").run_restarting(|| crate::auto_compound::run(
+                staking_wallet_lock.clone(),
+                mempool_lock.clone(),
+                broadcast_channel_sender.subscribe()
+            )), if auto_compound_threshold > 0 => {
+                if let Err(err) = res {
+                    error!("auto compound err {:?}", err)
+                }
+            },
+
+        //
         // Other
         //
             _ = self._shutdown_complete_tx.closed() => {
-                println!("Shutdown message complete")
+                info!("Shutdown message complete")
             }
         }
 
+        //
+        // the wallets are saved periodically, so save what changed since
+        //
+        wallet_manager.save_if_changed().await;
+
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::PeerSetting;
+    use crate::networking::peer_connection::PeerTransport;
+    use crate::networking::peer_host::PeerHost;
+    use std::net::IpAddr;
+
+    #[tokio::test]
+    async fn broadcast_lag_and_unreceived_messages_test() {
+        let (sender, mut receiver) = broadcast::channel(2);
+        let lagged_messages = BROADCAST_METRICS.get_lagged_messages(Subsystem::Miner);
+        for i in 0..4 {
+            send_message(
+                &sender,
+                SaitoMessage::BlockchainSavedBlock { hash: [i; 32] },
+            );
+        }
+
+        // the two oldest messages were dropped for the slow receiver
+        match recv_message(&mut receiver, Subsystem::Miner).await {
+            Some(SaitoMessage::BlockchainSavedBlock { hash }) => assert_eq!(hash, [2; 32]),
+            message => panic!("unexpected message {:?}", message),
+        }
+        assert_eq!(
+            BROADCAST_METRICS.get_lagged_messages(Subsystem::Miner),
+            lagged_messages + 2
+        );
+
+        drop(receiver);
+        let unreceived_messages = BROADCAST_METRICS.get_unreceived_messages();
+        send_message(
+            &sender,
+            SaitoMessage::BlockchainSavedBlock { hash: [0; 32] },
+        );
+        assert_eq!(
+            BROADCAST_METRICS.get_unreceived_messages(),
+            unreceived_messages + 1
+        );
+
+        let mut receiver = sender.subscribe();
+        drop(sender);
+        assert!(recv_message(&mut receiver, Subsystem::Miner)
+            .await
+            .is_none());
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn apply_changeable_settings_test() {
+        let mut settings = get_configuration().expect("Failed to read configuration.");
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let mempool_lock = Arc::new(RwLock::new(Mempool::new(wallet_lock.clone())));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let miner_lock = Arc::new(RwLock::new(Miner::new(wallet_lock.clone())));
+        let (broadcast_channel_sender, _broadcast_channel_receiver) = broadcast::channel(32);
+        let network_lock = Arc::new(RwLock::new(Network::new(
+            settings.clone(),
+            blockchain_lock,
+            mempool_lock.clone(),
+            wallet_lock,
+            broadcast_channel_sender,
+        )));
+        let peers_db_lock = network_lock.read().await.get_network_context().peers_db;
+
+        settings.mempool.transaction_ttl = 7;
+        settings.miner.intensity = 4;
+        settings.network.peers = Some(vec![PeerSetting {
+            host: PeerHost::Ip(IpAddr::from([127, 0, 0, 1])),
+            port: 3099,
+            transport: PeerTransport::default(),
+            roles: vec![],
+        }]);
+        apply_changeable_settings(
+            &settings,
+            mempool_lock.clone(),
+            miner_lock.clone(),
+            network_lock.clone(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(mempool_lock.read().await.get_transaction_ttl(), 7);
+        assert_eq!(miner_lock.read().await.get_intensity(), 4);
+        {
+            let peers_db = peers_db_lock.read().await;
+            assert_eq!(peers_db.len(), 1);
+            let peer = peers_db.values().next().unwrap();
+            assert_eq!(peer.get_port(), Some(3099));
+            assert!(peer.get_is_from_peer_list());
+        }
+
+        // a setting with a mistake changes nothing
+        let mut invalid_settings = settings.clone();
+        invalid_settings.log_level = Some(String::from("loud"));
+        invalid_settings.miner.intensity = 8;
+        assert!(apply_changeable_settings(
+            &invalid_settings,
+            mempool_lock.clone(),
+            miner_lock.clone(),
+            network_lock.clone(),
+        )
+        .await
+        .is_err());
+        assert_eq!(miner_lock.read().await.get_intensity(), 4);
+
+        // peers taken off the list are no longer connected to
+        settings.network.peers = None;
+        apply_changeable_settings(&settings, mempool_lock, miner_lock, network_lock)
+            .await
+            .unwrap();
+        {
+            let peers_db = peers_db_lock.read().await;
+            assert!(!peers_db.values().next().unwrap().get_is_from_peer_list());
+        }
+    }
+}
@@ -0,0 +1,27 @@
+use crate::layout::{SLIP_SIZE, TRANSACTION_LENGTH_PREFIX_SIZE, TRANSACTION_SIZE};
+use crate::transaction::MAX_TRANSACTION_OUTPUTS;
+
+/// The limits every node must agree on for a block to be valid. The limits of the transports
+/// that carry blocks are derived from them, so that no valid block is too large to be sent.
+pub struct ConsensusParams;
+
+impl ConsensusParams {
+    /// Max bytes in a block, counting its header and the transactions chosen by its creator,
+    /// see Block::get_limited_size. Block producers stop adding transactions at it.
+    pub const MAX_BLOCK_SIZE: usize = 10_000_000;
+
+    /// Max bytes of the ATR transactions in a block, with their length prefixes. Unspent slips
+    /// past it are not rebroadcast and are collected as fees like dust, so that the outputs
+    /// of one block cannot make the block rebroadcasting them any larger.
+    pub const MAX_REBROADCAST_SIZE: usize = 3 * Self::MAX_BLOCK_SIZE;
+
+    /// Max bytes of the fee transaction, with its length prefix. It pays a few slips for each
+    /// block it collects the fees of, far fewer than a transaction may hold.
+    pub const MAX_FEE_TRANSACTION_SIZE: usize =
+        TRANSACTION_LENGTH_PREFIX_SIZE + TRANSACTION_SIZE + 2 * MAX_TRANSACTION_OUTPUTS * SLIP_SIZE;
+
+    /// Max bytes of a whole block as serialized for the network, ATR and fee transactions
+    /// included. Blocks past it are rejected, and every transport carrying blocks allows it.
+    pub const MAX_SERIALIZED_BLOCK_SIZE: usize =
+        Self::MAX_BLOCK_SIZE + Self::MAX_REBROADCAST_SIZE + Self::MAX_FEE_TRANSACTION_SIZE;
+}
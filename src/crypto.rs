@@ -1,7 +1,7 @@
-use base58::ToBase58;
-use blake3::Hasher;
-use ring::digest::{Algorithm, SHA256 as sha256};
+use base58::{FromBase58, ToBase58};
+use ring::digest::{Algorithm, Context, SHA256 as sha256};
 pub use secp256k1::{Message, PublicKey, SecretKey, Signature, SECP256K1};
+use serde::{Deserialize, Serialize};
 pub static SHA256: &Algorithm = &sha256;
 pub use merkle::MerkleTree;
 
@@ -13,7 +13,7 @@ use block_modes::{BlockMode, Cbc};
 type Aes128Cbc = Cbc<Aes128, Pkcs7>;
 
 pub type SaitoHash = [u8; 32];
-pub type SaitoUTXOSetKey = [u8; 74];
+pub type SaitoUTXOSetKey = [u8; 75];
 pub type SaitoPublicKey = [u8; 33];
 pub type SaitoPrivateKey = [u8; 32]; // 256-bit key
 pub type SaitoSignature = [u8; 64];
@@ -33,6 +33,12 @@ pub fn encrypt_with_password(msg: Vec<u8>, password: &str) -> Vec<u8> {
     return encrypt_msg;
 }
 pub fn decrypt_with_password(msg: Vec<u8>, password: &str) -> Vec<u8> {
+    try_decrypt_with_password(&msg, password).unwrap()
+}
+
+/// Like decrypt_with_password, but returns None rather than panicking if the message cannot
+/// be decrypted, which almost always means the password is wrong.
+pub fn try_decrypt_with_password(msg: &[u8], password: &str) -> Option<Vec<u8>> {
     let hash = hash(&password.as_bytes().to_vec());
     let mut key: [u8; 16] = [0; 16];
     let mut iv: [u8; 16] = [0; 16];
@@ -40,9 +46,7 @@ pub fn decrypt_with_password(msg: Vec<u8>, password: &str) -> Vec<u8> {
     iv.clone_from_slice(&hash[16..32]);
 
     let cipher = Aes128Cbc::new_from_slices(&key, &iv).unwrap();
-    let decrypt_msg = cipher.decrypt_vec(&msg).unwrap();
-
-    return decrypt_msg;
+    cipher.decrypt_vec(msg).ok()
 }
 
 pub fn generate_keys() -> (SaitoPublicKey, SaitoPrivateKey) {
@@ -71,6 +75,15 @@ pub fn generate_keypair_from_privatekey(slice: &[u8]) -> (SaitoPublicKey, SaitoP
     (public_key.serialize(), secret_bytes)
 }
 
+/// Decode a base58 encoded publickey, checking that it is a valid secp256k1 key
+pub fn publickey_from_base58(publickey: &str) -> crate::Result<SaitoPublicKey> {
+    let bytes = publickey
+        .from_base58()
+        .map_err(|_| format!("invalid base58 publickey {}", publickey))?;
+    PublicKey::from_slice(&bytes)?;
+    Ok(bytes.try_into().unwrap())
+}
+
 pub fn sign_blob(vbytes: &mut Vec<u8>, privatekey: SaitoPrivateKey) -> &mut Vec<u8> {
     let sig = sign(&hash(vbytes.as_ref()), privatekey);
     vbytes.extend(&sig);
@@ -85,17 +98,135 @@ pub fn generate_random_bytes(len: u64) -> Vec<u8> {
     (0..len).map(|_| rand::random::<u8>()).collect()
 }
 
-pub fn hash(data: &Vec<u8>) -> SaitoHash {
-    let mut hasher = Hasher::new();
-    // Hashing in parallel can be faster if large enough
-    // TODO: Blake3 has benchmarked 128 kb as the cutoff,
-    // the benchmark should be redone for Saito's needs
-    if data.len() > PARALLEL_HASH_BYTE_THRESHOLD {
-        hasher.update(data);
-    } else {
-        hasher.update_rayon(data);
+/// The hash functions of consensus. Every block names the algorithm its hashes and merkle
+/// root are computed with, and HASH_ALGORITHM_SCHEDULE says which algorithm blocks must use
+/// from which block id on, so that moving to another hash function is a fork at a block id
+/// rather than a change to every place which hashes.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+#[repr(u8)]
+pub enum HashAlgorithm {
+    #[default]
+    Blake3 = 0,
+    Sha256 = 1,
+}
+
+/// The block ids from which blocks are hashed with each algorithm, in order of block id.
+#[cfg(not(feature = "experimental-sha256-blocks"))]
+pub const HASH_ALGORITHM_SCHEDULE: [(u64, HashAlgorithm); 1] = [(0, HashAlgorithm::Blake3)];
+
+//
+// nodes built with this feature hash their blocks, merkle trees and
+// rebroadcast hashes with SHA-256 from the genesis block on, so that block
+// production can be timed against blake3 end to end. They are on a chain of
+// their own and are for test networks only.
+//
+#[cfg(feature = "experimental-sha256-blocks")]
+pub const HASH_ALGORITHM_SCHEDULE: [(u64, HashAlgorithm); 1] = [(0, HashAlgorithm::Sha256)];
+
+impl HashAlgorithm {
+    /// The algorithm the block with the given id must be hashed with.
+    pub fn for_block_id(block_id: u64) -> HashAlgorithm {
+        HASH_ALGORITHM_SCHEDULE
+            .iter()
+            .rev()
+            .find(|(from_block_id, _)| block_id >= *from_block_id)
+            .map_or(HashAlgorithm::default(), |(_, algorithm)| *algorithm)
+    }
+}
+
+impl TryFrom<u8> for HashAlgorithm {
+    type Error = String;
+    fn try_from(id: u8) -> Result<Self, Self::Error> {
+        match id {
+            0 => Ok(HashAlgorithm::Blake3),
+            1 => Ok(HashAlgorithm::Sha256),
+            _ => Err(format!("unknown hash algorithm {}", id)),
+        }
+    }
+}
+
+// hashers live briefly on the stack, boxing the blake3 state would only add an allocation
+#[allow(clippy::large_enum_variant)]
+enum ConsensusHasherState {
+    Blake3(blake3::Hasher),
+    Sha256(Context),
+}
+
+/// An incremental hasher for one of the consensus hash algorithms, so that objects can be
+/// hashed as they are written out without first being serialized into a buffer.
+pub struct ConsensusHasher {
+    state: ConsensusHasherState,
+}
+
+impl ConsensusHasher {
+    pub fn new(algorithm: HashAlgorithm) -> Self {
+        let state = match algorithm {
+            HashAlgorithm::Blake3 => ConsensusHasherState::Blake3(blake3::Hasher::new()),
+            HashAlgorithm::Sha256 => ConsensusHasherState::Sha256(Context::new(SHA256)),
+        };
+        ConsensusHasher { state }
+    }
+
+    pub fn get_algorithm(&self) -> HashAlgorithm {
+        match self.state {
+            ConsensusHasherState::Blake3(_) => HashAlgorithm::Blake3,
+            ConsensusHasherState::Sha256(_) => HashAlgorithm::Sha256,
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match &mut self.state {
+            ConsensusHasherState::Blake3(hasher) => {
+                // Hashing in parallel can be faster if large enough
+                // TODO: Blake3 has benchmarked 128 kb as the cutoff,
+                // the benchmark should be redone for Saito's needs
+                if data.len() > PARALLEL_HASH_BYTE_THRESHOLD {
+                    update_rayon(hasher, data);
+                } else {
+                    hasher.update(data);
+                }
+            }
+            ConsensusHasherState::Sha256(context) => context.update(data),
+        }
     }
-    hasher.finalize().into()
+
+    pub fn finalize(self) -> SaitoHash {
+        match self.state {
+            ConsensusHasherState::Blake3(hasher) => hasher.finalize().into(),
+            ConsensusHasherState::Sha256(context) => context.finish().as_ref().try_into().unwrap(),
+        }
+    }
+}
+
+impl Default for ConsensusHasher {
+    fn default() -> Self {
+        ConsensusHasher::new(HashAlgorithm::default())
+    }
+}
+
+//
+// rayon is not available to the core feature, e.g. on wasm32
+//
+#[cfg(feature = "node")]
+fn update_rayon(hasher: &mut blake3::Hasher, data: &[u8]) {
+    hasher.update_rayon(data);
+}
+
+#[cfg(not(feature = "node"))]
+fn update_rayon(hasher: &mut blake3::Hasher, data: &[u8]) {
+    hasher.update(data);
+}
+
+/// Hashes the data with the default algorithm, which is the algorithm of everything not
+/// hashed as part of a block, e.g. transactions and golden tickets.
+pub fn hash(data: &[u8]) -> SaitoHash {
+    hash_with(HashAlgorithm::default(), data)
+}
+
+pub fn hash_with(algorithm: HashAlgorithm, data: &[u8]) -> SaitoHash {
+    let mut hasher = ConsensusHasher::new(algorithm);
+    hasher.update(data);
+    hasher.finalize()
 }
 
 pub fn sign(message_bytes: &[u8], privatekey: SaitoPrivateKey) -> SaitoSignature {
@@ -126,6 +257,39 @@ mod tests {
     use hex::FromHex;
     use std::str;
 
+    #[test]
+    fn consensus_hasher_test() {
+        assert_eq!(
+            hex::encode(hash_with(HashAlgorithm::Sha256, b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hash_with(HashAlgorithm::Blake3, b"abc"),
+            <[u8; 32]>::from(blake3::hash(b"abc"))
+        );
+        assert_eq!(hash(b"abc"), hash_with(HashAlgorithm::Blake3, b"abc"));
+
+        // hashing in pieces, and in parallel, gives the hash of the whole
+        let data = vec![7; PARALLEL_HASH_BYTE_THRESHOLD + 1];
+        for algorithm in [HashAlgorithm::Blake3, HashAlgorithm::Sha256] {
+            let mut hasher = ConsensusHasher::new(algorithm);
+            assert_eq!(hasher.get_algorithm(), algorithm);
+            hasher.update(&data[..10]);
+            hasher.update(&data[10..]);
+            assert_eq!(hasher.finalize(), hash_with(algorithm, &data));
+        }
+
+        for algorithm in [HashAlgorithm::Blake3, HashAlgorithm::Sha256] {
+            assert_eq!(HashAlgorithm::try_from(algorithm as u8), Ok(algorithm));
+        }
+        assert!(HashAlgorithm::try_from(2).is_err());
+        assert_eq!(HashAlgorithm::for_block_id(0), HASH_ALGORITHM_SCHEDULE[0].1);
+        assert_eq!(
+            HashAlgorithm::for_block_id(1_000_000),
+            HASH_ALGORITHM_SCHEDULE[HASH_ALGORITHM_SCHEDULE.len() - 1].1
+        );
+    }
+
     #[test]
     //
     // test symmetrical encryption works properly
@@ -0,0 +1,231 @@
+use crate::blockchain::Blockchain;
+use crate::configuration::DiskSpaceSettings;
+use log::{error, info, warn};
+use serde::Serialize;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+//
+// A node whose disk fills up writes block files it cannot finish, and then
+// prunes blocks from memory whose only complete copy was to be on disk, so
+// that neither the blocks nor the utxoset and wallets derived from them can
+// be rebuilt. The monitor checks the free space of the data directory
+// periodically. Below the warning threshold it warns, below the critical
+// threshold the blockchain stops storing new blocks and refuses to prune
+// the blocks it has not stored, and writes them once space is freed.
+//
+
+/// How often the free space is checked, in seconds.
+pub const DISK_SPACE_CHECK_INTERVAL: u64 = 60;
+/// Below this many free bytes the node warns.
+pub const DISK_SPACE_WARN_FREE_BYTES: u64 = 10 * 1024 * 1024 * 1024;
+/// Below this many free bytes the node stops storing blocks and pruning them.
+pub const DISK_SPACE_CRITICAL_FREE_BYTES: u64 = 1024 * 1024 * 1024;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum DiskSpaceStatus {
+    /// there is more free space than the warning threshold, or it cannot be read
+    #[default]
+    Ok,
+    /// the free space is below the warning threshold
+    Low,
+    /// the free space is below the critical threshold, blocks are kept in memory only
+    Critical,
+}
+
+/// Periodically checks the free space of the filesystem holding a directory and sets the
+/// resulting status on the blockchain.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiskSpaceMonitor {
+    path: String,
+    check_interval: Duration,
+    warn_free_bytes: u64,
+    critical_free_bytes: u64,
+}
+
+impl DiskSpaceMonitor {
+    pub fn from_settings(
+        path: &str,
+        disk_space_settings: &DiskSpaceSettings,
+    ) -> crate::Result<DiskSpaceMonitor> {
+        if disk_space_settings.critical_free_bytes > disk_space_settings.warn_free_bytes {
+            return Err(format!(
+                "disk_space.critical_free_bytes ({}) is above disk_space.warn_free_bytes ({})",
+                disk_space_settings.critical_free_bytes, disk_space_settings.warn_free_bytes
+            )
+            .into());
+        }
+        Ok(DiskSpaceMonitor {
+            path: path.to_string(),
+            check_interval: Duration::from_secs(disk_space_settings.check_interval.max(1)),
+            warn_free_bytes: disk_space_settings.warn_free_bytes,
+            critical_free_bytes: disk_space_settings.critical_free_bytes,
+        })
+    }
+
+    pub fn get_status(&self, free_bytes: u64) -> DiskSpaceStatus {
+        if free_bytes < self.critical_free_bytes {
+            DiskSpaceStatus::Critical
+        } else if free_bytes < self.warn_free_bytes {
+            DiskSpaceStatus::Low
+        } else {
+            DiskSpaceStatus::Ok
+        }
+    }
+
+    /// Reads the free space and sets the status on the blockchain, logging when it changes.
+    /// The status is left alone if the free space cannot be read.
+    pub fn check(&self, blockchain: &mut Blockchain) {
+        let free_bytes = match get_free_bytes(Path::new(&self.path)) {
+            Ok(free_bytes) => free_bytes,
+            Err(err) => {
+                error!(
+                    "ERROR 360101: cannot read the free space of {}: {:?}",
+                    self.path, err
+                );
+                return;
+            }
+        };
+        let status = self.get_status(free_bytes);
+        if status == blockchain.get_disk_space_status() {
+            return;
+        }
+        match status {
+            DiskSpaceStatus::Ok => info!("{} bytes free in {}", free_bytes, self.path),
+            DiskSpaceStatus::Low => warn!(
+                "only {} bytes free in {}, the node stops storing blocks below {}",
+                free_bytes, self.path, self.critical_free_bytes
+            ),
+            DiskSpaceStatus::Critical => error!(
+                "ERROR 360102: only {} bytes free in {}, blocks are kept in memory until space is freed",
+                free_bytes, self.path
+            ),
+        }
+        blockchain.set_disk_space_status(status);
+    }
+
+    /// Spawns the task checking the free space.
+    pub fn start(self, blockchain_lock: Arc<RwLock<Blockchain>>) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.check_interval);
+            loop {
+                interval.tick().await;
+                self.check(&mut *blockchain_lock.write().await);
+            }
+        });
+    }
+}
+
+/// The bytes available to the node on the filesystem holding the path. A path which does not
+/// exist yet is looked up through its closest existing ancestor.
+#[cfg(unix)]
+pub fn get_free_bytes(path: &Path) -> io::Result<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = path
+        .ancestors()
+        .find(|ancestor| ancestor.exists())
+        .filter(|ancestor| !ancestor.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+pub fn get_free_bytes(_path: &Path) -> io::Result<u64> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "free space is only read on unix",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::BlockType;
+    use crate::blockchain::PRUNE_AFTER_BLOCKS;
+    use crate::test_utilities::test_manager::TestManager;
+    use crate::wallet::Wallet;
+
+    #[test]
+    fn disk_space_status_test() {
+        let mut disk_space_settings = DiskSpaceSettings {
+            check_interval: 1,
+            warn_free_bytes: 100,
+            critical_free_bytes: 10,
+        };
+        let monitor = DiskSpaceMonitor::from_settings("./data/", &disk_space_settings).unwrap();
+        assert_eq!(monitor.get_status(100), DiskSpaceStatus::Ok);
+        assert_eq!(monitor.get_status(99), DiskSpaceStatus::Low);
+        assert_eq!(monitor.get_status(10), DiskSpaceStatus::Low);
+        assert_eq!(monitor.get_status(9), DiskSpaceStatus::Critical);
+
+        disk_space_settings.critical_free_bytes = 101;
+        assert!(DiskSpaceMonitor::from_settings("./data/", &disk_space_settings).is_err());
+
+        assert!(get_free_bytes(Path::new("./data/test/does/not/exist/")).unwrap() > 0);
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn disk_space_critical_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+
+        // no filesystem has this much space, so every block is kept in memory only
+        let monitor = DiskSpaceMonitor::from_settings(
+            "./data/",
+            &DiskSpaceSettings {
+                check_interval: 1,
+                warn_free_bytes: u64::MAX,
+                critical_free_bytes: u64::MAX,
+            },
+        )
+        .unwrap();
+        monitor.check(&mut *blockchain_lock.write().await);
+        assert_eq!(
+            blockchain_lock.read().await.get_disk_space_status(),
+            DiskSpaceStatus::Critical
+        );
+        test_manager
+            .generate_blockchain(PRUNE_AFTER_BLOCKS + 2, [0; 32])
+            .await;
+
+        let mut blockchain = blockchain_lock.write().await;
+        assert_eq!(
+            blockchain.get_unstored_block_hashes().len() as u64,
+            PRUNE_AFTER_BLOCKS + 2
+        );
+
+        // the blocks old enough to be pruned are kept whole, as they are not on disk
+        let hash = blockchain
+            .blockring
+            .get_longest_chain_block_hash_by_block_id(2);
+        let block = blockchain.get_block_sync(&hash).unwrap();
+        let timestamp = block.get_timestamp();
+        assert_eq!(block.get_block_type(), BlockType::Full);
+        assert!(blockchain
+            .get_block_storage()
+            .read_block(timestamp, &hash)
+            .is_err());
+
+        // once space is freed the blocks are stored after all
+        blockchain.set_disk_space_status(DiskSpaceStatus::Low);
+        assert!(blockchain.get_unstored_block_hashes().is_empty());
+        assert!(blockchain
+            .get_block_storage()
+            .read_block(timestamp, &hash)
+            .is_ok());
+    }
+}
@@ -0,0 +1,198 @@
+use crate::amount::Amount;
+use crate::block::Block;
+use crate::blockchain::{Blockchain, MAX_TOKEN_SUPPLY};
+use crate::crypto::publickey_from_base58;
+use crate::time::create_timestamp;
+use crate::transaction::Transaction;
+use crate::vip_policy::VipPolicy;
+use crate::wallet::Wallet;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// An initial payment made by the genesis block. The publickey is base58 encoded and the
+/// amount is in nolan.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct GenesisAllocation {
+    pub publickey: String,
+    pub amount: u64,
+}
+
+/// The specification of the first block of a chain, read from a JSON or TOML file so that
+/// private networks can define their own initial distribution. Each allocation is paid out
+/// by a VIP transaction.
+///
+/// ```json
+/// {
+///   "timestamp": 1637034582666,
+///   "treasury": 0,
+///   "allocations": [
+///     { "publickey": "fPNUJDc8ipMMnLFgGmDPQkNvRSNZbL1FsxTG6YPxHCHh", "amount": 1000000000 }
+///   ]
+/// }
+/// ```
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct GenesisSpec {
+    /// defaults to the time the block is created
+    #[serde(default)]
+    pub timestamp: Option<u64>,
+    #[serde(default)]
+    pub treasury: u64,
+    pub allocations: Vec<GenesisAllocation>,
+}
+
+impl GenesisSpec {
+    pub fn new(allocations: Vec<GenesisAllocation>) -> Self {
+        GenesisSpec {
+            timestamp: None,
+            treasury: 0,
+            allocations,
+        }
+    }
+
+    /// Reads a spec from a file, the format being taken from its extension.
+    pub fn load(filename: &str) -> crate::Result<GenesisSpec> {
+        let mut spec_file = config::Config::default();
+        spec_file.merge(config::File::with_name(filename))?;
+        let genesis_spec: GenesisSpec = spec_file.try_into()?;
+        genesis_spec.validate()?;
+        Ok(genesis_spec)
+    }
+
+    /// Checks the publickeys are valid and that no more than MAX_TOKEN_SUPPLY is issued.
+    pub fn validate(&self) -> crate::Result<()> {
+        if self.allocations.is_empty() {
+            return Err("the genesis spec has no allocations".into());
+        }
+        for allocation in &self.allocations {
+            publickey_from_base58(&allocation.publickey)?;
+        }
+        let total_issued = Amount::checked_sum(
+            self.allocations
+                .iter()
+                .map(|allocation| allocation.amount.into())
+                .chain(std::iter::once(self.treasury.into())),
+        );
+        match total_issued {
+            Some(total_issued) if total_issued.as_nolan() <= MAX_TOKEN_SUPPLY => Ok(()),
+            _ => Err(format!(
+                "the genesis spec issues more than the maximum supply of {} nolan",
+                MAX_TOKEN_SUPPLY
+            )
+            .into()),
+        }
+    }
+
+    /// Creates the VIP transactions paying out the allocations, signed by the wallet.
+    pub fn generate_transactions(
+        &self,
+        vip_policy: &VipPolicy,
+        wallet: &Wallet,
+    ) -> crate::Result<Vec<Transaction>> {
+        let mut transactions = vec![];
+        for allocation in &self.allocations {
+            let publickey = publickey_from_base58(&allocation.publickey)?;
+            transactions
+                .push(vip_policy.create_vip_transaction(wallet, &[(publickey, allocation.amount)]));
+        }
+        Ok(transactions)
+    }
+
+    /// Creates the first block of the chain, produced and signed by the wallet.
+    pub async fn generate_block(
+        &self,
+        wallet_lock: Arc<RwLock<Wallet>>,
+        blockchain_lock: Arc<RwLock<Blockchain>>,
+    ) -> crate::Result<Block> {
+        self.validate()?;
+        let mut transactions = self.generate_transactions(
+            blockchain_lock.read().await.get_vip_policy(),
+            &*wallet_lock.read().await,
+        )?;
+        let timestamp = self.timestamp.unwrap_or_else(create_timestamp);
+
+        let mut block = Block::generate(
+            &mut transactions,
+            [0; 32],
+            wallet_lock.clone(),
+            blockchain_lock,
+            timestamp,
+        )
+        .await?;
+
+        //
+        // the treasury is part of what the block producer signs
+        //
+        if self.treasury > 0 {
+            let wallet = wallet_lock.read().await;
+            block.set_treasury(self.treasury);
+            block.sign(wallet.get_publickey(), wallet.get_privatekey());
+        }
+        block.generate_metadata();
+
+        Ok(block)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::generate_keys;
+    use base58::ToBase58;
+
+    #[test]
+    fn genesis_spec_validate_test() {
+        let (publickey, _) = generate_keys();
+        let mut genesis_spec = GenesisSpec::new(vec![GenesisAllocation {
+            publickey: publickey.to_base58(),
+            amount: 1_000_000,
+        }]);
+        assert!(genesis_spec.validate().is_ok());
+
+        genesis_spec.treasury = MAX_TOKEN_SUPPLY;
+        assert!(genesis_spec.validate().is_err());
+        genesis_spec.treasury = 0;
+
+        genesis_spec.allocations[0].publickey = String::from("not a publickey");
+        assert!(genesis_spec.validate().is_err());
+
+        assert!(GenesisSpec::new(vec![]).validate().is_err());
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn genesis_spec_generate_block_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let (publickey, _) = generate_keys();
+
+        let genesis_spec_json = format!(
+            r#"{{ "timestamp": 1637034582666, "treasury": 5000, "allocations": [
+                {{ "publickey": "{}", "amount": 1000000 }},
+                {{ "publickey": "{}", "amount": 2000000 }} ] }}"#,
+            publickey.to_base58(),
+            wallet_lock.read().await.get_publickey().to_base58()
+        );
+        let genesis_spec: GenesisSpec = serde_json::from_str(&genesis_spec_json).unwrap();
+
+        let block = genesis_spec
+            .generate_block(wallet_lock.clone(), blockchain_lock.clone())
+            .await
+            .unwrap();
+        assert_eq!(block.get_id(), 1);
+        assert_eq!(block.get_timestamp(), 1637034582666);
+        assert_eq!(block.get_treasury(), 5000);
+        assert_eq!(block.get_transactions().len(), 2);
+        assert_eq!(
+            block.get_transactions()[0].get_outputs()[0].get_publickey(),
+            publickey
+        );
+
+        let block_hash = block.get_hash();
+        blockchain_lock.write().await.add_block(block).await;
+        assert_eq!(
+            blockchain_lock.read().await.get_latest_block_hash(),
+            block_hash
+        );
+        assert_eq!(wallet_lock.read().await.get_available_balance(), 2000000);
+    }
+}
@@ -0,0 +1,288 @@
+use crate::blockchain::Blockchain;
+use crate::burnfee::HEARTBEAT;
+use crate::crypto::try_decrypt_with_password;
+use crate::disk_space::DiskSpaceStatus;
+use crate::mempool::Mempool;
+use crate::storage::Storage;
+use crate::sync::SyncState;
+use crate::time::MAX_FUTURE_BLOCK_TIME;
+use crate::wallet::{WALLETS_DIR_PATH, WALLET_KEYS_SIZE};
+use serde::Serialize;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// the age in milliseconds beyond which the latest block means we are no longer in sync
+pub const MAX_SYNCED_BLOCK_AGE: u64 = 10 * HEARTBEAT;
+/// the earliest timestamp a correctly set clock can show, 2021-01-01T00:00:00Z
+pub const MIN_SANE_TIMESTAMP: u64 = 1_609_459_200_000;
+
+/// The reply to GET /health. The node is in sync if its peers' tips say so, or, before any
+/// peer has announced its tip, if its latest block is no older than MAX_SYNCED_BLOCK_AGE.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct HealthReport {
+    pub is_synced: bool,
+    pub sync_state: SyncState,
+    pub network_tip_block_id: u64,
+    pub latest_block_id: u64,
+    pub latest_block_hash: String,
+    pub peers_connected: usize,
+    pub mempool_transactions: usize,
+    pub last_block_age: Option<u64>,
+    pub disk_space_status: DiskSpaceStatus,
+}
+
+impl HealthReport {
+    pub fn new(
+        blockchain: &Blockchain,
+        mempool: &Mempool,
+        peers_connected: usize,
+        current_timestamp: u64,
+    ) -> Self {
+        let last_block_age = blockchain
+            .get_latest_block()
+            .map(|block| current_timestamp.saturating_sub(block.get_timestamp()));
+        let sync_status = blockchain.get_sync_status();
+        let is_synced = match sync_status.get_state() {
+            SyncState::Synced => true,
+            SyncState::SyncingHeaders | SyncState::SyncingBlocks => false,
+            SyncState::NotSynced => {
+                matches!(last_block_age, Some(age) if age <= MAX_SYNCED_BLOCK_AGE)
+            }
+        };
+        HealthReport {
+            is_synced,
+            sync_state: sync_status.get_state(),
+            network_tip_block_id: sync_status.get_network_tip_block_id(),
+            latest_block_id: blockchain.get_latest_block_id(),
+            latest_block_hash: hex::encode(blockchain.get_latest_block_hash()),
+            peers_connected,
+            mempool_transactions: mempool.transactions.len(),
+            last_block_age,
+            disk_space_status: blockchain.get_disk_space_status(),
+        }
+    }
+}
+
+/// A problem found by the startup self-test. Each one tells the operator what to fix, so
+/// that the node stops before starting rather than panicking once it is running.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SelfTestError {
+    DirectoryNotWritable {
+        path: String,
+        reason: String,
+    },
+    WalletNotReadable {
+        filename: String,
+        reason: String,
+    },
+    WalletNotUnlockable {
+        filename: String,
+    },
+    ClockBeforeMinimum {
+        timestamp: u64,
+    },
+    ClockBehindChain {
+        timestamp: u64,
+        latest_block_timestamp: u64,
+    },
+}
+
+impl fmt::Display for SelfTestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SelfTestError::DirectoryNotWritable { path, reason } => write!(
+                f,
+                "ERROR 910001: cannot write to {} ({}), check that the disk is not full and that the node may write to the directory",
+                path, reason
+            ),
+            SelfTestError::WalletNotReadable { filename, reason } => write!(
+                f,
+                "ERROR 910002: cannot read wallet {} ({}), check the wallet filename and its permissions",
+                filename, reason
+            ),
+            SelfTestError::WalletNotUnlockable { filename } => write!(
+                f,
+                "ERROR 910003: cannot unlock wallet {}, check the password given for it",
+                filename
+            ),
+            SelfTestError::ClockBeforeMinimum { timestamp } => write!(
+                f,
+                "ERROR 910004: the system clock reads {}, which cannot be right, set the clock or enable time synchronization",
+                timestamp
+            ),
+            SelfTestError::ClockBehindChain {
+                timestamp,
+                latest_block_timestamp,
+            } => write!(
+                f,
+                "ERROR 910005: the system clock reads {}, more than {}ms behind the latest block at {}, set the clock or enable time synchronization",
+                timestamp, MAX_FUTURE_BLOCK_TIME, latest_block_timestamp
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SelfTestError {}
+
+/// Checks that files can be created in the directory, creating it if need be.
+pub fn check_directory_writable(path: &str) -> Result<(), SelfTestError> {
+    let not_writable = |err: std::io::Error| SelfTestError::DirectoryNotWritable {
+        path: path.to_string(),
+        reason: err.to_string(),
+    };
+    fs::create_dir_all(path).map_err(not_writable)?;
+    let probe_filename = Path::new(path).join(".self_test");
+    fs::write(&probe_filename, b"saito").map_err(not_writable)?;
+    fs::remove_file(&probe_filename).map_err(not_writable)
+}
+
+/// Checks that the wallet saved as filename in WALLETS_DIR_PATH can be unlocked with the
+/// password. A wallet which does not exist yet passes, as it is created on load.
+pub fn check_wallet_unlockable(filename: &str, password: &str) -> Result<(), SelfTestError> {
+    let path = format!("{}{}", WALLETS_DIR_PATH, filename);
+    if !Storage::file_exists(&path) {
+        return Ok(());
+    }
+    let encrypted_wallet =
+        Storage::read(&path).map_err(|err| SelfTestError::WalletNotReadable {
+            filename: filename.to_string(),
+            reason: err.to_string(),
+        })?;
+    match try_decrypt_with_password(&encrypted_wallet, password) {
+        Some(decrypted_wallet) if decrypted_wallet.len() >= WALLET_KEYS_SIZE => Ok(()),
+        _ => Err(SelfTestError::WalletNotUnlockable {
+            filename: filename.to_string(),
+        }),
+    }
+}
+
+/// Checks that the clock is plausible, and not so far behind the latest block that blocks
+/// from the network would look like they come from the future.
+pub fn check_clock(timestamp: u64, latest_block_timestamp: u64) -> Result<(), SelfTestError> {
+    if timestamp < MIN_SANE_TIMESTAMP {
+        return Err(SelfTestError::ClockBeforeMinimum { timestamp });
+    }
+    if latest_block_timestamp > timestamp.saturating_add(MAX_FUTURE_BLOCK_TIME) {
+        return Err(SelfTestError::ClockBehindChain {
+            timestamp,
+            latest_block_timestamp,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::encrypt_with_password;
+    use crate::test_utilities::test_manager::TestManager;
+    use crate::time::create_timestamp;
+    use crate::wallet::Wallet;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    #[test]
+    fn check_directory_writable_test() {
+        assert!(check_directory_writable("./data/test/self_test/").is_ok());
+        fs::remove_dir("./data/test/self_test/").unwrap();
+
+        // a directory cannot be made under a file
+        let result = check_directory_writable("./Cargo.toml/self_test/");
+        assert!(matches!(
+            result,
+            Err(SelfTestError::DirectoryNotWritable { .. })
+        ));
+    }
+
+    #[test]
+    fn check_wallet_unlockable_test() {
+        let filename = "check_wallet_unlockable_test";
+        let wallet = Wallet::new();
+        fs::write(
+            format!("{}{}", WALLETS_DIR_PATH, filename),
+            encrypt_with_password(wallet.serialize_for_disk(), "password"),
+        )
+        .unwrap();
+
+        assert!(check_wallet_unlockable(filename, "password").is_ok());
+        assert_eq!(
+            check_wallet_unlockable(filename, "wrong password"),
+            Err(SelfTestError::WalletNotUnlockable {
+                filename: filename.to_string()
+            })
+        );
+        assert!(check_wallet_unlockable("check_wallet_unlockable_test_missing", "").is_ok());
+
+        fs::remove_file(format!("{}{}", WALLETS_DIR_PATH, filename)).unwrap();
+    }
+
+    #[test]
+    fn check_clock_test() {
+        let timestamp = create_timestamp();
+        assert!(check_clock(timestamp, 0).is_ok());
+        assert!(check_clock(timestamp, timestamp + MAX_FUTURE_BLOCK_TIME).is_ok());
+        assert!(matches!(
+            check_clock(timestamp, timestamp + MAX_FUTURE_BLOCK_TIME + 1),
+            Err(SelfTestError::ClockBehindChain { .. })
+        ));
+        assert_eq!(
+            check_clock(0, 0),
+            Err(SelfTestError::ClockBeforeMinimum { timestamp: 0 })
+        );
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn health_report_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mempool = Mempool::new(wallet_lock.clone());
+
+        let report = HealthReport::new(&*blockchain_lock.read().await, &mempool, 0, 0);
+        assert!(!report.is_synced);
+        assert_eq!(report.last_block_age, None);
+
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        test_manager.generate_blockchain(2, [0; 32]).await;
+        let blockchain = blockchain_lock.read().await;
+        let latest_block_timestamp = blockchain.get_latest_block().unwrap().get_timestamp();
+
+        let report = HealthReport::new(&blockchain, &mempool, 3, latest_block_timestamp + 1000);
+        assert!(report.is_synced);
+        assert_eq!(report.latest_block_id, 2);
+        assert_eq!(
+            report.latest_block_hash,
+            hex::encode(blockchain.get_latest_block_hash())
+        );
+        assert_eq!(report.peers_connected, 3);
+        assert_eq!(report.mempool_transactions, 0);
+        assert_eq!(report.last_block_age, Some(1000));
+
+        let report = HealthReport::new(
+            &blockchain,
+            &mempool,
+            3,
+            latest_block_timestamp + MAX_SYNCED_BLOCK_AGE + 1,
+        );
+        assert!(!report.is_synced);
+        assert_eq!(report.sync_state, SyncState::NotSynced);
+        drop(blockchain);
+
+        // once a peer has announced its tip, the age of the latest block no longer matters
+        blockchain_lock
+            .write()
+            .await
+            .get_mut_sync_status()
+            .on_peer_tip([1; 32], 2);
+        let report = HealthReport::new(
+            &*blockchain_lock.read().await,
+            &mempool,
+            3,
+            latest_block_timestamp + MAX_SYNCED_BLOCK_AGE + 1,
+        );
+        assert!(report.is_synced);
+        assert_eq!(report.sync_state, SyncState::Synced);
+        assert_eq!(report.network_tip_block_id, 2);
+    }
+}
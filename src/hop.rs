@@ -1,8 +1,13 @@
-use crate::crypto::{sign, SaitoHash, SaitoPublicKey, SaitoSignature};
+#[cfg(feature = "node")]
+use crate::crypto::{sign, SaitoHash};
+use crate::crypto::{SaitoPublicKey, SaitoSignature};
+#[cfg(feature = "node")]
 use crate::wallet::Wallet;
 use serde::{Deserialize, Serialize};
 use std::convert::TryInto;
+#[cfg(feature = "node")]
 use std::sync::Arc;
+#[cfg(feature = "node")]
 use tokio::sync::RwLock;
 
 //
@@ -10,7 +15,7 @@ use tokio::sync::RwLock;
 // the portions of the code that check the routing work start with
 // the sender of the transaction. This would be a good optimization
 //
-pub const HOP_SIZE: usize = 130;
+pub use crate::layout::HOP_SIZE;
 
 #[serde_with::serde_as]
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
@@ -32,6 +37,7 @@ impl Hop {
         }
     }
 
+    #[cfg(feature = "node")]
     pub async fn generate_hop(
         wallet_lock: Arc<RwLock<Wallet>>,
         to_publickey: SaitoPublicKey,
@@ -59,6 +65,15 @@ impl Hop {
         self.sig
     }
 
+    /// A readable view of the hop for the REST API, with the keys and signature hex encoded.
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "from": hex::encode(self.from),
+            "to": hex::encode(self.to),
+            "sig": hex::encode(self.sig),
+        })
+    }
+
     pub fn set_from(&mut self, from: SaitoPublicKey) {
         self.from = from
     }
@@ -0,0 +1,136 @@
+use crate::crypto::{SaitoHash, SaitoPublicKey, SaitoSignature, SaitoUTXOSetKey};
+use std::mem::size_of;
+
+//
+// The sizes of the fixed parts of blocks, transactions, slips and hops as
+// serialized for the network, added up from the types of their fields in
+// the order serialize_for_net() writes them. The deserializers use these
+// sizes for their offsets, so the sizes and the serializers must change
+// together. The assertions at the bottom stop the build if a size moves,
+// and the tests check the sizes against what the serializers write.
+//
+const U8_SIZE: usize = size_of::<u8>();
+const U32_SIZE: usize = size_of::<u32>();
+const U64_SIZE: usize = size_of::<u64>();
+const HASH_SIZE: usize = size_of::<SaitoHash>();
+const PUBLICKEY_SIZE: usize = size_of::<SaitoPublicKey>();
+const SIGNATURE_SIZE: usize = size_of::<SaitoSignature>();
+
+/// [publickey][uuid][amount - u64][slip ordinal - u8][slip type - u8]
+pub const SLIP_SIZE: usize = PUBLICKEY_SIZE + HASH_SIZE + U64_SIZE + U8_SIZE + U8_SIZE;
+
+/// The slip with the type it is kept under in the utxoset instead of its own, which is
+/// what identifies it in the utxoset, see SlipType::get_utxoset_type.
+pub const UTXOSET_KEY_SIZE: usize = SLIP_SIZE;
+
+/// [from - publickey][to - publickey][sig]
+pub const HOP_SIZE: usize = PUBLICKEY_SIZE + PUBLICKEY_SIZE + SIGNATURE_SIZE;
+
+/// The fixed part of a transaction, before its slips, message and hops:
+/// [len of inputs - u32][len of outputs - u32][len of message - u32][len of path - u32]
+/// [signature][timestamp - u64][transaction type - u8]
+pub const TRANSACTION_SIZE: usize = 4 * U32_SIZE + SIGNATURE_SIZE + U64_SIZE + U8_SIZE;
+
+/// Every transaction in a block is framed by its length, a u32, so that a reader whose idea
+/// of the transaction layout differs from the writer's fails on the first transaction
+/// instead of reading the rest of the block at the wrong offsets.
+pub const TRANSACTION_LENGTH_PREFIX_SIZE: usize = U32_SIZE;
+
+/// The block before its transactions:
+/// [len of transactions - u32][id - u64][timestamp - u64][previous block hash][creator]
+/// [merkle root][signature][treasury - u64][staking treasury - u64][burnfee - u64]
+/// [difficulty - u64][hash algorithm - u8]
+pub const BLOCK_HEADER_SIZE: usize = U32_SIZE
+    + U64_SIZE
+    + U64_SIZE
+    + HASH_SIZE
+    + PUBLICKEY_SIZE
+    + HASH_SIZE
+    + SIGNATURE_SIZE
+    + 4 * U64_SIZE
+    + U8_SIZE;
+
+//
+// other implementations read these layouts too, so changing a size is a
+// change of the protocol and must come with a new PROTOCOL_VERSION
+//
+const _: () = assert!(SLIP_SIZE == 75);
+const _: () = assert!(UTXOSET_KEY_SIZE == size_of::<SaitoUTXOSetKey>());
+const _: () = assert!(HOP_SIZE == 130);
+const _: () = assert!(TRANSACTION_SIZE == 89);
+const _: () = assert!(TRANSACTION_LENGTH_PREFIX_SIZE == 4);
+const _: () = assert!(BLOCK_HEADER_SIZE == 214);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hop::Hop;
+    use crate::slip::Slip;
+
+    #[test]
+    fn slip_and_hop_layout_test() {
+        assert_eq!(Slip::new().serialize_for_net().len(), SLIP_SIZE);
+        assert_eq!(Slip::new().get_utxoset_key().len(), UTXOSET_KEY_SIZE);
+        assert_eq!(Hop::new().serialize_for_net().len(), HOP_SIZE);
+    }
+
+    #[cfg(feature = "node")]
+    #[test]
+    fn transaction_and_block_layout_test() {
+        use crate::block::{Block, BlockType};
+        use crate::transaction::Transaction;
+
+        let mut empty_transaction = Transaction::new();
+        empty_transaction.set_message(vec![]);
+        assert_eq!(
+            empty_transaction.serialize_for_net().len(),
+            TRANSACTION_SIZE
+        );
+
+        let mut transaction = Transaction::new();
+        transaction.add_input(Slip::new());
+        transaction.add_output(Slip::new());
+        transaction.add_output(Slip::new());
+        transaction.set_message(vec![1; 10]);
+        transaction.set_path(vec![Hop::new()]);
+        assert_eq!(
+            transaction.serialize_for_net().len(),
+            TRANSACTION_SIZE + 3 * SLIP_SIZE + 10 + HOP_SIZE
+        );
+
+        let mut block = Block::new();
+        assert_eq!(
+            block.serialize_for_net(BlockType::Full).len(),
+            BLOCK_HEADER_SIZE
+        );
+        block.add_transaction(transaction.clone());
+        block.add_transaction(empty_transaction);
+        assert_eq!(
+            block.serialize_for_net(BlockType::Full).len(),
+            BLOCK_HEADER_SIZE
+                + 2 * TRANSACTION_LENGTH_PREFIX_SIZE
+                + transaction.get_serialized_size()
+                + TRANSACTION_SIZE
+        );
+        assert_eq!(
+            block.serialize_for_net(BlockType::Header).len(),
+            BLOCK_HEADER_SIZE
+        );
+    }
+
+    #[cfg(feature = "node")]
+    #[test]
+    #[should_panic(expected = "is framed as")]
+    fn misframed_transaction_test() {
+        use crate::block::{Block, BlockType};
+        use crate::transaction::Transaction;
+
+        let mut block = Block::new();
+        block.add_transaction(Transaction::new());
+        let mut serialized_block = block.serialize_for_net(BlockType::Full);
+        // a transaction framed one byte longer than it is laid out
+        serialized_block[BLOCK_HEADER_SIZE + TRANSACTION_LENGTH_PREFIX_SIZE - 1] += 1;
+        serialized_block.push(0);
+        Block::deserialize_for_net(&serialized_block);
+    }
+}
@@ -20,28 +20,93 @@ TODO
 The Saito Team
 dev@saito.tech
 
+# Features
+
+The `node` feature, on by default, builds the full node. Without it the `core` feature builds
+only the consensus primitives which do not need tokio or warp, e.g. for wasm32-unknown-unknown:
+amounts, the burn fee, hashing and signatures, golden tickets, hops, the sizes of the wire
+layout, merkle trees, hash chains and slips.
+
 */
+#[cfg(feature = "node")]
+pub mod address_book;
+#[cfg(feature = "node")]
+pub mod admission_policy;
+pub mod amount;
+#[cfg(feature = "node")]
+pub mod auto_compound;
+#[cfg(feature = "node")]
 pub mod block;
+#[cfg(feature = "node")]
+pub mod block_store;
+#[cfg(feature = "node")]
 pub mod blockchain;
+#[cfg(feature = "node")]
 pub mod blockring;
+#[cfg(feature = "node")]
+pub mod bootstrap;
 pub mod burnfee;
+#[cfg(feature = "node")]
 pub mod consensus;
+#[cfg(feature = "node")]
+pub mod consensus_params;
 pub mod crypto;
+#[cfg(feature = "node")]
+pub mod disk_space;
+#[cfg(feature = "node")]
+pub mod genesis;
 pub mod golden_ticket;
+#[cfg(feature = "node")]
+pub mod health;
 pub mod hop;
+pub mod layout;
+#[cfg(feature = "node")]
+pub mod logging;
+#[cfg(feature = "node")]
+pub mod memo_index;
+#[cfg(feature = "node")]
 pub mod mempool;
 pub mod merkle;
+#[cfg(feature = "node")]
+pub mod message_log;
+#[cfg(feature = "node")]
 pub mod miner;
+#[cfg(feature = "node")]
 pub mod network;
+#[cfg(feature = "node")]
 pub mod networking;
+#[cfg(feature = "node")]
+pub mod paranoid;
+#[cfg(feature = "node")]
 pub mod peer;
+pub mod random;
+#[cfg(feature = "node")]
+pub mod reindex;
+#[cfg(feature = "node")]
+pub mod reorg_log;
 pub mod slip;
+#[cfg(feature = "node")]
 pub mod staking;
+#[cfg(feature = "node")]
 pub mod storage;
+#[cfg(feature = "node")]
+pub mod supervisor;
+#[cfg(feature = "node")]
+pub mod supply_audit;
+#[cfg(feature = "node")]
+pub mod sync;
+#[cfg(feature = "node")]
 pub mod time;
+#[cfg(feature = "node")]
 pub mod transaction;
-pub mod util;
+#[cfg(feature = "node")]
+pub mod vip_policy;
+#[cfg(feature = "node")]
 pub mod wallet;
+#[cfg(feature = "node")]
+pub mod wallet_manager;
+#[cfg(feature = "node")]
+pub mod webhooks;
 
 extern crate lazy_static;
 
@@ -50,8 +115,11 @@ extern crate tracing;
 // TODO put test_utilities behind a feature flag so it's not built into non-test builds
 //   i.e. uncomment this line:
 // [cfg(feature = "test-utilities")]
-mod configuration;
+#[cfg(feature = "node")]
+pub mod configuration;
+#[cfg(feature = "node")]
 pub mod test_setup;
+#[cfg(feature = "node")]
 pub mod test_utilities;
 
 /// Error returned by most functions.
@@ -0,0 +1,309 @@
+use crate::configuration::LoggingSettings;
+use crate::time::create_timestamp;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::thread::{self, JoinHandle};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{fmt, reload, Registry};
+
+//
+// The level of our logs can be changed while the node runs, e.g. to look
+// into a problem on a node which cannot be restarted without dropping its
+// peers. Records of the log crate are passed on to tracing, and filtered by
+// the max level of the log crate first, so both levels are changed.
+//
+
+/// Log files larger than this many bytes are rotated.
+pub const LOG_FILE_MAX_SIZE: u64 = 100 * 1024 * 1024;
+/// Log files older than this many seconds are rotated.
+pub const LOG_FILE_ROTATION_INTERVAL: u64 = 24 * 60 * 60;
+/// How many rotated log files are kept.
+pub const LOG_FILES_KEPT: usize = 10;
+
+lazy_static::lazy_static! {
+    static ref LOG_LEVEL_HANDLE: Mutex<Option<reload::Handle<LevelFilter, Registry>>> = Mutex::new(None);
+}
+
+/// Installs the subscriber which prints our logs at the level, until set_log_level changes it,
+/// to stdout and to the log file of the settings.
+pub fn init_logging(level: LevelFilter, logging_settings: &LoggingSettings) -> crate::Result<()> {
+    let (level_filter, log_level_handle) = reload::Layer::new(level);
+    let stdout_layer = if logging_settings.stdout {
+        Some(fmt::layer())
+    } else {
+        None
+    };
+    let file_layer = match &logging_settings.file {
+        Some(filename) => {
+            let log_file = RotatingLogFile::open(filename, logging_settings)?;
+            Some(
+                fmt::layer()
+                    .with_ansi(false)
+                    .with_writer(Mutex::new(log_file)),
+            )
+        }
+        None => None,
+    };
+    tracing_subscriber::registry()
+        .with(level_filter)
+        .with(stdout_layer)
+        .with(file_layer)
+        .init();
+    log::set_max_level(as_log_level_filter(level));
+    *LOG_LEVEL_HANDLE.lock().unwrap() = Some(log_level_handle);
+    Ok(())
+}
+
+/// Parses a level such as info or debug.
+pub fn parse_log_level(level: &str) -> crate::Result<LevelFilter> {
+    LevelFilter::from_str(level).map_err(|_| format!("invalid log level {}", level).into())
+}
+
+/// Changes the level of our logs, if they are printed by the subscriber of init_logging.
+pub fn set_log_level(level: LevelFilter) -> crate::Result<()> {
+    if let Some(log_level_handle) = LOG_LEVEL_HANDLE.lock().unwrap().as_ref() {
+        log_level_handle.reload(level)?;
+        log::set_max_level(as_log_level_filter(level));
+    }
+    Ok(())
+}
+
+fn as_log_level_filter(level: LevelFilter) -> log::LevelFilter {
+    match level.into_level() {
+        Some(tracing::Level::ERROR) => log::LevelFilter::Error,
+        Some(tracing::Level::WARN) => log::LevelFilter::Warn,
+        Some(tracing::Level::INFO) => log::LevelFilter::Info,
+        Some(tracing::Level::DEBUG) => log::LevelFilter::Debug,
+        Some(tracing::Level::TRACE) => log::LevelFilter::Trace,
+        None => log::LevelFilter::Off,
+    }
+}
+
+//
+// A routing node logs every block it sees, which is one every few seconds,
+// so its logs are rotated before they fill the disk. The rotated file is
+// renamed to carry the time of its rotation, e.g. saito.log.1650000000000,
+// and gzipped on a thread of its own, so that logging does not wait for
+// the compression. Errors are printed to stderr, as logging them could
+// rotate the file again.
+//
+
+/// A log file which is rotated once it grows larger than max_file_size bytes or older than
+/// rotation_interval seconds, keeping the newest max_files rotated files.
+#[derive(Debug)]
+pub struct RotatingLogFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    opened_at: u64,
+    max_file_size: u64,
+    rotation_interval: u64,
+    max_files: usize,
+    compress: bool,
+    rotation: Option<JoinHandle<()>>,
+}
+
+impl RotatingLogFile {
+    /// Opens the file to append to, creating it and its directory if needed.
+    pub fn open(filename: &str, logging_settings: &LoggingSettings) -> io::Result<Self> {
+        let path = PathBuf::from(filename);
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(RotatingLogFile {
+            path,
+            file,
+            size,
+            opened_at: create_timestamp(),
+            max_file_size: logging_settings.max_file_size,
+            rotation_interval: logging_settings.rotation_interval * 1000,
+            max_files: logging_settings.max_files,
+            compress: logging_settings.compress,
+            rotation: None,
+        })
+    }
+
+    fn should_rotate(&self, len: usize) -> bool {
+        if self.size == 0 {
+            return false;
+        }
+        let too_large = self.max_file_size > 0 && self.size + len as u64 > self.max_file_size;
+        let too_old = self.rotation_interval > 0
+            && create_timestamp().saturating_sub(self.opened_at) >= self.rotation_interval;
+        too_large || too_old
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        let mut timestamp = create_timestamp();
+        let mut rotated_path = self.rotated_path(timestamp, "");
+        while rotated_path.exists() || self.rotated_path(timestamp, ".gz").exists() {
+            timestamp += 1;
+            rotated_path = self.rotated_path(timestamp, "");
+        }
+        fs::rename(&self.path, &rotated_path)?;
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        self.opened_at = create_timestamp();
+
+        // one rotation at a time, so that the files pruned are compressed already
+        self.join_rotation();
+        let path = self.path.clone();
+        let max_files = self.max_files;
+        let compress = self.compress;
+        self.rotation = Some(thread::spawn(move || {
+            if compress {
+                if let Err(err) = compress_log_file(&rotated_path) {
+                    eprintln!(
+                        "ERROR 380101: cannot compress log file {}: {}",
+                        rotated_path.display(),
+                        err
+                    );
+                }
+            }
+            if let Err(err) = prune_log_files(&path, max_files) {
+                eprintln!(
+                    "ERROR 380102: cannot delete old log files of {}: {}",
+                    path.display(),
+                    err
+                );
+            }
+        }));
+        Ok(())
+    }
+
+    fn rotated_path(&self, timestamp: u64, extension: &str) -> PathBuf {
+        let mut rotated_path = self.path.clone().into_os_string();
+        rotated_path.push(format!(".{}{}", timestamp, extension));
+        PathBuf::from(rotated_path)
+    }
+
+    fn join_rotation(&mut self) {
+        if let Some(rotation) = self.rotation.take() {
+            let _ = rotation.join();
+        }
+    }
+}
+
+impl Write for RotatingLogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.should_rotate(buf.len()) {
+            if let Err(err) = self.rotate() {
+                eprintln!(
+                    "ERROR 380103: cannot rotate log file {}: {}",
+                    self.path.display(),
+                    err
+                );
+                // keep appending to the file we have rather than losing logs
+                self.opened_at = create_timestamp();
+            }
+        }
+        // a line of the log is written whole, so that it is not split across files
+        self.file.write_all(buf)?;
+        self.size += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn compress_log_file(path: &Path) -> io::Result<()> {
+    let mut compressed_path = path.to_path_buf().into_os_string();
+    compressed_path.push(".gz");
+    let mut encoder = GzEncoder::new(File::create(&compressed_path)?, Compression::default());
+    io::copy(&mut File::open(path)?, &mut encoder)?;
+    encoder.finish()?;
+    fs::remove_file(path)
+}
+
+/// Deletes all but the newest max_files rotated files of the log file at path.
+fn prune_log_files(path: &Path, max_files: usize) -> io::Result<()> {
+    let directory = match path.parent() {
+        Some(directory) if !directory.as_os_str().is_empty() => directory,
+        _ => Path::new("."),
+    };
+    let prefix = format!(
+        "{}.",
+        path.file_name().unwrap_or_default().to_string_lossy()
+    );
+    let mut rotated_paths = vec![];
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        let filename = entry.file_name().to_string_lossy().to_string();
+        if filename.starts_with(&prefix) {
+            rotated_paths.push(entry.path());
+        }
+    }
+    // the timestamps in the names have as many digits, so the oldest sort first
+    rotated_paths.sort();
+    let excess = rotated_paths.len().saturating_sub(max_files);
+    for rotated_path in rotated_paths.into_iter().take(excess) {
+        fs::remove_file(rotated_path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    const LOG_TEST_DIR_PATH: &str = "./data/test/logs/";
+
+    #[test]
+    fn rotating_log_file_test() {
+        let _ = fs::remove_dir_all(LOG_TEST_DIR_PATH);
+        let filename = format!("{}saito.log", LOG_TEST_DIR_PATH);
+        let logging_settings = LoggingSettings {
+            stdout: false,
+            file: Some(filename.clone()),
+            max_file_size: 100,
+            rotation_interval: 0,
+            max_files: 2,
+            compress: true,
+        };
+        let mut log_file = RotatingLogFile::open(&filename, &logging_settings).unwrap();
+        let line = [b'a'; 39];
+        // two lines fit into a file, so the third, fifth, seventh and ninth rotate it
+        for _ in 0..9 {
+            log_file.write_all(&line).unwrap();
+        }
+        log_file.join_rotation();
+        assert_eq!(fs::metadata(&filename).unwrap().len(), 39);
+
+        let mut rotated_filenames: Vec<String> = fs::read_dir(LOG_TEST_DIR_PATH)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .filter(|filename| filename != "saito.log")
+            .collect();
+        rotated_filenames.sort();
+        // the oldest rotated files were deleted and the others compressed
+        assert_eq!(rotated_filenames.len(), 2);
+        for rotated_filename in rotated_filenames {
+            assert!(rotated_filename.ends_with(".gz"));
+            let mut contents = vec![];
+            GzDecoder::new(
+                File::open(format!("{}{}", LOG_TEST_DIR_PATH, rotated_filename)).unwrap(),
+            )
+            .read_to_end(&mut contents)
+            .unwrap();
+            assert_eq!(contents.len(), 78);
+        }
+
+        fs::remove_dir_all(LOG_TEST_DIR_PATH).unwrap();
+    }
+}
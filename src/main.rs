@@ -22,8 +22,12 @@ cargo run -- --help
 cargo run -- --password=asdf --wallet=test/testwallet
 ```
 */
+use saito_rust::configuration::get_configuration;
 use saito_rust::consensus;
+use saito_rust::logging::{init_logging, parse_log_level};
+use saito_rust::supervisor::install_panic_hook;
 use std::env;
+use tracing::info;
 
 #[tokio::main]
 pub async fn main() -> std::result::Result<(), Box<dyn std::error::Error + Send + Sync>> {
@@ -31,10 +35,14 @@ pub async fn main() -> std::result::Result<(), Box<dyn std::error::Error + Send
     if env::var("RUST_LOG").is_err() {
         env::set_var("RUST_LOG", "info")
     }
-    tracing_subscriber::fmt::init();
-
     let level = env::var("RUST_LOG").unwrap(); //safe to unwrap, set above
-    println!(
+                                               // a configuration which cannot be read is reported by the consensus runtime
+    let logging_settings = get_configuration()
+        .map(|settings| settings.logging)
+        .unwrap_or_default();
+    init_logging(parse_log_level(&level)?, &logging_settings)?;
+    install_panic_hook();
+    info!(
         "LOG LEVEL SET TO: {}. To set log level use RUST_LOG=[trace, info, debug, warn, error]",
         level
     );
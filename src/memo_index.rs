@@ -0,0 +1,174 @@
+use crate::block::Block;
+use crate::configuration::MemoIndexSettings;
+use crate::crypto::SaitoHash;
+use crate::transaction::TransactionType;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+//
+// Dapps keep their records on chain in the messages of their transactions,
+// but finding them again means reading every block. The memo index maps the
+// tag of a message, its first tag_length bytes, to the transactions of the
+// longest chain carrying it, so a dapp which starts its messages with a tag
+// of its own, or with the hash of a record, can look its transactions up.
+// Only normal transactions are indexed, as the messages of the others are
+// written by the protocol. The index grows with the chain and is off unless
+// enabled in the configuration.
+//
+
+/// How many bytes of a message are indexed by default.
+pub const MEMO_TAG_LENGTH: usize = 8;
+/// The most transactions a search returns.
+pub const MAX_MEMO_SEARCH_RESULTS: usize = 1000;
+
+/// A transaction of the longest chain whose message starts with the tag searched for.
+/// Hashes are hex encoded.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct MemoSearchResult {
+    pub block_hash: String,
+    pub block_id: u64,
+    pub transaction_hash: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct MemoIndexEntry {
+    block_hash: SaitoHash,
+    block_id: u64,
+    transaction_hash: SaitoHash,
+}
+
+/// The transactions of the longest chain by the tag their message starts with.
+#[derive(Debug)]
+pub struct MemoIndex {
+    tag_length: usize,
+    entries: BTreeMap<Vec<u8>, Vec<MemoIndexEntry>>,
+}
+
+impl MemoIndex {
+    pub fn new(tag_length: usize) -> Self {
+        MemoIndex {
+            tag_length: tag_length.max(1),
+            entries: BTreeMap::new(),
+        }
+    }
+
+    /// The index asked for by the settings, or None if it is not enabled.
+    pub fn from_settings(memo_index_settings: &MemoIndexSettings) -> Option<Self> {
+        memo_index_settings
+            .enabled
+            .then(|| MemoIndex::new(memo_index_settings.tag_length))
+    }
+
+    pub fn get_tag_length(&self) -> usize {
+        self.tag_length
+    }
+
+    /// Indexes the transactions of a block added to the longest chain, or drops those of a
+    /// block removed from it.
+    pub fn on_chain_reorganization(&mut self, block: &Block, longest_chain: bool) {
+        for transaction in block.get_transactions() {
+            if transaction.get_transaction_type() != TransactionType::Normal
+                || transaction.get_message().is_empty()
+            {
+                continue;
+            }
+            let message = transaction.get_message();
+            let tag = message[..message.len().min(self.tag_length)].to_vec();
+            if longest_chain {
+                self.entries.entry(tag).or_default().push(MemoIndexEntry {
+                    block_hash: block.get_hash(),
+                    block_id: block.get_id(),
+                    transaction_hash: transaction.get_hash_for_signature().unwrap_or([0; 32]),
+                });
+            } else if let Some(entries) = self.entries.get_mut(&tag) {
+                entries.retain(|entry| entry.block_hash != block.get_hash());
+                if entries.is_empty() {
+                    self.entries.remove(&tag);
+                }
+            }
+        }
+    }
+
+    /// The transactions whose message starts with the tag, oldest first and at most
+    /// MAX_MEMO_SEARCH_RESULTS of them. Tags longer than the indexed tag length cannot be
+    /// searched for.
+    pub fn search(&self, tag: &[u8]) -> Result<Vec<MemoSearchResult>, String> {
+        if tag.is_empty() || tag.len() > self.tag_length {
+            return Err(format!(
+                "tags are between 1 and {} bytes long",
+                self.tag_length
+            ));
+        }
+        let mut entries: Vec<&MemoIndexEntry> = self
+            .entries
+            .range(tag.to_vec()..)
+            .take_while(|(indexed_tag, _)| indexed_tag.starts_with(tag))
+            .flat_map(|(_, entries)| entries.iter())
+            .collect();
+        entries.sort_by_key(|entry| entry.block_id);
+        Ok(entries
+            .into_iter()
+            .take(MAX_MEMO_SEARCH_RESULTS)
+            .map(|entry| MemoSearchResult {
+                block_hash: hex::encode(entry.block_hash),
+                block_id: entry.block_id,
+                transaction_hash: hex::encode(entry.transaction_hash),
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::Transaction;
+
+    fn create_block(block_id: u64, messages: &[&[u8]]) -> Block {
+        let mut transactions: Vec<Transaction> = messages
+            .iter()
+            .map(|message| {
+                let mut transaction = Transaction::new();
+                transaction.set_message(message.to_vec());
+                transaction.set_hash_for_signature(transaction.generate_hash_for_signature());
+                transaction
+            })
+            .collect();
+        let mut block = Block::new();
+        block.set_id(block_id);
+        block.set_transactions(&mut transactions);
+        block.generate_hashes();
+        block
+    }
+
+    #[test]
+    fn memo_index_search_test() {
+        let mut memo_index = MemoIndex::new(4);
+        let first_block = create_block(1, &[b"app1-record-a", b"app2-record-b", b""]);
+        let second_block = create_block(2, &[b"app1-record-c", b"ap"]);
+        memo_index.on_chain_reorganization(&first_block, true);
+        memo_index.on_chain_reorganization(&second_block, true);
+
+        let results = memo_index.search(b"app1").unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].block_id, 1);
+        assert_eq!(results[1].block_id, 2);
+        assert_eq!(
+            results[0].transaction_hash,
+            hex::encode(
+                first_block.get_transactions()[0]
+                    .get_hash_for_signature()
+                    .unwrap()
+            )
+        );
+
+        // a shorter tag matches every tag it starts
+        assert_eq!(memo_index.search(b"ap").unwrap().len(), 4);
+        assert_eq!(memo_index.search(b"app3").unwrap().len(), 0);
+        assert!(memo_index.search(b"app1-").is_err());
+        assert!(memo_index.search(b"").is_err());
+
+        memo_index.on_chain_reorganization(&second_block, false);
+        assert_eq!(memo_index.search(b"app1").unwrap().len(), 1);
+        assert_eq!(memo_index.search(b"ap").unwrap().len(), 2);
+    }
+}
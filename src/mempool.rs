@@ -1,17 +1,36 @@
 use crate::{
-    block::Block,
-    blockchain::Blockchain,
+    admission_policy::{admission_policies_from_settings, AdmissionPolicy},
+    amount::Amount,
+    block::{Block, BlockValidationError, BLOCK_HEADER_SIZE},
+    blockchain::{Blockchain, BlockchainMessage, ATR_FEE, GENESIS_PERIOD},
     burnfee::BurnFee,
-    consensus::SaitoMessage,
-    crypto::{SaitoHash, SaitoPrivateKey, SaitoPublicKey},
+    configuration::{MempoolSettings, ThrottleAction},
+    consensus::{recv_message, send_message, SaitoMessage, Subsystem},
+    consensus_params::ConsensusParams,
+    crypto::{SaitoHash, SaitoPrivateKey, SaitoPublicKey, SaitoSignature, SaitoUTXOSetKey},
     golden_ticket::GoldenTicket,
-    time::create_timestamp,
+    layout::TRANSACTION_LENGTH_PREFIX_SIZE,
+    staking::Staking,
+    time::{create_timestamp, next_block_timestamp},
     transaction::Transaction,
     wallet::Wallet,
+    wallet_manager::{WalletManager, WalletRole},
 };
-use log::info;
-use std::{collections::HashMap, collections::VecDeque, sync::Arc, thread::sleep, time::Duration};
-use tokio::sync::{broadcast, mpsc, RwLock};
+use ahash::AHashMap;
+use base58::ToBase58;
+use log::{error, info};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::{
+    collections::HashMap, collections::HashSet, collections::VecDeque, sync::Arc, time::Duration,
+};
+use tokio::sync::{broadcast, mpsc, oneshot, RwLock};
+use tokio::time::{interval, sleep_until, Instant, MissedTickBehavior};
+
+/// the number of blocks a transaction may wait in the mempool before it is dropped
+pub const MEMPOOL_TRANSACTION_TTL: u64 = 3 * GENESIS_PERIOD;
+/// how often in milliseconds the mempool checks whether its routing work has changed
+pub const BUNDLE_BLOCK_INTERVAL: u64 = 1_000;
 
 //
 // In addition to responding to global broadcast messages, the
@@ -22,9 +41,68 @@ use tokio::sync::{broadcast, mpsc, RwLock};
 #[derive(Clone, Debug)]
 pub enum MempoolMessage {
     LocalTryBundleBlock,
+    LocalPreassembleBlock,
     LocalNewBlock,
 }
 
+//
+// Bundling a block, i.e. the ATR transactions, the fee transaction and the
+// merkle root, takes time after the routing work suffices. With a lead time
+// the next block is bundled shortly before, and only restamped, i.e. given
+// its timestamp, burnfee and signature, when it is produced. The block is
+// used only if neither the transactions in the mempool nor the latest block
+// have changed since it was bundled.
+//
+#[derive(Debug)]
+struct PreassembledBlock {
+    block: Block,
+    transaction_signatures: Vec<SaitoSignature>,
+}
+
+/// A summary of the transactions waiting in the mempool, and of the routing work they give
+/// us against the work needed to produce the next block.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct MempoolInfo {
+    pub transaction_count: usize,
+    pub bytes: usize,
+    pub total_fees: u64,
+    pub routing_work_available: u64,
+    pub routing_work_needed: u64,
+}
+
+/// A transaction waiting in the mempool. The hash is the hex encoded hash for signature.
+///
+/// The fees of a transaction are its total routing work, all of which goes to the first node
+/// on its routing path and half of which is lost with every hop after it. The routing work
+/// for us is what is left of it for us, and counts towards the work needed to produce the
+/// next block.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct MempoolTransactionInfo {
+    pub hash: String,
+    pub total_fees: u64,
+    pub size: usize,
+    pub fee_rate: u64,
+    pub routing_hops: usize,
+    pub routing_work_for_us: u64,
+}
+
+/// What became of a transaction of a batch: the base58 encoded signature it was added to the
+/// mempool with, or why it was not.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct TransactionBatchResult {
+    pub signature: Option<String>,
+    pub error: Option<String>,
+}
+
+impl TransactionBatchResult {
+    fn rejected(error: &str) -> Self {
+        TransactionBatchResult {
+            signature: None,
+            error: Some(error.to_string()),
+        }
+    }
+}
+
 /// The `Mempool` holds unprocessed blocks and transactions and is in control of
 /// discerning when the node is allowed to create a block. It bundles the block and
 /// sends it to the `Blockchain` to be added to the longest-chain. New `Block`s
@@ -35,11 +113,28 @@ pub struct Mempool {
     blocks_queue: VecDeque<Block>,
     pub transactions: Vec<Transaction>, // vector so we just copy it over
     routing_work_in_mempool: u64,
-    wallet_lock: Arc<RwLock<Wallet>>,
+    wallet_manager: WalletManager,
     currently_bundling_block: bool,
+    currently_producing_block: bool,
     broadcast_channel_sender: Option<broadcast::Sender<SaitoMessage>>,
+    blockchain_channel_sender: Option<mpsc::Sender<BlockchainMessage>>,
     mempool_publickey: SaitoPublicKey,
     mempool_privatekey: SaitoPrivateKey,
+    transaction_ttl: u64,
+    dust_consolidation_threshold: u64,
+    bundling_enabled: bool,
+    block_lead_time: u64,
+    admission_policies: Vec<Box<dyn AdmissionPolicy>>,
+    address_byte_budget: u64,
+    throttle_action: ThrottleAction,
+    // the bytes of the transactions in the mempool by their sender
+    address_bytes: AHashMap<SaitoPublicKey, u64>,
+    preassembled_block: Option<PreassembledBlock>,
+    latest_block_id: u64,
+    transaction_expiry_block_ids: AHashMap<SaitoSignature, u64>,
+    latest_block_hash: SaitoHash,
+    latest_block_difficulty: u64,
+    golden_ticket_signatures_by_target: AHashMap<SaitoHash, SaitoSignature>,
 }
 
 impl Mempool {
@@ -49,14 +144,125 @@ impl Mempool {
             blocks_queue: VecDeque::new(),
             transactions: vec![],
             routing_work_in_mempool: 0,
-            wallet_lock,
+            wallet_manager: WalletManager::new(wallet_lock),
             currently_bundling_block: false,
+            currently_producing_block: false,
             broadcast_channel_sender: None,
+            blockchain_channel_sender: None,
             mempool_publickey: [0; 33],
             mempool_privatekey: [0; 32],
+            transaction_ttl: MEMPOOL_TRANSACTION_TTL,
+            dust_consolidation_threshold: ATR_FEE,
+            bundling_enabled: true,
+            block_lead_time: 0,
+            admission_policies: vec![],
+            address_byte_budget: 0,
+            throttle_action: ThrottleAction::Deprioritize,
+            address_bytes: AHashMap::new(),
+            preassembled_block: None,
+            latest_block_id: 0,
+            transaction_expiry_block_ids: AHashMap::new(),
+            latest_block_hash: [0; 32],
+            latest_block_difficulty: 0,
+            golden_ticket_signatures_by_target: AHashMap::new(),
         }
     }
 
+    /// Applies the settings of the mempool, at startup or when the configuration is reloaded.
+    /// The admission policies are made anew, so rate limits start counting again.
+    pub fn apply_settings(&mut self, mempool_settings: &MempoolSettings) {
+        self.set_transaction_ttl(mempool_settings.transaction_ttl);
+        self.set_dust_consolidation_threshold(mempool_settings.dust_consolidation_threshold);
+        self.set_block_lead_time(mempool_settings.block_lead_time);
+        self.set_admission_policies(admission_policies_from_settings(
+            &mempool_settings.admission,
+        ));
+        self.set_address_byte_budget(mempool_settings.address_byte_budget);
+        self.set_throttle_action(mempool_settings.throttle_action);
+    }
+
+    /// Sets the number of blocks a transaction added from now on may wait in the mempool.
+    pub fn set_transaction_ttl(&mut self, transaction_ttl: u64) {
+        self.transaction_ttl = transaction_ttl;
+    }
+
+    pub fn get_transaction_ttl(&self) -> u64 {
+        self.transaction_ttl
+    }
+
+    /// Sets how many nolan of dust a wallet may hold before it is swept, 0 turns this off.
+    pub fn set_dust_consolidation_threshold(&mut self, dust_consolidation_threshold: u64) {
+        self.dust_consolidation_threshold = dust_consolidation_threshold;
+    }
+
+    pub fn get_dust_consolidation_threshold(&self) -> u64 {
+        self.dust_consolidation_threshold
+    }
+
+    /// Sets whether we bundle blocks, which nodes in transaction relay mode do not.
+    pub fn set_bundling_enabled(&mut self, bundling_enabled: bool) {
+        self.bundling_enabled = bundling_enabled;
+    }
+
+    pub fn get_bundling_enabled(&self) -> bool {
+        self.bundling_enabled
+    }
+
+    /// Sets how many milliseconds before the routing work suffices the next block is bundled,
+    /// 0 turns this off.
+    pub fn set_block_lead_time(&mut self, block_lead_time: u64) {
+        self.block_lead_time = block_lead_time;
+    }
+
+    pub fn get_block_lead_time(&self) -> u64 {
+        self.block_lead_time
+    }
+
+    /// Sets the policies consulted, in order, before a transaction is added.
+    pub fn set_admission_policies(&mut self, admission_policies: Vec<Box<dyn AdmissionPolicy>>) {
+        self.admission_policies = admission_policies;
+    }
+
+    pub fn add_admission_policy(&mut self, admission_policy: Box<dyn AdmissionPolicy>) {
+        self.admission_policies.push(admission_policy);
+    }
+
+    /// Sets how many bytes of transactions from one address the mempool holds before the
+    /// address is throttled, 0 turns throttling off.
+    pub fn set_address_byte_budget(&mut self, address_byte_budget: u64) {
+        self.address_byte_budget = address_byte_budget;
+    }
+
+    pub fn get_address_byte_budget(&self) -> u64 {
+        self.address_byte_budget
+    }
+
+    pub fn set_throttle_action(&mut self, throttle_action: ThrottleAction) {
+        self.throttle_action = throttle_action;
+    }
+
+    pub fn get_throttle_action(&self) -> ThrottleAction {
+        self.throttle_action
+    }
+
+    /// The bytes of the transactions from the address waiting in the mempool.
+    pub fn get_address_bytes(&self, publickey: &SaitoPublicKey) -> u64 {
+        self.address_bytes.get(publickey).copied().unwrap_or(0)
+    }
+
+    fn get_transaction_signatures(&self) -> Vec<SaitoSignature> {
+        self.transactions
+            .iter()
+            .map(|transaction| transaction.get_signature())
+            .collect()
+    }
+
+    /// Replaces the single wallet given to new(). Blocks are signed by the BlockProducer
+    /// wallet and golden tickets are submitted by the Mining wallet.
+    pub fn set_wallet_manager(&mut self, wallet_manager: WalletManager) {
+        self.wallet_manager = wallet_manager;
+    }
+
     pub fn add_block(&mut self, block: Block) {
         let hash_to_insert = block.get_hash();
         if self
@@ -69,19 +275,89 @@ impl Mempool {
             self.blocks_queue.push_back(block);
         }
     }
-    pub async fn add_golden_ticket(&mut self, golden_ticket: GoldenTicket) {
-        let mut wallet = self.wallet_lock.write().await;
-        let transaction = wallet.create_golden_ticket_transaction(golden_ticket).await;
 
+    //
+    // The miner can find several solutions for the same block, and keeps
+    // sending tickets for a block which is no longer the tip until it hears
+    // of the new one. Only the first valid ticket for the latest block is
+    // wrapped into a transaction, and tickets for older blocks, which no
+    // block on the tip could include, are dropped as the tip moves on.
+    //
+
+    /// Wraps the golden ticket into a transaction of the Mining wallet and adds it, unless the
+    /// ticket does not solve the latest block or a ticket for it was added already. Returns
+    /// whether the ticket was added.
+    pub async fn add_golden_ticket(&mut self, golden_ticket: GoldenTicket) -> bool {
+        let target = golden_ticket.get_target();
+        if target != self.latest_block_hash {
+            info!(
+                "dropping golden ticket for {}, which is not the latest block",
+                hex::encode(target)
+            );
+            return false;
+        }
         if self
-            .transactions
-            .iter()
-            .any(|transaction| transaction.is_golden_ticket())
+            .golden_ticket_signatures_by_target
+            .contains_key(&target)
+            || self.transactions.iter().any(|transaction| {
+                transaction.is_golden_ticket()
+                    && transaction.get_message().get(0..32) == Some(&target[..])
+            })
         {
-        } else {
-            info!("adding golden ticket to mempool...");
-            self.transactions.push(transaction);
+            info!(
+                "dropping golden ticket for {}, which we have a ticket for",
+                hex::encode(target)
+            );
+            return false;
+        }
+        let solution = GoldenTicket::generate_solution(
+            target,
+            golden_ticket.get_random(),
+            golden_ticket.get_publickey(),
+        );
+        if !GoldenTicket::is_valid_solution(solution, self.latest_block_difficulty) {
+            info!(
+                "dropping golden ticket for {}, which does not meet its difficulty",
+                hex::encode(target)
+            );
+            return false;
         }
+
+        let transaction = {
+            let wallet_lock = self.wallet_manager.get_wallet_lock(WalletRole::Mining);
+            let mut wallet = wallet_lock.write().await;
+            wallet.create_golden_ticket_transaction(golden_ticket).await
+        };
+        info!("adding golden ticket to mempool...");
+        self.golden_ticket_signatures_by_target
+            .insert(target, transaction.get_signature());
+        self.transactions.push(transaction);
+        true
+    }
+
+    /// Notes the new tip of the longest chain, which golden tickets must solve from now on,
+    /// and drops the golden ticket transactions for other blocks. Returns how many were
+    /// dropped.
+    pub fn set_latest_block(&mut self, block_hash: SaitoHash, difficulty: u64) -> usize {
+        self.latest_block_hash = block_hash;
+        self.latest_block_difficulty = difficulty;
+        self.golden_ticket_signatures_by_target
+            .retain(|target, _| *target == block_hash);
+
+        let transaction_count = self.transactions.len();
+        self.transactions.retain(|transaction| {
+            !transaction.is_golden_ticket()
+                || transaction.get_message().get(0..32) == Some(&block_hash[..])
+        });
+        let stale_transaction_count = transaction_count - self.transactions.len();
+        if stale_transaction_count > 0 {
+            info!(
+                "dropping {} stale golden tickets from the mempool",
+                stale_transaction_count
+            );
+            self.refresh_transaction_metadata();
+        }
+        stale_transaction_count
     }
 
     pub async fn add_transaction_if_validates(
@@ -94,23 +370,37 @@ impl Mempool {
         //
         let blockchain = blockchain_lock.read().await;
         if transaction.validate(&blockchain.utxoset, &blockchain.staking) {
-            self.add_transaction(transaction).await;
+            // the transactions the admission policies reject are logged
+            let _ = self.add_transaction(transaction).await;
         }
     }
-    pub async fn add_transaction(&mut self, mut transaction: Transaction) {
+
+    /// Adds the transaction unless an admission policy rejects it, returning why. Transactions
+    /// already in the mempool are not added again.
+    pub async fn add_transaction(&mut self, transaction: Transaction) -> Result<(), String> {
+        self.insert_transaction(transaction, true).await
+    }
+
+    // our own transactions, e.g. those sweeping dust, are not traffic we
+    // carry for others, and skip the admission policies.
+    async fn insert_transaction(
+        &mut self,
+        mut transaction: Transaction,
+        apply_admission_policies: bool,
+    ) -> Result<(), String> {
         info!("add_transaction {:?}", transaction.get_transaction_type());
         let tx_sig_to_insert = transaction.get_signature();
 
         //
         // this assigns the amount of routing work that this transaction
         // contains to us, which is why we need to provide our publickey
-        // so that we can calculate routing work.
+        // so that we can calculate routing work. the key peers route to is
+        // also the one signing our blocks, see WalletRole::BlockProducer.
         //
-        let publickey;
-        {
-            let wallet = self.wallet_lock.read().await;
-            publickey = wallet.get_publickey();
-        }
+        let publickey = self
+            .wallet_manager
+            .get_publickey(WalletRole::BlockProducer)
+            .await;
         transaction.generate_metadata(publickey);
 
         let routing_work_available_for_me =
@@ -121,33 +411,214 @@ impl Mempool {
             .iter()
             .any(|transaction| transaction.get_signature() == tx_sig_to_insert)
         {
-        } else {
-            self.transactions.push(transaction);
-            self.routing_work_in_mempool += routing_work_available_for_me;
+            return Ok(());
+        }
+        let sender = transaction.get_sender();
+        let transaction_size = transaction.get_serialized_size() as u64;
+        if apply_admission_policies && !transaction.is_golden_ticket() {
+            if let Some(sender) = sender.filter(|_| {
+                self.address_byte_budget > 0 && self.throttle_action == ThrottleAction::Reject
+            }) {
+                let address_bytes = self.get_address_bytes(&sender);
+                if address_bytes + transaction_size > self.address_byte_budget {
+                    info!(
+                        "not admitting transaction {}: {} holds {} bytes in the mempool",
+                        tx_sig_to_insert.to_base58(),
+                        sender.to_base58(),
+                        address_bytes
+                    );
+                    return Err(format!(
+                        "address is over its budget of {} bytes",
+                        self.address_byte_budget
+                    ));
+                }
+            }
+            let current_timestamp = create_timestamp();
+            for admission_policy in self.admission_policies.iter_mut() {
+                if let Err(reason) = admission_policy.admit(&transaction, current_timestamp) {
+                    info!(
+                        "not admitting transaction {}: {}",
+                        tx_sig_to_insert.to_base58(),
+                        reason
+                    );
+                    return Err(reason);
+                }
+            }
+        }
+        self.transaction_expiry_block_ids.insert(
+            tx_sig_to_insert,
+            self.latest_block_id.saturating_add(self.transaction_ttl),
+        );
+        self.transactions.push(transaction);
+        self.routing_work_in_mempool += routing_work_available_for_me;
+        if let Some(sender) = sender {
+            *self.address_bytes.entry(sender).or_insert(0) += transaction_size;
+        }
+        Ok(())
+    }
+
+    /// Sets the block id the ttl of the transactions added is counted from, which is otherwise
+    /// only updated as blocks are added, e.g. to the tip of the chain loaded at startup.
+    pub fn set_latest_block_id(&mut self, latest_block_id: u64) {
+        self.latest_block_id = latest_block_id;
+    }
+
+    /// Drops the transactions which have waited in the mempool for longer than their ttl now
+    /// that the longest chain is at latest_block_id, e.g. because their fees are too low to
+    /// ever be included. Our wallets are told so they can spend the slips again.
+    pub async fn expire_transactions(&mut self, latest_block_id: u64) -> Vec<Transaction> {
+        self.latest_block_id = latest_block_id;
+
+        let transaction_expiry_block_ids = &self.transaction_expiry_block_ids;
+        let (expired_transactions, transactions): (Vec<Transaction>, Vec<Transaction>) = self
+            .transactions
+            .drain(..)
+            .partition(|transaction| {
+                matches!(transaction_expiry_block_ids.get(&transaction.get_signature()), Some(expiry_block_id) if *expiry_block_id <= latest_block_id)
+            });
+        self.transactions = transactions;
+        for wallet_lock in self.wallet_manager.get_wallet_locks() {
+            wallet_lock
+                .write()
+                .await
+                .expire_restored_pending_transactions(latest_block_id, self.transaction_ttl);
+        }
+        if expired_transactions.is_empty() {
+            return expired_transactions;
+        }
+
+        info!(
+            "dropping {} expired transactions from the mempool",
+            expired_transactions.len()
+        );
+        self.refresh_transaction_metadata();
+        for wallet_lock in self.wallet_manager.get_wallet_locks() {
+            let mut wallet = wallet_lock.write().await;
+            for transaction in &expired_transactions {
+                wallet.on_transaction_expired(transaction);
+            }
+        }
+
+        expired_transactions
+    }
+
+    //
+    // recalculates the routing work and the bytes of every address, and
+    // forgets the expiry of transactions which have left the mempool.
+    //
+    fn refresh_transaction_metadata(&mut self) {
+        self.routing_work_in_mempool = 0;
+        self.address_bytes.clear();
+        for transaction in &self.transactions {
+            self.routing_work_in_mempool +=
+                transaction.get_routing_work_for_publickey(self.mempool_publickey);
+            if let Some(sender) = transaction.get_sender() {
+                *self.address_bytes.entry(sender).or_insert(0) +=
+                    transaction.get_serialized_size() as u64;
+            }
         }
+
+        let transactions = &self.transactions;
+        self.transaction_expiry_block_ids.retain(|signature, _| {
+            transactions
+                .iter()
+                .any(|transaction| transaction.get_signature() == *signature)
+        });
     }
 
+    /// Builds a block on the latest block from the transactions, signed by the wallet. The
+    /// mempool is not needed, so the block can be built without holding the mempool lock. The
+    /// transactions of an address beyond the address_byte_budget, if not 0, are deprioritized.
     pub async fn bundle_block(
-        &mut self,
+        mut transactions: Vec<Transaction>,
+        wallet_lock: Arc<RwLock<Wallet>>,
         blockchain_lock: Arc<RwLock<Blockchain>>,
         current_timestamp: u64,
-    ) -> Block {
+        address_byte_budget: u64,
+    ) -> Result<Block, BlockValidationError> {
+        //
+        // the golden ticket and the best paying transactions go first, and are
+        // kept while they fit in the block
+        //
+        transactions.sort_by_key(|transaction| {
+            std::cmp::Reverse((transaction.is_golden_ticket(), transaction.get_fee_rate()))
+        });
+        if address_byte_budget > 0 {
+            deprioritize_over_budget(&mut transactions, address_byte_budget);
+        }
+
+        //
+        // a block delegates a staker key to one key only, so a deposit delegating
+        // it to another key waits for a later block
+        //
+        let mut block_delegations = AHashMap::new();
+        transactions.retain(|transaction| {
+            Staking::add_block_delegations(&mut block_delegations, transaction)
+        });
+
+        let mut block_size = BLOCK_HEADER_SIZE;
+        transactions.retain(|transaction| {
+            let transaction_size =
+                TRANSACTION_LENGTH_PREFIX_SIZE + transaction.get_serialized_size();
+            let fits = block_size + transaction_size <= ConsensusParams::MAX_BLOCK_SIZE;
+            if fits {
+                block_size += transaction_size;
+            }
+            fits
+        });
+
         let blockchain = blockchain_lock.read().await;
         let previous_block_hash = blockchain.get_latest_block_hash();
+        let current_timestamp = match blockchain.get_latest_block() {
+            Some(previous_block) => {
+                next_block_timestamp(current_timestamp, previous_block.get_timestamp())
+            }
+            None => current_timestamp,
+        };
 
         let mut block = Block::generate(
-            &mut self.transactions,
+            &mut transactions,
             previous_block_hash,
-            self.wallet_lock.clone(),
+            wallet_lock,
             blockchain_lock.clone(),
             current_timestamp,
         )
-        .await;
+        .await?;
         block.generate_metadata();
 
-        self.routing_work_in_mempool = 0;
+        Ok(block)
+    }
 
-        block
+    /// Restamps a block bundled ahead of time for current_timestamp, or returns None if it is
+    /// not on our latest block.
+    pub async fn restamp_block(
+        mut block: Block,
+        wallet_lock: Arc<RwLock<Wallet>>,
+        blockchain_lock: Arc<RwLock<Blockchain>>,
+        current_timestamp: u64,
+    ) -> Option<Block> {
+        let blockchain = blockchain_lock.read().await;
+        if block.get_previous_block_hash() != blockchain.get_latest_block_hash() {
+            return None;
+        }
+        let (previous_block_burnfee, previous_block_timestamp, current_timestamp) =
+            match blockchain.get_latest_block() {
+                Some(previous_block) => (
+                    previous_block.get_burnfee(),
+                    previous_block.get_timestamp(),
+                    next_block_timestamp(current_timestamp, previous_block.get_timestamp()),
+                ),
+                None => (0, 0, current_timestamp),
+            };
+        let wallet = wallet_lock.read().await;
+        block.restamp(
+            current_timestamp,
+            previous_block_burnfee,
+            previous_block_timestamp,
+            wallet.get_publickey(),
+            wallet.get_privatekey(),
+        );
+        Some(block)
     }
 
     pub async fn can_bundle_block(
@@ -155,7 +626,10 @@ impl Mempool {
         blockchain_lock: Arc<RwLock<Blockchain>>,
         current_timestamp: u64,
     ) -> bool {
-        if self.currently_bundling_block {
+        if !self.bundling_enabled {
+            return false;
+        }
+        if self.currently_bundling_block || self.currently_producing_block {
             return false;
         }
         if self.transactions.is_empty() {
@@ -164,7 +638,16 @@ impl Mempool {
 
         let blockchain = blockchain_lock.read().await;
 
+        //
+        // a block on a tip far behind the network would only be orphaned
+        //
+        if blockchain.get_sync_status().is_far_behind() {
+            return false;
+        }
+
         if let Some(previous_block) = blockchain.get_latest_block() {
+            let current_timestamp =
+                next_block_timestamp(current_timestamp, previous_block.get_timestamp());
             let work_available = self.get_routing_work_available();
             let work_needed = self.get_routing_work_needed(previous_block, current_timestamp);
             let time_elapsed = current_timestamp - previous_block.get_timestamp();
@@ -180,6 +663,27 @@ impl Mempool {
         }
     }
 
+    /// The earliest timestamp at which the routing work in the mempool suffices to produce a
+    /// block on the latest block, or None if there is nothing to bundle, a block is already
+    /// being produced or we are far behind the network.
+    pub fn get_next_bundle_block_timestamp(&self, blockchain: &Blockchain) -> Option<u64> {
+        if self.currently_bundling_block
+            || self.currently_producing_block
+            || self.transactions.is_empty()
+            || blockchain.get_sync_status().is_far_behind()
+        {
+            return None;
+        }
+        match blockchain.get_latest_block() {
+            Some(previous_block) => Some(BurnFee::return_timestamp_when_routing_work_suffices(
+                previous_block.get_burnfee(),
+                self.get_routing_work_available(),
+                previous_block.get_timestamp(),
+            )),
+            None => Some(0),
+        }
+    }
+
     pub fn delete_transactions(&mut self, transactions: &Vec<Transaction>) {
         let mut tx_hashmap = HashMap::new();
         for transaction in transactions {
@@ -187,15 +691,10 @@ impl Mempool {
             tx_hashmap.entry(hash).or_insert(true);
         }
 
-        self.routing_work_in_mempool = 0;
-
         self.transactions
             .retain(|x| tx_hashmap.contains_key(&x.get_hash_for_signature()) != true);
 
-        for transaction in &self.transactions {
-            self.routing_work_in_mempool +=
-                transaction.get_routing_work_for_publickey(self.mempool_publickey);
-        }
+        self.refresh_transaction_metadata();
     }
 
     ///
@@ -224,12 +723,72 @@ impl Mempool {
         work_needed
     }
 
+    /// Summarizes the mempool, counting the routing work needed to produce a block on the
+    /// latest block at current_timestamp.
+    pub fn get_mempool_info(&self, blockchain: &Blockchain, current_timestamp: u64) -> MempoolInfo {
+        let routing_work_needed = match blockchain.get_latest_block() {
+            Some(previous_block) => self.get_routing_work_needed(
+                previous_block,
+                next_block_timestamp(current_timestamp, previous_block.get_timestamp()),
+            ),
+            None => 0,
+        };
+        MempoolInfo {
+            transaction_count: self.transactions.len(),
+            bytes: self
+                .transactions
+                .iter()
+                .map(|transaction| transaction.get_serialized_size())
+                .sum(),
+            total_fees: self
+                .transactions
+                .iter()
+                .map(|transaction| Amount::from(transaction.get_total_fees()))
+                .sum::<Amount>()
+                .into(),
+            routing_work_available: self.get_routing_work_available(),
+            routing_work_needed,
+        }
+    }
+
+    /// The transactions in the mempool with their fees and routing work, in the order they
+    /// were added.
+    pub fn get_raw_mempool(&self) -> Vec<MempoolTransactionInfo> {
+        self.transactions
+            .iter()
+            .map(|transaction| MempoolTransactionInfo {
+                hash: hex::encode(
+                    transaction
+                        .get_hash_for_signature()
+                        .unwrap_or_else(|| transaction.generate_hash_for_signature()),
+                ),
+                total_fees: transaction.get_total_fees(),
+                size: transaction.get_serialized_size(),
+                fee_rate: transaction.get_fee_rate(),
+                routing_hops: transaction.get_path().len(),
+                routing_work_for_us: transaction
+                    .get_routing_work_for_publickey(self.mempool_publickey),
+            })
+            .collect()
+    }
+
     pub fn set_broadcast_channel_sender(&mut self, bcs: broadcast::Sender<SaitoMessage>) {
         self.broadcast_channel_sender = Some(bcs);
     }
 
+    /// Blocks are sent to be added by the blockchain task over this channel.
+    pub fn set_blockchain_channel_sender(
+        &mut self,
+        blockchain_channel_sender: mpsc::Sender<BlockchainMessage>,
+    ) {
+        self.blockchain_channel_sender = Some(blockchain_channel_sender);
+    }
+
+    /// Sets the key routing work is counted for, recounting the work of the transactions
+    /// already in the mempool.
     pub fn set_mempool_publickey(&mut self, publickey: SaitoPublicKey) {
         self.mempool_publickey = publickey;
+        self.refresh_transaction_metadata();
     }
 
     pub fn set_mempool_privatekey(&mut self, privatekey: SaitoPrivateKey) {
@@ -240,21 +799,110 @@ impl Mempool {
         mempool_lock: Arc<RwLock<Mempool>>,
         blockchain_lock: Arc<RwLock<Blockchain>>,
     ) {
-        let mut mempool = mempool_lock.write().await;
-        mempool.currently_bundling_block = true;
-        let mut blockchain = blockchain_lock.write().await;
-        while let Some(block) = mempool.blocks_queue.pop_front() {
-            mempool.delete_transactions(&block.get_transactions());
-            blockchain.add_block(block).await;
+        let (blocks, blockchain_channel_sender): (Vec<Block>, _) = {
+            let mut mempool = mempool_lock.write().await;
+            mempool.currently_bundling_block = true;
+            (
+                mempool.blocks_queue.drain(..).collect(),
+                mempool.blockchain_channel_sender.clone(),
+            )
+        };
+
+        //
+        // the signatures are checked before taking the locks, so that the blocks of several
+        // peers are checked concurrently and the blockchain is only locked to add them
+        //
+        let blocks = Block::prevalidate_blocks(blocks).await;
+
+        {
+            let mut mempool = mempool_lock.write().await;
+            for block in blocks.iter() {
+                mempool.delete_transactions(block.get_transactions());
+            }
         }
+
+        //
+        // the mempool lock is not held while the blocks are added
+        //
+        let latest_block_id = match blockchain_channel_sender {
+            Some(blockchain_channel_sender) => {
+                let (reply_sender, reply_receiver) = oneshot::channel();
+                blockchain_channel_sender
+                    .send(BlockchainMessage::AddBlocks {
+                        blocks,
+                        reply: reply_sender,
+                    })
+                    .await
+                    .expect("error: AddBlocks message failed to send");
+                reply_receiver
+                    .await
+                    .expect("error: AddBlocks reply failed to arrive")
+            }
+            //
+            // without a blockchain task, e.g. in tests, the blocks are added here
+            //
+            None => Blockchain::add_blocks(blockchain_lock, blocks).await,
+        };
+
+        let mut mempool = mempool_lock.write().await;
+        mempool.expire_transactions(latest_block_id).await;
+        mempool.consolidate_dust().await;
         mempool.currently_bundling_block = false;
     }
 
+    /// Sweeps the dust of each of our wallets holding more than the dust consolidation
+    /// threshold into one slip, before the ATR mechanism takes it, and relays the sweeping
+    /// transactions. Returns the number of transactions added.
+    pub async fn consolidate_dust(&mut self) -> usize {
+        if self.dust_consolidation_threshold == 0 {
+            return 0;
+        }
+        let mut transactions = vec![];
+        for wallet_lock in self.wallet_manager.get_wallet_locks() {
+            let mut wallet = wallet_lock.write().await;
+            if wallet.get_dust_balance() > self.dust_consolidation_threshold {
+                if let Some(transaction) = wallet.create_dust_consolidation_transaction() {
+                    info!(
+                        "sweeping {} of dust for {}",
+                        transaction.get_outputs()[0].get_amount().to_nolan_string(),
+                        wallet.get_publickey().to_base58()
+                    );
+                    transactions.push(transaction);
+                }
+            }
+        }
+
+        let transaction_count = transactions.len();
+        for transaction in transactions {
+            self.add_wallet_transaction(transaction).await;
+        }
+        transaction_count
+    }
+
+    /// Adds a transaction created by one of our wallets and relays it to our peers.
+    pub async fn add_wallet_transaction(&mut self, transaction: Transaction) {
+        if let Some(broadcast_channel_sender) = self.broadcast_channel_sender.as_ref() {
+            send_message(
+                broadcast_channel_sender,
+                SaitoMessage::WalletNewTransaction {
+                    transaction: transaction.clone(),
+                },
+            );
+        }
+        let _ = self.insert_transaction(transaction, false).await;
+    }
+
     pub fn transaction_exists(&self, tx_hash: Option<SaitoHash>) -> bool {
         self.transactions
             .iter()
             .any(|transaction| transaction.get_hash_for_signature() == tx_hash)
     }
+
+    pub fn get_transaction(&self, tx_hash: SaitoHash) -> Option<&Transaction> {
+        self.transactions
+            .iter()
+            .find(|transaction| transaction.get_hash_for_signature() == Some(tx_hash))
+    }
 }
 
 pub async fn try_bundle_block(
@@ -263,24 +911,257 @@ pub async fn try_bundle_block(
     current_timestamp: u64,
 ) -> Option<Block> {
     info!("try_bundle_block");
-    // We use a boolean here so we can avoid taking the write lock most of the time
-    let can_bundle;
+
+    //
+    // we take a copy of the transactions and release the mempool lock while the
+    // block is built, so that new transactions can still be added meanwhile.
+    //
+    let transactions;
+    let wallet_lock;
+    let address_byte_budget;
+    let preassembled_block;
     {
-        let mempool = mempool_lock.read().await;
-        can_bundle = mempool
+        let mut mempool = mempool_lock.write().await;
+        if !mempool
             .can_bundle_block(blockchain_lock.clone(), current_timestamp)
-            .await;
+            .await
+        {
+            return None;
+        }
+        mempool.currently_producing_block = true;
+        transactions = mempool.transactions.clone();
+        address_byte_budget = mempool.address_byte_budget;
+        wallet_lock = mempool
+            .wallet_manager
+            .get_wallet_lock(WalletRole::BlockProducer);
+        let transaction_signatures = mempool.get_transaction_signatures();
+        preassembled_block = mempool
+            .preassembled_block
+            .take()
+            .filter(|preassembled_block| {
+                preassembled_block.transaction_signatures == transaction_signatures
+            });
     }
-    if can_bundle {
-        let mut mempool = mempool_lock.write().await;
-        Some(
-            mempool
-                .bundle_block(blockchain_lock.clone(), current_timestamp)
-                .await,
+
+    let mut restamped_block = None;
+    if let Some(preassembled_block) = preassembled_block {
+        restamped_block = Mempool::restamp_block(
+            preassembled_block.block,
+            wallet_lock.clone(),
+            blockchain_lock.clone(),
+            current_timestamp,
         )
-    } else {
-        None
+        .await;
+    }
+    let block = match restamped_block {
+        Some(block) => Ok(block),
+        None => {
+            Mempool::bundle_block(
+                transactions,
+                wallet_lock,
+                blockchain_lock.clone(),
+                current_timestamp,
+                address_byte_budget,
+            )
+            .await
+        }
+    };
+
+    //
+    // only the transactions in the block leave the mempool
+    //
+    let mut mempool = mempool_lock.write().await;
+    mempool.currently_producing_block = false;
+    match block {
+        Ok(block) => {
+            mempool.delete_transactions(block.get_transactions());
+            Some(block)
+        }
+        Err(error) => {
+            error!("{}", error);
+            None
+        }
+    }
+}
+
+/// Bundles the next block ahead of the time the routing work suffices, and keeps it for
+/// try_bundle_block to restamp.
+pub async fn preassemble_block(
+    mempool_lock: Arc<RwLock<Mempool>>,
+    blockchain_lock: Arc<RwLock<Blockchain>>,
+) {
+    let transactions;
+    let transaction_signatures;
+    let wallet_lock;
+    let address_byte_budget;
+    {
+        let mempool = mempool_lock.read().await;
+        if !mempool.bundling_enabled || mempool.transactions.is_empty() {
+            return;
+        }
+        transactions = mempool.transactions.clone();
+        transaction_signatures = mempool.get_transaction_signatures();
+        wallet_lock = mempool
+            .wallet_manager
+            .get_wallet_lock(WalletRole::BlockProducer);
+        address_byte_budget = mempool.address_byte_budget;
     }
+    let block = match Mempool::bundle_block(
+        transactions,
+        wallet_lock,
+        blockchain_lock,
+        create_timestamp(),
+        address_byte_budget,
+    )
+    .await
+    {
+        Ok(block) => block,
+        Err(error) => {
+            error!("{}", error);
+            return;
+        }
+    };
+    mempool_lock.write().await.preassembled_block = Some(PreassembledBlock {
+        block,
+        transaction_signatures,
+    });
+}
+
+/// Builds the block the mempool would produce on the latest block at current_timestamp,
+/// without taking any transactions out of the mempool, so that an external block producer
+/// can finalize it and hand it back.
+pub async fn generate_block_template(
+    mempool_lock: Arc<RwLock<Mempool>>,
+    blockchain_lock: Arc<RwLock<Blockchain>>,
+    current_timestamp: u64,
+) -> Result<Block, BlockValidationError> {
+    let transactions;
+    let wallet_lock;
+    let address_byte_budget;
+    {
+        let mempool = mempool_lock.read().await;
+        transactions = mempool.transactions.clone();
+        wallet_lock = mempool
+            .wallet_manager
+            .get_wallet_lock(WalletRole::BlockProducer);
+        address_byte_budget = mempool.address_byte_budget;
+    }
+    Mempool::bundle_block(
+        transactions,
+        wallet_lock,
+        blockchain_lock,
+        current_timestamp,
+        address_byte_budget,
+    )
+    .await
+}
+
+/// Moves the transactions of every address beyond the first address_byte_budget bytes it sent
+/// behind those of all other addresses, keeping their order otherwise, so that a single
+/// spammy address cannot crowd the others out of a block.
+fn deprioritize_over_budget(transactions: &mut Vec<Transaction>, address_byte_budget: u64) {
+    let mut address_bytes: AHashMap<SaitoPublicKey, u64> = AHashMap::new();
+    let (mut within_budget, over_budget): (Vec<Transaction>, Vec<Transaction>) = transactions
+        .drain(..)
+        .partition(|transaction| match transaction.get_sender() {
+            Some(sender) if !transaction.is_golden_ticket() => {
+                let bytes = address_bytes.entry(sender).or_insert(0);
+                *bytes += transaction.get_serialized_size() as u64;
+                *bytes <= address_byte_budget
+            }
+            _ => true,
+        });
+    within_budget.extend(over_budget);
+    *transactions = within_budget;
+}
+
+/// Validates a batch of transactions in parallel and adds the valid ones to the mempool at
+/// once, so that no block is bundled from part of the batch. Transactions which could not be
+/// deserialized are None. Returns the result of every transaction, in the order of the batch.
+/// Transactions in the batch spending a slip spent by one before them are rejected.
+pub async fn add_transaction_batch(
+    mempool_lock: Arc<RwLock<Mempool>>,
+    blockchain_lock: Arc<RwLock<Blockchain>>,
+    publickey: SaitoPublicKey,
+    mut transactions: Vec<Option<Transaction>>,
+) -> Vec<TransactionBatchResult> {
+    //
+    // the transactions are validated off the async runtime and before the
+    // mempool is locked, so that the mempool is only locked to add them.
+    // transactions posted over HTTP have no relaying peer, but any routing
+    // path they carry must still end with us.
+    //
+    let blockchain = blockchain_lock.read_owned().await;
+    let (transactions, validations) = tokio::task::spawn_blocking(move || {
+        let validations: Vec<std::result::Result<(), &str>> = transactions
+            .par_iter_mut()
+            .map(|transaction| {
+                let transaction = transaction.as_mut().ok_or("malformed transaction")?;
+                if !transaction.validate_routing_path_for_peer(None, publickey) {
+                    return Err("invalid routing path");
+                }
+                let sender = transaction
+                    .get_inputs()
+                    .first()
+                    .ok_or("invalid transaction")?
+                    .get_publickey();
+                transaction.generate_metadata(sender);
+                if !transaction.validate(&blockchain.utxoset, &blockchain.staking) {
+                    return Err("invalid transaction");
+                }
+                Ok(())
+            })
+            .collect();
+        (transactions, validations)
+    })
+    .await
+    .expect("transaction batch validation panicked");
+
+    let mut mempool = mempool_lock.write().await;
+    let mut spent_slips = HashSet::new();
+    let mut results = vec![];
+    for (transaction, validation) in transactions.into_iter().zip(validations) {
+        let transaction = match validation {
+            Ok(()) => transaction.unwrap(),
+            Err(error) => {
+                results.push(TransactionBatchResult::rejected(error));
+                continue;
+            }
+        };
+        if mempool.transaction_exists(transaction.get_hash_for_signature()) {
+            results.push(TransactionBatchResult::rejected("already in the mempool"));
+            continue;
+        }
+        let slip_keys: Vec<SaitoUTXOSetKey> = transaction
+            .get_inputs()
+            .iter()
+            .map(|input| input.get_utxoset_key())
+            .collect();
+        if slip_keys
+            .iter()
+            .any(|slip_key| spent_slips.contains(slip_key))
+        {
+            results.push(TransactionBatchResult::rejected(
+                "spends a slip spent earlier in the batch",
+            ));
+            continue;
+        }
+        let signature = transaction.get_signature().to_base58();
+        match mempool.add_transaction(transaction).await {
+            Ok(()) => {
+                spent_slips.extend(slip_keys);
+                results.push(TransactionBatchResult {
+                    signature: Some(signature),
+                    error: None,
+                });
+            }
+            Err(reason) => results.push(TransactionBatchResult::rejected(&format!(
+                "not admitted: {}",
+                reason
+            ))),
+        }
+    }
+    results
 }
 
 //
@@ -291,6 +1172,7 @@ pub async fn try_bundle_block(
 pub async fn run(
     mempool_lock: Arc<RwLock<Mempool>>,
     blockchain_lock: Arc<RwLock<Blockchain>>,
+    blockchain_channel_sender: mpsc::Sender<BlockchainMessage>,
     broadcast_channel_sender: broadcast::Sender<SaitoMessage>,
     mut broadcast_channel_receiver: broadcast::Receiver<SaitoMessage>,
 ) -> crate::Result<()> {
@@ -303,11 +1185,15 @@ pub async fn run(
         let publickey;
         let privatekey;
         {
-            let wallet = mempool.wallet_lock.read().await;
+            let wallet_lock = mempool
+                .wallet_manager
+                .get_wallet_lock(WalletRole::BlockProducer);
+            let wallet = wallet_lock.read().await;
             publickey = wallet.get_publickey();
             privatekey = wallet.get_privatekey();
         }
         mempool.set_broadcast_channel_sender(broadcast_channel_sender.clone());
+        mempool.set_blockchain_channel_sender(blockchain_channel_sender);
         mempool.set_mempool_publickey(publickey);
         mempool.set_mempool_privatekey(privatekey);
     }
@@ -321,15 +1207,83 @@ pub async fn run(
     // local channel sender -- send in clone as thread takes ownership
     //
     let bundle_block_sender = mempool_channel_sender.clone();
-    tokio::spawn(async move {
-        loop {
-            bundle_block_sender
-                .send(MempoolMessage::LocalTryBundleBlock)
-                .await
-                .expect("error: LocalTryBundleBlock message failed to send");
-            sleep(Duration::from_millis(1000));
-        }
-    });
+    let bundle_block_mempool_lock = mempool_lock.clone();
+    let bundle_block_blockchain_lock = blockchain_lock.clone();
+    if !mempool_lock.read().await.get_bundling_enabled() {
+        info!("not bundling blocks in transaction relay mode");
+    } else {
+        tokio::spawn(async move {
+            //
+            // we wake when the routing work in the mempool will suffice to produce a
+            // block, and otherwise every BUNDLE_BLOCK_INTERVAL to catch changes to the
+            // routing work, e.g. new transactions. Each wake-up from the burn fee curve
+            // is only used once, so that we do not spin if bundling fails anyway. With
+            // a lead time we also wake that much earlier, once, to preassemble.
+            //
+            let mut bundle_block_interval = interval(Duration::from_millis(BUNDLE_BLOCK_INTERVAL));
+            bundle_block_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            let mut woken_bundle_block_timestamp = None;
+            let mut preassembled_bundle_block_timestamp = None;
+            loop {
+                let (next_bundle_block_timestamp, block_lead_time) = {
+                    let mempool = bundle_block_mempool_lock.read().await;
+                    let blockchain = bundle_block_blockchain_lock.read().await;
+                    (
+                        mempool.get_next_bundle_block_timestamp(&blockchain),
+                        mempool.get_block_lead_time(),
+                    )
+                };
+                match next_bundle_block_timestamp {
+                    Some(timestamp)
+                        if block_lead_time > 0
+                            && timestamp > create_timestamp()
+                            && Some(timestamp) != preassembled_bundle_block_timestamp =>
+                    {
+                        let deadline = Instant::now()
+                            + Duration::from_millis(
+                                timestamp
+                                    .saturating_sub(block_lead_time)
+                                    .saturating_sub(create_timestamp()),
+                            );
+                        tokio::select! {
+                            _ = bundle_block_interval.tick() => {}
+                            _ = sleep_until(deadline) => {
+                                preassembled_bundle_block_timestamp = Some(timestamp);
+                                if bundle_block_sender
+                                    .send(MempoolMessage::LocalPreassembleBlock)
+                                    .await
+                                    .is_err()
+                                {
+                                    break;
+                                }
+                                continue;
+                            }
+                        }
+                    }
+                    Some(timestamp) if Some(timestamp) != woken_bundle_block_timestamp => {
+                        let deadline = Instant::now()
+                            + Duration::from_millis(timestamp.saturating_sub(create_timestamp()));
+                        tokio::select! {
+                            _ = bundle_block_interval.tick() => {}
+                            _ = sleep_until(deadline) => {
+                                woken_bundle_block_timestamp = Some(timestamp);
+                            }
+                        }
+                    }
+                    _ => {
+                        bundle_block_interval.tick().await;
+                    }
+                }
+                if bundle_block_sender
+                    .send(MempoolMessage::LocalTryBundleBlock)
+                    .await
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+    }
 
     //
     // global and local channel receivers
@@ -347,23 +1301,40 @@ pub async fn run(
                     // attempt to bundle block
                     //
                     MempoolMessage::LocalTryBundleBlock => {
-            let current_timestamp = create_timestamp();
-                        if let Some(block) = try_bundle_block(
-                            mempool_lock.clone(),
-                            blockchain_lock.clone(),
-                            current_timestamp,
-                        ).await {
-                            let mut mempool = mempool_lock.write().await;
-                            mempool.add_block(block);
-                            mempool_channel_sender.send(MempoolMessage::LocalNewBlock).await.expect("Failed to send LocalNewBlock message");
-                        }
+                        //
+                        // blocks are produced in their own task so this loop
+                        // keeps handling messages while the block is built
+                        //
+                        let mempool_lock = mempool_lock.clone();
+                        let blockchain_lock = blockchain_lock.clone();
+                        let mempool_channel_sender = mempool_channel_sender.clone();
+                        tokio::spawn(async move {
+                            let current_timestamp = create_timestamp();
+                            if let Some(block) = try_bundle_block(
+                                mempool_lock.clone(),
+                                blockchain_lock,
+                                current_timestamp,
+                            ).await {
+                                mempool_lock.write().await.add_block(block);
+                                mempool_channel_sender.send(MempoolMessage::LocalNewBlock).await.expect("Failed to send LocalNewBlock message");
+                            }
+                        });
+                    },
 
+                    //
+                    // bundle the next block ahead of time
+                    //
+                    MempoolMessage::LocalPreassembleBlock => {
+                        tokio::spawn(preassemble_block(mempool_lock.clone(), blockchain_lock.clone()));
                     },
 
                     //
                     // attempt to send to blockchain
                     //
                     MempoolMessage::LocalNewBlock => {
+                        //
+                        // no lock is held while the blockchain task adds the blocks
+                        //
                         Mempool::send_blocks_to_blockchain(mempool_lock.clone(), blockchain_lock.clone()).await;
                     },
 
@@ -374,13 +1345,17 @@ pub async fn run(
         //
         // global broadcast channel receivers
         //
-            Ok(message) = broadcast_channel_receiver.recv() => {
+            Some(message) = recv_message(&mut broadcast_channel_receiver, Subsystem::Mempool) => {
                 match message {
                     SaitoMessage::MinerNewGoldenTicket { ticket : golden_ticket } => {
                        // when miner produces golden ticket
                         let mut mempool = mempool_lock.write().await;
                         mempool.add_golden_ticket(golden_ticket).await;
                     },
+                    SaitoMessage::BlockchainNewLongestChainBlock { hash : block_hash, difficulty } => {
+                        let mut mempool = mempool_lock.write().await;
+                        mempool.set_latest_block(block_hash, difficulty);
+                    },
                     _ => {},
                 }
             }
@@ -392,7 +1367,10 @@ pub async fn run(
 mod tests {
 
     use super::*;
-    use crate::{block::Block, test_utilities::test_manager::TestManager, wallet::Wallet};
+    use crate::{
+        admission_policy::MinimumFeeRate, block::Block, burnfee::HEARTBEAT, slip::Slip,
+        test_utilities::test_manager::TestManager, wallet::Wallet,
+    };
 
     use std::sync::Arc;
     use tokio::sync::RwLock;
@@ -413,6 +1391,481 @@ mod tests {
         assert_eq!(Some(block), mempool.blocks_queue.pop_front())
     }
 
+    #[tokio::test]
+    async fn mempool_golden_ticket_from_mining_wallet_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let mining_wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let mining_publickey = mining_wallet_lock.read().await.get_publickey();
+
+        let mut wallet_manager = WalletManager::new(wallet_lock.clone());
+        wallet_manager
+            .add_wallet(mining_wallet_lock, &[WalletRole::Mining])
+            .unwrap();
+        let mut mempool = Mempool::new(wallet_lock);
+        mempool.set_wallet_manager(wallet_manager);
+
+        mempool.set_latest_block([1; 32], 0);
+        let golden_ticket = GoldenTicket::new([1; 32], [2; 32], mining_publickey);
+        assert!(mempool.add_golden_ticket(golden_ticket).await);
+
+        assert_eq!(mempool.transactions.len(), 1);
+        assert!(mempool.transactions[0].is_golden_ticket());
+        assert_eq!(
+            mempool.transactions[0].get_inputs()[0].get_publickey(),
+            mining_publickey
+        );
+    }
+
+    #[tokio::test]
+    async fn mempool_golden_ticket_per_target_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let publickey = wallet_lock.read().await.get_publickey();
+        let mut mempool = Mempool::new(wallet_lock.clone());
+        mempool.set_latest_block([1; 32], 0);
+
+        // a ticket for a block which is not the tip is dropped
+        assert!(
+            !mempool
+                .add_golden_ticket(GoldenTicket::new([2; 32], [1; 32], publickey))
+                .await
+        );
+        // only the first ticket for the tip is added
+        assert!(
+            mempool
+                .add_golden_ticket(GoldenTicket::new([1; 32], [1; 32], publickey))
+                .await
+        );
+        assert!(
+            !mempool
+                .add_golden_ticket(GoldenTicket::new([1; 32], [2; 32], publickey))
+                .await
+        );
+        assert_eq!(mempool.transactions.len(), 1);
+
+        // the ticket for the old tip leaves the mempool, other transactions stay
+        let mut transaction = Transaction::new();
+        transaction.set_message(vec![1; 32]);
+        transaction.sign(wallet_lock.read().await.get_privatekey());
+        mempool.add_transaction(transaction).await.unwrap();
+        assert_eq!(mempool.transactions.len(), 2);
+        assert_eq!(mempool.set_latest_block([3; 32], 64 * 16), 1);
+        assert_eq!(mempool.transactions.len(), 1);
+        assert!(!mempool.transactions[0].is_golden_ticket());
+
+        // a ticket which does not meet the difficulty is dropped
+        assert!(
+            !mempool
+                .add_golden_ticket(GoldenTicket::new([3; 32], [1; 32], publickey))
+                .await
+        );
+
+        let relayed_golden_ticket_transaction = wallet_lock
+            .write()
+            .await
+            .create_golden_ticket_transaction(GoldenTicket::new([3; 32], [1; 32], publickey))
+            .await;
+        mempool
+            .add_transaction(relayed_golden_ticket_transaction)
+            .await
+            .unwrap();
+        mempool.set_latest_block([3; 32], 0);
+        assert_eq!(mempool.transactions.len(), 2);
+
+        // a ticket for the tip relayed to us counts as the first one
+        assert!(
+            !mempool
+                .add_golden_ticket(GoldenTicket::new([3; 32], [2; 32], publickey))
+                .await
+        );
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn mempool_can_bundle_block_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        test_manager.generate_blockchain(1, [0; 32]).await;
+
+        let publickey = wallet_lock.read().await.get_publickey();
+        let mut mempool = Mempool::new(wallet_lock.clone());
+        let previous_block_timestamp = {
+            let blockchain = blockchain_lock.read().await;
+            blockchain.get_latest_block().unwrap().get_timestamp()
+        };
+        let work_needed = {
+            let blockchain = blockchain_lock.read().await;
+            mempool.get_routing_work_needed(
+                blockchain.get_latest_block().unwrap(),
+                previous_block_timestamp + 1000,
+            )
+        };
+        assert!(work_needed > 0);
+        assert_eq!(
+            mempool.get_next_bundle_block_timestamp(&*blockchain_lock.read().await),
+            None
+        );
+
+        //
+        // a transaction routed to us with one nolan too little in fees
+        //
+        let mut transaction = Transaction::generate_transaction(
+            wallet_lock.clone(),
+            publickey,
+            1000,
+            work_needed - 1,
+        )
+        .await;
+        transaction.sign(wallet_lock.read().await.get_privatekey());
+        transaction.add_hop(&*wallet_lock.read().await, publickey);
+        mempool.add_transaction(transaction).await.unwrap();
+
+        // routing work is only counted once we know our key
+        assert_eq!(mempool.get_routing_work_available(), 0);
+        mempool.set_mempool_publickey(publickey);
+        assert_eq!(mempool.get_routing_work_available(), work_needed - 1);
+        let raw_mempool = mempool.get_raw_mempool();
+        assert_eq!(raw_mempool[0].routing_hops, 1);
+        assert_eq!(raw_mempool[0].routing_work_for_us, work_needed - 1);
+
+        assert!(
+            !mempool
+                .can_bundle_block(blockchain_lock.clone(), previous_block_timestamp + 1000)
+                .await
+        );
+        assert!(
+            mempool
+                .can_bundle_block(blockchain_lock.clone(), previous_block_timestamp + 2000)
+                .await
+        );
+        assert!(
+            mempool
+                .can_bundle_block(
+                    blockchain_lock.clone(),
+                    previous_block_timestamp + 2 * HEARTBEAT
+                )
+                .await
+        );
+
+        // the mempool wakes exactly when the routing work suffices
+        let next_bundle_block_timestamp = mempool
+            .get_next_bundle_block_timestamp(&*blockchain_lock.read().await)
+            .unwrap();
+        assert!(next_bundle_block_timestamp > previous_block_timestamp + 1000);
+        assert!(next_bundle_block_timestamp <= previous_block_timestamp + 2000);
+        assert!(
+            mempool
+                .can_bundle_block(blockchain_lock.clone(), next_bundle_block_timestamp)
+                .await
+        );
+
+        // nor in transaction relay mode
+        mempool.set_bundling_enabled(false);
+        assert!(
+            !mempool
+                .can_bundle_block(blockchain_lock.clone(), next_bundle_block_timestamp)
+                .await
+        );
+        mempool.set_bundling_enabled(true);
+
+        // no blocks are produced while we are far behind the network
+        blockchain_lock
+            .write()
+            .await
+            .get_mut_sync_status()
+            .on_peer_tip([1; 32], 100);
+        assert!(
+            !mempool
+                .can_bundle_block(blockchain_lock.clone(), next_bundle_block_timestamp)
+                .await
+        );
+        assert_eq!(
+            mempool.get_next_bundle_block_timestamp(&*blockchain_lock.read().await),
+            None
+        );
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn mempool_bundle_block_without_mempool_lock_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        test_manager.generate_blockchain(1, [0; 32]).await;
+        let mempool_lock = test_manager.mempool_lock.clone();
+
+        let first_transaction = test_manager.generate_transaction(1000, 1000).await;
+        mempool_lock
+            .write()
+            .await
+            .add_transaction(first_transaction)
+            .await
+            .unwrap();
+        let transactions = mempool_lock.read().await.transactions.clone();
+
+        //
+        // the block is built while someone else holds the mempool lock and
+        // a transaction arrives before the block is done
+        //
+        let block = {
+            let mut mempool = mempool_lock.write().await;
+            let block = Mempool::bundle_block(
+                transactions,
+                wallet_lock.clone(),
+                blockchain_lock.clone(),
+                create_timestamp(),
+                0,
+            )
+            .await
+            .unwrap();
+            let second_transaction = test_manager.generate_transaction(1000, 1000).await;
+            mempool.add_transaction(second_transaction).await.unwrap();
+            block
+        };
+        assert_eq!(block.get_id(), 2);
+
+        let mut mempool = mempool_lock.write().await;
+        mempool.delete_transactions(block.get_transactions());
+        assert_eq!(mempool.transactions.len(), 1);
+        assert!(!block
+            .get_transactions()
+            .iter()
+            .any(|transaction| transaction.get_signature()
+                == mempool.transactions[0].get_signature()));
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn mempool_bundle_block_max_block_size_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        test_manager.generate_blockchain(1, [0; 32]).await;
+        let (publickey, privatekey) = {
+            let wallet = wallet_lock.read().await;
+            (wallet.get_publickey(), wallet.get_privatekey())
+        };
+
+        //
+        // only one of two transactions of half the max block size fits
+        //
+        let mut transactions = vec![];
+        for fee in [10_000_000, 20_000_000] {
+            let mut transaction = test_manager.generate_transaction(1000, fee).await;
+            transaction.set_message(vec![0; ConsensusParams::MAX_BLOCK_SIZE / 2]);
+            transaction.sign(privatekey);
+            transaction.generate_metadata(publickey);
+            transactions.push(transaction);
+        }
+        let block = Mempool::bundle_block(
+            transactions.clone(),
+            wallet_lock.clone(),
+            blockchain_lock.clone(),
+            create_timestamp(),
+            0,
+        )
+        .await
+        .unwrap();
+
+        assert!(block.get_limited_size() <= ConsensusParams::MAX_BLOCK_SIZE);
+        assert_eq!(block.get_transactions().len(), 1);
+        assert_eq!(
+            block.get_transactions()[0].get_signature(),
+            transactions[1].get_signature()
+        );
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn mempool_consolidate_dust_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        test_manager.generate_blockchain(1, [0; 32]).await;
+
+        //
+        // the first block pays us ten VIP slips, each of them dust
+        //
+        let dust_balance = wallet_lock.read().await.get_dust_balance();
+        assert_eq!(dust_balance, 10 * 10_000_000);
+
+        let mut mempool = Mempool::new(wallet_lock.clone());
+        mempool.set_dust_consolidation_threshold(dust_balance);
+        assert_eq!(mempool.consolidate_dust().await, 0);
+        mempool.set_dust_consolidation_threshold(0);
+        assert_eq!(mempool.consolidate_dust().await, 0);
+
+        mempool.set_dust_consolidation_threshold(dust_balance - 1);
+        assert_eq!(mempool.consolidate_dust().await, 1);
+        let transaction = &mempool.transactions[0];
+        assert_eq!(transaction.get_inputs().len(), 10);
+        assert_eq!(transaction.get_outputs().len(), 1);
+        assert_eq!(
+            transaction.get_outputs()[0].get_amount(),
+            Amount::from(dust_balance)
+        );
+        {
+            let blockchain = blockchain_lock.read().await;
+            assert!(transaction.validate(&blockchain.utxoset, &blockchain.staking));
+        }
+
+        let wallet = wallet_lock.read().await;
+        assert_eq!(wallet.get_dust_balance(), 0);
+        assert_eq!(wallet.get_pending_balance(), dust_balance);
+        drop(wallet);
+        assert_eq!(mempool.consolidate_dust().await, 0);
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn mempool_transaction_expiry_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        test_manager.generate_blockchain(1, [0; 32]).await;
+
+        let mut mempool = Mempool::new(wallet_lock.clone());
+        mempool.set_transaction_ttl(2);
+        mempool.expire_transactions(1).await;
+
+        let balance = wallet_lock.read().await.get_available_balance();
+        let mut transaction =
+            Transaction::generate_transaction(wallet_lock.clone(), [1; 33], 1000, 0).await;
+        transaction.sign(wallet_lock.read().await.get_privatekey());
+        mempool.add_transaction(transaction).await.unwrap();
+        assert!(wallet_lock.read().await.get_available_balance() < balance);
+
+        assert!(mempool.expire_transactions(2).await.is_empty());
+        assert_eq!(mempool.transactions.len(), 1);
+
+        //
+        // once expired the wallet can spend the slips again
+        //
+        assert_eq!(mempool.expire_transactions(3).await.len(), 1);
+        assert!(mempool.transactions.is_empty());
+        assert_eq!(mempool.get_routing_work_available(), 0);
+        let wallet = wallet_lock.read().await;
+        assert_eq!(wallet.get_available_balance(), balance);
+        assert!(wallet.get_pending_transactions().is_empty());
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn mempool_transaction_expiry_after_startup_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        test_manager
+            .generate_blockchain(MEMPOOL_TRANSACTION_TTL + 5, [0; 32])
+            .await;
+        let latest_block_id = blockchain_lock.read().await.get_latest_block_id();
+        assert!(latest_block_id > MEMPOOL_TRANSACTION_TTL);
+
+        //
+        // a mempool started on a loaded chain counts the ttl from its tip
+        //
+        let mut mempool = Mempool::new(wallet_lock.clone());
+        mempool.set_latest_block_id(latest_block_id);
+        let mut transaction =
+            Transaction::generate_transaction(wallet_lock.clone(), [1; 33], 1000, 0).await;
+        transaction.sign(wallet_lock.read().await.get_privatekey());
+        mempool.add_transaction(transaction).await.unwrap();
+
+        assert!(mempool
+            .expire_transactions(latest_block_id + 1)
+            .await
+            .is_empty());
+        assert_eq!(mempool.transactions.len(), 1);
+        assert_eq!(
+            mempool
+                .expire_transactions(latest_block_id + MEMPOOL_TRANSACTION_TTL)
+                .await
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn mempool_admission_policy_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        test_manager.generate_blockchain(1, [0; 32]).await;
+
+        let mut mempool = Mempool::new(wallet_lock.clone());
+        mempool.add_admission_policy(Box::new(MinimumFeeRate::new(1)));
+
+        let mut free_transaction =
+            Transaction::generate_transaction(wallet_lock.clone(), [1; 33], 1000, 0).await;
+        free_transaction.sign(wallet_lock.read().await.get_privatekey());
+        assert!(mempool.add_transaction(free_transaction).await.is_err());
+        assert!(mempool.transactions.is_empty());
+
+        let transaction = test_manager.generate_transaction(1000, 100_000).await;
+        mempool.add_transaction(transaction.clone()).await.unwrap();
+        // adding a transaction again is not an error
+        mempool.add_transaction(transaction).await.unwrap();
+        assert_eq!(mempool.transactions.len(), 1);
+
+        // the transactions sweeping our dust are our own, and skip the policies
+        mempool.set_dust_consolidation_threshold(1);
+        assert_eq!(mempool.consolidate_dust().await, 1);
+        assert_eq!(mempool.transactions.len(), 2);
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn mempool_spam_throttling_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        test_manager.generate_blockchain(1, [0; 32]).await;
+        let publickey = wallet_lock.read().await.get_publickey();
+
+        let transaction = test_manager.generate_transaction(1000, 1000).await;
+        let other_transaction = test_manager.generate_transaction(1000, 1000).await;
+        let transaction_size = transaction.get_serialized_size() as u64;
+
+        let mut mempool = Mempool::new(wallet_lock.clone());
+        mempool.set_address_byte_budget(transaction_size);
+        mempool.set_throttle_action(ThrottleAction::Reject);
+        mempool.add_transaction(transaction.clone()).await.unwrap();
+        assert_eq!(mempool.get_address_bytes(&publickey), transaction_size);
+        assert!(mempool
+            .add_transaction(other_transaction.clone())
+            .await
+            .is_err());
+
+        // once its transactions leave the mempool the address is within its budget again
+        mempool.delete_transactions(&vec![transaction]);
+        assert_eq!(mempool.get_address_bytes(&publickey), 0);
+        mempool.add_transaction(other_transaction).await.unwrap();
+
+        //
+        // deprioritized transactions go behind those of other addresses
+        //
+        let mut transactions = vec![];
+        for sender in [[1; 33], [1; 33], [2; 33]] {
+            let mut transaction = Transaction::new();
+            let mut input = Slip::new();
+            input.set_publickey(sender);
+            transaction.add_input(input);
+            transactions.push(transaction);
+        }
+        let budget = transactions[0].get_serialized_size() as u64;
+        let mut prioritized_transactions = transactions.clone();
+        deprioritize_over_budget(&mut prioritized_transactions, budget);
+        assert_eq!(
+            prioritized_transactions,
+            vec![
+                transactions[0].clone(),
+                transactions[2].clone(),
+                transactions[1].clone()
+            ]
+        );
+    }
+
     #[tokio::test]
     #[serial_test::serial]
     async fn mempool_bundle_blocks_test() {
@@ -443,4 +1896,126 @@ mod tests {
         // check chain consistence
         test_manager.check_blockchain().await;
     }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn mempool_preassembled_block_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        let mempool_lock = test_manager.mempool_lock.clone();
+        test_manager
+            .add_block(create_timestamp(), 3, 0, false, vec![])
+            .await;
+        let timestamp = blockchain_lock
+            .read()
+            .await
+            .get_latest_block()
+            .unwrap()
+            .get_timestamp()
+            + 2 * HEARTBEAT;
+
+        let transaction = test_manager.generate_transaction(1000, 1000).await;
+        mempool_lock
+            .write()
+            .await
+            .add_transaction(transaction)
+            .await
+            .unwrap();
+        preassemble_block(mempool_lock.clone(), blockchain_lock.clone()).await;
+        assert!(mempool_lock.read().await.preassembled_block.is_some());
+
+        // the restamped block is the block bundled at the time it is produced
+        let transactions = mempool_lock.read().await.transactions.clone();
+        let bundled_block = Mempool::bundle_block(
+            transactions,
+            wallet_lock.clone(),
+            blockchain_lock.clone(),
+            timestamp,
+            0,
+        )
+        .await
+        .unwrap();
+        let block = try_bundle_block(mempool_lock.clone(), blockchain_lock.clone(), timestamp)
+            .await
+            .unwrap();
+        assert!(mempool_lock.read().await.preassembled_block.is_none());
+        assert_eq!(block.get_timestamp(), timestamp);
+        assert_eq!(block.get_hash(), bundled_block.get_hash());
+        mempool_lock.write().await.add_block(block);
+        Mempool::send_blocks_to_blockchain(mempool_lock.clone(), blockchain_lock.clone()).await;
+        assert_eq!(blockchain_lock.read().await.get_latest_block_id(), 2);
+
+        // a block preassembled before a transaction arrived is bundled again
+        let transaction = test_manager.generate_transaction(1000, 1000).await;
+        mempool_lock
+            .write()
+            .await
+            .add_transaction(transaction)
+            .await
+            .unwrap();
+        preassemble_block(mempool_lock.clone(), blockchain_lock.clone()).await;
+        let transaction = test_manager.generate_transaction(1000, 1000).await;
+        mempool_lock
+            .write()
+            .await
+            .add_transaction(transaction)
+            .await
+            .unwrap();
+        let block = try_bundle_block(
+            mempool_lock.clone(),
+            blockchain_lock.clone(),
+            timestamp + 2 * HEARTBEAT,
+        )
+        .await
+        .unwrap();
+        assert!(mempool_lock.read().await.transactions.is_empty());
+        assert_eq!(
+            block
+                .get_transactions()
+                .iter()
+                .filter(|transaction| transaction.get_transaction_type()
+                    == crate::transaction::TransactionType::Normal)
+                .count(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn mempool_send_blocks_to_blockchain_task_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        let mempool_lock = test_manager.mempool_lock.clone();
+        test_manager
+            .add_block(create_timestamp(), 3, 0, false, vec![])
+            .await;
+
+        let (broadcast_channel_sender, broadcast_channel_receiver) = broadcast::channel(32);
+        let (blockchain_channel_sender, blockchain_channel_receiver) = mpsc::channel(4);
+        tokio::spawn(crate::blockchain::run(
+            blockchain_lock.clone(),
+            blockchain_channel_receiver,
+            broadcast_channel_sender,
+            broadcast_channel_receiver,
+        ));
+        mempool_lock
+            .write()
+            .await
+            .set_blockchain_channel_sender(blockchain_channel_sender);
+
+        let block = test_manager.generate_block_via_mempool().await;
+        let block_hash = block.get_hash();
+        mempool_lock.write().await.add_block(block);
+        Mempool::send_blocks_to_blockchain(mempool_lock.clone(), blockchain_lock.clone()).await;
+
+        //
+        // the block is added by the time the blockchain task replies
+        //
+        let blockchain = blockchain_lock.read().await;
+        assert_eq!(blockchain.get_latest_block_id(), 2);
+        assert_eq!(blockchain.get_latest_block_hash(), block_hash);
+        assert_eq!(mempool_lock.read().await.latest_block_id, 2);
+    }
 }
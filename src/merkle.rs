@@ -1,8 +1,8 @@
-use crate::crypto::{hash, SaitoHash};
+use crate::crypto::{hash_with, HashAlgorithm, SaitoHash};
 
 //
-// MerkleTreeLayer is a short implementation that uses the default
-// Saito hashing algorithm. It is also written to take advantage of
+// MerkleTreeLayer is a short implementation that hashes with the
+// algorithm of the block. It is also written to take advantage of
 // rayon parallelization.
 //
 #[derive(PartialEq, Debug, Clone)]
@@ -22,11 +22,11 @@ impl MerkleTreeLayer {
         }
     }
 
-    pub fn hash(&mut self) -> bool {
+    pub fn hash(&mut self, algorithm: HashAlgorithm) -> bool {
         let mut vbytes: Vec<u8> = vec![];
         vbytes.extend(&self.left);
         vbytes.extend(&self.right);
-        self.hash = hash(&vbytes);
+        self.hash = hash_with(algorithm, &vbytes);
         true
     }
 
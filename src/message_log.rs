@@ -0,0 +1,460 @@
+use crate::block::Block;
+use crate::blockchain::Blockchain;
+use crate::crypto::{SaitoHash, SaitoPublicKey};
+use crate::mempool::Mempool;
+use crate::networking::api_message::{APIMessage, MessageName, PROTOCOL_VERSION};
+use crate::networking::message_types::block_range_chunk::BlockRangeChunk;
+use crate::transaction::Transaction;
+use log::{info, warn};
+use std::convert::TryInto;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, Read, Write};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+//
+// Bugs in consensus often only show with the exact blocks and transactions
+// a node was sent, in the order it was sent them. A node can record the
+// consensus messages its peers send it to a message log, which is replayed
+// into a new node to reproduce what happened on the recording node. Blocks
+// reach us as responses to our requests, so the responses to REQBLOCK and
+// REQRANGE are recorded along with the commands peers send us.
+//
+
+/// Message logs start with these bytes and the protocol version of the recorded messages.
+pub const MESSAGE_LOG_MAGIC: [u8; 4] = *b"SAIM";
+pub const MESSAGE_LOG_HEADER_SIZE: usize = 8;
+/// Every record starts with the timestamp, the connection id, the command and the length of
+/// the message, a u32.
+pub const MESSAGE_RECORD_HEADER_SIZE: usize = 52;
+
+/// The commands from peers which are recorded.
+pub const RECORDED_COMMANDS: [MessageName; 5] = [
+    MessageName::HandshakeInit,
+    MessageName::HandshakeComplete,
+    MessageName::SendBlockchain,
+    MessageName::SendBlockHeader,
+    MessageName::SendTransaction,
+];
+/// Our requests whose responses are recorded.
+pub const RECORDED_RESPONSES: [MessageName; 2] =
+    [MessageName::RequestBlock, MessageName::RequestBlockRange];
+
+/// A message a peer sent us. The command is the name of the message, or the name of our
+/// request if the message is a response to it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageRecord {
+    timestamp: u64,
+    connection_id: SaitoHash,
+    command: MessageName,
+    api_message: APIMessage,
+}
+
+impl MessageRecord {
+    pub fn new(
+        timestamp: u64,
+        connection_id: SaitoHash,
+        command: MessageName,
+        api_message: APIMessage,
+    ) -> Self {
+        MessageRecord {
+            timestamp,
+            connection_id,
+            command,
+            api_message,
+        }
+    }
+
+    pub fn get_timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn get_connection_id(&self) -> SaitoHash {
+        self.connection_id
+    }
+
+    pub fn get_command(&self) -> MessageName {
+        self.command
+    }
+
+    pub fn get_api_message(&self) -> &APIMessage {
+        &self.api_message
+    }
+
+    /// Whether the message is a response to one of our requests.
+    pub fn is_response(&self) -> bool {
+        matches!(
+            self.api_message.get_message_name(),
+            Some(MessageName::Result) | Some(MessageName::Error)
+        )
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let serialized_api_message = self.api_message.serialize();
+        let mut vbytes: Vec<u8> = vec![];
+        vbytes.extend(&self.timestamp.to_be_bytes());
+        vbytes.extend(&self.connection_id);
+        vbytes.extend(self.command.as_bytes());
+        vbytes.extend(&(serialized_api_message.len() as u32).to_be_bytes());
+        vbytes.extend(serialized_api_message);
+        vbytes
+    }
+}
+
+/// Whether a message from a peer is recorded. command is the name of the message, or the name
+/// of our request if the message is a response to it.
+pub fn is_recorded(command: MessageName, api_message: &APIMessage) -> bool {
+    match api_message.get_message_name() {
+        Some(MessageName::Result) => RECORDED_RESPONSES.contains(&command),
+        Some(MessageName::Error) => false,
+        _ => RECORDED_COMMANDS.contains(&command),
+    }
+}
+
+/// Appends records to a message log file.
+#[derive(Debug)]
+pub struct MessageLog {
+    file: File,
+}
+
+impl MessageLog {
+    /// Opens the message log to append to it, creating it if it does not exist. Fails if the
+    /// file is not a message log of our protocol version.
+    pub fn open(filename: &str) -> crate::Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .create(true)
+            .append(true)
+            .open(filename)?;
+        if file.metadata()?.len() == 0 {
+            let mut header = MESSAGE_LOG_MAGIC.to_vec();
+            header.extend(&PROTOCOL_VERSION.to_be_bytes());
+            file.write_all(&header)?;
+        } else {
+            read_header(&mut file, filename)?;
+        }
+        Ok(MessageLog { file })
+    }
+
+    /// Writes the record with a single write, so that a crash leaves at most the last record
+    /// cut short.
+    pub fn record(&mut self, message_record: &MessageRecord) -> io::Result<()> {
+        self.file.write_all(&message_record.serialize())
+    }
+}
+
+/// Reads the records of a message log. A record cut short at the end of the file, as a crash
+/// of the recording node leaves it, ends the log.
+pub fn read_message_log(filename: &str) -> crate::Result<Vec<MessageRecord>> {
+    let mut reader = BufReader::new(File::open(filename)?);
+    read_header(&mut reader, filename)?;
+    let mut message_records = vec![];
+    loop {
+        let mut record_header = [0; MESSAGE_RECORD_HEADER_SIZE];
+        match reader.read_exact(&mut record_header) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(err.into()),
+        }
+        let command = MessageName::from_bytes(&record_header[40..48].try_into().unwrap())
+            .ok_or_else(|| {
+                format!(
+                    "record {} of {} has an unknown command",
+                    message_records.len() + 1,
+                    filename
+                )
+            })?;
+        let length = u32::from_be_bytes(record_header[48..52].try_into().unwrap()) as usize;
+        let mut serialized_api_message = vec![];
+        (&mut reader)
+            .take(length as u64)
+            .read_to_end(&mut serialized_api_message)?;
+        if serialized_api_message.len() != length || length < 12 {
+            warn!(
+                "record {} of {} is cut short, ending the log there",
+                message_records.len() + 1,
+                filename
+            );
+            break;
+        }
+        message_records.push(MessageRecord::new(
+            u64::from_be_bytes(record_header[0..8].try_into().unwrap()),
+            record_header[8..40].try_into().unwrap(),
+            command,
+            APIMessage::deserialize(&serialized_api_message),
+        ));
+    }
+    Ok(message_records)
+}
+
+fn read_header(reader: &mut impl Read, filename: &str) -> crate::Result<()> {
+    let mut header = [0; MESSAGE_LOG_HEADER_SIZE];
+    reader.read_exact(&mut header)?;
+    if header[0..4] != MESSAGE_LOG_MAGIC {
+        return Err(format!("{} is not a message log", filename).into());
+    }
+    let version = u32::from_be_bytes(header[4..8].try_into().unwrap());
+    if version != PROTOCOL_VERSION {
+        return Err(format!(
+            "{} has messages of protocol version {}, expected {}",
+            filename, version, PROTOCOL_VERSION
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// What happened while replaying a message log.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MessageReplay {
+    pub messages_replayed: u64,
+    pub handshakes: u64,
+    pub blocks_added: u64,
+    pub transactions_added: u64,
+    pub transactions_rejected: u64,
+}
+
+impl MessageReplay {
+    pub fn new() -> Self {
+        MessageReplay::default()
+    }
+
+    /// Feeds the records into the mempool and blockchain, which should be new, in the order
+    /// they were recorded, as the recording node handled them. Every record is handled to the
+    /// end before the next, so a replay does the same thing every time. Announcements of blocks
+    /// are only counted, as the blocks fetched because of them were recorded in the responses.
+    pub async fn replay(
+        mempool_lock: Arc<RwLock<Mempool>>,
+        blockchain_lock: Arc<RwLock<Blockchain>>,
+        publickey: SaitoPublicKey,
+        message_records: Vec<MessageRecord>,
+    ) -> MessageReplay {
+        let mut message_replay = MessageReplay::new();
+        for message_record in message_records {
+            message_replay
+                .replay_record(
+                    mempool_lock.clone(),
+                    blockchain_lock.clone(),
+                    publickey,
+                    message_record,
+                )
+                .await;
+        }
+        info!(
+            "replayed {} messages, the longest chain is at block {}",
+            message_replay.messages_replayed,
+            blockchain_lock.read().await.get_latest_block_id()
+        );
+        message_replay
+    }
+
+    async fn replay_record(
+        &mut self,
+        mempool_lock: Arc<RwLock<Mempool>>,
+        blockchain_lock: Arc<RwLock<Blockchain>>,
+        publickey: SaitoPublicKey,
+        message_record: MessageRecord,
+    ) {
+        self.messages_replayed += 1;
+        let message_data = message_record.get_api_message().get_message_data();
+        let serialized_blocks = match message_record.get_command() {
+            MessageName::HandshakeComplete => {
+                self.handshakes += 1;
+                return;
+            }
+            MessageName::SendTransaction => {
+                self.replay_transaction(mempool_lock, blockchain_lock, publickey, message_data)
+                    .await;
+                return;
+            }
+            MessageName::RequestBlock => vec![message_data.clone()],
+            MessageName::RequestBlockRange => {
+                match BlockRangeChunk::deserialize(message_data)
+                    .map(|block_range_chunk| block_range_chunk.decompress_blocks())
+                {
+                    Some(Ok(serialized_blocks)) => serialized_blocks,
+                    _ => {
+                        warn!("not replaying a bad chunk of blocks");
+                        return;
+                    }
+                }
+            }
+            _ => return,
+        };
+        {
+            let mut mempool = mempool_lock.write().await;
+            for bytes in serialized_blocks {
+                let mut block = Block::deserialize_for_net(&bytes);
+                block.set_source_connection_id(message_record.get_connection_id());
+                mempool.add_block(block);
+                self.blocks_added += 1;
+            }
+        }
+        Mempool::send_blocks_to_blockchain(mempool_lock, blockchain_lock).await;
+    }
+
+    async fn replay_transaction(
+        &mut self,
+        mempool_lock: Arc<RwLock<Mempool>>,
+        blockchain_lock: Arc<RwLock<Blockchain>>,
+        publickey: SaitoPublicKey,
+        message_data: &[u8],
+    ) {
+        let mut transaction = match Transaction::try_deserialize_from_net(message_data) {
+            Some(transaction) => transaction,
+            None => {
+                self.transactions_rejected += 1;
+                return;
+            }
+        };
+        transaction.generate_metadata(publickey);
+        let blockchain = blockchain_lock.read().await;
+        let mut mempool = mempool_lock.write().await;
+        if mempool.transaction_exists(transaction.get_hash_for_signature()) {
+            return;
+        }
+        if transaction.validate(&blockchain.utxoset, &blockchain.staking)
+            && mempool.add_transaction(transaction).await.is_ok()
+        {
+            self.transactions_added += 1;
+        } else {
+            self.transactions_rejected += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::BlockType;
+    use crate::test_utilities::test_manager::TestManager;
+    use crate::wallet::Wallet;
+    use std::fs;
+
+    const MESSAGE_LOG_TEST_FILE_PATH: &str = "./data/test/messages.log";
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn message_log_replay_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        test_manager.generate_blockchain(3, [0; 32]).await;
+
+        fs::create_dir_all("./data/test/").unwrap();
+        let _ = fs::remove_file(MESSAGE_LOG_TEST_FILE_PATH);
+        let mut message_log = MessageLog::open(MESSAGE_LOG_TEST_FILE_PATH).unwrap();
+        {
+            let blockchain = blockchain_lock.read().await;
+            for block_id in 1..=blockchain.get_latest_block_id() {
+                let block_hash = blockchain
+                    .blockring
+                    .get_longest_chain_block_hash_by_block_id(block_id);
+                let block = blockchain.get_block_sync(&block_hash).unwrap();
+                // the block was announced, and then sent to us when we asked for it
+                message_log
+                    .record(&MessageRecord::new(
+                        block_id,
+                        [1; 32],
+                        MessageName::SendBlockHeader,
+                        APIMessage::new(MessageName::SendBlockHeader, 1, block_hash.to_vec()),
+                    ))
+                    .unwrap();
+                message_log
+                    .record(&MessageRecord::new(
+                        block_id,
+                        [1; 32],
+                        MessageName::RequestBlock,
+                        APIMessage::new(
+                            MessageName::Result,
+                            2,
+                            block.serialize_for_net(BlockType::Full),
+                        ),
+                    ))
+                    .unwrap();
+            }
+        }
+        let transaction = test_manager.generate_transaction(1000, 0).await;
+        message_log
+            .record(&MessageRecord::new(
+                4,
+                [1; 32],
+                MessageName::SendTransaction,
+                APIMessage::new(
+                    MessageName::SendTransaction,
+                    3,
+                    transaction.serialize_for_net(),
+                ),
+            ))
+            .unwrap();
+        drop(message_log);
+
+        // a log is appended to when the node starts again
+        let mut message_log = MessageLog::open(MESSAGE_LOG_TEST_FILE_PATH).unwrap();
+        message_log
+            .record(&MessageRecord::new(
+                5,
+                [2; 32],
+                MessageName::SendTransaction,
+                APIMessage::new(MessageName::SendTransaction, 4, vec![0; 10]),
+            ))
+            .unwrap();
+        drop(message_log);
+
+        let message_records = read_message_log(MESSAGE_LOG_TEST_FILE_PATH).unwrap();
+        assert_eq!(message_records.len(), 8);
+        assert!(message_records[1].is_response());
+        assert_eq!(message_records[7].get_connection_id(), [2; 32]);
+
+        let replay = replay_into_new_node(message_records.clone()).await;
+        assert_eq!(replay.0.messages_replayed, 8);
+        assert_eq!(replay.0.blocks_added, 3);
+        assert_eq!(replay.0.transactions_added, 1);
+        assert_eq!(replay.0.transactions_rejected, 1);
+        assert_eq!(
+            replay.1,
+            blockchain_lock.read().await.get_latest_block_hash()
+        );
+        // and does the same every time
+        assert_eq!(replay_into_new_node(message_records).await, replay);
+
+        // a record cut short by a crash ends the log
+        let bytes = fs::read(MESSAGE_LOG_TEST_FILE_PATH).unwrap();
+        fs::write(MESSAGE_LOG_TEST_FILE_PATH, &bytes[..bytes.len() - 1]).unwrap();
+        assert_eq!(
+            read_message_log(MESSAGE_LOG_TEST_FILE_PATH).unwrap().len(),
+            7
+        );
+
+        fs::write(MESSAGE_LOG_TEST_FILE_PATH, b"SAIC").unwrap();
+        assert!(MessageLog::open(MESSAGE_LOG_TEST_FILE_PATH).is_err());
+        assert!(read_message_log(MESSAGE_LOG_TEST_FILE_PATH).is_err());
+        fs::remove_file(MESSAGE_LOG_TEST_FILE_PATH).unwrap();
+
+        // responses carrying errors, and requests from peers, are not recorded
+        let error = APIMessage::new_from_string(MessageName::Error, 1, "Unknown Block Hash");
+        assert!(!is_recorded(MessageName::RequestBlock, &error));
+        let request = APIMessage::new(MessageName::RequestBlock, 1, vec![]);
+        assert!(!is_recorded(MessageName::RequestBlock, &request));
+        let transaction = APIMessage::new(MessageName::SendTransaction, 1, vec![]);
+        assert!(is_recorded(MessageName::SendTransaction, &transaction));
+    }
+
+    async fn replay_into_new_node(
+        message_records: Vec<MessageRecord>,
+    ) -> (MessageReplay, SaitoHash) {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let mempool_lock = Arc::new(RwLock::new(Mempool::new(wallet_lock.clone())));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let publickey = wallet_lock.read().await.get_publickey();
+        let message_replay = MessageReplay::replay(
+            mempool_lock,
+            blockchain_lock.clone(),
+            publickey,
+            message_records,
+        )
+        .await;
+        let latest_block_hash = blockchain_lock.read().await.get_latest_block_hash();
+        (message_replay, latest_block_hash)
+    }
+}
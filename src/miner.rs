@@ -1,7 +1,8 @@
 use crate::{
-    consensus::SaitoMessage,
-    crypto::{generate_random_bytes, hash, SaitoHash, SaitoPublicKey},
+    consensus::{recv_message, send_message, SaitoMessage, Subsystem},
+    crypto::{SaitoHash, SaitoPublicKey},
     golden_ticket::GoldenTicket,
+    random::{OsRandom, RandomSource},
     wallet::Wallet,
 };
 use std::{sync::Arc, thread::sleep, time::Duration};
@@ -18,8 +19,11 @@ pub struct Miner {
     pub is_active: bool,
     pub target: SaitoHash,
     pub difficulty: u64,
+    // the solutions tried every time the miner is asked to mine
+    intensity: u64,
     pub wallet_lock: Arc<RwLock<Wallet>>,
     broadcast_channel_sender: Option<broadcast::Sender<SaitoMessage>>,
+    random_source: Box<dyn RandomSource>,
 }
 
 impl Miner {
@@ -28,41 +32,60 @@ impl Miner {
             is_active: false,
             target: [0; 32],
             difficulty: 0,
+            intensity: 1,
             wallet_lock,
             broadcast_channel_sender: None,
+            random_source: Box::new(OsRandom),
         }
     }
 
+    /// Replaces the randomness of the operating system, e.g. with a random::HashChain so
+    /// that tests find the same golden tickets, and so the same payouts, every time.
+    pub fn set_random_source(&mut self, random_source: Box<dyn RandomSource>) {
+        self.random_source = random_source;
+    }
+
     pub fn set_broadcast_channel_sender(&mut self, bcs: broadcast::Sender<SaitoMessage>) {
         self.broadcast_channel_sender = Some(bcs);
     }
 
-    pub async fn mine(&mut self) {
-        if self.is_active {
-            let publickey: SaitoPublicKey;
+    /// Sets how many solutions mine() tries, at least 1.
+    pub fn set_intensity(&mut self, intensity: u64) {
+        self.intensity = intensity.max(1);
+    }
 
-            {
-                let wallet = self.wallet_lock.read().await;
-                publickey = wallet.get_publickey();
-            }
+    pub fn get_intensity(&self) -> u64 {
+        self.intensity
+    }
 
-            let random_bytes = hash(&generate_random_bytes(32));
+    /// Tries as many solutions as the intensity, stopping at the first golden ticket.
+    pub async fn mine(&mut self) {
+        if !self.is_active {
+            return;
+        }
+        let publickey: SaitoPublicKey;
+        {
+            let wallet = self.wallet_lock.read().await;
+            publickey = wallet.get_publickey();
+        }
+        for _ in 0..self.intensity {
+            let random_bytes = self.random_source.next_random_number();
             let solution = GoldenTicket::generate_solution(self.target, random_bytes, publickey);
             if GoldenTicket::is_valid_solution(solution, self.difficulty) {
                 {
                     let gt = GoldenTicket::new(self.target, random_bytes, publickey);
 
-                    if !self.broadcast_channel_sender.is_none() {
-                        self.broadcast_channel_sender
-                            .as_ref()
-                            .unwrap()
-                            .send(SaitoMessage::MinerNewGoldenTicket { ticket: gt })
-                            .expect("error: MinerNewGoldenTicket message failed to send");
+                    if let Some(broadcast_channel_sender) = self.broadcast_channel_sender.as_ref() {
+                        send_message(
+                            broadcast_channel_sender,
+                            SaitoMessage::MinerNewGoldenTicket { ticket: gt },
+                        );
                     }
                 }
 
                 // stop mining
                 self.set_is_active(false);
+                break;
             }
         }
     }
@@ -75,12 +98,12 @@ impl Miner {
     ) -> GoldenTicket {
         let wallet = self.wallet_lock.read().await;
         let publickey = wallet.get_publickey();
-        let mut random_bytes = hash(&generate_random_bytes(32));
+        let mut random_bytes = self.random_source.next_random_number();
 
         let mut solution = GoldenTicket::generate_solution(block_hash, random_bytes, publickey);
 
         while !GoldenTicket::is_valid_solution(solution, block_difficulty) {
-            random_bytes = hash(&generate_random_bytes(32));
+            random_bytes = self.random_source.next_random_number();
             solution = GoldenTicket::generate_solution(block_hash, random_bytes, publickey);
         }
 
@@ -148,7 +171,7 @@ pub async fn run(
             //
             // Saito Channel Messages
             //
-            Ok(message) = broadcast_channel_receiver.recv() => {
+            Some(message) = recv_message(&mut broadcast_channel_receiver, Subsystem::Miner) => {
                 match message {
                     SaitoMessage::BlockchainNewLongestChainBlock { hash : block_hash, difficulty } => {
                         let mut miner = miner_lock.write().await;
@@ -164,4 +187,30 @@ pub async fn run(
     }
 }
 
-mod test {}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random::HashChain;
+
+    #[tokio::test]
+    async fn miner_random_source_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let block_hash = [3; 32];
+
+        let mut golden_tickets = vec![];
+        for _ in 0..2 {
+            let mut miner = Miner::new(wallet_lock.clone());
+            miner.set_random_source(Box::new(HashChain::new([1; 32])));
+            golden_tickets.push(
+                miner
+                    .mine_on_block_until_golden_ticket_found(block_hash, 2)
+                    .await,
+            );
+        }
+        assert_eq!(
+            golden_tickets[0].serialize_for_transaction(),
+            golden_tickets[1].serialize_for_transaction()
+        );
+        assert_eq!(golden_tickets[0].get_target(), block_hash);
+    }
+}
@@ -1,21 +1,43 @@
 use crate::blockchain::Blockchain;
-use crate::consensus::SaitoMessage;
-use crate::crypto::{hash, sign_blob, SaitoHash, SaitoPrivateKey, SaitoPublicKey};
+use crate::consensus::{recv_message, SaitoMessage, Subsystem};
+use crate::crypto::{
+    generate_random_bytes, hash, sign_blob, SaitoHash, SaitoPrivateKey, SaitoPublicKey,
+};
 use crate::mempool::Mempool;
+use crate::message_log::MessageLog;
 use crate::networking::filters::{
-    get_block_route_filter, post_transaction_route_filter, ws_upgrade_route_filter,
+    get_balance_route_filter, get_block_json_route_filter, get_block_payouts_route_filter,
+    get_block_route_filter, get_block_template_route_filter, get_blocks_route_filter,
+    get_broadcast_metrics_route_filter, get_chain_stats_route_filter,
+    get_explorer_block_route_filter, get_health_route_filter, get_mempool_info_route_filter,
+    get_mempool_transaction_route_filter, get_node_metadata_by_publickey_route_filter,
+    get_node_metadata_route_filter, get_peer_bandwidth_route_filter, get_peer_filter_route_filter,
+    get_peer_stats_route_filter, get_raw_mempool_route_filter, get_reorgs_route_filter,
+    get_search_transactions_route_filter, get_sync_status_route_filter,
+    get_unspent_slips_route_filter, get_utxoset_info_route_filter, post_rescan_route_filter,
+    post_submit_block_route_filter, post_transaction_batch_route_filter,
+    post_transaction_route_filter, put_peer_filter_route_filter, ws_upgrade_route_filter,
 };
 use crate::peer::{
-    socket_handshake_verify, InboundPeersDB, OutboundPeer, OutboundPeersDB, PeersDB,
-    RequestResponses, RequestWakers, SaitoPeer,
+    disconnect_peers_not_allowed, get_local_services, get_peer_bandwidth, is_peer_allowed,
+    run_peer_connection, socket_handshake_verify, update_peer_bandwidth, PeerConnectionsDB,
+    PeerTip, PeersBandwidthDB, PeersDB, RequestResponses, RequestWakers, SaitoPeer,
 };
+use crate::random::OsRandom;
+use crate::sync::TipComparison;
+use crate::time::{create_timestamp, estimate_clock_offset};
 use crate::transaction::Transaction;
 use crate::wallet::Wallet;
 use secp256k1::PublicKey;
-use std::{sync::Arc, time::Duration};
+use std::{
+    cmp::Reverse,
+    net::{IpAddr, Ipv4Addr},
+    sync::Arc,
+    time::Duration,
+};
 use tokio::sync::{broadcast, mpsc, RwLock};
 use tokio::time::sleep;
-use tokio_tungstenite::connect_async;
+use tokio_tungstenite::{client_async, connect_async, MaybeTlsStream};
 use tracing::{error, info, warn};
 
 use futures::StreamExt;
@@ -24,25 +46,106 @@ use warp::{Filter, Rejection};
 
 use crate::networking::signals::signal_for_shutdown;
 
-use crate::configuration::{PeerSetting, Settings};
-use crate::networking::api_message::APIMessage;
+use crate::configuration::{
+    NodeIdentitySettings, PeerFilterSettings, PeerSetting, Settings, Socks5ProxySettings,
+};
+use crate::networking::api_message::MessageName;
 use crate::networking::message_types::{
+    handshake_challenge::{HandshakeNonce, IssuedChallenges},
     request_blockchain_message::RequestBlockchainMessage,
     send_block_head_message::SendBlockHeadMessage,
 };
-use crate::util::format_url_string;
+use crate::networking::node_metadata::{
+    NodeMetadata, NodeMetadataDB, NODE_METADATA_REPUBLISH_INTERVAL,
+};
+use crate::networking::peer_connection::PeerTransport;
+use crate::networking::peer_filter::PeerFilter;
+use crate::networking::peer_host::{ip_address_to_bytes, PeerHost};
+use crate::networking::peer_role::{get_role_preference, order_peers_for_role, PeerRole};
+use crate::networking::peer_services::PeerServices;
+use crate::networking::peer_stats::PeerStatsDB;
+use crate::networking::quic::{run_quic_server, QuicPeerConnection};
+use crate::networking::recently_seen::{RecentlySeen, RECENT_BLOCK_HASHES_CAPACITY};
+use crate::networking::relay_policy::{
+    count_recent_block_producers, generate_diffusion_delays, order_peers_for_relay, PeerScore,
+};
+use crate::networking::socks5::connect_through_socks5_proxy;
 
 pub type Result<T> = std::result::Result<T, Rejection>;
 
-pub const CHALLENGE_SIZE: usize = 82;
+pub const CHALLENGE_SIZE: usize = 170;
+/// How long, in milliseconds, a handshake challenge can be signed and sent back for.
 pub const CHALLENGE_EXPIRATION_TIME: u64 = 60000;
 
-lazy_static::lazy_static! {
-    pub static ref PEERS_DB_GLOBAL: Arc<tokio::sync::RwLock<PeersDB>> = Arc::new(tokio::sync::RwLock::new(PeersDB::new()));
-    pub static ref PEERS_REQUEST_RESPONSES_GLOBAL: Arc<std::sync::RwLock<RequestResponses>> = Arc::new(std::sync::RwLock::new(RequestResponses::new()));
-    pub static ref PEERS_REQUEST_WAKERS_GLOBAL: Arc<std::sync::RwLock<RequestWakers>> = Arc::new(std::sync::RwLock::new(RequestWakers::new()));
-    pub static ref INBOUND_PEER_CONNECTIONS_GLOBAL: Arc<tokio::sync::RwLock<InboundPeersDB>> = Arc::new(tokio::sync::RwLock::new(InboundPeersDB::new()));
-    pub static ref OUTBOUND_PEER_CONNECTIONS_GLOBAL: Arc<tokio::sync::RwLock<OutboundPeersDB>> = Arc::new(tokio::sync::RwLock::new(OutboundPeersDB::new()));
+//
+// Blocks are announced once, when they are added, so a peer which missed
+// the announcement, e.g. while its connection was down, only learns of our
+// tip with the next block. While no new block arrives we announce our
+// latest block again every this many seconds, so that such peers converge
+// on quiet networks too.
+//
+pub const TIP_ANNOUNCEMENT_INTERVAL: u64 = 60;
+
+//
+// The latency and clock offset of a peer are first measured in the
+// handshake, but routes and clocks drift over a long-lived connection. We
+// ping every peer again every this many seconds, which keeps the relay
+// order and the network-adjusted time up to date.
+//
+pub const PING_INTERVAL: u64 = 30;
+
+//
+// The state of our peers belongs to the network of a node, rather than to
+// the process, so that several nodes can run in one process, e.g. in the
+// integration tests. The network creates it, and passes a clone to every
+// peer, to the handlers of our routes and to the servers it runs. Clones
+// share the state.
+//
+
+/// The state of the peers of a node.
+#[derive(Clone)]
+pub struct NetworkContext {
+    pub peers_db: Arc<tokio::sync::RwLock<PeersDB>>,
+    pub request_responses: Arc<std::sync::RwLock<RequestResponses>>,
+    pub request_wakers: Arc<std::sync::RwLock<RequestWakers>>,
+    pub peer_connections: Arc<tokio::sync::RwLock<PeerConnectionsDB>>,
+    pub peers_bandwidth: Arc<std::sync::RwLock<PeersBandwidthDB>>,
+    pub peer_filter: Arc<std::sync::RwLock<PeerFilter>>,
+    /// the services we serve our peers
+    pub local_peer_services: Arc<std::sync::RwLock<PeerServices>>,
+    /// the blocks we announced or requested lately
+    pub recent_block_hashes: Arc<std::sync::RwLock<RecentlySeen>>,
+    pub node_metadata: Arc<std::sync::RwLock<NodeMetadataDB>>,
+    pub message_log: Arc<std::sync::RwLock<Option<MessageLog>>>,
+    pub issued_challenges: Arc<std::sync::RwLock<IssuedChallenges>>,
+    pub peer_stats: Arc<std::sync::RwLock<PeerStatsDB>>,
+}
+
+impl NetworkContext {
+    pub fn new() -> Self {
+        NetworkContext {
+            peers_db: Arc::new(tokio::sync::RwLock::new(PeersDB::new())),
+            request_responses: Arc::new(std::sync::RwLock::new(RequestResponses::new())),
+            request_wakers: Arc::new(std::sync::RwLock::new(RequestWakers::new())),
+            peer_connections: Arc::new(tokio::sync::RwLock::new(PeerConnectionsDB::new())),
+            peers_bandwidth: Arc::new(std::sync::RwLock::new(PeersBandwidthDB::new())),
+            peer_filter: Arc::new(std::sync::RwLock::new(PeerFilter::new())),
+            local_peer_services: Arc::new(std::sync::RwLock::new(PeerServices::default())),
+            recent_block_hashes: Arc::new(std::sync::RwLock::new(RecentlySeen::new(
+                RECENT_BLOCK_HASHES_CAPACITY,
+            ))),
+            node_metadata: Arc::new(std::sync::RwLock::new(NodeMetadataDB::new())),
+            message_log: Arc::new(std::sync::RwLock::new(None)),
+            issued_challenges: Arc::new(std::sync::RwLock::new(IssuedChallenges::new())),
+            peer_stats: Arc::new(std::sync::RwLock::new(PeerStatsDB::default())),
+        }
+    }
+}
+
+impl Default for NetworkContext {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 //
@@ -56,6 +159,9 @@ lazy_static::lazy_static! {
 #[derive(Clone, Debug)]
 pub enum NetworkMessage {
     LocalNetworkMonitoring,
+    TipAnnouncement,
+    NodeMetadataRepublish,
+    PeerPing,
 }
 
 pub struct Network {
@@ -63,9 +169,22 @@ pub struct Network {
     mempool_lock: Arc<RwLock<Mempool>>,
     wallet_lock: Arc<RwLock<Wallet>>,
     broadcast_channel_sender: broadcast::Sender<SaitoMessage>,
-    host: [u8; 4],
+    host: IpAddr,
     port: u16,
+    quic_port: Option<u16>,
+    grpc_port: Option<u16>,
+    admin_port: Option<u16>,
     peer_conf: Option<Vec<PeerSetting>>,
+    peer_filter_conf: PeerFilterSettings,
+    services_conf: PeerServices,
+    tip_announcement_interval: u64,
+    ping_interval: u64,
+    identity_conf: NodeIdentitySettings,
+    min_fee_rate: u64,
+    socks5_proxy: Option<Socks5ProxySettings>,
+    message_log_conf: Option<String>,
+    peer_stats_conf: Option<String>,
+    network_context: NetworkContext,
 }
 
 impl Network {
@@ -80,7 +199,20 @@ impl Network {
         Network {
             host: configuration.network.host,
             port: configuration.network.port,
+            quic_port: configuration.network.quic_port,
+            grpc_port: configuration.network.grpc_port,
+            admin_port: configuration.network.admin_port,
             peer_conf: configuration.network.peers,
+            peer_filter_conf: configuration.network.peer_filter,
+            services_conf: configuration.network.services,
+            tip_announcement_interval: configuration.network.tip_announcement_interval,
+            ping_interval: configuration.network.ping_interval,
+            identity_conf: configuration.network.identity,
+            min_fee_rate: configuration.mempool.admission.min_fee_rate,
+            socks5_proxy: configuration.network.socks5_proxy,
+            message_log_conf: configuration.network.message_log,
+            peer_stats_conf: configuration.network.peer_stats_file,
+            network_context: NetworkContext::new(),
             blockchain_lock,
             mempool_lock,
             wallet_lock,
@@ -92,101 +224,193 @@ impl Network {
         self.broadcast_channel_sender = bcs;
     }
 
+    /// The state of our peers, shared with the clone.
+    pub fn get_network_context(&self) -> NetworkContext {
+        self.network_context.clone()
+    }
+
     /// Initialize the network class generally, including adding any peers we have
-    /// configured (peers set in the configuration/*.yml) into our PEERS_DB_GLOBAL
-    /// data structure.
+    /// configured (peers set in the configuration/*.yml) into our peers db.
     async fn initialize(&self) {
         info!("{:?}", self.peer_conf);
-        if let Some(peer_settings) = &self.peer_conf {
-            for peer_setting in peer_settings {
-                let connection_id: SaitoHash = hash(&Uuid::new_v4().as_bytes().to_vec());
-                let peer = SaitoPeer::new(
-                    connection_id,
-                    Some(peer_setting.host),
-                    Some(peer_setting.port),
-                    false,
-                    false,
-                    true,
-                    self.wallet_lock.clone(),
-                    self.mempool_lock.clone(),
-                    self.blockchain_lock.clone(),
-                    self.broadcast_channel_sender.clone(),
-                );
-                {
-                    let peers_db_global = PEERS_DB_GLOBAL.clone();
-                    peers_db_global
-                        .write()
-                        .await
-                        .insert(connection_id.clone(), peer);
+        {
+            let peer_filter = PeerFilter::from_settings(&self.peer_filter_conf)
+                .expect("Failed to parse peer_filter configuration");
+            *self.network_context.peer_filter.write().unwrap() = peer_filter;
+        }
+        *self.network_context.local_peer_services.write().unwrap() = self.services_conf;
+        if let Some(filename) = &self.message_log_conf {
+            let message_log = MessageLog::open(filename).expect("Failed to open message_log");
+            *self.network_context.message_log.write().unwrap() = Some(message_log);
+            info!("recording the messages of our peers to {}", filename);
+        }
+        if let Some(filename) = &self.peer_stats_conf {
+            match PeerStatsDB::load(filename) {
+                Ok(peer_stats) => *self.network_context.peer_stats.write().unwrap() = peer_stats,
+                Err(err) => {
+                    error!("ERROR 620913: cannot load peer stats: {:?}", err);
+                    *self.network_context.peer_stats.write().unwrap() =
+                        PeerStatsDB::new(Some(filename.clone()));
+                }
+            }
+        }
+        self.publish_node_metadata().await;
+        self.apply_peer_settings(self.peer_conf.as_deref().unwrap_or_default())
+            .await;
+    }
+
+    /// Makes the peer list that of the settings, when the node starts or its configuration
+    /// is reloaded. Peers new to the list are connected to by the network monitor, and peers
+    /// no longer on it are disconnected and not reconnected. Peers which stay on the list keep
+    /// their connection, and take the transport and roles of their setting.
+    pub async fn apply_peer_settings(&self, peer_settings: &[PeerSetting]) {
+        let mut peers_db = self.network_context.peers_db.write().await;
+        let mut new_peer_settings: Vec<&PeerSetting> = peer_settings.iter().collect();
+        for peer in peers_db.values_mut() {
+            if !peer.get_is_from_peer_list() {
+                continue;
+            }
+            let position = new_peer_settings.iter().position(|peer_setting| {
+                peer.get_host() == Some(&peer_setting.host)
+                    && peer.get_port() == Some(peer_setting.port)
+            });
+            match position {
+                Some(position) => {
+                    let peer_setting = new_peer_settings.remove(position);
+                    peer.set_transport(peer_setting.transport);
+                    peer.set_roles(peer_setting.roles.clone());
+                }
+                None => {
+                    info!(
+                        "disconnecting peer {} which is no longer in the peer list",
+                        hex::encode(peer.get_connection_id())
+                    );
+                    peer.set_is_from_peer_list(false);
+                    peer.set_is_connected_or_connecting(false).await;
                 }
             }
         }
+        for peer_setting in new_peer_settings {
+            let connection_id: SaitoHash = hash(Uuid::new_v4().as_bytes().as_ref());
+            let block_store = self.blockchain_lock.read().await.get_block_store();
+            let mut peer = SaitoPeer::new(
+                connection_id,
+                Some(peer_setting.host.clone()),
+                Some(peer_setting.port),
+                false,
+                false,
+                true,
+                self.wallet_lock.clone(),
+                self.mempool_lock.clone(),
+                self.blockchain_lock.clone(),
+                block_store,
+                self.broadcast_channel_sender.clone(),
+                self.network_context.clone(),
+            );
+            peer.set_transport(peer_setting.transport);
+            peer.set_roles(peer_setting.roles.clone());
+            peers_db.insert(connection_id, peer);
+        }
+    }
+
+    /// Replaces the peer filter with that of the settings, and drops the peers it no longer
+    /// allows.
+    pub async fn apply_peer_filter_settings(
+        network_context: &NetworkContext,
+        peer_filter_settings: &PeerFilterSettings,
+    ) -> crate::Result<()> {
+        let peer_filter = PeerFilter::from_settings(peer_filter_settings)?;
+        *network_context.peer_filter.write().unwrap() = peer_filter;
+        disconnect_peers_not_allowed(network_context).await;
+        Ok(())
     }
 
-    /// Connect to a peer via websocket and spawn a Task to handle message received on the socket
-    /// and pipe them to handle_peer_message().
-    async fn connect_to_peer(connection_id: SaitoHash, wallet_lock: Arc<RwLock<Wallet>>) {
-        let peers_db_global = PEERS_DB_GLOBAL.clone();
-        let peer_url;
+    /// Connect to a peer via websocket or QUIC and spawn a Task to handle message received on the
+    /// socket and pipe them to handle_peer_message(). If a SOCKS5 proxy is configured, the
+    /// websocket is opened through it; QUIC runs over UDP and cannot be proxied.
+    async fn connect_to_peer(
+        network_context: NetworkContext,
+        connection_id: SaitoHash,
+        wallet_lock: Arc<RwLock<Wallet>>,
+        socks5_proxy: Option<Socks5ProxySettings>,
+    ) {
+        let peers_db_global = network_context.peers_db.clone();
+        let host;
+        let port;
+        let transport;
         {
             let mut peer_db = peers_db_global.write().await;
             let peer = peer_db.get_mut(&connection_id).unwrap();
-            peer_url = url::Url::parse(&format!(
-                "ws://{}/wsopen",
-                format_url_string(peer.get_host().unwrap(), peer.get_port().unwrap()),
-            ))
-            .unwrap();
+            host = peer.get_host().unwrap().clone();
+            port = peer.get_port().unwrap();
+            transport = peer.get_transport();
             peer.set_is_connected_or_connecting(true).await;
         }
 
-        let ws_stream_result = connect_async(peer_url).await;
-        match ws_stream_result {
-            Ok((ws_stream, _)) => {
-                let (write_sink, mut read_stream) = ws_stream.split();
-                {
-                    let outbound_peer_db_global = OUTBOUND_PEER_CONNECTIONS_GLOBAL.clone();
-                    outbound_peer_db_global
-                        .write()
-                        .await
-                        .insert(connection_id, OutboundPeer { write_sink });
+        let connect_result: crate::Result<()> = match (transport, &socks5_proxy) {
+            (PeerTransport::Websocket, None) if is_onion_host(&host) => {
+                Err(format!("{} can only be reached through a SOCKS5 proxy", host).into())
+            }
+            (PeerTransport::Websocket, None) => {
+                let peer_url =
+                    url::Url::parse(&format!("ws://{}/wsopen", host.format_url_string(port)))
+                        .unwrap();
+                match connect_async(peer_url).await {
+                    Ok((ws_stream, _)) => {
+                        run_peer_connection(network_context.clone(), ws_stream, connection_id)
+                            .await;
+                        Ok(())
+                    }
+                    Err(error) => Err(error.into()),
                 }
-
-                tokio::spawn(async move {
-                    while let Some(result) = read_stream.next().await {
-                        match result {
-                            Ok(message) => {
-                                if !message.is_empty() {
-                                    let api_message = APIMessage::deserialize(&message.into_data());
-                                    SaitoPeer::handle_peer_message(api_message, connection_id)
-                                        .await;
-                                } else {
-                                    error!(
-                                        "Message of length 0... why?\n
-                                        This seems to occur if we aren't holding a reference to the sender/stream on the\n
-                                        other end of the connection. I suspect that when the stream goes out of scope,\n
-                                        it's deconstructor is being called and sends a 0 length message to indicate\n
-                                        that the stream has ended... I'm leaving this println here for now because\n
-                                        it would be very helpful to see this if starts to occur again. We may want to\n
-                                        treat this as a disconnect."
-                                    );
-                                }
-                            }
-                            Err(error) => {
-                                error!("Error reading from peer socket {:?}", error);
-                                let peers_db_global = PEERS_DB_GLOBAL.clone();
-                                let mut peer_db = peers_db_global.write().await;
-                                let peer = peer_db.get_mut(&connection_id).unwrap();
-                                peer.set_is_connected_or_connecting(false).await;
-                            }
+            }
+            (PeerTransport::Websocket, Some(socks5_proxy)) => {
+                let peer_url =
+                    url::Url::parse(&format!("ws://{}/wsopen", host.format_url_string(port)))
+                        .unwrap();
+                match connect_through_socks5_proxy(socks5_proxy, &host, port).await {
+                    Ok(stream) => match client_async(peer_url, MaybeTlsStream::Plain(stream)).await
+                    {
+                        Ok((ws_stream, _)) => {
+                            run_peer_connection(network_context.clone(), ws_stream, connection_id)
+                                .await;
+                            Ok(())
                         }
-                    }
-                });
-                Network::handshake_and_synchronize_chain(&connection_id, wallet_lock).await;
+                        Err(error) => Err(error.into()),
+                    },
+                    Err(error) => Err(error),
+                }
+            }
+            (PeerTransport::Quic, Some(_)) => {
+                Err("QUIC peers cannot be reached through a SOCKS5 proxy".into())
+            }
+            (PeerTransport::Quic, None) => match QuicPeerConnection::connect(&host, port).await {
+                Ok(quic_peer_connection) => {
+                    run_peer_connection(
+                        network_context.clone(),
+                        quic_peer_connection,
+                        connection_id,
+                    )
+                    .await;
+                    Ok(())
+                }
+                Err(error) => Err(error),
+            },
+        };
+        match connect_result {
+            Ok(()) => {
+                Network::handshake_and_synchronize_chain(
+                    &network_context,
+                    &connection_id,
+                    wallet_lock,
+                )
+                .await;
             }
             Err(error) => {
                 error!("Error connecting to peer {:?}", error);
                 let mut peer_db = peers_db_global.write().await;
                 let peer = peer_db.get_mut(&connection_id).unwrap();
+                peer.update_stats(|peer_stats| peer_stats.on_connection_failed());
                 peer.set_is_connected_or_connecting(false).await;
             }
         }
@@ -195,6 +419,7 @@ impl Network {
     /// After socket has been connected, the connector begins the handshake via SHAKINIT command.
     /// Once the handshake is complete, we synchronize the peers via REQCHAIN/SENDCHAIN and REQBLOCK.
     pub async fn handshake_and_synchronize_chain(
+        network_context: &NetworkContext,
         connection_id: &SaitoHash,
         wallet_lock: Arc<RwLock<Wallet>>,
     ) {
@@ -204,22 +429,30 @@ impl Network {
                 let wallet = wallet_lock.read().await;
                 publickey = wallet.get_publickey();
             }
-            let mut message_data = vec![127, 0, 0, 1];
+            //
+            // the peer must put our nonce into the challenge it signs, so that the
+            // challenge cannot be one it signed for somebody else
+            //
+            let nonce: HandshakeNonce = generate_random_bytes(32).try_into().unwrap();
+            let mut message_data = ip_address_to_bytes(IpAddr::from([127, 0, 0, 1])).to_vec();
             message_data.extend(
                 PublicKey::from_slice(&publickey)
                     .unwrap()
                     .serialize()
                     .to_vec(),
             );
+            message_data.extend(nonce);
+            message_data.extend(get_local_services(network_context).serialize());
 
-            let peers_db_global = PEERS_DB_GLOBAL.clone();
-            let mut peer_db = peers_db_global.write().await;
+            let mut peer_db = network_context.peers_db.write().await;
             let peer = peer_db.get_mut(connection_id).unwrap();
 
+            let request_timestamp = create_timestamp();
             let response_api_message = peer
-                .send_command(&String::from("SHAKINIT"), message_data)
+                .send_command(MessageName::HandshakeInit, message_data)
                 .await
                 .unwrap();
+            let response_timestamp = create_timestamp();
             // We should sign the response and send a SHAKCOMP.
             // We want to reuse socket_handshake_verify, so we will sign before verifying the peer's signature
             let privatekey: SaitoPrivateKey;
@@ -230,24 +463,103 @@ impl Network {
             let signed_challenge =
                 sign_blob(&mut response_api_message.message_data.to_vec(), privatekey).to_owned();
             match socket_handshake_verify(&signed_challenge) {
+                Some(deserialize_challenge)
+                    if deserialize_challenge.opponent_pubkey() != publickey
+                        || deserialize_challenge.opponent_nonce() != nonce =>
+                {
+                    error!("ERROR 410373: peer sent a challenge which was not issued to us");
+                    peer.set_is_connected_or_connecting(false).await;
+                }
                 Some(deserialize_challenge) => {
+                    if !is_peer_allowed(
+                        network_context,
+                        Some(deserialize_challenge.challenger_pubkey()),
+                        peer.get_ip_address(),
+                    ) {
+                        error!("ERROR 410372: peer is not allowed by the peer filter");
+                        peer.set_is_connected_or_connecting(false).await;
+                        return;
+                    }
+
+                    //
+                    // the peer created the challenge on its clock somewhere between
+                    // our request and its response
+                    //
+                    let clock_offset = match estimate_clock_offset(
+                        request_timestamp,
+                        response_timestamp,
+                        deserialize_challenge.timestamp(),
+                    ) {
+                        Some(clock_offset) => clock_offset,
+                        None => {
+                            error!("ERROR 410376: peer clock is too far from ours");
+                            peer.set_is_connected_or_connecting(false).await;
+                            return;
+                        }
+                    };
                     peer.set_has_completed_handshake(true);
                     peer.set_publickey(deserialize_challenge.challenger_pubkey());
+                    peer.set_clock_offset(clock_offset).await;
                     let result = peer
-                        .send_command(&String::from("SHAKCOMP"), signed_challenge)
+                        .send_command(MessageName::HandshakeComplete, signed_challenge)
                         .await;
 
-                    if result.is_ok() {
-                        let request_blockchain_message =
-                            RequestBlockchainMessage::new(0, [0; 32], [42; 32]);
+                    //
+                    // the peer advertises its services after the OK, unless it
+                    // speaks an older version of the protocol
+                    //
+                    if let Ok(response_api_message) = result.as_ref() {
+                        peer.set_services(
+                            response_api_message
+                                .get_message_data()
+                                .get(2..)
+                                .and_then(PeerServices::deserialize)
+                                .unwrap_or_else(PeerServices::unadvertised),
+                        );
+                        peer.send_known_node_metadata().await;
+                    }
+                    if result.is_ok() && !peer.has_role(PeerRole::BlockSource) {
+                        info!("peer is not a block source, not requesting its chain");
+                    } else if result.is_ok() && !peer.get_services().full_blocks {
+                        info!("peer does not serve full blocks, not requesting its chain");
+                    } else if result.is_ok() {
+                        //
+                        // the block locator lets the peer send its chain from where
+                        // it forks from ours
+                        //
+                        let mut request_blockchain_message;
+                        {
+                            let blockchain_lock = peer.get_blockchain_lock();
+                            let mut blockchain = blockchain_lock.write().await;
+                            blockchain.get_mut_sync_status().on_chain_requested();
+                            request_blockchain_message = RequestBlockchainMessage::new(
+                                blockchain.get_latest_block_id(),
+                                blockchain.get_latest_block_hash(),
+                                [42; 32],
+                            );
+                            request_blockchain_message
+                                .set_block_locator(blockchain.generate_block_locator());
+                        }
                         let _req_chain_result = peer
                             .send_command(
-                                &String::from("REQCHAIN"),
+                                MessageName::RequestBlockchain,
                                 request_blockchain_message.serialize(),
                             )
                             .await
                             .unwrap();
                         //
+                        // blocks whose files were corrupt may be older than the
+                        // chain the peer sends us, so they are asked for directly
+                        //
+                        let quarantined_block_hashes = peer
+                            .get_blockchain_lock()
+                            .read()
+                            .await
+                            .get_quarantined_block_hashes();
+                        for block_hash in quarantined_block_hashes {
+                            peer.do_reqblock(block_hash).await;
+                        }
+                        //
                         // TODO _req_chain_result will be an OK message. We could verify it here, but it's not very useful.
                         // However, if we are finding issues, it may be useful to retry if we don't receive an OK soon.
                         //
@@ -273,48 +585,354 @@ impl Network {
     //
     // send block to all peers
     //
-    async fn propagate_block(block_hash: SaitoHash) {
-        let peers_db_global = PEERS_DB_GLOBAL.clone();
-        let mut peers_db_mut = peers_db_global.write().await;
+    /// Announces a block to every peer which does not already know about it.
+    async fn propagate_block(
+        network_context: &NetworkContext,
+        blockchain_lock: Arc<RwLock<Blockchain>>,
+        block_hash: SaitoHash,
+    ) {
+        let send_block_head_message = {
+            let blockchain = blockchain_lock.read().await;
+            create_send_block_head_message(&blockchain, block_hash)
+        };
+        network_context
+            .recent_block_hashes
+            .write()
+            .unwrap()
+            .insert(block_hash);
+        let mut peers_db_mut = network_context.peers_db.write().await;
         // We need a stream iterator for async(to await send_command_fire_and_forget)
         let mut peers_iterator_stream = futures::stream::iter(peers_db_mut.values_mut());
         while let Some(peer) = peers_iterator_stream.next().await {
             if peer.get_has_completed_handshake() {
-                let send_block_head_message = SendBlockHeadMessage::new(block_hash);
-                peer.send_command_fire_and_forget("SNDBLKHD", send_block_head_message.serialize())
-                    .await;
+                //
+                // the peer announced or sent us this block, or we already
+                // announced it to them
+                //
+                if !peer.mark_block_hash_known(block_hash) {
+                    continue;
+                }
+                peer.send_command_fire_and_forget(
+                    MessageName::SendBlockHeader,
+                    send_block_head_message.serialize(),
+                )
+                .await;
             } else {
                 info!("Hasn't completed handshake, will not send block??");
             }
         }
     }
 
-    pub async fn propagate_transaction(wallet_lock: Arc<RwLock<Wallet>>, mut tx: Transaction) {
+    /// Signs our node metadata record with the current time, keeps it with the records of other
+    /// nodes and gossips it to every peer.
+    async fn publish_node_metadata(&self) {
+        let (publickey, privatekey) = {
+            let wallet = self.wallet_lock.read().await;
+            (wallet.get_publickey(), wallet.get_privatekey())
+        };
+        let current_timestamp = create_timestamp();
+        let mut node_metadata = NodeMetadata::new(
+            publickey,
+            current_timestamp,
+            self.services_conf,
+            self.min_fee_rate,
+            self.identity_conf.alias.clone(),
+            self.identity_conf.endpoints.clone(),
+        )
+        .expect("Failed to parse identity configuration");
+        node_metadata.sign(privatekey);
+        self.network_context
+            .node_metadata
+            .write()
+            .unwrap()
+            .insert(node_metadata.clone(), current_timestamp);
+        Network::propagate_node_metadata(self.network_context.clone(), node_metadata, None);
+    }
+
+    /// Gossips a node metadata record to every peer but the node itself and the peer on the
+    /// connection it came from.
+    pub fn propagate_node_metadata(
+        network_context: NetworkContext,
+        node_metadata: NodeMetadata,
+        from_connection_id: Option<SaitoHash>,
+    ) {
         tokio::spawn(async move {
-            let peers_db_global = PEERS_DB_GLOBAL.clone();
-            let mut peers_db_mut = peers_db_global.write().await;
-            // We need a stream iterator for async(to await send_command_fire_and_forget)
+            let mut peers_db_mut = network_context.peers_db.write().await;
             let mut peers_iterator_stream = futures::stream::iter(peers_db_mut.values_mut());
             while let Some(peer) = peers_iterator_stream.next().await {
-                if peer.get_has_completed_handshake() && !peer.is_in_path(&tx.get_path()) {
-                    // change the last bytes in the vector for each SNDTRANS
-                    let hop = tx
-                        .build_last_hop(wallet_lock.clone(), peer.get_publickey().unwrap())
-                        .await;
-
+                if peer.get_has_completed_handshake()
+                    && Some(peer.get_connection_id()) != from_connection_id
+                    && peer.get_publickey() != Some(node_metadata.get_publickey())
+                {
                     peer.send_command_fire_and_forget(
-                        "SNDTRANS",
-                        tx.serialize_for_net_with_hop(hop),
+                        MessageName::SendNodeMetadata,
+                        node_metadata.serialize(),
                     )
                     .await;
+                }
+            }
+        });
+    }
+
+    /// Announces our latest block again to every peer, whether or not it already knows it, if it
+    /// is still previous_tip, i.e. no block has arrived since the last announcement. Returns the
+    /// latest block hash, to be passed as previous_tip next time.
+    async fn announce_tip(
+        network_context: &NetworkContext,
+        blockchain_lock: Arc<RwLock<Blockchain>>,
+        previous_tip: SaitoHash,
+    ) -> SaitoHash {
+        let (block_hash, send_block_head_message) = {
+            let blockchain = blockchain_lock.read().await;
+            let block_hash = blockchain.get_latest_block_hash();
+            (
+                block_hash,
+                create_send_block_head_message(&blockchain, block_hash),
+            )
+        };
+        if block_hash != previous_tip || block_hash == [0; 32] {
+            return block_hash;
+        }
+        let mut peers_db_mut = network_context.peers_db.write().await;
+        let mut peers_iterator_stream = futures::stream::iter(peers_db_mut.values_mut());
+        while let Some(peer) = peers_iterator_stream.next().await {
+            if peer.get_has_completed_handshake() {
+                peer.mark_block_hash_known(block_hash);
+                peer.send_command_fire_and_forget(
+                    MessageName::SendBlockHeader,
+                    send_block_head_message.serialize(),
+                )
+                .await;
+            }
+        }
+        block_hash
+    }
+
+    /// Relays a transaction to every peer which has not already seen it, starting with the
+    /// peers the relay policy expects to be most likely to include it in a block.
+    ///
+    /// Transactions created by our own wallet are instead diffused, i.e. sent to each peer
+    /// after its own random delay, so that the timing of the relay does not give away that
+    /// the transaction started here.
+    ///
+    /// Transactions are only relayed to peers which advertise transaction relay, and only our
+    /// own are relayed if we do not offer it ourselves.
+    pub async fn propagate_transaction(
+        network_context: NetworkContext,
+        wallet_lock: Arc<RwLock<Wallet>>,
+        blockchain_lock: Arc<RwLock<Blockchain>>,
+        tx: Transaction,
+        is_from_own_wallet: bool,
+    ) {
+        tokio::spawn(async move {
+            let block_producers;
+            {
+                let blockchain = blockchain_lock.read().await;
+                //
+                // the transaction may spend slips we have not seen yet, so we do
+                // not relay while far behind the network
+                //
+                if blockchain.get_sync_status().is_far_behind() {
+                    info!("not relaying transaction while syncing");
+                    return;
+                }
+                block_producers = count_recent_block_producers(&blockchain);
+            }
+            if !is_from_own_wallet && !get_local_services(&network_context).transaction_relay {
+                return;
+            }
+            let wallet = wallet_lock.read().await;
+            let mut peers_db_mut = network_context.peers_db.write().await;
+
+            let mut peer_scores: Vec<PeerScore> = vec![];
+            for peer in peers_db_mut.values() {
+                if !peer.has_role(PeerRole::TransactionRelay)
+                    || !peer.get_services().transaction_relay
+                {
+                    continue;
+                }
+                if peer.get_has_completed_handshake() && !peer.is_in_path(tx.get_path()) {
+                    let connection_id = peer.get_connection_id();
+                    peer_scores.push(PeerScore::new(
+                        connection_id,
+                        *block_producers
+                            .get(&peer.get_publickey().unwrap())
+                            .unwrap_or(&0),
+                        peer.get_latency(),
+                        get_peer_bandwidth(&network_context, &connection_id).get_fees_received(),
+                    ));
                 } else {
                     info!("Hasn't completed handshake, will not send transaction??");
                 }
             }
+            order_peers_for_relay(&mut peer_scores);
+            // peers configured as transaction relays go first
+            peer_scores.sort_by_key(|peer_score| {
+                get_role_preference(
+                    peers_db_mut
+                        .get(&peer_score.get_connection_id())
+                        .unwrap()
+                        .get_roles(),
+                    PeerRole::TransactionRelay,
+                )
+            });
+
+            if is_from_own_wallet {
+                drop(peers_db_mut);
+                drop(wallet);
+                let diffusion_delays = generate_diffusion_delays(peer_scores.len(), &mut OsRandom);
+                for (peer_score, diffusion_delay) in peer_scores.into_iter().zip(diffusion_delays) {
+                    let network_context = network_context.clone();
+                    let wallet_lock = wallet_lock.clone();
+                    let tx = tx.clone();
+                    tokio::spawn(async move {
+                        sleep(Duration::from_millis(diffusion_delay)).await;
+                        let wallet = wallet_lock.read().await;
+                        let mut peers_db_mut = network_context.peers_db.write().await;
+                        // the peer may have disconnected while we waited
+                        if let Some(peer) = peers_db_mut.get_mut(&peer_score.get_connection_id()) {
+                            Network::relay_transaction_to_peer(
+                                &network_context,
+                                &wallet,
+                                peer,
+                                &tx,
+                            )
+                            .await;
+                        }
+                    });
+                }
+                return;
+            }
+
+            for peer_score in peer_scores {
+                let peer = peers_db_mut
+                    .get_mut(&peer_score.get_connection_id())
+                    .unwrap();
+                Network::relay_transaction_to_peer(&network_context, &wallet, peer, &tx).await;
+            }
+        });
+    }
+
+    /// Fetches a block we are missing, i.e. the parent of a block we were sent, from an archive
+    /// provider if we have one, or else from the peer which sent us the block, or else from
+    /// the peer whose last announced tip was furthest ahead of ours.
+    async fn fetch_missing_block(
+        network_context: &NetworkContext,
+        source_connection_id: SaitoHash,
+        block_hash: SaitoHash,
+    ) {
+        let peers_db = network_context.peers_db.read().await;
+        let mut candidates: Vec<&SaitoPeer> = peers_db
+            .values()
+            .filter(|peer| peer.get_has_completed_handshake() && peer.get_services().full_blocks)
+            .collect();
+        // of the peers preferred equally, the peer which sent us the block goes first
+        candidates.sort_by_key(|peer| {
+            (
+                peer.get_connection_id() != source_connection_id,
+                get_sync_source_rank(peer.get_tip()),
+            )
+        });
+        let candidates: Vec<(SaitoHash, &[PeerRole])> = candidates
+            .into_iter()
+            .map(|peer| (peer.get_connection_id(), peer.get_roles()))
+            .collect();
+        match order_peers_for_role(candidates, PeerRole::ArchiveProvider).first() {
+            Some(connection_id) => {
+                peers_db
+                    .get(connection_id)
+                    .unwrap()
+                    .do_reqblock(block_hash)
+                    .await;
+            }
+            None => {
+                warn!(
+                    "no peer to fetch missing block {} from",
+                    hex::encode(block_hash)
+                );
+            }
+        }
+    }
+
+    async fn relay_transaction_to_peer(
+        network_context: &NetworkContext,
+        wallet: &Wallet,
+        peer: &mut SaitoPeer,
+        tx: &Transaction,
+    ) {
+        //
+        // each peer gets its own copy of the transaction with a
+        // hop from us to them appended to the routing path
+        //
+        let mut relayed_tx = tx.clone();
+        relayed_tx.add_hop(wallet, peer.get_publickey().unwrap());
+
+        peer.send_command_fire_and_forget(
+            MessageName::SendTransaction,
+            relayed_tx.serialize_for_net(),
+        )
+        .await;
+        update_peer_bandwidth(network_context, &peer.get_connection_id(), |bandwidth| {
+            bandwidth.add_transaction_sent(&relayed_tx)
         });
     }
 }
 
+/// Tor onion services have no address outside of Tor.
+fn is_onion_host(host: &PeerHost) -> bool {
+    matches!(host, PeerHost::Hostname(hostname) if hostname.ends_with(".onion"))
+}
+
+/// Orders peers, given by their connection ids and addresses, best first by the stats of their
+/// addresses, so that after a restart we reconnect to the peers which served us well before
+/// the others, see PeerStats::get_score.
+fn order_peers_by_stats(
+    network_context: &NetworkContext,
+    peers: Vec<(SaitoHash, Option<String>)>,
+) -> Vec<SaitoHash> {
+    let timestamp = create_timestamp();
+    let peer_stats = network_context.peer_stats.read().unwrap();
+    let mut peers: Vec<(i64, SaitoHash)> = peers
+        .into_iter()
+        .map(|(connection_id, address)| {
+            let score = address.map_or(0, |address| peer_stats.get_score(&address, timestamp));
+            (score, connection_id)
+        })
+        .collect();
+    peers.sort_by_key(|(score, _)| Reverse(*score));
+    peers
+        .into_iter()
+        .map(|(_, connection_id)| connection_id)
+        .collect()
+}
+
+/// Orders the peers we sync from by the tips they announced: the peers ahead of us first,
+/// those with the most chain work before the others, then the peers with our tip, the peers
+/// which announced no tip, and last the peers behind us or on a fork.
+fn get_sync_source_rank(peer_tip: Option<PeerTip>) -> (u8, Reverse<u64>) {
+    match peer_tip {
+        Some(peer_tip) => match peer_tip.comparison {
+            TipComparison::Ahead => (0, Reverse(peer_tip.chain_work)),
+            TipComparison::Same => (1, Reverse(0)),
+            TipComparison::Behind | TipComparison::Fork => (3, Reverse(0)),
+        },
+        None => (2, Reverse(0)),
+    }
+}
+
+/// Announces a block with its id and the work of our chain up to it, so that the peer can
+/// tell whether we are ahead of it, behind it or on a fork. A block we do not hold is
+/// announced by its hash alone.
+fn create_send_block_head_message(
+    blockchain: &Blockchain,
+    block_hash: SaitoHash,
+) -> SendBlockHeadMessage {
+    match blockchain.get_cached_block_header(&block_hash) {
+        Some(header) => SendBlockHeadMessage::new(block_hash, header.id, header.chain_work),
+        None => SendBlockHeadMessage::new(block_hash, 0, 0),
+    }
+}
+
 pub async fn run(
     network_lock: Arc<RwLock<Network>>,
     broadcast_channel_sender: broadcast::Sender<SaitoMessage>,
@@ -323,9 +941,11 @@ pub async fn run(
     //
     // network gets global broadcast channel
     //
+    let network_context;
     {
         let mut network = network_lock.write().await;
         network.set_broadcast_channel_sender(broadcast_channel_sender.clone());
+        network_context = network.get_network_context();
     }
 
     //
@@ -342,17 +962,112 @@ pub async fn run(
             sleep(Duration::from_millis(10000)).await;
         }
     });
+    let tip_announcement_interval = network_lock.read().await.tip_announcement_interval;
+    if tip_announcement_interval > 0 {
+        let tip_announcement_sender = network_channel_sender.clone();
+        tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval(Duration::from_secs(tip_announcement_interval));
+            loop {
+                interval.tick().await;
+                tip_announcement_sender
+                    .send(NetworkMessage::TipAnnouncement)
+                    .await
+                    .expect("Failed to send TipAnnouncement message");
+            }
+        });
+    }
+
+    let ping_interval = network_lock.read().await.ping_interval;
+    if ping_interval > 0 {
+        let peer_ping_sender = network_channel_sender.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(ping_interval));
+            // peers are first measured in the handshake
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                peer_ping_sender
+                    .send(NetworkMessage::PeerPing)
+                    .await
+                    .expect("Failed to send PeerPing message");
+            }
+        });
+    }
+
+    let node_metadata_republish_sender = network_channel_sender.clone();
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(NODE_METADATA_REPUBLISH_INTERVAL));
+        // the record is first published as the network is initialized
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            node_metadata_republish_sender
+                .send(NetworkMessage::NodeMetadataRepublish)
+                .await
+                .expect("Failed to send NodeMetadataRepublish message");
+        }
+    });
 
     //
-    // initialize server
+    // initialize servers
     //
     let network_lock_clone = network_lock.clone();
-    tokio::select! {
-        res = run_server(network_lock_clone) => {
-            if let Err(err) = res {
-                eprintln!("run_server err {:?}", err)
+    tokio::spawn(async move {
+        if let Err(err) = run_server(network_lock_clone).await {
+            error!("run_server err {:?}", err)
+        }
+    });
+    let network_lock_clone = network_lock.clone();
+    tokio::spawn(async move {
+        if let Err(err) = run_admin_server(network_lock_clone).await {
+            error!("run_admin_server err {:?}", err)
+        }
+    });
+    {
+        let network = network_lock.read().await;
+        if let Some(quic_port) = network.quic_port {
+            let quic_server = run_quic_server(
+                network.get_network_context(),
+                network.host,
+                quic_port,
+                network.wallet_lock.clone(),
+                network.mempool_lock.clone(),
+                network.blockchain_lock.clone(),
+                network.broadcast_channel_sender.clone(),
+            );
+            tokio::spawn(async move {
+                if let Err(err) = quic_server.await {
+                    error!("run_quic_server err {:?}", err)
+                }
+            });
+        }
+        if let Some(grpc_port) = network.grpc_port {
+            #[cfg(feature = "grpc")]
+            {
+                let grpc_server = crate::networking::grpc::run_grpc_server(
+                    network.host,
+                    grpc_port,
+                    crate::networking::grpc::GrpcService::new(
+                        network.wallet_lock.clone(),
+                        network.mempool_lock.clone(),
+                        network.blockchain_lock.clone(),
+                        network.broadcast_channel_sender.clone(),
+                    ),
+                );
+                tokio::spawn(async move {
+                    if let Err(err) = grpc_server.await {
+                        error!("run_grpc_server err {:?}", err)
+                    }
+                });
             }
-        },
+            #[cfg(not(feature = "grpc"))]
+            warn!(
+                "grpc_port {} is set but this node was built without the grpc feature",
+                grpc_port
+            );
+        }
     }
 
     //
@@ -367,6 +1082,7 @@ pub async fn run(
     // listen to local and global messages
     //
     let network_lock_clone2 = network_lock.clone();
+    let mut previous_tip = [0; 32];
     loop {
         tokio::select! {
 
@@ -384,32 +1100,78 @@ pub async fn run(
                         //
                         // Check Disconnected Peers
                         //
-                        let peer_states: Vec<(SaitoHash, bool)>;
+                        let disconnected_peers: Vec<SaitoHash>;
                         {
-                            let peers_db_global = PEERS_DB_GLOBAL.clone();
-                            let peers_db = peers_db_global.read().await;
-                            peer_states = peers_db
-                            .keys()
-                            .map(|connection_id| {
-                                let peer = peers_db.get(connection_id).unwrap();
-                                let should_try_reconnect = peer.get_is_from_peer_list()
-                                    && !peer.get_is_connected_or_connecting();
-                                (*connection_id, should_try_reconnect)
-                            })
-                            .collect::<Vec<(SaitoHash, bool)>>();
+                            let peers_db = network_context.peers_db.read().await;
+                            disconnected_peers = order_peers_by_stats(
+                                &network_context,
+                                peers_db
+                                    .values()
+                                    .filter(|peer| {
+                                        peer.get_is_from_peer_list()
+                                            && !peer.get_is_connected_or_connecting()
+                                    })
+                                    .map(|peer| (peer.get_connection_id(), peer.get_address()))
+                                    .collect(),
+                            );
                         }
-                        for (connection_id, should_try_reconnect) in peer_states {
-                            if should_try_reconnect {
-                               info!("found disconnected peer in peer settings, (re)connecting...");
-                                let network = network_lock_clone2.read().await;
-                                let wallet_lock_clone = network.wallet_lock.clone();
-                                Network::connect_to_peer(connection_id, wallet_lock_clone).await;
-                            }
+                        for connection_id in disconnected_peers {
+                            info!("found disconnected peer in peer settings, (re)connecting...");
+                            let network = network_lock_clone2.read().await;
+                            let wallet_lock_clone = network.wallet_lock.clone();
+                            Network::connect_to_peer(
+                                network_context.clone(),
+                                connection_id,
+                                wallet_lock_clone,
+                                network.socks5_proxy.clone(),
+                            )
+                            .await;
                         }
 
                         // reconnect one-by-one
                         info!("Finished Connecting!");
 
+                        let result = network_context
+                            .peer_stats
+                            .write()
+                            .unwrap()
+                            .save(create_timestamp());
+                        if let Err(err) = result {
+                            error!("ERROR 620914: cannot save peer stats: {:?}", err);
+                        }
+
+                    },
+
+                    //
+                    // Announce our tip again on a quiet network
+                    //
+                    NetworkMessage::TipAnnouncement => {
+                        let network = network_lock_clone2.read().await;
+                        previous_tip = Network::announce_tip(
+                            &network_context,
+                            network.blockchain_lock.clone(),
+                            previous_tip,
+                        )
+                        .await;
+                    },
+
+                    //
+                    // Renew our node metadata record before it expires
+                    //
+                    NetworkMessage::NodeMetadataRepublish => {
+                        network_lock_clone2.read().await.publish_node_metadata().await;
+                    },
+
+                    //
+                    // Measure the latency and clock offset of our peers again
+                    //
+                    NetworkMessage::PeerPing => {
+                        let peers_db = network_context.peers_db.read().await;
+                        for peer in peers_db.values() {
+                            if peer.get_has_completed_handshake() {
+                                peer.do_ping().await;
+                            }
+                        }
                     },
                 }
             }
@@ -418,26 +1180,40 @@ pub async fn run(
             //
             // Saito Channel Messages
             //
-            Ok(message) = broadcast_channel_receiver.recv() => {
+            Some(message) = recv_message(&mut broadcast_channel_receiver, Subsystem::Network) => {
                 match message {
                     SaitoMessage::BlockchainNewLongestChainBlock { hash : block_hash, difficulty } => {
                     info!("Network aware of new longest chain block!");
                     },
                     SaitoMessage::BlockchainSavedBlock { hash: block_hash } => {
                         warn!("SaitoMessage::BlockchainSavedBlock recv'ed by network");
-                        Network::propagate_block(block_hash).await;
+                        let network = network_lock_clone2.read().await;
+                        Network::propagate_block(
+                            &network_context,
+                            network.blockchain_lock.clone(),
+                            block_hash,
+                        )
+                        .await;
                     },
                     SaitoMessage::WalletNewTransaction { transaction: tx } => {
                         info!("SaitoMessage::WalletNewTransaction new tx is detected by network");
                         let network = network_lock_clone2.read().await;
-                        Network::propagate_transaction(network.wallet_lock.clone(), tx).await;
+                        Network::propagate_transaction(
+                            network_context.clone(),
+                            network.wallet_lock.clone(),
+                            network.blockchain_lock.clone(),
+                            tx,
+                            true,
+                        )
+                        .await;
                     },
                     SaitoMessage::MissingBlock {
                         peer_id: connection_id,
                         hash: block_hash,
                     } => {
                         warn!("SaitoMessage::MissingBlock message received over broadcast channel");
-                        //Network::fetch_block();
+                        Network::fetch_missing_block(&network_context, connection_id, block_hash)
+                            .await;
                     },
                     _ => {}
                 }
@@ -449,12 +1225,67 @@ pub async fn run(
 /// Runs warp::serve to listen for incoming connections
 pub async fn run_server(network_lock_clone: Arc<RwLock<Network>>) -> crate::Result<()> {
     let network = network_lock_clone.read().await;
-    let routes = get_block_route_filter(network.blockchain_lock.clone())
+    let block_store = network.blockchain_lock.read().await.get_block_store();
+    //
+    // the json view and payouts of a block go first, as get_block_route_filter would take
+    // their paths
+    //
+    let routes = get_block_json_route_filter(block_store.clone())
+        .or(get_block_payouts_route_filter(
+            network.blockchain_lock.clone(),
+        ))
+        .or(get_block_route_filter(block_store.clone()))
+        .or(get_explorer_block_route_filter(block_store))
         .or(post_transaction_route_filter(
+            network.wallet_lock.clone(),
             network.mempool_lock.clone(),
             network.blockchain_lock.clone(),
         ))
+        .or(post_transaction_batch_route_filter(
+            network.wallet_lock.clone(),
+            network.mempool_lock.clone(),
+            network.blockchain_lock.clone(),
+        ))
+        .or(get_blocks_route_filter(network.blockchain_lock.clone()))
+        .or(get_balance_route_filter(network.blockchain_lock.clone()))
+        .or(get_unspent_slips_route_filter(
+            network.blockchain_lock.clone(),
+        ))
+        .or(get_utxoset_info_route_filter(
+            network.blockchain_lock.clone(),
+        ))
+        .or(get_chain_stats_route_filter(
+            network.blockchain_lock.clone(),
+        ))
+        .or(get_reorgs_route_filter(network.blockchain_lock.clone()))
+        .or(get_search_transactions_route_filter(
+            network.blockchain_lock.clone(),
+        ))
+        .or(get_broadcast_metrics_route_filter())
+        .or(get_sync_status_route_filter(
+            network.blockchain_lock.clone(),
+        ))
+        .or(get_health_route_filter(
+            network.mempool_lock.clone(),
+            network.blockchain_lock.clone(),
+            network.get_network_context(),
+        ))
+        .or(get_node_metadata_route_filter(
+            network.get_network_context(),
+        ))
+        .or(get_node_metadata_by_publickey_route_filter(
+            network.get_network_context(),
+        ))
+        .or(get_mempool_info_route_filter(
+            network.mempool_lock.clone(),
+            network.blockchain_lock.clone(),
+        ))
+        .or(get_raw_mempool_route_filter(network.mempool_lock.clone()))
+        .or(get_mempool_transaction_route_filter(
+            network.mempool_lock.clone(),
+        ))
         .or(ws_upgrade_route_filter(
+            network.get_network_context(),
             network.wallet_lock.clone(),
             network.mempool_lock.clone(),
             network.blockchain_lock.clone(),
@@ -464,96 +1295,144 @@ pub async fn run_server(network_lock_clone: Arc<RwLock<Network>>) -> crate::Resu
     info!("Listening for HTTP on port {}", network.port);
     let (_, server) = warp::serve(routes)
         .bind_with_graceful_shutdown((network.host, network.port), signal_for_shutdown());
+    // the network is initialized after the server is started, which takes the lock to write
+    drop(network);
     server.await;
     Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use std::convert::TryInto;
-
-    use super::*;
-    use crate::configuration::get_configuration;
-    use crate::transaction::Transaction;
-    use crate::{
+/// Serves the routes which manage the node, or show what it knows of its peers, on the admin
+/// port. They are kept off the public port, as they are not authenticated, so the admin port
+/// is only bound to localhost.
+pub async fn run_admin_server(network_lock_clone: Arc<RwLock<Network>>) -> crate::Result<()> {
+    let network = network_lock_clone.read().await;
+    let admin_port = match network.admin_port {
+        Some(admin_port) => admin_port,
+        None => return Ok(()),
+    };
+    let routes = get_peer_bandwidth_route_filter(network.get_network_context())
+        .or(get_peer_stats_route_filter(network.get_network_context()))
+        .or(get_peer_filter_route_filter(network.get_network_context()))
+        .or(put_peer_filter_route_filter(network.get_network_context()))
+        .or(post_rescan_route_filter(network.blockchain_lock.clone()))
+        .or(get_block_template_route_filter(
+            network.mempool_lock.clone(),
+            network.blockchain_lock.clone(),
+        ))
+        .or(post_submit_block_route_filter(
+            network.mempool_lock.clone(),
+            network.blockchain_lock.clone(),
+        ));
+
+    info!("Listening for admin HTTP on localhost port {}", admin_port);
+    let (_, server) = warp::serve(routes)
+        .bind_with_graceful_shutdown((Ipv4Addr::LOCALHOST, admin_port), signal_for_shutdown());
+    drop(network);
+    server.await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    use super::*;
+    use crate::configuration::get_configuration;
+    use crate::memo_index::MemoIndex;
+    use crate::message_log::read_message_log;
+    use crate::reorg_log::{ReorgEvent, ReorgLog};
+    use crate::transaction::Transaction;
+    use crate::vip_policy::VipPolicy;
+    use crate::{
         block::{Block, BlockType},
         crypto::{generate_keys, hash, sign_blob, verify, SaitoSignature},
+        layout::TRANSACTION_LENGTH_PREFIX_SIZE,
         mempool::Mempool,
         networking::{
-            api_message::APIMessage,
+            api_message::{APIMessage, MessageName},
             filters::ws_upgrade_route_filter,
+            handlers::MAX_TRANSACTIONS_PER_BATCH,
             message_types::{
+                block_range_chunk::BlockRangeChunk,
                 handshake_challenge::HandshakeChallenge,
                 request_block_message::RequestBlockMessage,
+                request_block_range_message::RequestBlockRangeMessage,
+                request_blockchain_message::RequestBlockchainMessage,
                 send_block_head_message::SendBlockHeadMessage,
                 send_blockchain_message::{
                     SendBlockchainBlockData, SendBlockchainMessage, SyncType,
                 },
             },
         },
+        peer::build_send_blockchain_message,
         test_utilities::test_manager::TestManager,
         time::create_timestamp,
     };
+    use base58::ToBase58;
     use secp256k1::PublicKey;
     use warp::{test::WsClient, ws::Message};
 
-    /// This doesn't currently seem to create a problem, but I think
-    async fn clean_peers_dbs() {
-        let peers_db_global = PEERS_DB_GLOBAL.clone();
-        let mut peer_db = peers_db_global.write().await;
-
-        let request_responses_lock = PEERS_REQUEST_RESPONSES_GLOBAL.clone();
-        let mut request_responses = request_responses_lock.write().unwrap();
-
-        let request_wakers_lock = PEERS_REQUEST_WAKERS_GLOBAL.clone();
-        let mut request_wakers = request_wakers_lock.write().unwrap();
-
-        let outbound_peer_connection_db_global = OUTBOUND_PEER_CONNECTIONS_GLOBAL.clone();
-        let mut outbound_peer_connection_db = outbound_peer_connection_db_global.write().await;
-        let inbound_peer_connection_db_global = INBOUND_PEER_CONNECTIONS_GLOBAL.clone();
-        let mut inbound_peer_connection_db = inbound_peer_connection_db_global.write().await;
-
-        peer_db.drain();
-        request_responses.drain();
-        request_wakers.drain();
-        outbound_peer_connection_db.drain();
-        inbound_peer_connection_db.drain();
-    }
-
     /// This function will be used in mosts test of network, it will open a socket, negotiate a handshake,
     /// and return the socket so we are ready to start sending APIMessages through the socket, which
     /// we can use as a mock peer.
     async fn create_socket_and_do_handshake(
+        network_context: NetworkContext,
         wallet_arc: Arc<RwLock<Wallet>>,
         mempool_arc: Arc<RwLock<Mempool>>,
         blockchain_arc: Arc<RwLock<Blockchain>>,
         broadcast_channel_sender: broadcast::Sender<SaitoMessage>,
     ) -> WsClient {
-        // mock things:
-        let (publickey, privatekey) = generate_keys();
+        let mut ws_client = open_socket(
+            network_context.clone(),
+            wallet_arc,
+            mempool_arc,
+            blockchain_arc,
+            broadcast_channel_sender,
+        )
+        .await;
+        do_handshake(&network_context, &mut ws_client).await;
+        ws_client
+    }
 
+    async fn open_socket(
+        network_context: NetworkContext,
+        wallet_arc: Arc<RwLock<Wallet>>,
+        mempool_arc: Arc<RwLock<Mempool>>,
+        blockchain_arc: Arc<RwLock<Blockchain>>,
+        broadcast_channel_sender: broadcast::Sender<SaitoMessage>,
+    ) -> WsClient {
         // use Warp test to open a socket:
         let socket_filter = ws_upgrade_route_filter(
+            network_context,
             wallet_arc,
             mempool_arc,
             blockchain_arc,
             broadcast_channel_sender,
         );
-        let mut ws_client = warp::test::ws()
+        warp::test::ws()
             .path("/wsopen")
             .handshake(socket_filter)
             .await
-            .expect("handshake");
+            .expect("handshake")
+    }
+
+    /// Does the handshake over the socket as a new peer, returning the SHAKCOMP payload, i.e.
+    /// the challenge signed by both sides.
+    async fn do_handshake(network_context: &NetworkContext, ws_client: &mut WsClient) -> Vec<u8> {
+        // mock things:
+        let (publickey, privatekey) = generate_keys();
 
         // create a SHAKINIT message
-        let mut message_data = vec![127, 0, 0, 1];
+        let mut message_data = ip_address_to_bytes(IpAddr::from([127, 0, 0, 1])).to_vec();
         message_data.extend(
             PublicKey::from_slice(&publickey)
                 .unwrap()
                 .serialize()
                 .to_vec(),
         );
-        let api_message = APIMessage::new("SHAKINIT", 42, message_data);
+        message_data.extend([9; 32]);
+        message_data.extend(PeerServices::default().serialize());
+        let api_message = APIMessage::new(MessageName::HandshakeInit, 42, message_data);
 
         // send SHAKINIT through the socket
         ws_client
@@ -579,10 +1458,14 @@ mod tests {
         // confirm the HandshakeChallenge has all the right data and the signature is correct:
         assert_eq!(
             deserialize_challenge.challenger_ip_address(),
-            [127, 0, 0, 1]
+            IpAddr::from([127, 0, 0, 1])
+        );
+        assert_eq!(
+            deserialize_challenge.opponent_ip_address(),
+            IpAddr::from([127, 0, 0, 1])
         );
-        assert_eq!(deserialize_challenge.opponent_ip_address(), [127, 0, 0, 1]);
         assert_eq!(deserialize_challenge.opponent_pubkey(), publickey);
+        assert_eq!(deserialize_challenge.opponent_nonce(), [9; 32]);
         assert!(verify(
             &hash(&raw_challenge.to_vec()),
             sig,
@@ -592,23 +1475,90 @@ mod tests {
         // sign the raw challenge and create a SHAKCOMP message from it
         let signed_challenge =
             sign_blob(&mut resp.as_bytes()[12..].to_vec(), privatekey).to_owned();
-        let api_message = APIMessage::new("SHAKCOMP", 43, signed_challenge);
+        let api_message =
+            APIMessage::new(MessageName::HandshakeComplete, 43, signed_challenge.clone());
 
         // send SHAKCOMP through the socket
         ws_client
             .send(Message::binary(api_message.serialize()))
             .await;
 
-        // read a message from the socket and confirm that the RESULT__ is OK
+        // read a message from the socket and confirm that the RESULT__ is OK, followed by
+        // the services of the node
         let resp = ws_client.recv().await.unwrap();
         let command = String::from_utf8_lossy(&resp.as_bytes()[0..8]);
         let index: u32 = u32::from_be_bytes(resp.as_bytes()[8..12].try_into().unwrap());
-        let msg = String::from_utf8_lossy(&resp.as_bytes()[12..]);
+        let msg = String::from_utf8_lossy(&resp.as_bytes()[12..14]);
         assert_eq!(command, "RESULT__");
         assert_eq!(index, 43);
         assert_eq!(msg, "OK");
+        assert_eq!(
+            PeerServices::deserialize(&resp.as_bytes()[14..]),
+            Some(get_local_services(network_context))
+        );
+
+        signed_challenge
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_handshake_replay() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let mempool_lock = Arc::new(RwLock::new(Mempool::new(wallet_lock.clone())));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let (broadcast_channel_sender, _broadcast_channel_receiver) = broadcast::channel(32);
+        let network_context = NetworkContext::new();
+        let mut ws_client = open_socket(
+            network_context.clone(),
+            wallet_lock.clone(),
+            mempool_lock.clone(),
+            blockchain_lock.clone(),
+            broadcast_channel_sender.clone(),
+        )
+        .await;
+        let signed_challenge = do_handshake(&network_context, &mut ws_client).await;
+        assert!(network_context
+            .issued_challenges
+            .clone()
+            .read()
+            .unwrap()
+            .is_empty());
+
+        // a signed challenge is not accepted a second time, on the same socket
+        let api_message =
+            APIMessage::new(MessageName::HandshakeComplete, 44, signed_challenge.clone());
+        ws_client
+            .send(Message::binary(api_message.serialize()))
+            .await;
+        let resp = APIMessage::deserialize(&ws_client.recv().await.unwrap().as_bytes().to_vec());
+        assert_eq!(resp.get_message_name(), Some(MessageName::Error));
+        assert_eq!(resp.get_message_data_as_string(), "INVALID CHALLENGE");
 
+        // or on another one, even if that socket has a challenge of its own outstanding
+        let mut ws_client = open_socket(
+            network_context.clone(),
+            wallet_lock.clone(),
+            mempool_lock.clone(),
+            blockchain_lock.clone(),
+            broadcast_channel_sender.clone(),
+        )
+        .await;
+        let (publickey, _privatekey) = generate_keys();
+        let mut message_data = ip_address_to_bytes(IpAddr::from([127, 0, 0, 1])).to_vec();
+        message_data.extend(publickey);
+        message_data.extend([9; 32]);
+        let api_message = APIMessage::new(MessageName::HandshakeInit, 45, message_data);
+        ws_client
+            .send(Message::binary(api_message.serialize()))
+            .await;
+        ws_client.recv().await.unwrap();
+        let api_message = APIMessage::new(MessageName::HandshakeComplete, 46, signed_challenge);
         ws_client
+            .send(Message::binary(api_message.serialize()))
+            .await;
+        let resp = APIMessage::deserialize(&ws_client.recv().await.unwrap().as_bytes().to_vec());
+        assert_eq!(resp.get_message_name(), Some(MessageName::Error));
+        assert_eq!(resp.get_message_data_as_string(), "INVALID CHALLENGE");
     }
 
     #[tokio::test]
@@ -620,8 +1570,9 @@ mod tests {
         let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
         let (broadcast_channel_sender, _broadcast_channel_receiver) = broadcast::channel(32);
         // create a mock peer/socket:
-        clean_peers_dbs().await;
+        let network_context = NetworkContext::new();
         let mut ws_client = create_socket_and_do_handshake(
+            network_context.clone(),
             wallet_lock.clone(),
             mempool_lock.clone(),
             blockchain_lock.clone(),
@@ -646,7 +1597,11 @@ mod tests {
             number_of_transactions: 0,
         });
         let send_chain_message = SendBlockchainMessage::new(SyncType::Full, [0; 32], blocks_data);
-        let api_message = APIMessage::new("SNDCHAIN", 12345, send_chain_message.serialize());
+        let api_message = APIMessage::new(
+            MessageName::SendBlockchain,
+            12345,
+            send_chain_message.serialize(),
+        );
         // send SNDCHAIN request
         ws_client
             .send(Message::binary(api_message.serialize()))
@@ -676,7 +1631,7 @@ mod tests {
         // Send a mock response to the first REQBLOCK request
         let block = Block::new();
         let request_block_response = APIMessage::new(
-            "RESULT__",
+            MessageName::Result,
             api_message_request.get_message_id(),
             block.serialize_for_net(BlockType::Full),
         );
@@ -694,6 +1649,67 @@ mod tests {
         assert_eq!(request_block_request.get_block_hash().unwrap(), [2; 32]);
     }
 
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_reqrange() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let mempool_lock = Arc::new(RwLock::new(Mempool::new(wallet_lock.clone())));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let (broadcast_channel_sender, _broadcast_channel_receiver) = broadcast::channel(32);
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        test_manager.generate_blockchain(3, [0; 32]).await;
+        let network_context = NetworkContext::new();
+        let mut ws_client = create_socket_and_do_handshake(
+            network_context.clone(),
+            wallet_lock.clone(),
+            mempool_lock.clone(),
+            blockchain_lock.clone(),
+            broadcast_channel_sender.clone(),
+        )
+        .await;
+
+        // the range is cut short at our latest block
+        let api_message = APIMessage::new(
+            MessageName::RequestBlockRange,
+            77,
+            RequestBlockRangeMessage::new(2, 100).serialize(),
+        );
+        ws_client
+            .send(Message::binary(api_message.serialize()))
+            .await;
+        let resp = APIMessage::deserialize(&ws_client.recv().await.unwrap().as_bytes().to_vec());
+        assert_eq!(resp.get_message_name(), Some(MessageName::Result));
+        assert_eq!(resp.get_message_id(), 77);
+        let block_range_chunk = BlockRangeChunk::deserialize(resp.get_message_data()).unwrap();
+        assert_eq!(block_range_chunk.get_first_block_id(), 2);
+        assert_eq!(block_range_chunk.get_last_block_id(), 3);
+        let blockchain = blockchain_lock.read().await;
+        for (block_id, bytes) in (2..).zip(block_range_chunk.decompress_blocks().unwrap()) {
+            let mut block = Block::deserialize_for_net(&bytes);
+            block.generate_metadata();
+            assert_eq!(
+                block.get_hash(),
+                blockchain
+                    .blockring
+                    .get_longest_chain_block_hash_by_block_id(block_id)
+            );
+            assert_eq!(block.get_block_type(), BlockType::Full);
+        }
+        drop(blockchain);
+
+        let api_message = APIMessage::new(
+            MessageName::RequestBlockRange,
+            78,
+            RequestBlockRangeMessage::new(4, 100).serialize(),
+        );
+        ws_client
+            .send(Message::binary(api_message.serialize()))
+            .await;
+        let resp = APIMessage::deserialize(&ws_client.recv().await.unwrap().as_bytes().to_vec());
+        assert_eq!(resp.get_message_name(), Some(MessageName::Error));
+        assert_eq!(resp.get_message_data_as_string(), "UNKNOWN BLOCK RANGE");
+    }
+
     #[tokio::test]
     #[serial_test::serial]
     async fn test_sndblkhd() {
@@ -703,8 +1719,9 @@ mod tests {
         let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
         let (broadcast_channel_sender, _broadcast_channel_receiver) = broadcast::channel(32);
         // create a mock peer/socket:
-        clean_peers_dbs().await;
+        let network_context = NetworkContext::new();
         let mut ws_client = create_socket_and_do_handshake(
+            network_context.clone(),
             wallet_lock.clone(),
             mempool_lock.clone(),
             blockchain_lock.clone(),
@@ -714,8 +1731,12 @@ mod tests {
 
         // create a SNDBLKHD message
         let mock_hash = [3; 32];
-        let send_chain_message = SendBlockHeadMessage::new(mock_hash);
-        let api_message = APIMessage::new("SNDBLKHD", 12345, send_chain_message.serialize());
+        let send_chain_message = SendBlockHeadMessage::new(mock_hash, 1, 100);
+        let api_message = APIMessage::new(
+            MessageName::SendBlockHeader,
+            12345,
+            send_chain_message.serialize(),
+        );
 
         // send SNDBLKHD message through the socket
         ws_client
@@ -748,6 +1769,201 @@ mod tests {
         assert_eq!(request_block_request.get_block_hash().unwrap(), [3; 32]);
     }
 
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_sndblkhd_tip_comparison() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let mempool_lock = Arc::new(RwLock::new(Mempool::new(wallet_lock.clone())));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let (broadcast_channel_sender, _broadcast_channel_receiver) = broadcast::channel(32);
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        test_manager.generate_blockchain(3, [0; 32]).await;
+        let network_context = NetworkContext::new();
+        let mut ws_client = create_socket_and_do_handshake(
+            network_context.clone(),
+            wallet_lock.clone(),
+            mempool_lock.clone(),
+            blockchain_lock.clone(),
+            broadcast_channel_sender.clone(),
+        )
+        .await;
+
+        // a tip on a fork neither longer nor heavier than our chain is not fetched
+        let api_message = APIMessage::new(
+            MessageName::SendBlockHeader,
+            12345,
+            SendBlockHeadMessage::new([3; 32], 2, 0).serialize(),
+        );
+        ws_client
+            .send(Message::binary(api_message.serialize()))
+            .await;
+        let resp = ws_client.recv().await.unwrap();
+        let api_message_response = APIMessage::deserialize(&resp.as_bytes().to_vec());
+        assert_eq!(
+            api_message_response.get_message_name_as_string(),
+            String::from("RESULT__")
+        );
+        assert!(
+            tokio::time::timeout(Duration::from_millis(200), ws_client.recv())
+                .await
+                .is_err()
+        );
+        let peer_tip = network_context
+            .peers_db
+            .read()
+            .await
+            .values()
+            .next()
+            .unwrap()
+            .get_tip()
+            .unwrap();
+        assert_eq!(peer_tip.comparison, TipComparison::Fork);
+        assert_eq!(get_sync_source_rank(Some(peer_tip)).0, 3);
+
+        // a tip ahead of ours is fetched, and moves the network tip
+        let api_message = APIMessage::new(
+            MessageName::SendBlockHeader,
+            12346,
+            SendBlockHeadMessage::new([4; 32], 20, 0).serialize(),
+        );
+        ws_client
+            .send(Message::binary(api_message.serialize()))
+            .await;
+        ws_client.recv().await.unwrap();
+        let resp = ws_client.recv().await.unwrap();
+        let api_message_request = APIMessage::deserialize(&resp.as_bytes().to_vec());
+        assert_eq!(
+            api_message_request.get_message_name_as_string(),
+            String::from("REQBLOCK")
+        );
+        // the request holds the peers until it is answered
+        let api_message_response = APIMessage::new_from_string(
+            MessageName::Error,
+            api_message_request.get_message_id(),
+            "Unknown Block Hash",
+        );
+        ws_client
+            .send(Message::binary(api_message_response.serialize()))
+            .await;
+        let peer_tip = network_context
+            .peers_db
+            .read()
+            .await
+            .values()
+            .next()
+            .unwrap()
+            .get_tip()
+            .unwrap();
+        assert_eq!(peer_tip.comparison, TipComparison::Ahead);
+        assert_eq!(peer_tip.block_id, 20);
+        assert_eq!(
+            blockchain_lock
+                .read()
+                .await
+                .get_sync_status()
+                .get_network_tip_block_id(),
+            20
+        );
+
+        // peers ahead of us are asked first, the heaviest chain first
+        let ahead = |chain_work| PeerTip {
+            block_hash: [5; 32],
+            block_id: 20,
+            chain_work,
+            comparison: TipComparison::Ahead,
+        };
+        assert!(get_sync_source_rank(Some(ahead(10))) < get_sync_source_rank(Some(ahead(5))));
+        assert!(get_sync_source_rank(Some(ahead(5))) < get_sync_source_rank(None));
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_sndblkhd_deduplication() {
+        // mock things:
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let mempool_lock = Arc::new(RwLock::new(Mempool::new(wallet_lock.clone())));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let (broadcast_channel_sender, _broadcast_channel_receiver) = broadcast::channel(32);
+        // create a mock peer/socket:
+        let network_context = NetworkContext::new();
+        let mut ws_client = create_socket_and_do_handshake(
+            network_context.clone(),
+            wallet_lock.clone(),
+            mempool_lock.clone(),
+            blockchain_lock.clone(),
+            broadcast_channel_sender.clone(),
+        )
+        .await;
+
+        // the block is already being fetched from another peer
+        let mock_hash = [4; 32];
+        network_context
+            .recent_block_hashes
+            .write()
+            .unwrap()
+            .insert(mock_hash);
+
+        let send_block_head_message = SendBlockHeadMessage::new(mock_hash, 1, 100);
+        let api_message = APIMessage::new(
+            MessageName::SendBlockHeader,
+            12345,
+            send_block_head_message.serialize(),
+        );
+        ws_client
+            .send(Message::binary(api_message.serialize()))
+            .await;
+
+        let resp = ws_client.recv().await.unwrap();
+        let api_message_response = APIMessage::deserialize(&resp.as_bytes().to_vec());
+        assert_eq!(
+            api_message_response.get_message_name_as_string(),
+            String::from("RESULT__")
+        );
+        assert_eq!(api_message_response.get_message_id(), 12345);
+
+        // the block is not requested a second time
+        assert!(
+            tokio::time::timeout(Duration::from_millis(200), ws_client.recv())
+                .await
+                .is_err()
+        );
+
+        // the peer announced the block, so it is not announced back to it
+        {
+            let peers_db_global = network_context.peers_db.clone();
+            let peers_db = peers_db_global.read().await;
+            assert!(peers_db
+                .values()
+                .all(|peer| peer.knows_block_hash(&mock_hash)));
+        }
+        Network::propagate_block(&network_context, blockchain_lock.clone(), mock_hash).await;
+        assert!(
+            tokio::time::timeout(Duration::from_millis(200), ws_client.recv())
+                .await
+                .is_err()
+        );
+
+        // a block it does not know about is announced, but only once
+        Network::propagate_block(&network_context, blockchain_lock.clone(), [5; 32]).await;
+        Network::propagate_block(&network_context, blockchain_lock.clone(), [5; 32]).await;
+        let resp = ws_client.recv().await.unwrap();
+        let api_message_request = APIMessage::deserialize(&resp.as_bytes().to_vec());
+        assert_eq!(
+            api_message_request.get_message_name_as_string(),
+            String::from("SNDBLKHD")
+        );
+        assert_eq!(
+            SendBlockHeadMessage::deserialize(api_message_request.get_message_data())
+                .get_block_hash(),
+            &[5; 32]
+        );
+        assert!(
+            tokio::time::timeout(Duration::from_millis(200), ws_client.recv())
+                .await
+                .is_err()
+        );
+    }
+
     #[tokio::test]
     async fn missing_blocks_test() {
         let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
@@ -802,13 +2018,10 @@ mod tests {
         block_with_unknown_parent.set_burnfee(10);
         block_with_unknown_parent.set_timestamp(create_timestamp());
 
-        let mut tx =
-            Transaction::generate_vip_transaction(wallet_lock.clone(), publickey, 10_000_000, 1)
-                .await;
+        let mut tx = VipPolicy::default()
+            .create_vip_transaction(&*wallet_lock.read().await, &[(publickey, 10_000_000)]);
         tx.generate_metadata(publickey);
 
-        tx.sign(privatekey);
-
         block_with_unknown_parent.set_transactions(&mut vec![tx]);
 
         let block_merkle_root = block_with_unknown_parent.generate_merkle_root();
@@ -819,6 +2032,7 @@ mod tests {
 
         // connect a peer
         // let mut ws_client = create_socket_and_do_handshake(
+        //     network_context.clone(),
         //     wallet_lock.clone(),
         //     mempool_lock.clone(),
         //     blockchain_lock.clone(),
@@ -855,48 +2069,521 @@ mod tests {
 
     #[tokio::test]
     #[serial_test::serial]
-    async fn test_blockchain_causes_sndblkhd() {
-        // initialize peers db:
-        clean_peers_dbs().await;
-        // mock things:
-        let mut settings = get_configuration().expect("Failed to read configuration.");
-        //TODO: inject configs for testing only
-        settings.network.host = [127, 0, 0, 1];
-        settings.network.port = 3002;
-
+    async fn test_fetch_missing_block_from_archive_provider() {
         let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
         let mempool_lock = Arc::new(RwLock::new(Mempool::new(wallet_lock.clone())));
         let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
-        let (broadcast_channel_sender, broadcast_channel_receiver) = broadcast::channel(32);
-
-        let network_lock = Arc::new(RwLock::new(Network::new(
-            settings,
-            blockchain_lock.clone(),
-            mempool_lock.clone(),
+        let (broadcast_channel_sender, _broadcast_channel_receiver) = broadcast::channel(32);
+        let network_context = NetworkContext::new();
+        let mut source_ws_client = create_socket_and_do_handshake(
+            network_context.clone(),
             wallet_lock.clone(),
+            mempool_lock.clone(),
+            blockchain_lock.clone(),
             broadcast_channel_sender.clone(),
-        )));
-        // TODO
-        // This should be in the blockchain constructor.
-        // Normally this is done in Network::run, but we need to set the broadcast_channel_sender here.
-        {
-            let mut blockchain = blockchain_lock.write().await;
-            blockchain.set_broadcast_channel_sender(broadcast_channel_sender.clone());
-        }
-
-        // connect a peer
-        let mut ws_client = create_socket_and_do_handshake(
+        )
+        .await;
+        let source_connection_id = *network_context
+            .peers_db
+            .clone()
+            .read()
+            .await
+            .keys()
+            .next()
+            .unwrap();
+        let mut archive_ws_client = create_socket_and_do_handshake(
+            network_context.clone(),
             wallet_lock.clone(),
             mempool_lock.clone(),
             blockchain_lock.clone(),
             broadcast_channel_sender.clone(),
         )
         .await;
+        // without an archive provider the block is fetched from the peer which sent its child
+        Network::fetch_missing_block(&network_context, source_connection_id, [7; 32]).await;
+        let resp = source_ws_client.recv().await.unwrap();
+        let api_message_request = APIMessage::deserialize(&resp.as_bytes().to_vec());
+        assert_eq!(
+            api_message_request.get_message_name(),
+            Some(MessageName::RequestBlock)
+        );
+        let request_block_request =
+            RequestBlockMessage::deserialize(api_message_request.get_message_data());
+        assert_eq!(request_block_request.get_block_hash().unwrap(), [7; 32]);
+        // the request holds the peers until it is answered
+        let api_message_response = APIMessage::new_from_string(
+            MessageName::Error,
+            api_message_request.get_message_id(),
+            "Unknown Block Hash",
+        );
+        source_ws_client
+            .send(Message::binary(api_message_response.serialize()))
+            .await;
 
-        // Start the network listening for messages on the global broadcast channel.
-        // TODO All these things should also perhaps be passed to the network constructor
-        let wallet_lock_for_task = wallet_lock.clone();
-        let mempool_lock_for_task = mempool_lock.clone();
+        {
+            let peers_db_global = network_context.peers_db.clone();
+            let mut peers_db = peers_db_global.write().await;
+            for peer in peers_db.values_mut() {
+                if peer.get_connection_id() != source_connection_id {
+                    peer.set_roles(vec![PeerRole::ArchiveProvider]);
+                }
+            }
+        }
+        Network::fetch_missing_block(&network_context, source_connection_id, [8; 32]).await;
+        let resp = archive_ws_client.recv().await.unwrap();
+        let api_message_request = APIMessage::deserialize(&resp.as_bytes().to_vec());
+        assert_eq!(
+            api_message_request.get_message_name(),
+            Some(MessageName::RequestBlock)
+        );
+        let request_block_request =
+            RequestBlockMessage::deserialize(api_message_request.get_message_data());
+        assert_eq!(request_block_request.get_block_hash().unwrap(), [8; 32]);
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_peer_ping() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let mempool_lock = Arc::new(RwLock::new(Mempool::new(wallet_lock.clone())));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let (broadcast_channel_sender, _broadcast_channel_receiver) = broadcast::channel(32);
+        let network_context = NetworkContext::new();
+        let mut ws_client = create_socket_and_do_handshake(
+            network_context.clone(),
+            wallet_lock.clone(),
+            mempool_lock.clone(),
+            blockchain_lock.clone(),
+            broadcast_channel_sender.clone(),
+        )
+        .await;
+
+        // we are answered with the peer's timestamp
+        let request_timestamp = create_timestamp();
+        let api_message = APIMessage::new(MessageName::Ping, 90, vec![]);
+        ws_client
+            .send(Message::binary(api_message.serialize()))
+            .await;
+        let resp = APIMessage::deserialize(&ws_client.recv().await.unwrap().as_bytes().to_vec());
+        assert_eq!(resp.get_message_name(), Some(MessageName::Result));
+        assert_eq!(resp.get_message_id(), 90);
+        let peer_timestamp =
+            u64::from_be_bytes(resp.get_message_data().as_slice().try_into().unwrap());
+        assert!(peer_timestamp >= request_timestamp);
+        assert!(peer_timestamp <= create_timestamp());
+
+        // and measure the peer's clock offset when we ping it
+        network_context
+            .peers_db
+            .read()
+            .await
+            .values()
+            .next()
+            .unwrap()
+            .do_ping()
+            .await;
+        let resp = APIMessage::deserialize(&ws_client.recv().await.unwrap().as_bytes().to_vec());
+        assert_eq!(resp.get_message_name(), Some(MessageName::Ping));
+        let api_message_response = APIMessage::new(
+            MessageName::Result,
+            resp.get_message_id(),
+            (create_timestamp() + 60_000).to_be_bytes().to_vec(),
+        );
+        ws_client
+            .send(Message::binary(api_message_response.serialize()))
+            .await;
+        let clock_offset;
+        {
+            let peers_db_global = network_context.peers_db.clone();
+            let peers_db = peers_db_global.read().await;
+            let peer = peers_db.values().next().unwrap();
+            clock_offset = peer.get_clock_offset().unwrap();
+            assert!(peer.get_latency().is_some());
+            assert_eq!(
+                blockchain_lock
+                    .read()
+                    .await
+                    .get_network_time()
+                    .get_clock_offset(&peer.get_publickey().unwrap()),
+                Some(clock_offset)
+            );
+        }
+        // the response was created at some point within the round trip
+        assert!((clock_offset - 60_000).abs() < 1000);
+
+        // peers speaking an older protocol keep the offset we have
+        network_context
+            .peers_db
+            .read()
+            .await
+            .values()
+            .next()
+            .unwrap()
+            .do_ping()
+            .await;
+        let resp = APIMessage::deserialize(&ws_client.recv().await.unwrap().as_bytes().to_vec());
+        let api_message_response =
+            APIMessage::new_from_string(MessageName::Error, resp.get_message_id(), "NO SUCH");
+        ws_client
+            .send(Message::binary(api_message_response.serialize()))
+            .await;
+        let peers_db_global = network_context.peers_db.clone();
+        let mut peers_db = peers_db_global.write().await;
+        let peer = peers_db.values_mut().next().unwrap();
+        assert_eq!(peer.get_clock_offset(), Some(clock_offset));
+
+        // the offset no longer counts once the peer disconnects
+        peer.set_is_connected_or_connecting(false).await;
+        assert_eq!(
+            blockchain_lock
+                .read()
+                .await
+                .get_network_time()
+                .get_clock_offset(&peer.get_publickey().unwrap()),
+            None
+        );
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_message_log() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let mempool_lock = Arc::new(RwLock::new(Mempool::new(wallet_lock.clone())));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let (broadcast_channel_sender, _broadcast_channel_receiver) = broadcast::channel(32);
+        let network_context = NetworkContext::new();
+        let filename = "./data/test/network_messages.log";
+        std::fs::create_dir_all("./data/test/").unwrap();
+        let _ = std::fs::remove_file(filename);
+        *network_context.message_log.clone().write().unwrap() =
+            Some(MessageLog::open(filename).unwrap());
+
+        let mut ws_client = create_socket_and_do_handshake(
+            network_context.clone(),
+            wallet_lock.clone(),
+            mempool_lock.clone(),
+            blockchain_lock.clone(),
+            broadcast_channel_sender.clone(),
+        )
+        .await;
+        // pings are not consensus messages
+        let api_message = APIMessage::new(MessageName::Ping, 90, vec![]);
+        ws_client
+            .send(Message::binary(api_message.serialize()))
+            .await;
+        ws_client.recv().await.unwrap();
+        let connection_id = *network_context
+            .peers_db
+            .clone()
+            .read()
+            .await
+            .keys()
+            .next()
+            .unwrap();
+
+        let message_records = read_message_log(filename).unwrap();
+        let commands: Vec<MessageName> = message_records
+            .iter()
+            .map(|message_record| message_record.get_command())
+            .collect();
+        assert_eq!(
+            commands,
+            vec![MessageName::HandshakeInit, MessageName::HandshakeComplete]
+        );
+        assert_eq!(message_records[0].get_connection_id(), connection_id);
+
+        std::fs::remove_file(filename).unwrap();
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_tip_announcement() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let mempool_lock = Arc::new(RwLock::new(Mempool::new(wallet_lock.clone())));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let (broadcast_channel_sender, _broadcast_channel_receiver) = broadcast::channel(32);
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        test_manager.generate_blockchain(2, [0; 32]).await;
+        let network_context = NetworkContext::new();
+        let mut ws_client = create_socket_and_do_handshake(
+            network_context.clone(),
+            wallet_lock.clone(),
+            mempool_lock.clone(),
+            blockchain_lock.clone(),
+            broadcast_channel_sender.clone(),
+        )
+        .await;
+        let latest_block_hash = blockchain_lock.read().await.get_latest_block_hash();
+        network_context
+            .peers_db
+            .write()
+            .await
+            .values_mut()
+            .next()
+            .unwrap()
+            .mark_block_hash_known(latest_block_hash);
+
+        // the tip is new since the last announcement, so it was just announced
+        let previous_tip =
+            Network::announce_tip(&network_context, blockchain_lock.clone(), [0; 32]).await;
+        assert_eq!(previous_tip, latest_block_hash);
+        assert!(
+            tokio::time::timeout(Duration::from_millis(100), ws_client.recv())
+                .await
+                .is_err()
+        );
+
+        // the tip is announced again even to a peer which knows it
+        Network::announce_tip(&network_context, blockchain_lock.clone(), previous_tip).await;
+        let resp = ws_client.recv().await.unwrap();
+        let api_message = APIMessage::deserialize(&resp.as_bytes().to_vec());
+        assert_eq!(
+            api_message.get_message_name(),
+            Some(MessageName::SendBlockHeader)
+        );
+        assert_eq!(
+            SendBlockHeadMessage::deserialize(api_message.get_message_data()).get_block_hash(),
+            &latest_block_hash
+        );
+    }
+
+    fn create_node_metadata(alias: &str) -> NodeMetadata {
+        let (publickey, privatekey) = generate_keys();
+        let mut node_metadata = NodeMetadata::new(
+            publickey,
+            create_timestamp(),
+            PeerServices::default(),
+            0,
+            alias.to_string(),
+            vec![],
+        )
+        .unwrap();
+        node_metadata.sign(privatekey);
+        node_metadata
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_node_metadata_gossip() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let mempool_lock = Arc::new(RwLock::new(Mempool::new(wallet_lock.clone())));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let (broadcast_channel_sender, _broadcast_channel_receiver) = broadcast::channel(32);
+        let network_context = NetworkContext::new();
+
+        // the records we know are sent to a peer once the handshake completes
+        let known_node_metadata = create_node_metadata("known");
+        network_context
+            .node_metadata
+            .write()
+            .unwrap()
+            .insert(known_node_metadata.clone(), create_timestamp());
+        let mut ws_client = create_socket_and_do_handshake(
+            network_context.clone(),
+            wallet_lock.clone(),
+            mempool_lock.clone(),
+            blockchain_lock.clone(),
+            broadcast_channel_sender.clone(),
+        )
+        .await;
+        let resp = APIMessage::deserialize(&ws_client.recv().await.unwrap().as_bytes().to_vec());
+        assert_eq!(resp.get_message_name(), Some(MessageName::SendNodeMetadata));
+        assert_eq!(
+            NodeMetadata::deserialize(resp.get_message_data()),
+            Some(known_node_metadata)
+        );
+
+        // a record the peer gossips is kept, and not sent back to it
+        let node_metadata = create_node_metadata("gossiped");
+        let api_message =
+            APIMessage::new(MessageName::SendNodeMetadata, 80, node_metadata.serialize());
+        ws_client
+            .send(Message::binary(api_message.serialize()))
+            .await;
+        let resp = APIMessage::deserialize(&ws_client.recv().await.unwrap().as_bytes().to_vec());
+        assert_eq!(resp.get_message_name(), Some(MessageName::Result));
+        assert!(
+            tokio::time::timeout(Duration::from_millis(100), ws_client.recv())
+                .await
+                .is_err()
+        );
+
+        let mut tampered = node_metadata.serialize();
+        let last_byte = tampered.len() - 1;
+        tampered[last_byte] = b'x';
+        let api_message = APIMessage::new(MessageName::SendNodeMetadata, 81, tampered);
+        ws_client
+            .send(Message::binary(api_message.serialize()))
+            .await;
+        let resp = APIMessage::deserialize(&ws_client.recv().await.unwrap().as_bytes().to_vec());
+        assert_eq!(resp.get_message_name(), Some(MessageName::Error));
+        assert_eq!(resp.get_message_data_as_string(), "INVALID NODE METADATA");
+
+        let resp = warp::test::request()
+            .method("GET")
+            .path("/nodes")
+            .reply(&get_node_metadata_route_filter(network_context.clone()))
+            .await;
+        assert_eq!(resp.status(), 200);
+        let nodes: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(nodes.as_array().unwrap().len(), 2);
+
+        let resp = warp::test::request()
+            .method("GET")
+            .path(&format!(
+                "/nodes/{}",
+                node_metadata.get_publickey().to_base58()
+            ))
+            .reply(&get_node_metadata_by_publickey_route_filter(
+                network_context.clone(),
+            ))
+            .await;
+        assert_eq!(resp.status(), 200);
+        let node: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(node["alias"], "gossiped");
+        assert_eq!(node["services"]["full_blocks"], true);
+
+        let resp = warp::test::request()
+            .method("GET")
+            .path(&format!("/nodes/{}", generate_keys().0.to_base58()))
+            .reply(&get_node_metadata_by_publickey_route_filter(
+                network_context.clone(),
+            ))
+            .await;
+        assert_eq!(resp.status(), 404);
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_peer_services() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let mempool_lock = Arc::new(RwLock::new(Mempool::new(wallet_lock.clone())));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let (broadcast_channel_sender, _broadcast_channel_receiver) = broadcast::channel(32);
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        test_manager.generate_blockchain(3, [0; 32]).await;
+        let network_context = NetworkContext::new();
+
+        // we serve headers and the last two blocks only
+        *network_context.local_peer_services.clone().write().unwrap() = PeerServices {
+            full_blocks: false,
+            headers: true,
+            transaction_relay: false,
+            archive_depth: 2,
+        };
+        let mut ws_client = create_socket_and_do_handshake(
+            network_context.clone(),
+            wallet_lock.clone(),
+            mempool_lock.clone(),
+            blockchain_lock.clone(),
+            broadcast_channel_sender.clone(),
+        )
+        .await;
+        {
+            let peers_db_global = network_context.peers_db.clone();
+            let peers_db = peers_db_global.read().await;
+            let peer = peers_db.values().next().unwrap();
+            assert_eq!(peer.get_services(), PeerServices::default());
+        }
+
+        let block_hash = blockchain_lock.read().await.get_latest_block_hash();
+        let api_message = APIMessage::new(
+            MessageName::RequestBlock,
+            77,
+            RequestBlockMessage::new(None, Some(block_hash), None).serialize(),
+        );
+        ws_client
+            .send(Message::binary(api_message.serialize()))
+            .await;
+        let resp = APIMessage::deserialize(&ws_client.recv().await.unwrap().as_bytes().to_vec());
+        assert_eq!(resp.get_message_name(), Some(MessageName::Error));
+        assert_eq!(resp.get_message_data_as_string(), "NOT SERVED");
+
+        let api_message = APIMessage::new(
+            MessageName::RequestBlockRange,
+            78,
+            RequestBlockRangeMessage::new(1, 3).serialize(),
+        );
+        ws_client
+            .send(Message::binary(api_message.serialize()))
+            .await;
+        let resp = APIMessage::deserialize(&ws_client.recv().await.unwrap().as_bytes().to_vec());
+        assert_eq!(resp.get_message_name(), Some(MessageName::Error));
+        assert_eq!(resp.get_message_data_as_string(), "NOT SERVED");
+
+        let api_message = APIMessage::new(
+            MessageName::RequestBlockRange,
+            79,
+            RequestBlockRangeMessage::new(2, 3).serialize(),
+        );
+        ws_client
+            .send(Message::binary(api_message.serialize()))
+            .await;
+        let resp = APIMessage::deserialize(&ws_client.recv().await.unwrap().as_bytes().to_vec());
+        assert_eq!(resp.get_message_name(), Some(MessageName::Result));
+
+        // blocks are not requested from a peer which does not serve them
+        {
+            let peers_db_global = network_context.peers_db.clone();
+            let mut peers_db = peers_db_global.write().await;
+            let peer = peers_db.values_mut().next().unwrap();
+            peer.set_services(PeerServices {
+                full_blocks: false,
+                ..PeerServices::default()
+            });
+            peer.do_reqblock([7; 32]).await;
+        }
+        assert!(!network_context
+            .recent_block_hashes
+            .read()
+            .unwrap()
+            .contains(&[7; 32]));
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_blockchain_causes_sndblkhd() {
+        // mock things:
+        let mut settings = get_configuration().expect("Failed to read configuration.");
+        //TODO: inject configs for testing only
+        settings.network.host = IpAddr::from([127, 0, 0, 1]);
+        settings.network.port = 3002;
+
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let mempool_lock = Arc::new(RwLock::new(Mempool::new(wallet_lock.clone())));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let (broadcast_channel_sender, broadcast_channel_receiver) = broadcast::channel(32);
+
+        let network_lock = Arc::new(RwLock::new(Network::new(
+            settings,
+            blockchain_lock.clone(),
+            mempool_lock.clone(),
+            wallet_lock.clone(),
+            broadcast_channel_sender.clone(),
+        )));
+        let network_context = network_lock.read().await.get_network_context();
+        // TODO
+        // This should be in the blockchain constructor.
+        // Normally this is done in Network::run, but we need to set the broadcast_channel_sender here.
+        {
+            let mut blockchain = blockchain_lock.write().await;
+            blockchain.set_broadcast_channel_sender(broadcast_channel_sender.clone());
+        }
+
+        // connect a peer
+        let mut ws_client = create_socket_and_do_handshake(
+            network_context.clone(),
+            wallet_lock.clone(),
+            mempool_lock.clone(),
+            blockchain_lock.clone(),
+            broadcast_channel_sender.clone(),
+        )
+        .await;
+
+        // Start the network listening for messages on the global broadcast channel.
+        // TODO All these things should also perhaps be passed to the network constructor
+        let wallet_lock_for_task = wallet_lock.clone();
+        let mempool_lock_for_task = mempool_lock.clone();
         let blockchain_lock_for_task = blockchain_lock.clone();
         tokio::spawn(async move {
             crate::network::run(
@@ -934,7 +2621,7 @@ mod tests {
         // mock things:
         let mut settings = get_configuration().expect("Failed to read configuration.");
         //TODO: inject configs for testing only
-        settings.network.host = [127, 0, 0, 1];
+        settings.network.host = IpAddr::from([127, 0, 0, 1]);
         settings.network.port = 3002;
 
         let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
@@ -951,8 +2638,9 @@ mod tests {
         )));
 
         // connect a peer to the client
-        clean_peers_dbs().await;
+        let network_context = network_lock.read().await.get_network_context();
         let mut ws_client = create_socket_and_do_handshake(
+            network_context.clone(),
             wallet_lock.clone(),
             mempool_lock.clone(),
             blockchain_lock.clone(),
@@ -966,19 +2654,24 @@ mod tests {
                 .await
                 .unwrap();
         });
-        // send 2 message to network:
+        // send 2 message to network, a block is only announced once to each peer:
         tokio::spawn(async move {
             broadcast_channel_sender
                 .send(SaitoMessage::BlockchainSavedBlock { hash: [0; 32] })
                 .expect("error: BlockchainAddBlockFailure message failed to send");
             broadcast_channel_sender
-                .send(SaitoMessage::BlockchainSavedBlock { hash: [0; 32] })
+                .send(SaitoMessage::BlockchainSavedBlock { hash: [1; 32] })
                 .expect("error: BlockchainAddBlockFailure message failed to send");
         });
-        // These messages should prompt SNDBLKHD commands to each peer
+        // These messages should prompt SNDBLKHD commands to each peer, besides the node
+        // metadata the network publishes when it starts
         for _i in 0..2 {
-            let resp = ws_client.recv().await.unwrap();
-            let api_message_request = APIMessage::deserialize(&resp.as_bytes().to_vec());
+            let mut resp = ws_client.recv().await.unwrap();
+            let mut api_message_request = APIMessage::deserialize(&resp.as_bytes().to_vec());
+            while api_message_request.get_message_name() == Some(MessageName::SendNodeMetadata) {
+                resp = ws_client.recv().await.unwrap();
+                api_message_request = APIMessage::deserialize(&resp.as_bytes().to_vec());
+            }
             assert_eq!(
                 api_message_request.get_message_name_as_string(),
                 String::from("SNDBLKHD")
@@ -986,6 +2679,872 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_peer_bandwidth() {
+        // mock things:
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let mempool_lock = Arc::new(RwLock::new(Mempool::new(wallet_lock.clone())));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let (broadcast_channel_sender, _broadcast_channel_receiver) = broadcast::channel(32);
+        // create a mock peer/socket, the handshake is 2 requests and 2 responses:
+        let network_context = NetworkContext::new();
+        let _ws_client = create_socket_and_do_handshake(
+            network_context.clone(),
+            wallet_lock.clone(),
+            mempool_lock.clone(),
+            blockchain_lock.clone(),
+            broadcast_channel_sender.clone(),
+        )
+        .await;
+
+        let resp = warp::test::request()
+            .method("GET")
+            .path("/peerbandwidth")
+            .reply(&get_peer_bandwidth_route_filter(network_context.clone()))
+            .await;
+        assert_eq!(resp.status(), 200);
+
+        let reports: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        let reports = reports.as_array().unwrap();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0]["is_connected"], true);
+        assert_eq!(reports[0]["messages_received"], 2);
+        assert_eq!(reports[0]["messages_sent"], 2);
+        assert!(reports[0]["bytes_received"].as_u64().unwrap() > 0);
+        assert!(reports[0]["bytes_sent"].as_u64().unwrap() > 0);
+        assert_eq!(reports[0]["transactions_received"], 0);
+        assert_eq!(reports[0]["fees_received"], 0);
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_peer_stats() {
+        let network_context = NetworkContext::new();
+        {
+            let mut peer_stats = network_context.peer_stats.write().unwrap();
+            peer_stats.update("10.0.0.1:12101", |peer_stats| {
+                peer_stats.add_invalid_message()
+            });
+            peer_stats.update("10.0.0.2:12101", |peer_stats| {
+                peer_stats.add_blocks_served(10)
+            });
+        }
+
+        // the peer which served us blocks is reconnected to first, and the peer which sent
+        // us an invalid message after the peer we know nothing of
+        assert_eq!(
+            order_peers_by_stats(
+                &network_context,
+                vec![
+                    ([1; 32], Some(String::from("10.0.0.1:12101"))),
+                    ([2; 32], None),
+                    ([3; 32], Some(String::from("10.0.0.2:12101"))),
+                ]
+            ),
+            vec![[3; 32], [2; 32], [1; 32]]
+        );
+
+        let resp = warp::test::request()
+            .method("GET")
+            .path("/peerstats")
+            .reply(&get_peer_stats_route_filter(network_context.clone()))
+            .await;
+        assert_eq!(resp.status(), 200);
+        let reports: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        let reports = reports.as_array().unwrap();
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0]["address"], "10.0.0.2:12101");
+        assert_eq!(reports[0]["blocks_served"], 10);
+        assert_eq!(reports[1]["invalid_messages"], 1);
+        assert_eq!(reports[1]["latency_p50"], serde_json::Value::Null);
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_health() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let mempool_lock = Arc::new(RwLock::new(Mempool::new(wallet_lock.clone())));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let network_context = NetworkContext::new();
+
+        // an empty chain is not in sync
+        let resp = warp::test::request()
+            .method("GET")
+            .path("/health")
+            .reply(&get_health_route_filter(
+                mempool_lock.clone(),
+                blockchain_lock.clone(),
+                network_context.clone(),
+            ))
+            .await;
+        assert_eq!(resp.status(), 503);
+        let report: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(report["is_synced"], false);
+        assert_eq!(report["latest_block_id"], 0);
+        assert_eq!(report["peers_connected"], 0);
+        assert_eq!(report["mempool_transactions"], 0);
+        assert!(report["last_block_age"].is_null());
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_build_send_blockchain_message_from_fork_point() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+
+        let block3_hash = test_manager.generate_blockchain(3, [0; 32]).await;
+        let block5_hash = test_manager.generate_blockchain(2, block3_hash).await;
+        let peer_block_locator = blockchain_lock.read().await.generate_block_locator();
+        let block3_timestamp = blockchain_lock
+            .read()
+            .await
+            .get_block_sync(&block3_hash)
+            .unwrap()
+            .get_timestamp();
+        // a fork after block 3, whose earlier first block makes it win
+        let block4_hash = test_manager
+            .add_block_on_hash(block3_timestamp + 110000, 0, 0, true, vec![], block3_hash)
+            .await;
+        let block6_hash = test_manager.generate_blockchain(2, block4_hash).await;
+
+        //
+        // the peer is still on the old chain, whose tip we no longer have on
+        // our longest chain
+        //
+        let mut request_blockchain_message =
+            RequestBlockchainMessage::new(5, block5_hash, [42; 32]);
+        request_blockchain_message.set_block_locator(peer_block_locator);
+        let send_blockchain_message =
+            build_send_blockchain_message(&request_blockchain_message, blockchain_lock.clone())
+                .await
+                .unwrap();
+        assert_eq!(send_blockchain_message.get_starting_hash(), &block3_hash);
+        let block_ids: Vec<u64> = send_blockchain_message
+            .get_blocks_data()
+            .iter()
+            .map(|send_blockchain_block_data| send_blockchain_block_data.block_id)
+            .collect();
+        assert_eq!(block_ids, vec![6, 5, 4]);
+        assert_eq!(
+            send_blockchain_message.get_blocks_data()[0].block_hash,
+            block6_hash
+        );
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_sync_status() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        blockchain_lock
+            .write()
+            .await
+            .get_mut_sync_status()
+            .on_peer_tip([1; 32], 20);
+
+        let resp = warp::test::request()
+            .method("GET")
+            .path("/syncstatus")
+            .reply(&get_sync_status_route_filter(blockchain_lock.clone()))
+            .await;
+        assert_eq!(resp.status(), 200);
+
+        let report: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(report["state"], "SyncingBlocks");
+        assert_eq!(report["latest_block_id"], 0);
+        assert_eq!(report["network_tip_block_id"], 20);
+        assert_eq!(report["peers_with_tips"], 1);
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_broadcast_metrics() {
+        let resp = warp::test::request()
+            .method("GET")
+            .path("/broadcastmetrics")
+            .reply(&get_broadcast_metrics_route_filter())
+            .await;
+        assert_eq!(resp.status(), 200);
+
+        let report: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert!(report["unreceived_messages"].is_u64());
+        assert!(report["network_lagged_messages"].is_u64());
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_mempool_routes() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        test_manager.generate_blockchain(1, [0; 32]).await;
+        let mempool_lock = test_manager.mempool_lock.clone();
+
+        let transaction = test_manager.generate_transaction(1000, 1000).await;
+        let transaction_hash = hex::encode(transaction.get_hash_for_signature().unwrap());
+        mempool_lock
+            .write()
+            .await
+            .add_transaction(transaction)
+            .await
+            .unwrap();
+
+        let resp = warp::test::request()
+            .method("GET")
+            .path("/mempool")
+            .reply(&get_mempool_info_route_filter(
+                mempool_lock.clone(),
+                blockchain_lock.clone(),
+            ))
+            .await;
+        assert_eq!(resp.status(), 200);
+        let mempool_info: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(mempool_info["transaction_count"], 1);
+        assert_eq!(mempool_info["total_fees"], 1000);
+        assert!(mempool_info["bytes"].as_u64().unwrap() > 0);
+        assert_eq!(mempool_info["routing_work_available"], 0);
+        assert!(mempool_info["routing_work_needed"].is_u64());
+
+        let resp = warp::test::request()
+            .method("GET")
+            .path("/mempool/transactions")
+            .reply(&get_raw_mempool_route_filter(mempool_lock.clone()))
+            .await;
+        assert_eq!(resp.status(), 200);
+        let raw_mempool: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        let raw_mempool = raw_mempool.as_array().unwrap();
+        assert_eq!(raw_mempool.len(), 1);
+        assert_eq!(raw_mempool[0]["hash"], transaction_hash);
+        assert_eq!(raw_mempool[0]["total_fees"], 1000);
+        assert_eq!(raw_mempool[0]["routing_hops"], 0);
+        assert_eq!(raw_mempool[0]["routing_work_for_us"], 0);
+
+        let resp = warp::test::request()
+            .method("GET")
+            .path(&format!("/mempool/transactions/{}", transaction_hash))
+            .reply(&get_mempool_transaction_route_filter(mempool_lock.clone()))
+            .await;
+        assert_eq!(resp.status(), 200);
+        let transaction_json: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(transaction_json["hash"], transaction_hash);
+        assert_eq!(transaction_json["transaction_type"], "Normal");
+        assert_eq!(transaction_json["inputs"][0]["slip_type"], "Normal");
+        assert_eq!(
+            transaction_json["inputs"][0]["publickey"],
+            hex::encode(wallet_lock.read().await.get_publickey())
+        );
+
+        let resp = warp::test::request()
+            .method("GET")
+            .path(&format!("/mempool/transactions/{}", hex::encode([0; 32])))
+            .reply(&get_mempool_transaction_route_filter(mempool_lock.clone()))
+            .await;
+        assert_eq!(resp.status(), 404);
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_transaction_batch_route() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        test_manager.generate_blockchain(1, [0; 32]).await;
+        let mempool_lock = test_manager.mempool_lock.clone();
+        let privatekey = wallet_lock.read().await.get_privatekey();
+
+        let transaction = test_manager.generate_transaction(1000, 1000).await;
+        let other_transaction = test_manager.generate_transaction(1000, 1000).await;
+        let mut double_spend = transaction.clone();
+        double_spend.set_message(vec![1, 2, 3]);
+        double_spend.sign(privatekey);
+
+        let mut body = vec![];
+        for bytes in [
+            transaction.serialize_for_net(),
+            vec![0; 10],
+            transaction.serialize_for_net(),
+            double_spend.serialize_for_net(),
+            other_transaction.serialize_for_net(),
+        ] {
+            body.extend(&(bytes.len() as u32).to_be_bytes());
+            body.extend(bytes);
+        }
+
+        let resp = warp::test::request()
+            .method("POST")
+            .path("/sendtransactions")
+            .body(body.clone())
+            .reply(&post_transaction_batch_route_filter(
+                wallet_lock.clone(),
+                mempool_lock.clone(),
+                blockchain_lock.clone(),
+            ))
+            .await;
+        assert_eq!(resp.status(), 200);
+        let results: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        let results = results.as_array().unwrap();
+        assert_eq!(results.len(), 5);
+        assert_eq!(
+            results[0]["signature"],
+            transaction.get_signature().to_base58()
+        );
+        assert_eq!(results[1]["error"], "malformed transaction");
+        assert_eq!(results[2]["error"], "already in the mempool");
+        assert_eq!(
+            results[3]["error"],
+            "spends a slip spent earlier in the batch"
+        );
+        assert_eq!(
+            results[4]["signature"],
+            other_transaction.get_signature().to_base58()
+        );
+        assert!(results[4]["error"].is_null());
+        assert_eq!(mempool_lock.read().await.transactions.len(), 2);
+
+        // a body which cannot be split into transactions is rejected as a whole
+        let resp = warp::test::request()
+            .method("POST")
+            .path("/sendtransactions")
+            .body(&body[..body.len() - 1])
+            .reply(&post_transaction_batch_route_filter(
+                wallet_lock.clone(),
+                mempool_lock.clone(),
+                blockchain_lock.clone(),
+            ))
+            .await;
+        assert_ne!(resp.status(), 200);
+
+        // as is a batch of more than MAX_TRANSACTIONS_PER_BATCH transactions
+        let resp = warp::test::request()
+            .method("POST")
+            .path("/sendtransactions")
+            .body(vec![
+                0;
+                TRANSACTION_LENGTH_PREFIX_SIZE
+                    * (MAX_TRANSACTIONS_PER_BATCH + 1)
+            ])
+            .reply(&post_transaction_batch_route_filter(
+                wallet_lock.clone(),
+                mempool_lock.clone(),
+                blockchain_lock.clone(),
+            ))
+            .await;
+        assert_ne!(resp.status(), 200);
+        assert_eq!(mempool_lock.read().await.transactions.len(), 2);
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_blocks_route() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        test_manager.generate_blockchain(5, [0; 32]).await;
+
+        let resp = warp::test::request()
+            .method("GET")
+            .path("/blocks?from_id=2&to_id=4&limit=2")
+            .reply(&get_blocks_route_filter(blockchain_lock.clone()))
+            .await;
+        assert_eq!(resp.status(), 200);
+        let blocks_page: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        let blocks = blocks_page["blocks"].as_array().unwrap();
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0]["id"], 2);
+        assert_eq!(blocks[1]["id"], 3);
+        assert_eq!(blocks_page["next_from_id"], 4);
+
+        let blockchain = blockchain_lock.read().await;
+        let block_hash = blockchain
+            .blockring
+            .get_longest_chain_block_hash_by_block_id(2);
+        let block = blockchain.get_block_sync(&block_hash).unwrap();
+        assert_eq!(blocks[0]["hash"], hex::encode(block_hash));
+        assert_eq!(blocks[0]["timestamp"], block.get_timestamp());
+        assert_eq!(
+            blocks[0]["transaction_count"],
+            block.get_transactions().len()
+        );
+        assert_eq!(blocks[0]["total_fees"], block.get_total_fees());
+        let block_json = block.to_json();
+        drop(blockchain);
+
+        let resp = warp::test::request()
+            .method("GET")
+            .path(&format!("/block/{}/json", hex::encode(block_hash)))
+            .reply(&get_block_json_route_filter(
+                blockchain_lock.read().await.get_block_store(),
+            ))
+            .await;
+        assert_eq!(resp.status(), 200);
+        let resp_json: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(resp_json, block_json);
+        assert_eq!(resp_json["hash"], hex::encode(block_hash));
+        assert_eq!(resp_json["id"], 2);
+        assert_eq!(resp_json["block_type"], "Full");
+
+        let resp = warp::test::request()
+            .method("GET")
+            .path("/blocks")
+            .reply(&get_blocks_route_filter(blockchain_lock.clone()))
+            .await;
+        assert_eq!(resp.status(), 200);
+        let blocks_page: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(blocks_page["blocks"].as_array().unwrap().len(), 5);
+        assert!(blocks_page["next_from_id"].is_null());
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_explorer_block_route() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        test_manager.generate_blockchain(5, [0; 32]).await;
+
+        let blockchain = blockchain_lock.read().await;
+        let block = blockchain.get_latest_block().unwrap();
+        let fee_transaction = block.get_fee_transaction().unwrap();
+        let block_store = blockchain.get_block_store();
+
+        let resp = warp::test::request()
+            .method("GET")
+            .path(&format!(
+                "/explorer/block/{}",
+                hex::encode(block.get_hash())
+            ))
+            .reply(&get_explorer_block_route_filter(block_store.clone()))
+            .await;
+        assert_eq!(resp.status(), 200);
+        let resp_json: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(resp_json["header"], block.header_to_json());
+        assert!(resp_json["header"]["transactions"].is_null());
+        let transactions = resp_json["transactions"].as_array().unwrap();
+        assert_eq!(transactions.len(), block.get_transactions().len());
+        assert_eq!(transactions[0]["transaction_type"], "GoldenTicket");
+        assert_eq!(
+            transactions[transactions.len() - 1]["transaction_type"],
+            "Fee"
+        );
+
+        //
+        // the payouts are those of the fee transaction
+        //
+        let payouts = &resp_json["payouts"];
+        let miners = payouts["miners"].as_array().unwrap();
+        assert_eq!(miners.len(), 1);
+        let total_payout: u64 = ["miners", "routers", "stakers"]
+            .iter()
+            .flat_map(|kind| payouts[kind].as_array().unwrap().iter())
+            .map(|recipient| recipient["amount"].as_u64().unwrap())
+            .sum();
+        assert_eq!(
+            total_payout,
+            fee_transaction
+                .get_outputs()
+                .iter()
+                .map(|output| u64::from(output.get_amount()))
+                .sum::<u64>()
+        );
+        assert_eq!(
+            total_payout,
+            block.get_payout_breakdown().get_total_payout()
+        );
+        assert_eq!(resp_json["atr"]["rebroadcast_slips"], 0);
+
+        let resp = warp::test::request()
+            .method("GET")
+            .path(&format!("/explorer/block/{}", hex::encode([7; 32])))
+            .reply(&get_explorer_block_route_filter(block_store.clone()))
+            .await;
+        assert_eq!(resp.status(), 404);
+        let resp = warp::test::request()
+            .method("GET")
+            .path("/explorer/block/nothex")
+            .reply(&get_explorer_block_route_filter(block_store))
+            .await;
+        assert_ne!(resp.status(), 200);
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_block_payouts_route() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        test_manager.generate_blockchain(5, [0; 32]).await;
+
+        let blockchain = blockchain_lock.read().await;
+        let block = blockchain.get_latest_block().unwrap();
+        let fee_transaction = block.get_fee_transaction().unwrap();
+        let block_payouts = blockchain.get_block_payouts(&block.get_hash()).unwrap();
+
+        let resp = warp::test::request()
+            .method("GET")
+            .path(&format!("/block/{}/payouts", hex::encode(block.get_hash())))
+            .reply(&get_block_payouts_route_filter(blockchain_lock.clone()))
+            .await;
+        assert_eq!(resp.status(), 200);
+        let resp_json: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        let payouts = resp_json.as_array().unwrap();
+        assert_eq!(payouts.len(), block_payouts.len());
+
+        assert_eq!(payouts[0], block_payouts[0].to_json());
+
+        //
+        // the winners are those paid by the fee transaction
+        //
+        let miner_output = &fee_transaction.get_outputs()[0];
+        assert_eq!(
+            payouts[0]["miner"],
+            hex::encode(miner_output.get_publickey())
+        );
+        assert_eq!(
+            payouts
+                .iter()
+                .map(|payout| payout["miner_payout"].as_u64().unwrap()
+                    + payout["router_payout"].as_u64().unwrap()
+                    + payout["staker_payout"].as_u64().unwrap())
+                .sum::<u64>(),
+            fee_transaction
+                .get_outputs()
+                .iter()
+                .map(|output| u64::from(output.get_amount()))
+                .sum::<u64>()
+        );
+
+        let resp = warp::test::request()
+            .method("GET")
+            .path(&format!("/block/{}/payouts", hex::encode([7; 32])))
+            .reply(&get_block_payouts_route_filter(blockchain_lock.clone()))
+            .await;
+        assert_eq!(resp.status(), 404);
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_balance_routes() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        test_manager.generate_blockchain(1, [0; 32]).await;
+        let publickey = hex::encode(wallet_lock.read().await.get_publickey());
+
+        let resp = warp::test::request()
+            .method("GET")
+            .path(&format!("/balance/{}", publickey))
+            .reply(&get_balance_route_filter(blockchain_lock.clone()))
+            .await;
+        assert_eq!(resp.status(), 200);
+        let balance: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(balance["publickey"], publickey);
+        assert_eq!(balance["balance"], 10 * 10_000_000);
+        assert_eq!(balance["unspent_slip_count"], 10);
+
+        let resp = warp::test::request()
+            .method("GET")
+            .path(&format!("/utxos/{}", publickey))
+            .reply(&get_unspent_slips_route_filter(blockchain_lock.clone()))
+            .await;
+        assert_eq!(resp.status(), 200);
+        let unspent_slips: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        let unspent_slips = unspent_slips.as_array().unwrap();
+        assert_eq!(unspent_slips.len(), 10);
+        assert!(unspent_slips
+            .iter()
+            .all(|slip| slip["publickey"] == publickey && slip["amount"] == 10_000_000));
+
+        let resp = warp::test::request()
+            .method("GET")
+            .path(&format!("/balance/{}", hex::encode([2; 33])))
+            .reply(&get_balance_route_filter(blockchain_lock.clone()))
+            .await;
+        let balance: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(balance["balance"], 0);
+
+        let resp = warp::test::request()
+            .method("GET")
+            .path("/balance/nothex")
+            .reply(&get_balance_route_filter(blockchain_lock.clone()))
+            .await;
+        assert_ne!(resp.status(), 200);
+
+        let resp = warp::test::request()
+            .method("GET")
+            .path("/utxoset")
+            .reply(&get_utxoset_info_route_filter(blockchain_lock.clone()))
+            .await;
+        assert_eq!(resp.status(), 200);
+        let utxoset_info: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(utxoset_info["latest_block_id"], 1);
+        assert_eq!(utxoset_info["unspent_slips"], 10);
+        assert_eq!(utxoset_info["total_nolan"], 10 * 10_000_000);
+        assert_eq!(utxoset_info["dust_slips"], 10);
+        assert!(utxoset_info["entries"].as_u64().unwrap() >= 10);
+        assert!(utxoset_info["memory_bytes"].as_u64().unwrap() > 0);
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_chain_stats_route() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        test_manager.generate_blockchain(5, [0; 32]).await;
+
+        let resp = warp::test::request()
+            .method("GET")
+            .path("/chain/stats?blocks=3")
+            .reply(&get_chain_stats_route_filter(blockchain_lock.clone()))
+            .await;
+        assert_eq!(resp.status(), 200);
+        let chain_stats: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(
+            chain_stats,
+            serde_json::to_value(blockchain_lock.read().await.get_chain_stats(3)).unwrap()
+        );
+        assert_eq!(chain_stats["from_block_id"], 3);
+        assert_eq!(chain_stats["to_block_id"], 5);
+        assert_eq!(chain_stats["difficulty"].as_array().unwrap().len(), 3);
+
+        let resp = warp::test::request()
+            .method("GET")
+            .path("/chain/stats")
+            .reply(&get_chain_stats_route_filter(blockchain_lock.clone()))
+            .await;
+        let chain_stats: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(chain_stats["blocks"], 5);
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_reorgs_route() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut reorg_log = ReorgLog::default();
+        reorg_log.record(ReorgEvent::new(
+            create_timestamp(),
+            ([1; 32], 10),
+            ([2; 32], 11),
+            &[[1; 32], [3; 32]],
+        ));
+        blockchain_lock.write().await.set_reorg_log(reorg_log);
+
+        let resp = warp::test::request()
+            .method("GET")
+            .path("/reorgs")
+            .reply(&get_reorgs_route_filter(blockchain_lock.clone()))
+            .await;
+        assert_eq!(resp.status(), 200);
+        let reorgs: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        let reorgs = reorgs.as_array().unwrap();
+        assert_eq!(reorgs.len(), 1);
+        assert_eq!(reorgs[0]["old_tip"], hex::encode([1; 32]));
+        assert_eq!(reorgs[0]["new_tip_id"], 11);
+        assert_eq!(reorgs[0]["depth"], 2);
+        assert_eq!(reorgs[0]["displaced_block_hashes"][1], hex::encode([3; 32]));
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_search_transactions_route() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+
+        // transactions are not indexed by default
+        let resp = warp::test::request()
+            .method("GET")
+            .path(&format!("/transactions/search/{}", hex::encode(b"app1")))
+            .reply(&get_search_transactions_route_filter(
+                blockchain_lock.clone(),
+            ))
+            .await;
+        assert_ne!(resp.status(), 200);
+
+        let mut transaction = Transaction::new();
+        transaction.set_message(b"app1-record".to_vec());
+        transaction.set_hash_for_signature(transaction.generate_hash_for_signature());
+        let mut block = Block::new();
+        block.set_id(1);
+        block.set_transactions(&mut vec![transaction]);
+        block.generate_hashes();
+        let mut memo_index = MemoIndex::new(4);
+        memo_index.on_chain_reorganization(&block, true);
+        blockchain_lock
+            .write()
+            .await
+            .set_memo_index(Some(memo_index));
+
+        let resp = warp::test::request()
+            .method("GET")
+            .path(&format!("/transactions/search/{}", hex::encode(b"app1")))
+            .reply(&get_search_transactions_route_filter(
+                blockchain_lock.clone(),
+            ))
+            .await;
+        assert_eq!(resp.status(), 200);
+        let results: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        let results = results.as_array().unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0]["block_hash"], hex::encode(block.get_hash()));
+        assert_eq!(results[0]["block_id"], 1);
+
+        let resp = warp::test::request()
+            .method("GET")
+            .path("/transactions/search/not-hex")
+            .reply(&get_search_transactions_route_filter(
+                blockchain_lock.clone(),
+            ))
+            .await;
+        assert_ne!(resp.status(), 200);
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_block_template_routes() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        //
+        // block 1 is old enough for the next block to need no routing work
+        //
+        let block1_hash = test_manager
+            .add_block(create_timestamp() - 120000, 3, 0, false, vec![])
+            .await;
+        let mempool_lock = test_manager.mempool_lock.clone();
+
+        let transaction = test_manager.generate_transaction(1000, 1000).await;
+        mempool_lock
+            .write()
+            .await
+            .add_transaction(transaction)
+            .await
+            .unwrap();
+
+        let resp = warp::test::request()
+            .method("GET")
+            .path("/blocktemplate")
+            .reply(&get_block_template_route_filter(
+                mempool_lock.clone(),
+                blockchain_lock.clone(),
+            ))
+            .await;
+        assert_eq!(resp.status(), 200);
+        let block_template = Block::deserialize_for_net(&resp.body().to_vec());
+        assert_eq!(block_template.get_id(), 2);
+        assert_eq!(block_template.get_previous_block_hash(), block1_hash);
+        assert_eq!(block_template.get_transactions().len(), 1);
+
+        // the template leaves the transactions in the mempool
+        assert_eq!(mempool_lock.read().await.transactions.len(), 1);
+
+        let resp = warp::test::request()
+            .method("POST")
+            .path("/submitblock")
+            .body(resp.body())
+            .reply(&post_submit_block_route_filter(
+                mempool_lock.clone(),
+                blockchain_lock.clone(),
+            ))
+            .await;
+        assert_eq!(resp.status(), 200);
+        let report: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(report["is_longest_chain"], true);
+        assert_eq!(blockchain_lock.read().await.get_latest_block_id(), 2);
+        assert!(mempool_lock.read().await.transactions.is_empty());
+
+        // a body which is not a block is rejected
+        let resp = warp::test::request()
+            .method("POST")
+            .path("/submitblock")
+            .body(vec![0; 16])
+            .reply(&post_submit_block_route_filter(
+                mempool_lock.clone(),
+                blockchain_lock.clone(),
+            ))
+            .await;
+        assert!(!resp.status().is_success());
+        assert_eq!(blockchain_lock.read().await.get_latest_block_id(), 2);
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_peer_filter() {
+        // mock things:
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let mempool_lock = Arc::new(RwLock::new(Mempool::new(wallet_lock.clone())));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let (broadcast_channel_sender, _broadcast_channel_receiver) = broadcast::channel(32);
+        // create a mock peer/socket:
+        let network_context = NetworkContext::new();
+        let _ws_client = create_socket_and_do_handshake(
+            network_context.clone(),
+            wallet_lock.clone(),
+            mempool_lock.clone(),
+            blockchain_lock.clone(),
+            broadcast_channel_sender.clone(),
+        )
+        .await;
+
+        // pin ourselves to some other peer, the mock peer should be disconnected
+        let (trusted_publickey, _) = generate_keys();
+        let peer_filter_settings = PeerFilterSettings {
+            allowed_publickeys: vec![trusted_publickey.to_base58()],
+            denied_ip_ranges: vec![String::from("10.0.0.0/8")],
+            ..PeerFilterSettings::default()
+        };
+        let resp = warp::test::request()
+            .method("PUT")
+            .path("/peerfilter")
+            .json(&peer_filter_settings)
+            .reply(&put_peer_filter_route_filter(network_context.clone()))
+            .await;
+        assert_eq!(resp.status(), 200);
+        {
+            let peers_db_global = network_context.peers_db.clone();
+            let peer_db = peers_db_global.read().await;
+            assert!(peer_db
+                .values()
+                .all(|peer| !peer.get_is_connected_or_connecting()));
+        }
+
+        let resp = warp::test::request()
+            .method("GET")
+            .path("/peerfilter")
+            .reply(&get_peer_filter_route_filter(network_context.clone()))
+            .await;
+        assert_eq!(resp.status(), 200);
+        let returned_peer_filter_settings: PeerFilterSettings =
+            serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(
+            returned_peer_filter_settings.allowed_publickeys,
+            peer_filter_settings.allowed_publickeys
+        );
+        assert_eq!(
+            returned_peer_filter_settings.denied_ip_ranges,
+            vec![String::from("10.0.0.0/8")]
+        );
+
+        // an invalid filter is rejected and leaves the current one in place
+        let resp = warp::test::request()
+            .method("PUT")
+            .path("/peerfilter")
+            .json(&PeerFilterSettings {
+                denied_ip_ranges: vec![String::from("not an ip range")],
+                ..PeerFilterSettings::default()
+            })
+            .reply(&put_peer_filter_route_filter(network_context.clone()))
+            .await;
+        assert!(!resp.status().is_success());
+        assert!(!is_peer_allowed(
+            &network_context,
+            None,
+            Some(IpAddr::from([10, 1, 2, 3]))
+        ));
+    }
+
     //////// TEST SNDTRANS ////////
     // TODO: currently the main logic "test sndtrans to peers" passed. But there is no way to get
     // tx to be validated & send it to peer in the test. We may figured out how to get tx validation
@@ -1000,11 +3559,12 @@ mod tests {
     //     let mempool_lock = Arc::new(RwLock::new(Mempool::new(wallet_lock.clone())));
     //     let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
     //     // create a mock peer/socket:
-    //     clean_peers_dbs().await;
+    //     let network_context = NetworkContext::new();
     //
     //     let wallet = wallet_lock.read().await;
     //
     //     let mut ws_client = create_socket_and_do_handshake(
+    //         network_context.clone(),
     //         wallet_lock.clone(),
     //         mempool_lock.clone(),
     //         blockchain_lock.clone(),
@@ -1031,7 +3591,7 @@ mod tests {
     //     mock_tx.set_path(mock_path);
     //
     //     let serialized_tx = mock_tx.serialize_for_net();
-    //     let api_message = APIMessage::new("SNDTRANS", 67890, serialized_tx);
+    //     let api_message = APIMessage::new(MessageName::SendTransaction, 67890, serialized_tx);
     //
     //     // send SNDTRANS message through the socket
     //     ws_client
@@ -1065,8 +3625,9 @@ mod tests {
     //     let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
     //     let (broadcast_channel_sender, broadcast_channel_receiver) = broadcast::channel(32);
     //     // connect a peer to the client
-    //     clean_peers_dbs().await;
+    //     let network_context = NetworkContext::new();
     //     let mut ws_client = create_socket_and_do_handshake(
+    //         network_context.clone(),
     //         wallet_lock.clone(),
     //         mempool_lock.clone(),
     //         blockchain_lock.clone(),
@@ -1118,6 +3679,7 @@ mod tests {
     //     let blockchain_lock2 = Arc::new(RwLock::new(Blockchain::new(wallet_lock2.clone())));
     //     let (_broadcast_channel_sender, broadcast_channel_receiver) = broadcast::channel(32);
     //     let mut ws_client2 = create_socket_and_do_handshake(
+    //         network_context.clone(),
     //         wallet_lock2.clone(),
     //         mempool_lock2.clone(),
     //         blockchain_lock2.clone(),
@@ -1169,9 +3731,10 @@ mod tests {
     //     let mempool_lock = Arc::new(RwLock::new(Mempool::new(wallet_lock.clone())));
     //     let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
     //     // create a mock peer/socket:
-    //     clean_peers_dbs().await;
+    //     let network_context = NetworkContext::new();
     //
     //     let mut ws_client = create_socket_and_do_handshake(
+    //         network_context.clone(),
     //         wallet_lock.clone(),
     //         mempool_lock.clone(),
     //         blockchain_lock.clone(),
@@ -1203,7 +3766,7 @@ mod tests {
     //     mock_tx.set_path(mock_path);
     //
     //     let serialized_tx = mock_tx.serialize_for_net();
-    //     let api_message = APIMessage::new("SNDTRANS", 67890, serialized_tx);
+    //     let api_message = APIMessage::new(MessageName::SendTransaction, 67890, serialized_tx);
     //
     //     // create 2nd mock peer/socket
     //     let wallet_lock2 = Arc::new(RwLock::new(Wallet::new()));
@@ -1211,6 +3774,7 @@ mod tests {
     //     let blockchain_lock2 = Arc::new(RwLock::new(Blockchain::new(wallet_lock2.clone())));
     //
     //     let mut ws_client2 = create_socket_and_do_handshake(
+    //         network_context.clone(),
     //         wallet_lock2.clone(),
     //         mempool_lock2.clone(),
     //         blockchain_lock2.clone(),
@@ -1,25 +1,111 @@
+use macros::MessageNames;
 use serde::{Deserialize, Serialize};
 use std::convert::TryInto;
+use std::fmt;
+
+/// The version of the peer protocol described by MessageName and the message types. Bump it
+/// with any change to the messages on the wire.
+pub const PROTOCOL_VERSION: u32 = 8;
+
+/// Where the protocol documentation generated by generate_protocol_documentation() is kept.
+pub const PROTOCOL_DOCUMENTATION_PATH: &str = "docs/protocol.md";
+
+/// The bytes of an APIMessage before its data: [message name - 8 bytes][message id - u32]
+pub const API_MESSAGE_HEADER_SIZE: usize = 12;
+
+/// The names of the APIMessages peers exchange. Commands are matched exhaustively by the peer
+/// handler, so adding a message here fails to build until the message is handled.
+///
+/// The doc comments are the descriptions in the generated protocol documentation.
+#[derive(MessageNames, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MessageName {
+    /// A response to a request. The message id is the id of the request.
+    #[message_name = "RESULT__"]
+    Result,
+    /// An error response to a request, with a UTF8 encoded error message. The message id is
+    /// the id of the request.
+    #[message_name = "ERROR___"]
+    Error,
+    /// Opens a handshake with our address, publickey, a random nonce and the services we
+    /// serve. The response is a HandshakeChallenge signed by the peer, which must carry our
+    /// nonce.
+    #[message_name = "SHAKINIT"]
+    HandshakeInit,
+    /// Completes a handshake with the HandshakeChallenge signed by both peers. A challenge is
+    /// accepted once, on the connection it was issued on and before it expires. The response
+    /// is OK followed by the services the peer serves.
+    #[message_name = "SHAKCOMP"]
+    HandshakeComplete,
+    /// Asks for the peer's longest chain after our latest block, as a RequestBlockchainMessage.
+    #[message_name = "REQCHAIN"]
+    RequestBlockchain,
+    /// Sends the block ids and hashes of our longest chain, as a SendBlockchainMessage.
+    #[message_name = "SNDCHAIN"]
+    SendBlockchain,
+    /// Asks for the header of a block by its hash. The peer responds OK and then sends the
+    /// header serialized for the network with SNDBLKHD.
+    #[message_name = "REQBLKHD"]
+    RequestBlockHeader,
+    /// Announces a block by its hash, as a SendBlockHeadMessage.
+    #[message_name = "SNDBLKHD"]
+    SendBlockHeader,
+    /// Relays a transaction serialized for the network.
+    #[message_name = "SNDTRANS"]
+    SendTransaction,
+    /// Asks for a full block, as a RequestBlockMessage. The response is the block serialized
+    /// for the network.
+    #[message_name = "REQBLOCK"]
+    RequestBlock,
+    /// Asks an archive provider for the blocks of its longest chain from one block id to
+    /// another, as a RequestBlockRangeMessage. The response is a BlockRangeChunk with the
+    /// compressed blocks from the first id on, as many as fit in a chunk. The rest of the range
+    /// is requested again from the id after the last block of the chunk.
+    #[message_name = "REQRANGE"]
+    RequestBlockRange,
+    /// Gossips the signed metadata record of a node, as a NodeMetadata. The record is sent on
+    /// to other peers if it is newer than the one we have of the node.
+    #[message_name = "SNDNMETA"]
+    SendNodeMetadata,
+    /// Measures the round trip to the peer and the offset of its clock. The response is the
+    /// peer's timestamp when it handled the ping, as a u64.
+    #[message_name = "PING____"]
+    Ping,
+    /// Sends a list of publickeys the peer wants transactions for. Not yet supported.
+    #[message_name = "SNDKYLST"]
+    SendKeyList,
+}
+
+impl fmt::Display for MessageName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", String::from_utf8_lossy(self.as_bytes()))
+    }
+}
+
+/// Writes the message names and their descriptions as a markdown document.
+pub fn generate_protocol_documentation() -> String {
+    let mut documentation = format!(
+        "# Saito Peer Protocol\n\n\
+         Protocol version {}. Generated from networking::api_message::MessageName, do not edit.\n\n\
+         | Name | Message | Description |\n\
+         | --- | --- | --- |\n",
+        PROTOCOL_VERSION
+    );
+    for message_name in MessageName::ALL {
+        documentation.push_str(&format!(
+            "| `{}` | {:?} | {} |\n",
+            message_name,
+            message_name,
+            message_name.get_description()
+        ));
+    }
+    documentation
+}
 
 /// The core data type for transporting data across the Saito Network.
 /// See the Network module doc for more details.
 ///
-/// ```bytes
-/// ERROR___
-/// RESULT__
-/// ```
-///
-/// ```bytes
-/// SHAKINIT
-/// SHAKCOMP
-/// REQCHAIN
-/// SNDCHAIN
-/// REQBLKHD
-/// SNDBLKHD
-/// SNDTRANS
-/// REQBLOCK
-/// SNDKYLST
-/// ```
+/// The message name is kept as it came off the wire, so that messages with names we do not
+/// know can still be answered with an error.
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct APIMessage {
     pub message_name: [u8; 8],
@@ -28,15 +114,15 @@ pub struct APIMessage {
 }
 
 impl APIMessage {
-    pub fn new(message_name: &str, message_id: u32, message_data: Vec<u8>) -> APIMessage {
+    pub fn new(message_name: MessageName, message_id: u32, message_data: Vec<u8>) -> APIMessage {
         APIMessage {
-            message_name: String::from(message_name).as_bytes().try_into().unwrap(),
+            message_name: *message_name.as_bytes(),
             message_id,
             message_data,
         }
     }
     pub fn new_from_string(
-        message_name: &str,
+        message_name: MessageName,
         message_id: u32,
         message_string: &str,
     ) -> APIMessage {
@@ -46,8 +132,9 @@ impl APIMessage {
             message_string.as_bytes().try_into().unwrap(),
         )
     }
-    pub fn get_message_name(&self) -> &[u8; 8] {
-        &self.message_name
+    /// The name of the message, or None if it is not one we know.
+    pub fn get_message_name(&self) -> Option<MessageName> {
+        MessageName::from_bytes(&self.message_name)
     }
     pub fn get_message_name_as_string(&self) -> String {
         String::from_utf8_lossy(&self.message_name).to_string()
@@ -64,6 +151,10 @@ impl APIMessage {
     pub fn get_message_data_as_string(&self) -> String {
         String::from_utf8_lossy(&self.message_data).to_string()
     }
+    /// The number of bytes this message takes up on the wire.
+    pub fn get_serialized_size(&self) -> usize {
+        API_MESSAGE_HEADER_SIZE + self.message_data.len()
+    }
     pub fn deserialize(bytes: &Vec<u8>) -> APIMessage {
         let message_name: [u8; 8] = bytes[0..8].try_into().unwrap();
         let message_id: u32 = u32::from_be_bytes(bytes[8..12].try_into().unwrap());
@@ -85,7 +176,9 @@ impl APIMessage {
 
 #[cfg(test)]
 mod tests {
-    use crate::networking::api_message::APIMessage;
+    use crate::networking::api_message::{
+        generate_protocol_documentation, APIMessage, MessageName, PROTOCOL_DOCUMENTATION_PATH,
+    };
     use std::convert::TryInto;
 
     #[tokio::test]
@@ -101,4 +194,45 @@ mod tests {
         let deserialized_api_message = APIMessage::deserialize(&serialized_api_message);
         assert_eq!(api_message, deserialized_api_message);
     }
+
+    #[test]
+    fn message_name_test() {
+        for message_name in MessageName::ALL {
+            assert_eq!(
+                MessageName::from_bytes(message_name.as_bytes()),
+                Some(*message_name)
+            );
+            assert!(!message_name.get_description().is_empty());
+            let api_message = APIMessage::new(*message_name, 1, vec![]);
+            assert_eq!(
+                APIMessage::deserialize(&api_message.serialize()).get_message_name(),
+                Some(*message_name)
+            );
+        }
+        assert_eq!(MessageName::SendBlockHeader.to_string(), "SNDBLKHD");
+        assert_eq!(MessageName::from_bytes(b"HLLOWRLD"), None);
+    }
+
+    #[test]
+    fn protocol_documentation_is_current_test() {
+        //
+        // fails if a message name or description changes. If the change is intended, write
+        // the documentation again with `cargo test write_protocol_documentation -- --ignored`.
+        //
+        assert_eq!(
+            std::fs::read_to_string(PROTOCOL_DOCUMENTATION_PATH).unwrap(),
+            generate_protocol_documentation()
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn write_protocol_documentation() {
+        std::fs::create_dir_all("docs").unwrap();
+        std::fs::write(
+            PROTOCOL_DOCUMENTATION_PATH,
+            generate_protocol_documentation(),
+        )
+        .unwrap();
+    }
 }
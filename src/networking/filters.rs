@@ -1,18 +1,31 @@
+use crate::block_store::BlockStore;
 use crate::blockchain::Blockchain;
 use crate::consensus::SaitoMessage;
+use crate::consensus_params::ConsensusParams;
 use crate::mempool::Mempool;
-use crate::network::PEERS_DB_GLOBAL;
+use crate::network::NetworkContext;
 use crate::wallet::Wallet;
 use std::convert::Infallible;
 use std::sync::Arc;
 use tokio::sync::{broadcast, RwLock};
 use warp::{body, Filter, Reply};
 
-use super::handlers::{get_block_handler, post_transaction_handler, ws_upgrade_handler};
-use crate::peer::PeersDB;
+use super::handlers::{
+    get_balance_handler, get_block_handler, get_block_json_handler, get_block_payouts_handler,
+    get_block_template_handler, get_blocks_handler, get_broadcast_metrics_handler,
+    get_chain_stats_handler, get_explorer_block_handler, get_health_handler,
+    get_mempool_info_handler, get_mempool_transaction_handler,
+    get_node_metadata_by_publickey_handler, get_node_metadata_handler, get_peer_bandwidth_handler,
+    get_peer_filter_handler, get_peer_stats_handler, get_raw_mempool_handler, get_reorgs_handler,
+    get_search_transactions_handler, get_sync_status_handler, get_unspent_slips_handler,
+    get_utxoset_info_handler, post_rescan_handler, post_submit_block_handler,
+    post_transaction_batch_handler, post_transaction_handler, put_peer_filter_handler,
+    ws_upgrade_handler, BlockRangeQuery, ChainStatsQuery,
+};
 
 /// websocket upgrade filter.
 pub fn ws_upgrade_route_filter(
+    network_context: NetworkContext,
     wallet_lock: Arc<RwLock<Wallet>>,
     mempool_lock: Arc<RwLock<Mempool>>,
     blockchain_lock: Arc<RwLock<Blockchain>>,
@@ -20,7 +33,8 @@ pub fn ws_upgrade_route_filter(
 ) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone {
     warp::path("wsopen")
         .and(warp::ws())
-        .and(with_peers_filter())
+        .and(warp::addr::remote())
+        .and(with_network_context(network_context))
         .and(with_wallet(wallet_lock))
         .and(with_mempool(mempool_lock))
         .and(with_blockchain(blockchain_lock))
@@ -30,18 +44,142 @@ pub fn ws_upgrade_route_filter(
 /// get block filter.
 /// TODO remove this? I believe we want ot use the socket for everything...
 pub fn get_block_route_filter(
-    blockchain_lock: Arc<RwLock<Blockchain>>,
+    block_store: BlockStore,
 ) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone {
     warp::path("block").and(
         warp::path::param()
-            .and(with_blockchain(blockchain_lock))
+            .and(with_block_store(block_store))
             .and_then(get_block_handler),
     )
 }
 
+/// GET block json filter, e.g. /block/<hash>/json.
+pub fn get_block_json_route_filter(
+    block_store: BlockStore,
+) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone {
+    warp::get()
+        .and(warp::path("block"))
+        .and(warp::path::param())
+        .and(warp::path("json"))
+        .and(warp::path::end())
+        .and(with_block_store(block_store))
+        .and_then(get_block_json_handler)
+}
+
+/// GET block payouts filter, e.g. /block/<hash>/payouts.
+pub fn get_block_payouts_route_filter(
+    blockchain_lock: Arc<RwLock<Blockchain>>,
+) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone {
+    warp::get()
+        .and(warp::path("block"))
+        .and(warp::path::param())
+        .and(warp::path("payouts"))
+        .and(warp::path::end())
+        .and(with_blockchain(blockchain_lock))
+        .and_then(get_block_payouts_handler)
+}
+
+/// GET explorer block filter, e.g. /explorer/block/<hash>.
+pub fn get_explorer_block_route_filter(
+    block_store: BlockStore,
+) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone {
+    warp::get()
+        .and(warp::path("explorer"))
+        .and(warp::path("block"))
+        .and(warp::path::param())
+        .and(warp::path::end())
+        .and(with_block_store(block_store))
+        .and_then(get_explorer_block_handler)
+}
+
+/// GET blocks filter. Lists block summaries, e.g. /blocks?from_id=1&to_id=100.
+pub fn get_blocks_route_filter(
+    blockchain_lock: Arc<RwLock<Blockchain>>,
+) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone {
+    warp::get()
+        .and(warp::path("blocks"))
+        .and(warp::path::end())
+        .and(warp::query::<BlockRangeQuery>())
+        .and(with_blockchain(blockchain_lock))
+        .and_then(get_blocks_handler)
+}
+
+/// GET balance filter, e.g. /balance/<publickey>.
+pub fn get_balance_route_filter(
+    blockchain_lock: Arc<RwLock<Blockchain>>,
+) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone {
+    warp::get()
+        .and(warp::path("balance"))
+        .and(warp::path::param())
+        .and(warp::path::end())
+        .and(with_blockchain(blockchain_lock))
+        .and_then(get_balance_handler)
+}
+
+/// GET unspent slips filter, e.g. /utxos/<publickey>.
+pub fn get_unspent_slips_route_filter(
+    blockchain_lock: Arc<RwLock<Blockchain>>,
+) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone {
+    warp::get()
+        .and(warp::path("utxos"))
+        .and(warp::path::param())
+        .and(warp::path::end())
+        .and(with_blockchain(blockchain_lock))
+        .and_then(get_unspent_slips_handler)
+}
+
+/// GET utxoset info filter.
+pub fn get_utxoset_info_route_filter(
+    blockchain_lock: Arc<RwLock<Blockchain>>,
+) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone {
+    warp::get()
+        .and(warp::path("utxoset"))
+        .and(warp::path::end())
+        .and(with_blockchain(blockchain_lock))
+        .and_then(get_utxoset_info_handler)
+}
+
+/// GET chain stats filter, e.g. /chain/stats?blocks=100.
+pub fn get_chain_stats_route_filter(
+    blockchain_lock: Arc<RwLock<Blockchain>>,
+) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone {
+    warp::get()
+        .and(warp::path("chain"))
+        .and(warp::path("stats"))
+        .and(warp::path::end())
+        .and(warp::query::<ChainStatsQuery>())
+        .and(with_blockchain(blockchain_lock))
+        .and_then(get_chain_stats_handler)
+}
+
+/// GET reorgs filter.
+pub fn get_reorgs_route_filter(
+    blockchain_lock: Arc<RwLock<Blockchain>>,
+) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone {
+    warp::get()
+        .and(warp::path("reorgs"))
+        .and(warp::path::end())
+        .and(with_blockchain(blockchain_lock))
+        .and_then(get_reorgs_handler)
+}
+
+/// GET search transactions filter, e.g. /transactions/search/<hex tag>.
+pub fn get_search_transactions_route_filter(
+    blockchain_lock: Arc<RwLock<Blockchain>>,
+) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone {
+    warp::get()
+        .and(warp::path("transactions"))
+        .and(warp::path("search"))
+        .and(warp::path::param())
+        .and(warp::path::end())
+        .and(with_blockchain(blockchain_lock))
+        .and_then(get_search_transactions_handler)
+}
+
 /// POST tx filter.
 /// TODO remove this? I believe we want ot use the socket for everything...
 pub fn post_transaction_route_filter(
+    wallet_lock: Arc<RwLock<Wallet>>,
     mempool_lock: Arc<RwLock<Mempool>>,
     blockchain_lock: Arc<RwLock<Blockchain>>,
 ) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone {
@@ -49,16 +187,222 @@ pub fn post_transaction_route_filter(
         .and(warp::path("sendtransaction"))
         .and(warp::path::end())
         .and(body::aggregate())
+        .and(with_wallet(wallet_lock))
         .and(with_mempool(mempool_lock))
         .and(with_blockchain(blockchain_lock))
         .and_then(post_transaction_handler)
 }
 
-/// inject peers db lock
-/// TODO Can this just be deleted? we should be able to just get the Peers DB from lazy_static global object PEERS_DB_GLOBAL
-fn with_peers_filter() -> impl Filter<Extract = (Arc<RwLock<PeersDB>>,), Error = Infallible> + Clone
-{
-    warp::any().map(move || PEERS_DB_GLOBAL.clone())
+/// POST transaction batch filter, see post_transaction_batch_handler.
+pub fn post_transaction_batch_route_filter(
+    wallet_lock: Arc<RwLock<Wallet>>,
+    mempool_lock: Arc<RwLock<Mempool>>,
+    blockchain_lock: Arc<RwLock<Blockchain>>,
+) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone {
+    warp::post()
+        .and(warp::path("sendtransactions"))
+        .and(warp::path::end())
+        .and(body::content_length_limit(
+            ConsensusParams::MAX_BLOCK_SIZE as u64,
+        ))
+        .and(body::aggregate())
+        .and(with_wallet(wallet_lock))
+        .and(with_mempool(mempool_lock))
+        .and(with_blockchain(blockchain_lock))
+        .and_then(post_transaction_batch_handler)
+}
+
+/// GET peer bandwidth filter.
+pub fn get_peer_bandwidth_route_filter(
+    network_context: NetworkContext,
+) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone {
+    warp::get()
+        .and(warp::path("peerbandwidth"))
+        .and(warp::path::end())
+        .and(with_network_context(network_context))
+        .and_then(get_peer_bandwidth_handler)
+}
+
+/// GET peer stats filter.
+pub fn get_peer_stats_route_filter(
+    network_context: NetworkContext,
+) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone {
+    warp::get()
+        .and(warp::path("peerstats"))
+        .and(warp::path::end())
+        .and(with_network_context(network_context))
+        .and_then(get_peer_stats_handler)
+}
+
+/// GET health filter.
+pub fn get_health_route_filter(
+    mempool_lock: Arc<RwLock<Mempool>>,
+    blockchain_lock: Arc<RwLock<Blockchain>>,
+    network_context: NetworkContext,
+) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone {
+    warp::get()
+        .and(warp::path("health"))
+        .and(warp::path::end())
+        .and(with_mempool(mempool_lock))
+        .and(with_blockchain(blockchain_lock))
+        .and(with_network_context(network_context))
+        .and_then(get_health_handler)
+}
+
+/// GET sync status filter.
+pub fn get_sync_status_route_filter(
+    blockchain_lock: Arc<RwLock<Blockchain>>,
+) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone {
+    warp::get()
+        .and(warp::path("syncstatus"))
+        .and(warp::path::end())
+        .and(with_blockchain(blockchain_lock))
+        .and_then(get_sync_status_handler)
+}
+
+/// GET broadcast metrics filter.
+pub fn get_broadcast_metrics_route_filter(
+) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone {
+    warp::get()
+        .and(warp::path("broadcastmetrics"))
+        .and(warp::path::end())
+        .and_then(get_broadcast_metrics_handler)
+}
+
+/// GET node metadata filter.
+pub fn get_node_metadata_route_filter(
+    network_context: NetworkContext,
+) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone {
+    warp::get()
+        .and(warp::path("nodes"))
+        .and(warp::path::end())
+        .and(with_network_context(network_context))
+        .and_then(get_node_metadata_handler)
+}
+
+/// GET node metadata by publickey filter.
+pub fn get_node_metadata_by_publickey_route_filter(
+    network_context: NetworkContext,
+) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone {
+    warp::get()
+        .and(warp::path("nodes"))
+        .and(warp::path::param())
+        .and(warp::path::end())
+        .and(with_network_context(network_context))
+        .and_then(get_node_metadata_by_publickey_handler)
+}
+
+/// GET peer filter filter.
+pub fn get_peer_filter_route_filter(
+    network_context: NetworkContext,
+) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone {
+    warp::get()
+        .and(warp::path("peerfilter"))
+        .and(warp::path::end())
+        .and(with_network_context(network_context))
+        .and_then(get_peer_filter_handler)
+}
+
+/// PUT peer filter filter. Replaces the allow and deny lists with the JSON body.
+pub fn put_peer_filter_route_filter(
+    network_context: NetworkContext,
+) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone {
+    warp::put()
+        .and(warp::path("peerfilter"))
+        .and(warp::path::end())
+        .and(body::content_length_limit(1024 * 64))
+        .and(body::json())
+        .and(with_network_context(network_context))
+        .and_then(put_peer_filter_handler)
+}
+
+/// POST rescan filter. Rebuilds the wallets from the block id in the path.
+pub fn post_rescan_route_filter(
+    blockchain_lock: Arc<RwLock<Blockchain>>,
+) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone {
+    warp::post()
+        .and(warp::path("rescan"))
+        .and(warp::path::param::<u64>())
+        .and(warp::path::end())
+        .and(with_blockchain(blockchain_lock))
+        .and_then(post_rescan_handler)
+}
+
+/// GET mempool info filter.
+pub fn get_mempool_info_route_filter(
+    mempool_lock: Arc<RwLock<Mempool>>,
+    blockchain_lock: Arc<RwLock<Blockchain>>,
+) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone {
+    warp::get()
+        .and(warp::path("mempool"))
+        .and(warp::path::end())
+        .and(with_mempool(mempool_lock))
+        .and(with_blockchain(blockchain_lock))
+        .and_then(get_mempool_info_handler)
+}
+
+/// GET raw mempool filter.
+pub fn get_raw_mempool_route_filter(
+    mempool_lock: Arc<RwLock<Mempool>>,
+) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone {
+    warp::get()
+        .and(warp::path("mempool"))
+        .and(warp::path("transactions"))
+        .and(warp::path::end())
+        .and(with_mempool(mempool_lock))
+        .and_then(get_raw_mempool_handler)
+}
+
+/// GET mempool transaction filter, e.g. /mempool/transactions/<hash>.
+pub fn get_mempool_transaction_route_filter(
+    mempool_lock: Arc<RwLock<Mempool>>,
+) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone {
+    warp::get()
+        .and(warp::path("mempool"))
+        .and(warp::path("transactions"))
+        .and(warp::path::param())
+        .and(warp::path::end())
+        .and(with_mempool(mempool_lock))
+        .and_then(get_mempool_transaction_handler)
+}
+
+/// GET block template filter.
+pub fn get_block_template_route_filter(
+    mempool_lock: Arc<RwLock<Mempool>>,
+    blockchain_lock: Arc<RwLock<Blockchain>>,
+) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone {
+    warp::get()
+        .and(warp::path("blocktemplate"))
+        .and(warp::path::end())
+        .and(with_mempool(mempool_lock))
+        .and(with_blockchain(blockchain_lock))
+        .and_then(get_block_template_handler)
+}
+
+/// POST submit block filter. The body is a block serialized for the network, as raw bytes
+/// without any further encoding, so it may be as large as a whole block with its ATR and fee
+/// transactions.
+pub fn post_submit_block_route_filter(
+    mempool_lock: Arc<RwLock<Mempool>>,
+    blockchain_lock: Arc<RwLock<Blockchain>>,
+) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone {
+    warp::post()
+        .and(warp::path("submitblock"))
+        .and(warp::path::end())
+        .and(body::content_length_limit(
+            ConsensusParams::MAX_SERIALIZED_BLOCK_SIZE as u64,
+        ))
+        .and(body::aggregate())
+        .and(with_mempool(mempool_lock))
+        .and(with_blockchain(blockchain_lock))
+        .and_then(post_submit_block_handler)
+}
+
+/// inject the state of our peers
+fn with_network_context(
+    network_context: NetworkContext,
+) -> impl Filter<Extract = (NetworkContext,), Error = Infallible> + Clone {
+    warp::any().map(move || network_context.clone())
 }
 
 /// inject wallet lock
@@ -80,6 +424,13 @@ fn with_blockchain(
     warp::any().map(move || blockchain_lock.clone())
 }
 
+/// inject block store
+fn with_block_store(
+    block_store: BlockStore,
+) -> impl Filter<Extract = (BlockStore,), Error = Infallible> + Clone {
+    warp::any().map(move || block_store.clone())
+}
+
 /// inject blockchain lock
 fn with_broadcast_channel_sender(
     broadcast_channel_sender: broadcast::Sender<SaitoMessage>,
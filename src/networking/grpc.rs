@@ -0,0 +1,376 @@
+use crate::block::{Block, BlockType};
+use crate::blockchain::Blockchain;
+use crate::consensus::{recv_message, SaitoMessage, Subsystem};
+use crate::crypto::SaitoHash;
+use crate::hop::Hop;
+use crate::mempool::{generate_block_template, Mempool};
+use crate::networking::handlers::BlockRangeQuery;
+use crate::networking::signals::signal_for_shutdown;
+use crate::slip::Slip;
+use crate::time::create_timestamp;
+use crate::transaction::Transaction;
+use crate::wallet::Wallet;
+use std::convert::TryInto;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use tokio::sync::{broadcast, mpsc, RwLock};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status};
+use tracing::info;
+
+/// The messages and service generated from proto/saito.proto.
+pub mod proto {
+    tonic::include_proto!("saito");
+}
+
+use proto::saito_server::{Saito, SaitoServer};
+
+impl From<&Slip> for proto::Slip {
+    fn from(slip: &Slip) -> Self {
+        proto::Slip {
+            publickey: slip.get_publickey().to_vec(),
+            uuid: slip.get_uuid().to_vec(),
+            amount: slip.get_amount().into(),
+            payout: slip.get_payout(),
+            slip_ordinal: slip.get_slip_ordinal() as u32,
+            slip_type: slip.get_slip_type() as i32,
+        }
+    }
+}
+
+impl From<&Hop> for proto::Hop {
+    fn from(hop: &Hop) -> Self {
+        proto::Hop {
+            from: hop.get_from().to_vec(),
+            to: hop.get_to().to_vec(),
+            sig: hop.get_sig().to_vec(),
+        }
+    }
+}
+
+impl From<&Transaction> for proto::Transaction {
+    fn from(transaction: &Transaction) -> Self {
+        proto::Transaction {
+            hash: transaction
+                .get_hash_for_signature()
+                .map(|hash| hash.to_vec())
+                .unwrap_or_default(),
+            timestamp: transaction.get_timestamp(),
+            transaction_type: transaction.get_transaction_type() as i32,
+            inputs: transaction.get_inputs().iter().map(Into::into).collect(),
+            outputs: transaction.get_outputs().iter().map(Into::into).collect(),
+            message: transaction.get_message().clone(),
+            signature: transaction.get_signature().to_vec(),
+            path: transaction.get_path().iter().map(Into::into).collect(),
+            total_in: transaction.total_in,
+            total_out: transaction.total_out,
+            total_fees: transaction.get_total_fees(),
+        }
+    }
+}
+
+impl From<&Block> for proto::Block {
+    fn from(block: &Block) -> Self {
+        proto::Block {
+            hash: block.get_hash().to_vec(),
+            id: block.get_id(),
+            timestamp: block.get_timestamp(),
+            previous_block_hash: block.get_previous_block_hash().to_vec(),
+            creator: block.get_creator().to_vec(),
+            merkle_root: block.get_merkle_root().to_vec(),
+            signature: block.get_signature().to_vec(),
+            treasury: block.get_treasury(),
+            staking_treasury: block.get_staking_treasury(),
+            burnfee: block.get_burnfee(),
+            difficulty: block.get_difficulty(),
+            total_fees: block.get_total_fees(),
+            block_type: block.get_block_type() as i32,
+            hash_algorithm: block.get_hash_algorithm() as i32,
+            transactions: block.get_transactions().iter().map(Into::into).collect(),
+        }
+    }
+}
+
+/// The gRPC interface of the node. Each method does what the REST route of the same name
+/// does, see networking::handlers.
+pub struct GrpcService {
+    wallet_lock: Arc<RwLock<Wallet>>,
+    mempool_lock: Arc<RwLock<Mempool>>,
+    blockchain_lock: Arc<RwLock<Blockchain>>,
+    broadcast_channel_sender: broadcast::Sender<SaitoMessage>,
+}
+
+impl GrpcService {
+    pub fn new(
+        wallet_lock: Arc<RwLock<Wallet>>,
+        mempool_lock: Arc<RwLock<Mempool>>,
+        blockchain_lock: Arc<RwLock<Blockchain>>,
+        broadcast_channel_sender: broadcast::Sender<SaitoMessage>,
+    ) -> Self {
+        GrpcService {
+            wallet_lock,
+            mempool_lock,
+            blockchain_lock,
+            broadcast_channel_sender,
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Saito for GrpcService {
+    async fn get_block(
+        &self,
+        request: Request<proto::GetBlockRequest>,
+    ) -> Result<Response<proto::Block>, Status> {
+        let block_hash: SaitoHash = request
+            .into_inner()
+            .hash
+            .try_into()
+            .map_err(|_| Status::invalid_argument("block hash must be 32 bytes"))?;
+        let blockchain = self.blockchain_lock.read().await;
+        match blockchain.get_block_sync(&block_hash) {
+            Some(block) => Ok(Response::new(block.into())),
+            None => Err(Status::not_found("block not found")),
+        }
+    }
+
+    async fn get_blocks(
+        &self,
+        request: Request<proto::GetBlocksRequest>,
+    ) -> Result<Response<proto::GetBlocksReply>, Status> {
+        let request = request.into_inner();
+        let block_range_query = BlockRangeQuery {
+            from_id: request.from_id,
+            to_id: request.to_id,
+            limit: request.limit,
+        };
+        let blockchain = self.blockchain_lock.read().await;
+        let (from_id, to_id, next_from_id) =
+            block_range_query.get_page(blockchain.get_latest_block_id());
+        let blocks = blockchain
+            .get_block_summaries(from_id, to_id)
+            .into_iter()
+            .map(|block_summary| proto::BlockSummary {
+                hash: hex::decode(&block_summary.hash).unwrap_or_default(),
+                id: block_summary.id,
+                timestamp: block_summary.timestamp,
+                transaction_count: block_summary.transaction_count as u64,
+                total_fees: block_summary.total_fees,
+            })
+            .collect();
+        Ok(Response::new(proto::GetBlocksReply {
+            blocks,
+            next_from_id,
+        }))
+    }
+
+    async fn get_mempool_info(
+        &self,
+        _request: Request<proto::Empty>,
+    ) -> Result<Response<proto::MempoolInfo>, Status> {
+        let mempool = self.mempool_lock.read().await;
+        let blockchain = self.blockchain_lock.read().await;
+        let mempool_info = mempool.get_mempool_info(&blockchain, create_timestamp());
+        Ok(Response::new(proto::MempoolInfo {
+            transaction_count: mempool_info.transaction_count as u64,
+            bytes: mempool_info.bytes as u64,
+            total_fees: mempool_info.total_fees,
+            routing_work_available: mempool_info.routing_work_available,
+            routing_work_needed: mempool_info.routing_work_needed,
+        }))
+    }
+
+    async fn get_raw_mempool(
+        &self,
+        _request: Request<proto::Empty>,
+    ) -> Result<Response<proto::RawMempool>, Status> {
+        let mempool = self.mempool_lock.read().await;
+        let transactions = mempool
+            .get_raw_mempool()
+            .into_iter()
+            .map(|transaction_info| proto::MempoolTransaction {
+                hash: hex::decode(&transaction_info.hash).unwrap_or_default(),
+                total_fees: transaction_info.total_fees,
+                routing_hops: transaction_info.routing_hops as u64,
+                routing_work_for_us: transaction_info.routing_work_for_us,
+            })
+            .collect();
+        Ok(Response::new(proto::RawMempool { transactions }))
+    }
+
+    async fn send_transaction(
+        &self,
+        request: Request<proto::RawTransaction>,
+    ) -> Result<Response<proto::SendTransactionReply>, Status> {
+        let mut transaction = Transaction::deserialize_from_net(request.into_inner().transaction);
+
+        let publickey = self.wallet_lock.read().await.get_publickey();
+        if !transaction.validate_routing_path_for_peer(None, publickey) {
+            return Err(Status::invalid_argument("invalid routing path"));
+        }
+
+        let blockchain = self.blockchain_lock.read().await;
+        transaction.generate_metadata(transaction.inputs[0].get_publickey());
+        if !transaction.validate(&blockchain.utxoset, &blockchain.staking) {
+            return Err(Status::invalid_argument("invalid transaction"));
+        }
+        let signature = transaction.get_signature().to_vec();
+        self.mempool_lock
+            .write()
+            .await
+            .add_transaction(transaction)
+            .await
+            .map_err(Status::failed_precondition)?;
+        Ok(Response::new(proto::SendTransactionReply { signature }))
+    }
+
+    async fn get_block_template(
+        &self,
+        _request: Request<proto::Empty>,
+    ) -> Result<Response<proto::RawBlock>, Status> {
+        let block = generate_block_template(
+            self.mempool_lock.clone(),
+            self.blockchain_lock.clone(),
+            create_timestamp(),
+        )
+        .await
+        .map_err(|error| Status::internal(error.to_string()))?;
+        Ok(Response::new(proto::RawBlock {
+            block: block.serialize_for_net(BlockType::Full),
+        }))
+    }
+
+    async fn submit_block(
+        &self,
+        request: Request<proto::RawBlock>,
+    ) -> Result<Response<proto::SubmitBlockReply>, Status> {
+        let mut block = Block::try_deserialize_for_net(&request.into_inner().block)
+            .ok_or_else(|| Status::invalid_argument("invalid block"))?;
+        block.generate_metadata();
+        let block_hash = block.get_hash();
+        let block_id = block.get_id();
+
+        self.mempool_lock.write().await.add_block(block);
+        Mempool::send_blocks_to_blockchain(self.mempool_lock.clone(), self.blockchain_lock.clone())
+            .await;
+
+        let blockchain = self.blockchain_lock.read().await;
+        Ok(Response::new(proto::SubmitBlockReply {
+            hash: block_hash.to_vec(),
+            is_longest_chain: blockchain
+                .blockring
+                .get_longest_chain_block_hash_by_block_id(block_id)
+                == block_hash,
+        }))
+    }
+
+    type SubscribeBlocksStream = ReceiverStream<Result<proto::Block, Status>>;
+
+    async fn subscribe_blocks(
+        &self,
+        _request: Request<proto::Empty>,
+    ) -> Result<Response<Self::SubscribeBlocksStream>, Status> {
+        let mut broadcast_channel_receiver = self.broadcast_channel_sender.subscribe();
+        let blockchain_lock = self.blockchain_lock.clone();
+        let (block_sender, block_receiver) = mpsc::channel(4);
+
+        tokio::spawn(async move {
+            loop {
+                match recv_message(&mut broadcast_channel_receiver, Subsystem::Grpc).await {
+                    Some(SaitoMessage::BlockchainNewLongestChainBlock { hash, .. }) => {
+                        let block = blockchain_lock
+                            .read()
+                            .await
+                            .get_block_sync(&hash)
+                            .map(proto::Block::from);
+                        if let Some(block) = block {
+                            //
+                            // the client has gone once its stream is dropped
+                            //
+                            if block_sender.send(Ok(block)).await.is_err() {
+                                break;
+                            }
+                        }
+                    }
+                    Some(_) => {}
+                    None => break,
+                }
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(block_receiver)))
+    }
+}
+
+/// Serves the gRPC interface until the node is shut down.
+pub async fn run_grpc_server(host: IpAddr, port: u16, service: GrpcService) -> crate::Result<()> {
+    info!("Listening for gRPC on port {}", port);
+    tonic::transport::Server::builder()
+        .add_service(SaitoServer::new(service))
+        .serve_with_shutdown(SocketAddr::from((host, port)), signal_for_shutdown())
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utilities::test_manager::TestManager;
+    use tokio_stream::StreamExt;
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn grpc_service_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let (broadcast_channel_sender, _) = broadcast::channel(32);
+        blockchain_lock
+            .write()
+            .await
+            .set_broadcast_channel_sender(broadcast_channel_sender.clone());
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        let grpc_service = GrpcService::new(
+            wallet_lock.clone(),
+            test_manager.mempool_lock.clone(),
+            blockchain_lock.clone(),
+            broadcast_channel_sender,
+        );
+
+        let mut block_stream = grpc_service
+            .subscribe_blocks(Request::new(proto::Empty {}))
+            .await
+            .unwrap()
+            .into_inner();
+        test_manager.generate_blockchain(3, [0; 32]).await;
+
+        let latest_block_hash = blockchain_lock.read().await.get_latest_block_hash();
+        let block = grpc_service
+            .get_block(Request::new(proto::GetBlockRequest {
+                hash: latest_block_hash.to_vec(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(block.hash, latest_block_hash.to_vec());
+        assert_eq!(block.id, 3);
+        assert_eq!(block.block_type, proto::BlockType::Full as i32);
+
+        let blocks = grpc_service
+            .get_blocks(Request::new(proto::GetBlocksRequest {
+                from_id: Some(1),
+                to_id: None,
+                limit: Some(2),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(blocks.blocks.len(), 2);
+        assert_eq!(blocks.blocks[1].id, 2);
+        assert_eq!(blocks.next_from_id, Some(3));
+
+        for block_id in 1..=3 {
+            let block = block_stream.next().await.unwrap().unwrap();
+            assert_eq!(block.id, block_id);
+        }
+    }
+}
@@ -1,18 +1,31 @@
-use crate::block::BlockType;
-use crate::blockchain::Blockchain;
-use crate::consensus::SaitoMessage;
-use crate::mempool::Mempool;
-use crate::network::Result;
+use crate::block::{Block, BlockType};
+use crate::block_store::BlockStore;
+use crate::blockchain::{BlockSummary, Blockchain};
+use crate::configuration::PeerFilterSettings;
+use crate::consensus::{SaitoMessage, BROADCAST_METRICS};
+use crate::crypto::{publickey_from_base58, SaitoHash, SaitoPublicKey};
+use crate::health::HealthReport;
+use crate::layout::TRANSACTION_LENGTH_PREFIX_SIZE;
+use crate::mempool::{add_transaction_batch, generate_block_template, Mempool};
+use crate::network::{Network, NetworkContext, Result};
+use crate::networking::node_metadata::NodeMetadataInfo;
+use crate::networking::peer_host::PeerHost;
+use crate::slip::{get_balance, iter_unspent_slips};
+use crate::time::create_timestamp;
 use crate::transaction::Transaction;
 use crate::wallet::Wallet;
 use base58::ToBase58;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::sync::{broadcast, RwLock};
+use tracing::error;
+use warp::http::StatusCode;
 use warp::reject::Reject;
 use warp::reply::Response;
 use warp::{Buf, Rejection, Reply};
 
-use crate::peer::{handle_inbound_peer_connection, PeersDB};
+use crate::peer::{get_peer_bandwidth, handle_inbound_peer_connection, PeerBandwidth};
 
 #[derive(Debug)]
 struct Invalid;
@@ -37,12 +50,104 @@ impl warp::Reply for Message {
     }
 }
 
+/// One entry in the reply to GET /peerbandwidth.
+#[derive(Serialize)]
+struct PeerBandwidthReport {
+    connection_id: String,
+    publickey: Option<String>,
+    host: Option<PeerHost>,
+    port: Option<u16>,
+    is_connected: bool,
+    latency: Option<u64>,
+    clock_offset: Option<i64>,
+    #[serde(flatten)]
+    bandwidth: PeerBandwidth,
+}
+
+/// One entry in the reply to POST /rescan.
+#[derive(Serialize)]
+struct WalletBalanceReport {
+    publickey: String,
+    available_balance: u64,
+}
+
+/// The most blocks GET /blocks lists in one page.
+pub const MAX_BLOCKS_PER_PAGE: u64 = 100;
+
+/// The most transactions POST /sendtransactions takes in one batch.
+pub const MAX_TRANSACTIONS_PER_BATCH: usize = 1000;
+
+/// The blocks GET /chain/stats covers by default, and the most it covers.
+pub const DEFAULT_CHAIN_STATS_BLOCKS: u64 = 100;
+pub const MAX_CHAIN_STATS_BLOCKS: u64 = 10_000;
+
+/// The query of GET /chain/stats, e.g. /chain/stats?blocks=500.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct ChainStatsQuery {
+    pub blocks: Option<u64>,
+}
+
+/// The query of GET /blocks. Without from_id the page ends at to_id, or at the latest block.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct BlockRangeQuery {
+    pub from_id: Option<u64>,
+    pub to_id: Option<u64>,
+    pub limit: Option<u64>,
+}
+
+impl BlockRangeQuery {
+    /// The first and last block ids of the page, and the from_id of the next page if there is
+    /// one, given the latest block id of the longest chain.
+    pub fn get_page(&self, latest_block_id: u64) -> (u64, u64, Option<u64>) {
+        let limit = self
+            .limit
+            .unwrap_or(MAX_BLOCKS_PER_PAGE)
+            .clamp(1, MAX_BLOCKS_PER_PAGE);
+        let to_id = self.to_id.unwrap_or(latest_block_id).min(latest_block_id);
+        let from_id = self
+            .from_id
+            .unwrap_or_else(|| to_id.saturating_sub(limit - 1))
+            .max(1);
+
+        let page_to_id = to_id.min(from_id.saturating_add(limit - 1));
+        let next_from_id = if page_to_id < to_id {
+            Some(page_to_id + 1)
+        } else {
+            None
+        };
+        (from_id, page_to_id, next_from_id)
+    }
+}
+
+/// The reply to GET /blocks. next_from_id is the from_id of the next page, if there is one.
+#[derive(Serialize)]
+struct BlockRangeReport {
+    blocks: Vec<BlockSummary>,
+    next_from_id: Option<u64>,
+}
+
+/// The reply to GET /balance/<publickey>.
+#[derive(Serialize)]
+struct BalanceReport {
+    publickey: String,
+    balance: u64,
+    unspent_slip_count: usize,
+}
+
+/// The reply to POST /submitblock.
+#[derive(Serialize)]
+struct SubmitBlockReport {
+    hash: String,
+    is_longest_chain: bool,
+}
+
 /// websocket upgrade handler. accepts an http connection and upgrades it to WebSocket.
 /// https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Upgrade
 /// Thanks, Ryan Dahl!!
 pub async fn ws_upgrade_handler(
     ws: warp::ws::Ws,
-    peer_db_lock: Arc<RwLock<PeersDB>>,
+    remote_addr: Option<SocketAddr>,
+    network_context: NetworkContext,
     wallet_lock: Arc<RwLock<Wallet>>,
     mempool_lock: Arc<RwLock<Mempool>>,
     blockchain_lock: Arc<RwLock<Blockchain>>,
@@ -51,7 +156,8 @@ pub async fn ws_upgrade_handler(
     Ok(ws.on_upgrade(move |socket| {
         handle_inbound_peer_connection(
             socket,
-            peer_db_lock,
+            remote_addr.map(|remote_addr| PeerHost::from(remote_addr.ip())),
+            network_context,
             wallet_lock,
             mempool_lock,
             blockchain_lock,
@@ -66,6 +172,7 @@ pub async fn ws_upgrade_handler(
 /// Once SNDBLKHD is being actively used, this should be deleted.
 pub async fn post_transaction_handler(
     mut body: impl Buf,
+    wallet_lock: Arc<RwLock<Wallet>>,
     mempool_lock: Arc<RwLock<Mempool>>,
     blockchain_lock: Arc<RwLock<Blockchain>>,
 ) -> Result<impl Reply> {
@@ -77,6 +184,16 @@ pub async fn post_transaction_handler(
     }
 
     let mut tx = Transaction::deserialize_from_net(buffer);
+
+    //
+    // transactions posted over HTTP have no relaying peer, but any
+    // routing path they carry must still end with us.
+    //
+    let publickey = wallet_lock.read().await.get_publickey();
+    if !tx.validate_routing_path_for_peer(None, publickey) {
+        return Err(warp::reject::custom(Invalid));
+    }
+
     let blockchain = blockchain_lock.read().await;
     tx.generate_metadata(tx.inputs[0].get_publickey());
     if tx.validate(&blockchain.utxoset, &blockchain.staking) {
@@ -84,31 +201,462 @@ pub async fn post_transaction_handler(
             .unwrap()
             .to_string();
         let mut mempool = mempool_lock.write().await;
-        mempool.add_transaction(tx).await;
-        Ok(Message { msg: response })
+        match mempool.add_transaction(tx).await {
+            Ok(()) => Ok(Message { msg: response }),
+            Err(_) => Err(warp::reject::custom(Invalid)),
+        }
     } else {
         Err(warp::reject::custom(Invalid))
     }
 }
 
+/// POST transaction batch handler. The body is any number of transactions serialized for the
+/// network, each preceded by its length, a u32. Replies with the result of every transaction,
+/// see add_transaction_batch, or rejects the batch if the body cannot be split into transactions
+/// or holds more than MAX_TRANSACTIONS_PER_BATCH of them.
+pub async fn post_transaction_batch_handler(
+    mut body: impl Buf,
+    wallet_lock: Arc<RwLock<Wallet>>,
+    mempool_lock: Arc<RwLock<Mempool>>,
+    blockchain_lock: Arc<RwLock<Blockchain>>,
+) -> Result<impl Reply> {
+    let buffer = body.copy_to_bytes(body.remaining());
+
+    let mut transactions = vec![];
+    let mut start_of_transaction = 0;
+    while start_of_transaction < buffer.len() {
+        if transactions.len() == MAX_TRANSACTIONS_PER_BATCH {
+            return Err(warp::reject::custom(Invalid));
+        }
+        let start_of_data = start_of_transaction + TRANSACTION_LENGTH_PREFIX_SIZE;
+        let length = match buffer.get(start_of_transaction..start_of_data) {
+            Some(length) => u32::from_be_bytes(length.try_into().unwrap()) as usize,
+            None => return Err(warp::reject::custom(Invalid)),
+        };
+        match buffer.get(start_of_data..start_of_data + length) {
+            Some(bytes) => transactions.push(Transaction::try_deserialize_from_net(bytes)),
+            None => return Err(warp::reject::custom(Invalid)),
+        }
+        start_of_transaction = start_of_data + length;
+    }
+
+    let publickey = wallet_lock.read().await.get_publickey();
+    let results =
+        add_transaction_batch(mempool_lock, blockchain_lock, publickey, transactions).await;
+    Ok(warp::reply::json(&results))
+}
+
 /// get block handler.
 // TODO remove this. For now it is just in place as a simple means to transfer blocks to saito-lite so we
 // can test the ability to serialize/deserialize blocks.
 pub async fn get_block_handler(
     str_block_hash: String,
-    blockchain_lock: Arc<RwLock<Blockchain>>,
+    block_store: BlockStore,
 ) -> Result<impl Reply> {
     let mut block_hash = [0u8; 32];
     hex::decode_to_slice(str_block_hash.clone(), &mut block_hash).expect("Failed to parse hash");
-    {
-        let blockchain = blockchain_lock.read().await;
-        let block = blockchain.get_block(&block_hash).await;
-        match block {
-            Some(block) => {
-                let block_bytes = block.serialize_for_net(BlockType::Full);
-                Ok(block_bytes)
+    match block_store.get_block(&block_hash) {
+        Some(block) => {
+            let block_bytes = block.serialize_for_net(BlockType::Full);
+            Ok(block_bytes)
+        }
+        None => Err(warp::reject()),
+    }
+}
+
+/// get block json handler. Replies with a readable view of the block, see Block::to_json.
+pub async fn get_block_json_handler(
+    str_block_hash: String,
+    block_store: BlockStore,
+) -> Result<impl Reply> {
+    let mut block_hash = [0u8; 32];
+    if hex::decode_to_slice(str_block_hash, &mut block_hash).is_err() {
+        return Err(warp::reject::custom(Invalid));
+    }
+    match block_store.get_block(&block_hash) {
+        Some(block) => Ok(warp::reply::json(&block.to_json())),
+        None => Err(warp::reject::not_found()),
+    }
+}
+
+/// get block payouts handler. Replies with the winners of the payout lottery of a block on
+/// the longest chain, see Blockchain::get_block_payouts.
+pub async fn get_block_payouts_handler(
+    str_block_hash: String,
+    blockchain_lock: Arc<RwLock<Blockchain>>,
+) -> Result<impl Reply> {
+    let mut block_hash = [0u8; 32];
+    if hex::decode_to_slice(str_block_hash, &mut block_hash).is_err() {
+        return Err(warp::reject::custom(Invalid));
+    }
+    let blockchain = blockchain_lock.read().await;
+    match blockchain.get_block_payouts(&block_hash) {
+        Some(block_payouts) => {
+            let block_payouts: Vec<serde_json::Value> = block_payouts
+                .iter()
+                .map(|block_payout| block_payout.to_json())
+                .collect();
+            Ok(warp::reply::json(&block_payouts))
+        }
+        None => Err(warp::reject::not_found()),
+    }
+}
+
+/// get explorer block handler. Replies with the header, transaction summaries, payouts and
+/// rebroadcasts of a block in one response, see Block::get_explorer_block.
+pub async fn get_explorer_block_handler(
+    str_block_hash: String,
+    block_store: BlockStore,
+) -> Result<impl Reply> {
+    let mut block_hash = [0u8; 32];
+    if hex::decode_to_slice(str_block_hash, &mut block_hash).is_err() {
+        return Err(warp::reject::custom(Invalid));
+    }
+    match block_store.get_block(&block_hash) {
+        Some(block) => Ok(warp::reply::json(&block.get_explorer_block())),
+        None => Err(warp::reject::not_found()),
+    }
+}
+
+/// get mempool transaction handler. Replies with a readable view of a transaction waiting in
+/// the mempool, see Transaction::to_json.
+pub async fn get_mempool_transaction_handler(
+    str_transaction_hash: String,
+    mempool_lock: Arc<RwLock<Mempool>>,
+) -> Result<impl Reply> {
+    let mut transaction_hash = [0u8; 32];
+    if hex::decode_to_slice(str_transaction_hash, &mut transaction_hash).is_err() {
+        return Err(warp::reject::custom(Invalid));
+    }
+    let mempool = mempool_lock.read().await;
+    match mempool.get_transaction(transaction_hash) {
+        Some(transaction) => Ok(warp::reply::json(&transaction.to_json())),
+        None => Err(warp::reject::not_found()),
+    }
+}
+
+/// get blocks handler. Lists summaries of a range of blocks on the longest chain, a page
+/// of at most MAX_BLOCKS_PER_PAGE at a time, so explorers need not fetch every full block.
+pub async fn get_blocks_handler(
+    block_range_query: BlockRangeQuery,
+    blockchain_lock: Arc<RwLock<Blockchain>>,
+) -> Result<impl Reply> {
+    let blockchain = blockchain_lock.read().await;
+    let (from_id, to_id, next_from_id) =
+        block_range_query.get_page(blockchain.get_latest_block_id());
+    Ok(warp::reply::json(&BlockRangeReport {
+        blocks: blockchain.get_block_summaries(from_id, to_id),
+        next_from_id,
+    }))
+}
+
+/// get balance handler. Sums the unspent slips of a hex encoded publickey in the utxoset, so
+/// balances can be looked up without holding the wallet.
+pub async fn get_balance_handler(
+    str_publickey: String,
+    blockchain_lock: Arc<RwLock<Blockchain>>,
+) -> Result<impl Reply> {
+    let mut publickey: SaitoPublicKey = [0; 33];
+    if hex::decode_to_slice(&str_publickey, &mut publickey).is_err() {
+        return Err(warp::reject::custom(Invalid));
+    }
+    let blockchain = blockchain_lock.read().await;
+    Ok(warp::reply::json(&BalanceReport {
+        publickey: str_publickey,
+        balance: get_balance(&blockchain.utxoset, publickey),
+        unspent_slip_count: iter_unspent_slips(&blockchain.utxoset, Some(publickey)).count(),
+    }))
+}
+
+/// get unspent slips handler. Lists the unspent slips of a hex encoded publickey, see
+/// Slip::to_json.
+pub async fn get_unspent_slips_handler(
+    str_publickey: String,
+    blockchain_lock: Arc<RwLock<Blockchain>>,
+) -> Result<impl Reply> {
+    let mut publickey: SaitoPublicKey = [0; 33];
+    if hex::decode_to_slice(&str_publickey, &mut publickey).is_err() {
+        return Err(warp::reject::custom(Invalid));
+    }
+    let blockchain = blockchain_lock.read().await;
+    let unspent_slips: Vec<serde_json::Value> =
+        iter_unspent_slips(&blockchain.utxoset, Some(publickey))
+            .map(|slip| slip.to_json())
+            .collect();
+    Ok(warp::reply::json(&unspent_slips))
+}
+
+/// get utxoset info handler. Reports the size of the utxoset and the nolan it holds, see
+/// Blockchain::get_utxoset_info.
+pub async fn get_utxoset_info_handler(
+    blockchain_lock: Arc<RwLock<Blockchain>>,
+) -> Result<impl Reply> {
+    let blockchain = blockchain_lock.read().await;
+    Ok(warp::reply::json(&blockchain.get_utxoset_info()))
+}
+
+/// get reorgs handler. Lists the last reorganizations of the longest chain, oldest first,
+/// see reorg_log::ReorgLog.
+pub async fn get_reorgs_handler(blockchain_lock: Arc<RwLock<Blockchain>>) -> Result<impl Reply> {
+    let blockchain = blockchain_lock.read().await;
+    Ok(warp::reply::json(blockchain.get_reorg_log().get_events()))
+}
+
+/// get search transactions handler. Lists the transactions of the longest chain whose
+/// message starts with a hex encoded tag, see memo_index::MemoIndex. Rejects the request if
+/// the node does not index transactions.
+pub async fn get_search_transactions_handler(
+    str_tag: String,
+    blockchain_lock: Arc<RwLock<Blockchain>>,
+) -> Result<impl Reply> {
+    let tag = match hex::decode(str_tag) {
+        Ok(tag) => tag,
+        Err(_) => return Err(warp::reject::custom(Invalid)),
+    };
+    let blockchain = blockchain_lock.read().await;
+    match blockchain.search_transactions(&tag) {
+        Ok(results) => Ok(warp::reply::json(&results)),
+        Err(_) => Err(warp::reject::custom(Invalid)),
+    }
+}
+
+/// get chain stats handler. Reports fee and throughput statistics over the last blocks of
+/// the longest chain, see Blockchain::get_chain_stats.
+pub async fn get_chain_stats_handler(
+    chain_stats_query: ChainStatsQuery,
+    blockchain_lock: Arc<RwLock<Blockchain>>,
+) -> Result<impl Reply> {
+    let n_blocks = chain_stats_query
+        .blocks
+        .unwrap_or(DEFAULT_CHAIN_STATS_BLOCKS)
+        .clamp(1, MAX_CHAIN_STATS_BLOCKS);
+    let blockchain = blockchain_lock.read().await;
+    Ok(warp::reply::json(&blockchain.get_chain_stats(n_blocks)))
+}
+
+/// get peer bandwidth handler. Reports the traffic and fee volume exchanged with each
+/// of our peers as JSON, so operators can see which peers bring in profitable routing work.
+pub async fn get_peer_bandwidth_handler(network_context: NetworkContext) -> Result<impl Reply> {
+    let peer_db = network_context.peers_db.read().await;
+    let mut connection_ids: Vec<&SaitoHash> = peer_db.keys().collect();
+    connection_ids.sort();
+    let peer_bandwidth_reports: Vec<PeerBandwidthReport> = connection_ids
+        .into_iter()
+        .map(|connection_id| {
+            let peer = peer_db.get(connection_id).unwrap();
+            PeerBandwidthReport {
+                connection_id: hex::encode(connection_id),
+                publickey: peer.get_publickey().map(|publickey| publickey.to_base58()),
+                host: peer.get_host().cloned(),
+                port: peer.get_port(),
+                is_connected: peer.get_is_connected_or_connecting(),
+                latency: peer.get_latency(),
+                clock_offset: peer.get_clock_offset(),
+                bandwidth: get_peer_bandwidth(&network_context, connection_id),
             }
-            None => Err(warp::reject()),
+        })
+        .collect();
+    Ok(warp::reply::json(&peer_bandwidth_reports))
+}
+
+/// get peer stats handler. Reports the stats kept of every peer address we have connected
+/// to, best scoring first, see networking::peer_stats.
+pub async fn get_peer_stats_handler(network_context: NetworkContext) -> Result<impl Reply> {
+    let peer_stats = network_context.peer_stats.read().unwrap();
+    Ok(warp::reply::json(
+        &peer_stats.get_reports(create_timestamp()),
+    ))
+}
+
+/// get health handler. The reply is 503 Service Unavailable rather than 200 OK while the node
+/// is not in sync, so that load balancers can route around it.
+pub async fn get_health_handler(
+    mempool_lock: Arc<RwLock<Mempool>>,
+    blockchain_lock: Arc<RwLock<Blockchain>>,
+    network_context: NetworkContext,
+) -> Result<impl Reply> {
+    let peers_connected = network_context
+        .peers_db
+        .read()
+        .await
+        .values()
+        .filter(|peer| peer.get_is_connected_or_connecting())
+        .count();
+    let mempool = mempool_lock.read().await;
+    let blockchain = blockchain_lock.read().await;
+    let health_report =
+        HealthReport::new(&blockchain, &mempool, peers_connected, create_timestamp());
+    let status = if health_report.is_synced {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    Ok(warp::reply::with_status(
+        warp::reply::json(&health_report),
+        status,
+    ))
+}
+
+/// get sync status handler. Returns how far we are behind the tips announced by our peers.
+pub async fn get_sync_status_handler(
+    blockchain_lock: Arc<RwLock<Blockchain>>,
+) -> Result<impl Reply> {
+    let blockchain = blockchain_lock.read().await;
+    Ok(warp::reply::json(
+        &blockchain.get_sync_status().get_report(),
+    ))
+}
+
+/// get broadcast metrics handler. Returns how many broadcast messages were lost.
+pub async fn get_broadcast_metrics_handler() -> Result<impl Reply> {
+    Ok(warp::reply::json(&BROADCAST_METRICS.get_report()))
+}
+
+/// get node metadata handler. Lists the metadata records we know of the nodes on the network,
+/// ours included.
+pub async fn get_node_metadata_handler(network_context: NetworkContext) -> Result<impl Reply> {
+    let node_metadata_infos: Vec<NodeMetadataInfo> = network_context
+        .node_metadata
+        .read()
+        .unwrap()
+        .get_all(create_timestamp())
+        .iter()
+        .map(|node_metadata| node_metadata.get_info())
+        .collect();
+    Ok(warp::reply::json(&node_metadata_infos))
+}
+
+/// get node metadata by publickey handler. Replies with the record of the node with the base58
+/// encoded publickey.
+pub async fn get_node_metadata_by_publickey_handler(
+    str_publickey: String,
+    network_context: NetworkContext,
+) -> Result<impl Reply> {
+    let publickey = match publickey_from_base58(&str_publickey) {
+        Ok(publickey) => publickey,
+        Err(_) => return Err(warp::reject::custom(Invalid)),
+    };
+    match network_context
+        .node_metadata
+        .read()
+        .unwrap()
+        .get(&publickey)
+    {
+        Some(node_metadata) => Ok(warp::reply::json(&node_metadata.get_info())),
+        None => Err(warp::reject::not_found()),
+    }
+}
+
+/// get peer filter handler. Returns the current allow and deny lists.
+pub async fn get_peer_filter_handler(network_context: NetworkContext) -> Result<impl Reply> {
+    let peer_filter_settings = network_context.peer_filter.read().unwrap().to_settings();
+    Ok(warp::reply::json(&peer_filter_settings))
+}
+
+/// put peer filter handler. Replaces the allow and deny lists and disconnects any peer which
+/// they no longer allow.
+pub async fn put_peer_filter_handler(
+    peer_filter_settings: PeerFilterSettings,
+    network_context: NetworkContext,
+) -> Result<impl Reply> {
+    if let Err(error) =
+        Network::apply_peer_filter_settings(&network_context, &peer_filter_settings).await
+    {
+        error!("ERROR 410373: invalid peer filter {:?}", error);
+        return Err(warp::reject::custom(Invalid));
+    }
+    get_peer_filter_handler(network_context).await
+}
+
+/// rescan handler. Rebuilds the slips of every wallet from the given block id and replies
+/// with the balances found.
+pub async fn post_rescan_handler(
+    from_block_id: u64,
+    blockchain_lock: Arc<RwLock<Blockchain>>,
+) -> Result<impl Reply> {
+    let blockchain = blockchain_lock.read().await;
+    if let Err(err) = blockchain.rescan_wallets(from_block_id).await {
+        error!("ERROR 290314: cannot rescan the wallets: {}", err);
+        return Err(warp::reject::custom(Invalid));
+    }
+
+    let mut wallet_balance_reports = vec![];
+    for wallet_lock in blockchain.get_wallet_manager().get_wallet_locks() {
+        let wallet = wallet_lock.read().await;
+        wallet_balance_reports.push(WalletBalanceReport {
+            publickey: wallet.get_publickey().to_base58(),
+            available_balance: wallet.get_available_balance(),
+        });
+    }
+    Ok(warp::reply::json(&wallet_balance_reports))
+}
+
+/// get mempool info handler. Reports the size of the mempool and the routing work it holds
+/// against the work needed to produce a block now.
+pub async fn get_mempool_info_handler(
+    mempool_lock: Arc<RwLock<Mempool>>,
+    blockchain_lock: Arc<RwLock<Blockchain>>,
+) -> Result<impl Reply> {
+    let mempool = mempool_lock.read().await;
+    let blockchain = blockchain_lock.read().await;
+    Ok(warp::reply::json(
+        &mempool.get_mempool_info(&blockchain, create_timestamp()),
+    ))
+}
+
+/// get raw mempool handler. Lists the hashes and fees of the transactions in the mempool.
+pub async fn get_raw_mempool_handler(mempool_lock: Arc<RwLock<Mempool>>) -> Result<impl Reply> {
+    let mempool = mempool_lock.read().await;
+    Ok(warp::reply::json(&mempool.get_raw_mempool()))
+}
+
+/// get block template handler. Replies with the block the mempool would produce now,
+/// serialized for the network.
+pub async fn get_block_template_handler(
+    mempool_lock: Arc<RwLock<Mempool>>,
+    blockchain_lock: Arc<RwLock<Blockchain>>,
+) -> Result<impl Reply> {
+    match generate_block_template(mempool_lock, blockchain_lock, create_timestamp()).await {
+        Ok(block) => Ok(block.serialize_for_net(BlockType::Full)),
+        Err(error) => {
+            error!("{}", error);
+            Err(warp::reject::custom(Invalid))
         }
     }
 }
+
+/// submit block handler. Adds a block finalized outside of the node to the blockchain and
+/// replies with whether it is now part of the longest chain, or rejects the body if it is
+/// not a block.
+pub async fn post_submit_block_handler(
+    mut body: impl Buf,
+    mempool_lock: Arc<RwLock<Mempool>>,
+    blockchain_lock: Arc<RwLock<Blockchain>>,
+) -> Result<impl Reply> {
+    let mut buffer = vec![];
+    while body.has_remaining() {
+        buffer.append(&mut body.chunk().to_vec());
+        let cnt = body.chunk().len();
+        body.advance(cnt);
+    }
+
+    let mut block = match Block::try_deserialize_for_net(&buffer) {
+        Some(block) => block,
+        None => return Err(warp::reject::custom(Invalid)),
+    };
+    block.generate_metadata();
+    let block_hash = block.get_hash();
+    let block_id = block.get_id();
+
+    mempool_lock.write().await.add_block(block);
+    Mempool::send_blocks_to_blockchain(mempool_lock, blockchain_lock.clone()).await;
+
+    let blockchain = blockchain_lock.read().await;
+    Ok(warp::reply::json(&SubmitBlockReport {
+        hash: hex::encode(block_hash),
+        is_longest_chain: blockchain
+            .blockring
+            .get_longest_chain_block_hash_by_block_id(block_id)
+            == block_hash,
+    }))
+}
@@ -0,0 +1,161 @@
+use crate::bootstrap::{read_block_record, write_block_record, BOOTSTRAP_RECORD_HEADER_SIZE};
+use crate::consensus_params::ConsensusParams;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::convert::TryInto;
+use std::io::{self, Read, Write};
+
+/// A chunk is filled with blocks until their serialized size reaches this many bytes, so a
+/// range of large blocks is sent as more chunks rather than as one huge message.
+pub const BLOCK_RANGE_CHUNK_SIZE: usize = 4 * 1024 * 1024;
+/// The most a chunk may decompress to: a full chunk whose last block is as large as a block
+/// may be. Anything beyond it is not read, so that a peer cannot send a compression bomb.
+pub const MAX_BLOCK_RANGE_CHUNK_SIZE: usize = BLOCK_RANGE_CHUNK_SIZE
+    + ConsensusParams::MAX_SERIALIZED_BLOCK_SIZE
+    + 2 * BOOTSTRAP_RECORD_HEADER_SIZE;
+/// The most a serialized chunk may take up: its ids and the blocks of the largest chunk
+/// compressed, which zlib may grow by as much as its deflateBound allows for data that does
+/// not compress.
+pub const MAX_SERIALIZED_BLOCK_RANGE_CHUNK_SIZE: usize = 16
+    + MAX_BLOCK_RANGE_CHUNK_SIZE
+    + (MAX_BLOCK_RANGE_CHUNK_SIZE >> 12)
+    + (MAX_BLOCK_RANGE_CHUNK_SIZE >> 14)
+    + (MAX_BLOCK_RANGE_CHUNK_SIZE >> 25)
+    + 13
+    + 6;
+
+/// Data Object for the RESULT__ of REQRANGE. Is used as a payload in an APIMessage message
+/// field.
+/// - `first_block_id` - the first block id of the chunk, that of the request
+/// - `last_block_id` - the last block id of the chunk, the next chunk is requested from the id
+///   after it
+/// - `compressed_blocks` - the blocks serialized for the network, each framed as a record of a
+///   bootstrap file, compressed with zlib
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockRangeChunk {
+    first_block_id: u64,
+    last_block_id: u64,
+    compressed_blocks: Vec<u8>,
+}
+
+impl BlockRangeChunk {
+    /// The chunk holding the serialized blocks, which follow each other on the longest chain
+    /// from first_block_id on. There must be at least one block.
+    pub fn new(first_block_id: u64, serialized_blocks: &[Vec<u8>]) -> io::Result<Self> {
+        let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+        for bytes in serialized_blocks {
+            write_block_record(&mut encoder, bytes)?;
+        }
+        encoder.flush()?;
+        Ok(BlockRangeChunk {
+            first_block_id,
+            last_block_id: first_block_id + serialized_blocks.len() as u64 - 1,
+            compressed_blocks: encoder.finish()?,
+        })
+    }
+
+    /// The blocks serialized for the network, failing if the chunk is corrupt or does not
+    /// hold one block for every id in it.
+    pub fn decompress_blocks(&self) -> crate::Result<Vec<Vec<u8>>> {
+        let mut decoder =
+            ZlibDecoder::new(&self.compressed_blocks[..]).take(MAX_BLOCK_RANGE_CHUNK_SIZE as u64);
+        let mut serialized_blocks = vec![];
+        while let Some(bytes) = read_block_record(&mut decoder)
+            .map_err(|err| format!("block {} {}", serialized_blocks.len() + 1, err))?
+        {
+            serialized_blocks.push(bytes);
+        }
+        if serialized_blocks.len() as u64 != self.get_block_count() {
+            return Err(format!(
+                "chunk of blocks {} to {} has {} blocks",
+                self.first_block_id,
+                self.last_block_id,
+                serialized_blocks.len()
+            )
+            .into());
+        }
+        Ok(serialized_blocks)
+    }
+
+    /// None if the bytes are too short to be a BlockRangeChunk or its ids are out of order.
+    pub fn deserialize(bytes: &[u8]) -> Option<BlockRangeChunk> {
+        if bytes.len() < 16 {
+            return None;
+        }
+        let first_block_id = u64::from_be_bytes(bytes[0..8].try_into().unwrap());
+        let last_block_id = u64::from_be_bytes(bytes[8..16].try_into().unwrap());
+        if last_block_id < first_block_id {
+            return None;
+        }
+        Some(BlockRangeChunk {
+            first_block_id,
+            last_block_id,
+            compressed_blocks: bytes[16..].to_vec(),
+        })
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut vbytes: Vec<u8> = vec![];
+        vbytes.extend(&self.first_block_id.to_be_bytes());
+        vbytes.extend(&self.last_block_id.to_be_bytes());
+        vbytes.extend(&self.compressed_blocks);
+        vbytes
+    }
+    pub fn get_first_block_id(&self) -> u64 {
+        self.first_block_id
+    }
+    pub fn get_last_block_id(&self) -> u64 {
+        self.last_block_id
+    }
+    pub fn get_block_count(&self) -> u64 {
+        self.last_block_id - self.first_block_id + 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networking::message_types::request_block_range_message::RequestBlockRangeMessage;
+
+    #[test]
+    fn block_range_messages_test() {
+        let request_block_range_message = RequestBlockRangeMessage::new(3, 700);
+        assert_eq!(
+            RequestBlockRangeMessage::deserialize(&request_block_range_message.serialize()),
+            Some(request_block_range_message)
+        );
+        assert_eq!(RequestBlockRangeMessage::deserialize(&[0; 15]), None);
+
+        // repetitive blocks compress well
+        let serialized_blocks = vec![vec![1; 1000], vec![2; 1000], vec![3; 10]];
+        let block_range_chunk = BlockRangeChunk::new(5, &serialized_blocks).unwrap();
+        assert_eq!(block_range_chunk.get_last_block_id(), 7);
+        let bytes = block_range_chunk.serialize();
+        assert!(bytes.len() < 500);
+        let block_range_chunk = BlockRangeChunk::deserialize(&bytes).unwrap();
+        assert_eq!(block_range_chunk.get_first_block_id(), 5);
+        assert_eq!(
+            block_range_chunk.decompress_blocks().unwrap(),
+            serialized_blocks
+        );
+
+        // a chunk claiming more blocks than it has, or damaged, is refused
+        let mut bytes = block_range_chunk.serialize();
+        bytes[15] += 1;
+        assert!(BlockRangeChunk::deserialize(&bytes)
+            .unwrap()
+            .decompress_blocks()
+            .is_err());
+        let mut bytes = block_range_chunk.serialize();
+        let last_byte = bytes.len() - 1;
+        bytes[last_byte] ^= 1;
+        assert!(BlockRangeChunk::deserialize(&bytes)
+            .unwrap()
+            .decompress_blocks()
+            .is_err());
+        let mut bytes = block_range_chunk.serialize();
+        bytes[15] -= 3;
+        assert_eq!(BlockRangeChunk::deserialize(&bytes), None);
+    }
+}
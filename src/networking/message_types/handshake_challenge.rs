@@ -1,11 +1,24 @@
-use crate::crypto::{sign_blob, SaitoPrivateKey, SaitoPublicKey, SaitoSignature};
+use crate::crypto::{
+    generate_random_bytes, sign_blob, SaitoHash, SaitoPrivateKey, SaitoPublicKey, SaitoSignature,
+};
 
-use crate::network::CHALLENGE_SIZE;
+use crate::network::{CHALLENGE_EXPIRATION_TIME, CHALLENGE_SIZE};
+use crate::networking::peer_host::{ip_address_from_bytes, ip_address_to_bytes};
 use crate::time::create_timestamp;
+use ahash::AHashMap;
 use std::convert::TryInto;
+use std::net::IpAddr;
+
+/// A random value each side of a handshake puts into the challenge, so that a signed challenge
+/// cannot be replayed on another connection.
+pub type HandshakeNonce = [u8; 32];
 
 /// Data Object for passing handshake information. Is used as a
 /// payload in an APIMessage message field.
+///
+/// Addresses are sent as 16 bytes, with IPv4 addresses mapped into IPv6. The challenger's
+/// nonce is generated when the challenge is created and the opponent's nonce is the one it
+/// sent in its SHAKINIT, so both signatures cover a value the other side chose.
 #[derive(Debug, PartialEq)]
 pub struct HandshakeChallenge {
     pub challenger_node: HandshakeNode,
@@ -15,25 +28,28 @@ pub struct HandshakeChallenge {
 
 #[derive(Debug, PartialEq)]
 pub struct HandshakeNode {
-    pub ip_address: [u8; 4],
+    pub ip_address: IpAddr,
     pub public_key: SaitoPublicKey,
+    pub nonce: HandshakeNonce,
     pub sig: Option<SaitoSignature>,
 }
 
 impl HandshakeChallenge {
     pub fn new(
-        (challenger_ip_address, challenger_public_key): ([u8; 4], SaitoPublicKey),
-        (opponent_ip_adress, opponent_public_key): ([u8; 4], SaitoPublicKey),
+        (challenger_ip_address, challenger_public_key): (IpAddr, SaitoPublicKey),
+        (opponent_ip_adress, opponent_public_key): (IpAddr, SaitoPublicKey),
     ) -> Self {
         Self {
             challenger_node: HandshakeNode {
                 ip_address: challenger_ip_address,
                 public_key: challenger_public_key,
+                nonce: generate_random_bytes(32).try_into().unwrap(),
                 sig: None,
             },
             opponent_node: HandshakeNode {
                 ip_address: opponent_ip_adress,
                 public_key: opponent_public_key,
+                nonce: [0; 32],
                 sig: None,
             },
             timestamp: create_timestamp(),
@@ -41,21 +57,23 @@ impl HandshakeChallenge {
     }
 
     pub fn deserialize(bytes: &Vec<u8>) -> HandshakeChallenge {
-        let mut challenger_octet: [u8; 4] = [0; 4];
-        challenger_octet[0..4].clone_from_slice(&bytes[0..4]);
-        let mut opponent_octet: [u8; 4] = [0; 4];
-        opponent_octet[0..4].clone_from_slice(&bytes[4..8]);
+        let challenger_ip_address = ip_address_from_bytes(bytes[0..16].try_into().unwrap());
+        let opponent_ip_address = ip_address_from_bytes(bytes[16..32].try_into().unwrap());
 
-        let challenger_pubkey: SaitoPublicKey = bytes[8..41].try_into().unwrap();
-        let opponent_pubkey: SaitoPublicKey = bytes[41..74].try_into().unwrap();
-        let timestamp: u64 = u64::from_be_bytes(bytes[74..CHALLENGE_SIZE].try_into().unwrap());
+        let challenger_pubkey: SaitoPublicKey = bytes[32..65].try_into().unwrap();
+        let opponent_pubkey: SaitoPublicKey = bytes[65..98].try_into().unwrap();
+        let timestamp: u64 = u64::from_be_bytes(bytes[98..106].try_into().unwrap());
+        let challenger_nonce: HandshakeNonce = bytes[106..138].try_into().unwrap();
+        let opponent_nonce: HandshakeNonce = bytes[138..CHALLENGE_SIZE].try_into().unwrap();
 
         let mut handshake_challenge = HandshakeChallenge::new(
-            (challenger_octet, challenger_pubkey),
-            (opponent_octet, opponent_pubkey),
+            (challenger_ip_address, challenger_pubkey),
+            (opponent_ip_address, opponent_pubkey),
         );
 
         handshake_challenge.set_timestamp(timestamp);
+        handshake_challenge.set_challenger_nonce(challenger_nonce);
+        handshake_challenge.set_opponent_nonce(opponent_nonce);
 
         if bytes.len() > CHALLENGE_SIZE {
             handshake_challenge.set_challenger_sig(Some(
@@ -77,11 +95,13 @@ impl HandshakeChallenge {
 
     pub fn serialize_raw(&self) -> Vec<u8> {
         let mut vbytes: Vec<u8> = vec![];
-        vbytes.extend(&self.challenger_node.ip_address);
-        vbytes.extend(&self.opponent_node.ip_address);
+        vbytes.extend(&ip_address_to_bytes(self.challenger_node.ip_address));
+        vbytes.extend(&ip_address_to_bytes(self.opponent_node.ip_address));
         vbytes.extend(&self.challenger_node.public_key);
         vbytes.extend(&self.opponent_node.public_key);
         vbytes.extend(&self.timestamp.to_be_bytes());
+        vbytes.extend(&self.challenger_node.nonce);
+        vbytes.extend(&self.opponent_node.nonce);
         vbytes
     }
 
@@ -89,11 +109,11 @@ impl HandshakeChallenge {
         sign_blob(&mut self.serialize_raw(), privatekey).to_owned()
     }
 
-    pub fn challenger_ip_address(&self) -> [u8; 4] {
+    pub fn challenger_ip_address(&self) -> IpAddr {
         self.challenger_node.ip_address
     }
 
-    pub fn opponent_ip_address(&self) -> [u8; 4] {
+    pub fn opponent_ip_address(&self) -> IpAddr {
         self.opponent_node.ip_address
     }
 
@@ -113,6 +133,14 @@ impl HandshakeChallenge {
         self.opponent_node.sig
     }
 
+    pub fn challenger_nonce(&self) -> HandshakeNonce {
+        self.challenger_node.nonce
+    }
+
+    pub fn opponent_nonce(&self) -> HandshakeNonce {
+        self.opponent_node.nonce
+    }
+
     pub fn timestamp(&self) -> u64 {
         self.timestamp
     }
@@ -121,6 +149,14 @@ impl HandshakeChallenge {
         self.timestamp = timestamp;
     }
 
+    pub fn set_challenger_nonce(&mut self, nonce: HandshakeNonce) {
+        self.challenger_node.nonce = nonce
+    }
+
+    pub fn set_opponent_nonce(&mut self, nonce: HandshakeNonce) {
+        self.opponent_node.nonce = nonce
+    }
+
     pub fn set_challenger_sig(&mut self, sig: Option<SaitoSignature>) {
         self.challenger_node.sig = sig
     }
@@ -130,29 +166,92 @@ impl HandshakeChallenge {
     }
 }
 
+/// The challenges this node has sent in answer to a SHAKINIT and not yet seen signed in a
+/// SHAKCOMP, by the connection they were sent on. A connection has at most one outstanding
+/// challenge, and a challenge is accepted once, on its own connection and before it expires.
+#[derive(Debug, Default)]
+pub struct IssuedChallenges {
+    challenges: AHashMap<SaitoHash, (HandshakeNonce, u64)>,
+}
+
+impl IssuedChallenges {
+    pub fn new() -> Self {
+        IssuedChallenges {
+            challenges: AHashMap::new(),
+        }
+    }
+
+    /// Records the challenge sent on the connection, replacing any earlier one, and forgets
+    /// challenges which have expired.
+    pub fn issue(&mut self, connection_id: SaitoHash, challenge: &HandshakeChallenge) {
+        let timestamp = challenge.timestamp();
+        self.challenges
+            .retain(|_, (_, issued_at)| *issued_at + CHALLENGE_EXPIRATION_TIME >= timestamp);
+        self.challenges
+            .insert(connection_id, (challenge.challenger_nonce(), timestamp));
+    }
+
+    /// Whether the challenge is the one we issued on the connection and has not expired. The
+    /// challenge is forgotten either way, so it cannot be used again.
+    pub fn redeem(
+        &mut self,
+        connection_id: &SaitoHash,
+        challenge: &HandshakeChallenge,
+        current_timestamp: u64,
+    ) -> bool {
+        match self.challenges.remove(connection_id) {
+            Some((nonce, issued_at)) => {
+                nonce == challenge.challenger_nonce()
+                    && issued_at == challenge.timestamp()
+                    && issued_at + CHALLENGE_EXPIRATION_TIME >= current_timestamp
+            }
+            None => false,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.challenges.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.challenges.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.challenges.is_empty()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
-        crypto::generate_keys, networking::message_types::handshake_challenge::HandshakeChallenge,
+        crypto::generate_keys,
+        network::{CHALLENGE_EXPIRATION_TIME, CHALLENGE_SIZE},
+        networking::message_types::handshake_challenge::{HandshakeChallenge, IssuedChallenges},
     };
+    use std::net::IpAddr;
 
     #[tokio::test]
     #[serial_test::serial]
     async fn test_challenge_serialize() {
         let (publickey, privatekey) = generate_keys();
-        let challenge =
-            HandshakeChallenge::new(([127, 0, 0, 1], publickey), ([127, 0, 0, 1], publickey));
+        let mut challenge = HandshakeChallenge::new(
+            (IpAddr::from([127, 0, 0, 1]), publickey),
+            ("2001:db8::1".parse().unwrap(), publickey),
+        );
+        challenge.set_opponent_nonce([7; 32]);
 
         let serialized_challenge = challenge.serialize_with_sig(privatekey);
         let deserialized_challenge = HandshakeChallenge::deserialize(&serialized_challenge);
 
+        assert_eq!(serialized_challenge.len(), CHALLENGE_SIZE + 64);
         assert_eq!(
             challenge.challenger_ip_address(),
             deserialized_challenge.challenger_ip_address()
         );
         assert_eq!(
-            challenge.challenger_ip_address(),
-            deserialized_challenge.challenger_ip_address()
+            challenge.opponent_ip_address(),
+            deserialized_challenge.opponent_ip_address()
         );
 
         assert_eq!(
@@ -165,5 +264,49 @@ mod tests {
         );
 
         assert_eq!(challenge.timestamp, deserialized_challenge.timestamp);
+        assert_eq!(
+            challenge.challenger_nonce(),
+            deserialized_challenge.challenger_nonce()
+        );
+        assert_eq!(deserialized_challenge.opponent_nonce(), [7; 32]);
+    }
+
+    #[test]
+    fn issued_challenges_test() {
+        let (publickey, _privatekey) = generate_keys();
+        let ip_address = IpAddr::from([127, 0, 0, 1]);
+        let challenge = HandshakeChallenge::new((ip_address, publickey), (ip_address, publickey));
+        let timestamp = challenge.timestamp();
+        let mut issued_challenges = IssuedChallenges::new();
+
+        // a challenge is accepted once, and only on the connection it was issued on
+        issued_challenges.issue([1; 32], &challenge);
+        assert!(!issued_challenges.redeem(&[2; 32], &challenge, timestamp));
+        assert!(issued_challenges.redeem(&[1; 32], &challenge, timestamp));
+        assert!(!issued_challenges.redeem(&[1; 32], &challenge, timestamp));
+
+        // a newer challenge on the same connection replaces the older one
+        let newer_challenge =
+            HandshakeChallenge::new((ip_address, publickey), (ip_address, publickey));
+        issued_challenges.issue([1; 32], &challenge);
+        issued_challenges.issue([1; 32], &newer_challenge);
+        assert_eq!(issued_challenges.len(), 1);
+        assert!(!issued_challenges.redeem(&[1; 32], &challenge, timestamp));
+
+        // expired challenges are rejected
+        issued_challenges.issue([1; 32], &challenge);
+        assert!(!issued_challenges.redeem(
+            &[1; 32],
+            &challenge,
+            timestamp + CHALLENGE_EXPIRATION_TIME + 1
+        ));
+
+        // and forgotten when another challenge is issued
+        let mut later_challenge =
+            HandshakeChallenge::new((ip_address, publickey), (ip_address, publickey));
+        later_challenge.set_timestamp(timestamp + CHALLENGE_EXPIRATION_TIME + 1);
+        issued_challenges.issue([1; 32], &challenge);
+        issued_challenges.issue([2; 32], &later_challenge);
+        assert_eq!(issued_challenges.len(), 1);
     }
 }
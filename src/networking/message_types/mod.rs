@@ -1,5 +1,7 @@
+pub mod block_range_chunk;
 pub mod handshake_challenge;
 pub mod request_block_message;
+pub mod request_block_range_message;
 pub mod request_blockchain_message;
 pub mod send_block_head_message;
 pub mod send_blockchain_message;
@@ -0,0 +1,43 @@
+use std::convert::TryInto;
+
+/// Data Object for REQRANGE. Is used as a payload in an APIMessage message field.
+/// - `first_block_id` - the first block of the range on the longest chain of the responder
+/// - `last_block_id` - the last block of the range, the responder may send fewer
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestBlockRangeMessage {
+    first_block_id: u64,
+    last_block_id: u64,
+}
+
+impl RequestBlockRangeMessage {
+    pub fn new(first_block_id: u64, last_block_id: u64) -> Self {
+        RequestBlockRangeMessage {
+            first_block_id,
+            last_block_id,
+        }
+    }
+
+    /// None if the bytes are too short to be a RequestBlockRangeMessage.
+    pub fn deserialize(bytes: &[u8]) -> Option<RequestBlockRangeMessage> {
+        if bytes.len() < 16 {
+            return None;
+        }
+        Some(RequestBlockRangeMessage::new(
+            u64::from_be_bytes(bytes[0..8].try_into().unwrap()),
+            u64::from_be_bytes(bytes[8..16].try_into().unwrap()),
+        ))
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut vbytes: Vec<u8> = vec![];
+        vbytes.extend(&self.first_block_id.to_be_bytes());
+        vbytes.extend(&self.last_block_id.to_be_bytes());
+        vbytes
+    }
+    pub fn get_first_block_id(&self) -> u64 {
+        self.first_block_id
+    }
+    pub fn get_last_block_id(&self) -> u64 {
+        self.last_block_id
+    }
+}
@@ -7,11 +7,16 @@ use crate::crypto::SaitoHash;
 /// `latest_block_hash` - (optional)(starts with latest)
 /// 'fork_id` - This is a data object which looks like a hash. It is used to find a common ancestor.
 /// It allows some false positives and will not find the most recent common ancestor.
+/// `block_locator` - (optional) a sparse list of hashes of the longest chain of the requester,
+/// newest first. The responder sends its chain from the latest of them which is on its own
+/// longest chain, i.e. from where the two chains fork. Peers which do not send one are
+/// assumed to share the history of their latest block with the responder.
 #[derive(Debug)]
 pub struct RequestBlockchainMessage {
     latest_block_id: u64,
     latest_block_hash: SaitoHash,
     fork_id: SaitoHash,
+    block_locator: Vec<SaitoHash>,
 }
 
 impl RequestBlockchainMessage {
@@ -20,6 +25,7 @@ impl RequestBlockchainMessage {
             latest_block_id,
             latest_block_hash,
             fork_id,
+            block_locator: vec![],
         }
     }
 
@@ -28,7 +34,21 @@ impl RequestBlockchainMessage {
         let latest_block_hash: SaitoHash = bytes[8..40].try_into().unwrap();
         let fork_id: SaitoHash = bytes[40..72].try_into().unwrap();
 
-        RequestBlockchainMessage::new(latest_block_id, latest_block_hash, fork_id)
+        let mut request_blockchain_message =
+            RequestBlockchainMessage::new(latest_block_id, latest_block_hash, fork_id);
+
+        //
+        // older peers send no block locator
+        //
+        if bytes.len() >= 76 {
+            let block_locator_len = u32::from_be_bytes(bytes[72..76].try_into().unwrap()) as usize;
+            request_blockchain_message.block_locator = bytes[76..]
+                .chunks_exact(32)
+                .take(block_locator_len)
+                .map(|block_hash| block_hash.try_into().unwrap())
+                .collect();
+        }
+        request_blockchain_message
     }
 
     pub fn serialize(&self) -> Vec<u8> {
@@ -36,6 +56,10 @@ impl RequestBlockchainMessage {
         vbytes.extend(&self.latest_block_id.to_be_bytes());
         vbytes.extend(&self.latest_block_hash);
         vbytes.extend(&self.fork_id);
+        vbytes.extend(&(self.block_locator.len() as u32).to_be_bytes());
+        for block_hash in &self.block_locator {
+            vbytes.extend(block_hash);
+        }
         vbytes
     }
     pub fn get_latest_block_id(&self) -> u64 {
@@ -47,6 +71,12 @@ impl RequestBlockchainMessage {
     pub fn get_fork_id(&self) -> &SaitoHash {
         &self.fork_id
     }
+    pub fn get_block_locator(&self) -> &Vec<SaitoHash> {
+        &self.block_locator
+    }
+    pub fn set_block_locator(&mut self, block_locator: Vec<SaitoHash>) {
+        self.block_locator = block_locator;
+    }
 }
 
 #[cfg(test)]
@@ -56,7 +86,8 @@ mod tests {
     #[tokio::test]
     #[serial_test::serial]
     async fn test_request_blockchain_message_serialize() {
-        let request_blockchain_message = RequestBlockchainMessage::new(50, [42; 32], [42; 32]);
+        let mut request_blockchain_message = RequestBlockchainMessage::new(50, [42; 32], [42; 32]);
+        request_blockchain_message.set_block_locator(vec![[1; 32], [2; 32]]);
 
         let serialized_request_blockchain_message = request_blockchain_message.serialize();
         let deserialized_request_blockchain_message =
@@ -73,5 +104,21 @@ mod tests {
             request_blockchain_message.get_fork_id(),
             deserialized_request_blockchain_message.get_fork_id()
         );
+        assert_eq!(
+            request_blockchain_message.get_block_locator(),
+            deserialized_request_blockchain_message.get_block_locator()
+        );
+
+        // a request without a block locator
+        let deserialized_request_blockchain_message = RequestBlockchainMessage::deserialize(
+            &serialized_request_blockchain_message[0..72].to_vec(),
+        );
+        assert_eq!(
+            deserialized_request_blockchain_message.get_latest_block_id(),
+            50
+        );
+        assert!(deserialized_request_blockchain_message
+            .get_block_locator()
+            .is_empty());
     }
 }
@@ -1,35 +1,71 @@
 use std::convert::TryInto;
 
 use crate::crypto::SaitoHash;
+
+/// The size of an announcement carrying the tip of the sender's chain, see
+/// SendBlockHeadMessage::get_chain_work.
+pub const SEND_BLOCK_HEAD_MESSAGE_SIZE: usize = 48;
+
 ///
 /// Data Object for SNDBLKHD
 /// `block_hash` - hash of block we wish to inform our peer about
+/// `block_id` - id of the block, or 0 if the sender did not say
+/// `chain_work` - the burn fees of the sender's chain summed up to the block
+///
+/// Nodes which predate the block id and chain work announce the bare hash.
 ///
 #[derive(Debug)]
 pub struct SendBlockHeadMessage {
     block_hash: SaitoHash,
+    block_id: u64,
+    chain_work: u64,
 }
 
 impl SendBlockHeadMessage {
-    pub fn new(block_hash: SaitoHash) -> Self {
-        SendBlockHeadMessage { block_hash }
+    pub fn new(block_hash: SaitoHash, block_id: u64, chain_work: u64) -> Self {
+        SendBlockHeadMessage {
+            block_hash,
+            block_id,
+            chain_work,
+        }
     }
 
     pub fn deserialize(bytes: &Vec<u8>) -> SendBlockHeadMessage {
         let block_hash: SaitoHash = bytes[0..32].try_into().unwrap();
+        if bytes.len() != SEND_BLOCK_HEAD_MESSAGE_SIZE {
+            return SendBlockHeadMessage::new(block_hash, 0, 0);
+        }
+        let block_id = u64::from_be_bytes(bytes[32..40].try_into().unwrap());
+        let chain_work = u64::from_be_bytes(bytes[40..48].try_into().unwrap());
 
-        SendBlockHeadMessage::new(block_hash)
+        SendBlockHeadMessage::new(block_hash, block_id, chain_work)
     }
 
     pub fn serialize(&self) -> Vec<u8> {
         let mut vbytes: Vec<u8> = vec![];
         vbytes.extend(&self.block_hash);
+        vbytes.extend(&self.block_id.to_be_bytes());
+        vbytes.extend(&self.chain_work.to_be_bytes());
         vbytes
     }
 
     pub fn get_block_hash(&self) -> &SaitoHash {
         &self.block_hash
     }
+
+    pub fn get_block_id(&self) -> u64 {
+        self.block_id
+    }
+
+    pub fn get_chain_work(&self) -> u64 {
+        self.chain_work
+    }
+
+    /// Whether the sender told us the id and chain work of the block, i.e. whether we can
+    /// compare its tip with ours.
+    pub fn has_tip(&self) -> bool {
+        self.block_id != 0
+    }
 }
 
 #[cfg(test)]
@@ -40,14 +76,30 @@ mod tests {
     #[serial_test::serial]
     async fn test_send_block_head_message_serialize() {
         let mock_block_hash = [1; 32];
-        let send_block_head_message = SendBlockHeadMessage::new(mock_block_hash);
+        let send_block_head_message = SendBlockHeadMessage::new(mock_block_hash, 10, 5000);
 
         let serialized_send_block_head_message = send_block_head_message.serialize();
+        assert_eq!(
+            serialized_send_block_head_message.len(),
+            SEND_BLOCK_HEAD_MESSAGE_SIZE
+        );
         let deserialized_send_block_head_message =
             SendBlockHeadMessage::deserialize(&serialized_send_block_head_message);
         assert_eq!(
             send_block_head_message.get_block_hash(),
             deserialized_send_block_head_message.get_block_hash()
         );
+        assert_eq!(deserialized_send_block_head_message.get_block_id(), 10);
+        assert_eq!(deserialized_send_block_head_message.get_chain_work(), 5000);
+        assert!(deserialized_send_block_head_message.has_tip());
+
+        // older nodes announce the bare hash
+        let deserialized_send_block_head_message =
+            SendBlockHeadMessage::deserialize(&mock_block_hash.to_vec());
+        assert_eq!(
+            deserialized_send_block_head_message.get_block_hash(),
+            &mock_block_hash
+        );
+        assert!(!deserialized_send_block_head_message.has_tip());
     }
 }
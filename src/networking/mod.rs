@@ -32,6 +32,8 @@ MessageData should be encoded in a type in networking/message_types for clarity
 
 We reserve 8 bytes for RPC names so that we can easily add and deprecate methods if needed in the future.
 
+The names are the variants of `api_message::MessageName`, and docs/protocol.md is generated from their descriptions.
+
 Two special names are used for responses.
 
 ```bytes
@@ -48,6 +50,9 @@ REQBLKHD
 SNDBLKHD
 SNDTRANS
 REQBLOCK
+REQRANGE
+SNDNMETA
+PING____
 SNDKYLST
 ```
 
@@ -79,10 +84,50 @@ MessageData:
 
 After opening a socket a node should initialize a handshake via SHAKINIT with a `HandshakeChallenge` payload.
 
+MessageData:
+```bytes
+0-15        IP address(IPv4 addresses are mapped into IPv6)
+16-48       Publickey
+49-80       Nonce
+81-89       Services(optional)
+```
+
+The services are what the node serves its peers, which are asked only for what they serve:
+```bytes
+0           Flags: 1 full blocks(REQBLOCK), 2 headers(REQBLKHD), 4 transaction relay(SNDTRANS)
+1-8         Archive depth, how many blocks back from its latest block it serves REQRANGE for
+```
+
+Nodes of protocol versions before 6 send no services, and are taken to serve full blocks,
+headers and transactions, but not REQRANGE. Unknown flags are ignored.
+
+The RESULT__ carries the challenge signed by the responder:
+```bytes
+0-15        Challenger IP address
+16-31       Opponent IP address
+32-64       Challenger publickey
+65-97       Opponent publickey
+98-105      Timestamp
+106-137     Challenger nonce
+138-169     Opponent nonce, as sent in the SHAKINIT
+170-233     Challenger signature
+```
+
+The challenger signs the address the socket was opened from, when it knows it, rather than
+the address claimed in the SHAKINIT. The opponent should check that the challenge carries its
+publickey and nonce before signing it.
+
 ### SHAKCOMP
 
 The opponent must sign the SHAKINIT payload. I.E. sign the entire blob and append the sig(opponent_sig) to it.
 
+The challenger accepts a signed challenge only on the connection it issued it on, only once,
+and only within CHALLENGE_EXPIRATION_TIME of issuing it. Anything else gets an ERROR___ with
+INVALID CHALLENGE.
+
+The RESULT__ is OK followed by the services of the challenger, as in the SHAKINIT. A request
+for something a node does not serve gets an ERROR___ with NOT SERVED.
+
 The Saito CLI can be used to produce a signed challenge.
 
 ### REQCHAIN
@@ -111,7 +156,52 @@ TODO
 
 TODO
 
+### REQRANGE
+
+Asks a peer, usually one configured as an archive provider, for a range of blocks of its longest chain.
+
+MessageData:
+```bytes
+0-7         First block id(big-endian u64)
+8-15        Last block id(big-endian u64)
+```
+
+The RESULT__ holds as many blocks from the first id on as fit in a chunk of about 4MB:
+```bytes
+0-7         First block id of the chunk
+8-15        Last block id of the chunk
+16..        zlib compressed blocks, each as [length - u32][hash of the block bytes][block bytes]
+```
+
+The requester asks for the rest of the range from the block after the last block of the chunk, so a transfer which breaks off resumes from the first block the requester is missing.
+
+### SNDNMETA
+
+Gossips the signed metadata record of a node, which nodes use to discover each other's services. Nodes send the records they know to a peer once the handshake completes, and gossip every record newer than the one they had of its node on to their other peers.
+
+MessageData:
+```bytes
+0-32        Publickey of the node
+33-96       Signature of the hash of the bytes from 97 on, by the node
+97-104      Timestamp
+105-113     Services, as in the SHAKINIT
+114-121     Lowest fee rate the node takes, in nolan per byte
+122         Length of the alias
+123..       Alias(UTF8), then the number of endpoints(u8), then each endpoint(UTF8) preceded by its length(u8)
+```
+
+Records which are invalid or badly signed get an ERROR___ with INVALID NODE METADATA. Records not renewed for a day are dropped.
+
+### PING____
+
+Measures the round trip to the peer and how far its clock is off ours. Nodes ping each of their peers periodically, after first measuring them in the handshake, and use the latencies to order peers when relaying transactions and the clock offsets for network-adjusted time. A ping carries no data.
+
+Response MessageData:
+```bytes
+0-7         Timestamp of the peer when it handled the ping
+```
 
+The peer is taken to have handled the ping halfway through the round trip. Peers speaking an older version of the protocol respond with an ERROR___, and keep the clock offset measured in the handshake.
 
 ### SNDKYLST
 
@@ -121,6 +211,19 @@ TODO
 
 pub mod api_message;
 pub mod filters;
+#[cfg(feature = "grpc")]
+pub mod grpc;
 pub mod handlers;
 pub mod message_types;
+pub mod node_metadata;
+pub mod peer_connection;
+pub mod peer_filter;
+pub mod peer_host;
+pub mod peer_role;
+pub mod peer_services;
+pub mod peer_stats;
+pub mod quic;
+pub mod recently_seen;
+pub mod relay_policy;
 pub mod signals;
+pub mod socks5;
@@ -0,0 +1,385 @@
+use crate::crypto::{hash, sign, verify, SaitoPrivateKey, SaitoPublicKey, SaitoSignature};
+use crate::networking::peer_services::{PeerServices, PEER_SERVICES_SIZE};
+use ahash::AHashMap;
+use base58::ToBase58;
+use serde::Serialize;
+use std::convert::TryInto;
+
+//
+// Nodes find each other's services through metadata records: a node signs
+// its alias, what it serves, where it can be reached and the lowest fee
+// rate it takes, and its peers gossip the record on to theirs. A record is
+// replaced by a newer one of the same node, so nodes sign theirs again
+// periodically, and records which have not been renewed for a day are
+// dropped as those of nodes which have left the network.
+//
+
+/// How many records a node keeps. When full the oldest record makes way for a new one.
+pub const NODE_METADATA_CAPACITY: usize = 1024;
+/// Records older than this many milliseconds are dropped.
+pub const NODE_METADATA_MAX_AGE: u64 = 24 * 60 * 60 * 1000;
+/// How often, in seconds, a node signs and gossips its record again.
+pub const NODE_METADATA_REPUBLISH_INTERVAL: u64 = 60 * 60;
+/// Records dated more than this many milliseconds ahead of our clock are rejected.
+pub const NODE_METADATA_MAX_CLOCK_SKEW: u64 = 10 * 60 * 1000;
+pub const MAX_NODE_ALIAS_LENGTH: usize = 64;
+pub const MAX_NODE_ENDPOINTS: usize = 8;
+pub const MAX_NODE_ENDPOINT_LENGTH: usize = 255;
+
+/// [publickey - 33][signature - 64][timestamp - u64][services - 9][min fee rate - u64]
+pub const NODE_METADATA_HEADER_SIZE: usize = 33 + 64 + 8 + PEER_SERVICES_SIZE + 8;
+
+/// The metadata a node publishes about itself, signed with its publickey. Followed on the wire
+/// by the alias and the endpoints, each preceded by its length as a u8, the endpoints by their
+/// count as a u8.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeMetadata {
+    publickey: SaitoPublicKey,
+    signature: SaitoSignature,
+    timestamp: u64,
+    services: PeerServices,
+    min_fee_rate: u64,
+    alias: String,
+    endpoints: Vec<String>,
+}
+
+/// A readable view of a NodeMetadata, with the publickey base58 encoded.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct NodeMetadataInfo {
+    pub publickey: String,
+    pub timestamp: u64,
+    pub alias: String,
+    pub services: PeerServices,
+    pub min_fee_rate: u64,
+    pub endpoints: Vec<String>,
+}
+
+impl NodeMetadata {
+    /// An unsigned record, failing if the alias or the endpoints do not fit on the wire.
+    pub fn new(
+        publickey: SaitoPublicKey,
+        timestamp: u64,
+        services: PeerServices,
+        min_fee_rate: u64,
+        alias: String,
+        endpoints: Vec<String>,
+    ) -> crate::Result<NodeMetadata> {
+        if alias.len() > MAX_NODE_ALIAS_LENGTH {
+            return Err(
+                format!("node alias is longer than {} bytes", MAX_NODE_ALIAS_LENGTH).into(),
+            );
+        }
+        if endpoints.len() > MAX_NODE_ENDPOINTS {
+            return Err(format!("node has more than {} endpoints", MAX_NODE_ENDPOINTS).into());
+        }
+        if let Some(endpoint) = endpoints
+            .iter()
+            .find(|endpoint| endpoint.len() > MAX_NODE_ENDPOINT_LENGTH)
+        {
+            return Err(format!(
+                "node endpoint {} is longer than {} bytes",
+                endpoint, MAX_NODE_ENDPOINT_LENGTH
+            )
+            .into());
+        }
+        Ok(NodeMetadata {
+            publickey,
+            signature: [0; 64],
+            timestamp,
+            services,
+            min_fee_rate,
+            alias,
+            endpoints,
+        })
+    }
+
+    pub fn get_publickey(&self) -> SaitoPublicKey {
+        self.publickey
+    }
+
+    pub fn get_timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn get_services(&self) -> PeerServices {
+        self.services
+    }
+
+    pub fn get_min_fee_rate(&self) -> u64 {
+        self.min_fee_rate
+    }
+
+    pub fn get_alias(&self) -> &str {
+        &self.alias
+    }
+
+    pub fn get_endpoints(&self) -> &Vec<String> {
+        &self.endpoints
+    }
+
+    pub fn get_info(&self) -> NodeMetadataInfo {
+        NodeMetadataInfo {
+            publickey: self.publickey.to_base58(),
+            timestamp: self.timestamp,
+            alias: self.alias.clone(),
+            services: self.services,
+            min_fee_rate: self.min_fee_rate,
+            endpoints: self.endpoints.clone(),
+        }
+    }
+
+    pub fn sign(&mut self, privatekey: SaitoPrivateKey) {
+        self.signature = sign(&hash(&self.serialize_for_signature()), privatekey);
+    }
+
+    /// Whether the record was signed by the node it is about.
+    pub fn verify(&self) -> bool {
+        verify(
+            &hash(&self.serialize_for_signature()),
+            self.signature,
+            self.publickey,
+        )
+    }
+
+    // everything but the publickey and the signature
+    fn serialize_for_signature(&self) -> Vec<u8> {
+        let mut vbytes: Vec<u8> = vec![];
+        vbytes.extend(&self.timestamp.to_be_bytes());
+        vbytes.extend(self.services.serialize());
+        vbytes.extend(&self.min_fee_rate.to_be_bytes());
+        vbytes.push(self.alias.len() as u8);
+        vbytes.extend(self.alias.as_bytes());
+        vbytes.push(self.endpoints.len() as u8);
+        for endpoint in &self.endpoints {
+            vbytes.push(endpoint.len() as u8);
+            vbytes.extend(endpoint.as_bytes());
+        }
+        vbytes
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut vbytes: Vec<u8> = vec![];
+        vbytes.extend(&self.publickey);
+        vbytes.extend(&self.signature);
+        vbytes.extend(self.serialize_for_signature());
+        vbytes
+    }
+
+    /// None if the bytes are not exactly one record, or its strings are not UTF8. The
+    /// signature is not checked.
+    pub fn deserialize(bytes: &[u8]) -> Option<NodeMetadata> {
+        if bytes.len() < NODE_METADATA_HEADER_SIZE + 2 {
+            return None;
+        }
+        let publickey: SaitoPublicKey = bytes[0..33].try_into().unwrap();
+        let signature: SaitoSignature = bytes[33..97].try_into().unwrap();
+        let timestamp = u64::from_be_bytes(bytes[97..105].try_into().unwrap());
+        let services = PeerServices::deserialize(&bytes[105..114])?;
+        let min_fee_rate = u64::from_be_bytes(bytes[114..122].try_into().unwrap());
+
+        let mut offset = NODE_METADATA_HEADER_SIZE;
+        let alias = read_string(bytes, &mut offset)?;
+        let endpoint_count = *bytes.get(offset)?;
+        offset += 1;
+        let mut endpoints = vec![];
+        for _ in 0..endpoint_count {
+            endpoints.push(read_string(bytes, &mut offset)?);
+        }
+        if offset != bytes.len() {
+            return None;
+        }
+
+        let mut node_metadata = NodeMetadata::new(
+            publickey,
+            timestamp,
+            services,
+            min_fee_rate,
+            alias,
+            endpoints,
+        )
+        .ok()?;
+        node_metadata.signature = signature;
+        Some(node_metadata)
+    }
+}
+
+/// Reads the string preceded by its length, a u8, at the offset, and moves the offset past it.
+fn read_string(bytes: &[u8], offset: &mut usize) -> Option<String> {
+    let length = *bytes.get(*offset)? as usize;
+    let string = bytes.get(*offset + 1..*offset + 1 + length)?;
+    *offset += 1 + length;
+    String::from_utf8(string.to_vec()).ok()
+}
+
+/// The newest record we know of every node, up to NODE_METADATA_CAPACITY records.
+#[derive(Debug, Default)]
+pub struct NodeMetadataDB {
+    records: AHashMap<SaitoPublicKey, NodeMetadata>,
+}
+
+impl NodeMetadataDB {
+    pub fn new() -> Self {
+        NodeMetadataDB {
+            records: AHashMap::new(),
+        }
+    }
+
+    /// Keeps the record if it is newer than the one we have of its node and neither expired
+    /// nor dated ahead of our clock at current_timestamp, returning whether it was kept, i.e.
+    /// is to be gossiped on. The signature is checked by the caller.
+    pub fn insert(&mut self, node_metadata: NodeMetadata, current_timestamp: u64) -> bool {
+        if node_metadata
+            .timestamp
+            .saturating_add(NODE_METADATA_MAX_AGE)
+            < current_timestamp
+            || node_metadata.timestamp
+                > current_timestamp.saturating_add(NODE_METADATA_MAX_CLOCK_SKEW)
+        {
+            return false;
+        }
+        if let Some(known_node_metadata) = self.records.get(&node_metadata.publickey) {
+            if known_node_metadata.timestamp >= node_metadata.timestamp {
+                return false;
+            }
+        }
+        self.records.retain(|_, known_node_metadata| {
+            known_node_metadata.timestamp + NODE_METADATA_MAX_AGE >= current_timestamp
+        });
+        if self.records.len() >= NODE_METADATA_CAPACITY
+            && !self.records.contains_key(&node_metadata.publickey)
+        {
+            let oldest = self
+                .records
+                .values()
+                .min_by_key(|known_node_metadata| known_node_metadata.timestamp)
+                .map(|known_node_metadata| {
+                    (known_node_metadata.publickey, known_node_metadata.timestamp)
+                });
+            match oldest {
+                Some((publickey, timestamp)) if timestamp < node_metadata.timestamp => {
+                    self.records.remove(&publickey);
+                }
+                _ => return false,
+            }
+        }
+        self.records.insert(node_metadata.publickey, node_metadata);
+        true
+    }
+
+    pub fn get(&self, publickey: &SaitoPublicKey) -> Option<&NodeMetadata> {
+        self.records.get(publickey)
+    }
+
+    /// The records which have not expired at current_timestamp, by publickey.
+    pub fn get_all(&self, current_timestamp: u64) -> Vec<&NodeMetadata> {
+        let mut records: Vec<&NodeMetadata> = self
+            .records
+            .values()
+            .filter(|node_metadata| {
+                node_metadata.timestamp + NODE_METADATA_MAX_AGE >= current_timestamp
+            })
+            .collect();
+        records.sort_by_key(|node_metadata| node_metadata.publickey);
+        records
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::generate_keys;
+
+    fn create_node_metadata(timestamp: u64) -> NodeMetadata {
+        let (publickey, privatekey) = generate_keys();
+        let mut node_metadata = NodeMetadata::new(
+            publickey,
+            timestamp,
+            PeerServices::default(),
+            10,
+            String::from("relay"),
+            vec![String::from("wss://relay.example:12101")],
+        )
+        .unwrap();
+        node_metadata.sign(privatekey);
+        node_metadata
+    }
+
+    #[test]
+    fn node_metadata_serialize_test() {
+        let node_metadata = create_node_metadata(1000);
+        assert!(node_metadata.verify());
+        let serialized = node_metadata.serialize();
+        assert_eq!(
+            NodeMetadata::deserialize(&serialized),
+            Some(node_metadata.clone())
+        );
+        assert_eq!(
+            NodeMetadata::deserialize(&serialized[..serialized.len() - 1]),
+            None
+        );
+
+        // a record changed after signing does not verify
+        let mut tampered = serialized.clone();
+        let last_byte = tampered.len() - 1;
+        tampered[last_byte] = b'2';
+        assert!(!NodeMetadata::deserialize(&tampered).unwrap().verify());
+
+        assert!(NodeMetadata::new(
+            [0; 33],
+            0,
+            PeerServices::default(),
+            0,
+            "a".repeat(MAX_NODE_ALIAS_LENGTH + 1),
+            vec![]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn node_metadata_db_test() {
+        let mut node_metadata_db = NodeMetadataDB::new();
+        let node_metadata = create_node_metadata(NODE_METADATA_MAX_AGE);
+        assert!(node_metadata_db.insert(node_metadata.clone(), NODE_METADATA_MAX_AGE));
+        // only newer records replace those we have
+        assert!(!node_metadata_db.insert(node_metadata.clone(), NODE_METADATA_MAX_AGE));
+        let mut newer_node_metadata = node_metadata.clone();
+        newer_node_metadata.timestamp += 1;
+        assert!(node_metadata_db.insert(newer_node_metadata, NODE_METADATA_MAX_AGE));
+        assert_eq!(
+            node_metadata_db
+                .get(&node_metadata.get_publickey())
+                .unwrap()
+                .get_timestamp(),
+            NODE_METADATA_MAX_AGE + 1
+        );
+
+        // expired records and those from the future are rejected
+        assert!(!node_metadata_db.insert(create_node_metadata(0), NODE_METADATA_MAX_AGE + 1));
+        assert!(!node_metadata_db.insert(
+            create_node_metadata(NODE_METADATA_MAX_AGE + NODE_METADATA_MAX_CLOCK_SKEW + 1),
+            NODE_METADATA_MAX_AGE
+        ));
+        assert_eq!(
+            node_metadata_db
+                .get_all(2 * NODE_METADATA_MAX_AGE + 1)
+                .len(),
+            1
+        );
+        assert!(node_metadata_db
+            .get_all(2 * NODE_METADATA_MAX_AGE + 2)
+            .is_empty());
+
+        // when full, the oldest record makes way
+        let mut node_metadata_db = NodeMetadataDB::new();
+        for timestamp in 0..NODE_METADATA_CAPACITY as u64 {
+            assert!(node_metadata_db.insert(create_node_metadata(timestamp), 0));
+        }
+        assert!(!node_metadata_db.insert(create_node_metadata(0), 0));
+        assert!(node_metadata_db.insert(create_node_metadata(1), 0));
+        assert_eq!(node_metadata_db.get_all(0).len(), NODE_METADATA_CAPACITY);
+        assert!(node_metadata_db
+            .get_all(0)
+            .iter()
+            .all(|node_metadata| node_metadata.get_timestamp() > 0));
+    }
+}
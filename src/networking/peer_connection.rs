@@ -0,0 +1,99 @@
+use crate::networking::api_message::APIMessage;
+use async_trait::async_trait;
+use futures::{SinkExt, StreamExt};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{tungstenite, MaybeTlsStream, WebSocketStream};
+use tracing::error;
+
+/// The transport used to connect to a peer. Set per peer in the configuration,
+/// websockets are used unless QUIC is requested.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Copy, Clone, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PeerTransport {
+    #[default]
+    Websocket,
+    Quic,
+}
+
+/// The transport underneath a SaitoPeer. Implementations move APIMessages
+/// across the wire and know nothing about what the messages mean, so
+/// a new transport can be added by implementing this trait without touching
+/// the message-handling logic in SaitoPeer.
+///
+/// Inbound peers connect to us through warp's /wsopen route and outbound
+/// peers are connected to with tokio-tungstenite, so both are implemented
+/// here.
+#[async_trait]
+pub trait PeerConnection: Send {
+    /// Write a message to the peer.
+    async fn send(&mut self, api_message: APIMessage) -> crate::Result<()>;
+    /// Read the next message from the peer. Returns None once the connection
+    /// has been closed by the other side.
+    async fn recv(&mut self) -> Option<crate::Result<APIMessage>>;
+    /// Close the connection.
+    async fn close(&mut self) -> crate::Result<()>;
+}
+
+fn log_empty_message() {
+    error!(
+        "Message of length 0... why?\n
+        This seems to occur if we aren't holding a reference to the sender/stream on the\n
+        other end of the connection. I suspect that when the stream goes out of scope,\n
+        it's deconstructor is being called and sends a 0 length message to indicate\n
+        that the stream has ended... I'm leaving this println here for now because\n
+        it would be very helpful to see this if starts to occur again. We may want to\n
+        treat this as a disconnect."
+    );
+}
+
+#[async_trait]
+impl PeerConnection for warp::ws::WebSocket {
+    async fn send(&mut self, api_message: APIMessage) -> crate::Result<()> {
+        SinkExt::send(self, warp::ws::Message::binary(api_message.serialize())).await?;
+        Ok(())
+    }
+    async fn recv(&mut self) -> Option<crate::Result<APIMessage>> {
+        while let Some(result) = self.next().await {
+            match result {
+                Ok(message) => {
+                    if !message.as_bytes().is_empty() {
+                        return Some(Ok(APIMessage::deserialize(&message.as_bytes().to_vec())));
+                    }
+                    log_empty_message();
+                }
+                Err(error) => return Some(Err(error.into())),
+            }
+        }
+        None
+    }
+    async fn close(&mut self) -> crate::Result<()> {
+        SinkExt::close(self).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl PeerConnection for WebSocketStream<MaybeTlsStream<TcpStream>> {
+    async fn send(&mut self, api_message: APIMessage) -> crate::Result<()> {
+        SinkExt::send(self, tungstenite::Message::binary(api_message.serialize())).await?;
+        Ok(())
+    }
+    async fn recv(&mut self) -> Option<crate::Result<APIMessage>> {
+        while let Some(result) = self.next().await {
+            match result {
+                Ok(message) => {
+                    if !message.is_empty() {
+                        return Some(Ok(APIMessage::deserialize(&message.into_data())));
+                    }
+                    log_empty_message();
+                }
+                Err(error) => return Some(Err(error.into())),
+            }
+        }
+        None
+    }
+    async fn close(&mut self) -> crate::Result<()> {
+        SinkExt::close(self).await?;
+        Ok(())
+    }
+}
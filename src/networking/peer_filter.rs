@@ -0,0 +1,226 @@
+use crate::configuration::PeerFilterSettings;
+use crate::crypto::{publickey_from_base58, SaitoPublicKey};
+use base58::ToBase58;
+use std::collections::HashSet;
+use std::fmt;
+use std::net::IpAddr;
+
+/// An IPv4 or IPv6 address range in CIDR notation, e.g. 10.0.0.0/8 or 2001:db8::/32. A bare
+/// address is a range of one. IPv4 ranges never contain IPv6 addresses and vice versa.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IpRange {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpRange {
+    pub fn parse(ip_range: &str) -> crate::Result<IpRange> {
+        let (address, prefix_len) = match ip_range.split_once('/') {
+            Some((address, prefix_len)) => (address, Some(prefix_len.parse::<u8>()?)),
+            None => (ip_range, None),
+        };
+        let network = address.parse::<IpAddr>()?;
+        let max_prefix_len = if network.is_ipv4() { 32 } else { 128 };
+        let prefix_len = prefix_len.unwrap_or(max_prefix_len);
+        if prefix_len > max_prefix_len {
+            return Err(format!("invalid prefix length in ip range {}", ip_range).into());
+        }
+        Ok(IpRange {
+            network,
+            prefix_len,
+        })
+    }
+    pub fn contains(&self, host: IpAddr) -> bool {
+        match (self.network, host.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(host)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u32::from(network) & mask == u32::from(host) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(host)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u128::from(network) & mask == u128::from(host) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for IpRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix_len)
+    }
+}
+
+/// Allow and deny lists for the peers we will complete a handshake with. A peer is refused if
+/// its publickey or host is denied. If anything is on the allow lists, a peer must also match
+/// one of the allowed publickeys or ip ranges, which lets an operator pin the node to a set of
+/// trusted routing partners.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PeerFilter {
+    allowed_publickeys: HashSet<SaitoPublicKey>,
+    denied_publickeys: HashSet<SaitoPublicKey>,
+    allowed_ip_ranges: Vec<IpRange>,
+    denied_ip_ranges: Vec<IpRange>,
+}
+
+impl PeerFilter {
+    pub fn new() -> Self {
+        PeerFilter::default()
+    }
+
+    pub fn from_settings(peer_filter_settings: &PeerFilterSettings) -> crate::Result<PeerFilter> {
+        Ok(PeerFilter {
+            allowed_publickeys: peer_filter_settings
+                .allowed_publickeys
+                .iter()
+                .map(|publickey| publickey_from_base58(publickey))
+                .collect::<crate::Result<_>>()?,
+            denied_publickeys: peer_filter_settings
+                .denied_publickeys
+                .iter()
+                .map(|publickey| publickey_from_base58(publickey))
+                .collect::<crate::Result<_>>()?,
+            allowed_ip_ranges: peer_filter_settings
+                .allowed_ip_ranges
+                .iter()
+                .map(|ip_range| IpRange::parse(ip_range))
+                .collect::<crate::Result<_>>()?,
+            denied_ip_ranges: peer_filter_settings
+                .denied_ip_ranges
+                .iter()
+                .map(|ip_range| IpRange::parse(ip_range))
+                .collect::<crate::Result<_>>()?,
+        })
+    }
+
+    pub fn to_settings(&self) -> PeerFilterSettings {
+        let mut allowed_publickeys: Vec<String> = self
+            .allowed_publickeys
+            .iter()
+            .map(|publickey| publickey.to_base58())
+            .collect();
+        allowed_publickeys.sort();
+        let mut denied_publickeys: Vec<String> = self
+            .denied_publickeys
+            .iter()
+            .map(|publickey| publickey.to_base58())
+            .collect();
+        denied_publickeys.sort();
+        PeerFilterSettings {
+            allowed_publickeys,
+            denied_publickeys,
+            allowed_ip_ranges: self
+                .allowed_ip_ranges
+                .iter()
+                .map(|ip_range| ip_range.to_string())
+                .collect(),
+            denied_ip_ranges: self
+                .denied_ip_ranges
+                .iter()
+                .map(|ip_range| ip_range.to_string())
+                .collect(),
+        }
+    }
+
+    pub fn is_peer_allowed(&self, publickey: Option<SaitoPublicKey>, host: Option<IpAddr>) -> bool {
+        let is_publickey_in = |publickeys: &HashSet<SaitoPublicKey>| matches!(publickey, Some(publickey) if publickeys.contains(&publickey));
+        let is_host_in = |ip_ranges: &Vec<IpRange>| matches!(host, Some(host) if ip_ranges.iter().any(|ip_range| ip_range.contains(host)));
+
+        if is_publickey_in(&self.denied_publickeys) || is_host_in(&self.denied_ip_ranges) {
+            return false;
+        }
+        if self.allowed_publickeys.is_empty() && self.allowed_ip_ranges.is_empty() {
+            return true;
+        }
+        is_publickey_in(&self.allowed_publickeys) || is_host_in(&self.allowed_ip_ranges)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::generate_keys;
+
+    #[test]
+    fn ip_range_test() {
+        let ip_range = IpRange::parse("10.1.0.0/16").unwrap();
+        assert!(ip_range.contains(IpAddr::from([10, 1, 0, 1])));
+        assert!(ip_range.contains(IpAddr::from([10, 1, 255, 255])));
+        assert!(!ip_range.contains(IpAddr::from([10, 2, 0, 1])));
+        assert!(ip_range.contains("::ffff:10.1.0.1".parse().unwrap()));
+        assert_eq!(ip_range.to_string(), "10.1.0.0/16");
+
+        let ip_range = IpRange::parse("192.168.1.7").unwrap();
+        assert!(ip_range.contains(IpAddr::from([192, 168, 1, 7])));
+        assert!(!ip_range.contains(IpAddr::from([192, 168, 1, 8])));
+
+        let ip_range = IpRange::parse("2001:db8::/32").unwrap();
+        assert!(ip_range.contains("2001:db8:1::1".parse().unwrap()));
+        assert!(!ip_range.contains("2001:db9::1".parse().unwrap()));
+        assert!(!ip_range.contains(IpAddr::from([32, 1, 13, 184])));
+        assert_eq!(ip_range.to_string(), "2001:db8::/32");
+        assert_eq!(IpRange::parse("::1").unwrap().to_string(), "::1/128");
+
+        assert!(IpRange::parse("0.0.0.0/0")
+            .unwrap()
+            .contains(IpAddr::from([8, 8, 8, 8])));
+        assert!(IpRange::parse("10.0.0.0/33").is_err());
+        assert!(IpRange::parse("::/129").is_err());
+        assert!(IpRange::parse("10.0.0/8").is_err());
+    }
+
+    #[test]
+    fn peer_filter_test() {
+        let (publickey, _) = generate_keys();
+        let (other_publickey, _) = generate_keys();
+
+        let peer_filter = PeerFilter::new();
+        assert!(peer_filter.is_peer_allowed(Some(publickey), Some(IpAddr::from([1, 2, 3, 4]))));
+        assert!(peer_filter.is_peer_allowed(None, None));
+
+        let peer_filter = PeerFilter::from_settings(&PeerFilterSettings {
+            denied_publickeys: vec![publickey.to_base58()],
+            denied_ip_ranges: vec![String::from("10.0.0.0/8")],
+            ..PeerFilterSettings::default()
+        })
+        .unwrap();
+        assert!(!peer_filter.is_peer_allowed(Some(publickey), Some(IpAddr::from([1, 2, 3, 4]))));
+        assert!(
+            !peer_filter.is_peer_allowed(Some(other_publickey), Some(IpAddr::from([10, 0, 0, 1])))
+        );
+        assert!(
+            peer_filter.is_peer_allowed(Some(other_publickey), Some(IpAddr::from([1, 2, 3, 4])))
+        );
+        assert!(peer_filter.is_peer_allowed(Some(other_publickey), None));
+
+        let peer_filter = PeerFilter::from_settings(&PeerFilterSettings {
+            allowed_publickeys: vec![publickey.to_base58()],
+            allowed_ip_ranges: vec![String::from("192.168.0.0/16")],
+            denied_ip_ranges: vec![String::from("192.168.1.1")],
+            ..PeerFilterSettings::default()
+        })
+        .unwrap();
+        assert!(peer_filter.is_peer_allowed(Some(publickey), None));
+        assert!(peer_filter
+            .is_peer_allowed(Some(other_publickey), Some(IpAddr::from([192, 168, 2, 1]))));
+        assert!(
+            !peer_filter.is_peer_allowed(Some(other_publickey), Some(IpAddr::from([8, 8, 8, 8])))
+        );
+        assert!(!peer_filter.is_peer_allowed(Some(other_publickey), None));
+        assert!(!peer_filter.is_peer_allowed(Some(publickey), Some(IpAddr::from([192, 168, 1, 1]))));
+
+        assert_eq!(
+            PeerFilter::from_settings(&peer_filter.to_settings()).unwrap(),
+            peer_filter
+        );
+        assert!(PeerFilter::from_settings(&PeerFilterSettings {
+            allowed_publickeys: vec![String::from("not a publickey")],
+            ..PeerFilterSettings::default()
+        })
+        .is_err());
+    }
+}
@@ -0,0 +1,216 @@
+use serde::de::{self, Deserializer, SeqAccess, Visitor};
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+
+/// Where a peer can be reached. Hostnames are resolved each time we connect, so a peer
+/// configured by name follows its DNS records.
+///
+/// In the configuration a host is written as a string, e.g. "10.0.0.1", "::1" or
+/// "node.saito.io". The octet arrays of older configurations, e.g. [127, 0, 0, 1], are also
+/// accepted.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PeerHost {
+    Ip(IpAddr),
+    Hostname(String),
+}
+
+impl PeerHost {
+    /// Parses an IPv4 or IPv6 address, optionally in brackets, falling back to a hostname.
+    pub fn parse(host: &str) -> PeerHost {
+        let address = host.trim_start_matches('[').trim_end_matches(']');
+        match address.parse::<IpAddr>() {
+            Ok(ip_address) => PeerHost::Ip(ip_address.to_canonical()),
+            Err(_) => PeerHost::Hostname(host.to_string()),
+        }
+    }
+
+    /// The address of the host, if it is not a hostname.
+    pub fn get_ip_address(&self) -> Option<IpAddr> {
+        match self {
+            PeerHost::Ip(ip_address) => Some(*ip_address),
+            PeerHost::Hostname(_) => None,
+        }
+    }
+
+    /// The host and port as written in a URL, with IPv6 addresses in brackets.
+    pub fn format_url_string(&self, port: u16) -> String {
+        match self {
+            PeerHost::Ip(IpAddr::V6(ip6)) => format!("[{}]:{}", ip6, port),
+            _ => format!("{}:{}", self, port),
+        }
+    }
+
+    /// Looks up the socket address to connect to, resolving hostnames through DNS.
+    pub async fn resolve(&self, port: u16) -> crate::Result<SocketAddr> {
+        match self {
+            PeerHost::Ip(ip_address) => Ok(SocketAddr::new(*ip_address, port)),
+            PeerHost::Hostname(hostname) => tokio::net::lookup_host((hostname.as_str(), port))
+                .await?
+                .next()
+                .ok_or_else(|| format!("no addresses found for host {}", hostname).into()),
+        }
+    }
+}
+
+impl From<IpAddr> for PeerHost {
+    fn from(ip_address: IpAddr) -> Self {
+        PeerHost::Ip(ip_address.to_canonical())
+    }
+}
+
+impl fmt::Display for PeerHost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PeerHost::Ip(ip_address) => write!(f, "{}", ip_address),
+            PeerHost::Hostname(hostname) => write!(f, "{}", hostname),
+        }
+    }
+}
+
+impl Serialize for PeerHost {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for PeerHost {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(PeerHostVisitor)
+    }
+}
+
+struct PeerHostVisitor;
+
+impl<'de> Visitor<'de> for PeerHostVisitor {
+    type Value = PeerHost;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an ip address, a hostname or an array of 4 or 16 octets")
+    }
+
+    fn visit_str<E: de::Error>(self, host: &str) -> Result<PeerHost, E> {
+        Ok(PeerHost::parse(host))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<PeerHost, A::Error> {
+        let mut octets: Vec<u8> = vec![];
+        while let Some(octet) = seq.next_element::<u8>()? {
+            octets.push(octet);
+        }
+        match octets.len() {
+            4 => Ok(PeerHost::from(IpAddr::from(
+                <[u8; 4]>::try_from(octets).unwrap(),
+            ))),
+            16 => Ok(PeerHost::from(IpAddr::from(
+                <[u8; 16]>::try_from(octets).unwrap(),
+            ))),
+            len => Err(de::Error::invalid_length(len, &self)),
+        }
+    }
+}
+
+/// Deserializes an address the node binds to, which must be an ip address rather than a
+/// hostname.
+pub fn deserialize_ip_address<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<IpAddr, D::Error> {
+    match PeerHost::deserialize(deserializer)? {
+        PeerHost::Ip(ip_address) => Ok(ip_address),
+        PeerHost::Hostname(hostname) => Err(de::Error::custom(format!(
+            "{} is not an ip address",
+            hostname
+        ))),
+    }
+}
+
+/// The 16 bytes of an address as sent over the network. IPv4 addresses are mapped into IPv6.
+pub fn ip_address_to_bytes(ip_address: IpAddr) -> [u8; 16] {
+    match ip_address {
+        IpAddr::V4(ip4) => ip4.to_ipv6_mapped().octets(),
+        IpAddr::V6(ip6) => ip6.octets(),
+    }
+}
+
+pub fn ip_address_from_bytes(bytes: [u8; 16]) -> IpAddr {
+    IpAddr::V6(Ipv6Addr::from(bytes)).to_canonical()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn peer_host_parse_test() {
+        assert_eq!(
+            PeerHost::parse("10.0.0.1"),
+            PeerHost::Ip(IpAddr::from([10, 0, 0, 1]))
+        );
+        assert_eq!(
+            PeerHost::parse("[::1]"),
+            PeerHost::Ip("::1".parse().unwrap())
+        );
+        // IPv4 addresses mapped into IPv6 are the same host as the IPv4 address
+        assert_eq!(
+            PeerHost::parse("::ffff:10.0.0.1"),
+            PeerHost::Ip(IpAddr::from([10, 0, 0, 1]))
+        );
+        assert_eq!(
+            PeerHost::parse("node.saito.io"),
+            PeerHost::Hostname(String::from("node.saito.io"))
+        );
+
+        assert_eq!(
+            PeerHost::parse("10.0.0.1").format_url_string(12101),
+            "10.0.0.1:12101"
+        );
+        assert_eq!(
+            PeerHost::parse("::1").format_url_string(12101),
+            "[::1]:12101"
+        );
+        assert_eq!(
+            PeerHost::parse("node.saito.io").format_url_string(12101),
+            "node.saito.io:12101"
+        );
+    }
+
+    #[test]
+    fn peer_host_deserialize_test() {
+        let peer_host: PeerHost = serde_json::from_str("[127, 0, 0, 1]").unwrap();
+        assert_eq!(peer_host, PeerHost::Ip(IpAddr::from([127, 0, 0, 1])));
+        let peer_host: PeerHost = serde_json::from_str("\"::1\"").unwrap();
+        assert_eq!(peer_host, PeerHost::Ip("::1".parse().unwrap()));
+        let peer_host: PeerHost = serde_json::from_str("\"localhost\"").unwrap();
+        assert_eq!(peer_host, PeerHost::Hostname(String::from("localhost")));
+        assert!(serde_json::from_str::<PeerHost>("[127, 0, 1]").is_err());
+
+        assert_eq!(
+            serde_json::to_string(&PeerHost::parse("::1")).unwrap(),
+            "\"::1\""
+        );
+    }
+
+    #[test]
+    fn ip_address_bytes_test() {
+        for ip_address in [
+            IpAddr::from([127, 0, 0, 1]),
+            "2001:db8::1".parse::<IpAddr>().unwrap(),
+        ] {
+            assert_eq!(
+                ip_address_from_bytes(ip_address_to_bytes(ip_address)),
+                ip_address
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn peer_host_resolve_test() {
+        assert_eq!(
+            PeerHost::parse("::1").resolve(12101).await.unwrap(),
+            "[::1]:12101".parse::<SocketAddr>().unwrap()
+        );
+        let socket_addr = PeerHost::parse("localhost").resolve(12101).await.unwrap();
+        assert!(socket_addr.ip().is_loopback());
+        assert_eq!(socket_addr.port(), 12101);
+    }
+}
@@ -0,0 +1,94 @@
+use crate::crypto::SaitoHash;
+
+/// What we use a peer from the peer list for. Routing nodes are usually deployed with a few
+/// trusted block sources, separate transaction relays and an archive node which keeps the
+/// whole chain, and the roles let the configuration say which is which.
+///
+/// Peers configured without roles, and peers which connect to us, are used for everything.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum PeerRole {
+    /// we ask the peer for its chain after the handshake
+    BlockSource,
+    /// we relay transactions to the peer
+    TransactionRelay,
+    /// we ask the peer for old blocks we are missing
+    ArchiveProvider,
+}
+
+/// How strongly a peer with the given roles is preferred for a request needing the role,
+/// lower first: peers configured for the role are preferred over peers used for everything.
+/// None if the peer is configured for other roles only.
+pub fn get_role_preference(roles: &[PeerRole], role: PeerRole) -> Option<u8> {
+    if roles.contains(&role) {
+        Some(0)
+    } else if roles.is_empty() {
+        Some(1)
+    } else {
+        None
+    }
+}
+
+/// Orders the candidate peers by their preference for the role, keeping the order of peers
+/// preferred equally, and leaves out peers which are not used for the role.
+pub fn order_peers_for_role(
+    candidates: Vec<(SaitoHash, &[PeerRole])>,
+    role: PeerRole,
+) -> Vec<SaitoHash> {
+    let mut preferred: Vec<(u8, SaitoHash)> = candidates
+        .into_iter()
+        .filter_map(|(connection_id, roles)| {
+            get_role_preference(roles, role).map(|preference| (preference, connection_id))
+        })
+        .collect();
+    preferred.sort_by_key(|(preference, _)| *preference);
+    preferred
+        .into_iter()
+        .map(|(_, connection_id)| connection_id)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn order_peers_for_role_test() {
+        let block_source = [PeerRole::BlockSource];
+        let archive_provider = [PeerRole::ArchiveProvider, PeerRole::BlockSource];
+        let candidates: Vec<(SaitoHash, &[PeerRole])> = vec![
+            ([1; 32], &[]),
+            ([2; 32], &block_source),
+            ([3; 32], &archive_provider),
+            ([4; 32], &[]),
+        ];
+
+        assert_eq!(
+            order_peers_for_role(candidates.clone(), PeerRole::ArchiveProvider),
+            vec![[3; 32], [1; 32], [4; 32]]
+        );
+        assert_eq!(
+            order_peers_for_role(candidates.clone(), PeerRole::BlockSource),
+            vec![[2; 32], [3; 32], [1; 32], [4; 32]]
+        );
+        assert_eq!(
+            order_peers_for_role(candidates, PeerRole::TransactionRelay),
+            vec![[1; 32], [4; 32]]
+        );
+    }
+
+    #[test]
+    fn peer_role_deserialize_test() {
+        let roles: Vec<PeerRole> =
+            serde_json::from_str("[\"block_source\", \"transaction_relay\", \"archive_provider\"]")
+                .unwrap();
+        assert_eq!(
+            roles,
+            vec![
+                PeerRole::BlockSource,
+                PeerRole::TransactionRelay,
+                PeerRole::ArchiveProvider
+            ]
+        );
+    }
+}
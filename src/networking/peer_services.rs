@@ -0,0 +1,130 @@
+use std::convert::TryInto;
+
+//
+// Not every node serves everything: a lite node keeps headers only, a node
+// with little disk keeps the recent blocks only, and a routing node may not
+// want to relay transactions. Peers advertise what they serve during the
+// handshake, the initiator at the end of its SHAKINIT and the responder at
+// the end of the RESULT__ to the SHAKCOMP, and are only asked for what they
+// advertised. Peers which advertise nothing speak an older version of the
+// protocol, and are taken to serve full blocks, headers and transactions,
+// but not ranges of blocks, which older versions do not know.
+//
+
+/// [flags - u8][archive depth - u64]
+pub const PEER_SERVICES_SIZE: usize = 9;
+
+const FULL_BLOCKS_FLAG: u8 = 1;
+const HEADERS_FLAG: u8 = 1 << 1;
+const TRANSACTION_RELAY_FLAG: u8 = 1 << 2;
+
+/// What a node serves its peers. Our own services are configured in the network settings, by
+/// default everything, including our whole chain.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct PeerServices {
+    /// the node answers REQBLOCK with full blocks
+    pub full_blocks: bool,
+    /// the node answers REQBLKHD with block headers
+    pub headers: bool,
+    /// the node accepts transactions and relays them on
+    pub transaction_relay: bool,
+    /// how many blocks back from its latest block the node answers REQRANGE for, 0 if it does
+    /// not serve ranges of blocks and u64::MAX for its whole chain
+    pub archive_depth: u64,
+}
+
+impl Default for PeerServices {
+    fn default() -> Self {
+        PeerServices {
+            full_blocks: true,
+            headers: true,
+            transaction_relay: true,
+            archive_depth: u64::MAX,
+        }
+    }
+}
+
+impl PeerServices {
+    /// What a peer which advertises nothing is taken to serve.
+    pub fn unadvertised() -> Self {
+        PeerServices {
+            archive_depth: 0,
+            ..PeerServices::default()
+        }
+    }
+
+    /// Whether the node serves REQRANGE for the blocks from first_block_id while its latest
+    /// block is latest_block_id.
+    pub fn serves_block_range(&self, first_block_id: u64, latest_block_id: u64) -> bool {
+        self.archive_depth > 0
+            && latest_block_id.saturating_sub(first_block_id) < self.archive_depth
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut flags = 0;
+        if self.full_blocks {
+            flags |= FULL_BLOCKS_FLAG;
+        }
+        if self.headers {
+            flags |= HEADERS_FLAG;
+        }
+        if self.transaction_relay {
+            flags |= TRANSACTION_RELAY_FLAG;
+        }
+        let mut vbytes: Vec<u8> = vec![flags];
+        vbytes.extend(&self.archive_depth.to_be_bytes());
+        vbytes
+    }
+
+    /// None if the bytes are too few, i.e. the peer advertised nothing. Flags this version does
+    /// not know are ignored.
+    pub fn deserialize(bytes: &[u8]) -> Option<PeerServices> {
+        if bytes.len() < PEER_SERVICES_SIZE {
+            return None;
+        }
+        let flags = bytes[0];
+        Some(PeerServices {
+            full_blocks: flags & FULL_BLOCKS_FLAG != 0,
+            headers: flags & HEADERS_FLAG != 0,
+            transaction_relay: flags & TRANSACTION_RELAY_FLAG != 0,
+            archive_depth: u64::from_be_bytes(bytes[1..9].try_into().unwrap()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn peer_services_test() {
+        let lite_node = PeerServices {
+            full_blocks: false,
+            headers: true,
+            transaction_relay: false,
+            archive_depth: 0,
+        };
+        let serialized = lite_node.serialize();
+        assert_eq!(serialized.len(), PEER_SERVICES_SIZE);
+        assert_eq!(PeerServices::deserialize(&serialized), Some(lite_node));
+        assert_eq!(PeerServices::deserialize(&serialized[..8]), None);
+
+        // flags from a later version are ignored
+        let mut serialized = PeerServices::default().serialize();
+        serialized[0] |= 1 << 7;
+        assert_eq!(
+            PeerServices::deserialize(&serialized),
+            Some(PeerServices::default())
+        );
+
+        assert!(!PeerServices::unadvertised().serves_block_range(1, 10));
+        assert!(PeerServices::default().serves_block_range(0, u64::MAX - 1));
+        let archive_node = PeerServices {
+            archive_depth: 10,
+            ..PeerServices::default()
+        };
+        assert!(archive_node.serves_block_range(1, 10));
+        assert!(!archive_node.serves_block_range(1, 11));
+    }
+}
@@ -0,0 +1,298 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::path::Path;
+
+//
+// What we learn about a peer while connected to it is lost when the
+// connection drops, and with it everything we knew on a restart. The peer
+// stats keep, for every peer address we connect to, how long we stayed
+// connected, how often connecting failed, how many blocks it served us, how
+// many invalid messages it sent and the latency of its last responses. They
+// are saved to a file, so that a restarted node reconnects to the peers
+// which served it well before the others, see PeerStats::get_score.
+//
+
+/// How many of the latest latency samples of a peer are kept for its percentiles.
+pub const PEER_LATENCY_SAMPLES: usize = 100;
+
+/// The record of a peer address across its connections.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct PeerStats {
+    /// milliseconds we were connected to the peer, the current connection excepted
+    connected_time: u64,
+    connections: u64,
+    failed_connections: u64,
+    blocks_served: u64,
+    invalid_messages: u64,
+    latency_samples: VecDeque<u64>,
+    // when the current connection was made, not saved as it ends with the node
+    #[serde(skip)]
+    connected_since: Option<u64>,
+}
+
+/// The stats of a peer address as reported by GET /peerstats. Latencies are in milliseconds.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PeerStatsReport {
+    pub address: String,
+    pub connected_time: u64,
+    pub connections: u64,
+    pub failed_connections: u64,
+    pub blocks_served: u64,
+    pub invalid_messages: u64,
+    pub latency_p50: Option<u64>,
+    pub latency_p90: Option<u64>,
+    pub latency_p99: Option<u64>,
+    pub score: i64,
+}
+
+impl PeerStats {
+    pub fn on_connected(&mut self, timestamp: u64) {
+        if self.connected_since.is_none() {
+            self.connections += 1;
+            self.connected_since = Some(timestamp);
+        }
+    }
+
+    pub fn on_disconnected(&mut self, timestamp: u64) {
+        if let Some(connected_since) = self.connected_since.take() {
+            self.connected_time += timestamp.saturating_sub(connected_since);
+        }
+    }
+
+    pub fn on_connection_failed(&mut self) {
+        self.failed_connections += 1;
+    }
+
+    pub fn add_blocks_served(&mut self, blocks: u64) {
+        self.blocks_served += blocks;
+    }
+
+    pub fn add_invalid_message(&mut self) {
+        self.invalid_messages += 1;
+    }
+
+    pub fn add_latency_sample(&mut self, round_trip_time: u64) {
+        if self.latency_samples.len() == PEER_LATENCY_SAMPLES {
+            self.latency_samples.pop_front();
+        }
+        self.latency_samples.push_back(round_trip_time);
+    }
+
+    /// Milliseconds we have been connected to the peer up to timestamp, over all connections.
+    pub fn get_connected_time(&self, timestamp: u64) -> u64 {
+        self.connected_time
+            + self.connected_since.map_or(0, |connected_since| {
+                timestamp.saturating_sub(connected_since)
+            })
+    }
+
+    pub fn get_blocks_served(&self) -> u64 {
+        self.blocks_served
+    }
+
+    pub fn get_invalid_messages(&self) -> u64 {
+        self.invalid_messages
+    }
+
+    /// The latency below which percentile percent of the latest samples fall, by the nearest
+    /// rank, or None without samples.
+    pub fn get_latency_percentile(&self, percentile: u64) -> Option<u64> {
+        if self.latency_samples.is_empty() {
+            return None;
+        }
+        let mut latency_samples: Vec<u64> = self.latency_samples.iter().copied().collect();
+        latency_samples.sort_unstable();
+        let rank = (percentile.min(100) as usize * latency_samples.len()).div_ceil(100);
+        Some(latency_samples[rank.max(1) - 1])
+    }
+
+    /// How good a peer has been to us. A minute connected or a block served counts one, a
+    /// failed connection takes ten off, an invalid message a hundred and every tenth of a
+    /// second of median latency one. Peers we know nothing of score 0.
+    pub fn get_score(&self, timestamp: u64) -> i64 {
+        (self.get_connected_time(timestamp) / 60000) as i64 + self.blocks_served as i64
+            - 10 * self.failed_connections as i64
+            - 100 * self.invalid_messages as i64
+            - self.get_latency_percentile(50).unwrap_or(0) as i64 / 100
+    }
+
+    /// The stats as they would be saved at timestamp, i.e. with the current connection ended.
+    fn to_saved(&self, timestamp: u64) -> Self {
+        let mut peer_stats = self.clone();
+        peer_stats.on_disconnected(timestamp);
+        peer_stats
+    }
+}
+
+/// The stats of every peer address we have connected to, by host and port, e.g.
+/// 127.0.0.1:12101.
+#[derive(Debug, Default)]
+pub struct PeerStatsDB {
+    entries: BTreeMap<String, PeerStats>,
+    // the file the stats are saved to
+    stats_file: Option<String>,
+    // whether the stats changed since they were last saved
+    changed: bool,
+}
+
+impl PeerStatsDB {
+    pub fn new(stats_file: Option<String>) -> Self {
+        PeerStatsDB {
+            entries: BTreeMap::new(),
+            stats_file,
+            changed: false,
+        }
+    }
+
+    /// Loads the stats saved to stats_file, or starts without stats if there is no such file.
+    pub fn load(stats_file: &str) -> crate::Result<Self> {
+        let mut peer_stats_db = PeerStatsDB::new(Some(stats_file.to_string()));
+        if Path::new(stats_file).exists() {
+            let bytes = std::fs::read(stats_file)?;
+            peer_stats_db.entries = serde_json::from_slice(&bytes)
+                .map_err(|err| format!("cannot read peer stats from {}: {}", stats_file, err))?;
+        }
+        Ok(peer_stats_db)
+    }
+
+    /// Saves the stats to the stats file if they changed since they were last saved, which
+    /// they do all the time while we are connected to a peer. The file is replaced whole, so a
+    /// crash while saving leaves the previous stats.
+    pub fn save(&mut self, timestamp: u64) -> crate::Result<()> {
+        let changed = self.changed
+            || self
+                .entries
+                .values()
+                .any(|peer_stats| peer_stats.connected_since.is_some());
+        let stats_file = match self.stats_file.as_ref() {
+            Some(stats_file) if changed => stats_file,
+            _ => return Ok(()),
+        };
+        let entries: BTreeMap<&String, PeerStats> = self
+            .entries
+            .iter()
+            .map(|(address, peer_stats)| (address, peer_stats.to_saved(timestamp)))
+            .collect();
+        let temporary_file = format!("{}.tmp", stats_file);
+        std::fs::write(&temporary_file, serde_json::to_vec_pretty(&entries)?)?;
+        std::fs::rename(&temporary_file, stats_file)?;
+        self.changed = false;
+        Ok(())
+    }
+
+    pub fn get(&self, address: &str) -> Option<&PeerStats> {
+        self.entries.get(address)
+    }
+
+    /// Updates the stats of a peer address, creating them on first use.
+    pub fn update<F: FnOnce(&mut PeerStats)>(&mut self, address: &str, update: F) {
+        update(self.entries.entry(address.to_string()).or_default());
+        self.changed = true;
+    }
+
+    pub fn get_score(&self, address: &str, timestamp: u64) -> i64 {
+        self.get(address)
+            .map_or(0, |peer_stats| peer_stats.get_score(timestamp))
+    }
+
+    /// The stats of every peer address, best scoring first.
+    pub fn get_reports(&self, timestamp: u64) -> Vec<PeerStatsReport> {
+        let mut reports: Vec<PeerStatsReport> = self
+            .entries
+            .iter()
+            .map(|(address, peer_stats)| PeerStatsReport {
+                address: address.clone(),
+                connected_time: peer_stats.get_connected_time(timestamp),
+                connections: peer_stats.connections,
+                failed_connections: peer_stats.failed_connections,
+                blocks_served: peer_stats.blocks_served,
+                invalid_messages: peer_stats.invalid_messages,
+                latency_p50: peer_stats.get_latency_percentile(50),
+                latency_p90: peer_stats.get_latency_percentile(90),
+                latency_p99: peer_stats.get_latency_percentile(99),
+                score: peer_stats.get_score(timestamp),
+            })
+            .collect();
+        reports.sort_by_key(|report| -report.score);
+        reports
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn peer_stats_test() {
+        let mut peer_stats = PeerStats::default();
+        assert_eq!(peer_stats.get_score(0), 0);
+        assert_eq!(peer_stats.get_latency_percentile(50), None);
+
+        peer_stats.on_connected(1000);
+        peer_stats.on_connected(2000);
+        assert_eq!(peer_stats.get_connected_time(121000), 120000);
+        peer_stats.on_disconnected(121000);
+        assert_eq!(peer_stats.get_connected_time(500000), 120000);
+        assert_eq!(peer_stats.connections, 1);
+
+        for latency in 1..=200 {
+            peer_stats.add_latency_sample(latency);
+        }
+        // only the latest samples are kept
+        assert_eq!(peer_stats.latency_samples.len(), PEER_LATENCY_SAMPLES);
+        assert_eq!(peer_stats.get_latency_percentile(50), Some(150));
+        assert_eq!(peer_stats.get_latency_percentile(90), Some(190));
+        assert_eq!(peer_stats.get_latency_percentile(100), Some(200));
+        assert_eq!(peer_stats.get_latency_percentile(0), Some(101));
+
+        peer_stats.add_blocks_served(10);
+        assert_eq!(peer_stats.get_score(0), 2 + 10 - 1);
+        peer_stats.on_connection_failed();
+        peer_stats.add_invalid_message();
+        assert_eq!(peer_stats.get_score(0), 2 + 10 - 1 - 10 - 100);
+    }
+
+    #[test]
+    fn peer_stats_db_save_load_test() {
+        let stats_file = "data/test/peer_stats.json";
+        std::fs::create_dir_all("data/test").unwrap();
+        let _ = std::fs::remove_file(stats_file);
+
+        let mut peer_stats_db = PeerStatsDB::load(stats_file).unwrap();
+        peer_stats_db.update("127.0.0.1:12101", |peer_stats| {
+            peer_stats.on_connected(0);
+            peer_stats.add_blocks_served(5);
+            peer_stats.add_latency_sample(40);
+        });
+        peer_stats_db.update("127.0.0.1:12102", |peer_stats| {
+            peer_stats.add_invalid_message();
+        });
+        peer_stats_db.save(60000).unwrap();
+
+        // the connection still open counts as ended when it is saved
+        let loaded_peer_stats_db = PeerStatsDB::load(stats_file).unwrap();
+        let peer_stats = loaded_peer_stats_db.get("127.0.0.1:12101").unwrap();
+        assert_eq!(peer_stats.get_connected_time(1000000), 60000);
+        assert_eq!(peer_stats.get_blocks_served(), 5);
+        assert_eq!(peer_stats.get_latency_percentile(50), Some(40));
+        assert_eq!(
+            loaded_peer_stats_db
+                .get_reports(0)
+                .iter()
+                .map(|report| report.address.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["127.0.0.1:12101", "127.0.0.1:12102"]
+        );
+        assert_eq!(loaded_peer_stats_db.get_score("127.0.0.1:12103", 0), 0);
+        // the live stats keep counting the connection
+        assert_eq!(
+            peer_stats_db
+                .get("127.0.0.1:12101")
+                .unwrap()
+                .get_connected_time(120000),
+            120000
+        );
+
+        std::fs::remove_file(stats_file).unwrap();
+    }
+}
@@ -0,0 +1,311 @@
+use crate::blockchain::Blockchain;
+use crate::consensus::SaitoMessage;
+use crate::mempool::Mempool;
+use crate::network::NetworkContext;
+use crate::networking::api_message::{APIMessage, API_MESSAGE_HEADER_SIZE};
+use crate::networking::message_types::block_range_chunk::MAX_SERIALIZED_BLOCK_RANGE_CHUNK_SIZE;
+use crate::networking::peer_connection::PeerConnection;
+use crate::networking::peer_host::PeerHost;
+use crate::peer::handle_inbound_peer_connection;
+use crate::wallet::Wallet;
+use async_trait::async_trait;
+use futures::StreamExt;
+use quinn::{ClientConfig, Connection, Endpoint, NewConnection, RecvStream, SendStream};
+use std::convert::TryInto;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use tokio::sync::{broadcast, RwLock};
+use tracing::{error, info};
+
+//
+// QUIC is only used to carry APIMessages between nodes. Peers prove their
+// identity by signing the SHAKINIT challenge, so the TLS certificate is a
+// throwaway self-signed one and we do not verify the certificates of the
+// nodes we connect to.
+//
+const QUIC_SERVER_NAME: &str = "saito";
+
+/// The largest frame a peer may send us. The largest APIMessages carry a block range chunk,
+/// which holds at least the largest block we may be sent on its own. A peer announcing a
+/// larger frame is disconnected rather than buffered.
+pub const MAX_QUIC_FRAME_SIZE: usize =
+    API_MESSAGE_HEADER_SIZE + MAX_SERIALIZED_BLOCK_RANGE_CHUNK_SIZE;
+
+/// A peer connection over a single bidirectional QUIC stream. Each APIMessage
+/// is framed with its length as a 4-byte big-endian u32.
+pub struct QuicPeerConnection {
+    connection: Connection,
+    send_stream: SendStream,
+    recv_stream: RecvStream,
+    read_buffer: Vec<u8>,
+    // the client endpoint must outlive the connections made from it
+    _endpoint: Option<Endpoint>,
+}
+
+impl QuicPeerConnection {
+    fn new(
+        connection: Connection,
+        send_stream: SendStream,
+        recv_stream: RecvStream,
+        endpoint: Option<Endpoint>,
+    ) -> Self {
+        QuicPeerConnection {
+            connection,
+            send_stream,
+            recv_stream,
+            read_buffer: vec![],
+            _endpoint: endpoint,
+        }
+    }
+
+    /// Connect to a peer's QUIC endpoint and open the stream used to exchange messages.
+    /// Hostnames are resolved here, and the client binds to the address family of the result.
+    pub async fn connect(host: &PeerHost, port: u16) -> crate::Result<QuicPeerConnection> {
+        let socket_addr = host.resolve(port).await?;
+        let local_addr: IpAddr = if socket_addr.is_ipv6() {
+            Ipv6Addr::UNSPECIFIED.into()
+        } else {
+            Ipv4Addr::UNSPECIFIED.into()
+        };
+        let mut endpoint = Endpoint::client(SocketAddr::new(local_addr, 0))?;
+        endpoint.set_default_client_config(build_client_config());
+        let NewConnection { connection, .. } =
+            endpoint.connect(socket_addr, QUIC_SERVER_NAME)?.await?;
+        let (send_stream, recv_stream) = connection.open_bi().await?;
+        Ok(QuicPeerConnection::new(
+            connection,
+            send_stream,
+            recv_stream,
+            Some(endpoint),
+        ))
+    }
+
+    //
+    // returns the first complete frame in the read buffer, if any, and
+    // fails as soon as a frame is announced which is larger than we accept
+    //
+    fn take_frame_from_buffer(&mut self) -> crate::Result<Option<Vec<u8>>> {
+        if self.read_buffer.len() < 4 {
+            return Ok(None);
+        }
+        let frame_len = u32::from_be_bytes(self.read_buffer[0..4].try_into().unwrap()) as usize;
+        if frame_len > MAX_QUIC_FRAME_SIZE {
+            return Err(format!("QUIC frame of {} bytes is too large", frame_len).into());
+        }
+        if self.read_buffer.len() < 4 + frame_len {
+            return Ok(None);
+        }
+        let frame = self.read_buffer[4..4 + frame_len].to_vec();
+        self.read_buffer.drain(..4 + frame_len);
+        Ok(Some(frame))
+    }
+}
+
+#[async_trait]
+impl PeerConnection for QuicPeerConnection {
+    async fn send(&mut self, api_message: APIMessage) -> crate::Result<()> {
+        let serialized_api_message = api_message.serialize();
+        let mut vbytes: Vec<u8> = vec![];
+        vbytes.extend(&(serialized_api_message.len() as u32).to_be_bytes());
+        vbytes.extend(&serialized_api_message);
+        self.send_stream.write_all(&vbytes).await?;
+        Ok(())
+    }
+
+    //
+    // recv is raced against outgoing messages, so it only ever reads
+    // into the buffer and must not lose a partially-read frame when
+    // it is cancelled.
+    //
+    async fn recv(&mut self) -> Option<crate::Result<APIMessage>> {
+        loop {
+            match self.take_frame_from_buffer() {
+                Ok(Some(frame)) => return Some(Ok(APIMessage::deserialize(&frame))),
+                Ok(None) => {}
+                // the peer is closed by the caller on any error
+                Err(error) => return Some(Err(error)),
+            }
+            let mut chunk = [0u8; 4096];
+            match self.recv_stream.read(&mut chunk).await {
+                Ok(Some(len)) => self.read_buffer.extend(&chunk[..len]),
+                Ok(None) => return None,
+                Err(error) => return Some(Err(error.into())),
+            }
+        }
+    }
+
+    async fn close(&mut self) -> crate::Result<()> {
+        let _ = self.send_stream.finish().await;
+        self.connection.close(0u32.into(), b"");
+        Ok(())
+    }
+}
+
+/// Dummy certificate verifier. See the comment on QUIC_SERVER_NAME.
+struct SkipServerVerification;
+
+impl rustls::client::ServerCertVerifier for SkipServerVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &rustls::Certificate,
+        _intermediates: &[rustls::Certificate],
+        _server_name: &rustls::ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: std::time::SystemTime,
+    ) -> Result<rustls::client::ServerCertVerified, rustls::Error> {
+        Ok(rustls::client::ServerCertVerified::assertion())
+    }
+}
+
+fn build_client_config() -> ClientConfig {
+    let crypto = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(SkipServerVerification))
+        .with_no_client_auth();
+    ClientConfig::new(Arc::new(crypto))
+}
+
+fn build_server_config() -> crate::Result<quinn::ServerConfig> {
+    let cert = rcgen::generate_simple_self_signed(vec![QUIC_SERVER_NAME.into()])?;
+    let cert_chain = vec![rustls::Certificate(cert.serialize_der()?)];
+    let private_key = rustls::PrivateKey(cert.serialize_private_key_der());
+    Ok(quinn::ServerConfig::with_single_cert(
+        cert_chain,
+        private_key,
+    )?)
+}
+
+/// Listens for QUIC connections from other nodes. Each connection is handled like
+/// an inbound websocket connection once the peer has opened its message stream.
+pub async fn run_quic_server(
+    network_context: NetworkContext,
+    host: IpAddr,
+    port: u16,
+    wallet_lock: Arc<RwLock<Wallet>>,
+    mempool_lock: Arc<RwLock<Mempool>>,
+    blockchain_lock: Arc<RwLock<Blockchain>>,
+    broadcast_channel_sender: broadcast::Sender<SaitoMessage>,
+) -> crate::Result<()> {
+    let (_endpoint, mut incoming) =
+        Endpoint::server(build_server_config()?, SocketAddr::from((host, port)))?;
+    info!("Listening for QUIC on port {}", port);
+
+    while let Some(connecting) = incoming.next().await {
+        let network_context = network_context.clone();
+        let wallet_lock = wallet_lock.clone();
+        let mempool_lock = mempool_lock.clone();
+        let blockchain_lock = blockchain_lock.clone();
+        let broadcast_channel_sender = broadcast_channel_sender.clone();
+        tokio::spawn(async move {
+            let NewConnection {
+                connection,
+                mut bi_streams,
+                ..
+            } = match connecting.await {
+                Ok(new_connection) => new_connection,
+                Err(error) => {
+                    error!("Error accepting QUIC connection {:?}", error);
+                    return;
+                }
+            };
+            match bi_streams.next().await {
+                Some(Ok((send_stream, recv_stream))) => {
+                    let host = Some(PeerHost::from(connection.remote_address().ip()));
+                    handle_inbound_peer_connection(
+                        QuicPeerConnection::new(connection, send_stream, recv_stream, None),
+                        host,
+                        network_context,
+                        wallet_lock,
+                        mempool_lock,
+                        blockchain_lock,
+                        broadcast_channel_sender,
+                    )
+                    .await;
+                }
+                Some(Err(error)) => error!("Error accepting QUIC stream {:?}", error),
+                None => {}
+            }
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networking::api_message::MessageName;
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn quic_peer_connection_send_and_recv_test() {
+        let (_endpoint, mut incoming) = Endpoint::server(
+            build_server_config().unwrap(),
+            SocketAddr::from(([127, 0, 0, 1], 3010)),
+        )
+        .unwrap();
+
+        let server = tokio::spawn(async move {
+            let NewConnection {
+                connection,
+                mut bi_streams,
+                ..
+            } = incoming.next().await.unwrap().await.unwrap();
+            let (send_stream, recv_stream) = bi_streams.next().await.unwrap().unwrap();
+            let mut server_connection =
+                QuicPeerConnection::new(connection, send_stream, recv_stream, None);
+            let api_message = server_connection.recv().await.unwrap().unwrap();
+            server_connection.send(api_message).await.unwrap();
+            server_connection
+        });
+
+        let mut client_connection =
+            QuicPeerConnection::connect(&PeerHost::parse("127.0.0.1"), 3010)
+                .await
+                .unwrap();
+        let api_message = APIMessage::new(MessageName::HandshakeInit, 1, vec![42; 10_000]);
+        client_connection.send(api_message.clone()).await.unwrap();
+        let echoed_api_message = client_connection.recv().await.unwrap().unwrap();
+        assert_eq!(api_message, echoed_api_message);
+
+        let _server_connection = server.await.unwrap();
+        client_connection.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn quic_peer_connection_rejects_oversized_frame_test() {
+        let (_endpoint, mut incoming) = Endpoint::server(
+            build_server_config().unwrap(),
+            SocketAddr::from(([127, 0, 0, 1], 3010)),
+        )
+        .unwrap();
+
+        let server = tokio::spawn(async move {
+            let NewConnection {
+                connection,
+                mut bi_streams,
+                ..
+            } = incoming.next().await.unwrap().await.unwrap();
+            let (send_stream, recv_stream) = bi_streams.next().await.unwrap().unwrap();
+            let mut server_connection =
+                QuicPeerConnection::new(connection, send_stream, recv_stream, None);
+            server_connection.recv().await.unwrap()
+        });
+
+        let mut client_connection =
+            QuicPeerConnection::connect(&PeerHost::parse("127.0.0.1"), 3010)
+                .await
+                .unwrap();
+        // only the length is sent, the frame is rejected before its bytes are awaited
+        let frame_len = (MAX_QUIC_FRAME_SIZE + 1) as u32;
+        client_connection
+            .send_stream
+            .write_all(&frame_len.to_be_bytes())
+            .await
+            .unwrap();
+        assert!(server.await.unwrap().is_err());
+
+        client_connection.close().await.unwrap();
+    }
+}
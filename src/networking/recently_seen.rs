@@ -0,0 +1,117 @@
+use crate::crypto::SaitoHash;
+use ahash::AHashMap;
+use std::collections::VecDeque;
+
+/// How many block hashes the node remembers having requested or processed.
+pub const RECENT_BLOCK_HASHES_CAPACITY: usize = 1024;
+/// How many block hashes we remember a peer knowing about, i.e. which it announced to us,
+/// sent to us or was told about by us.
+pub const PEER_KNOWN_BLOCK_HASHES_CAPACITY: usize = 256;
+
+/// A bounded set of recently seen hashes which forgets the least recently seen hash when full.
+///
+/// Every insert is stamped with a sequence number and queued. Refreshing a hash queues it
+/// again under a new number, and queue entries whose number no longer matches the map are
+/// stale and skipped when evicting.
+#[derive(Debug, Clone)]
+pub struct RecentlySeen {
+    capacity: usize,
+    sequence: u64,
+    hashes: AHashMap<SaitoHash, u64>,
+    queue: VecDeque<(u64, SaitoHash)>,
+}
+
+impl RecentlySeen {
+    pub fn new(capacity: usize) -> Self {
+        RecentlySeen {
+            capacity,
+            sequence: 0,
+            hashes: AHashMap::new(),
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Marks the hash as seen, returning true if it was not already in the set.
+    pub fn insert(&mut self, hash: SaitoHash) -> bool {
+        self.sequence += 1;
+        let is_new = self.hashes.insert(hash, self.sequence).is_none();
+        self.queue.push_back((self.sequence, hash));
+
+        while self.hashes.len() > self.capacity {
+            if let Some((sequence, hash)) = self.queue.pop_front() {
+                if self.hashes.get(&hash) == Some(&sequence) {
+                    self.hashes.remove(&hash);
+                }
+            }
+        }
+        //
+        // refreshed hashes leave stale entries behind, drop them before the
+        // queue grows much beyond the set
+        //
+        if self.queue.len() > 2 * self.capacity.max(1) {
+            let hashes = &self.hashes;
+            self.queue
+                .retain(|(sequence, hash)| hashes.get(hash) == Some(sequence));
+        }
+        is_new
+    }
+
+    pub fn contains(&self, hash: &SaitoHash) -> bool {
+        self.hashes.contains_key(hash)
+    }
+
+    /// Forgets the hash, e.g. when fetching the block failed and it should be fetched again.
+    pub fn remove(&mut self, hash: &SaitoHash) -> bool {
+        self.hashes.remove(hash).is_some()
+    }
+
+    pub fn clear(&mut self) {
+        self.hashes.clear();
+        self.queue.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recently_seen_test() {
+        let mut recently_seen = RecentlySeen::new(2);
+        assert!(recently_seen.is_empty());
+
+        assert!(recently_seen.insert([1; 32]));
+        assert!(recently_seen.insert([2; 32]));
+        assert!(!recently_seen.insert([1; 32]));
+        assert_eq!(recently_seen.len(), 2);
+
+        // [2; 32] is now the least recently seen
+        assert!(recently_seen.insert([3; 32]));
+        assert!(recently_seen.contains(&[1; 32]));
+        assert!(!recently_seen.contains(&[2; 32]));
+        assert!(recently_seen.contains(&[3; 32]));
+
+        assert!(recently_seen.remove(&[1; 32]));
+        assert!(!recently_seen.remove(&[1; 32]));
+        assert!(recently_seen.insert([1; 32]));
+        assert_eq!(recently_seen.len(), 2);
+
+        // refreshing the same hash over and over does not grow the queue without bound
+        for _ in 0..100 {
+            recently_seen.insert([1; 32]);
+        }
+        assert!(recently_seen.queue.len() <= 4);
+        assert!(recently_seen.contains(&[3; 32]));
+
+        recently_seen.clear();
+        assert!(recently_seen.is_empty());
+    }
+}
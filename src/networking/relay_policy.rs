@@ -0,0 +1,177 @@
+use crate::blockchain::Blockchain;
+use crate::crypto::{SaitoHash, SaitoPublicKey};
+use crate::random::RandomSource;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+//
+// Routing payouts only reach us if a transaction we relayed ends up in a
+// block, so new transactions are relayed first to the peers which are most
+// likely to put them there. Peers are ranked by how many of the recent blocks
+// on the longest chain they produced, then by how quickly they answer our
+// requests, and finally by the fee volume they have relayed to us.
+//
+pub const RELAY_POLICY_BLOCK_WINDOW: u64 = 100;
+
+//
+// The first peer to hear of a transaction is most likely the one connected
+// to its origin, so transactions from our own wallet are relayed to each
+// peer after a random delay of up to this many milliseconds.
+//
+pub const TRANSACTION_DIFFUSION_MAX_DELAY: u64 = 2000;
+
+/// What we know about a peer when deciding the order in which new transactions are relayed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeerScore {
+    connection_id: SaitoHash,
+    blocks_produced: u64,
+    latency: Option<u64>,
+    fees_received: u64,
+}
+
+impl PeerScore {
+    pub fn new(
+        connection_id: SaitoHash,
+        blocks_produced: u64,
+        latency: Option<u64>,
+        fees_received: u64,
+    ) -> Self {
+        PeerScore {
+            connection_id,
+            blocks_produced,
+            latency,
+            fees_received,
+        }
+    }
+    pub fn get_connection_id(&self) -> SaitoHash {
+        self.connection_id
+    }
+    pub fn get_blocks_produced(&self) -> u64 {
+        self.blocks_produced
+    }
+    pub fn get_latency(&self) -> Option<u64> {
+        self.latency
+    }
+    pub fn get_fees_received(&self) -> u64 {
+        self.fees_received
+    }
+}
+
+/// Counts the blocks produced by each publickey over the last RELAY_POLICY_BLOCK_WINDOW
+/// blocks of the longest chain.
+pub fn count_recent_block_producers(blockchain: &Blockchain) -> HashMap<SaitoPublicKey, u64> {
+    let mut block_producers: HashMap<SaitoPublicKey, u64> = HashMap::new();
+    let mut block_hash = blockchain.get_latest_block_hash();
+    for _ in 0..RELAY_POLICY_BLOCK_WINDOW {
+        match blockchain.get_block_sync(&block_hash) {
+            Some(block) => {
+                *block_producers.entry(block.get_creator()).or_insert(0) += 1;
+                block_hash = block.get_previous_block_hash();
+            }
+            None => break,
+        }
+    }
+    block_producers
+}
+
+/// Orders peers from the most to the least preferred relay for a new transaction.
+pub fn order_peers_for_relay(peer_scores: &mut [PeerScore]) {
+    peer_scores.sort_by(compare_peer_scores);
+}
+
+/// Draws an independent relay delay in milliseconds, below TRANSACTION_DIFFUSION_MAX_DELAY,
+/// for each of peer_count peers.
+pub fn generate_diffusion_delays(
+    peer_count: usize,
+    random_source: &mut dyn RandomSource,
+) -> Vec<u64> {
+    (0..peer_count)
+        .map(|_| {
+            let random_number = random_source.next_random_number();
+            u64::from_be_bytes(random_number[0..8].try_into().unwrap())
+                % TRANSACTION_DIFFUSION_MAX_DELAY
+        })
+        .collect()
+}
+
+fn compare_peer_scores(a: &PeerScore, b: &PeerScore) -> Ordering {
+    b.blocks_produced
+        .cmp(&a.blocks_produced)
+        .then_with(|| match (a.latency, b.latency) {
+            (Some(a_latency), Some(b_latency)) => a_latency.cmp(&b_latency),
+            // peers we have timed are preferred over those we know nothing about
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        })
+        .then_with(|| b.fees_received.cmp(&a.fees_received))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random::HashChain;
+    use crate::test_utilities::test_manager::TestManager;
+    use crate::wallet::Wallet;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    #[test]
+    fn order_peers_for_relay_test() {
+        let mut peer_scores = vec![
+            PeerScore::new([1; 32], 0, None, 1000),
+            PeerScore::new([2; 32], 0, Some(50), 0),
+            PeerScore::new([3; 32], 2, Some(300), 0),
+            PeerScore::new([4; 32], 0, Some(50), 10),
+            PeerScore::new([5; 32], 5, None, 0),
+            PeerScore::new([6; 32], 2, Some(100), 0),
+        ];
+        order_peers_for_relay(&mut peer_scores);
+
+        let ordered_connection_ids: Vec<SaitoHash> = peer_scores
+            .iter()
+            .map(|peer_score| peer_score.get_connection_id())
+            .collect();
+        assert_eq!(
+            ordered_connection_ids,
+            vec![[5; 32], [6; 32], [3; 32], [4; 32], [2; 32], [1; 32]]
+        );
+    }
+
+    #[test]
+    fn generate_diffusion_delays_test() {
+        let diffusion_delays = generate_diffusion_delays(8, &mut HashChain::new([1; 32]));
+        assert_eq!(diffusion_delays.len(), 8);
+        assert!(diffusion_delays
+            .iter()
+            .all(|delay| *delay < TRANSACTION_DIFFUSION_MAX_DELAY));
+        // each peer gets its own delay
+        assert!(diffusion_delays
+            .iter()
+            .any(|delay| *delay != diffusion_delays[0]));
+        assert_eq!(
+            generate_diffusion_delays(8, &mut HashChain::new([1; 32])),
+            diffusion_delays
+        );
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn count_recent_block_producers_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+
+        let blockchain = blockchain_lock.read().await;
+        assert!(count_recent_block_producers(&blockchain).is_empty());
+        drop(blockchain);
+
+        test_manager.generate_blockchain(3, [0; 32]).await;
+
+        let publickey = wallet_lock.read().await.get_publickey();
+        let blockchain = blockchain_lock.read().await;
+        let block_producers = count_recent_block_producers(&blockchain);
+        assert_eq!(block_producers.len(), 1);
+        assert_eq!(block_producers.get(&publickey), Some(&3));
+    }
+}
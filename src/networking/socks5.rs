@@ -0,0 +1,220 @@
+use crate::configuration::Socks5ProxySettings;
+use crate::networking::peer_host::PeerHost;
+use std::net::IpAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+//
+// A minimal SOCKS5 client (RFC 1928, with the username and password
+// authentication of RFC 1929) for reaching peers through a proxy such as
+// Tor. Hostnames are always passed to the proxy unresolved, so that .onion
+// addresses work and our DNS lookups do not leak which peers we connect to.
+//
+const SOCKS5_VERSION: u8 = 0x05;
+const SOCKS5_NO_AUTHENTICATION: u8 = 0x00;
+const SOCKS5_USERNAME_PASSWORD: u8 = 0x02;
+const SOCKS5_NO_ACCEPTABLE_METHODS: u8 = 0xff;
+const SOCKS5_USERNAME_PASSWORD_VERSION: u8 = 0x01;
+const SOCKS5_CONNECT: u8 = 0x01;
+const SOCKS5_ADDRESS_IPV4: u8 = 0x01;
+const SOCKS5_ADDRESS_DOMAIN_NAME: u8 = 0x03;
+const SOCKS5_ADDRESS_IPV6: u8 = 0x04;
+const SOCKS5_SUCCEEDED: u8 = 0x00;
+
+/// Opens a TCP connection to host:port through the proxy.
+pub async fn connect_through_socks5_proxy(
+    socks5_proxy: &Socks5ProxySettings,
+    host: &PeerHost,
+    port: u16,
+) -> crate::Result<TcpStream> {
+    let mut stream =
+        TcpStream::connect(socks5_proxy.host.resolve(socks5_proxy.port).await?).await?;
+
+    let credentials = match (&socks5_proxy.username, &socks5_proxy.password) {
+        (Some(username), Some(password)) => Some((username, password)),
+        _ => None,
+    };
+    let method = if credentials.is_some() {
+        SOCKS5_USERNAME_PASSWORD
+    } else {
+        SOCKS5_NO_AUTHENTICATION
+    };
+    stream.write_all(&[SOCKS5_VERSION, 1, method]).await?;
+    let mut method_selection = [0; 2];
+    stream.read_exact(&mut method_selection).await?;
+    if method_selection[0] != SOCKS5_VERSION {
+        return Err("proxy is not a SOCKS5 proxy".into());
+    }
+    if method_selection[1] == SOCKS5_NO_ACCEPTABLE_METHODS || method_selection[1] != method {
+        return Err("proxy refused our authentication method".into());
+    }
+
+    if let Some((username, password)) = credentials {
+        stream
+            .write_all(&build_username_password_request(username, password)?)
+            .await?;
+        let mut status = [0; 2];
+        stream.read_exact(&mut status).await?;
+        if status[1] != SOCKS5_SUCCEEDED {
+            return Err("proxy refused our username and password".into());
+        }
+    }
+
+    stream
+        .write_all(&build_connect_request(host, port)?)
+        .await?;
+    let mut reply = [0; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[1] != SOCKS5_SUCCEEDED {
+        return Err(format!(
+            "proxy could not connect to {}, SOCKS5 reply {}",
+            host.format_url_string(port),
+            reply[1]
+        )
+        .into());
+    }
+    //
+    // skip the address the proxy bound for us, which we have no use for
+    //
+    let bound_address_len = match reply[3] {
+        SOCKS5_ADDRESS_IPV4 => 4,
+        SOCKS5_ADDRESS_IPV6 => 16,
+        SOCKS5_ADDRESS_DOMAIN_NAME => stream.read_u8().await? as usize,
+        address_type => return Err(format!("unknown SOCKS5 address type {}", address_type).into()),
+    };
+    let mut bound_address = vec![0; bound_address_len + 2];
+    stream.read_exact(&mut bound_address).await?;
+
+    Ok(stream)
+}
+
+fn build_username_password_request(username: &str, password: &str) -> crate::Result<Vec<u8>> {
+    if username.len() > 255 || password.len() > 255 {
+        return Err("SOCKS5 username and password must be at most 255 bytes".into());
+    }
+    let mut request = vec![SOCKS5_USERNAME_PASSWORD_VERSION, username.len() as u8];
+    request.extend(username.as_bytes());
+    request.push(password.len() as u8);
+    request.extend(password.as_bytes());
+    Ok(request)
+}
+
+fn build_connect_request(host: &PeerHost, port: u16) -> crate::Result<Vec<u8>> {
+    let mut request = vec![SOCKS5_VERSION, SOCKS5_CONNECT, 0x00];
+    match host {
+        PeerHost::Ip(IpAddr::V4(ip4)) => {
+            request.push(SOCKS5_ADDRESS_IPV4);
+            request.extend(ip4.octets());
+        }
+        PeerHost::Ip(IpAddr::V6(ip6)) => {
+            request.push(SOCKS5_ADDRESS_IPV6);
+            request.extend(ip6.octets());
+        }
+        PeerHost::Hostname(hostname) => {
+            if hostname.len() > 255 {
+                return Err(format!("hostname {} is too long for SOCKS5", hostname).into());
+            }
+            request.push(SOCKS5_ADDRESS_DOMAIN_NAME);
+            request.push(hostname.len() as u8);
+            request.extend(hostname.as_bytes());
+        }
+    }
+    request.extend(port.to_be_bytes());
+    Ok(request)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn build_connect_request_test() {
+        assert_eq!(
+            build_connect_request(&PeerHost::parse("10.0.0.1"), 12101).unwrap(),
+            vec![5, 1, 0, 1, 10, 0, 0, 1, 0x2f, 0x45]
+        );
+        let request = build_connect_request(&PeerHost::parse("::1"), 12101).unwrap();
+        assert_eq!(request[3], SOCKS5_ADDRESS_IPV6);
+        assert_eq!(request.len(), 4 + 16 + 2);
+
+        let onion_address = "saitoxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx.onion";
+        let request = build_connect_request(&PeerHost::parse(onion_address), 80).unwrap();
+        assert_eq!(request[3], SOCKS5_ADDRESS_DOMAIN_NAME);
+        assert_eq!(request[4] as usize, onion_address.len());
+        assert_eq!(
+            &request[5..5 + onion_address.len()],
+            onion_address.as_bytes()
+        );
+        assert_eq!(&request[5 + onion_address.len()..], &[0, 80]);
+
+        assert!(build_connect_request(&PeerHost::parse(&"a".repeat(256)), 80).is_err());
+    }
+
+    #[tokio::test]
+    async fn connect_through_socks5_proxy_test() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_port = listener.local_addr().unwrap().port();
+
+        //
+        // a proxy which checks the handshake and then echoes whatever it is sent
+        //
+        let proxy = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut greeting = [0; 3];
+            stream.read_exact(&mut greeting).await.unwrap();
+            assert_eq!(greeting, [5, 1, SOCKS5_USERNAME_PASSWORD]);
+            stream
+                .write_all(&[5, SOCKS5_USERNAME_PASSWORD])
+                .await
+                .unwrap();
+
+            let mut authentication = [0; 11];
+            stream.read_exact(&mut authentication).await.unwrap();
+            assert_eq!(&authentication[..], b"\x01\x05saito\x03tor");
+            stream.write_all(&[1, SOCKS5_SUCCEEDED]).await.unwrap();
+
+            let mut request = vec![0; 5 + "peer.onion".len() + 2];
+            stream.read_exact(&mut request).await.unwrap();
+            assert_eq!(
+                request,
+                build_connect_request(&PeerHost::parse("peer.onion"), 12101).unwrap()
+            );
+            stream
+                .write_all(&[
+                    5,
+                    SOCKS5_SUCCEEDED,
+                    0,
+                    SOCKS5_ADDRESS_IPV4,
+                    0,
+                    0,
+                    0,
+                    0,
+                    0,
+                    0,
+                ])
+                .await
+                .unwrap();
+
+            let mut message = [0; 5];
+            stream.read_exact(&mut message).await.unwrap();
+            stream.write_all(&message).await.unwrap();
+        });
+
+        let socks5_proxy = Socks5ProxySettings {
+            host: PeerHost::parse("127.0.0.1"),
+            port: proxy_port,
+            username: Some(String::from("saito")),
+            password: Some(String::from("tor")),
+        };
+        let mut stream =
+            connect_through_socks5_proxy(&socks5_proxy, &PeerHost::parse("peer.onion"), 12101)
+                .await
+                .unwrap();
+        stream.write_all(b"hello").await.unwrap();
+        let mut message = [0; 5];
+        stream.read_exact(&mut message).await.unwrap();
+        assert_eq!(&message, b"hello");
+        proxy.await.unwrap();
+    }
+}
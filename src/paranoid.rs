@@ -0,0 +1,241 @@
+use crate::amount::Amount;
+use crate::block::Block;
+use crate::blockchain::{Blockchain, UtxoSet};
+use crate::blockring::RING_BUFFER_LENGTH;
+use crate::crypto::{SaitoHash, SaitoUTXOSetKey};
+use ahash::AHashMap;
+use log::error;
+
+//
+// In paranoid mode the blockchain checks its derived state after every block
+// it winds or unwinds, and stops the node at the first divergence rather
+// than letting a bad utxoset or blockring spread into later blocks. The
+// checks copy the whole utxoset for every block, so the mode is meant for
+// development and for replaying a chain which is suspected to be broken.
+//
+
+/// The utxoset values the block should leave behind when added to (or removed from) the
+/// longest chain, derived from its transactions alone: inputs are spent at the block id and
+/// outputs are unspent when winding, and inputs are unspent and outputs unknown when
+/// unwinding. Slips without an amount are not in the utxoset.
+pub fn derive_utxoset_changes(
+    block: &Block,
+    longest_chain: bool,
+) -> AHashMap<SaitoUTXOSetKey, u64> {
+    let (input_slip_value, output_slip_value) = if longest_chain {
+        (block.get_id(), 1)
+    } else {
+        (1, 0)
+    };
+    let mut changes = AHashMap::new();
+    for transaction in block.get_transactions() {
+        for input in transaction.get_inputs() {
+            if input.get_amount() > Amount::ZERO {
+                changes.insert(input.get_utxoset_key(), input_slip_value);
+            }
+        }
+        for output in transaction.get_outputs() {
+            if output.get_amount() > Amount::ZERO {
+                changes.insert(output.get_utxoset_key(), output_slip_value);
+            }
+        }
+    }
+    changes
+}
+
+/// Checks that the utxoset changed from before to after exactly as the block says it should,
+/// i.e. that every slip of the block has its expected value and no other entry changed.
+pub fn check_utxoset_changes(
+    utxoset_before: &UtxoSet,
+    utxoset_after: &UtxoSet,
+    block: &Block,
+    longest_chain: bool,
+) -> Result<(), String> {
+    let expected_changes = derive_utxoset_changes(block, longest_chain);
+    for (utxoset_key, expected_value) in expected_changes.iter() {
+        let value = utxoset_after.get(utxoset_key);
+        if value != Some(expected_value) {
+            return Err(format!(
+                "slip {} of block {} is {:?} in the utxoset, expected {}",
+                hex::encode(utxoset_key),
+                hex::encode(block.get_hash()),
+                value,
+                expected_value
+            ));
+        }
+    }
+    for (utxoset_key, value) in utxoset_after.iter() {
+        if utxoset_before.get(utxoset_key) != Some(value)
+            && !expected_changes.contains_key(utxoset_key)
+        {
+            return Err(format!(
+                "slip {} changed to {} in the utxoset but is not in block {}",
+                hex::encode(utxoset_key),
+                value,
+                hex::encode(block.get_hash())
+            ));
+        }
+    }
+    for utxoset_key in utxoset_before.keys() {
+        if !utxoset_after.contains_key(utxoset_key) {
+            return Err(format!(
+                "slip {} was removed from the utxoset by block {}",
+                hex::encode(utxoset_key),
+                hex::encode(block.get_hash())
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Checks that the blockring has the block at its id on the longest chain after winding it,
+/// and not after unwinding it, and that the longest chain it holds is a chain: every block
+/// sits at its own id and points to the block before it.
+pub fn check_longest_chain(
+    blockchain: &Blockchain,
+    block_id: u64,
+    block_hash: SaitoHash,
+    longest_chain: bool,
+) -> Result<(), String> {
+    let longest_chain_hash = blockchain
+        .blockring
+        .get_longest_chain_block_hash_by_block_id(block_id);
+    if longest_chain && longest_chain_hash != block_hash {
+        return Err(format!(
+            "block {} was wound but the longest chain has {} at id {}",
+            hex::encode(block_hash),
+            hex::encode(longest_chain_hash),
+            block_id
+        ));
+    }
+    if !longest_chain && longest_chain_hash == block_hash {
+        return Err(format!(
+            "block {} was unwound but is still on the longest chain at id {}",
+            hex::encode(block_hash),
+            block_id
+        ));
+    }
+
+    //
+    // walk back from the tip for as long as the blockring remembers the
+    // longest chain and we still hold its blocks
+    //
+    let latest_block_id = blockchain.blockring.get_latest_block_id();
+    let earliest_block_id = latest_block_id
+        .saturating_sub(RING_BUFFER_LENGTH - 1)
+        .max(1);
+    let mut block_id = latest_block_id;
+    while block_id >= earliest_block_id && block_id > 0 {
+        let hash = blockchain
+            .blockring
+            .get_longest_chain_block_hash_by_block_id(block_id);
+        let block = match blockchain.blocks.get(&hash) {
+            Some(block) => block,
+            None => break,
+        };
+        if block.get_id() != block_id {
+            return Err(format!(
+                "block {} is on the longest chain at id {} but has id {}",
+                hex::encode(hash),
+                block_id,
+                block.get_id()
+            ));
+        }
+        if block_id > earliest_block_id {
+            let previous_hash = blockchain
+                .blockring
+                .get_longest_chain_block_hash_by_block_id(block_id - 1);
+            if block.get_previous_block_hash() != previous_hash {
+                return Err(format!(
+                    "block {} at id {} does not follow {} on the longest chain",
+                    hex::encode(hash),
+                    block_id,
+                    hex::encode(previous_hash)
+                ));
+            }
+        }
+        block_id -= 1;
+    }
+    Ok(())
+}
+
+/// Stops the node if a paranoid check failed.
+pub fn assert_paranoid_check(result: Result<(), String>) {
+    if let Err(err) = result {
+        error!("ERROR 330101: paranoid check failed: {}", err);
+        panic!("paranoid check failed: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utilities::test_manager::TestManager;
+    use crate::wallet::Wallet;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn paranoid_blockchain_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let mut blockchain = Blockchain::new(wallet_lock.clone());
+        blockchain.set_paranoid(true);
+        let blockchain_lock = Arc::new(RwLock::new(blockchain));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+
+        // winding every block passes the checks
+        test_manager.generate_blockchain(5, [0; 32]).await;
+
+        let blockchain = blockchain_lock.read().await;
+        let latest_block_id = blockchain.get_latest_block_id();
+        let latest_block = blockchain.get_latest_block().unwrap();
+        assert_eq!(latest_block_id, 5);
+        assert!(
+            check_longest_chain(&blockchain, latest_block_id, latest_block.get_hash(), true)
+                .is_ok()
+        );
+        assert!(
+            check_longest_chain(&blockchain, latest_block_id, latest_block.get_hash(), false)
+                .is_err()
+        );
+
+        // the changes of unwinding a block with slips are those of unwinding it only
+        let block = (1..=latest_block_id)
+            .rev()
+            .map(|block_id| {
+                let block_hash = blockchain
+                    .blockring
+                    .get_longest_chain_block_hash_by_block_id(block_id);
+                blockchain.get_block_sync(&block_hash).unwrap()
+            })
+            .find(|block| !derive_utxoset_changes(block, false).is_empty())
+            .unwrap();
+        let mut utxoset = blockchain.utxoset.clone();
+        let utxoset_wound = utxoset.clone();
+        block.on_chain_reorganization(&mut utxoset, false);
+        assert!(check_utxoset_changes(&utxoset_wound, &utxoset, block, false).is_ok());
+        assert!(check_utxoset_changes(&utxoset_wound, &utxoset, block, true).is_err());
+
+        // a slip the block does not have must not change
+        let utxoset_key = [7; 75];
+        let mut utxoset_wound_with_slip = utxoset_wound.clone();
+        utxoset_wound_with_slip.insert(utxoset_key, 1);
+        let mut tampered_utxoset = utxoset.clone();
+        tampered_utxoset.insert(utxoset_key, 2);
+        assert!(
+            check_utxoset_changes(&utxoset_wound_with_slip, &tampered_utxoset, block, false)
+                .is_err()
+        );
+        tampered_utxoset.remove(&utxoset_key);
+        assert!(
+            check_utxoset_changes(&utxoset_wound_with_slip, &tampered_utxoset, block, false)
+                .is_err()
+        );
+        tampered_utxoset.insert(utxoset_key, 1);
+        assert!(
+            check_utxoset_changes(&utxoset_wound_with_slip, &tampered_utxoset, block, false)
+                .is_ok()
+        );
+    }
+}
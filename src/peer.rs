@@ -1,50 +1,75 @@
 /// A Peer. i.e. another node in the network.
 use crate::block::{Block, BlockType};
+use crate::block_store::BlockStore;
 use crate::blockchain::{Blockchain, GENESIS_PERIOD};
 use crate::consensus::SaitoMessage;
 use crate::crypto::{hash, verify, SaitoHash, SaitoPublicKey};
 use crate::hop::Hop;
 use crate::mempool::Mempool;
-use crate::network::{
-    Network, CHALLENGE_EXPIRATION_TIME, CHALLENGE_SIZE, INBOUND_PEER_CONNECTIONS_GLOBAL,
-    OUTBOUND_PEER_CONNECTIONS_GLOBAL, PEERS_DB_GLOBAL, PEERS_REQUEST_RESPONSES_GLOBAL,
-    PEERS_REQUEST_WAKERS_GLOBAL,
+use crate::message_log::{is_recorded, MessageRecord};
+use crate::network::{Network, NetworkContext, CHALLENGE_EXPIRATION_TIME, CHALLENGE_SIZE};
+use crate::networking::message_types::block_range_chunk::{
+    BlockRangeChunk, BLOCK_RANGE_CHUNK_SIZE,
 };
-use crate::networking::message_types::handshake_challenge::HandshakeChallenge;
+use crate::networking::message_types::handshake_challenge::{HandshakeChallenge, HandshakeNonce};
 use crate::networking::message_types::request_block_message::RequestBlockMessage;
+use crate::networking::message_types::request_block_range_message::RequestBlockRangeMessage;
 use crate::networking::message_types::request_blockchain_message::RequestBlockchainMessage;
 use crate::networking::message_types::send_block_head_message::SendBlockHeadMessage;
 use crate::networking::message_types::send_blockchain_message::{
     SendBlockchainBlockData, SendBlockchainMessage, SyncType,
 };
-use crate::time::create_timestamp;
+use crate::networking::node_metadata::NodeMetadata;
+use crate::networking::peer_connection::{PeerConnection, PeerTransport};
+use crate::networking::peer_host::{ip_address_from_bytes, PeerHost};
+use crate::networking::peer_role::{get_role_preference, PeerRole};
+use crate::networking::peer_services::PeerServices;
+use crate::networking::peer_stats::PeerStats;
+use crate::networking::recently_seen::{RecentlySeen, PEER_KNOWN_BLOCK_HASHES_CAPACITY};
+use crate::supervisor::catch_panic;
+use crate::sync::TipComparison;
+use crate::time::{create_timestamp, estimate_clock_offset};
 use crate::transaction::Transaction;
 use crate::wallet::Wallet;
 use async_recursion::async_recursion;
-use futures::stream::SplitSink;
+use serde::Serialize;
 use std::collections::HashMap;
 use std::convert::TryInto;
 use std::error::Error;
+use std::net::IpAddr;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll, Waker};
 use tokio::sync::{broadcast, mpsc, RwLock};
-use tokio_stream::wrappers::UnboundedReceiverStream;
 use tracing::{error, info};
 use uuid::Uuid;
-use warp::ws::{Message, WebSocket};
 
-use crate::networking::api_message::APIMessage;
-use futures::{Future, FutureExt, SinkExt, StreamExt};
-use tokio::net::TcpStream;
+use crate::networking::api_message::{APIMessage, MessageName};
+use futures::Future;
 use tokio::sync::broadcast::Sender;
-use tokio_tungstenite::{tungstenite, MaybeTlsStream, WebSocketStream};
 
 pub type PeersDB = HashMap<SaitoHash, SaitoPeer>;
 pub type RequestResponses = HashMap<(SaitoHash, u32), APIMessage>;
 pub type RequestWakers = HashMap<(SaitoHash, u32), Waker>;
-pub type OutboundPeersDB = HashMap<SaitoHash, OutboundPeer>;
-pub type InboundPeersDB = HashMap<SaitoHash, InboundPeer>;
+pub type PeerConnectionsDB = HashMap<SaitoHash, mpsc::UnboundedSender<APIMessage>>;
+pub type PeersBandwidthDB = HashMap<SaitoHash, PeerBandwidth>;
+
+/// When an archive provider's chain is at least this many blocks ahead of ours, we fetch the
+/// blocks in ranges with REQRANGE rather than one by one with REQBLOCK.
+pub const BLOCK_RANGE_SYNC_THRESHOLD: u64 = 2;
+
+/// The last tip a peer announced, and how it compared with ours when it was announced.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PeerTip {
+    pub block_hash: SaitoHash,
+    pub block_id: u64,
+    pub chain_work: u64,
+    pub comparison: TipComparison,
+}
+
+/// The most commands of a peer which are queued for its command task. A peer which sends
+/// commands faster than we handle them is disconnected.
+pub const PEER_COMMAND_QUEUE_SIZE: usize = 1000;
 
 /// Flags for Peer state.
 pub struct PeerFlags {
@@ -58,51 +83,200 @@ pub struct SaitoPeer {
     peer_flags: PeerFlags,
     connection_id: SaitoHash,
     publickey: Option<SaitoPublicKey>,
-    host: Option<[u8; 4]>,
+    host: Option<PeerHost>,
     port: Option<u16>,
+    transport: PeerTransport,
+    roles: Vec<PeerRole>,
+    services: PeerServices,
+    latency: Option<u64>,
+    clock_offset: Option<i64>,
     request_count: u32,
+    known_block_hashes: RecentlySeen,
+    tip: Option<PeerTip>,
     wallet_lock: Arc<RwLock<Wallet>>,
     mempool_lock: Arc<RwLock<Mempool>>,
     blockchain_lock: Arc<RwLock<Blockchain>>,
+    block_store: BlockStore,
     broadcast_channel_sender: broadcast::Sender<SaitoMessage>,
+    network_context: NetworkContext,
+}
+
+/// Traffic exchanged with a peer over its connection. Routing nodes use this to
+/// decide which peers are worth staying connected to: bytes are counted as
+/// serialized APIMessages, and fees are the total fees of the transactions which
+/// were relayed in each direction.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct PeerBandwidth {
+    bytes_sent: u64,
+    bytes_received: u64,
+    messages_sent: u64,
+    messages_received: u64,
+    transactions_sent: u64,
+    transactions_received: u64,
+    fees_sent: u64,
+    fees_received: u64,
 }
 
-/// An inbound Peer. This holds a Stream(Sender/Sink) for a peer which has initialized a connection to us via /wsopen.
-pub struct InboundPeer {
-    pub sender: mpsc::UnboundedSender<std::result::Result<Message, warp::Error>>,
+impl PeerBandwidth {
+    pub fn get_bytes_sent(&self) -> u64 {
+        self.bytes_sent
+    }
+    pub fn get_bytes_received(&self) -> u64 {
+        self.bytes_received
+    }
+    pub fn get_messages_sent(&self) -> u64 {
+        self.messages_sent
+    }
+    pub fn get_messages_received(&self) -> u64 {
+        self.messages_received
+    }
+    pub fn get_transactions_sent(&self) -> u64 {
+        self.transactions_sent
+    }
+    pub fn get_transactions_received(&self) -> u64 {
+        self.transactions_received
+    }
+    pub fn get_fees_sent(&self) -> u64 {
+        self.fees_sent
+    }
+    pub fn get_fees_received(&self) -> u64 {
+        self.fees_received
+    }
+    pub fn add_message_sent(&mut self, api_message: &APIMessage) {
+        self.messages_sent += 1;
+        self.bytes_sent += api_message.get_serialized_size() as u64;
+    }
+    pub fn add_message_received(&mut self, api_message: &APIMessage) {
+        self.messages_received += 1;
+        self.bytes_received += api_message.get_serialized_size() as u64;
+    }
+    pub fn add_transaction_sent(&mut self, tx: &Transaction) {
+        self.transactions_sent += 1;
+        self.fees_sent = self.fees_sent.saturating_add(tx.get_total_fees());
+    }
+    pub fn add_transaction_received(&mut self, tx: &Transaction) {
+        self.transactions_received += 1;
+        self.fees_received = self.fees_received.saturating_add(tx.get_total_fees());
+    }
 }
 
-/// An outbound Peer. This holds a Stream(Sender/Sink) for a peer which we have connected to via /wsopen.
-/// TODO: Unify InboundPeer and OutboundPeer into a single trait and perhaps integrate it into SaitoPeer.
-///       This has proven to be a very difficult task because of ownership loops that make it very tricky
-///       to interact with a peer by reading messages from a socket which is inside the peer. This is why
-///       we have a separate structure to hold the sockets vs the rest of the peer data. It may be easy
-///       to at least unify Inbound and Outbound into a single Trait though and merge InboundPeersDB and
-///       OutboundPeersDB
-pub struct OutboundPeer {
-    pub write_sink:
-        SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, tungstenite::protocol::Message>,
+/// Updates the bandwidth accounting of a connection, creating it on first use.
+pub fn update_peer_bandwidth<F: FnOnce(&mut PeerBandwidth)>(
+    network_context: &NetworkContext,
+    connection_id: &SaitoHash,
+    update: F,
+) {
+    let mut peers_bandwidth = network_context.peers_bandwidth.write().unwrap();
+    update(peers_bandwidth.entry(*connection_id).or_default());
+}
+
+/// Updates the stats of a peer address, if the peer has one.
+pub fn update_peer_stats<F: FnOnce(&mut PeerStats)>(
+    network_context: &NetworkContext,
+    address: Option<&str>,
+    update: F,
+) {
+    if let Some(address) = address {
+        network_context
+            .peer_stats
+            .write()
+            .unwrap()
+            .update(address, update);
+    }
+}
+
+/// Appends a message from a peer to the message log, if we keep one and the message is one we
+/// record. command is the name of the message, or the name of our request if the message is a
+/// response to it.
+pub fn record_message(
+    network_context: &NetworkContext,
+    connection_id: &SaitoHash,
+    command: MessageName,
+    api_message: &APIMessage,
+) {
+    let mut message_log = network_context.message_log.write().unwrap();
+    if let Some(message_log) = message_log.as_mut() {
+        if !is_recorded(command, api_message) {
+            return;
+        }
+        let message_record = MessageRecord::new(
+            create_timestamp(),
+            *connection_id,
+            command,
+            api_message.clone(),
+        );
+        if let Err(err) = message_log.record(&message_record) {
+            error!("ERROR 410382: cannot write to the message log: {}", err);
+        }
+    }
+}
+
+/// Returns a copy of the bandwidth accounting of a connection.
+pub fn get_peer_bandwidth(
+    network_context: &NetworkContext,
+    connection_id: &SaitoHash,
+) -> PeerBandwidth {
+    let peers_bandwidth = network_context.peers_bandwidth.read().unwrap();
+    peers_bandwidth
+        .get(connection_id)
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Checks a peer against the allow and deny lists of our peer filter.
+pub fn is_peer_allowed(
+    network_context: &NetworkContext,
+    publickey: Option<SaitoPublicKey>,
+    host: Option<IpAddr>,
+) -> bool {
+    let peer_filter = network_context.peer_filter.read().unwrap();
+    peer_filter.is_peer_allowed(publickey, host)
+}
+
+/// The services we serve our peers.
+pub fn get_local_services(network_context: &NetworkContext) -> PeerServices {
+    *network_context.local_peer_services.read().unwrap()
+}
+
+/// Drops the connections of peers which are no longer allowed, e.g. after the peer filter has
+/// been changed at runtime.
+pub async fn disconnect_peers_not_allowed(network_context: &NetworkContext) {
+    let mut peer_db = network_context.peers_db.write().await;
+    for peer in peer_db.values_mut() {
+        if peer.get_is_connected_or_connecting()
+            && peer.get_has_completed_handshake()
+            && !is_peer_allowed(network_context, peer.get_publickey(), peer.get_ip_address())
+        {
+            info!(
+                "disconnecting peer {} which is no longer allowed",
+                hex::encode(peer.get_connection_id())
+            );
+            peer.set_is_connected_or_connecting(false).await;
+        }
+    }
 }
 
 pub struct PeerRequest {
+    network_context: NetworkContext,
     connection_id: SaitoHash,
     request_id: u32,
     // This is here for debugging
-    api_message_command: String,
+    api_message_command: MessageName,
 }
 /// A future which wraps an APIMessage REQUEST->RESPONSE into a Future(e.g. REQBLOCK->RESULT__).
 /// This enables a much cleaner interface for inter-node message relays by allowing a response to
 /// be returned directly from peer.send_command() and to turn an ERROR___ response into an Err()
 /// Result.
 impl PeerRequest {
-    pub async fn new(command: &str, message: Vec<u8>, peer: &mut SaitoPeer) -> Self {
+    pub async fn new(command: MessageName, message: Vec<u8>, peer: &mut SaitoPeer) -> Self {
         peer.request_count += 1;
         let api_message = APIMessage::new(command, peer.request_count - 1, message);
-        send_message_to_socket(api_message, &peer.connection_id).await;
+        send_message_to_socket(&peer.network_context, api_message, &peer.connection_id).await;
         PeerRequest {
+            network_context: peer.network_context.clone(),
             connection_id: peer.connection_id,
             request_id: peer.request_count - 1,
-            api_message_command: String::from(command),
+            api_message_command: command,
         }
     }
 }
@@ -110,8 +284,7 @@ impl PeerRequest {
 impl Future for PeerRequest {
     type Output = Result<APIMessage, Box<dyn Error>>;
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        let request_responses_lock = PEERS_REQUEST_RESPONSES_GLOBAL.clone();
-        let mut request_responses = request_responses_lock.write().unwrap();
+        let mut request_responses = self.network_context.request_responses.write().unwrap();
         match request_responses.remove(&(self.connection_id, self.request_id)) {
             Some(response) => {
                 // Return from the Future with an important message!
@@ -119,55 +292,148 @@ impl Future for PeerRequest {
                 Poll::Ready(Ok(response))
             }
             None => {
-                let request_wakers_lock = PEERS_REQUEST_WAKERS_GLOBAL.clone();
-                let mut request_wakers = request_wakers_lock.write().unwrap();
+                let mut request_wakers = self.network_context.request_wakers.write().unwrap();
                 request_wakers.insert((self.connection_id, self.request_id), cx.waker().clone());
                 Poll::Pending
             }
         }
     }
 }
-/// Sends an APIMessage to a socket connection. Since Outbound and Inbound peers Streams(Sinks) are
-/// not unified into a single Trait yet, we must check both dbs to find out which sort of sink this
-/// peer is using and send the message through the appopriate stream.
-pub async fn send_message_to_socket(api_message: APIMessage, connection_id: &SaitoHash) {
-    let outbound_peer_connection_db_global = OUTBOUND_PEER_CONNECTIONS_GLOBAL.clone();
-    let mut outbound_peer_connection_db = outbound_peer_connection_db_global.write().await;
-    let inbound_peer_connection_db_global = INBOUND_PEER_CONNECTIONS_GLOBAL.clone();
-    let mut inbound_peer_connection_db = inbound_peer_connection_db_global.write().await;
-
-    let outbound_peer_connection = outbound_peer_connection_db.get_mut(connection_id);
-    let inbound_peer_connection = inbound_peer_connection_db.get_mut(connection_id);
-
-    if inbound_peer_connection.is_some() && outbound_peer_connection.is_some() {
-        panic!("Peer has both inbound and outbound connections, this should never happen");
-    }
-    if inbound_peer_connection.is_none() && outbound_peer_connection.is_none() {
-        panic!("Peer has neither outbound nor inbound connection");
-    }
-    if outbound_peer_connection.is_some() {
-        outbound_peer_connection
-            .unwrap()
-            .write_sink
-            .send(api_message.serialize().into())
-            .await
-            .expect("unable to send to outbound_peer_connection... It may be that the socket\n
-            was closed but the outbound peer was not cleaned up. If this occurs, it should be investigated.");
+/// Sends an APIMessage to a socket connection. The message is queued for the task spawned by
+/// run_peer_connection, which owns the PeerConnection and writes it out.
+pub async fn send_message_to_socket(
+    network_context: &NetworkContext,
+    api_message: APIMessage,
+    connection_id: &SaitoHash,
+) {
+    let peer_connection_db = network_context.peer_connections.read().await;
+    match peer_connection_db.get(connection_id) {
+        Some(sender) => {
+            if sender.send(api_message).is_err() {
+                error!("unable to send to peer connection... It may be that the socket\n
+                was closed but the peer was not cleaned up. If this occurs, it should be investigated.");
+            }
+        }
+        None => panic!("Peer has no connection"),
     }
-    if inbound_peer_connection.is_some() {
-        inbound_peer_connection
-            .unwrap()
-            .sender
-            .send(Ok(Message::binary(api_message.serialize())))
-            .expect("unable to send to outbound_peer_connection... It may be that the socket\n
-            was closed but the inbount peer was not cleaned up. If this occurs, it should be investigated.");
+}
+
+/// Takes ownership of a PeerConnection and spawns a task which writes the messages queued for
+/// this connection_id and pipes the messages it receives to handle_peer_message(). When the
+/// connection is closed the peer is marked as disconnected, and peers which did not come from
+/// our peer list are forgotten.
+pub async fn run_peer_connection<C: PeerConnection + 'static>(
+    network_context: NetworkContext,
+    mut peer_connection: C,
+    connection_id: SaitoHash,
+) {
+    let (peer_connection_sender, mut peer_connection_receiver) = mpsc::unbounded_channel();
+    {
+        network_context
+            .peer_connections
+            .write()
+            .await
+            .insert(connection_id, peer_connection_sender);
     }
+
+    tokio::spawn(async move {
+        // a panic in the handler of a message ends this connection only, and the peer is
+        // still marked as disconnected below, so that we reconnect to it
+        let context = format!("connection to peer {}", hex::encode(connection_id));
+        let connection_network_context = network_context.clone();
+        catch_panic(&context, async move {
+            let network_context = connection_network_context;
+            //
+            // the commands of the peer are handled in order on a task of their own, as
+            // their handlers wait for the lock on the peers db, which is held while we
+            // wait for the responses to our requests, e.g. in the handshake. Responses
+            // are handled here, so that they are not queued behind those commands.
+            //
+            let (command_sender, mut command_receiver) = mpsc::channel(PEER_COMMAND_QUEUE_SIZE);
+            let command_network_context = network_context.clone();
+            tokio::spawn(async move {
+                while let Some(api_message) = command_receiver.recv().await {
+                    SaitoPeer::handle_peer_message(
+                        &command_network_context,
+                        api_message,
+                        connection_id,
+                    )
+                    .await;
+                }
+            });
+            loop {
+                tokio::select! {
+                    outgoing = peer_connection_receiver.recv() => {
+                        match outgoing {
+                            Some(api_message) => {
+                                update_peer_bandwidth(&network_context, &connection_id, |bandwidth| {
+                                    bandwidth.add_message_sent(&api_message)
+                                });
+                                if let Err(error) = peer_connection.send(api_message).await {
+                                    error!("Error writing to peer socket {:?}", error);
+                                    break;
+                                }
+                            }
+                            // the peer has been disconnected and its sender dropped
+                            None => break,
+                        }
+                    }
+                    incoming = peer_connection.recv() => {
+                        match incoming {
+                            Some(Ok(api_message)) => {
+                                update_peer_bandwidth(&network_context, &connection_id, |bandwidth| {
+                                    bandwidth.add_message_received(&api_message)
+                                });
+                                let is_response = matches!(
+                                    api_message.get_message_name(),
+                                    Some(MessageName::Result) | Some(MessageName::Error)
+                                );
+                                if is_response {
+                                    SaitoPeer::handle_peer_message(
+                                        &network_context,
+                                        api_message,
+                                        connection_id,
+                                    )
+                                    .await;
+                                } else if let Err(error) = command_sender.try_send(api_message) {
+                                    // the peer sends commands faster than we handle them, or
+                                    // the handler of a command panicked
+                                    error!("Error queueing peer command {}", error);
+                                    break;
+                                }
+                            }
+                            Some(Err(error)) => {
+                                error!("Error reading from peer socket {:?}", error);
+                                break;
+                            }
+                            None => break,
+                        }
+                    }
+                }
+            }
+            let _ = peer_connection.close().await;
+        })
+        .await;
+
+        let mut peer_db = network_context.peers_db.write().await;
+        if let Some(peer) = peer_db.get_mut(&connection_id) {
+            peer.set_is_connected_or_connecting(false).await;
+            if !peer.get_is_from_peer_list() {
+                peer_db.remove(&connection_id);
+                network_context
+                    .peers_bandwidth
+                    .write()
+                    .unwrap()
+                    .remove(&connection_id);
+            }
+        }
+    });
 }
 
 impl SaitoPeer {
     pub fn new(
         connection_id: SaitoHash,
-        host: Option<[u8; 4]>,
+        host: Option<PeerHost>,
         port: Option<u16>,
         is_connected_or_connecting: bool,
         has_completed_handshake: bool,
@@ -175,7 +441,9 @@ impl SaitoPeer {
         wallet_lock: Arc<RwLock<Wallet>>,
         mempool_lock: Arc<RwLock<Mempool>>,
         blockchain_lock: Arc<RwLock<Blockchain>>,
+        block_store: BlockStore,
         broadcast_channel_sender: Sender<SaitoMessage>,
+        network_context: NetworkContext,
     ) -> SaitoPeer {
         SaitoPeer {
             peer_flags: PeerFlags {
@@ -186,18 +454,34 @@ impl SaitoPeer {
             connection_id,
             host,
             port,
+            transport: PeerTransport::default(),
+            roles: vec![],
+            services: PeerServices::unadvertised(),
+            latency: None,
+            clock_offset: None,
             publickey: None,
             request_count: 0,
+            known_block_hashes: RecentlySeen::new(PEER_KNOWN_BLOCK_HASHES_CAPACITY),
+            tip: None,
             wallet_lock,
             mempool_lock,
             blockchain_lock,
+            block_store,
             broadcast_channel_sender,
+            network_context,
         }
     }
     pub fn get_is_from_peer_list(&self) -> bool {
         self.peer_flags.is_from_peer_list
     }
+    /// Peers from the peer list are reconnected whenever their connection drops.
+    pub fn set_is_from_peer_list(&mut self, is_from_peer_list: bool) {
+        self.peer_flags.is_from_peer_list = is_from_peer_list;
+    }
     pub fn set_has_completed_handshake(&mut self, has_completed_handshake: bool) {
+        if has_completed_handshake && !self.peer_flags.has_completed_handshake {
+            self.update_stats(|peer_stats| peer_stats.on_connected(create_timestamp()));
+        }
         self.peer_flags.has_completed_handshake = has_completed_handshake;
     }
     pub fn get_has_completed_handshake(&self) -> bool {
@@ -209,6 +493,17 @@ impl SaitoPeer {
     pub fn get_publickey(&self) -> Option<SaitoPublicKey> {
         self.publickey
     }
+    /// Records that the peer knows about the block, returning false if we already knew that.
+    pub fn mark_block_hash_known(&mut self, block_hash: SaitoHash) -> bool {
+        self.known_block_hashes.insert(block_hash)
+    }
+    pub fn knows_block_hash(&self, block_hash: &SaitoHash) -> bool {
+        self.known_block_hashes.contains(block_hash)
+    }
+    /// The last tip the peer announced with its chain work, if any.
+    pub fn get_tip(&self) -> Option<PeerTip> {
+        self.tip
+    }
     pub fn get_broadcast_channel_sender(&self) -> &broadcast::Sender<SaitoMessage> {
         &self.broadcast_channel_sender
     }
@@ -218,15 +513,19 @@ impl SaitoPeer {
     pub async fn set_is_connected_or_connecting(&mut self, is_connected_or_connecting: bool) {
         // we need to clean out the connections from the connection DBs if we disconnected
         if !is_connected_or_connecting {
-            let outbound_peer_connection_db_global = OUTBOUND_PEER_CONNECTIONS_GLOBAL.clone();
-            let mut outbound_peer_connection_db = outbound_peer_connection_db_global.write().await;
-            outbound_peer_connection_db.remove(&self.connection_id);
-            let inbound_peer_connection_db_global = INBOUND_PEER_CONNECTIONS_GLOBAL.clone();
-            let mut inbound_peer_connection_db = inbound_peer_connection_db_global.write().await;
-            inbound_peer_connection_db.remove(&self.connection_id);
+            let mut peer_connection_db = self.network_context.peer_connections.write().await;
+            peer_connection_db.remove(&self.connection_id);
             // If we lose connection, we must also re-shake hands. Otherwise we risk IP-based handshake theft. This may be
             // a problem anyway with something like a CSFR, but we should at least make it as difficult as possible.
             self.peer_flags.has_completed_handshake = false;
+            self.update_stats(|peer_stats| peer_stats.on_disconnected(create_timestamp()));
+            if let Some(publickey) = self.publickey {
+                self.blockchain_lock
+                    .write()
+                    .await
+                    .get_mut_network_time()
+                    .remove_clock_offset(&publickey);
+            }
         }
         // and set the flag
         self.peer_flags.is_connected_or_connecting = is_connected_or_connecting;
@@ -234,15 +533,138 @@ impl SaitoPeer {
     pub fn get_is_connected_or_connecting(&self) -> bool {
         self.peer_flags.is_connected_or_connecting
     }
-    pub fn get_host(&self) -> Option<[u8; 4]> {
-        self.host
+    pub fn get_blockchain_lock(&self) -> Arc<RwLock<Blockchain>> {
+        self.blockchain_lock.clone()
+    }
+    pub fn get_host(&self) -> Option<&PeerHost> {
+        self.host.as_ref()
+    }
+    /// The address of the peer, unless it was configured by a hostname.
+    pub fn get_ip_address(&self) -> Option<IpAddr> {
+        self.host.as_ref().and_then(|host| host.get_ip_address())
     }
     pub fn get_port(&self) -> Option<u16> {
         self.port
     }
+    /// The host and port we connect to the peer on, which its stats are kept by. Peers which
+    /// connected to us have none.
+    pub fn get_address(&self) -> Option<String> {
+        Some(self.host.as_ref()?.format_url_string(self.port?))
+    }
+    /// Updates the stats of the peer's address, see networking::peer_stats.
+    pub fn update_stats<F: FnOnce(&mut PeerStats)>(&self, update: F) {
+        update_peer_stats(&self.network_context, self.get_address().as_deref(), update);
+    }
+    pub fn get_transport(&self) -> PeerTransport {
+        self.transport
+    }
+    pub fn set_transport(&mut self, transport: PeerTransport) {
+        self.transport = transport;
+    }
+    /// The roles the peer was configured with, empty if it is used for everything.
+    pub fn get_roles(&self) -> &[PeerRole] {
+        &self.roles
+    }
+    pub fn set_roles(&mut self, roles: Vec<PeerRole>) {
+        self.roles = roles;
+    }
+    pub fn has_role(&self, role: PeerRole) -> bool {
+        get_role_preference(&self.roles, role).is_some()
+    }
+    /// The services the peer advertised in the handshake.
+    pub fn get_services(&self) -> PeerServices {
+        self.services
+    }
+    pub fn set_services(&mut self, services: PeerServices) {
+        self.services = services;
+    }
+    /// Smoothed round-trip time in milliseconds of the requests we have sent this peer.
+    pub fn get_latency(&self) -> Option<u64> {
+        self.latency
+    }
+    fn add_latency_sample(&mut self, round_trip_time: u64) {
+        self.latency = Some(match self.latency {
+            Some(latency) => (3 * latency + round_trip_time) / 4,
+            None => round_trip_time,
+        });
+        self.update_stats(|peer_stats| peer_stats.add_latency_sample(round_trip_time));
+    }
+    /// How many milliseconds the peer's clock was ahead of ours when we last measured it, in
+    /// the handshake or a ping.
+    pub fn get_clock_offset(&self) -> Option<i64> {
+        self.clock_offset
+    }
+    /// Records the peer's clock offset, which also feeds into the network-adjusted time of
+    /// the blockchain. The peer must have completed the handshake.
+    pub async fn set_clock_offset(&mut self, clock_offset: i64) {
+        self.clock_offset = Some(clock_offset);
+        if let Some(publickey) = self.publickey {
+            self.blockchain_lock
+                .write()
+                .await
+                .get_mut_network_time()
+                .add_clock_offset(publickey, clock_offset);
+        }
+    }
     pub fn get_connection_id(&self) -> SaitoHash {
         self.connection_id
     }
+    /// Sends the peer the node metadata records we know, but its own, once the handshake
+    /// has completed.
+    pub async fn send_known_node_metadata(&mut self) {
+        let serialized_records: Vec<Vec<u8>> = self
+            .network_context
+            .node_metadata
+            .read()
+            .unwrap()
+            .get_all(create_timestamp())
+            .into_iter()
+            .filter(|node_metadata| Some(node_metadata.get_publickey()) != self.publickey)
+            .map(|node_metadata| node_metadata.serialize())
+            .collect();
+        for serialized_record in serialized_records {
+            self.send_command_fire_and_forget(MessageName::SendNodeMetadata, serialized_record)
+                .await;
+        }
+    }
+    /// Pings the peer, which measures its latency and clock offset again. Peers speaking an
+    /// older version of the protocol respond with an error, and keep the clock offset measured
+    /// in the handshake.
+    pub async fn do_ping(&self) {
+        let connection_id_clone = self.connection_id;
+        let network_context = self.network_context.clone();
+        tokio::spawn(async move {
+            let mut peer_db = network_context.peers_db.write().await;
+            let peer = match peer_db.get_mut(&connection_id_clone) {
+                Some(peer) => peer,
+                None => return,
+            };
+            let request_timestamp = create_timestamp();
+            let result = peer.send_command(MessageName::Ping, vec![]).await;
+            let response_timestamp = create_timestamp();
+            match result.map(|api_message| {
+                <[u8; 8]>::try_from(api_message.get_message_data().as_slice())
+                    .map(u64::from_be_bytes)
+            }) {
+                Ok(Ok(peer_timestamp)) => {
+                    match estimate_clock_offset(
+                        request_timestamp,
+                        response_timestamp,
+                        peer_timestamp,
+                    ) {
+                        Some(clock_offset) => peer.set_clock_offset(clock_offset).await,
+                        None => error!("PING ERROR: peer clock is too far from ours"),
+                    }
+                }
+                Ok(Err(_)) => {
+                    error!("PING ERROR: peer responded without a timestamp");
+                }
+                Err(error_message) => {
+                    info!("PING ERROR: {}", error_message.get_message_data_as_string());
+                }
+            }
+        });
+    }
     pub fn is_in_path(&self, path: &Vec<Hop>) -> bool {
         for hop in path {
             if self.publickey.unwrap() == hop.get_from() {
@@ -257,33 +679,42 @@ impl SaitoPeer {
     #[async_recursion]
     pub async fn send_command(
         &mut self,
-        command: &str,
+        command: MessageName,
         message: Vec<u8>,
     ) -> Result<APIMessage, APIMessage> {
+        let request_timestamp = create_timestamp();
         let peer_request = PeerRequest::new(command, message, self).await;
         // TODO should we turn this expect into an Err()???
         let response_message = peer_request
             .await
             .expect(&format!("Error returned from {}", command));
-        match response_message.get_message_name_as_string().as_str() {
-            "RESULT__" => Ok(response_message),
-            "ERROR___" => Err(response_message),
+        self.add_latency_sample(create_timestamp().saturating_sub(request_timestamp));
+        record_message(
+            &self.network_context,
+            &self.connection_id,
+            command,
+            &response_message,
+        );
+        match response_message.get_message_name() {
+            Some(MessageName::Result) => Ok(response_message),
+            Some(MessageName::Error) => Err(response_message),
             _ => {
                 panic!("Received non-response response");
             }
         }
     }
     /// sends a command to the peer's socket but doesn't not await the response
-    pub async fn send_command_fire_and_forget(&mut self, command: &str, message: Vec<u8>) {
+    pub async fn send_command_fire_and_forget(&mut self, command: MessageName, message: Vec<u8>) {
         // Create a PeerRequest(Future), but do not await it.
         let _peer_request = PeerRequest::new(command, message, self).await;
-        // TODO: low priority. Ensure that commands sent this way are actually cleaned from PEERS_REQUEST_RESPONSES_GLOBAL and PEERS_REQUEST_WAKERS_GLOBAL.
+        // TODO: low priority. Ensure that commands sent this way are actually cleaned from the request responses and wakers of the network context.
         //       I'm quite sure this isn't a problem, but did not confirm.
     }
     /// Helper function for sending basic OK results.
     pub async fn send_response_from_str(&mut self, message_id: u32, message_str: &str) {
         send_message_to_socket(
-            APIMessage::new_from_string("RESULT__", message_id, message_str),
+            &self.network_context,
+            APIMessage::new_from_string(MessageName::Result, message_id, message_str),
             &self.connection_id,
         )
         .await;
@@ -291,7 +722,8 @@ impl SaitoPeer {
     /// Helper function for sending RESULT__.
     pub async fn send_response(&mut self, message_id: u32, message: Vec<u8>) {
         send_message_to_socket(
-            APIMessage::new("RESULT__", message_id, message),
+            &self.network_context,
+            APIMessage::new(MessageName::Result, message_id, message),
             &self.connection_id,
         )
         .await;
@@ -299,7 +731,8 @@ impl SaitoPeer {
     /// Helper function for sending basic errors with a string message.
     pub async fn send_error_response_from_str(&mut self, message_id: u32, message_str: &str) {
         send_message_to_socket(
-            APIMessage::new_from_string("ERROR___", message_id, message_str),
+            &self.network_context,
+            APIMessage::new_from_string(MessageName::Error, message_id, message_str),
             &self.connection_id,
         )
         .await;
@@ -307,23 +740,26 @@ impl SaitoPeer {
     /// Helper function for sending errors
     pub async fn send_error_response(&mut self, message_id: u32, message: Vec<u8>) {
         send_message_to_socket(
-            APIMessage::new("ERROR___", message_id, message),
+            &self.network_context,
+            APIMessage::new(MessageName::Error, message_id, message),
             &self.connection_id,
         )
         .await;
     }
     /// handle any APIMessage from the socket. RESULT/ERROR will be matched to it's COMMAND via
     /// the ID and the Future will be polled via it's waker. Normal commands will be handled by handle_peer_command.
-    pub async fn handle_peer_message(api_message_orig: APIMessage, connection_id: SaitoHash) {
-        match api_message_orig.get_message_name_as_string().as_str() {
-            "RESULT__" | "ERROR___" => {
-                let request_wakers_lock = PEERS_REQUEST_WAKERS_GLOBAL.clone();
-                let mut request_wakers = request_wakers_lock.write().unwrap();
+    pub async fn handle_peer_message(
+        network_context: &NetworkContext,
+        api_message_orig: APIMessage,
+        connection_id: SaitoHash,
+    ) {
+        match api_message_orig.get_message_name() {
+            Some(MessageName::Result) | Some(MessageName::Error) => {
+                let mut request_wakers = network_context.request_wakers.write().unwrap();
                 let option_waker =
                     request_wakers.remove(&(connection_id, api_message_orig.message_id));
 
-                let request_responses_lock = PEERS_REQUEST_RESPONSES_GLOBAL.clone();
-                let mut request_responses = request_responses_lock.write().unwrap();
+                let mut request_responses = network_context.request_responses.write().unwrap();
                 request_responses.insert(
                     (connection_id, api_message_orig.message_id),
                     api_message_orig,
@@ -334,8 +770,10 @@ impl SaitoPeer {
                 }
             }
             _ => {
-                let peers_db_global = PEERS_DB_GLOBAL.clone();
-                let mut peer_db = peers_db_global.write().await;
+                if let Some(command) = api_message_orig.get_message_name() {
+                    record_message(network_context, &connection_id, command, &api_message_orig);
+                }
+                let mut peer_db = network_context.peers_db.write().await;
                 let peer = peer_db.get_mut(&connection_id).unwrap();
                 SaitoPeer::handle_peer_command(peer, api_message_orig).await;
             }
@@ -343,23 +781,63 @@ impl SaitoPeer {
     }
 
     // REQBLOCK is a response to both SNDCHAIN and SNDBLKHD. This function simply wraps shared functionality.
+    //
+    // Many peers announce the same block, so each block is only requested from the first peer
+    // which tells us about it. If that request fails the hash is forgotten again, so that the
+    // next announcement of the block fetches it from another peer.
     pub async fn do_reqblock(&self, block_hash: SaitoHash) {
+        if !self.services.full_blocks {
+            info!(
+                "peer does not serve full blocks, not requesting: {}",
+                hex::encode(block_hash)
+            );
+            return;
+        }
+        if !self
+            .network_context
+            .recent_block_hashes
+            .write()
+            .unwrap()
+            .insert(block_hash)
+        {
+            info!("block already requested: {}", hex::encode(block_hash));
+            return;
+        }
         let request_block_message = RequestBlockMessage::new(None, Some(block_hash), None);
         let connection_id_clone = self.connection_id.clone();
         let mempool_lock = self.mempool_lock.clone();
+        let network_context = self.network_context.clone();
 
         tokio::spawn(async move {
-            let peers_db_global = PEERS_DB_GLOBAL.clone();
-            let mut peer_db = peers_db_global.write().await;
+            let mut peer_db = network_context.peers_db.write().await;
             let peer = peer_db.get_mut(&connection_id_clone).unwrap();
             let result = peer
-                .send_command(&String::from("REQBLOCK"), request_block_message.serialize())
+                .send_command(MessageName::RequestBlock, request_block_message.serialize())
                 .await;
             match result {
                 Ok(serialized_block_message) => {
-                    let mut block =
-                        Block::deserialize_for_net(serialized_block_message.get_message_data());
+                    let mut block = match Block::try_deserialize_for_net(
+                        serialized_block_message.get_message_data(),
+                    ) {
+                        Some(block) => block,
+                        None => {
+                            error!(
+                                "ERROR 410383: peer sent a malformed block for {}, disconnecting",
+                                hex::encode(block_hash)
+                            );
+                            peer.update_stats(|peer_stats| peer_stats.add_invalid_message());
+                            network_context
+                                .recent_block_hashes
+                                .write()
+                                .unwrap()
+                                .remove(&block_hash);
+                            peer.set_is_connected_or_connecting(false).await;
+                            return;
+                        }
+                    };
                     block.set_source_connection_id(peer.connection_id);
+                    peer.mark_block_hash_known(block_hash);
+                    peer.update_stats(|peer_stats| peer_stats.add_blocks_served(1));
                     {
                         let mut mempool = mempool_lock.write().await;
                         mempool.add_block(block);
@@ -375,7 +853,108 @@ impl SaitoPeer {
                         "REQBLOCK ERROR: {}",
                         error_message.get_message_data_as_string()
                     );
+                    network_context
+                        .recent_block_hashes
+                        .write()
+                        .unwrap()
+                        .remove(&block_hash);
+                }
+            }
+        });
+    }
+    /// Fetches the blocks of the peer's longest chain from first_block_id to last_block_id with
+    /// REQRANGE, one chunk at a time, adding each chunk to the blockchain before asking for the
+    /// next. A range which breaks off is resumed by the next SNDCHAIN, from our latest block.
+    pub async fn do_reqrange(&self, first_block_id: u64, last_block_id: u64) {
+        let connection_id_clone = self.connection_id;
+        let address = self.get_address();
+        let mempool_lock = self.mempool_lock.clone();
+        let blockchain_lock = self.blockchain_lock.clone();
+        let network_context = self.network_context.clone();
+
+        tokio::spawn(async move {
+            let mut first_block_id = first_block_id;
+            while first_block_id <= last_block_id {
+                let request_block_range_message =
+                    RequestBlockRangeMessage::new(first_block_id, last_block_id);
+                let result = {
+                    let mut peer_db = network_context.peers_db.write().await;
+                    let peer = match peer_db.get_mut(&connection_id_clone) {
+                        Some(peer) => peer,
+                        None => return,
+                    };
+                    peer.send_command(
+                        MessageName::RequestBlockRange,
+                        request_block_range_message.serialize(),
+                    )
+                    .await
+                };
+                let block_range_chunk = match result
+                    .map(|api_message| BlockRangeChunk::deserialize(api_message.get_message_data()))
+                {
+                    Ok(Some(block_range_chunk))
+                        if block_range_chunk.get_first_block_id() == first_block_id =>
+                    {
+                        block_range_chunk
+                    }
+                    Ok(_) => {
+                        error!("REQRANGE ERROR: no chunk of blocks from {}", first_block_id);
+                        update_peer_stats(&network_context, address.as_deref(), |peer_stats| {
+                            peer_stats.add_invalid_message()
+                        });
+                        return;
+                    }
+                    Err(error_message) => {
+                        error!(
+                            "REQRANGE ERROR: {}",
+                            error_message.get_message_data_as_string()
+                        );
+                        return;
+                    }
+                };
+                let serialized_blocks = match block_range_chunk.decompress_blocks() {
+                    Ok(serialized_blocks) => serialized_blocks,
+                    Err(err) => {
+                        error!(
+                            "REQRANGE ERROR: bad chunk of blocks from {}: {}",
+                            first_block_id, err
+                        );
+                        update_peer_stats(&network_context, address.as_deref(), |peer_stats| {
+                            peer_stats.add_invalid_message()
+                        });
+                        return;
+                    }
+                };
+                let blocks = match serialized_blocks
+                    .iter()
+                    .map(|bytes| Block::try_deserialize_for_net(bytes))
+                    .collect::<Option<Vec<Block>>>()
+                {
+                    Some(blocks) => blocks,
+                    None => {
+                        error!(
+                            "REQRANGE ERROR: malformed block in chunk of blocks from {}",
+                            first_block_id
+                        );
+                        update_peer_stats(&network_context, address.as_deref(), |peer_stats| {
+                            peer_stats.add_invalid_message()
+                        });
+                        return;
+                    }
+                };
+                update_peer_stats(&network_context, address.as_deref(), |peer_stats| {
+                    peer_stats.add_blocks_served(blocks.len() as u64)
+                });
+                {
+                    let mut mempool = mempool_lock.write().await;
+                    for mut block in blocks {
+                        block.set_source_connection_id(connection_id_clone);
+                        mempool.add_block(block);
+                    }
                 }
+                Mempool::send_blocks_to_blockchain(mempool_lock.clone(), blockchain_lock.clone())
+                    .await;
+                first_block_id = block_range_chunk.get_last_block_id() + 1;
             }
         });
     }
@@ -383,46 +962,127 @@ impl SaitoPeer {
     async fn handle_peer_command(peer: &mut SaitoPeer, api_message: APIMessage) {
         let mempool_lock = peer.mempool_lock.clone();
         let blockchain_lock = peer.blockchain_lock.clone();
-        let command = api_message.get_message_name_as_string();
+        let network_context = peer.network_context.clone();
+        let command = match api_message.get_message_name() {
+            Some(command) => command,
+            None => {
+                error!(
+                    "Unhandled command received by client... {}",
+                    &api_message.get_message_name_as_string()
+                );
+                peer.update_stats(|peer_stats| peer_stats.add_invalid_message());
+                peer.send_error_response_from_str(api_message.message_id, "NO SUCH")
+                    .await;
+                return;
+            }
+        };
         info!("HANDLING COMMAND {}", command);
-        match command.as_str() {
-            "SHAKINIT" => {
-                if let Ok(serialized_handshake_challenge) =
-                    build_serialized_challenge(&api_message, peer.wallet_lock.clone()).await
+        match command {
+            MessageName::HandshakeInit => {
+                if let Ok(serialized_handshake_challenge) = build_serialized_challenge(
+                    &network_context,
+                    &api_message,
+                    &peer.connection_id,
+                    peer.get_ip_address(),
+                    peer.wallet_lock.clone(),
+                )
+                .await
                 {
+                    // the services follow the nonce, unless the peer advertises none
+                    peer.set_services(
+                        api_message
+                            .get_message_data()
+                            .get(81..)
+                            .and_then(PeerServices::deserialize)
+                            .unwrap_or_else(PeerServices::unadvertised),
+                    );
                     peer.send_response(api_message.message_id, serialized_handshake_challenge)
                         .await;
                 }
             }
-            "SHAKCOMP" => match socket_handshake_verify(&api_message.get_message_data()) {
-                Some(deserialize_challenge) => {
-                    peer.set_has_completed_handshake(true);
-                    peer.set_publickey(deserialize_challenge.opponent_pubkey());
-                    peer.send_response(
-                        api_message.message_id,
-                        String::from("OK").as_bytes().try_into().unwrap(),
-                    )
-                    .await;
-                }
-                None => {
-                    error!("Error verifying peer handshake signature");
+            MessageName::HandshakeComplete => {
+                match socket_handshake_verify(api_message.get_message_data()) {
+                    Some(deserialize_challenge)
+                        if !redeem_issued_challenge(
+                            &network_context,
+                            &peer.connection_id,
+                            &deserialize_challenge,
+                            peer.wallet_lock.clone(),
+                        )
+                        .await =>
+                    {
+                        error!("ERROR 410374: peer signed a challenge we did not issue on this connection, or which has expired or was already used");
+                        peer.send_error_response_from_str(
+                            api_message.message_id,
+                            "INVALID CHALLENGE",
+                        )
+                        .await;
+                    }
+                    Some(deserialize_challenge) => {
+                        if !is_peer_allowed(
+                            &network_context,
+                            Some(deserialize_challenge.opponent_pubkey()),
+                            peer.get_ip_address(),
+                        ) {
+                            error!("ERROR 410371: peer is not allowed by the peer filter");
+                            peer.send_error_response_from_str(
+                                api_message.message_id,
+                                "PEER NOT ALLOWED",
+                            )
+                            .await;
+                            peer.set_is_connected_or_connecting(false).await;
+                            return;
+                        }
+                        peer.set_has_completed_handshake(true);
+                        peer.set_publickey(deserialize_challenge.opponent_pubkey());
+                        let mut message_data: Vec<u8> = String::from("OK").as_bytes().into();
+                        message_data.extend(get_local_services(&network_context).serialize());
+                        peer.send_response(api_message.message_id, message_data)
+                            .await;
+                        peer.send_known_node_metadata().await;
+                    }
+                    None => {
+                        error!("Error verifying peer handshake signature");
+                        peer.send_error_response_from_str(
+                            api_message.message_id,
+                            "INVALID CHALLENGE",
+                        )
+                        .await;
+                    }
                 }
-            },
-            "REQBLOCK" => {
-                let api_message = build_request_block_response(&api_message, blockchain_lock).await;
-                send_message_to_socket(api_message, &peer.connection_id).await;
             }
-            "REQBLKHD" => {
+            MessageName::RequestBlock if !get_local_services(&network_context).full_blocks => {
+                peer.send_error_response_from_str(api_message.message_id, "NOT SERVED")
+                    .await;
+            }
+            MessageName::RequestBlock => {
+                let api_message = build_request_block_response(&api_message, &peer.block_store);
+                send_message_to_socket(&network_context, api_message, &peer.connection_id).await;
+            }
+            MessageName::RequestBlockRange => {
+                let api_message = build_request_block_range_response(
+                    &network_context,
+                    &api_message,
+                    blockchain_lock,
+                )
+                .await;
+                send_message_to_socket(&network_context, api_message, &peer.connection_id).await;
+            }
+            MessageName::RequestBlockHeader if !get_local_services(&network_context).headers => {
+                peer.send_error_response_from_str(api_message.message_id, "NOT SERVED")
+                    .await;
+            }
+            MessageName::RequestBlockHeader => {
                 let message_id = api_message.message_id;
-                if let Some(bytes) = socket_send_block_header(&api_message, blockchain_lock).await {
+                if let Some(bytes) = socket_send_block_header(&api_message, &peer.block_store) {
                     let message_data = String::from("OK").as_bytes().try_into().unwrap();
                     peer.send_response(message_id, message_data).await;
-                    let _result = peer.send_command("SNDBLKHD", bytes).await;
+                    let _result = peer.send_command(MessageName::SendBlockHeader, bytes).await;
                 } else {
                     peer.send_error_response_from_str(message_id, "ERROR").await;
                 }
             }
-            "REQCHAIN" => {
+            MessageName::RequestBlockchain => {
                 peer.send_response_from_str(api_message.message_id, "OK")
                     .await;
                 if let Some(send_blockchain_message) = build_send_blockchain_message(
@@ -433,13 +1093,12 @@ impl SaitoPeer {
                 {
                     let connection_id_clone = peer.connection_id.clone();
                     tokio::spawn(async move {
-                        let peers_db_global = PEERS_DB_GLOBAL.clone();
-                        let mut peer_db = peers_db_global.write().await;
+                        let mut peer_db = network_context.peers_db.write().await;
                         let peer = peer_db.get_mut(&connection_id_clone).unwrap();
 
                         let _result = peer
                             .send_command(
-                                &String::from("SNDCHAIN"),
+                                MessageName::SendBlockchain,
                                 send_blockchain_message.serialize(),
                             )
                             .await;
@@ -449,24 +1108,115 @@ impl SaitoPeer {
                         .await;
                 }
             }
-            "SNDCHAIN" => {
+            MessageName::SendBlockchain => {
                 peer.send_response_from_str(api_message.message_id, "OK")
                     .await;
 
                 let send_blockchain_message =
                     SendBlockchainMessage::deserialize(api_message.get_message_data());
+
+                //
+                // the blocks are those of the peer's longest chain, so the highest
+                // of them is its tip
+                //
+                let peer_tip_block_id = send_blockchain_message
+                    .get_blocks_data()
+                    .iter()
+                    .map(|send_blockchain_block_data| send_blockchain_block_data.block_id)
+                    .max()
+                    .unwrap_or(0);
+                blockchain_lock
+                    .write()
+                    .await
+                    .get_mut_sync_status()
+                    .on_peer_tip(peer.connection_id, peer_tip_block_id);
+
+                //
+                // an archive provider far enough ahead of us sends its chain in
+                // ranges, from the first block we are missing up to its tip, if
+                // it advertised that it keeps the blocks that far back
+                //
+                let mut missing_block_data = vec![];
                 for send_blockchain_block_data in
                     send_blockchain_message.get_blocks_data().into_iter()
                 {
-                    peer.do_reqblock(send_blockchain_block_data.block_hash)
+                    let block_hash = send_blockchain_block_data.block_hash;
+                    peer.mark_block_hash_known(block_hash);
+                    if blockchain_lock
+                        .read()
+                        .await
+                        .get_block_sync(&block_hash)
+                        .is_none()
+                    {
+                        missing_block_data.push(send_blockchain_block_data);
+                    }
+                }
+                let first_missing_block_id = missing_block_data
+                    .iter()
+                    .map(|send_blockchain_block_data| send_blockchain_block_data.block_id)
+                    .min()
+                    .unwrap_or(0)
+                    .min(blockchain_lock.read().await.get_latest_block_id() + 1);
+                if peer.get_roles().contains(&PeerRole::ArchiveProvider)
+                    && !missing_block_data.is_empty()
+                    && peer_tip_block_id + 1 >= first_missing_block_id + BLOCK_RANGE_SYNC_THRESHOLD
+                    && peer
+                        .get_services()
+                        .serves_block_range(first_missing_block_id, peer_tip_block_id)
+                {
+                    peer.do_reqrange(first_missing_block_id, peer_tip_block_id)
                         .await;
+                } else {
+                    //
+                    // the chain is sent from the tip back, but a block is only
+                    // validated if its parent was added before it
+                    //
+                    missing_block_data.sort_by_key(|send_blockchain_block_data| {
+                        send_blockchain_block_data.block_id
+                    });
+                    for send_blockchain_block_data in missing_block_data {
+                        peer.do_reqblock(send_blockchain_block_data.block_hash)
+                            .await;
+                    }
                 }
             }
-            "SNDBLKHD" => {
+            MessageName::SendBlockHeader => {
                 let send_block_head_message =
                     SendBlockHeadMessage::deserialize(api_message.get_message_data());
-                send_block_head_message.get_block_hash();
-                let blockchain = blockchain_lock.read().await;
+                let block_hash = *send_block_head_message.get_block_hash();
+                peer.mark_block_hash_known(block_hash);
+                let mut blockchain = blockchain_lock.write().await;
+                if send_block_head_message.has_tip() {
+                    let block_id = send_block_head_message.get_block_id();
+                    let chain_work = send_block_head_message.get_chain_work();
+                    let comparison = blockchain.compare_tip(&block_hash, block_id, chain_work);
+                    peer.tip = Some(PeerTip {
+                        block_hash,
+                        block_id,
+                        chain_work,
+                        comparison,
+                    });
+                    if comparison != TipComparison::Fork {
+                        blockchain
+                            .get_mut_sync_status()
+                            .on_peer_tip(peer.connection_id, block_id);
+                    }
+                    //
+                    // a chain neither longer nor heavier than ours cannot become our
+                    // longest chain, so we wait for the peer to build on it first
+                    //
+                    if comparison == TipComparison::Fork
+                        && blockchain.get_block(&block_hash).await.is_none()
+                    {
+                        info!(
+                            "SNDBLKHD tip on a fork behind ours, not fetching: {}",
+                            hex::encode(block_hash),
+                        );
+                        peer.send_response_from_str(api_message.get_message_id(), "OK")
+                            .await;
+                        return;
+                    }
+                }
                 match blockchain
                     .get_block(send_block_head_message.get_block_hash())
                     .await
@@ -486,27 +1236,59 @@ impl SaitoPeer {
                     }
                 }
             }
-            "SNDTRANS" => {
+            MessageName::SendTransaction => {
                 if let Some(mut tx) = socket_receive_transaction(api_message.clone()) {
                     let wallet_lock_clone = peer.wallet_lock.clone();
                     let wallet = wallet_lock_clone.read().await;
                     tx.generate_metadata(wallet.get_publickey());
 
+                    if !tx.validate_routing_path_for_peer(
+                        peer.get_publickey(),
+                        wallet.get_publickey(),
+                    ) {
+                        error!("ERROR 739102: routing path does not match relaying peer");
+                        peer.update_stats(|peer_stats| peer_stats.add_invalid_message());
+                        peer.send_error_response_from_str(
+                            api_message.message_id,
+                            "INVALID ROUTING PATH",
+                        )
+                        .await;
+                        return;
+                    }
+
                     let blockchain = blockchain_lock.read().await;
                     let mut mempool = mempool_lock.write().await;
                     if !mempool.transaction_exists(tx.get_hash_for_signature()) {
-                        if tx.validate(&blockchain.utxoset, &blockchain.staking) {
-                            mempool.add_transaction(tx.clone()).await;
-
-                            peer.send_response_from_str(api_message.message_id, "OK")
-                                .await;
-                            Network::propagate_transaction(peer.wallet_lock.clone(), tx).await;
-                        } else {
+                        if !tx.validate(&blockchain.utxoset, &blockchain.staking) {
+                            peer.update_stats(|peer_stats| peer_stats.add_invalid_message());
                             peer.send_error_response_from_str(
                                 api_message.message_id,
                                 "INVALID TRANSACTION",
                             )
                             .await;
+                        } else if mempool.add_transaction(tx.clone()).await.is_err() {
+                            peer.send_error_response_from_str(
+                                api_message.message_id,
+                                "TRANSACTION NOT ADMITTED",
+                            )
+                            .await;
+                        } else {
+                            update_peer_bandwidth(
+                                &network_context,
+                                &peer.connection_id,
+                                |bandwidth| bandwidth.add_transaction_received(&tx),
+                            );
+
+                            peer.send_response_from_str(api_message.message_id, "OK")
+                                .await;
+                            Network::propagate_transaction(
+                                network_context,
+                                peer.wallet_lock.clone(),
+                                peer.blockchain_lock.clone(),
+                                tx,
+                                false,
+                            )
+                            .await;
                         }
                     } else {
                         peer.send_error_response_from_str(
@@ -517,43 +1299,66 @@ impl SaitoPeer {
                     }
                 }
             }
-            "SNDKYLST" => {
-                peer.send_error_response_from_str(api_message.message_id, "UNHANDLED COMMAND")
-                    .await;
+            MessageName::SendNodeMetadata => {
+                match NodeMetadata::deserialize(api_message.get_message_data()) {
+                    Some(node_metadata) if node_metadata.verify() => {
+                        peer.send_response_from_str(api_message.message_id, "OK")
+                            .await;
+                        let is_new = network_context
+                            .node_metadata
+                            .write()
+                            .unwrap()
+                            .insert(node_metadata.clone(), create_timestamp());
+                        if is_new {
+                            Network::propagate_node_metadata(
+                                network_context,
+                                node_metadata,
+                                Some(peer.connection_id),
+                            );
+                        }
+                    }
+                    _ => {
+                        error!("ERROR 410381: peer sent invalid node metadata");
+                        peer.send_error_response_from_str(
+                            api_message.message_id,
+                            "INVALID NODE METADATA",
+                        )
+                        .await;
+                    }
+                }
             }
-            _ => {
-                error!(
-                    "Unhandled command received by client... {}",
-                    &api_message.get_message_name_as_string()
-                );
-                peer.send_error_response_from_str(api_message.message_id, "NO SUCH")
+            MessageName::Ping => {
+                peer.send_response(
+                    api_message.message_id,
+                    create_timestamp().to_be_bytes().to_vec(),
+                )
+                .await;
+            }
+            MessageName::SendKeyList => {
+                peer.send_error_response_from_str(api_message.message_id, "UNHANDLED COMMAND")
                     .await;
             }
+            // responses are matched to their requests by handle_peer_message
+            MessageName::Result | MessageName::Error => {}
         }
     }
 }
-/// spawns a task to read messages from the socket of inbound peers.
-pub async fn handle_inbound_peer_connection(
-    ws: WebSocket,
-    peer_db_lock: Arc<RwLock<PeersDB>>,
+/// spawns a task to read messages from the socket of inbound peers. host is the address the
+/// peer connected from, if it is known.
+pub async fn handle_inbound_peer_connection<C: PeerConnection + 'static>(
+    peer_connection: C,
+    host: Option<PeerHost>,
+    network_context: NetworkContext,
     wallet_lock: Arc<RwLock<Wallet>>,
     mempool_lock: Arc<RwLock<Mempool>>,
     blockchain_lock: Arc<RwLock<Blockchain>>,
     broadcast_channel_sender: broadcast::Sender<SaitoMessage>,
 ) {
-    let (peer_ws_sender, mut peer_ws_rcv) = ws.split();
-    let (peer_sender, peer_rcv) = mpsc::unbounded_channel();
-    let peer_rcv = UnboundedReceiverStream::new(peer_rcv);
-    tokio::task::spawn(peer_rcv.forward(peer_ws_sender).map(|result| {
-        if let Err(e) = result {
-            error!("error sending websocket msg: {}", e);
-        }
-    }));
-
     let connection_id: SaitoHash = hash(&Uuid::new_v4().as_bytes().to_vec());
+    let block_store = blockchain_lock.read().await.get_block_store();
     let peer = SaitoPeer::new(
         connection_id,
-        None,
+        host,
         None,
         true,
         false,
@@ -561,83 +1366,76 @@ pub async fn handle_inbound_peer_connection(
         wallet_lock.clone(),
         mempool_lock.clone(),
         blockchain_lock.clone(),
+        block_store,
         broadcast_channel_sender.clone(),
+        network_context.clone(),
     );
 
-    peer_db_lock
+    network_context
+        .peers_db
         .write()
         .await
         .insert(connection_id.clone(), peer);
 
-    let inbound_peer_db_lock_global = INBOUND_PEER_CONNECTIONS_GLOBAL.clone();
-    inbound_peer_db_lock_global.write().await.insert(
-        connection_id.clone(),
-        InboundPeer {
-            sender: peer_sender,
-        },
-    );
-
-    tokio::task::spawn(async move {
-        while let Some(result) = peer_ws_rcv.next().await {
-            let msg = match result {
-                Ok(msg) => msg,
-                Err(e) => {
-                    eprintln!("error receiving ws message for peer: {}", e);
-                    break;
-                }
-            };
-            if !msg.as_bytes().is_empty() {
-                let api_message = APIMessage::deserialize(&msg.as_bytes().to_vec());
-                SaitoPeer::handle_peer_message(api_message, connection_id).await;
-            } else {
-                error!(
-                    "Message of length 0... why?\n
-                    This seems to occur if we aren't holding a reference to the sender/stream on the\n
-                    other end of the connection. I suspect that when the stream goes out of scope,\n
-                    it's deconstructor is being called and sends a 0 length message to indicate\n
-                    that the stream has ended... I'm leaving this println here for now because\n
-                    it would be very helpful to see this if starts to occur again. We may want to\n
-                    treat this as a disconnect."
-                );
-            }
-        }
-        // We had some error. Remove all references to this peer.
-        {
-            let mut peer_db = peer_db_lock.write().await;
-            let peer = peer_db.get_mut(&connection_id).unwrap();
-            peer.set_is_connected_or_connecting(false).await;
-            peer_db.remove(&connection_id);
-        }
-    });
+    run_peer_connection(network_context, peer_connection, connection_id).await;
 }
 
+/// Answers a SHAKINIT with a signed challenge and remembers it as issued on the connection.
+/// connected_ip_address is the address the peer connected from, if it is known, which we sign
+/// in place of the address the peer claims.
 pub async fn build_serialized_challenge(
+    network_context: &NetworkContext,
     message: &APIMessage,
+    connection_id: &SaitoHash,
+    connected_ip_address: Option<IpAddr>,
     wallet_lock: Arc<RwLock<Wallet>>,
 ) -> crate::Result<Vec<u8>> {
     let wallet = wallet_lock.read().await;
     let my_pubkey = wallet.get_publickey();
     let my_privkey = wallet.get_privatekey();
 
-    let mut peer_octets: [u8; 4] = [0; 4];
-    peer_octets[0..4].clone_from_slice(&message.message_data[0..4]);
-    let peer_pubkey: SaitoPublicKey = message.message_data[4..37].try_into().unwrap();
+    let peer_ip_address = match connected_ip_address {
+        Some(ip_address) => ip_address,
+        None => ip_address_from_bytes(message.message_data[0..16].try_into()?),
+    };
+    let peer_pubkey: SaitoPublicKey = message.message_data[16..49].try_into()?;
+    let peer_nonce: HandshakeNonce = message.message_data[49..81].try_into()?;
 
     // TODO configure the node's IP somewhere...
-    let my_octets: [u8; 4] = [127, 0, 0, 1];
-
-    // TODO get the IP of this socket connection somehow and validate it..
-    // let peer_octets: [u8; 4] = match addr.unwrap().ip() {
-    //     IpAddr::V4(ip4) => ip4.octets(),
-    //     _ => panic!("Saito Handshake does not support IPV6"),
-    // };
+    let my_ip_address = IpAddr::from([127, 0, 0, 1]);
 
-    let challenge = HandshakeChallenge::new((my_octets, my_pubkey), (peer_octets, peer_pubkey));
+    let mut challenge =
+        HandshakeChallenge::new((my_ip_address, my_pubkey), (peer_ip_address, peer_pubkey));
+    challenge.set_opponent_nonce(peer_nonce);
     let serialized_challenge = challenge.serialize_with_sig(my_privkey);
 
+    network_context
+        .issued_challenges
+        .write()
+        .unwrap()
+        .issue(*connection_id, &challenge);
+
     Ok(serialized_challenge)
 }
 
+/// Whether a challenge signed in a SHAKCOMP is one we issued on the connection, which has not
+/// expired or been used before.
+pub async fn redeem_issued_challenge(
+    network_context: &NetworkContext,
+    connection_id: &SaitoHash,
+    challenge: &HandshakeChallenge,
+    wallet_lock: Arc<RwLock<Wallet>>,
+) -> bool {
+    if challenge.challenger_pubkey() != wallet_lock.read().await.get_publickey() {
+        return false;
+    }
+    network_context.issued_challenges.write().unwrap().redeem(
+        connection_id,
+        challenge,
+        create_timestamp(),
+    )
+}
+
 pub fn socket_handshake_verify(message_data: &Vec<u8>) -> Option<HandshakeChallenge> {
     let challenge = HandshakeChallenge::deserialize(message_data);
     if challenge.timestamp() < create_timestamp() - CHALLENGE_EXPIRATION_TIME {
@@ -672,50 +1470,140 @@ pub fn socket_receive_transaction(message: APIMessage) -> Option<Transaction> {
     Some(tx)
 }
 
-pub async fn build_request_block_response(
+pub fn build_request_block_response(
     api_message: &APIMessage,
-    blockchain_lock: Arc<RwLock<Blockchain>>,
+    block_store: &BlockStore,
 ) -> APIMessage {
     let request_block_message = RequestBlockMessage::deserialize(api_message.get_message_data());
-    let blockchain = blockchain_lock.read().await;
     if request_block_message.get_block_id().is_some() {
-        APIMessage::new_from_string("ERROR___", api_message.message_id, "Unsupported: fetching blocks by id is not yet supported, please fetch the block by hash.")
+        APIMessage::new_from_string(MessageName::Error, api_message.message_id, "Unsupported: fetching blocks by id is not yet supported, please fetch the block by hash.")
     } else if request_block_message.get_block_hash().is_some() {
         //let block_hash: SaitoHash = api_message.message_data[0..32].try_into().unwrap();
         let block_hash: SaitoHash = request_block_message.get_block_hash().unwrap();
 
-        match blockchain.get_block_sync(&block_hash) {
+        match block_store.get_block(&block_hash) {
             Some(target_block) => APIMessage::new(
-                "RESULT__",
+                MessageName::Result,
                 api_message.message_id,
                 target_block.serialize_for_net(BlockType::Full),
             ),
             None => APIMessage::new_from_string(
-                "ERROR___",
+                MessageName::Error,
                 api_message.message_id,
                 "Unknown Block Hash",
             ),
         }
     } else {
         APIMessage::new_from_string(
-            "ERROR___",
+            MessageName::Error,
             api_message.message_id,
             "REQBLOCK requires ID or Hash of desired block",
         )
     }
 }
 
-pub async fn socket_send_block_header(
+/// The RESULT__ of a REQRANGE: the blocks of our longest chain from the first block id of the
+/// request on, up to its last block id or until the chunk is full. Blocks we have pruned are
+/// read back from the block storage.
+pub async fn build_request_block_range_response(
+    network_context: &NetworkContext,
     api_message: &APIMessage,
     blockchain_lock: Arc<RwLock<Blockchain>>,
+) -> APIMessage {
+    let request_block_range_message =
+        match RequestBlockRangeMessage::deserialize(api_message.get_message_data()) {
+            Some(request_block_range_message) => request_block_range_message,
+            None => {
+                return APIMessage::new_from_string(
+                    MessageName::Error,
+                    api_message.message_id,
+                    "REQRANGE requires the first and last id of the range",
+                )
+            }
+        };
+    let first_block_id = request_block_range_message.get_first_block_id();
+    let last_block_id = request_block_range_message.get_last_block_id();
+    if first_block_id == 0 || last_block_id < first_block_id {
+        return APIMessage::new_from_string(
+            MessageName::Error,
+            api_message.message_id,
+            "REQRANGE requires a range of block ids from 1",
+        );
+    }
+
+    let blockchain = blockchain_lock.read().await;
+    if !get_local_services(network_context)
+        .serves_block_range(first_block_id, blockchain.get_latest_block_id())
+    {
+        return APIMessage::new_from_string(
+            MessageName::Error,
+            api_message.message_id,
+            "NOT SERVED",
+        );
+    }
+    let mut serialized_blocks = vec![];
+    let mut chunk_size = 0;
+    let mut block_id = first_block_id;
+    while block_id <= last_block_id && chunk_size < BLOCK_RANGE_CHUNK_SIZE {
+        let block_hash = blockchain
+            .blockring
+            .get_longest_chain_block_hash_by_block_id(block_id);
+        let block = match blockchain.get_block_sync(&block_hash) {
+            Some(block) if block.get_id() == block_id => block,
+            _ => break,
+        };
+        let bytes = if block.get_block_type() == BlockType::Full {
+            block.serialize_for_net(BlockType::Full)
+        } else {
+            match blockchain
+                .get_block_storage()
+                .read_block(block.get_timestamp(), &block_hash)
+            {
+                Ok(block) => block.serialize_for_net(BlockType::Full),
+                Err(err) => {
+                    error!(
+                        "ERROR 290314: cannot load block {}: {}",
+                        hex::encode(block_hash),
+                        err
+                    );
+                    break;
+                }
+            }
+        };
+        chunk_size += bytes.len();
+        serialized_blocks.push(bytes);
+        block_id += 1;
+    }
+    if serialized_blocks.is_empty() {
+        return APIMessage::new_from_string(
+            MessageName::Error,
+            api_message.message_id,
+            "UNKNOWN BLOCK RANGE",
+        );
+    }
+    match BlockRangeChunk::new(first_block_id, &serialized_blocks) {
+        Ok(block_range_chunk) => APIMessage::new(
+            MessageName::Result,
+            api_message.message_id,
+            block_range_chunk.serialize(),
+        ),
+        Err(err) => APIMessage::new_from_string(
+            MessageName::Error,
+            api_message.message_id,
+            &format!("cannot compress block range: {}", err),
+        ),
+    }
+}
+
+pub fn socket_send_block_header(
+    api_message: &APIMessage,
+    block_store: &BlockStore,
 ) -> Option<Vec<u8>> {
     let block_hash: SaitoHash = api_message.message_data[0..32].try_into().unwrap();
-    let blockchain = blockchain_lock.read().await;
 
-    match blockchain.get_block_sync(&block_hash) {
-        Some(target_block) => Some(target_block.serialize_for_net(BlockType::Header)),
-        None => None,
-    }
+    block_store
+        .get_block(&block_hash)
+        .map(|target_block| target_block.serialize_for_net(BlockType::Header))
 }
 
 pub async fn build_send_blockchain_message(
@@ -723,17 +1611,24 @@ pub async fn build_send_blockchain_message(
     blockchain_lock: Arc<RwLock<Blockchain>>,
 ) -> Option<SendBlockchainMessage> {
     let block_zero_hash: SaitoHash = [0; 32];
+    let blockchain = blockchain_lock.read().await;
 
     let peers_latest_hash: &SaitoHash;
-    if request_blockchain_message.get_latest_block_id() == 0
+    let fork_point_hash: SaitoHash;
+    if !request_blockchain_message.get_block_locator().is_empty() {
+        //
+        // we send our chain from where the peer's chain forks from ours, or from as
+        // far back as we go if our chains have no block in common
+        //
+        fork_point_hash = blockchain
+            .find_fork_point(request_blockchain_message.get_block_locator())
+            .unwrap_or(block_zero_hash);
+        peers_latest_hash = &fork_point_hash;
+    } else if request_blockchain_message.get_latest_block_id() == 0
         && request_blockchain_message.get_latest_block_hash() == &block_zero_hash
     {
         peers_latest_hash = &block_zero_hash;
     } else {
-        // TODO contains_block_hash_at_block_id for some reason needs mutable access to block_ring
-        // We should figure out why this is and get rid of this problem, I don't think there's any
-        // reason we should need to get the write lock for this operation...
-        let blockchain = blockchain_lock.read().await;
         if !blockchain.contains_block_hash_at_block_id(
             request_blockchain_message.get_latest_block_id(),
             *request_blockchain_message.get_latest_block_hash(),
@@ -744,16 +1639,19 @@ pub async fn build_send_blockchain_message(
         peers_latest_hash = request_blockchain_message.get_latest_block_hash();
     }
 
-    let blockchain = blockchain_lock.read().await;
-
     let mut blocks_data: Vec<SendBlockchainBlockData> = vec![];
     if let Some(latest_block) = blockchain.get_latest_block() {
         let mut previous_block_hash: SaitoHash = latest_block.get_hash();
-        let mut this_block: &Block; // = blockchain.get_block_sync(&previous_block_hash).unwrap();
         let mut block_count = 0;
         while &previous_block_hash != peers_latest_hash && block_count < GENESIS_PERIOD {
             block_count += 1;
-            this_block = blockchain.get_block_sync(&previous_block_hash).unwrap();
+            //
+            // the chain may have been pruned before we get back to the fork point
+            //
+            let this_block = match blockchain.get_block_sync(&previous_block_hash) {
+                Some(block) => block,
+                None => break,
+            };
             blocks_data.push(SendBlockchainBlockData {
                 block_id: this_block.get_id(),
                 block_hash: this_block.get_hash(),
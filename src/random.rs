@@ -0,0 +1,87 @@
+use crate::crypto::{generate_random_bytes, hash, SaitoHash};
+
+/// A source of random numbers, given to the parts of the node which need them so that tests
+/// can replace the randomness of the operating system with a HashChain.
+pub trait RandomSource: Send + Sync {
+    fn next_random_number(&mut self) -> SaitoHash;
+}
+
+/// Random numbers from the operating system, for anything which must not be predictable,
+/// such as the randoms the miner tries for golden tickets.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OsRandom;
+
+impl RandomSource for OsRandom {
+    fn next_random_number(&mut self) -> SaitoHash {
+        hash(&generate_random_bytes(32))
+    }
+}
+
+/// A chain of hashes from a seed. Every node derives the same numbers from the same seed, so
+/// consensus code picks routers and stakers with a HashChain seeded by the random of the
+/// golden ticket, and never with OsRandom.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HashChain {
+    random_number: SaitoHash,
+}
+
+impl HashChain {
+    /// Starts the chain at the hash of the seed.
+    pub fn new(seed: SaitoHash) -> Self {
+        HashChain {
+            random_number: hash(&seed[..]),
+        }
+    }
+
+    pub fn get_random_number(&self) -> SaitoHash {
+        self.random_number
+    }
+
+    /// Moves n hashes along the chain.
+    pub fn advance(&mut self, n: u64) {
+        for _ in 0..n {
+            self.random_number = hash(&self.random_number[..]);
+        }
+    }
+}
+
+impl RandomSource for HashChain {
+    /// Returns the current number and moves one hash along the chain.
+    fn next_random_number(&mut self) -> SaitoHash {
+        let random_number = self.random_number;
+        self.advance(1);
+        random_number
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_chain_test() {
+        let seed = [1; 32];
+        let mut hash_chain = HashChain::new(seed);
+        assert_eq!(hash_chain.get_random_number(), hash(&seed[..]));
+
+        hash_chain.advance(2);
+        assert_eq!(
+            hash_chain.get_random_number(),
+            hash(&hash(&hash(&seed[..]).to_vec()).to_vec())
+        );
+
+        let mut other_hash_chain = HashChain::new(seed);
+        other_hash_chain.next_random_number();
+        other_hash_chain.next_random_number();
+        assert_eq!(other_hash_chain, hash_chain);
+        assert_eq!(
+            other_hash_chain.next_random_number(),
+            hash_chain.get_random_number()
+        );
+    }
+
+    #[test]
+    fn os_random_test() {
+        assert_ne!(OsRandom.next_random_number(), OsRandom.next_random_number());
+    }
+}
@@ -0,0 +1,203 @@
+use crate::blockchain::Blockchain;
+use crate::crypto::SaitoHash;
+use crate::storage::Storage;
+use std::path::{Path, PathBuf};
+use tracing::{error, info};
+
+//
+// Everything the node knows besides its blocks, i.e. the utxoset, the
+// staking tables, the blockring and the slips in the wallets, is derived
+// from the blocks. A reindex throws that state away and builds it again by
+// replaying the block files, so a node whose wallets or indices went bad
+// can recover without downloading the chain again.
+//
+
+/// What was found while replaying the block files into a new blockchain.
+#[derive(Debug, Default)]
+pub struct Reindex {
+    blocks_replayed: u64,
+    corrupt_block_files: Vec<PathBuf>,
+    blocks_off_longest_chain: Vec<SaitoHash>,
+}
+
+impl Reindex {
+    pub fn new() -> Self {
+        Reindex::default()
+    }
+
+    /// Replays the block files in blocks_dir, oldest block first, into the blockchain, which
+    /// should be new, and then rebuilds its wallets from the replayed chain. The blocks are
+    /// written to the block storage of the blockchain, so it should not be the one in blocks_dir.
+    pub async fn reindex_blocks_dir(
+        blockchain: &mut Blockchain,
+        blocks_dir: &str,
+    ) -> crate::Result<Reindex> {
+        let mut reindex = Reindex::new();
+        //
+        // block filenames start with the block timestamp, so sorting them puts
+        // every block after its parent even if the files were copied around
+        // and their modification times lost
+        //
+        let mut paths = Storage::list_block_files(blocks_dir);
+        paths.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+        for path in paths {
+            reindex.add_block_file(blockchain, &path).await;
+        }
+        reindex.finish(blockchain).await?;
+        Ok(reindex)
+    }
+
+    /// Adds the block in the file to the blockchain, unless the file cannot be read or the
+    /// block in it does not hash to the hash in the filename.
+    pub async fn add_block_file(&mut self, blockchain: &mut Blockchain, path: &Path) -> bool {
+        let bytes = match Storage::read(path.to_str().unwrap()) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                error!("ERROR 290310: cannot read block file {:?}: {:?}", path, err);
+                self.corrupt_block_files.push(path.to_path_buf());
+                return false;
+            }
+        };
+        let mut block = match Storage::deserialize_block_from_disk(&bytes) {
+            Ok(block) => block,
+            Err(err) => {
+                error!("ERROR 290313: cannot read block file {:?}: {}", path, err);
+                self.corrupt_block_files.push(path.to_path_buf());
+                return false;
+            }
+        };
+        block.generate_metadata();
+        if Storage::get_block_hash_from_filename(path) != Some(block.get_hash()) {
+            error!(
+                "ERROR 290311: block in {:?} hashes to {}",
+                path,
+                hex::encode(block.get_hash())
+            );
+            self.corrupt_block_files.push(path.to_path_buf());
+            return false;
+        }
+
+        blockchain.add_block(block).await;
+        self.blocks_replayed += 1;
+        true
+    }
+
+    /// Rebuilds the wallets from the replayed chain and notes the blocks which did not end up
+    /// in the longest chain, i.e. blocks which failed validation or are on a stale fork.
+    pub async fn finish(&mut self, blockchain: &Blockchain) -> crate::Result<()> {
+        self.blocks_off_longest_chain = blockchain
+            .blocks
+            .values()
+            .filter(|block| !block.get_lc())
+            .map(|block| block.get_hash())
+            .collect();
+        blockchain.rescan_wallets(0).await?;
+        info!(
+            "reindexed {} blocks, the longest chain is at block {}",
+            self.blocks_replayed,
+            blockchain.get_latest_block_id()
+        );
+        Ok(())
+    }
+
+    pub fn get_blocks_replayed(&self) -> u64 {
+        self.blocks_replayed
+    }
+
+    /// Block files which could not be read, or whose block does not match the filename.
+    pub fn get_corrupt_block_files(&self) -> &Vec<PathBuf> {
+        &self.corrupt_block_files
+    }
+
+    pub fn get_blocks_off_longest_chain(&self) -> &Vec<SaitoHash> {
+        &self.blocks_off_longest_chain
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utilities::test_manager::TestManager;
+    use crate::wallet::Wallet;
+    use std::fs;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    const REINDEX_TEST_DIR_PATH: &str = "./data/test/reindex/";
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn reindex_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        test_manager.generate_blockchain(5, [0; 32]).await;
+
+        let blockchain = blockchain_lock.read().await;
+        let _ = fs::remove_dir_all(REINDEX_TEST_DIR_PATH);
+        fs::create_dir_all(REINDEX_TEST_DIR_PATH).unwrap();
+        let mut block_filenames = vec![];
+        for block_id in 1..=blockchain.get_latest_block_id() {
+            let block_hash = blockchain
+                .blockring
+                .get_longest_chain_block_hash_by_block_id(block_id);
+            let block = blockchain.get_block_sync(&block_hash).unwrap();
+            let filename = format!(
+                "{}{}",
+                REINDEX_TEST_DIR_PATH,
+                Path::new(&Storage::generate_block_filename(block))
+                    .file_name()
+                    .unwrap()
+                    .to_str()
+                    .unwrap()
+            );
+            fs::write(&filename, Storage::serialize_block_for_disk(block)).unwrap();
+            block_filenames.push(filename);
+        }
+
+        // a new wallet with the same keys finds the same slips
+        let mut reindexed_wallet = Wallet::new();
+        reindexed_wallet.set_privatekey(wallet_lock.read().await.get_privatekey());
+        reindexed_wallet.set_publickey(wallet_lock.read().await.get_publickey());
+        let reindexed_wallet_lock = Arc::new(RwLock::new(reindexed_wallet));
+        let mut reindexed_blockchain = Blockchain::new(reindexed_wallet_lock.clone());
+        let reindex = Reindex::reindex_blocks_dir(&mut reindexed_blockchain, REINDEX_TEST_DIR_PATH)
+            .await
+            .unwrap();
+
+        assert_eq!(reindex.get_blocks_replayed(), 5);
+        assert!(reindex.get_corrupt_block_files().is_empty());
+        assert!(reindex.get_blocks_off_longest_chain().is_empty());
+        assert_eq!(
+            reindexed_blockchain.get_latest_block_hash(),
+            blockchain.get_latest_block_hash()
+        );
+        assert!(wallet_lock.read().await.get_available_balance() > 0);
+        assert_eq!(
+            reindexed_wallet_lock.read().await.get_available_balance(),
+            wallet_lock.read().await.get_available_balance()
+        );
+
+        // a block whose contents do not match its filename is left out
+        let last_filename = block_filenames.pop().unwrap();
+        let corrupt_filename = format!(
+            "{}{}.sai",
+            &last_filename[..last_filename.len() - 68],
+            "00".repeat(32)
+        );
+        fs::rename(&last_filename, &corrupt_filename).unwrap();
+        let mut reindexed_blockchain = Blockchain::new(Arc::new(RwLock::new(Wallet::new())));
+        let reindex = Reindex::reindex_blocks_dir(&mut reindexed_blockchain, REINDEX_TEST_DIR_PATH)
+            .await
+            .unwrap();
+
+        assert_eq!(reindex.get_blocks_replayed(), 4);
+        assert_eq!(
+            reindex.get_corrupt_block_files(),
+            &vec![PathBuf::from(&corrupt_filename)]
+        );
+        assert_eq!(reindexed_blockchain.get_latest_block_id(), 4);
+
+        fs::remove_dir_all(REINDEX_TEST_DIR_PATH).unwrap();
+    }
+}
@@ -0,0 +1,144 @@
+use crate::crypto::SaitoHash;
+use log::error;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fs::OpenOptions;
+use std::io::Write;
+
+//
+// A reorganization replaces the blocks at the tip of the longest chain with
+// those of a fork. Frequent or deep reorganizations point at an unstable
+// network, e.g. a partition or a peer mining on a stale tip, but by the time
+// an operator looks the displaced blocks are gone from the chain. The log
+// keeps the last reorganizations in memory for the RPC, and optionally
+// appends every one of them to a file as a line of JSON.
+//
+
+/// How many reorganizations the log keeps in memory.
+pub const REORG_LOG_LENGTH: usize = 1000;
+
+/// A reorganization of the longest chain. Hashes are hex encoded, the timestamp is when the
+/// new tip was added and the depth is the number of blocks displaced from the longest chain,
+/// listed from the old tip back.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ReorgEvent {
+    pub timestamp: u64,
+    pub old_tip: String,
+    pub old_tip_id: u64,
+    pub new_tip: String,
+    pub new_tip_id: u64,
+    pub depth: u64,
+    pub displaced_block_hashes: Vec<String>,
+}
+
+impl ReorgEvent {
+    pub fn new(
+        timestamp: u64,
+        (old_tip, old_tip_id): (SaitoHash, u64),
+        (new_tip, new_tip_id): (SaitoHash, u64),
+        displaced_block_hashes: &[SaitoHash],
+    ) -> Self {
+        ReorgEvent {
+            timestamp,
+            old_tip: hex::encode(old_tip),
+            old_tip_id,
+            new_tip: hex::encode(new_tip),
+            new_tip_id,
+            depth: displaced_block_hashes.len() as u64,
+            displaced_block_hashes: displaced_block_hashes.iter().map(hex::encode).collect(),
+        }
+    }
+}
+
+/// The last REORG_LOG_LENGTH reorganizations, oldest first.
+#[derive(Debug, Default)]
+pub struct ReorgLog {
+    events: VecDeque<ReorgEvent>,
+    // a file every reorganization is appended to
+    log_file: Option<String>,
+}
+
+impl ReorgLog {
+    pub fn new(log_file: Option<String>) -> Self {
+        ReorgLog {
+            events: VecDeque::new(),
+            log_file,
+        }
+    }
+
+    pub fn record(&mut self, event: ReorgEvent) {
+        if let Some(log_file) = self.log_file.as_ref() {
+            if let Err(err) = append_to_log_file(log_file, &event) {
+                error!(
+                    "ERROR 350101: cannot append reorganization to {}: {:?}",
+                    log_file, err
+                );
+            }
+        }
+        if self.events.len() == REORG_LOG_LENGTH {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
+    pub fn get_events(&self) -> &VecDeque<ReorgEvent> {
+        &self.events
+    }
+}
+
+fn append_to_log_file(log_file: &str, event: &ReorgEvent) -> std::io::Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_file)?;
+    writeln!(file, "{}", serde_json::to_string(event)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    const REORG_LOG_TEST_FILE_PATH: &str = "./data/test/reorgs.log";
+
+    #[test]
+    fn reorg_log_test() {
+        let _ = fs::remove_file(REORG_LOG_TEST_FILE_PATH);
+        fs::create_dir_all("./data/test/").unwrap();
+        let mut reorg_log = ReorgLog::new(Some(REORG_LOG_TEST_FILE_PATH.to_string()));
+
+        for i in 0..REORG_LOG_LENGTH + 2 {
+            reorg_log.record(ReorgEvent::new(
+                i as u64,
+                ([1; 32], 10),
+                ([2; 32], 11),
+                &[[1; 32], [3; 32]],
+            ));
+        }
+
+        // the oldest events are dropped from memory, but not from the file
+        let events = reorg_log.get_events();
+        assert_eq!(events.len(), REORG_LOG_LENGTH);
+        assert_eq!(events.front().unwrap().timestamp, 2);
+        assert_eq!(events.back().unwrap().depth, 2);
+        assert_eq!(
+            events.back().unwrap().displaced_block_hashes,
+            vec![hex::encode([1; 32]), hex::encode([3; 32])]
+        );
+        let log = fs::read_to_string(REORG_LOG_TEST_FILE_PATH).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(lines.len(), REORG_LOG_LENGTH + 2);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(lines[0]).unwrap(),
+            serde_json::to_value(ReorgEvent::new(
+                0,
+                ([1; 32], 10),
+                ([2; 32], 11),
+                &[[1; 32], [3; 32]]
+            ))
+            .unwrap()
+        );
+
+        fs::remove_file(REORG_LOG_TEST_FILE_PATH).unwrap();
+    }
+}
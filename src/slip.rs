@@ -1,7 +1,5 @@
-use crate::{
-    blockchain::UtxoSet,
-    crypto::{SaitoHash, SaitoPublicKey, SaitoUTXOSetKey},
-};
+use crate::amount::Amount;
+use crate::crypto::{ConsensusHasher, SaitoHash, SaitoPublicKey, SaitoUTXOSetKey};
 use ahash::AHashMap;
 use bigint::uint::U256;
 use log::{error, info};
@@ -9,8 +7,10 @@ use macros::TryFromByte;
 use serde::{Deserialize, Serialize};
 use std::convert::{TryFrom, TryInto};
 
-/// The size of a serilized slip in bytes.
-pub const SLIP_SIZE: usize = 75;
+/// The unspent slips, keyed by their utxoset keys.
+pub type UtxoSet = AHashMap<SaitoUTXOSetKey, u64>;
+
+pub use crate::layout::SLIP_SIZE;
 
 /// SlipType is a human-readable indicator of the slip-type, such
 /// as in a normal transaction, a VIP-transaction, a rebroadcast
@@ -29,6 +29,43 @@ pub enum SlipType {
     StakerDeposit,
     StakerWithdrawalPending,
     StakerWithdrawalStaking,
+    // zero-amount output naming the key that collects the payouts of the
+    // StakerDeposit output directly before it
+    StakerDelegation,
+    // the return on a delegated stake, paid to its delegated key
+    StakerPayout,
+}
+
+impl SlipType {
+    /// Whether the slip holds tokens which its owner may spend in an ordinary transaction.
+    /// Staking slips are only spent by fee and staking withdrawal transactions.
+    pub fn is_spendable(&self) -> bool {
+        matches!(
+            self,
+            SlipType::Normal
+                | SlipType::ATR
+                | SlipType::VipOutput
+                | SlipType::MinerOutput
+                | SlipType::RouterOutput
+                | SlipType::StakerPayout
+        )
+    }
+
+    /// The type the slip is kept under in the utxoset. Spending a slip relabels it as the
+    /// transaction spending it requires, so the types between which that is allowed are kept
+    /// under one type: the spendable slips under Normal and the staked slips under
+    /// StakerDeposit. A slip spent under a type it was not created with is then not found
+    /// in the utxoset.
+    pub fn get_utxoset_type(&self) -> SlipType {
+        match self {
+            SlipType::StakerDeposit
+            | SlipType::StakerOutput
+            | SlipType::StakerWithdrawalStaking
+            | SlipType::StakerWithdrawalPending => SlipType::StakerDeposit,
+            slip_type if slip_type.is_spendable() => SlipType::Normal,
+            slip_type => *slip_type,
+        }
+    }
 }
 
 #[serde_with::serde_as]
@@ -37,11 +74,11 @@ pub struct Slip {
     #[serde_as(as = "[_; 33]")]
     publickey: SaitoPublicKey,
     uuid: SaitoHash,
-    amount: u64,
+    amount: Amount,
     payout: u64,
     slip_ordinal: u8,
     slip_type: SlipType,
-    #[serde_as(as = "[_; 74]")]
+    #[serde_as(as = "[_; 75]")]
     utxoset_key: SaitoUTXOSetKey,
     is_utxoset_key_set: bool,
 }
@@ -52,17 +89,17 @@ impl Slip {
         Self {
             publickey: [0; 33],
             uuid: [0; 32],
-            amount: 0,
+            amount: Amount::ZERO,
             payout: 0,
             slip_ordinal: 0,
             slip_type: SlipType::Normal,
-            utxoset_key: [0; 74],
+            utxoset_key: [0; 75],
             is_utxoset_key_set: false,
         }
     }
 
     pub fn validate(&self, utxoset: &UtxoSet) -> bool {
-        if self.get_amount() > 0 {
+        if self.get_amount() > Amount::ZERO {
             match utxoset.get(&self.utxoset_key) {
                 Some(value) => {
                     if *value == 1 {
@@ -119,7 +156,7 @@ impl Slip {
             }
         }
 
-        if self.get_amount() > 0 {
+        if self.get_amount() > Amount::ZERO {
             //
             // TODO cleanup once ready
             //
@@ -147,7 +184,7 @@ impl Slip {
         self.publickey
     }
 
-    pub fn get_amount(&self) -> u64 {
+    pub fn get_amount(&self) -> Amount {
         self.amount
     }
 
@@ -167,11 +204,23 @@ impl Slip {
         self.slip_type
     }
 
+    /// A readable view of the slip for the REST API, with the publickey and uuid hex encoded.
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "publickey": hex::encode(self.publickey),
+            "uuid": hex::encode(self.uuid),
+            "amount": self.amount,
+            "payout": self.payout,
+            "slip_ordinal": self.slip_ordinal,
+            "slip_type": format!("{:?}", self.slip_type),
+        })
+    }
+
     pub fn set_publickey(&mut self, publickey: SaitoPublicKey) {
         self.publickey = publickey;
     }
 
-    pub fn set_amount(&mut self, amount: u64) {
+    pub fn set_amount(&mut self, amount: Amount) {
         self.amount = amount;
     }
 
@@ -195,7 +244,7 @@ impl Slip {
     // runs when block is purged for good or staking slip deleted
     //
     pub fn delete(&self, utxoset: &mut AHashMap<SaitoUTXOSetKey, u64>) -> bool {
-        if self.get_utxoset_key() == [0; 74] {
+        if self.get_utxoset_key() == [0; 75] {
             error!("ERROR 572034: asked to remove a slip without its utxoset_key properly set!");
             false;
         }
@@ -250,7 +299,7 @@ impl Slip {
         let mut vbytes: Vec<u8> = vec![];
         vbytes.extend(&self.publickey);
         vbytes.extend(&self.uuid);
-        vbytes.extend(&self.amount.to_be_bytes());
+        vbytes.extend(&self.amount.as_nolan().to_be_bytes());
         vbytes.extend(&(self.slip_ordinal.to_be_bytes()));
         vbytes.extend(&(self.slip_type as u32).to_be_bytes());
         vbytes
@@ -272,12 +321,30 @@ impl Slip {
         let mut vbytes: Vec<u8> = vec![];
         vbytes.extend(&self.publickey);
         vbytes.extend(&[0; 32]);
-        vbytes.extend(&self.amount.to_be_bytes());
+        vbytes.extend(&self.amount.as_nolan().to_be_bytes());
         vbytes.extend(&(self.slip_ordinal.to_be_bytes()));
         vbytes.extend(&(self.slip_type as u32).to_be_bytes());
         vbytes
     }
 
+    /// Writes serialize_input_for_signature() into the hasher without allocating.
+    pub fn hash_input_for_signature_into(&self, hasher: &mut ConsensusHasher) {
+        hasher.update(&self.publickey);
+        hasher.update(&self.uuid);
+        hasher.update(&self.amount.as_nolan().to_be_bytes());
+        hasher.update(&self.slip_ordinal.to_be_bytes());
+        hasher.update(&(self.slip_type as u32).to_be_bytes());
+    }
+
+    /// Writes serialize_output_for_signature() into the hasher without allocating.
+    pub fn hash_output_for_signature_into(&self, hasher: &mut ConsensusHasher) {
+        hasher.update(&self.publickey);
+        hasher.update(&[0; 32]);
+        hasher.update(&self.amount.as_nolan().to_be_bytes());
+        hasher.update(&self.slip_ordinal.to_be_bytes());
+        hasher.update(&(self.slip_type as u32).to_be_bytes());
+    }
+
     pub fn generate_utxoset_key(&mut self) {
         self.utxoset_key = self.get_utxoset_key();
         self.is_utxoset_key_set = true;
@@ -287,14 +354,16 @@ impl Slip {
     // 32 bytes uuid
     // 8 bytes amount
     // 1 byte slip_ordinal
+    // 1 byte slip type, see SlipType::get_utxoset_type
     pub fn get_utxoset_key(&self) -> SaitoUTXOSetKey {
         let mut res: Vec<u8> = vec![];
         res.extend(&self.get_publickey());
         res.extend(&self.get_uuid());
-        res.extend(&self.get_amount().to_be_bytes());
+        res.extend(&self.get_amount().as_nolan().to_be_bytes());
         res.extend(&self.get_slip_ordinal().to_be_bytes());
+        res.push(self.get_slip_type().get_utxoset_type() as u8);
 
-        res[0..74].try_into().unwrap()
+        res[0..75].try_into().unwrap()
 
         //        res
     }
@@ -302,7 +371,7 @@ impl Slip {
     pub fn deserialize_from_net(bytes: Vec<u8>) -> Slip {
         let publickey: SaitoPublicKey = bytes[..33].try_into().unwrap();
         let uuid: SaitoHash = bytes[33..65].try_into().unwrap();
-        let amount: u64 = u64::from_be_bytes(bytes[65..73].try_into().unwrap());
+        let amount = Amount::from_nolan(u64::from_be_bytes(bytes[65..73].try_into().unwrap()));
         let slip_ordinal: u8 = bytes[73];
         let slip_type: SlipType = SlipType::try_from(bytes[SLIP_SIZE - 1]).unwrap();
         let mut slip = Slip::new();
@@ -319,11 +388,49 @@ impl Slip {
         let mut vbytes: Vec<u8> = vec![];
         vbytes.extend(&self.publickey);
         vbytes.extend(&self.uuid);
-        vbytes.extend(&self.amount.to_be_bytes());
+        vbytes.extend(&self.amount.as_nolan().to_be_bytes());
         vbytes.extend(&self.slip_ordinal.to_be_bytes());
         vbytes.extend(&(self.slip_type as u8).to_be_bytes());
         vbytes
     }
+
+    /// The slip a utxoset key was made from. The key holds only the type the slip is kept
+    /// under, so the slip has that type.
+    pub fn from_utxoset_key(utxoset_key: &SaitoUTXOSetKey) -> Slip {
+        let mut slip = Slip::new();
+        slip.set_publickey(utxoset_key[..33].try_into().unwrap());
+        slip.set_uuid(utxoset_key[33..65].try_into().unwrap());
+        slip.set_amount(Amount::from_nolan(u64::from_be_bytes(
+            utxoset_key[65..73].try_into().unwrap(),
+        )));
+        slip.set_slip_ordinal(utxoset_key[73]);
+        slip.set_slip_type(SlipType::try_from(utxoset_key[74]).unwrap());
+        slip.generate_utxoset_key();
+        slip
+    }
+}
+
+/// Iterates over the unspent slips in the utxoset, or only over those of publickey.
+pub fn iter_unspent_slips(
+    utxoset: &UtxoSet,
+    publickey: Option<SaitoPublicKey>,
+) -> impl Iterator<Item = Slip> + '_ {
+    utxoset
+        .iter()
+        .filter(|(_, value)| **value == 1)
+        .filter(move |(utxoset_key, _)| match publickey {
+            Some(publickey) => utxoset_key[..33] == publickey,
+            None => true,
+        })
+        .map(|(utxoset_key, _)| Slip::from_utxoset_key(utxoset_key))
+}
+
+/// The sum of the unspent slips of publickey.
+pub fn get_balance(utxoset: &UtxoSet, publickey: SaitoPublicKey) -> u64 {
+    iter_unspent_slips(utxoset, Some(publickey))
+        .map(|slip| slip.get_amount())
+        .sum::<Amount>()
+        .into()
 }
 
 #[cfg(test)]
@@ -339,15 +446,15 @@ mod tests {
         let mut slip = Slip::new();
         assert_eq!(slip.get_publickey(), [0; 33]);
         assert_eq!(slip.get_uuid(), [0; 32]);
-        assert_eq!(slip.get_amount(), 0);
+        assert_eq!(slip.get_amount(), Amount::from_nolan(0));
         assert_eq!(slip.get_slip_type(), SlipType::Normal);
         assert_eq!(slip.get_slip_ordinal(), 0);
 
         slip.set_publickey([1; 33]);
         assert_eq!(slip.get_publickey(), [1; 33]);
 
-        slip.set_amount(100);
-        assert_eq!(slip.get_amount(), 100);
+        slip.set_amount(Amount::from_nolan(100));
+        assert_eq!(slip.get_amount(), Amount::from_nolan(100));
 
         slip.set_uuid([30; 32]);
         assert_eq!(slip.get_uuid(), [30; 32]);
@@ -359,6 +466,19 @@ mod tests {
         assert_eq!(slip.get_slip_type(), SlipType::MinerInput);
     }
 
+    #[test]
+    fn slip_utxoset_type_test() {
+        let mut slip = Slip::new();
+        slip.set_slip_type(SlipType::StakerDeposit);
+        let staked_utxoset_key = slip.get_utxoset_key();
+        slip.set_slip_type(SlipType::StakerWithdrawalStaking);
+        assert_eq!(slip.get_utxoset_key(), staked_utxoset_key);
+        slip.set_slip_type(SlipType::Normal);
+        assert_ne!(slip.get_utxoset_key(), staked_utxoset_key);
+        slip.set_slip_type(SlipType::MinerOutput);
+        assert_eq!(slip.get_utxoset_key(), Slip::new().get_utxoset_key());
+    }
+
     #[test]
     fn slip_serialize_for_signature_test() {
         let slip = Slip::new();
@@ -368,7 +488,7 @@ mod tests {
     #[test]
     fn slip_get_utxoset_key_test() {
         let slip = Slip::new();
-        assert_eq!(slip.get_utxoset_key(), [0; 74]);
+        assert_eq!(slip.get_utxoset_key(), [0; 75]);
     }
     #[test]
     fn slip_serialization_for_net_test() {
@@ -378,6 +498,37 @@ mod tests {
         let deserilialized_slip = Slip::deserialize_from_net(serialized_slip);
         assert_eq!(slip, deserilialized_slip);
     }
+    #[test]
+    fn utxoset_balance_test() {
+        let mut utxoset = UtxoSet::default();
+        let mut slips = vec![];
+        // the last slip is spent
+        for (publickey, amount, value) in [
+            ([1; 33], 100, 1),
+            ([1; 33], 50, 1),
+            ([2; 33], 70, 1),
+            ([1; 33], 30, 0),
+        ] {
+            let mut slip = Slip::new();
+            slip.set_publickey(publickey);
+            slip.set_uuid([amount as u8; 32]);
+            slip.set_amount(Amount::from_nolan(amount));
+            slip.generate_utxoset_key();
+            utxoset.insert(slip.get_utxoset_key(), value);
+            slips.push(slip);
+        }
+
+        assert_eq!(
+            Slip::from_utxoset_key(&slips[0].get_utxoset_key()),
+            slips[0]
+        );
+        assert_eq!(iter_unspent_slips(&utxoset, None).count(), 3);
+        assert_eq!(iter_unspent_slips(&utxoset, Some([1; 33])).count(), 2);
+        assert_eq!(get_balance(&utxoset, [1; 33]), 150);
+        assert_eq!(get_balance(&utxoset, [2; 33]), 70);
+        assert_eq!(get_balance(&utxoset, [3; 33]), 0);
+    }
+
     #[tokio::test]
     #[serial_test::serial]
     async fn slip_addition_and_removal_from_utxoset() {
@@ -386,7 +537,7 @@ mod tests {
         let mut blockchain = blockchain_lock.write().await;
         let wallet = wallet_lock.write().await;
         let mut slip = Slip::new();
-        slip.set_amount(100_000);
+        slip.set_amount(Amount::from_nolan(100_000));
         slip.set_uuid([1; 32]);
         slip.set_publickey(wallet.get_publickey());
         slip.generate_utxoset_key();
@@ -4,13 +4,16 @@
 // - insert needs to place into a specified position, probabaly ordered by publickey and then UUID
 //
 use crate::{
+    amount::Amount,
     block::Block,
-    blockchain::GENESIS_PERIOD,
-    crypto::{hash, SaitoHash},
+    blockchain::{GENESIS_PERIOD, PRUNE_AFTER_BLOCKS},
+    crypto::{SaitoHash, SaitoPublicKey},
     golden_ticket::GoldenTicket,
+    random::HashChain,
     slip::{Slip, SlipType},
-    transaction::TransactionType,
+    transaction::{Transaction, TransactionType},
 };
+use ahash::AHashMap;
 use bigint::uint::U256;
 use log::{info, trace};
 
@@ -22,6 +25,13 @@ pub struct Staking {
     pub stakers: Vec<Slip>,
     // waiting for reset of staking table
     pub pending: Vec<Slip>,
+    // staker publickeys and the keys collecting their payouts, in the order
+    // the delegations were confirmed
+    pub delegations: Vec<(SaitoPublicKey, SaitoPublicKey)>,
+    // delegations dropped when the last stake of their staker key was withdrawn,
+    // with the id of the block withdrawing it, so that they are restored if that
+    // block is unwound
+    pub withdrawn_delegations: Vec<(u64, SaitoPublicKey, SaitoPublicKey)>,
 }
 
 impl Staking {
@@ -30,6 +40,8 @@ impl Staking {
             deposits: vec![],
             stakers: vec![],
             pending: vec![],
+            delegations: vec![],
+            withdrawn_delegations: vec![],
         }
     }
 
@@ -109,7 +121,7 @@ impl Staking {
         //
         let mut total_staked: u64 = 0;
         for i in 0..self.stakers.len() {
-            total_staked += self.stakers[i].get_amount();
+            total_staked += u64::from(self.stakers[i].get_amount());
         }
         let average_staked = total_staked / self.stakers.len() as u64;
 
@@ -129,7 +141,7 @@ impl Staking {
             //
             // get the total staked
             //
-            let my_staked_amount = self.stakers[i].get_amount();
+            let my_staked_amount = self.stakers[i].get_amount().as_nolan();
 
             //
             // figure how much we are due...
@@ -183,6 +195,90 @@ impl Staking {
         false
     }
 
+    //
+    // cold staking
+    //
+    // a StakerDeposit transaction may follow its StakerDeposit output with a
+    // StakerDelegation output naming another key. the stake stays with the
+    // key which deposited it, so only that key can withdraw it, while the
+    // return on the stake is paid to the delegated key as a StakerPayout.
+    // a delegation covers every stake of the staker key, so a key which has
+    // delegated may not delegate to a different key until it is unwound.
+    //
+    pub fn get_delegate(&self, staker: &SaitoPublicKey) -> Option<SaitoPublicKey> {
+        self.delegations
+            .iter()
+            .rev()
+            .find(|(publickey, _)| publickey == staker)
+            .map(|(_, delegate)| *delegate)
+    }
+
+    pub fn validate_delegation(&self, deposit: &Slip, delegation: &Slip) -> bool {
+        if deposit.get_slip_type() != SlipType::StakerDeposit
+            || delegation.get_slip_type() != SlipType::StakerDelegation
+        {
+            return false;
+        }
+        if delegation.get_amount() != Amount::ZERO
+            || delegation.get_publickey() == [0; 33]
+            || delegation.get_publickey() == deposit.get_publickey()
+        {
+            return false;
+        }
+        match self.get_delegate(&deposit.get_publickey()) {
+            Some(delegate) => delegate == delegation.get_publickey(),
+            None => true,
+        }
+    }
+
+    pub fn add_delegation(&mut self, staker: SaitoPublicKey, delegate: SaitoPublicKey) {
+        self.delegations.push((staker, delegate));
+    }
+
+    //
+    // the delegations of one block are validated against those confirmed
+    // before it, so a block may not delegate a staker key to two keys either.
+    // a transaction is only added to the delegations of its block if it
+    // agrees with them.
+    //
+    pub fn add_block_delegations(
+        block_delegations: &mut AHashMap<SaitoPublicKey, SaitoPublicKey>,
+        transaction: &Transaction,
+    ) -> bool {
+        let delegations = transaction.get_delegations();
+        for (i, (staker, delegate)) in delegations.iter().enumerate() {
+            if block_delegations
+                .get(staker)
+                .into_iter()
+                .chain(
+                    delegations[..i]
+                        .iter()
+                        .filter(|(publickey, _)| publickey == staker)
+                        .map(|(_, other_delegate)| other_delegate),
+                )
+                .any(|other_delegate| other_delegate != delegate)
+            {
+                return false;
+            }
+        }
+        block_delegations.extend(delegations);
+        true
+    }
+
+    pub fn remove_delegation(&mut self, staker: SaitoPublicKey, delegate: SaitoPublicKey) -> bool {
+        match self
+            .delegations
+            .iter()
+            .rposition(|delegation| *delegation == (staker, delegate))
+        {
+            Some(i) => {
+                self.delegations.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
     pub fn add_deposit(&mut self, slip: Slip) {
         self.deposits.push(slip);
     }
@@ -230,6 +326,50 @@ impl Staking {
         self.pending.push(slip);
     }
 
+    // whether the key has a stake in any of the tables
+    pub fn has_stake(&self, publickey: &SaitoPublicKey) -> bool {
+        self.deposits
+            .iter()
+            .chain(self.stakers.iter())
+            .chain(self.pending.iter())
+            .any(|slip| slip.get_publickey() == *publickey)
+    }
+
+    //
+    // a delegation covers the stakes of its staker key, so it ends with the
+    // withdrawal of the last of them and the key may then delegate anew
+    //
+    fn withdraw_delegations(&mut self, block_id: u64, staker: SaitoPublicKey) {
+        if self.has_stake(&staker) {
+            return;
+        }
+        let delegations = std::mem::take(&mut self.delegations);
+        for (publickey, delegate) in delegations {
+            if publickey == staker {
+                self.withdrawn_delegations
+                    .push((block_id, publickey, delegate));
+            } else {
+                self.delegations.push((publickey, delegate));
+            }
+        }
+        self.withdrawn_delegations
+            .retain(|(withdrawn_block_id, _, _)| {
+                withdrawn_block_id + PRUNE_AFTER_BLOCKS >= block_id
+            });
+    }
+
+    fn restore_withdrawn_delegations(&mut self, block_id: u64, staker: SaitoPublicKey) {
+        let withdrawn_delegations = std::mem::take(&mut self.withdrawn_delegations);
+        for (withdrawn_block_id, publickey, delegate) in withdrawn_delegations {
+            if withdrawn_block_id == block_id && publickey == staker {
+                self.delegations.push((publickey, delegate));
+            } else {
+                self.withdrawn_delegations
+                    .push((withdrawn_block_id, publickey, delegate));
+            }
+        }
+    }
+
     pub fn remove_deposit(&mut self, slip: Slip) -> bool {
         for i in 0..self.deposits.len() {
             if slip.get_utxoset_key() == self.deposits[i].get_utxoset_key() {
@@ -296,6 +436,7 @@ impl Staking {
                     if tx.inputs[0].get_slip_type() == SlipType::StakerWithdrawalStaking {
                         self.remove_staker(tx.inputs[0].clone());
                     }
+                    self.withdraw_delegations(block.get_id(), tx.inputs[0].get_publickey());
                 //
                 // roll backward
                 //
@@ -306,6 +447,10 @@ impl Staking {
                     if tx.inputs[0].get_slip_type() == SlipType::StakerWithdrawalStaking {
                         self.add_staker(tx.inputs[0].clone());
                     }
+                    self.restore_withdrawn_delegations(
+                        block.get_id(),
+                        tx.inputs[0].get_publickey(),
+                    );
                 }
             }
 
@@ -324,6 +469,15 @@ impl Staking {
                             self.remove_deposit(tx.outputs[i].clone());
                         }
                     }
+                    if tx.outputs[i].get_slip_type() == SlipType::StakerDelegation && i > 0 {
+                        let staker = tx.outputs[i - 1].get_publickey();
+                        let delegate = tx.outputs[i].get_publickey();
+                        if longest_chain {
+                            self.add_delegation(staker, delegate);
+                        } else {
+                            self.remove_delegation(staker, delegate);
+                        }
+                    }
                 }
             }
         }
@@ -376,9 +530,8 @@ impl Staking {
             );
 
             // pick router and burn one
-            let mut next_random_number = hash(&golden_ticket.get_random().to_vec());
-            next_random_number = hash(&next_random_number.to_vec());
-            next_random_number = hash(&next_random_number.to_vec());
+            let mut random_numbers = HashChain::new(golden_ticket.get_random());
+            random_numbers.advance(2);
 
             let mut is_there_a_staker_output = false;
 
@@ -430,13 +583,13 @@ impl Staking {
                     }
                     if staker_output.get_slip_type() == SlipType::StakerOutput {
                         // ROUTER BURNED FIRST
-                        next_random_number = hash(&next_random_number.to_vec()); // router + burn
-                        next_random_number = hash(&next_random_number.to_vec()); // burn second
+                        random_numbers.advance(2); // router + burn
 
                         //
                         // move staker to pending
                         //
-                        let lucky_staker_option = self.find_winning_staker(next_random_number); // use first
+                        let lucky_staker_option =
+                            self.find_winning_staker(random_numbers.get_random_number()); // use first
 
                         if let Some(lucky_staker) = lucky_staker_option {
                             info!("the lucky staker is: {:?}", lucky_staker);
@@ -451,7 +604,7 @@ impl Staking {
                         staker_slip_num += 1;
 
                         // setup for router selection next loop
-                        next_random_number = hash(&next_random_number.to_vec());
+                        random_numbers.advance(1);
                     }
                 }
 
@@ -504,7 +657,7 @@ impl Staking {
                 let mut staker_slip_num = 0;
                 for i in 0..fee_transaction.outputs.len() {
                     let staker_output = fee_transaction.outputs[i].clone();
-                    if fee_transaction.inputs.len() < staker_slip_num {
+                    if fee_transaction.inputs.len() <= staker_slip_num {
                         break;
                     }
                     let staker_input = fee_transaction.inputs[staker_slip_num].clone();
@@ -565,6 +718,124 @@ mod tests {
     use std::sync::Arc;
     use tokio::sync::RwLock;
 
+    #[test]
+    fn staking_validate_delegation_test() {
+        let mut staking = Staking::new();
+
+        let mut deposit = Slip::new();
+        deposit.set_publickey([1; 33]);
+        deposit.set_amount(Amount::from_nolan(100));
+        deposit.set_slip_type(SlipType::StakerDeposit);
+
+        let mut delegation = Slip::new();
+        delegation.set_publickey([2; 33]);
+        delegation.set_slip_type(SlipType::StakerDelegation);
+
+        assert_eq!(staking.get_delegate(&[1; 33]), None);
+        assert!(staking.validate_delegation(&deposit, &delegation));
+
+        // delegations hold no tokens and name another key
+        let mut funded_delegation = delegation.clone();
+        funded_delegation.set_amount(Amount::from_nolan(1));
+        assert!(!staking.validate_delegation(&deposit, &funded_delegation));
+        let mut self_delegation = delegation.clone();
+        self_delegation.set_publickey([1; 33]);
+        assert!(!staking.validate_delegation(&deposit, &self_delegation));
+
+        // a staker key is delegated to one key at a time
+        staking.add_delegation([1; 33], [2; 33]);
+        assert_eq!(staking.get_delegate(&[1; 33]), Some([2; 33]));
+        assert!(staking.validate_delegation(&deposit, &delegation));
+        let mut other_delegation = delegation.clone();
+        other_delegation.set_publickey([3; 33]);
+        assert!(!staking.validate_delegation(&deposit, &other_delegation));
+
+        assert!(staking.remove_delegation([1; 33], [2; 33]));
+        assert_eq!(staking.get_delegate(&[1; 33]), None);
+        assert!(staking.validate_delegation(&deposit, &other_delegation));
+    }
+
+    fn delegated_deposit_transaction(delegations: &[(u8, u8)]) -> Transaction {
+        let mut transaction = Transaction::new();
+        transaction.set_transaction_type(TransactionType::StakerDeposit);
+        for (staker, delegate) in delegations {
+            let mut deposit = Slip::new();
+            deposit.set_publickey([*staker; 33]);
+            deposit.set_slip_type(SlipType::StakerDeposit);
+            transaction.add_output(deposit);
+            let mut delegation = Slip::new();
+            delegation.set_publickey([*delegate; 33]);
+            delegation.set_slip_type(SlipType::StakerDelegation);
+            transaction.add_output(delegation);
+        }
+        transaction
+    }
+
+    #[test]
+    fn staking_block_delegations_test() {
+        let mut block_delegations = AHashMap::new();
+        assert!(Staking::add_block_delegations(
+            &mut block_delegations,
+            &delegated_deposit_transaction(&[(1, 2)])
+        ));
+        assert!(Staking::add_block_delegations(
+            &mut block_delegations,
+            &delegated_deposit_transaction(&[(1, 2), (3, 4)])
+        ));
+
+        // a block may not delegate a key to two keys, in one transaction or in two
+        assert!(!Staking::add_block_delegations(
+            &mut block_delegations,
+            &delegated_deposit_transaction(&[(1, 3)])
+        ));
+        assert!(!Staking::add_block_delegations(
+            &mut block_delegations,
+            &delegated_deposit_transaction(&[(5, 6), (5, 7)])
+        ));
+        assert_eq!(block_delegations.get(&[1; 33]), Some(&[2; 33]));
+        assert_eq!(block_delegations.get(&[5; 33]), None);
+    }
+
+    #[test]
+    fn staking_withdrawal_removes_delegation_test() {
+        let mut staking = Staking::new();
+        let mut stakes = vec![];
+        for i in 0..2 {
+            let mut stake = Slip::new();
+            stake.set_publickey([1; 33]);
+            stake.set_amount(Amount::from_nolan(100));
+            stake.set_uuid([i; 32]);
+            stake.set_slip_type(SlipType::StakerDeposit);
+            staking.add_staker(stake.clone());
+            stakes.push(stake);
+        }
+        staking.add_delegation([1; 33], [2; 33]);
+
+        let withdrawal_block = |stake: &Slip, block_id: u64| {
+            let mut input = stake.clone();
+            input.set_slip_type(SlipType::StakerWithdrawalStaking);
+            let mut transaction = Transaction::new();
+            transaction.set_transaction_type(TransactionType::StakerWithdrawal);
+            transaction.add_input(input);
+            let mut block = Block::new();
+            block.set_id(block_id);
+            block.set_transactions(&mut vec![transaction]);
+            block
+        };
+
+        // the delegation covers the stake which is left
+        staking.on_chain_reorganization(&withdrawal_block(&stakes[0], 2), true);
+        assert_eq!(staking.get_delegate(&[1; 33]), Some([2; 33]));
+
+        // and ends with the last one, until the withdrawal is unwound
+        let block = withdrawal_block(&stakes[1], 3);
+        staking.on_chain_reorganization(&block, true);
+        assert_eq!(staking.get_delegate(&[1; 33]), None);
+        staking.on_chain_reorganization(&block, false);
+        assert_eq!(staking.get_delegate(&[1; 33]), Some([2; 33]));
+        assert!(staking.withdrawn_delegations.is_empty());
+    }
+
     //
     // does adding staking slips in different orders give us the same
     // results?
@@ -575,23 +846,23 @@ mod tests {
         let mut staking2 = Staking::new();
 
         let mut slip1 = Slip::new();
-        slip1.set_amount(1);
+        slip1.set_amount(Amount::from_nolan(1));
         slip1.set_slip_type(SlipType::StakerDeposit);
 
         let mut slip2 = Slip::new();
-        slip2.set_amount(2);
+        slip2.set_amount(Amount::from_nolan(2));
         slip2.set_slip_type(SlipType::StakerDeposit);
 
         let mut slip3 = Slip::new();
-        slip3.set_amount(3);
+        slip3.set_amount(Amount::from_nolan(3));
         slip3.set_slip_type(SlipType::StakerDeposit);
 
         let mut slip4 = Slip::new();
-        slip4.set_amount(4);
+        slip4.set_amount(Amount::from_nolan(4));
         slip4.set_slip_type(SlipType::StakerDeposit);
 
         let mut slip5 = Slip::new();
-        slip5.set_amount(5);
+        slip5.set_amount(Amount::from_nolan(5));
         slip5.set_slip_type(SlipType::StakerDeposit);
 
         staking1.add_staker(slip1.clone());
@@ -646,23 +917,23 @@ mod tests {
         let mut staking = Staking::new();
 
         let mut slip1 = Slip::new();
-        slip1.set_amount(200_000_000);
+        slip1.set_amount(Amount::from_nolan(200_000_000));
         slip1.set_slip_type(SlipType::StakerDeposit);
 
         let mut slip2 = Slip::new();
-        slip2.set_amount(300_000_000);
+        slip2.set_amount(Amount::from_nolan(300_000_000));
         slip2.set_slip_type(SlipType::StakerDeposit);
 
         let mut slip3 = Slip::new();
-        slip3.set_amount(400_000_000);
+        slip3.set_amount(Amount::from_nolan(400_000_000));
         slip3.set_slip_type(SlipType::StakerDeposit);
 
         let mut slip4 = Slip::new();
-        slip4.set_amount(500_000_000);
+        slip4.set_amount(Amount::from_nolan(500_000_000));
         slip4.set_slip_type(SlipType::StakerDeposit);
 
         let mut slip5 = Slip::new();
-        slip5.set_amount(600_000_000);
+        slip5.set_amount(Amount::from_nolan(600_000_000));
         slip5.set_slip_type(SlipType::StakerDeposit);
 
         staking.add_deposit(slip1);
@@ -674,23 +945,23 @@ mod tests {
         let (_res_spend, _res_unspend, _res_delete) = staking.reset_staker_table(1_000_000_000); // 10 Saito
 
         assert_eq!(
-            staking.stakers[4].get_amount() + staking.stakers[4].get_payout(),
+            u64::from(staking.stakers[4].get_amount()) + staking.stakers[4].get_payout(),
             210000000
         );
         assert_eq!(
-            staking.stakers[3].get_amount() + staking.stakers[3].get_payout(),
+            u64::from(staking.stakers[3].get_amount()) + staking.stakers[3].get_payout(),
             315000000
         );
         assert_eq!(
-            staking.stakers[2].get_amount() + staking.stakers[2].get_payout(),
+            u64::from(staking.stakers[2].get_amount()) + staking.stakers[2].get_payout(),
             420000000
         );
         assert_eq!(
-            staking.stakers[1].get_amount() + staking.stakers[1].get_payout(),
+            u64::from(staking.stakers[1].get_amount()) + staking.stakers[1].get_payout(),
             525000000
         );
         assert_eq!(
-            staking.stakers[0].get_amount() + staking.stakers[0].get_payout(),
+            u64::from(staking.stakers[0].get_amount()) + staking.stakers[0].get_payout(),
             630000000
         );
     }
@@ -705,23 +976,23 @@ mod tests {
         let mut staking = Staking::new();
 
         let mut slip1 = Slip::new();
-        slip1.set_amount(200_000_000);
+        slip1.set_amount(Amount::from_nolan(200_000_000));
         slip1.set_slip_type(SlipType::StakerDeposit);
 
         let mut slip2 = Slip::new();
-        slip2.set_amount(300_000_000);
+        slip2.set_amount(Amount::from_nolan(300_000_000));
         slip2.set_slip_type(SlipType::StakerDeposit);
 
         let mut slip3 = Slip::new();
-        slip3.set_amount(400_000_000);
+        slip3.set_amount(Amount::from_nolan(400_000_000));
         slip3.set_slip_type(SlipType::StakerDeposit);
 
         let mut slip4 = Slip::new();
-        slip4.set_amount(500_000_000);
+        slip4.set_amount(Amount::from_nolan(500_000_000));
         slip4.set_slip_type(SlipType::StakerDeposit);
 
         let mut slip5 = Slip::new();
-        slip5.set_amount(600_000_000);
+        slip5.set_amount(Amount::from_nolan(600_000_000));
         slip5.set_slip_type(SlipType::StakerDeposit);
 
         staking.add_deposit(slip1.clone());
@@ -762,11 +1033,11 @@ mod tests {
             let publickey = wallet.get_publickey();
 
             let mut slip1 = Slip::new();
-            slip1.set_amount(200_000_000);
+            slip1.set_amount(Amount::from_nolan(200_000_000));
             slip1.set_slip_type(SlipType::StakerDeposit);
 
             let mut slip2 = Slip::new();
-            slip2.set_amount(300_000_000);
+            slip2.set_amount(Amount::from_nolan(300_000_000));
             slip2.set_slip_type(SlipType::StakerDeposit);
 
             slip1.set_publickey(publickey);
@@ -962,15 +1233,15 @@ mod tests {
             let publickey = wallet.get_publickey();
 
             let mut slip1 = Slip::new();
-            slip1.set_amount(200_000_000);
+            slip1.set_amount(Amount::from_nolan(200_000_000));
             slip1.set_slip_type(SlipType::StakerDeposit);
 
             let mut slip2 = Slip::new();
-            slip2.set_amount(300_000_000);
+            slip2.set_amount(Amount::from_nolan(300_000_000));
             slip2.set_slip_type(SlipType::StakerDeposit);
 
             let mut slip3 = Slip::new();
-            slip3.set_amount(400_000_000);
+            slip3.set_amount(Amount::from_nolan(400_000_000));
             slip3.set_slip_type(SlipType::StakerDeposit);
 
             slip1.set_publickey(publickey);
@@ -1132,7 +1403,8 @@ mod tests {
             blockchain_lock.clone(),
             current_timestamp + 120000,
         )
-        .await;
+        .await
+        .unwrap();
         block2.generate_metadata();
         let block2_hash = block2.get_hash();
         Blockchain::add_block_to_blockchain(blockchain_lock.clone(), block2).await;
@@ -1215,7 +1487,8 @@ mod tests {
             blockchain_lock.clone(),
             current_timestamp + 600000,
         )
-        .await;
+        .await
+        .unwrap();
         block6.generate_metadata();
         let block6_id = block6.get_id();
         Blockchain::add_block_to_blockchain(blockchain_lock.clone(), block6).await;
@@ -1,11 +1,16 @@
+use crate::amount::Amount;
 use crate::blockchain::MAX_TOKEN_SUPPLY;
-use crate::crypto::SaitoPublicKey;
+use crate::crypto::{hash, SaitoHash, SaitoPublicKey};
 use crate::slip::{Slip, SlipType};
+use log::{error, warn};
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::fmt::Debug;
 use std::{
     fs::{self, File},
     io::{self, BufRead, Read, Write},
-    path::Path,
-    sync::Arc,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
 };
 
 use tokio::sync::RwLock;
@@ -23,6 +28,17 @@ pub const ISSUANCE_FILE_PATH: &'static str = "./data/issuance/issuance";
 pub const EARLYBIRDS_FILE_PATH: &'static str = "./data/issuance/earlybirds";
 pub const DEFAULT_FILE_PATH: &'static str = "./data/issuance/default";
 
+/// Block files start with these bytes, the version of the block file format and the hash of
+/// the rest of the file, followed by the block serialized for the network, so that a block
+/// read from disk is byte for byte the block as it was received.
+pub const BLOCK_FILE_MAGIC: [u8; 4] = *b"SAIB";
+/// The version of the block file format. Bump it with any change to the layout of a block
+/// serialized for the network, so that older block files are refused rather than misread.
+pub const BLOCK_FILE_VERSION: u32 = 3;
+pub const BLOCK_FILE_HEADER_SIZE: usize = 40;
+/// The directory in a blocks directory which corrupt block files are moved to.
+pub const QUARANTINE_DIR_NAME: &str = "quarantine";
+
 pub struct StorageConfigurer {}
 
 pub fn configure_storage() -> String {
@@ -33,6 +49,217 @@ pub fn configure_storage() -> String {
     }
 }
 
+/// Where the blockchain keeps its blocks. Blocks are written once they are added to the
+/// blockchain, read back when the transactions of a pruned block are needed again, and
+/// deleted when they are purged. A block is addressed by its timestamp and hash, which is
+/// how block files are named.
+pub trait BlockStorage: Debug + Send + Sync {
+    fn write_block(&self, block: &Block) -> crate::Result<()>;
+    fn read_block(&self, timestamp: u64, block_hash: &SaitoHash) -> crate::Result<Block>;
+    fn delete_block(&self, timestamp: u64, block_hash: &SaitoHash) -> crate::Result<()>;
+    /// every stored block, in the order they are loaded in when the node starts. Blocks which
+    /// cannot be read are taken out of the storage.
+    fn iterate(&self) -> Box<dyn Iterator<Item = Result<Block, CorruptBlock>> + '_>;
+}
+
+/// A stored block which could not be read, or which does not hash to the hash it was stored
+/// under. The block should be fetched again from our peers, if we know its hash.
+#[derive(Debug)]
+pub struct CorruptBlock {
+    pub block_hash: Option<SaitoHash>,
+    pub error: crate::Error,
+}
+
+/// Reads the block in the contents of a block file stored under the block hash, failing if
+/// the block does not hash to it.
+fn deserialize_stored_block(bytes: &[u8], block_hash: Option<SaitoHash>) -> crate::Result<Block> {
+    let mut block = Storage::deserialize_block_from_disk(bytes)?;
+    block.generate_hashes();
+    if block_hash != Some(block.get_hash()) {
+        return Err(format!(
+            "block is stored as {:?} but hashes to {}",
+            block_hash.map(hex::encode),
+            hex::encode(block.get_hash())
+        )
+        .into());
+    }
+    Ok(block)
+}
+
+/// The block storage of a new blockchain: the block files in BLOCKS_DIR_PATH, or memory in
+/// unit tests so that they do not write into the data directory.
+pub fn default_block_storage() -> Arc<dyn BlockStorage> {
+    if cfg!(test) {
+        Arc::new(MemoryBlockStorage::new())
+    } else {
+        Arc::new(FileBlockStorage::new(&BLOCKS_DIR_PATH))
+    }
+}
+
+/// Blocks kept as block files in a directory, see Storage::serialize_block_for_disk. Corrupt
+/// block files are moved to the QUARANTINE_DIR_NAME directory in it, where they can be looked
+/// at but are not loaded again.
+#[derive(Debug, Clone)]
+pub struct FileBlockStorage {
+    blocks_dir: String,
+}
+
+impl FileBlockStorage {
+    pub fn new(blocks_dir: &str) -> Self {
+        FileBlockStorage {
+            blocks_dir: blocks_dir.to_string(),
+        }
+    }
+
+    pub fn get_block_filename(&self, timestamp: u64, block_hash: &SaitoHash) -> String {
+        format!(
+            "{}{}-{}.sai",
+            self.blocks_dir,
+            hex::encode(timestamp.to_be_bytes()),
+            hex::encode(block_hash)
+        )
+    }
+
+    pub fn get_quarantine_dir(&self) -> String {
+        format!("{}{}/", self.blocks_dir, QUARANTINE_DIR_NAME)
+    }
+
+    fn quarantine_block_file(&self, path: &Path) {
+        let quarantine_dir = self.get_quarantine_dir();
+        let result = fs::create_dir_all(&quarantine_dir).and_then(|_| {
+            fs::rename(
+                path,
+                Path::new(&quarantine_dir).join(path.file_name().unwrap()),
+            )
+        });
+        if let Err(err) = result {
+            error!(
+                "ERROR 290317: cannot move block file {:?} to {}: {:?}",
+                path, quarantine_dir, err
+            );
+        }
+    }
+}
+
+impl BlockStorage for FileBlockStorage {
+    fn write_block(&self, block: &Block) -> crate::Result<()> {
+        if block.get_block_type() == BlockType::Pruned {
+            return Err("pruned blocks cannot be saved".into());
+        }
+        let filename = self.get_block_filename(block.get_timestamp(), &block.get_hash());
+        if !Path::new(&filename).exists() {
+            fs::write(&filename, Storage::serialize_block_for_disk(block))?;
+        }
+        Ok(())
+    }
+
+    fn read_block(&self, timestamp: u64, block_hash: &SaitoHash) -> crate::Result<Block> {
+        let bytes = Storage::read(&self.get_block_filename(timestamp, block_hash))?;
+        deserialize_stored_block(&bytes, Some(*block_hash))
+    }
+
+    fn delete_block(&self, timestamp: u64, block_hash: &SaitoHash) -> crate::Result<()> {
+        fs::remove_file(self.get_block_filename(timestamp, block_hash))?;
+        Ok(())
+    }
+
+    fn iterate(&self) -> Box<dyn Iterator<Item = Result<Block, CorruptBlock>> + '_> {
+        Box::new(
+            Storage::list_block_files(&self.blocks_dir)
+                .into_iter()
+                .map(move |path| {
+                    let block_hash = Storage::get_block_hash_from_filename(&path);
+                    Storage::read(path.to_str().unwrap())
+                        .map_err(crate::Error::from)
+                        .and_then(|bytes| deserialize_stored_block(&bytes, block_hash))
+                        .map_err(|err| {
+                            self.quarantine_block_file(&path);
+                            CorruptBlock {
+                                block_hash,
+                                error: format!("block file {:?}: {}", path, err).into(),
+                            }
+                        })
+                }),
+        )
+    }
+}
+
+/// Blocks kept in memory as they would be written to block files, oldest first.
+#[derive(Debug, Default)]
+pub struct MemoryBlockStorage {
+    blocks: Mutex<BTreeMap<(u64, SaitoHash), Vec<u8>>>,
+}
+
+impl MemoryBlockStorage {
+    pub fn new() -> Self {
+        MemoryBlockStorage::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl BlockStorage for MemoryBlockStorage {
+    fn write_block(&self, block: &Block) -> crate::Result<()> {
+        if block.get_block_type() == BlockType::Pruned {
+            return Err("pruned blocks cannot be saved".into());
+        }
+        self.blocks
+            .lock()
+            .unwrap()
+            .entry((block.get_timestamp(), block.get_hash()))
+            .or_insert_with(|| Storage::serialize_block_for_disk(block));
+        Ok(())
+    }
+
+    fn read_block(&self, timestamp: u64, block_hash: &SaitoHash) -> crate::Result<Block> {
+        match self.blocks.lock().unwrap().get(&(timestamp, *block_hash)) {
+            Some(bytes) => deserialize_stored_block(bytes, Some(*block_hash)),
+            None => Err(format!("block {} is not stored", hex::encode(block_hash)).into()),
+        }
+    }
+
+    fn delete_block(&self, timestamp: u64, block_hash: &SaitoHash) -> crate::Result<()> {
+        match self
+            .blocks
+            .lock()
+            .unwrap()
+            .remove(&(timestamp, *block_hash))
+        {
+            Some(_) => Ok(()),
+            None => Err(format!("block {} is not stored", hex::encode(block_hash)).into()),
+        }
+    }
+
+    fn iterate(&self) -> Box<dyn Iterator<Item = Result<Block, CorruptBlock>> + '_> {
+        //
+        // the blocks are copied out so that the lock is not held while the
+        // caller adds them to a blockchain
+        //
+        let blocks: Vec<((u64, SaitoHash), Vec<u8>)> = self
+            .blocks
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(key, bytes)| (*key, bytes.clone()))
+            .collect();
+        Box::new(blocks.into_iter().map(move |(key, bytes)| {
+            deserialize_stored_block(&bytes, Some(key.1)).map_err(|error| {
+                self.blocks.lock().unwrap().remove(&key);
+                CorruptBlock {
+                    block_hash: Some(key.1),
+                    error,
+                }
+            })
+        }))
+    }
+}
+
 pub struct Storage {}
 
 impl Storage {
@@ -49,35 +276,111 @@ impl Storage {
         buffer.write_all(&data[..]).unwrap();
     }
 
+    /// write to a temporary file which is then renamed over filename, so that a crash
+    /// while writing never leaves a partially written file behind.
+    pub fn write_atomically(data: Vec<u8>, filename: &str) -> io::Result<()> {
+        let tmp_filename = format!("{}.tmp", filename);
+        {
+            let mut buffer = File::create(&tmp_filename)?;
+            buffer.write_all(&data[..])?;
+            buffer.sync_all()?;
+        }
+        fs::rename(&tmp_filename, filename)
+    }
+
     pub fn file_exists(filename: &str) -> bool {
         let path = Path::new(&filename);
         path.exists()
     }
 
     pub fn generate_block_filename(block: &Block) -> String {
-        let mut filename = BLOCKS_DIR_PATH.clone();
-
-        filename.push_str(&hex::encode(block.get_timestamp().to_be_bytes()));
-        filename.push_str(&String::from("-"));
-        filename.push_str(&hex::encode(&block.get_hash()));
-        filename.push_str(&".sai");
-        filename
+        FileBlockStorage::new(&BLOCKS_DIR_PATH)
+            .get_block_filename(block.get_timestamp(), &block.get_hash())
     }
-    pub fn write_block_to_disk(block: &mut Block) -> String {
-        if block.get_block_type() == BlockType::Pruned {
-            panic!("pruned blocks cannot be saved");
+    /// The block hash in a filename made by generate_block_filename, if it has one.
+    pub fn get_block_hash_from_filename(path: &Path) -> Option<SaitoHash> {
+        let file_stem = path.file_stem()?.to_str()?;
+        let (_timestamp, block_hash) = file_stem.rsplit_once('-')?;
+        hex::decode(block_hash).ok()?.try_into().ok()
+    }
+    /// The contents of a block file: the block file header and the block serialized for the
+    /// network.
+    pub fn serialize_block_for_disk(block: &Block) -> Vec<u8> {
+        let serialized_block = block.serialize_for_net(BlockType::Full);
+        let mut vbytes: Vec<u8> = vec![];
+        vbytes.extend(&BLOCK_FILE_MAGIC);
+        vbytes.extend(&BLOCK_FILE_VERSION.to_be_bytes());
+        vbytes.extend(&hash(&serialized_block));
+        vbytes.extend(serialized_block);
+        vbytes
+    }
+
+    /// Reads the block in the contents of a block file, failing if the file does not start
+    /// with a block file header of the current version or the block does not match the hash
+    /// in the header, so that a damaged file is refused before it is deserialized.
+    pub fn deserialize_block_from_disk(bytes: &[u8]) -> crate::Result<Block> {
+        if bytes.len() < BLOCK_FILE_HEADER_SIZE || bytes[0..4] != BLOCK_FILE_MAGIC {
+            return Err("not a block file".into());
         }
-        let filename = Storage::generate_block_filename(block);
-        if !Path::new(&filename).exists() {
-            let mut buffer = File::create(filename.clone()).unwrap();
-            let byte_array: Vec<u8> = block.serialize_for_net(BlockType::Full);
-            buffer.write_all(&byte_array[..]).unwrap();
+        let version = u32::from_be_bytes(bytes[4..8].try_into().unwrap());
+        if version != BLOCK_FILE_VERSION {
+            return Err(format!(
+                "block file version {} is not version {}",
+                version, BLOCK_FILE_VERSION
+            )
+            .into());
+        }
+        if bytes[8..BLOCK_FILE_HEADER_SIZE] != hash(&bytes[BLOCK_FILE_HEADER_SIZE..]) {
+            return Err("block file checksum does not match".into());
         }
-        filename
+        Ok(Block::deserialize_for_net(&bytes[BLOCK_FILE_HEADER_SIZE..]))
     }
 
+    /// Adds the blocks in the block storage of the blockchain to it, see BlockStorage::iterate.
+    ///
+    /// Corrupt blocks are left for the blockchain to fetch again from our peers. The blocks
+    /// after a corrupt block which no longer have a parent are not loaded either, as they
+    /// could not be added to the longest chain, and are fetched again with the rest of the
+    /// chain from where the loaded chain ends.
     pub async fn load_blocks_from_disk(blockchain_lock: Arc<RwLock<Blockchain>>) {
-        let mut paths: Vec<_> = fs::read_dir(BLOCKS_DIR_PATH.clone())
+        let block_storage = blockchain_lock.read().await.get_block_storage();
+        let mut has_corrupt_blocks = false;
+        for block in block_storage.iterate() {
+            let mut block = match block {
+                Ok(block) => block,
+                Err(corrupt_block) => {
+                    error!("ERROR 290312: cannot load block: {}", corrupt_block.error);
+                    has_corrupt_blocks = true;
+                    if let Some(block_hash) = corrupt_block.block_hash {
+                        blockchain_lock
+                            .write()
+                            .await
+                            .add_quarantined_block_hash(block_hash);
+                    }
+                    continue;
+                }
+            };
+            let mut blockchain = blockchain_lock.write().await;
+            if has_corrupt_blocks
+                && !blockchain.blocks.is_empty()
+                && blockchain
+                    .get_block_sync(&block.get_previous_block_hash())
+                    .is_none()
+            {
+                warn!(
+                    "not loading block {} as its parent is not loaded",
+                    hex::encode(block.get_hash())
+                );
+                continue;
+            }
+            block.generate_metadata();
+            blockchain.add_block(block).await;
+        }
+    }
+
+    /// the block files in a directory, oldest first, which is the order they are loaded in
+    pub fn list_block_files(blocks_dir: &str) -> Vec<PathBuf> {
+        let mut paths: Vec<_> = fs::read_dir(blocks_dir)
             .unwrap()
             .map(|r| r.unwrap())
             .collect();
@@ -90,17 +393,12 @@ impl Storage {
                 .partial_cmp(&b_metadata.modified().unwrap())
                 .unwrap()
         });
-        for (_pos, path) in paths.iter().enumerate() {
-            if !path.path().to_str().unwrap().ends_with(".gitignore") {
-                let mut f = File::open(path.path()).unwrap();
-                let mut encoded = Vec::<u8>::new();
-                f.read_to_end(&mut encoded).unwrap();
-                let mut block = Block::deserialize_for_net(&encoded);
-                let mut blockchain = blockchain_lock.write().await;
-                block.generate_metadata();
-                blockchain.add_block(block).await;
-            }
-        }
+        paths
+            .iter()
+            .map(|path| path.path())
+            .filter(|path| path.is_file())
+            .filter(|path| !path.to_str().unwrap().ends_with(".gitignore"))
+            .collect()
     }
 
     pub async fn load_block_from_disk(filename: String) -> Block {
@@ -108,14 +406,8 @@ impl Storage {
         let mut f = File::open(file_to_load).unwrap();
         let mut encoded = Vec::<u8>::new();
         f.read_to_end(&mut encoded).unwrap();
-        Block::deserialize_for_net(&encoded)
-    }
-
-    pub async fn delete_block_from_disk(filename: String) -> bool {
-        // TODO: get rid of this function or make it useful.
-        // it should match the result and provide some error handling.
-        let _res = std::fs::remove_file(filename);
-        true
+        Storage::deserialize_block_from_disk(&encoded)
+            .unwrap_or_else(|err| panic!("cannot load block file {}: {}", filename, err))
     }
 
     //
@@ -123,7 +415,7 @@ impl Storage {
     //
     pub fn return_token_supply_slips_from_disk() -> Vec<Slip> {
         let mut v: Vec<Slip> = vec![];
-        let mut tokens_issued = 0;
+        let mut tokens_issued = Amount::ZERO;
 
         if let Ok(lines) = Storage::read_lines_from_file(ISSUANCE_FILE_PATH) {
             for line in lines {
@@ -143,14 +435,20 @@ impl Storage {
         }
 
         for i in 0..v.len() {
-            tokens_issued += v[i].get_amount();
+            tokens_issued = tokens_issued
+                .checked_add(v[i].get_amount())
+                .expect("the issuance overflows");
         }
 
         if let Ok(lines) = Storage::read_lines_from_file(DEFAULT_FILE_PATH) {
             for line in lines {
                 if let Ok(ip) = line {
                     let mut s = Storage::convert_issuance_into_slip(ip);
-                    s.set_amount(MAX_TOKEN_SUPPLY - tokens_issued);
+                    s.set_amount(
+                        Amount::from(MAX_TOKEN_SUPPLY)
+                            .checked_sub(tokens_issued)
+                            .expect("the issuance exceeds the maximum token supply"),
+                    );
                     v.push(s);
                 }
             }
@@ -183,7 +481,7 @@ impl Storage {
 
         let mut slip = Slip::new();
         slip.set_publickey(add);
-        slip.set_amount(amt);
+        slip.set_amount(amt.into());
         if typ.eq("VipOutput") {
             slip.set_slip_type(SlipType::VipOutput);
         }
@@ -205,37 +503,13 @@ mod tests {
     use crate::time::create_timestamp;
     use crate::wallet::Wallet;
 
-    impl Drop for Blockchain {
-        fn drop(&mut self) {
-            let paths: Vec<_> = fs::read_dir(BLOCKS_DIR_PATH.clone())
-                .unwrap()
-                .map(|r| r.unwrap())
-                .collect();
-            for (_pos, path) in paths.iter().enumerate() {
-                if !path.path().to_str().unwrap().ends_with(".gitignore") {
-                    match std::fs::remove_file(path.path()) {
-                        Err(err) => {
-                            eprintln!("Error cleaning up after tests {}", err);
-                        }
-                        _ => {
-                            log::trace!(
-                                "block removed from disk : {}",
-                                path.path().to_str().unwrap()
-                            );
-                        }
-                    }
-                }
-            }
-        }
-    }
-
     #[test]
     fn read_issuance_file_test() {
         let slips = Storage::return_token_supply_slips_from_disk();
         let mut total_issuance = 0;
 
         for i in 0..slips.len() {
-            total_issuance += slips[i].get_amount();
+            total_issuance += u64::from(slips[i].get_amount());
         }
 
         assert_eq!(total_issuance, MAX_TOKEN_SUPPLY);
@@ -254,10 +528,170 @@ mod tests {
             .generate_block_and_metadata([0; 32], current_timestamp, 0, 1, false, vec![])
             .await;
 
-        let filename = Storage::write_block_to_disk(&mut block);
-        log::trace!("block written to file : {}", filename);
-        let retrieved_block = Storage::load_block_from_disk(filename).await;
+        let block_storage = FileBlockStorage::new(&BLOCKS_DIR_PATH);
+        block_storage.write_block(&block).unwrap();
+        let filename = block_storage.get_block_filename(block.get_timestamp(), &block.get_hash());
+        assert_eq!(filename, Storage::generate_block_filename(&block));
+        let retrieved_block = Storage::load_block_from_disk(filename.clone()).await;
 
         assert_eq!(block.get_hash(), retrieved_block.get_hash());
+
+        block_storage
+            .delete_block(block.get_timestamp(), &block.get_hash())
+            .unwrap();
+        assert!(!Storage::file_exists(&filename));
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn memory_block_storage_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        test_manager.generate_blockchain(3, [0; 32]).await;
+
+        // the blocks added to a blockchain in a test are kept in memory
+        let blockchain = blockchain_lock.read().await;
+        let block_storage = blockchain.get_block_storage();
+        let block_hashes: Vec<SaitoHash> = block_storage
+            .iterate()
+            .map(|block| block.unwrap().get_hash())
+            .collect();
+        assert_eq!(
+            block_hashes,
+            (1..=3)
+                .map(|block_id| blockchain
+                    .blockring
+                    .get_longest_chain_block_hash_by_block_id(block_id))
+                .collect::<Vec<SaitoHash>>()
+        );
+
+        let block = blockchain.get_latest_block().unwrap();
+        let read_block = block_storage
+            .read_block(block.get_timestamp(), &block.get_hash())
+            .unwrap();
+        assert_eq!(
+            read_block.serialize_for_net(BlockType::Full),
+            block.serialize_for_net(BlockType::Full)
+        );
+
+        let mut pruned_block = block.clone();
+        pruned_block
+            .downgrade_block_to_block_type(BlockType::Pruned)
+            .await;
+        assert!(block_storage.write_block(&pruned_block).is_err());
+
+        block_storage
+            .delete_block(block.get_timestamp(), &block.get_hash())
+            .unwrap();
+        assert!(block_storage
+            .read_block(block.get_timestamp(), &block.get_hash())
+            .is_err());
+        assert_eq!(block_storage.iterate().count(), 2);
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn block_file_format_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        let block = test_manager
+            .generate_block_and_metadata([0; 32], create_timestamp(), 0, 1, false, vec![])
+            .await;
+
+        // the block on disk is the block as sent over the network
+        let serialized_block = Storage::serialize_block_for_disk(&block);
+        assert_eq!(&serialized_block[0..4], b"SAIB");
+        assert_eq!(
+            &serialized_block[BLOCK_FILE_HEADER_SIZE..],
+            &block.serialize_for_net(BlockType::Full)[..]
+        );
+        let read_block = Storage::deserialize_block_from_disk(&serialized_block).unwrap();
+        assert_eq!(read_block.get_hash(), block.get_hash());
+        assert_eq!(
+            read_block.serialize_for_net(BlockType::Full),
+            block.serialize_for_net(BlockType::Full)
+        );
+
+        // files without the header, or of another version, are refused
+        assert!(
+            Storage::deserialize_block_from_disk(&block.serialize_for_net(BlockType::Full))
+                .is_err()
+        );
+        let mut other_version = serialized_block.clone();
+        other_version[7] += 1;
+        assert!(Storage::deserialize_block_from_disk(&other_version).is_err());
+        assert!(Storage::deserialize_block_from_disk(&serialized_block[..6]).is_err());
+
+        // as are damaged files, and blocks stored under another hash
+        let mut damaged_block = serialized_block.clone();
+        *damaged_block.last_mut().unwrap() ^= 1;
+        assert!(Storage::deserialize_block_from_disk(&damaged_block).is_err());
+        assert!(deserialize_stored_block(&serialized_block, Some(block.get_hash())).is_ok());
+        assert!(deserialize_stored_block(&serialized_block, Some([1; 32])).is_err());
+        assert!(deserialize_stored_block(&serialized_block, None).is_err());
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn corrupt_block_file_quarantine_test() {
+        const QUARANTINE_TEST_DIR_PATH: &str = "./data/test/quarantine_blocks/";
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        test_manager.generate_blockchain(4, [0; 32]).await;
+
+        let _ = fs::remove_dir_all(QUARANTINE_TEST_DIR_PATH);
+        fs::create_dir_all(QUARANTINE_TEST_DIR_PATH).unwrap();
+        let block_storage = Arc::new(FileBlockStorage::new(QUARANTINE_TEST_DIR_PATH));
+        let mut filenames = vec![];
+        let mut block_hashes = vec![];
+        {
+            let blockchain = blockchain_lock.read().await;
+            for block_id in 1..=4 {
+                let block_hash = blockchain
+                    .blockring
+                    .get_longest_chain_block_hash_by_block_id(block_id);
+                let block = blockchain.get_block_sync(&block_hash).unwrap();
+                let filename = block_storage.get_block_filename(block.get_timestamp(), &block_hash);
+                let mut bytes = Storage::serialize_block_for_disk(block);
+                // damage the file of block 2
+                if block_id == 2 {
+                    *bytes.last_mut().unwrap() ^= 1;
+                }
+                fs::write(&filename, bytes).unwrap();
+                filenames.push(filename);
+                block_hashes.push(block_hash);
+                // block files are loaded in the order they were written
+                std::thread::sleep(std::time::Duration::from_millis(10));
+            }
+        }
+
+        let loaded_blockchain_lock = Arc::new(RwLock::new(Blockchain::new(Arc::new(RwLock::new(
+            Wallet::new(),
+        )))));
+        loaded_blockchain_lock
+            .write()
+            .await
+            .set_block_storage(block_storage.clone());
+        Storage::load_blocks_from_disk(loaded_blockchain_lock.clone()).await;
+
+        // the chain ends before block 2, which is to be fetched from our peers
+        let loaded_blockchain = loaded_blockchain_lock.read().await;
+        assert_eq!(loaded_blockchain.get_latest_block_hash(), block_hashes[0]);
+        assert_eq!(loaded_blockchain.blocks.len(), 1);
+        assert_eq!(
+            loaded_blockchain.get_quarantined_block_hashes(),
+            vec![block_hashes[1]]
+        );
+        assert!(!Storage::file_exists(&filenames[1]));
+        assert!(Path::new(&block_storage.get_quarantine_dir())
+            .join(Path::new(&filenames[1]).file_name().unwrap())
+            .exists());
+        assert!(Storage::file_exists(&filenames[2]));
+        assert_eq!(Storage::list_block_files(QUARANTINE_TEST_DIR_PATH).len(), 3);
+
+        fs::remove_dir_all(QUARANTINE_TEST_DIR_PATH).unwrap();
     }
 }
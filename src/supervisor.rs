@@ -0,0 +1,206 @@
+use crate::time::create_timestamp;
+use std::any::Any;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::time::Duration;
+use tokio::time::sleep;
+use tracing::{error, warn};
+
+//
+// A panic in a task of tokio ends that task only, so a node whose mempool
+// panicked on an expect() kept running without a mempool, and a peer whose
+// handler panicked stayed connected in our peer list forever. Long-running
+// tasks are therefore run by a supervisor, which logs the panic with the
+// name of the task and either restarts it, if it can be started again from
+// the state in its locks, or returns an error which shuts the node down.
+//
+
+/// How many times a task may be restarted within TASK_RESTART_WINDOW before the node is
+/// shut down instead.
+pub const MAX_TASK_RESTARTS: usize = 5;
+/// In milliseconds.
+pub const TASK_RESTART_WINDOW: u64 = 60_000;
+/// How long we wait before restarting a task, in milliseconds.
+pub const TASK_RESTART_DELAY: u64 = 1000;
+
+/// Runs the long-running tasks of the node, catching their panics.
+#[derive(Debug, Clone)]
+pub struct Supervisor {
+    name: String,
+    max_restarts: usize,
+    restart_window: u64,
+    restart_delay: u64,
+}
+
+impl Supervisor {
+    /// A supervisor for the task of the name, which is used in its logs.
+    pub fn new(name: &str) -> Self {
+        Supervisor {
+            name: name.to_string(),
+            max_restarts: MAX_TASK_RESTARTS,
+            restart_window: TASK_RESTART_WINDOW,
+            restart_delay: TASK_RESTART_DELAY,
+        }
+    }
+
+    pub fn set_max_restarts(&mut self, max_restarts: usize) {
+        self.max_restarts = max_restarts;
+    }
+
+    /// Sets how long we wait before restarting a task, in milliseconds.
+    pub fn set_restart_delay(&mut self, restart_delay: u64) {
+        self.restart_delay = restart_delay;
+    }
+
+    /// Runs a task which cannot be restarted, e.g. because it owns the receiver of a channel.
+    /// If the task panics, the panic is returned as an error to shut the node down.
+    pub async fn run<T, F>(self, task: F) -> crate::Result<T>
+    where
+        T: Send + 'static,
+        F: Future<Output = crate::Result<T>> + Send + 'static,
+    {
+        match catch_panic(&self.name, task).await {
+            Some(result) => result,
+            None => Err(format!("task {} panicked, shutting down", self.name).into()),
+        }
+    }
+
+    /// Runs the task which start returns, and starts it again if it panics. If it panics more
+    /// than max_restarts times within the restart window, an error is returned to shut the
+    /// node down.
+    pub async fn run_restarting<S, F>(self, mut start: S) -> crate::Result<()>
+    where
+        S: FnMut() -> F,
+        F: Future<Output = crate::Result<()>> + Send + 'static,
+    {
+        let mut restart_timestamps: VecDeque<u64> = VecDeque::new();
+        loop {
+            if let Some(result) = catch_panic(&self.name, start()).await {
+                return result;
+            }
+            let current_timestamp = create_timestamp();
+            while restart_timestamps.front().is_some_and(|restart_timestamp| {
+                restart_timestamp + self.restart_window <= current_timestamp
+            }) {
+                restart_timestamps.pop_front();
+            }
+            if restart_timestamps.len() >= self.max_restarts {
+                return Err(format!(
+                    "task {} panicked {} times in {} ms, shutting down",
+                    self.name,
+                    restart_timestamps.len() + 1,
+                    self.restart_window
+                )
+                .into());
+            }
+            restart_timestamps.push_back(current_timestamp);
+            warn!("restarting task {} in {} ms", self.name, self.restart_delay);
+            sleep(Duration::from_millis(self.restart_delay)).await;
+        }
+    }
+}
+
+/// Runs the task on a task of its own, and logs it with the context if it panics. None if the
+/// task panicked or was cancelled.
+pub async fn catch_panic<T, F>(context: &str, task: F) -> Option<T>
+where
+    T: Send + 'static,
+    F: Future<Output = T> + Send + 'static,
+{
+    match tokio::spawn(task).await {
+        Ok(output) => Some(output),
+        Err(err) if err.is_panic() => {
+            let message = panic_message(err.into_panic().as_ref());
+            error!("ERROR 390101: {} panicked: {}", context, message);
+            None
+        }
+        Err(err) => {
+            warn!("{} was cancelled: {}", context, err);
+            None
+        }
+    }
+}
+
+/// Logs every panic with where it happened, before its task ends. Panics are otherwise only
+/// printed to stderr, and not to the log file.
+pub fn install_panic_hook() {
+    std::panic::set_hook(Box::new(|panic_info| {
+        let location = panic_info
+            .location()
+            .map(|location| format!("{}:{}", location.file(), location.line()))
+            .unwrap_or_default();
+        let message = panic_message(panic_info.payload());
+        error!(
+            "ERROR 390102: panic at {} on thread {}: {}",
+            location,
+            std::thread::current().name().unwrap_or("unnamed"),
+            message
+        );
+    }));
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        String::from("unknown panic")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn supervisor_test() {
+        let supervisor = Supervisor::new("test");
+        assert_eq!(supervisor.clone().run(async { Ok(1) }).await.unwrap(), 1);
+        assert!(supervisor
+            .run(async {
+                if create_timestamp() > 0 {
+                    panic!("test panic");
+                }
+                Ok(())
+            })
+            .await
+            .is_err());
+
+        // a task which panics twice is restarted, and then returns
+        let mut supervisor = Supervisor::new("test");
+        supervisor.set_restart_delay(0);
+        let starts = Arc::new(AtomicUsize::new(0));
+        let result = supervisor
+            .clone()
+            .run_restarting(|| {
+                let starts = starts.clone();
+                async move {
+                    if starts.fetch_add(1, Ordering::SeqCst) < 2 {
+                        panic!("test panic");
+                    }
+                    Ok(())
+                }
+            })
+            .await;
+        assert!(result.is_ok());
+        assert_eq!(starts.load(Ordering::SeqCst), 3);
+
+        // a task which keeps panicking shuts the node down
+        supervisor.set_max_restarts(3);
+        starts.store(0, Ordering::SeqCst);
+        let result = supervisor
+            .run_restarting(|| {
+                let starts = starts.clone();
+                async move {
+                    starts.fetch_add(1, Ordering::SeqCst);
+                    panic!("test panic");
+                }
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(starts.load(Ordering::SeqCst), 4);
+    }
+}
@@ -0,0 +1,267 @@
+use crate::amount::Amount;
+use crate::block::Block;
+use crate::blockchain::{Blockchain, UtxoSet, MAX_STAKER_RECURSION};
+use crate::crypto::SaitoHash;
+use crate::storage::Storage;
+use crate::transaction::TransactionType;
+use ahash::AHashMap;
+use std::convert::TryInto;
+
+//
+// Every nolan issued must at all times be in one of four places: an unspent
+// slip, the treasury, the staking treasury, or the fees of a recent block
+// which a later block may still pay out. The fees of a block are paid out
+// by the first block after it with a golden ticket, as long as that block is
+// at most MAX_STAKER_RECURSION blocks later. Fees which are not paid out
+// must fall into the treasury, so fees older than that are no longer counted
+// and any which were not accounted for show up as missing supply.
+//
+
+/// The supply accounted for at one block of the longest chain.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SupplyAuditEntry {
+    block_id: u64,
+    block_hash: SaitoHash,
+    utxo_total: Amount,
+    treasury: Amount,
+    staking_treasury: Amount,
+    unsettled_fees: Amount,
+    issued: Amount,
+}
+
+impl SupplyAuditEntry {
+    pub fn get_block_id(&self) -> u64 {
+        self.block_id
+    }
+    pub fn get_block_hash(&self) -> SaitoHash {
+        self.block_hash
+    }
+    pub fn get_utxo_total(&self) -> Amount {
+        self.utxo_total
+    }
+    pub fn get_treasury(&self) -> Amount {
+        self.treasury
+    }
+    pub fn get_staking_treasury(&self) -> Amount {
+        self.staking_treasury
+    }
+    pub fn get_unsettled_fees(&self) -> Amount {
+        self.unsettled_fees
+    }
+    pub fn get_issued(&self) -> Amount {
+        self.issued
+    }
+
+    /// The nolan we can find, or None if adding them up overflows.
+    pub fn get_accounted(&self) -> Option<Amount> {
+        Amount::checked_sum(vec![
+            self.utxo_total,
+            self.treasury,
+            self.staking_treasury,
+            self.unsettled_fees,
+        ])
+    }
+
+    pub fn is_conserved(&self) -> bool {
+        self.get_accounted() == Some(self.issued)
+    }
+}
+
+/// Replays blocks into a blockchain and checks after every new tip of the longest chain
+/// that all of the supply issued so far is accounted for.
+pub struct SupplyAudit {
+    issued_by_block_hash: AHashMap<SaitoHash, Amount>,
+    entries: Vec<SupplyAuditEntry>,
+}
+
+impl SupplyAudit {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        SupplyAudit {
+            issued_by_block_hash: AHashMap::new(),
+            entries: vec![],
+        }
+    }
+
+    /// Replays the block files in blocks_dir in the order the node loads them. The blocks are
+    /// written to the block storage of the blockchain, so it should not be the one in blocks_dir.
+    pub async fn audit_blocks_dir(blockchain: &mut Blockchain, blocks_dir: &str) -> SupplyAudit {
+        let mut supply_audit = SupplyAudit::new();
+        for path in Storage::list_block_files(blocks_dir) {
+            let block = Storage::load_block_from_disk(path.to_str().unwrap().to_string()).await;
+            supply_audit.add_block(blockchain, block).await;
+        }
+        supply_audit
+    }
+
+    /// Adds the block to the blockchain and audits the supply if it became the new tip of
+    /// the longest chain.
+    pub async fn add_block(
+        &mut self,
+        blockchain: &mut Blockchain,
+        mut block: Block,
+    ) -> Option<SupplyAuditEntry> {
+        block.generate_metadata();
+        let block_hash = block.get_hash();
+        let previously_issued = self
+            .issued_by_block_hash
+            .get(&block.get_previous_block_hash())
+            .cloned()
+            .unwrap_or(Amount::ZERO);
+        self.issued_by_block_hash.insert(
+            block_hash,
+            previously_issued.saturating_add(get_issued_in_block(&block)),
+        );
+
+        blockchain.add_block(block).await;
+        if blockchain.get_latest_block_hash() != block_hash {
+            return None;
+        }
+
+        let block = blockchain.get_block_sync(&block_hash).unwrap();
+        let entry = SupplyAuditEntry {
+            block_id: block.get_id(),
+            block_hash,
+            utxo_total: get_utxo_total(&blockchain.utxoset),
+            treasury: block.get_treasury().into(),
+            staking_treasury: block.get_staking_treasury().into(),
+            unsettled_fees: get_unsettled_fees(blockchain, block.get_id()),
+            issued: self.issued_by_block_hash[&block_hash],
+        };
+        self.entries.push(entry.clone());
+        Some(entry)
+    }
+
+    pub fn get_entries(&self) -> &Vec<SupplyAuditEntry> {
+        &self.entries
+    }
+
+    /// The first tip at which the supply was not conserved.
+    pub fn get_first_break(&self) -> Option<&SupplyAuditEntry> {
+        self.entries.iter().find(|entry| !entry.is_conserved())
+    }
+}
+
+//
+// new supply enters the chain through the issuance transactions in the first
+// block and through VIP transactions.
+//
+fn get_issued_in_block(block: &Block) -> Amount {
+    block
+        .get_transactions()
+        .iter()
+        .filter(|transaction| {
+            transaction.get_transaction_type() == TransactionType::Issuance
+                || transaction.get_transaction_type() == TransactionType::Vip
+        })
+        .flat_map(|transaction| transaction.get_outputs())
+        .map(|output| output.get_amount())
+        .sum()
+}
+
+//
+// utxoset keys end with the amount and slip ordinal, and spendable slips have
+// a value of 1.
+//
+fn get_utxo_total(utxoset: &UtxoSet) -> Amount {
+    utxoset
+        .iter()
+        .filter(|(_, value)| **value == 1)
+        .map(|(utxoset_key, _)| {
+            Amount::from(u64::from_be_bytes(utxoset_key[65..73].try_into().unwrap()))
+        })
+        .sum()
+}
+
+fn get_unsettled_fees(blockchain: &Blockchain, block_id: u64) -> Amount {
+    let mut recent_blocks = vec![];
+    for id in block_id.saturating_sub(MAX_STAKER_RECURSION - 1).max(1)..=block_id {
+        let block_hash = blockchain
+            .blockring
+            .get_longest_chain_block_hash_by_block_id(id);
+        if let Some(block) = blockchain.get_block_sync(&block_hash) {
+            recent_blocks.push((block.get_total_fees(), block.get_has_golden_ticket()));
+        }
+    }
+    calculate_unsettled_fees(&recent_blocks)
+}
+
+/// The fees which can still be paid out, given the total fees and whether there was a
+/// golden ticket in each of the last MAX_STAKER_RECURSION blocks, oldest first. These are
+/// the fees of the blocks from the last one with a golden ticket onwards.
+pub fn calculate_unsettled_fees(recent_blocks: &[(u64, bool)]) -> Amount {
+    let mut unsettled_fees = Amount::ZERO;
+    for (total_fees, has_golden_ticket) in recent_blocks.iter().rev() {
+        unsettled_fees = unsettled_fees.saturating_add((*total_fees).into());
+        if *has_golden_ticket {
+            break;
+        }
+    }
+    unsettled_fees
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::BlockType;
+    use crate::test_utilities::test_manager::TestManager;
+    use crate::wallet::Wallet;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    #[test]
+    fn calculate_unsettled_fees_test() {
+        assert_eq!(calculate_unsettled_fees(&[]), Amount::ZERO);
+        assert_eq!(
+            calculate_unsettled_fees(&[(100, false), (200, false), (300, false)]),
+            Amount::from(600)
+        );
+        // the golden ticket pays out the block before it, but not its own fees
+        assert_eq!(
+            calculate_unsettled_fees(&[(100, false), (200, true), (300, false)]),
+            Amount::from(500)
+        );
+        assert_eq!(
+            calculate_unsettled_fees(&[(100, false), (200, false), (300, true)]),
+            Amount::from(300)
+        );
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn supply_audit_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        test_manager.generate_blockchain(5, [0; 32]).await;
+
+        let blockchain = blockchain_lock.read().await;
+        let blocks: Vec<Block> = (1..=blockchain.get_latest_block_id())
+            .map(|block_id| {
+                let block_hash = blockchain
+                    .blockring
+                    .get_longest_chain_block_hash_by_block_id(block_id);
+                let block = blockchain.get_block_sync(&block_hash).unwrap();
+                Block::deserialize_for_net(&block.serialize_for_net(BlockType::Full))
+            })
+            .collect();
+
+        let mut audited_blockchain = Blockchain::new(Arc::new(RwLock::new(Wallet::new())));
+        let mut supply_audit = SupplyAudit::new();
+        for block in blocks {
+            assert!(supply_audit
+                .add_block(&mut audited_blockchain, block)
+                .await
+                .is_some());
+        }
+
+        let entries = supply_audit.get_entries();
+        assert_eq!(entries.len(), 5);
+        assert!(entries[0].get_issued() > Amount::ZERO);
+        assert_eq!(supply_audit.get_first_break(), None);
+        assert_eq!(
+            entries[4].get_block_hash(),
+            blockchain.get_latest_block_hash()
+        );
+    }
+}
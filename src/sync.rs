@@ -0,0 +1,168 @@
+use crate::crypto::SaitoHash;
+use ahash::AHashMap;
+use serde::Serialize;
+
+/// how many blocks we may be behind the network tip and still count as synced
+pub const MAX_SYNCED_BLOCK_LAG: u64 = 10;
+
+/// Where the node is in catching up with the network.
+///
+/// A node starts NotSynced, and is SyncingHeaders once it has asked a peer for its chain.
+/// The tips our peers announce then tell us whether we are SyncingBlocks, i.e. more than
+/// MAX_SYNCED_BLOCK_LAG blocks behind the network tip, or Synced. A Synced node goes back
+/// to SyncingBlocks if it hears of a tip far ahead of its own.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum SyncState {
+    NotSynced,
+    SyncingHeaders,
+    SyncingBlocks,
+    Synced,
+}
+
+/// How the tip a peer announced compares with ours, see Blockchain::compare_tip.
+///
+/// A tip is Ahead if we do not hold its block and the peer's chain is longer or has more work
+/// than ours, whether it extends our chain or forks from it. Behind is a block of our longest
+/// chain other than our tip, and Fork any other block, i.e. a chain we have seen and not
+/// adopted, or one neither longer nor heavier than ours which we need not fetch.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum TipComparison {
+    Ahead,
+    Same,
+    Behind,
+    Fork,
+}
+
+/// The sync state of the node and the tips announced by its peers.
+#[derive(Debug, Clone)]
+pub struct SyncStatus {
+    state: SyncState,
+    latest_block_id: u64,
+    peer_tips: AHashMap<SaitoHash, u64>,
+}
+
+/// The reply to GET /syncstatus.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SyncStatusReport {
+    pub state: SyncState,
+    pub latest_block_id: u64,
+    pub network_tip_block_id: u64,
+    pub peers_with_tips: usize,
+}
+
+impl SyncStatus {
+    pub fn new() -> Self {
+        SyncStatus {
+            state: SyncState::NotSynced,
+            latest_block_id: 0,
+            peer_tips: AHashMap::new(),
+        }
+    }
+
+    pub fn get_state(&self) -> SyncState {
+        self.state
+    }
+
+    /// The highest block id announced by any peer, or 0 if no peer has announced a tip.
+    pub fn get_network_tip_block_id(&self) -> u64 {
+        self.peer_tips.values().copied().max().unwrap_or(0)
+    }
+
+    /// Whether we are so far behind the network that we should neither produce blocks nor
+    /// relay transactions.
+    pub fn is_far_behind(&self) -> bool {
+        self.state == SyncState::SyncingBlocks
+    }
+
+    pub fn get_report(&self) -> SyncStatusReport {
+        SyncStatusReport {
+            state: self.state,
+            latest_block_id: self.latest_block_id,
+            network_tip_block_id: self.get_network_tip_block_id(),
+            peers_with_tips: self.peer_tips.len(),
+        }
+    }
+
+    /// Called once we have asked a peer for its chain.
+    pub fn on_chain_requested(&mut self) {
+        if self.state == SyncState::NotSynced {
+            self.state = SyncState::SyncingHeaders;
+        }
+    }
+
+    /// Called when the peer announces a tip, e.g. in its reply to REQCHAIN or by sending us
+    /// a block. Tips only move forward, as a peer may announce blocks out of order.
+    pub fn on_peer_tip(&mut self, connection_id: SaitoHash, block_id: u64) {
+        let peer_tip = self.peer_tips.entry(connection_id).or_insert(0);
+        *peer_tip = (*peer_tip).max(block_id);
+        self.update_state();
+    }
+
+    /// Called whenever our latest block changes.
+    pub fn on_latest_block(&mut self, latest_block_id: u64) {
+        self.latest_block_id = latest_block_id;
+        self.update_state();
+    }
+
+    fn update_state(&mut self) {
+        if self.peer_tips.is_empty() {
+            return;
+        }
+        self.state =
+            if self.get_network_tip_block_id() > self.latest_block_id + MAX_SYNCED_BLOCK_LAG {
+                SyncState::SyncingBlocks
+            } else {
+                SyncState::Synced
+            };
+    }
+}
+
+impl Default for SyncStatus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sync_status_transitions_test() {
+        let mut sync_status = SyncStatus::new();
+        assert_eq!(sync_status.get_state(), SyncState::NotSynced);
+
+        // our own blocks say nothing about the network
+        sync_status.on_latest_block(5);
+        assert_eq!(sync_status.get_state(), SyncState::NotSynced);
+
+        sync_status.on_chain_requested();
+        assert_eq!(sync_status.get_state(), SyncState::SyncingHeaders);
+
+        sync_status.on_peer_tip([1; 32], 5 + MAX_SYNCED_BLOCK_LAG + 1);
+        assert_eq!(sync_status.get_state(), SyncState::SyncingBlocks);
+        assert!(sync_status.is_far_behind());
+
+        sync_status.on_latest_block(6);
+        assert_eq!(sync_status.get_state(), SyncState::Synced);
+        assert!(!sync_status.is_far_behind());
+
+        // asking a new peer for its chain does not make us unsynced
+        sync_status.on_chain_requested();
+        assert_eq!(sync_status.get_state(), SyncState::Synced);
+
+        // a tip far ahead sends us back to syncing, and tips only move forward
+        sync_status.on_peer_tip([2; 32], 100);
+        sync_status.on_peer_tip([2; 32], 7);
+        assert_eq!(sync_status.get_state(), SyncState::SyncingBlocks);
+        assert_eq!(
+            sync_status.get_report(),
+            SyncStatusReport {
+                state: SyncState::SyncingBlocks,
+                latest_block_id: 6,
+                network_tip_block_id: 100,
+                peers_with_tips: 2,
+            }
+        );
+    }
+}
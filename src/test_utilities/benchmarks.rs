@@ -0,0 +1,76 @@
+use crate::amount::Amount;
+use crate::block::Block;
+use crate::crypto::{generate_keypair_from_privatekey, SaitoPrivateKey, SaitoPublicKey};
+use crate::slip::Slip;
+use crate::transaction::Transaction;
+
+/// The block sizes, in transactions, the consensus benchmarks are run for.
+pub const BENCHMARK_TRANSACTION_COUNTS: [usize; 3] = [1_000, 10_000, 100_000];
+
+fn create_keypair(seed: u8) -> (SaitoPublicKey, SaitoPrivateKey) {
+    generate_keypair_from_privatekey(&[seed; 32])
+}
+
+/// A signed block with the given number of signed transactions, each spending one slip to
+/// one output. The keys and timestamps are fixed, so the block is the same on every run.
+pub fn create_block_with_transactions(transaction_count: usize) -> Block {
+    let sender = create_keypair(1);
+    let receiver = create_keypair(2);
+    let creator = create_keypair(3);
+
+    let mut transactions: Vec<Transaction> = (0..transaction_count)
+        .map(|i| {
+            let mut uuid = [0; 32];
+            uuid[..8].copy_from_slice(&(i as u64).to_be_bytes());
+            let mut input = Slip::new();
+            input.set_publickey(sender.0);
+            input.set_uuid(uuid);
+            input.set_amount(Amount::from_nolan(1_000_000));
+
+            let mut output = Slip::new();
+            output.set_publickey(receiver.0);
+            output.set_amount(Amount::from_nolan(999_000));
+
+            let mut transaction = Transaction::new();
+            transaction.set_timestamp(1_637_034_582_666 + i as u64);
+            transaction.add_input(input);
+            transaction.add_output(output);
+            transaction.sign(sender.1);
+            transaction
+        })
+        .collect();
+
+    let mut block = Block::new();
+    block.set_id(2);
+    block.set_timestamp(1_637_034_582_700);
+    block.set_previous_block_hash([9; 32]);
+    block.set_burnfee(50_000_000);
+    block.set_difficulty(1);
+    block.set_transactions(&mut transactions);
+    block.set_merkle_root(block.generate_merkle_root());
+    block.sign(creator.0, creator.1);
+    block
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::BlockType;
+
+    #[test]
+    fn create_block_with_transactions_test() {
+        let mut block = create_block_with_transactions(10);
+        assert_eq!(block.get_transactions().len(), 10);
+        assert!(block.prevalidate().is_ok());
+
+        let mut deserialized_block =
+            Block::deserialize_for_net(&block.serialize_for_net(BlockType::Full));
+        deserialized_block.generate_metadata();
+        deserialized_block.generate_hashes();
+        assert_eq!(deserialized_block.get_hash(), block.get_hash());
+        assert_eq!(
+            deserialized_block.generate_merkle_root(),
+            block.get_merkle_root()
+        );
+    }
+}
@@ -1,2 +1,5 @@
+pub mod benchmarks;
 pub mod memory_stats;
 pub mod test_manager;
+pub mod test_network;
+pub mod test_vectors;
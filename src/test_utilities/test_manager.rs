@@ -9,6 +9,7 @@ use crate::crypto::{SaitoHash, SaitoPrivateKey, SaitoPublicKey, SaitoUTXOSetKey}
 use crate::golden_ticket::GoldenTicket;
 use crate::mempool::Mempool;
 use crate::miner::Miner;
+use crate::random::HashChain;
 use crate::time::create_timestamp;
 use crate::transaction::{Transaction, TransactionType};
 use crate::wallet::Wallet;
@@ -142,8 +143,11 @@ impl TestManager {
         }
 
         for i in 0..chain_length as u64 {
+            //
+            // VIP transactions are only allowed in the first block
+            //
             let mut vip_txs = 10;
-            if i > 0 {
+            if parent_hash != [0; 32] {
                 vip_txs = 0;
             }
 
@@ -186,7 +190,12 @@ impl TestManager {
         additional_transactions: Vec<Transaction>,
     ) -> Block {
         let mut transactions: Vec<Transaction> = vec![];
+        //
+        // golden tickets are derived from the parent block, so a block built on the same
+        // parent pays out the same routers and stakers
+        //
         let mut miner = Miner::new(self.wallet_lock.clone());
+        miner.set_random_source(Box::new(HashChain::new(parent_hash)));
         let blockchain = self.blockchain_lock.read().await;
         let privatekey: SaitoPrivateKey;
         let publickey: SaitoPublicKey;
@@ -198,15 +207,11 @@ impl TestManager {
         }
 
         if 0 < vip_transactions {
-            let mut tx = Transaction::generate_vip_transaction(
-                self.wallet_lock.clone(),
-                publickey,
-                10_000_000,
-                vip_transactions as u64,
-            )
-            .await;
+            let wallet = self.wallet_lock.read().await;
+            let mut tx = blockchain
+                .get_vip_policy()
+                .create_vip_transaction(&wallet, &vec![(publickey, 10_000_000); vip_transactions]);
             tx.generate_metadata(publickey);
-            tx.sign(privatekey);
             transactions.push(tx);
         }
 
@@ -251,7 +256,8 @@ impl TestManager {
             self.blockchain_lock.clone(),
             timestamp,
         )
-        .await;
+        .await
+        .unwrap();
 
         block
     }
@@ -286,7 +292,7 @@ impl TestManager {
         let transaction = self.generate_transaction(1000, 1000).await;
         {
             let mut mempool = self.mempool_lock.write().await;
-            mempool.add_transaction(transaction).await;
+            mempool.add_transaction(transaction).await.unwrap();
         }
 
         // get timestamp of previous block
@@ -506,10 +512,12 @@ impl TestManager {
                     block_fee_tx_idx = t as usize;
                 } else {
                     for z in 0..block.get_transactions()[t].inputs.len() {
-                        block_inputs += block.get_transactions()[t].inputs[z].get_amount();
+                        block_inputs +=
+                            u64::from(block.get_transactions()[t].inputs[z].get_amount());
                     }
                     for z in 0..block.get_transactions()[t].outputs.len() {
-                        block_outputs += block.get_transactions()[t].outputs[z].get_amount();
+                        block_outputs +=
+                            u64::from(block.get_transactions()[t].outputs[z].get_amount());
                     }
                 }
 
@@ -545,7 +553,7 @@ impl TestManager {
                         let mut total_fees_paid: u64 = 0;
                         let fee_transaction = &block.get_transactions()[block_fee_tx_idx];
                         for output in fee_transaction.get_outputs() {
-                            total_fees_paid += output.get_amount();
+                            total_fees_paid += u64::from(output.get_amount());
                         }
 
                         current_supply -= block_inputs;
@@ -697,20 +705,14 @@ impl TestManager {
             {
                 let blockchain = blockchain_lock_clone.read().await;
                 latest_block_id = blockchain.get_latest_block_id();
-            }
 
-            {
                 if latest_block_id == 0 {
-                    let mut vip_transaction = Transaction::generate_vip_transaction(
-                        wallet_lock_clone.clone(),
-                        publickey,
-                        100_000_000,
-                        10,
-                    )
-                    .await;
-                    vip_transaction.sign(privatekey);
+                    let wallet = wallet_lock_clone.read().await;
+                    let vip_transaction = blockchain
+                        .get_vip_policy()
+                        .create_vip_transaction(&wallet, &[(publickey, 100_000_000); 10]);
                     let mut mempool = mempool_lock_clone.write().await;
-                    mempool.add_transaction(vip_transaction).await;
+                    mempool.add_transaction(vip_transaction).await.unwrap();
                 }
             }
 
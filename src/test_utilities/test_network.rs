@@ -0,0 +1,239 @@
+//
+// TestNode runs a node, with its own wallet, mempool, blockchain and
+// network, inside the process of a test, so that several nodes can be
+// connected to one another. Nodes are connected over MemoryPeerConnection,
+// which moves messages over channels, so the test exercises the handshake,
+// relay and validation of blocks without opening sockets to the peers.
+//
+use crate::blockchain::Blockchain;
+use crate::configuration::{get_configuration, Settings};
+use crate::consensus::SaitoMessage;
+use crate::crypto::{hash, SaitoHash};
+use crate::mempool::Mempool;
+use crate::network::{self, Network};
+use crate::networking::api_message::APIMessage;
+use crate::networking::peer_connection::PeerConnection;
+use crate::peer::{handle_inbound_peer_connection, run_peer_connection, SaitoPeer};
+use crate::storage::MemoryBlockStorage;
+use crate::test_utilities::test_manager::TestManager;
+use crate::wallet::Wallet;
+use async_trait::async_trait;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc, RwLock};
+use tokio::time::{sleep, Instant};
+use uuid::Uuid;
+
+/// One end of a connection between two nodes in the same process.
+pub struct MemoryPeerConnection {
+    sender: Option<mpsc::UnboundedSender<APIMessage>>,
+    receiver: mpsc::UnboundedReceiver<APIMessage>,
+}
+
+impl MemoryPeerConnection {
+    /// The two ends of a new connection. What is sent on one end is received on the other.
+    pub fn pair() -> (Self, Self) {
+        let (sender_a, receiver_b) = mpsc::unbounded_channel();
+        let (sender_b, receiver_a) = mpsc::unbounded_channel();
+        (
+            MemoryPeerConnection {
+                sender: Some(sender_a),
+                receiver: receiver_a,
+            },
+            MemoryPeerConnection {
+                sender: Some(sender_b),
+                receiver: receiver_b,
+            },
+        )
+    }
+}
+
+#[async_trait]
+impl PeerConnection for MemoryPeerConnection {
+    async fn send(&mut self, api_message: APIMessage) -> crate::Result<()> {
+        match &self.sender {
+            Some(sender) => sender
+                .send(api_message)
+                .map_err(|_| "the other end of the connection was closed".into()),
+            None => Err("the connection was closed".into()),
+        }
+    }
+    async fn recv(&mut self) -> Option<crate::Result<APIMessage>> {
+        self.receiver.recv().await.map(Ok)
+    }
+    async fn close(&mut self) -> crate::Result<()> {
+        // the other end receives None once our sender is dropped
+        self.sender = None;
+        self.receiver.close();
+        Ok(())
+    }
+}
+
+/// A node running in the process of a test. Its blocks are kept in memory.
+pub struct TestNode {
+    pub wallet_lock: Arc<RwLock<Wallet>>,
+    pub mempool_lock: Arc<RwLock<Mempool>>,
+    pub blockchain_lock: Arc<RwLock<Blockchain>>,
+    pub network_lock: Arc<RwLock<Network>>,
+    pub broadcast_channel_sender: broadcast::Sender<SaitoMessage>,
+}
+
+impl TestNode {
+    /// Starts the network of a new node. It serves its routes on a port of the system's
+    /// choosing, and has no peers until it is connected to another node.
+    pub async fn new() -> Self {
+        let mut settings: Settings = get_configuration().expect("Failed to read configuration.");
+        settings.network.host = IpAddr::from([127, 0, 0, 1]);
+        settings.network.port = 0;
+        settings.network.quic_port = None;
+        settings.network.grpc_port = None;
+        settings.network.peers = None;
+        settings.network.message_log = None;
+
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let mempool_lock = Arc::new(RwLock::new(Mempool::new(wallet_lock.clone())));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let (broadcast_channel_sender, broadcast_channel_receiver) = broadcast::channel(1000);
+        {
+            let mut blockchain = blockchain_lock.write().await;
+            blockchain.set_block_storage(Arc::new(MemoryBlockStorage::new()));
+            blockchain.set_broadcast_channel_sender(broadcast_channel_sender.clone());
+        }
+        let network_lock = Arc::new(RwLock::new(Network::new(
+            settings,
+            blockchain_lock.clone(),
+            mempool_lock.clone(),
+            wallet_lock.clone(),
+            broadcast_channel_sender.clone(),
+        )));
+        let network_lock_clone = network_lock.clone();
+        let broadcast_channel_sender_clone = broadcast_channel_sender.clone();
+        tokio::spawn(async move {
+            network::run(
+                network_lock_clone,
+                broadcast_channel_sender_clone,
+                broadcast_channel_receiver,
+            )
+            .await
+        });
+
+        TestNode {
+            wallet_lock,
+            mempool_lock,
+            blockchain_lock,
+            network_lock,
+            broadcast_channel_sender,
+        }
+    }
+
+    /// A TestManager which produces blocks on the blockchain of the node.
+    pub fn get_test_manager(&self) -> TestManager {
+        TestManager::new(self.blockchain_lock.clone(), self.wallet_lock.clone())
+    }
+
+    /// Connects to the other node as an outbound peer of ours, and does the handshake and
+    /// synchronizes our chain with it, as if it were in our peer list. Returns the connection
+    /// id of the peer.
+    pub async fn connect_to(&self, other: &TestNode) -> SaitoHash {
+        let (connection, other_connection) = MemoryPeerConnection::pair();
+        let network_context = self.network_lock.read().await.get_network_context();
+        let other_network_context = other.network_lock.read().await.get_network_context();
+        tokio::spawn(handle_inbound_peer_connection(
+            other_connection,
+            None,
+            other_network_context,
+            other.wallet_lock.clone(),
+            other.mempool_lock.clone(),
+            other.blockchain_lock.clone(),
+            other.broadcast_channel_sender.clone(),
+        ));
+
+        let connection_id: SaitoHash = hash(Uuid::new_v4().as_bytes().as_ref());
+        let block_store = self.blockchain_lock.read().await.get_block_store();
+        let mut peer = SaitoPeer::new(
+            connection_id,
+            None,
+            None,
+            false,
+            false,
+            false,
+            self.wallet_lock.clone(),
+            self.mempool_lock.clone(),
+            self.blockchain_lock.clone(),
+            block_store,
+            self.broadcast_channel_sender.clone(),
+            network_context.clone(),
+        );
+        peer.set_is_connected_or_connecting(true).await;
+        network_context
+            .peers_db
+            .write()
+            .await
+            .insert(connection_id, peer);
+        run_peer_connection(network_context.clone(), connection, connection_id).await;
+        Network::handshake_and_synchronize_chain(
+            &network_context,
+            &connection_id,
+            self.wallet_lock.clone(),
+        )
+        .await;
+        connection_id
+    }
+
+    /// Waits until the latest block of our longest chain is the block of the hash, and
+    /// returns false if it is not within the timeout.
+    pub async fn wait_for_latest_block_hash(
+        &self,
+        block_hash: SaitoHash,
+        timeout: Duration,
+    ) -> bool {
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            if self.blockchain_lock.read().await.get_latest_block_hash() == block_hash {
+                return true;
+            }
+            sleep(Duration::from_millis(50)).await;
+        }
+        self.blockchain_lock.read().await.get_latest_block_hash() == block_hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn two_nodes_sync_a_chain_test() {
+        let producer = TestNode::new().await;
+        let follower = TestNode::new().await;
+        let mut test_manager = producer.get_test_manager();
+
+        // the blocks produced before the nodes are connected are synced in the handshake
+        let latest_block_hash = test_manager.generate_blockchain(10, [0; 32]).await;
+        follower.connect_to(&producer).await;
+        assert!(
+            follower
+                .wait_for_latest_block_hash(latest_block_hash, Duration::from_secs(20))
+                .await
+        );
+
+        // and the blocks produced after are relayed as they are added
+        let latest_block_hash = test_manager
+            .generate_blockchain(10, latest_block_hash)
+            .await;
+        assert!(
+            follower
+                .wait_for_latest_block_hash(latest_block_hash, Duration::from_secs(20))
+                .await
+        );
+        assert_eq!(
+            follower.blockchain_lock.read().await.get_latest_block_id(),
+            20
+        );
+        assert_eq!(
+            follower.blockchain_lock.read().await.get_latest_block_id(),
+            producer.blockchain_lock.read().await.get_latest_block_id()
+        );
+    }
+}
@@ -0,0 +1,266 @@
+use crate::amount::Amount;
+use crate::block::{Block, BlockType};
+use crate::crypto::{
+    generate_keypair_from_privatekey, hash, sign, SaitoPrivateKey, SaitoPublicKey,
+};
+use crate::golden_ticket::GoldenTicket;
+use crate::hop::Hop;
+use crate::networking::message_types::handshake_challenge::HandshakeChallenge;
+use crate::slip::{Slip, SlipType};
+use crate::transaction::{Transaction, TransactionType};
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+
+/// Where the test vectors shared with saito-js and saito-wasm are kept.
+pub const TEST_VECTORS_PATH: &str = "data/test/test_vectors.json";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TestVectorKind {
+    Block,
+    Transaction,
+    GoldenTicket,
+    HandshakeChallenge,
+}
+
+/// An object serialized for the network, hex encoded. Every implementation must read the
+/// bytes and write them back unchanged, and, for blocks and transactions, hash them the same.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TestVector {
+    pub name: String,
+    pub kind: TestVectorKind,
+    pub hex: String,
+    /// the hex encoded block hash, or transaction hash for signature
+    #[serde(default)]
+    pub hash: Option<String>,
+}
+
+impl TestVector {
+    pub fn new(name: &str, kind: TestVectorKind, bytes: &[u8], hash: Option<String>) -> Self {
+        TestVector {
+            name: String::from(name),
+            kind,
+            hex: hex::encode(bytes),
+            hash,
+        }
+    }
+
+    /// Deserializes the vector and serializes it again, returning the bytes and the hash.
+    pub fn round_trip(&self) -> crate::Result<(Vec<u8>, Option<String>)> {
+        let bytes = hex::decode(&self.hex)?;
+        Ok(match self.kind {
+            TestVectorKind::Block => {
+                let mut block = Block::deserialize_for_net(&bytes);
+                block.generate_hashes();
+                (
+                    block.serialize_for_net(BlockType::Full),
+                    Some(hex::encode(block.get_hash())),
+                )
+            }
+            TestVectorKind::Transaction => {
+                let transaction = Transaction::deserialize_from_net(bytes);
+                (
+                    transaction.serialize_for_net(),
+                    Some(hex::encode(hash(&transaction.serialize_for_signature()))),
+                )
+            }
+            TestVectorKind::GoldenTicket => (
+                GoldenTicket::deserialize_for_transaction(bytes).serialize_for_transaction(),
+                None,
+            ),
+            TestVectorKind::HandshakeChallenge => {
+                let handshake_challenge = HandshakeChallenge::deserialize(&bytes);
+                let mut serialized = handshake_challenge.serialize_raw();
+                serialized.extend(handshake_challenge.challenger_sig().unwrap_or([0; 64]));
+                serialized.extend(handshake_challenge.opponent_sig().unwrap_or([0; 64]));
+                serialized.truncate(bytes.len());
+                (serialized, None)
+            }
+        })
+    }
+}
+
+pub fn load_test_vectors(filename: &str) -> crate::Result<Vec<TestVector>> {
+    let test_vectors_json = std::fs::read_to_string(filename)?;
+    Ok(serde_json::from_str(&test_vectors_json)?)
+}
+
+//
+// the vectors are built from fixed keys and timestamps, so generating them again gives the
+// same bytes. Signatures are deterministic (RFC 6979).
+//
+fn create_keypair(seed: u8) -> (SaitoPublicKey, SaitoPrivateKey) {
+    generate_keypair_from_privatekey(&[seed; 32])
+}
+
+fn create_hop(
+    from: (SaitoPublicKey, SaitoPrivateKey),
+    to: SaitoPublicKey,
+    transaction: &Transaction,
+) -> Hop {
+    let mut vbytes: Vec<u8> = vec![];
+    vbytes.extend(&transaction.get_signature());
+    vbytes.extend(&to);
+    let mut hop = Hop::new();
+    hop.set_from(from.0);
+    hop.set_to(to);
+    hop.set_sig(sign(&hash(&vbytes), from.1));
+    hop
+}
+
+fn create_transaction() -> Transaction {
+    let sender = create_keypair(1);
+    let receiver = create_keypair(2);
+    let relay = create_keypair(3);
+
+    let mut input = Slip::new();
+    input.set_publickey(sender.0);
+    input.set_uuid([7; 32]);
+    input.set_amount(Amount::from_nolan(1_000_000));
+    input.set_slip_ordinal(1);
+
+    let mut output = Slip::new();
+    output.set_publickey(receiver.0);
+    output.set_amount(Amount::from_nolan(999_000));
+
+    let mut transaction = Transaction::new();
+    transaction.set_timestamp(1_637_034_582_666);
+    transaction.add_input(input);
+    transaction.add_output(output);
+    transaction.set_message(b"saito".to_vec());
+    transaction.sign(sender.1);
+
+    let hop = create_hop(sender, relay.0, &transaction);
+    transaction.set_path(vec![hop]);
+    transaction
+}
+
+fn create_golden_ticket() -> GoldenTicket {
+    GoldenTicket::new([1; 32], [2; 32], create_keypair(4).0)
+}
+
+fn create_golden_ticket_transaction() -> Transaction {
+    let miner = create_keypair(4);
+    let mut transaction = Transaction::new();
+    transaction.set_timestamp(1_637_034_582_667);
+    transaction.set_transaction_type(TransactionType::GoldenTicket);
+    transaction.set_message(create_golden_ticket().serialize_for_transaction());
+
+    let mut output = Slip::new();
+    output.set_publickey(miner.0);
+    output.set_slip_type(SlipType::MinerOutput);
+    transaction.add_output(output);
+    transaction.sign(miner.1);
+    transaction
+}
+
+fn create_block() -> Block {
+    let creator = create_keypair(5);
+    let mut block = Block::new();
+    block.set_id(2);
+    block.set_timestamp(1_637_034_582_700);
+    block.set_previous_block_hash([9; 32]);
+    block.set_burnfee(50_000_000);
+    block.set_difficulty(1);
+    block.set_treasury(1_000);
+    block.set_transactions(&mut vec![
+        create_transaction(),
+        create_golden_ticket_transaction(),
+    ]);
+    block.set_merkle_root(block.generate_merkle_root());
+    block.sign(creator.0, creator.1);
+    block
+}
+
+fn create_handshake_challenge() -> Vec<u8> {
+    let challenger = create_keypair(6);
+    let opponent = create_keypair(7);
+    let mut handshake_challenge = HandshakeChallenge::new(
+        (IpAddr::from([127, 0, 0, 1]), challenger.0),
+        (IpAddr::from([10, 0, 0, 2]), opponent.0),
+    );
+    handshake_challenge.set_timestamp(1_637_034_582_800);
+    handshake_challenge.set_challenger_nonce([8; 32]);
+    handshake_challenge.set_opponent_nonce([9; 32]);
+    let mut signed_challenge = handshake_challenge.serialize_with_sig(challenger.1);
+    let opponent_sig = sign(&hash(&signed_challenge), opponent.1);
+    signed_challenge.extend(opponent_sig);
+    signed_challenge
+}
+
+/// The vectors kept in TEST_VECTORS_PATH.
+pub fn generate_test_vectors() -> Vec<TestVector> {
+    let transaction = create_transaction();
+    let golden_ticket_transaction = create_golden_ticket_transaction();
+    let block = create_block();
+    vec![
+        TestVector::new(
+            "transaction with one hop",
+            TestVectorKind::Transaction,
+            &transaction.serialize_for_net(),
+            transaction.get_hash_for_signature().map(hex::encode),
+        ),
+        TestVector::new(
+            "golden ticket transaction",
+            TestVectorKind::Transaction,
+            &golden_ticket_transaction.serialize_for_net(),
+            golden_ticket_transaction
+                .get_hash_for_signature()
+                .map(hex::encode),
+        ),
+        TestVector::new(
+            "golden ticket",
+            TestVectorKind::GoldenTicket,
+            &create_golden_ticket().serialize_for_transaction(),
+            None,
+        ),
+        TestVector::new(
+            "block with two transactions",
+            TestVectorKind::Block,
+            &block.serialize_for_net(BlockType::Full),
+            Some(hex::encode(block.get_hash())),
+        ),
+        TestVector::new(
+            "signed handshake challenge",
+            TestVectorKind::HandshakeChallenge,
+            &create_handshake_challenge(),
+            None,
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vectors_round_trip_test() {
+        let test_vectors = load_test_vectors(TEST_VECTORS_PATH).unwrap();
+        assert!(!test_vectors.is_empty());
+        for test_vector in test_vectors {
+            let (bytes, hash) = test_vector.round_trip().unwrap();
+            assert_eq!(hex::encode(bytes), test_vector.hex, "{}", test_vector.name);
+            assert_eq!(hash, test_vector.hash, "{}", test_vector.name);
+        }
+    }
+
+    #[test]
+    fn test_vectors_are_current_test() {
+        //
+        // fails if serialize_for_net changes. If the change is intended, write the vectors
+        // again with `cargo test write_test_vectors -- --ignored` and share them with the
+        // other implementations.
+        //
+        assert_eq!(
+            load_test_vectors(TEST_VECTORS_PATH).unwrap(),
+            generate_test_vectors()
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn write_test_vectors() {
+        let test_vectors_json = serde_json::to_string_pretty(&generate_test_vectors()).unwrap();
+        std::fs::write(TEST_VECTORS_PATH, test_vectors_json + "\n").unwrap();
+    }
+}
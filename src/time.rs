@@ -1,6 +1,18 @@
+use crate::blockchain::Blockchain;
+use crate::crypto::SaitoPublicKey;
 use chrono::prelude::*;
+use std::collections::VecDeque;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+/// how far in milliseconds a block's timestamp may be ahead of network-adjusted time
+pub const MAX_FUTURE_BLOCK_TIME: u64 = 3_600_000;
+/// peer clock offsets larger than this many milliseconds are ignored
+pub const MAX_CLOCK_ADJUSTMENT: i64 = 1_800_000;
+/// the most peer clock offsets kept, the oldest is dropped to make room for a new one
+pub const MAX_CLOCK_OFFSETS: usize = 128;
+/// peers whose clocks are further than this many milliseconds from ours are not believed
+pub const MAX_CLOCK_SKEW: i64 = 86_400_000;
+
 pub fn create_timestamp() -> u64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -16,7 +28,232 @@ pub fn format_timestamp(
     datetime.format("%Y-%m-%d %H:%M:%S")
 }
 
+/// The median timestamp of the latest block and the n - 1 blocks before it, or 0 if the
+/// chain is empty. Unlike the timestamp of the latest block alone, a single producer with a
+/// bad clock cannot move it far.
+pub fn median_time_past(blockchain: &Blockchain, n: u64) -> u64 {
+    let mut timestamps = vec![];
+    let mut block_hash = blockchain.get_latest_block_hash();
+    while (timestamps.len() as u64) < n {
+        match blockchain.get_block_sync(&block_hash) {
+            Some(block) => {
+                timestamps.push(block.get_timestamp());
+                block_hash = block.get_previous_block_hash();
+            }
+            None => break,
+        }
+    }
+    if timestamps.is_empty() {
+        return 0;
+    }
+    timestamps.sort_unstable();
+    timestamps[timestamps.len() / 2]
+}
+
+/// The timestamp for a block built on a parent with previous_block_timestamp: the current
+/// timestamp, or just after the parent if our clock has gone backwards since the parent was
+/// made. Block timestamps must increase, so this keeps us from producing a block which fails
+/// our own validation.
+pub fn next_block_timestamp(current_timestamp: u64, previous_block_timestamp: u64) -> u64 {
+    current_timestamp.max(previous_block_timestamp.saturating_add(1))
+}
+
+/// How many milliseconds a peer's clock is ahead of ours, given the timestamp the peer put
+/// into its response to a request we sent at request_timestamp and had the response to at
+/// response_timestamp. The peer is taken to have responded halfway through the round trip.
+/// None if the peer's clock is more than MAX_CLOCK_SKEW away from ours.
+pub fn estimate_clock_offset(
+    request_timestamp: u64,
+    response_timestamp: u64,
+    peer_timestamp: u64,
+) -> Option<i64> {
+    let local_timestamp = request_timestamp as i128
+        + (response_timestamp.saturating_sub(request_timestamp) / 2) as i128;
+    let clock_offset = peer_timestamp as i128 - local_timestamp;
+    if clock_offset.abs() > MAX_CLOCK_SKEW as i128 {
+        return None;
+    }
+    Some(clock_offset as i64)
+}
+
+/// Network-adjusted time is our clock moved by the median of the offsets of our peers'
+/// clocks, as measured in the handshake and the pings since. A single peer cannot move it far, and
+/// offsets beyond MAX_CLOCK_ADJUSTMENT are ignored so that a majority of peers cannot either.
+/// Only the offsets of connected peers are kept, and at most MAX_CLOCK_OFFSETS of them.
+#[derive(Debug, Clone, Default)]
+pub struct NetworkTime {
+    clock_offsets: VecDeque<(SaitoPublicKey, i64)>,
+}
+
+impl NetworkTime {
+    pub fn new() -> Self {
+        NetworkTime {
+            clock_offsets: VecDeque::new(),
+        }
+    }
+
+    /// Records how many milliseconds the peer's clock is ahead of ours, replacing any earlier
+    /// offset for the same peer.
+    pub fn add_clock_offset(&mut self, publickey: SaitoPublicKey, clock_offset: i64) {
+        self.remove_clock_offset(&publickey);
+        if self.clock_offsets.len() == MAX_CLOCK_OFFSETS {
+            self.clock_offsets.pop_front();
+        }
+        self.clock_offsets.push_back((publickey, clock_offset));
+    }
+
+    /// Forgets the offset of a peer, e.g. when it disconnects.
+    pub fn remove_clock_offset(&mut self, publickey: &SaitoPublicKey) {
+        self.clock_offsets
+            .retain(|(offset_publickey, _)| offset_publickey != publickey);
+    }
+
+    pub fn get_clock_offset(&self, publickey: &SaitoPublicKey) -> Option<i64> {
+        self.clock_offsets
+            .iter()
+            .find(|(offset_publickey, _)| offset_publickey == publickey)
+            .map(|(_, clock_offset)| *clock_offset)
+    }
+
+    /// The median of the peer clock offsets, or 0 if we have none.
+    pub fn get_offset(&self) -> i64 {
+        let mut clock_offsets: Vec<i64> = self
+            .clock_offsets
+            .iter()
+            .map(|(_, clock_offset)| *clock_offset)
+            .filter(|clock_offset| clock_offset.abs() <= MAX_CLOCK_ADJUSTMENT)
+            .collect();
+        if clock_offsets.is_empty() {
+            return 0;
+        }
+        clock_offsets.sort_unstable();
+        let length = clock_offsets.len();
+        (clock_offsets[(length - 1) / 2] + clock_offsets[length / 2]) / 2
+    }
+
+    pub fn get_adjusted_timestamp(&self) -> u64 {
+        adjust_timestamp(create_timestamp(), self.get_offset())
+    }
+}
+
+fn adjust_timestamp(timestamp: u64, offset: i64) -> u64 {
+    if offset < 0 {
+        timestamp.saturating_sub(offset.unsigned_abs())
+    } else {
+        timestamp.saturating_add(offset as u64)
+    }
+}
+
 pub trait Persistable {
     fn save(&self);
     fn load(filename: &str) -> Self;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utilities::test_manager::TestManager;
+    use crate::wallet::Wallet;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    #[test]
+    fn network_time_offset_test() {
+        let mut network_time = NetworkTime::new();
+        assert_eq!(network_time.get_offset(), 0);
+
+        network_time.add_clock_offset([1; 33], 1000);
+        assert_eq!(network_time.get_offset(), 1000);
+        network_time.add_clock_offset([2; 33], -3000);
+        assert_eq!(network_time.get_offset(), -1000);
+        network_time.add_clock_offset([3; 33], 2000);
+        assert_eq!(network_time.get_offset(), 1000);
+
+        // a peer far out of line is ignored, and a peer's new offset replaces its old one
+        network_time.add_clock_offset([4; 33], MAX_CLOCK_ADJUSTMENT + 1);
+        assert_eq!(network_time.get_offset(), 1000);
+        network_time.add_clock_offset([1; 33], 5000);
+        assert_eq!(network_time.get_offset(), 2000);
+
+        // a peer which disconnects no longer counts
+        network_time.remove_clock_offset(&[1; 33]);
+        assert_eq!(network_time.get_clock_offset(&[1; 33]), None);
+        assert_eq!(network_time.get_offset(), -500);
+
+        assert_eq!(adjust_timestamp(10_000, -3000), 7000);
+        assert_eq!(adjust_timestamp(1000, -3000), 0);
+        assert_eq!(adjust_timestamp(10_000, 3000), 13_000);
+    }
+
+    #[test]
+    fn network_time_max_clock_offsets_test() {
+        let mut network_time = NetworkTime::new();
+        for i in 0..=MAX_CLOCK_OFFSETS {
+            network_time.add_clock_offset([i as u8; 33], i as i64);
+        }
+        assert_eq!(network_time.get_clock_offset(&[0; 33]), None);
+        assert_eq!(network_time.get_clock_offset(&[1; 33]), Some(1));
+
+        // a new offset for a peer makes it the newest
+        network_time.add_clock_offset([1; 33], 1);
+        network_time.add_clock_offset([0; 33], 0);
+        assert_eq!(network_time.get_clock_offset(&[1; 33]), Some(1));
+        assert_eq!(network_time.get_clock_offset(&[2; 33]), None);
+    }
+
+    #[test]
+    fn estimate_clock_offset_test() {
+        assert_eq!(estimate_clock_offset(10_000, 10_200, 10_100), Some(0));
+        assert_eq!(estimate_clock_offset(10_000, 10_200, 12_100), Some(2000));
+        assert_eq!(estimate_clock_offset(10_000, 10_200, 9_100), Some(-1000));
+
+        // clocks too far apart to be believed, however far apart they are
+        let now = create_timestamp();
+        assert_eq!(
+            estimate_clock_offset(now, now, now + MAX_CLOCK_SKEW as u64),
+            Some(MAX_CLOCK_SKEW)
+        );
+        assert_eq!(
+            estimate_clock_offset(now, now, now + MAX_CLOCK_SKEW as u64 + 1),
+            None
+        );
+        assert_eq!(estimate_clock_offset(now, now, 0), None);
+        assert_eq!(estimate_clock_offset(now, now, u64::MAX), None);
+        assert_eq!(estimate_clock_offset(u64::MAX, u64::MAX, 0), None);
+    }
+
+    #[test]
+    fn next_block_timestamp_test() {
+        assert_eq!(next_block_timestamp(10_000, 5000), 10_000);
+        assert_eq!(next_block_timestamp(5000, 5000), 5001);
+        assert_eq!(next_block_timestamp(4000, 5000), 5001);
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn median_time_past_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+
+        assert_eq!(median_time_past(&*blockchain_lock.read().await, 11), 0);
+
+        let current_timestamp = create_timestamp();
+        test_manager
+            .add_block(current_timestamp, 3, 0, false, vec![])
+            .await;
+        for i in 1..5 {
+            test_manager
+                .add_block(current_timestamp + i * 120000, 0, 1, false, vec![])
+                .await;
+        }
+
+        let blockchain = blockchain_lock.read().await;
+        assert_eq!(median_time_past(&blockchain, 1), current_timestamp + 480000);
+        assert_eq!(median_time_past(&blockchain, 3), current_timestamp + 360000);
+        assert_eq!(
+            median_time_past(&blockchain, 11),
+            current_timestamp + 240000
+        );
+    }
+}
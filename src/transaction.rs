@@ -1,10 +1,11 @@
 use std::convert::TryInto;
 
 use crate::{
+    amount::Amount,
     blockchain::UtxoSet,
     crypto::{
-        generate_random_bytes, hash, sign, verify, SaitoHash, SaitoPrivateKey, SaitoPublicKey,
-        SaitoSignature, SaitoUTXOSetKey,
+        generate_random_bytes, hash, sign, verify, ConsensusHasher, SaitoHash, SaitoPrivateKey,
+        SaitoPublicKey, SaitoSignature, SaitoUTXOSetKey,
     },
     hop::{Hop, HOP_SIZE},
     slip::{Slip, SlipType, SLIP_SIZE},
@@ -22,7 +23,10 @@ use rayon::prelude::*;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
-pub const TRANSACTION_SIZE: usize = 89;
+pub use crate::layout::TRANSACTION_SIZE;
+
+/// The most outputs a transaction may create, as their slip ordinals are a single byte.
+pub const MAX_TRANSACTION_OUTPUTS: usize = u8::MAX as usize + 1;
 
 /// TransactionType is a human-readable indicator of the type of
 /// transaction such as a normal user-initiated transaction, a
@@ -41,6 +45,71 @@ pub enum TransactionType {
     SPV,
 }
 
+//
+// the lifecycle of a slip
+//
+// slips move from one type to another only through the transactions allowed to spend
+// and create them:
+//
+//   spendable slips  -- Normal, GoldenTicket, Vip, SPV -->  Normal
+//   spendable slips  -- StakerDeposit -->  StakerDeposit, StakerDelegation (and Normal change)
+//   StakerDeposit / StakerOutput  -- Fee -->  StakerOutput, StakerPayout, MinerOutput, RouterOutput
+//   StakerWithdrawalStaking / StakerWithdrawalPending  -- StakerWithdrawal -->  Normal
+//   nothing  -- ATR -->  ATR
+//   nothing  -- Issuance -->  Normal, VipOutput, StakerDeposit
+//
+// VIP transactions create VipOutput slips, and the spendable slips are Normal, ATR,
+// VipOutput, MinerOutput, RouterOutput and StakerPayout. StakerDelegation slips hold
+// no tokens and are never spent.
+//
+impl TransactionType {
+    /// Whether a transaction of this type may spend a slip of the given type.
+    pub fn can_spend(&self, slip_type: SlipType) -> bool {
+        match self {
+            TransactionType::Fee => {
+                matches!(slip_type, SlipType::StakerDeposit | SlipType::StakerOutput)
+            }
+            TransactionType::StakerWithdrawal => matches!(
+                slip_type,
+                SlipType::StakerWithdrawalStaking | SlipType::StakerWithdrawalPending
+            ),
+            TransactionType::ATR | TransactionType::Issuance => false,
+            TransactionType::Normal
+            | TransactionType::GoldenTicket
+            | TransactionType::Vip
+            | TransactionType::StakerDeposit
+            | TransactionType::SPV => slip_type.is_spendable(),
+        }
+    }
+
+    /// Whether a transaction of this type may create a slip of the given type.
+    pub fn can_create(&self, slip_type: SlipType) -> bool {
+        match self {
+            TransactionType::Fee => matches!(
+                slip_type,
+                SlipType::MinerOutput
+                    | SlipType::RouterOutput
+                    | SlipType::StakerOutput
+                    | SlipType::StakerPayout
+            ),
+            TransactionType::ATR => slip_type == SlipType::ATR,
+            TransactionType::Vip => matches!(slip_type, SlipType::VipOutput | SlipType::Normal),
+            TransactionType::StakerDeposit => matches!(
+                slip_type,
+                SlipType::StakerDeposit | SlipType::StakerDelegation | SlipType::Normal
+            ),
+            TransactionType::Issuance => matches!(
+                slip_type,
+                SlipType::Normal | SlipType::VipOutput | SlipType::StakerDeposit
+            ),
+            TransactionType::Normal
+            | TransactionType::GoldenTicket
+            | TransactionType::StakerWithdrawal
+            | TransactionType::SPV => slip_type == SlipType::Normal,
+        }
+    }
+}
+
 #[serde_with::serde_as]
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct Transaction {
@@ -88,20 +157,25 @@ impl Transaction {
         }
     }
 
-    pub async fn add_hop_to_path(
-        &mut self,
-        wallet_lock: Arc<RwLock<Wallet>>,
-        to_publickey: SaitoPublicKey,
-    ) {
+    //
+    // sign and append a hop to the routing path which transfers this
+    // transaction from us to the next peer. this is done every time we
+    // relay a transaction so that the routing work is credited along
+    // the path.
+    //
+    pub fn add_hop(&mut self, wallet: &Wallet, next_peer_publickey: SaitoPublicKey) {
         //
         // msg is transaction signature and next peer
         //
         let mut vbytes: Vec<u8> = vec![];
         vbytes.extend(&self.get_signature());
-        vbytes.extend(&to_publickey);
+        vbytes.extend(&next_peer_publickey);
         let hash_to_sign = hash(&vbytes);
 
-        let hop = Hop::generate_hop(wallet_lock.clone(), to_publickey, hash_to_sign).await;
+        let mut hop = Hop::new();
+        hop.set_from(wallet.get_publickey());
+        hop.set_to(next_peer_publickey);
+        hop.set_sig(sign(&hash_to_sign, wallet.get_privatekey()));
 
         //
         // add to path
@@ -109,19 +183,13 @@ impl Transaction {
         self.path.push(hop);
     }
 
-    pub async fn build_last_hop(
+    pub async fn add_hop_to_path(
         &mut self,
         wallet_lock: Arc<RwLock<Wallet>>,
-        peer_pubkey: SaitoPublicKey,
-    ) -> Option<Hop> {
-        let mut vbytes: Vec<u8> = vec![];
-        vbytes.extend(&self.get_signature());
-        vbytes.extend(&peer_pubkey);
-        let hash_to_sign = hash(&vbytes);
-
-        let hop = Hop::generate_hop(wallet_lock.clone(), peer_pubkey, hash_to_sign).await;
-
-        Some(hop)
+        to_publickey: SaitoPublicKey,
+    ) {
+        let wallet = wallet_lock.read().await;
+        self.add_hop(&wallet, to_publickey);
     }
 
     pub fn validate_routing_path(&self) -> bool {
@@ -153,6 +221,54 @@ impl Transaction {
         true
     }
 
+    //
+    // validate the routing path of a transaction we have received from the network
+    //
+    // in addition to the signature and continuity checks, the path must start with
+    // the sender of the transaction and its final hop must have been signed by the
+    // peer that relayed the transaction to us and be addressed to our publickey.
+    // transactions without a routing path are only accepted directly from their
+    // sender. peer_publickey is None when the sender of the transaction is not
+    // known, such as when it is posted over HTTP.
+    //
+    pub fn validate_routing_path_for_peer(
+        &self,
+        peer_publickey: Option<SaitoPublicKey>,
+        my_publickey: SaitoPublicKey,
+    ) -> bool {
+        if self.inputs.is_empty() {
+            return false;
+        }
+        let sender_publickey = self.inputs[0].get_publickey();
+
+        if self.path.is_empty() {
+            return match peer_publickey {
+                Some(peer_publickey) => peer_publickey == sender_publickey,
+                None => true,
+            };
+        }
+
+        if !self.validate_routing_path() {
+            return false;
+        }
+
+        if self.path[0].get_from() != sender_publickey {
+            return false;
+        }
+
+        let last_hop = &self.path[self.path.len() - 1];
+        if last_hop.get_to() != my_publickey {
+            return false;
+        }
+        if let Some(peer_publickey) = peer_publickey {
+            if last_hop.get_from() != peer_publickey {
+                return false;
+            }
+        }
+
+        true
+    }
+
     //
     // this function exists largely for testing. It attempts to attach the requested fee
     // to the transaction if possible. If not possible it reverts back to a transaction
@@ -189,7 +305,7 @@ impl Transaction {
             // add the payment
             let mut output = Slip::new();
             output.set_publickey(to_publickey);
-            output.set_amount(with_payment);
+            output.set_amount(with_payment.into());
             transaction.add_output(output);
 
             transaction
@@ -212,7 +328,7 @@ impl Transaction {
                 // add the payment
                 let mut output = Slip::new();
                 output.set_publickey(to_publickey);
-                output.set_amount(with_payment);
+                output.set_amount(with_payment.into());
                 transaction.add_output(output);
 
                 return transaction;
@@ -245,13 +361,13 @@ impl Transaction {
 
             let mut input1 = Slip::new();
             input1.set_publickey(to_publickey);
-            input1.set_amount(0);
+            input1.set_amount(Amount::ZERO);
             let random_uuid = hash(&generate_random_bytes(32));
             input1.set_uuid(random_uuid);
 
             let mut output1 = Slip::new();
             output1.set_publickey(wallet_publickey);
-            output1.set_amount(0);
+            output1.set_amount(Amount::ZERO);
             output1.set_uuid([0; 32]);
 
             transaction.add_input(input1);
@@ -261,33 +377,6 @@ impl Transaction {
         }
     }
 
-    //
-    // generate a transaction that has more outputs than inputs. this is primarily being used for testing
-    // but it will be used to populate the first block with the VIP transactions in the future. VIP
-    // transactions are provided . they are distinct from normal transactions in that they do not have
-    // fees assessed for rebroadcasting. thank you to everyone who has supported Saito and help make
-    // this blockchain a reality. you deserve it.
-    //
-    pub async fn generate_vip_transaction(
-        _wallet_lock: Arc<RwLock<Wallet>>,
-        to_publickey: SaitoPublicKey,
-        with_amount: u64,
-        number_of_vip_slips: u64,
-    ) -> Transaction {
-        let mut transaction = Transaction::new();
-        transaction.set_transaction_type(TransactionType::Vip);
-
-        for _i in 0..number_of_vip_slips {
-            let mut output = Slip::new();
-            output.set_publickey(to_publickey);
-            output.set_amount(with_amount);
-            output.set_slip_type(SlipType::VipOutput);
-            transaction.add_output(output);
-        }
-
-        transaction
-    }
-
     //
     //
     // generate ATR transaction using source transaction and output slip
@@ -300,10 +389,10 @@ impl Transaction {
         with_fee: u64,
     ) -> Transaction {
         let mut transaction = Transaction::new();
-        let mut output_payment = 0;
-        if output_slip_to_rebroadcast.get_amount() > with_fee {
-            output_payment = output_slip_to_rebroadcast.get_amount() - with_fee;
-        }
+        let output_payment = output_slip_to_rebroadcast
+            .get_amount()
+            .checked_sub(with_fee.into())
+            .unwrap_or(Amount::ZERO);
 
         transaction.set_transaction_type(TransactionType::ATR);
 
@@ -363,7 +452,7 @@ impl Transaction {
         //
         for _i in 1..self.path.len() {
             // return nothing if the path is broken
-            if self.path[_i].get_to() != self.path[_i - 1].get_from() {
+            if self.path[_i].get_from() != self.path[_i - 1].get_to() {
                 return 0;
             }
 
@@ -403,10 +492,67 @@ impl Transaction {
         &self.path
     }
 
+    pub fn get_total_in(&self) -> u64 {
+        self.total_in
+    }
+
+    pub fn get_total_out(&self) -> u64 {
+        self.total_out
+    }
+
     pub fn get_total_fees(&self) -> u64 {
         self.total_fees
     }
 
+    /// The size in bytes of a serialized transaction with the given numbers of slips, bytes
+    /// of message and hops, as read from the start of its serialization.
+    pub fn calculate_serialized_size(
+        inputs_len: usize,
+        outputs_len: usize,
+        message_len: usize,
+        path_len: usize,
+    ) -> usize {
+        TRANSACTION_SIZE
+            + (inputs_len + outputs_len) * SLIP_SIZE
+            + message_len
+            + path_len * HOP_SIZE
+    }
+
+    /// The length of serialize_for_net(), without serializing the transaction.
+    pub fn get_serialized_size(&self) -> usize {
+        Transaction::calculate_serialized_size(
+            self.inputs.len(),
+            self.outputs.len(),
+            self.message.len(),
+            self.path.len(),
+        )
+    }
+
+    /// The publickey of the first input, which the transaction comes from, or None if it has no
+    /// inputs.
+    pub fn get_sender(&self) -> Option<SaitoPublicKey> {
+        self.inputs.first().map(|input| input.get_publickey())
+    }
+
+    /// The staker keys a StakerDeposit transaction delegates and the keys it delegates them to,
+    /// see Staking::get_delegate.
+    pub fn get_delegations(&self) -> Vec<(SaitoPublicKey, SaitoPublicKey)> {
+        if self.transaction_type != TransactionType::StakerDeposit {
+            return vec![];
+        }
+        self.outputs
+            .windows(2)
+            .filter(|outputs| outputs[1].get_slip_type() == SlipType::StakerDelegation)
+            .map(|outputs| (outputs[0].get_publickey(), outputs[1].get_publickey()))
+            .collect()
+    }
+
+    /// The fees paid per serialized byte, in nolan and rounded down. The fees are counted by
+    /// generate_metadata().
+    pub fn get_fee_rate(&self) -> u64 {
+        self.total_fees / self.get_serialized_size() as u64
+    }
+
     pub fn get_timestamp(&self) -> u64 {
         self.timestamp
     }
@@ -443,6 +589,24 @@ impl Transaction {
         self.signature
     }
 
+    /// A readable view of the transaction for the REST API. Hashes, keys and the message are
+    /// hex encoded, and the slips and routing path are nested.
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "hash": self.hash_for_signature.map(hex::encode),
+            "timestamp": self.timestamp,
+            "transaction_type": format!("{:?}", self.transaction_type),
+            "inputs": self.inputs.iter().map(Slip::to_json).collect::<Vec<_>>(),
+            "outputs": self.outputs.iter().map(Slip::to_json).collect::<Vec<_>>(),
+            "message": hex::encode(&self.message),
+            "signature": hex::encode(self.signature),
+            "path": self.path.iter().map(Hop::to_json).collect::<Vec<_>>(),
+            "total_in": self.total_in,
+            "total_out": self.total_out,
+            "total_fees": self.total_fees,
+        })
+    }
+
     pub fn get_winning_routing_node(&self, random_hash: SaitoHash) -> SaitoPublicKey {
         //
         // if there are no routing paths, we return the sender of
@@ -546,11 +710,46 @@ impl Transaction {
             output.set_slip_ordinal(i as u8);
         }
 
-        let hash_for_signature = hash(&self.serialize_for_signature());
+        let hash_for_signature = self.generate_hash_for_signature();
         self.set_signature(sign(&hash_for_signature, privatekey));
         self.set_hash_for_signature(hash_for_signature);
     }
 
+    //
+    // output slips take the hash_for_signature as their UUID. Outputs are signed with
+    // zero'd-out UUIDs, so this can run before the transaction is signed and nothing
+    // changes once it is. Slip ordinals must already be set. ATR slips keep their UUID.
+    //
+    pub fn generate_output_uuids(&mut self) {
+        let hash_for_signature: SaitoHash = self.generate_hash_for_signature();
+        self.set_hash_for_signature(hash_for_signature);
+        for output in &mut self.outputs {
+            if output.get_slip_type() != SlipType::ATR {
+                output.set_uuid(hash_for_signature);
+            }
+        }
+    }
+
+    /// Writes serialize_for_signature() into the hasher without allocating.
+    pub fn hash_for_signature_into(&self, hasher: &mut ConsensusHasher) {
+        hasher.update(&self.timestamp.to_be_bytes());
+        for input in &self.inputs {
+            input.hash_input_for_signature_into(hasher);
+        }
+        for output in &self.outputs {
+            output.hash_output_for_signature_into(hasher);
+        }
+        hasher.update(&(self.transaction_type as u32).to_be_bytes());
+        hasher.update(&self.message);
+    }
+
+    /// The hash of serialize_for_signature(), computed without serializing the transaction.
+    pub fn generate_hash_for_signature(&self) -> SaitoHash {
+        let mut hasher = ConsensusHasher::default();
+        self.hash_for_signature_into(&mut hasher);
+        hasher.finalize()
+    }
+
     pub fn serialize_for_signature(&self) -> Vec<u8> {
         //
         // fastest known way that isn't bincode ??
@@ -580,6 +779,35 @@ impl Transaction {
     /// [output][output][output]...
     /// [message]
     /// [hop][hop][hop]...
+    /// Like deserialize_from_net, but for bytes from outside the node: None unless the bytes are
+    /// exactly one transaction, of a known type and with slips of known types.
+    pub fn try_deserialize_from_net(bytes: &[u8]) -> Option<Transaction> {
+        if bytes.len() < TRANSACTION_SIZE {
+            return None;
+        }
+        let inputs_len = u32::from_be_bytes(bytes[0..4].try_into().unwrap()) as usize;
+        let outputs_len = u32::from_be_bytes(bytes[4..8].try_into().unwrap()) as usize;
+        let message_len = u32::from_be_bytes(bytes[8..12].try_into().unwrap()) as usize;
+        let path_len = u32::from_be_bytes(bytes[12..16].try_into().unwrap()) as usize;
+        if TransactionType::try_from(bytes[88]).is_err()
+            || bytes.len()
+                != Transaction::calculate_serialized_size(
+                    inputs_len,
+                    outputs_len,
+                    message_len,
+                    path_len,
+                )
+        {
+            return None;
+        }
+        let slip_types_are_known = (0..inputs_len + outputs_len)
+            .all(|n| SlipType::try_from(bytes[TRANSACTION_SIZE + (n + 1) * SLIP_SIZE - 1]).is_ok());
+        if !slip_types_are_known {
+            return None;
+        }
+        Some(Transaction::deserialize_from_net(bytes.to_vec()))
+    }
+
     pub fn deserialize_from_net(bytes: Vec<u8>) -> Transaction {
         let inputs_len: u32 = u32::from_be_bytes(bytes[0..4].try_into().unwrap());
         let outputs_len: u32 = u32::from_be_bytes(bytes[4..8].try_into().unwrap());
@@ -641,19 +869,11 @@ impl Transaction {
     /// [message]
     /// [hop][hop][hop]...
     pub fn serialize_for_net(&self) -> Vec<u8> {
-        self.serialize_for_net_with_hop(None)
-    }
-
-    pub(crate) fn serialize_for_net_with_hop(&self, opt_hop: Option<Hop>) -> Vec<u8> {
         let mut vbytes: Vec<u8> = vec![];
         vbytes.extend(&(self.inputs.len() as u32).to_be_bytes());
         vbytes.extend(&(self.outputs.len() as u32).to_be_bytes());
         vbytes.extend(&(self.message.len() as u32).to_be_bytes());
-        let mut path_len = self.path.len();
-        if !opt_hop.is_none() {
-            path_len = path_len + 1;
-        }
-        vbytes.extend(&(path_len as u32).to_be_bytes());
+        vbytes.extend(&(self.path.len() as u32).to_be_bytes());
         vbytes.extend(&self.signature);
         vbytes.extend(&self.timestamp.to_be_bytes());
         vbytes.extend(&(self.transaction_type as u8).to_be_bytes());
@@ -667,9 +887,6 @@ impl Transaction {
         for hop in &self.path {
             vbytes.extend(&hop.serialize_for_net());
         }
-        if !opt_hop.is_none() {
-            vbytes.extend(opt_hop.unwrap().serialize_for_net());
-        }
         vbytes
     }
 
@@ -709,11 +926,13 @@ impl Transaction {
     }
 
     //
-    // calculate cumulative fee share in block
+    // calculate cumulative fee share in block, or None if it overflows
     //
-    pub fn generate_metadata_cumulative_fees(&mut self, cumulative_fees: u64) -> u64 {
-        self.cumulative_fees = cumulative_fees + self.total_fees;
-        self.cumulative_fees
+    pub fn generate_metadata_cumulative_fees(&mut self, cumulative_fees: u64) -> Option<u64> {
+        self.cumulative_fees = Amount::from(cumulative_fees)
+            .checked_add(self.total_fees.into())?
+            .into();
+        Some(self.cumulative_fees)
     }
     //
     // calculate cumulative routing work in block
@@ -732,7 +951,7 @@ impl Transaction {
         // output slips created in this blog. Otherwise, our hash for the transaction
         // will change since the slips will be generated with a different UUID.
         //
-        let hash_for_signature: SaitoHash = hash(&self.serialize_for_signature());
+        let hash_for_signature: SaitoHash = self.generate_hash_for_signature();
         self.set_hash_for_signature(hash_for_signature);
 
         true
@@ -747,13 +966,17 @@ impl Transaction {
         //
         // calculate nolan in / out, fees
         //
-        let mut nolan_in: u64 = 0;
-        let mut nolan_out: u64 = 0;
-        let hash_for_signature = self.get_hash_for_signature();
+        //
+        // fee transactions have their UUIDs set before signing, and these are checked
+        // against the consensus values in validate(), so we must not overwrite them
+        //
+        let hash_for_signature = if self.is_fee_transaction() {
+            None
+        } else {
+            self.get_hash_for_signature()
+        };
 
         for input in &mut self.inputs {
-            nolan_in += input.get_amount();
-
             // generate utxoset key cache
 
             //
@@ -765,8 +988,6 @@ impl Transaction {
             input.generate_utxoset_key();
         }
         for output in &mut self.outputs {
-            nolan_out += output.get_amount();
-
             //
             // generate utxoset key cache
             // and set the UUID needed for insertion to shashmap
@@ -780,8 +1001,14 @@ impl Transaction {
             output.generate_utxoset_key();
         }
 
-        self.total_in = nolan_in;
-        self.total_out = nolan_out;
+        //
+        // amounts which overflow are capped here and rejected in validate()
+        //
+        let nolan_in: Amount = self.inputs.iter().map(|input| input.get_amount()).sum();
+        let nolan_out: Amount = self.outputs.iter().map(|output| output.get_amount()).sum();
+
+        self.total_in = nolan_in.into();
+        self.total_out = nolan_out.into();
         self.total_fees = 0;
 
         //
@@ -790,8 +1017,8 @@ impl Transaction {
         // monetary policy. All sanity checks need to be in the validate()
         // function.
         //
-        if nolan_in > nolan_out {
-            self.total_fees = nolan_in - nolan_out;
+        if let Some(total_fees) = nolan_in.checked_sub(nolan_out) {
+            self.total_fees = total_fees.into();
         }
 
         //
@@ -809,7 +1036,104 @@ impl Transaction {
         true
     }
 
+    /// Whether the transaction only spends and creates the slip types its type allows.
+    pub fn validate_slip_types(&self) -> bool {
+        let transaction_type = self.get_transaction_type();
+        self.inputs
+            .iter()
+            .all(|input| transaction_type.can_spend(input.get_slip_type()))
+            && self
+                .outputs
+                .iter()
+                .all(|output| transaction_type.can_create(output.get_slip_type()))
+    }
+
     pub fn validate(&self, utxoset: &UtxoSet, staking: &Staking) -> bool {
+        self.validate_signatures() && self.validate_against_utxoset(utxoset, staking)
+    }
+
+    /// Checks the signature and the routing path of user-sent transactions. Unlike the
+    /// rest of validate() this does not depend on the state of the chain, so it can be done
+    /// before the blockchain is locked.
+    pub fn validate_signatures(&self) -> bool {
+        //
+        // most transactions are identifiable by the publickey that
+        // has signed their input transaction, but some transactions
+        // do not have senders as they are auto-generated as part of
+        // the block itself.
+        //
+        // ATR transactions
+        // VIP transactions
+        // FEE transactions
+        //
+        let transaction_type = self.get_transaction_type();
+        if transaction_type == TransactionType::Fee
+            || transaction_type == TransactionType::ATR
+            || transaction_type == TransactionType::Vip
+            || transaction_type == TransactionType::Issuance
+        {
+            return true;
+        }
+
+        //
+        // validate sender exists
+        //
+        if self.get_inputs().is_empty() {
+            error!("ERROR 582039: less than 1 input in transaction");
+            return false;
+        }
+
+        //
+        // validate signature
+        //
+        if let Some(hash_for_signature) = self.get_hash_for_signature() {
+            let sig: SaitoSignature = self.get_signature();
+            let publickey: SaitoPublicKey = self.get_inputs()[0].get_publickey();
+            if !verify(&hash_for_signature, sig, publickey) {
+                error!("message verifies not");
+                return false;
+            }
+        } else {
+            //
+            // we reach here if we have not already calculated the hash
+            // that is checked by the signature. while we could auto-gen
+            // it here, we choose to throw an error to raise visibility of
+            // unexpected behavior.
+            //
+            error!("ERROR 757293: there is no hash for signature in a transaction");
+            return false;
+        }
+
+        //
+        // validate routing path sigs
+        //
+        // a transaction without routing paths is valid, and pays off the
+        // sender in the payment lottery. but a transaction with an invalid
+        // routing path is fraudulent.
+        //
+        if !self.validate_routing_path() {
+            error!("ERROR 482033: routing paths do not validate, transaction invalid");
+            return false;
+        }
+
+        true
+    }
+
+    /// The part of validate() which depends on the state of the chain. The signatures are
+    /// assumed to have been checked with validate_signatures().
+    pub fn validate_against_utxoset(&self, utxoset: &UtxoSet, staking: &Staking) -> bool {
+        //
+        // slips of every transaction, including fee transactions, must move
+        // through their lifecycle in the order it allows
+        //
+        if !self.validate_slip_types() {
+            error!(
+                "ERROR 573910: {:?} transaction moves slips through an illegal type transition",
+                self.get_transaction_type()
+            );
+            return false;
+        }
+
         //
         // Fee Transactions are validated in the block class. There can only
         // be one per block, and they are checked by ensuring the transaction hash
@@ -824,20 +1148,10 @@ impl Transaction {
         //
         // User-Sent Transactions
         //
-        // most transactions are identifiable by the publickey that
-        // has signed their input transaction, but some transactions
-        // do not have senders as they are auto-generated as part of
-        // the block itself.
-        //
-        // ATR transactions
-        // VIP transactions
-        // FEE transactions
-        //
         // the first set of validation criteria is applied only to
-        // user-sent transactions. validation criteria for the above
-        // classes of transactions are further down in this function.
-        // at the bottom is the validation criteria applied to ALL
-        // transaction types.
+        // user-sent transactions. validation criteria for auto-generated
+        // transactions are further down in this function. at the bottom
+        // is the validation criteria applied to ALL transaction types.
         //
         let transaction_type = self.get_transaction_type();
 
@@ -847,43 +1161,13 @@ impl Transaction {
             && transaction_type != TransactionType::Issuance
         {
             //
-            // validate signature
+            // validate the amounts do not overflow
             //
-            if let Some(hash_for_signature) = self.get_hash_for_signature() {
-                let sig: SaitoSignature = self.get_signature();
-                let publickey: SaitoPublicKey = self.get_inputs()[0].get_publickey();
-                if !verify(&hash_for_signature, sig, publickey) {
-                    error!("message verifies not");
-                    return false;
-                }
-            } else {
-                //
-                // we reach here if we have not already calculated the hash
-                // that is checked by the signature. while we could auto-gen
-                // it here, we choose to throw an error to raise visibility of
-                // unexpected behavior.
-                //
-                error!("ERROR 757293: there is no hash for signature in a transaction");
-                return false;
-            }
-
-            //
-            // validate sender exists
-            //
-            if self.get_inputs().is_empty() {
-                error!("ERROR 582039: less than 1 input in transaction");
-                return false;
-            }
-
-            //
-            // validate routing path sigs
-            //
-            // a transaction without routing paths is valid, and pays off the
-            // sender in the payment lottery. but a transaction with an invalid
-            // routing path is fraudulent.
-            //
-            if !self.validate_routing_path() {
-                error!("ERROR 482033: routing paths do not validate, transaction invalid");
+            if Amount::checked_sum(self.inputs.iter().map(|input| input.get_amount())).is_none()
+                || Amount::checked_sum(self.outputs.iter().map(|output| output.get_amount()))
+                    .is_none()
+            {
+                error!("ERROR 672942: transaction amounts overflow, transaction invalid");
                 return false;
             }
 
@@ -946,6 +1230,29 @@ impl Transaction {
             }
         }
 
+        //
+        // Staking Deposit Transactions
+        //
+        // a delegation must directly follow the deposit it delegates, and the
+        // deposit must be made by the key which signs the transaction, so that
+        // nobody can redirect the payouts of a stake they do not hold.
+        //
+        if transaction_type == TransactionType::StakerDeposit {
+            for i in 0..self.outputs.len() {
+                if self.outputs[i].get_slip_type() != SlipType::StakerDelegation {
+                    continue;
+                }
+                if i == 0
+                    || self.inputs.is_empty()
+                    || self.outputs[i - 1].get_publickey() != self.inputs[0].get_publickey()
+                    || !staking.validate_delegation(&self.outputs[i - 1], &self.outputs[i])
+                {
+                    error!("ERROR 573911: staker delegation is invalid, transaction invalid");
+                    return false;
+                }
+            }
+        }
+
         //
         // vip transactions
         //
@@ -955,8 +1262,9 @@ impl Transaction {
         // for the faith and support.
         //
         if transaction_type == TransactionType::Vip {
-            // we should validate that VIP transactions are signed by the
-            // publickey associated with the Saito project.
+            // which blocks may contain VIP transactions and who may sign
+            // them is checked against the VipPolicy of the network when
+            // the block is validated.
         }
 
         //
@@ -973,6 +1281,17 @@ impl Transaction {
             return false;
         }
 
+        //
+        // and no more outputs than slip ordinals can tell apart
+        //
+        if self.get_outputs().len() > MAX_TRANSACTION_OUTPUTS {
+            error!(
+                "ERROR 582040: more than {} outputs in transaction",
+                MAX_TRANSACTION_OUTPUTS
+            );
+            return false;
+        }
+
         //
         // if inputs exist, they must validate against the UTXOSET
         // if they claim to spend tokens. if the slip has no spendable
@@ -998,7 +1317,9 @@ impl Transaction {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{slip::Slip, time::create_timestamp, wallet::Wallet};
+    use crate::{
+        crypto::PARALLEL_HASH_BYTE_THRESHOLD, slip::Slip, time::create_timestamp, wallet::Wallet,
+    };
     use hex::FromHex;
 
     #[test]
@@ -1063,7 +1384,7 @@ mod tests {
             )
             .unwrap(),
         );
-        input_slip.set_amount(123);
+        input_slip.set_amount(Amount::from_nolan(123));
         input_slip.set_slip_ordinal(10);
         input_slip.set_slip_type(SlipType::ATR);
 
@@ -1080,7 +1401,7 @@ mod tests {
             )
             .unwrap(),
         );
-        output_slip.set_amount(345);
+        output_slip.set_amount(Amount::from_nolan(345));
         output_slip.set_slip_ordinal(23);
         output_slip.set_slip_type(SlipType::Normal);
 
@@ -1126,7 +1447,7 @@ mod tests {
             )
             .unwrap(),
         );
-        input_slip.set_amount(123);
+        input_slip.set_amount(Amount::from_nolan(123));
         input_slip.set_slip_ordinal(10);
         input_slip.set_slip_type(SlipType::ATR);
 
@@ -1143,7 +1464,7 @@ mod tests {
             )
             .unwrap(),
         );
-        output_slip.set_amount(345);
+        output_slip.set_amount(Amount::from_nolan(345));
         output_slip.set_slip_ordinal(23);
         output_slip.set_slip_type(SlipType::Normal);
 
@@ -1169,11 +1490,259 @@ mod tests {
         );
     }
 
+    #[test]
+    fn slip_type_transitions_test() {
+        assert!(TransactionType::Normal.can_spend(SlipType::MinerOutput));
+        assert!(!TransactionType::Normal.can_spend(SlipType::StakerDeposit));
+        assert!(!TransactionType::Normal.can_create(SlipType::StakerOutput));
+        assert!(TransactionType::StakerDeposit.can_create(SlipType::StakerDeposit));
+        assert!(TransactionType::Fee.can_spend(SlipType::StakerDeposit));
+        assert!(TransactionType::Fee.can_create(SlipType::RouterOutput));
+        assert!(!TransactionType::Fee.can_create(SlipType::Normal));
+        assert!(TransactionType::StakerWithdrawal.can_spend(SlipType::StakerWithdrawalPending));
+        assert!(!TransactionType::StakerWithdrawal.can_spend(SlipType::Normal));
+        assert!(!TransactionType::ATR.can_spend(SlipType::ATR));
+        assert!(!TransactionType::ATR.can_create(SlipType::Normal));
+        assert!(TransactionType::StakerDeposit.can_create(SlipType::StakerDelegation));
+        assert!(!TransactionType::Normal.can_create(SlipType::StakerDelegation));
+        assert!(TransactionType::Fee.can_create(SlipType::StakerPayout));
+        assert!(TransactionType::Normal.can_spend(SlipType::StakerPayout));
+        assert!(!TransactionType::Normal.can_spend(SlipType::StakerDelegation));
+    }
+
+    #[test]
+    fn validate_slip_types_test() {
+        let wallet = Wallet::new();
+        let mut input = Slip::new();
+        input.set_publickey(wallet.get_publickey());
+        let mut output = Slip::new();
+        output.set_publickey(wallet.get_publickey());
+        output.set_slip_type(SlipType::StakerOutput);
+
+        let mut tx = Transaction::new();
+        tx.add_input(input);
+        tx.add_output(output);
+        tx.sign(wallet.get_privatekey());
+        assert!(!tx.validate_slip_types());
+        assert!(!tx.validate(&UtxoSet::default(), &Staking::new()));
+
+        tx.get_mut_outputs()[0].set_slip_type(SlipType::Normal);
+        tx.sign(wallet.get_privatekey());
+        assert!(tx.validate_slip_types());
+        assert!(tx.validate(&UtxoSet::default(), &Staking::new()));
+
+        tx.set_transaction_type(TransactionType::Fee);
+        assert!(!tx.validate(&UtxoSet::default(), &Staking::new()));
+    }
+
+    #[test]
+    fn validate_relabeled_input_test() {
+        let wallet = Wallet::new();
+        let mut utxoset = UtxoSet::default();
+        for (uuid, slip_type) in [
+            ([1; 32], SlipType::Normal),
+            ([2; 32], SlipType::StakerDeposit),
+        ] {
+            let mut slip = Slip::new();
+            slip.set_publickey(wallet.get_publickey());
+            slip.set_uuid(uuid);
+            slip.set_amount(Amount::from_nolan(1_000));
+            slip.set_slip_type(slip_type);
+            slip.generate_utxoset_key();
+            utxoset.insert(slip.get_utxoset_key(), 1);
+        }
+
+        // a staked slip spent as a normal one is not found in the utxoset
+        for (uuid, is_valid) in [([1; 32], true), ([2; 32], false)] {
+            let mut input = Slip::new();
+            input.set_publickey(wallet.get_publickey());
+            input.set_uuid(uuid);
+            input.set_amount(Amount::from_nolan(1_000));
+            let mut output = Slip::new();
+            output.set_publickey(wallet.get_publickey());
+            output.set_amount(Amount::from_nolan(1_000));
+
+            let mut tx = Transaction::new();
+            tx.add_input(input);
+            tx.add_output(output);
+            tx.sign(wallet.get_privatekey());
+            tx.generate_metadata(wallet.get_publickey());
+            assert_eq!(tx.validate(&utxoset, &Staking::new()), is_valid);
+        }
+    }
+
+    #[test]
+    fn validate_max_transaction_outputs_test() {
+        let wallet = Wallet::new();
+        let mut input = Slip::new();
+        input.set_publickey(wallet.get_publickey());
+
+        let mut tx = Transaction::new();
+        tx.add_input(input);
+        for _ in 0..MAX_TRANSACTION_OUTPUTS {
+            let mut output = Slip::new();
+            output.set_publickey(wallet.get_publickey());
+            tx.add_output(output);
+        }
+        tx.sign(wallet.get_privatekey());
+        assert!(tx.validate(&UtxoSet::default(), &Staking::new()));
+
+        tx.add_output(Slip::new());
+        tx.sign(wallet.get_privatekey());
+        assert!(!tx.validate(&UtxoSet::default(), &Staking::new()));
+    }
+
+    #[test]
+    fn transaction_serialized_size_and_fee_rate_test() {
+        let wallet = Wallet::new();
+        let mut input = Slip::new();
+        input.set_amount(Amount::from_nolan(10_000));
+        let mut output = Slip::new();
+        output.set_amount(Amount::from_nolan(1_000));
+
+        let mut tx = Transaction::new();
+        tx.add_input(input);
+        tx.add_output(output);
+        tx.set_message(vec![1; 100]);
+        tx.set_path(vec![Hop::new()]);
+        assert_eq!(tx.get_serialized_size(), tx.serialize_for_net().len());
+        assert_eq!(
+            tx.get_serialized_size(),
+            TRANSACTION_SIZE + 2 * SLIP_SIZE + 100 + HOP_SIZE
+        );
+
+        assert_eq!(tx.get_fee_rate(), 0);
+        tx.generate_metadata(wallet.get_publickey());
+        assert_eq!(tx.get_fee_rate(), 9_000 / tx.get_serialized_size() as u64);
+    }
+
+    #[test]
+    fn transaction_generate_hash_for_signature_test() {
+        let wallet = Wallet::new();
+        let mut input = Slip::new();
+        input.set_publickey(wallet.get_publickey());
+        input.set_amount(Amount::from_nolan(10_000));
+        input.set_uuid([7; 32]);
+        input.set_slip_type(SlipType::ATR);
+        let mut output = Slip::new();
+        output.set_publickey(wallet.get_publickey());
+        output.set_amount(Amount::from_nolan(1_000));
+        output.set_uuid([9; 32]);
+        output.set_slip_ordinal(1);
+
+        let mut tx = Transaction::new();
+        tx.set_timestamp(create_timestamp());
+        tx.add_input(input);
+        tx.add_output(output);
+        tx.set_transaction_type(TransactionType::Vip);
+        assert_eq!(
+            tx.generate_hash_for_signature(),
+            hash(&tx.serialize_for_signature())
+        );
+
+        // large enough to be hashed in parallel
+        tx.set_message(vec![3; PARALLEL_HASH_BYTE_THRESHOLD + 1]);
+        assert_eq!(
+            tx.generate_hash_for_signature(),
+            hash(&tx.serialize_for_signature())
+        );
+    }
+
     #[test]
     fn transaction_generate_metadata_cumulative_fees_test() {
         let mut tx = Transaction::new();
-        tx.generate_metadata_cumulative_fees(1_0000);
+        assert_eq!(tx.generate_metadata_cumulative_fees(1_0000), Some(1_0000));
         assert_eq!(tx.cumulative_fees, 1_0000);
+
+        tx.total_fees = 1;
+        assert_eq!(tx.generate_metadata_cumulative_fees(u64::MAX), None);
+    }
+
+    #[test]
+    fn transaction_add_hop_test() {
+        let wallet = Wallet::new();
+        let next_peer_publickey = Wallet::new().get_publickey();
+        let mut tx = Transaction::new();
+        tx.set_outputs(vec![Slip::new()]);
+        tx.sign(wallet.get_privatekey());
+
+        tx.add_hop(&wallet, next_peer_publickey);
+
+        assert_eq!(tx.get_path().len(), 1);
+        assert_eq!(tx.get_path()[0].get_from(), wallet.get_publickey());
+        assert_eq!(tx.get_path()[0].get_to(), next_peer_publickey);
+        assert!(tx.validate_routing_path());
+
+        let deserialized_tx = Transaction::deserialize_from_net(tx.serialize_for_net());
+        assert_eq!(deserialized_tx.get_path(), tx.get_path());
+    }
+
+    #[tokio::test]
+    async fn validate_routing_path_for_peer_test() {
+        let sender_wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let relay_wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let (sender_publickey, sender_privatekey) = {
+            let wallet = sender_wallet_lock.read().await;
+            (wallet.get_publickey(), wallet.get_privatekey())
+        };
+        let relay_publickey = relay_wallet_lock.read().await.get_publickey();
+        let my_publickey = Wallet::new().get_publickey();
+
+        let mut input = Slip::new();
+        input.set_publickey(sender_publickey);
+        let mut tx = Transaction::new();
+        tx.add_input(input);
+        tx.add_output(Slip::new());
+        tx.sign(sender_privatekey);
+
+        // sender may hand us the transaction directly without a path
+        assert!(tx.validate_routing_path_for_peer(Some(sender_publickey), my_publickey));
+        assert!(!tx.validate_routing_path_for_peer(Some(relay_publickey), my_publickey));
+
+        tx.add_hop_to_path(sender_wallet_lock.clone(), relay_publickey)
+            .await;
+        tx.add_hop_to_path(relay_wallet_lock.clone(), my_publickey)
+            .await;
+
+        assert!(tx.validate_routing_path_for_peer(Some(relay_publickey), my_publickey));
+        assert!(tx.validate_routing_path_for_peer(None, my_publickey));
+        assert!(!tx.validate_routing_path_for_peer(Some(sender_publickey), my_publickey));
+        assert!(!tx.validate_routing_path_for_peer(Some(relay_publickey), relay_publickey));
+
+        // a path which does not start with the sender is forged
+        let mut forged_tx = tx.clone();
+        forged_tx.set_path(vec![tx.get_path()[1].clone()]);
+        assert!(!forged_tx.validate_routing_path_for_peer(Some(relay_publickey), my_publickey));
+    }
+
+    #[test]
+    fn get_routing_work_for_publickey_test() {
+        let sender_publickey = Wallet::new().get_publickey();
+        let relay_publickey = Wallet::new().get_publickey();
+        let my_publickey = Wallet::new().get_publickey();
+        let create_hop = |from: SaitoPublicKey, to: SaitoPublicKey| {
+            let mut hop = Hop::new();
+            hop.set_from(from);
+            hop.set_to(to);
+            hop
+        };
+
+        let mut tx = Transaction::new();
+        tx.total_fees = 1000;
+        tx.set_path(vec![
+            create_hop(sender_publickey, relay_publickey),
+            create_hop(relay_publickey, my_publickey),
+        ]);
+        // the first hop gets all the routing work, and every later hop half of it
+        assert_eq!(tx.get_routing_work_for_publickey(my_publickey), 500);
+        assert_eq!(tx.get_routing_work_for_publickey(relay_publickey), 0);
+
+        // a hop which does not start where the previous hop ended breaks the path
+        tx.set_path(vec![
+            create_hop(sender_publickey, relay_publickey),
+            create_hop(sender_publickey, my_publickey),
+        ]);
+        assert_eq!(tx.get_routing_work_for_publickey(my_publickey), 0);
     }
 
     #[test]
@@ -1199,6 +1768,23 @@ mod tests {
 
         let serialized_tx = mock_tx.serialize_for_net();
 
+        assert_eq!(
+            Transaction::try_deserialize_from_net(&serialized_tx),
+            Some(mock_tx.clone())
+        );
+        // bytes which are not exactly one transaction are rejected
+        assert_eq!(
+            Transaction::try_deserialize_from_net(&serialized_tx[..serialized_tx.len() - 1]),
+            None
+        );
+        assert_eq!(Transaction::try_deserialize_from_net(&[0; 16]), None);
+        let mut unknown_slip_type = serialized_tx.clone();
+        unknown_slip_type[TRANSACTION_SIZE + SLIP_SIZE - 1] = u8::MAX;
+        assert_eq!(
+            Transaction::try_deserialize_from_net(&unknown_slip_type),
+            None
+        );
+
         let deserialized_tx = Transaction::deserialize_from_net(serialized_tx);
         assert_eq!(mock_tx, deserialized_tx);
     }
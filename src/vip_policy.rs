@@ -0,0 +1,183 @@
+use crate::block::Block;
+use crate::configuration::VipPolicySettings;
+use crate::crypto::{publickey_from_base58, verify, SaitoPublicKey};
+use crate::slip::{Slip, SlipType};
+use crate::transaction::{Transaction, TransactionType};
+use crate::wallet::Wallet;
+use base58::ToBase58;
+use tracing::error;
+
+/// Decides where VIP transactions may appear on a network and who may issue them. VIP
+/// transactions create new supply, so a block may only contain them if its id is within the
+/// bootstrap window and each of them is signed by an authorized key. If no keys are
+/// authorized, the creator of the block is the only key allowed to sign its VIP transactions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VipPolicy {
+    bootstrap_window: u64,
+    authorized_publickeys: Vec<SaitoPublicKey>,
+}
+
+impl VipPolicy {
+    pub fn new(bootstrap_window: u64, authorized_publickeys: Vec<SaitoPublicKey>) -> Self {
+        VipPolicy {
+            bootstrap_window,
+            authorized_publickeys,
+        }
+    }
+
+    pub fn from_settings(vip_policy_settings: &VipPolicySettings) -> crate::Result<VipPolicy> {
+        Ok(VipPolicy {
+            bootstrap_window: vip_policy_settings.bootstrap_window,
+            authorized_publickeys: vip_policy_settings
+                .authorized_publickeys
+                .iter()
+                .map(|publickey| publickey_from_base58(publickey))
+                .collect::<crate::Result<_>>()?,
+        })
+    }
+
+    pub fn to_settings(&self) -> VipPolicySettings {
+        VipPolicySettings {
+            bootstrap_window: self.bootstrap_window,
+            authorized_publickeys: self
+                .authorized_publickeys
+                .iter()
+                .map(|publickey| publickey.to_base58())
+                .collect(),
+        }
+    }
+
+    pub fn get_bootstrap_window(&self) -> u64 {
+        self.bootstrap_window
+    }
+
+    pub fn get_authorized_publickeys(&self) -> &Vec<SaitoPublicKey> {
+        &self.authorized_publickeys
+    }
+
+    pub fn is_in_bootstrap_window(&self, block_id: u64) -> bool {
+        block_id <= self.bootstrap_window
+    }
+
+    /// Creates a VIP transaction paying out the outputs, signed by the wallet.
+    pub fn create_vip_transaction(
+        &self,
+        wallet: &Wallet,
+        outputs: &[(SaitoPublicKey, u64)],
+    ) -> Transaction {
+        let mut transaction = Transaction::new();
+        transaction.set_transaction_type(TransactionType::Vip);
+
+        for (publickey, amount) in outputs {
+            let mut output = Slip::new();
+            output.set_publickey(*publickey);
+            output.set_amount((*amount).into());
+            output.set_slip_type(SlipType::VipOutput);
+            transaction.add_output(output);
+        }
+
+        transaction.sign(wallet.get_privatekey());
+        transaction
+    }
+
+    /// Checks every VIP transaction in the block is allowed by the policy.
+    pub fn validate_block(&self, block: &Block) -> bool {
+        let vip_transactions: Vec<&Transaction> = block
+            .get_transactions()
+            .iter()
+            .filter(|transaction| transaction.get_transaction_type() == TransactionType::Vip)
+            .collect();
+        if vip_transactions.is_empty() {
+            return true;
+        }
+
+        if !self.is_in_bootstrap_window(block.get_id()) {
+            error!(
+                "ERROR 639201: block {} contains VIP transactions outside of the bootstrap window of {} blocks",
+                block.get_id(),
+                self.bootstrap_window
+            );
+            return false;
+        }
+
+        let authorized_publickeys = if self.authorized_publickeys.is_empty() {
+            vec![block.get_creator()]
+        } else {
+            self.authorized_publickeys.clone()
+        };
+        for transaction in vip_transactions {
+            let hash_for_signature = transaction.generate_hash_for_signature();
+            if !authorized_publickeys.iter().any(|publickey| {
+                verify(&hash_for_signature, transaction.get_signature(), *publickey)
+            }) {
+                error!("ERROR 639202: VIP transaction is not signed by an authorized key");
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+impl Default for VipPolicy {
+    /// VIP transactions may only appear in block 1, signed by its creator.
+    fn default() -> Self {
+        VipPolicy::new(1, vec![])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::generate_keys;
+    use crate::time::create_timestamp;
+
+    fn create_block_with_vip_transaction(
+        block_id: u64,
+        block_wallet: &Wallet,
+        vip_wallet: &Wallet,
+    ) -> Block {
+        let vip_transaction = VipPolicy::default()
+            .create_vip_transaction(vip_wallet, &[(vip_wallet.get_publickey(), 1_000_000)]);
+        let mut block = Block::new();
+        block.set_id(block_id);
+        block.set_timestamp(create_timestamp());
+        block.set_transactions(&mut vec![vip_transaction]);
+        block.set_merkle_root(block.generate_merkle_root());
+        block.sign(block_wallet.get_publickey(), block_wallet.get_privatekey());
+        block
+    }
+
+    #[test]
+    fn vip_policy_from_settings_test() {
+        let (publickey, _) = generate_keys();
+        let vip_policy = VipPolicy::new(3, vec![publickey]);
+        assert_eq!(
+            VipPolicy::from_settings(&vip_policy.to_settings()).unwrap(),
+            vip_policy
+        );
+        assert!(VipPolicy::from_settings(&VipPolicySettings {
+            bootstrap_window: 1,
+            authorized_publickeys: vec![String::from("not a publickey")],
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn vip_policy_validate_block_test() {
+        let wallet = Wallet::new();
+        let other_wallet = Wallet::new();
+
+        let block = create_block_with_vip_transaction(1, &wallet, &wallet);
+        assert!(VipPolicy::default().validate_block(&block));
+        assert!(!VipPolicy::new(1, vec![other_wallet.get_publickey()]).validate_block(&block));
+
+        let block = create_block_with_vip_transaction(2, &wallet, &wallet);
+        assert!(!VipPolicy::default().validate_block(&block));
+        assert!(VipPolicy::new(2, vec![]).validate_block(&block));
+
+        let block = create_block_with_vip_transaction(1, &wallet, &other_wallet);
+        assert!(!VipPolicy::default().validate_block(&block));
+        assert!(VipPolicy::new(1, vec![other_wallet.get_publickey()]).validate_block(&block));
+    }
+}
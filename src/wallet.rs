@@ -1,18 +1,38 @@
-use log::info;
+use base58::ToBase58;
+use log::{error, info};
 
-use crate::block::Block;
+use crate::address_book::AddressBook;
+use crate::amount::Amount;
+use crate::block::{Block, BlockType};
+use crate::blockchain::{Blockchain, ATR_FEE};
 use crate::crypto::{
-    decrypt_with_password, encrypt_with_password, generate_keys, hash, sign, SaitoHash,
-    SaitoPrivateKey, SaitoPublicKey, SaitoSignature, SaitoUTXOSetKey,
+    encrypt_with_password, generate_keys, publickey_from_base58, sign, try_decrypt_with_password,
+    SaitoHash, SaitoPrivateKey, SaitoPublicKey, SaitoSignature, SaitoUTXOSetKey,
 };
 use crate::golden_ticket::GoldenTicket;
+use crate::layout::UTXOSET_KEY_SIZE;
 use crate::slip::{Slip, SlipType};
 use crate::staking::Staking;
 use crate::storage::Storage;
-use crate::transaction::{Transaction, TransactionType};
+use crate::time::create_timestamp;
+use crate::transaction::{Transaction, TransactionType, MAX_TRANSACTION_OUTPUTS};
+use qrcode::render::unicode::Dense1x2;
+use qrcode::QrCode;
 use std::convert::TryInto;
-
-pub const WALLET_SIZE: usize = 65;
+use url::{form_urlencoded, Url};
+
+/// the keys, which is all that version 0 wallets saved to disk
+pub const WALLET_KEYS_SIZE: usize = 65;
+/// the size of a wallet without any slips
+pub const WALLET_SIZE: usize = WALLET_KEYS_SIZE + 12;
+pub const WALLET_SLIP_SIZE: usize = 158;
+/// the size of the slips of wallets before version 4, whose utxokeys have no slip type
+const LEGACY_WALLET_SLIP_SIZE: usize = 157;
+pub const WALLET_VERSION: u32 = 4;
+/// where wallets are saved, relative to the working directory
+pub const WALLETS_DIR_PATH: &str = "data/wallets/";
+/// the scheme of payment request URIs, see PaymentRequest
+pub const PAYMENT_URI_SCHEME: &str = "saito";
 
 /// The `Wallet` manages the public and private keypair of the node and holds the
 /// slips that are used to form transactions on the network.
@@ -24,6 +44,16 @@ pub struct Wallet {
     staked_slips: Vec<WalletSlip>,
     filename: String,
     filepass: String,
+    is_saved_to_disk: bool,
+    has_unsaved_changes: bool,
+    pending_transactions: Vec<PendingTransaction>,
+    // when the pending transactions restored from disk are released, once known
+    restored_pending_expiry_block_id: Option<u64>,
+    replaced_slips: Vec<(SaitoUTXOSetKey, WalletSlip)>,
+    address_book: AddressBook,
+    auto_compound_threshold: u64,
+    // the StakerPayout nolan confirmed to us since we last staked them again
+    uncompounded_payouts: u64,
 }
 
 impl Wallet {
@@ -36,66 +66,236 @@ impl Wallet {
             staked_slips: vec![],
             filename: "default".to_string(),
             filepass: "password".to_string(),
+            is_saved_to_disk: false,
+            has_unsaved_changes: false,
+            pending_transactions: vec![],
+            restored_pending_expiry_block_id: None,
+            replaced_slips: vec![],
+            address_book: AddressBook::new(),
+            auto_compound_threshold: 0,
+            uncompounded_payouts: 0,
         }
     }
 
-    pub fn load(&mut self) {
-        let mut filename = String::from("data/wallets/");
+    /// Loads the wallet from disk, or saves it there if there is no wallet yet. Fails if the
+    /// wallet cannot be read or decrypted, or is cut short.
+    pub fn load(&mut self) -> crate::Result<()> {
+        let mut filename = String::from(WALLETS_DIR_PATH);
         filename.push_str(&self.filename);
 
         if Storage::file_exists(&filename) {
             let password = self.get_password();
-            let encoded = Storage::read(&filename).unwrap();
-            let decrypted_encoded = decrypt_with_password(encoded, &password);
-            self.deserialize_for_disk(&decrypted_encoded);
+            let encoded = Storage::read(&filename)?;
+            let decrypted_encoded = try_decrypt_with_password(&encoded, &password)
+                .ok_or_else(|| format!("cannot decrypt wallet {}", filename))?;
+            self.deserialize_for_disk(&decrypted_encoded)
+                .map_err(|err| format!("cannot read wallet {}: {}", filename, err))?;
+            self.is_saved_to_disk = true;
+            self.has_unsaved_changes = false;
         } else {
             //
             // new wallet, save to disk
             //
             self.save();
         }
+        Ok(())
     }
 
-    pub fn load_wallet(&mut self, wallet_path: &str, password: Option<&str>) {
+    pub fn load_wallet(&mut self, wallet_path: &str, password: Option<&str>) -> crate::Result<()> {
         self.set_filename(wallet_path.to_string());
         self.set_password(password.unwrap().to_string());
-        self.load();
+        self.load()
     }
 
+    //
+    // the wallet is written to a temporary file and renamed over the old one,
+    // so a crash while saving leaves the previous wallet in place.
+    //
     pub fn save(&mut self) {
-        let mut filename = String::from("data/wallets/");
+        let mut filename = String::from(WALLETS_DIR_PATH);
         filename.push_str(&self.filename);
 
         let password = self.get_password();
         let byte_array: Vec<u8> = self.serialize_for_disk();
         let encrypted_wallet = encrypt_with_password((&byte_array[..]).to_vec(), &password);
 
-        Storage::write(encrypted_wallet, &filename);
+        match Storage::write_atomically(encrypted_wallet, &filename) {
+            Ok(()) => {
+                self.is_saved_to_disk = true;
+                self.has_unsaved_changes = false;
+            }
+            Err(err) => {
+                error!(
+                    "ERROR 820134: failed to save wallet {}: {:?}",
+                    filename, err
+                );
+            }
+        }
+    }
+
+    /// Saves the wallet if it lives on disk and changed since it was last saved, so that
+    /// spent slips are remembered across restarts. The slips change with every block, so
+    /// the node saves its wallets periodically rather than as they change, see
+    /// WalletManager::start_saving.
+    pub fn save_if_changed(&mut self) {
+        if self.is_saved_to_disk && self.has_unsaved_changes {
+            self.save();
+        }
     }
 
     /// [privatekey - 32 bytes]
     /// [publickey - 33 bytes]
+    /// [version - 4 bytes]
+    /// [slips count - 4 bytes]
+    /// [staked slips count - 4 bytes]
+    /// [slips - WALLET_SLIP_SIZE each]
+    /// [staked slips - WALLET_SLIP_SIZE each]
+    /// [pending transactions count - 4 bytes]
+    /// [pending transactions - see PendingTransaction::serialize_for_disk]
+    /// [address book - see AddressBook::serialize]
     pub fn serialize_for_disk(&self) -> Vec<u8> {
         let mut vbytes: Vec<u8> = vec![];
 
         vbytes.extend(&self.privatekey);
         vbytes.extend(&self.publickey);
+        vbytes.extend(&WALLET_VERSION.to_be_bytes());
+        vbytes.extend(&(self.slips.len() as u32).to_be_bytes());
+        vbytes.extend(&(self.staked_slips.len() as u32).to_be_bytes());
+        for slip in self.slips.iter().chain(self.staked_slips.iter()) {
+            vbytes.extend(&slip.serialize_for_disk());
+        }
+        vbytes.extend(&(self.pending_transactions.len() as u32).to_be_bytes());
+        for pending_transaction in self.pending_transactions.iter() {
+            vbytes.extend(&pending_transaction.serialize_for_disk());
+        }
+        vbytes.extend(&self.address_book.serialize());
 
         vbytes
     }
 
     /// [privatekey - 32 bytes
     /// [publickey - 33 bytes]
-    pub fn deserialize_for_disk(&mut self, bytes: &Vec<u8>) {
+    /// followed by the slips, pending transactions and the address book, see
+    /// serialize_for_disk. Version 0 wallets hold only the keys, version 1 wallets no address
+    /// book and version 2 wallets no pending transactions. The utxokeys of the slips of wallets
+    /// before version 4 have no slip type, so it is taken from whether the slip is staked.
+    /// Fails if the bytes are cut short.
+    pub fn deserialize_for_disk(&mut self, bytes: &[u8]) -> crate::Result<()> {
+        Wallet::check_size_on_disk(bytes)?;
         self.privatekey = bytes[0..32].try_into().unwrap();
         self.publickey = bytes[32..65].try_into().unwrap();
+        self.slips = vec![];
+        self.staked_slips = vec![];
+        self.pending_transactions = vec![];
+        self.address_book = AddressBook::new();
+
+        if bytes.len() == WALLET_KEYS_SIZE {
+            return Ok(());
+        }
+        let version = u32::from_be_bytes(bytes[65..69].try_into().unwrap());
+        if !(1..=WALLET_VERSION).contains(&version) {
+            error!(
+                "ERROR 820135: unsupported wallet version {}, only the keys were loaded",
+                version
+            );
+            return Ok(());
+        }
+        let slips_count = u32::from_be_bytes(bytes[69..73].try_into().unwrap()) as usize;
+        let staked_slips_count = u32::from_be_bytes(bytes[73..77].try_into().unwrap()) as usize;
+        let wallet_slip_size = Wallet::get_wallet_slip_size(version);
+        for i in 0..slips_count + staked_slips_count {
+            let start = WALLET_SIZE + i * wallet_slip_size;
+            let mut slip_bytes = bytes[start..start + wallet_slip_size].to_vec();
+            if version < 4 {
+                let slip_type = if i < slips_count {
+                    SlipType::Normal
+                } else {
+                    SlipType::StakerDeposit
+                };
+                slip_bytes.insert(32 + UTXOSET_KEY_SIZE - 1, slip_type as u8);
+            }
+            let wallet_slip = WalletSlip::deserialize_for_disk(&slip_bytes)?;
+            if i < slips_count {
+                self.slips.push(wallet_slip);
+            } else {
+                self.staked_slips.push(wallet_slip);
+            }
+        }
+        if version == 1 {
+            return Ok(());
+        }
+        let mut start = WALLET_SIZE + (slips_count + staked_slips_count) * wallet_slip_size;
+        if version >= 3 {
+            let pending_transactions_count = bytes
+                .get(start..start + 4)
+                .ok_or("the wallet is too short for its pending transactions")?;
+            let pending_transactions_count =
+                u32::from_be_bytes(pending_transactions_count.try_into().unwrap());
+            start += 4;
+            for _ in 0..pending_transactions_count {
+                let (pending_transaction, size) =
+                    PendingTransaction::deserialize_for_disk(&bytes[start..])
+                        .ok_or("the wallet is too short for its pending transactions")?;
+                self.pending_transactions.push(pending_transaction);
+                start += size;
+            }
+        }
+        match AddressBook::deserialize(&bytes[start..]) {
+            Some(address_book) => self.address_book = address_book,
+            None => error!("ERROR 820136: cannot read the address book of the wallet"),
+        }
+        Ok(())
+    }
+
+    fn get_wallet_slip_size(version: u32) -> usize {
+        if version < 4 {
+            LEGACY_WALLET_SLIP_SIZE
+        } else {
+            WALLET_SLIP_SIZE
+        }
+    }
+
+    //
+    // fails if a wallet on disk is too short for its keys, or for the slips
+    // it claims to hold
+    //
+    fn check_size_on_disk(bytes: &[u8]) -> crate::Result<()> {
+        if bytes.len() < WALLET_KEYS_SIZE
+            || (bytes.len() > WALLET_KEYS_SIZE && bytes.len() < WALLET_SIZE)
+        {
+            return Err(format!("{} bytes are too few for a wallet", bytes.len()).into());
+        }
+        if bytes.len() == WALLET_KEYS_SIZE {
+            return Ok(());
+        }
+        let version = u32::from_be_bytes(bytes[65..69].try_into().unwrap());
+        if !(1..=WALLET_VERSION).contains(&version) {
+            return Ok(());
+        }
+        let slips_count = u32::from_be_bytes(bytes[69..73].try_into().unwrap()) as usize;
+        let staked_slips_count = u32::from_be_bytes(bytes[73..77].try_into().unwrap()) as usize;
+        if bytes.len()
+            < WALLET_SIZE
+                + (slips_count + staked_slips_count) * Wallet::get_wallet_slip_size(version)
+        {
+            return Err(format!(
+                "{} bytes are too few for {} slips",
+                bytes.len(),
+                slips_count + staked_slips_count
+            )
+            .into());
+        }
+        Ok(())
     }
 
     pub fn on_chain_reorganization(&mut self, block: &Block, lc: bool) {
         if lc {
             for tx in block.get_transactions() {
                 for input in tx.get_inputs() {
-                    if input.get_amount() > 0 && input.get_publickey() == self.get_publickey() {
+                    if input.get_amount() > Amount::ZERO
+                        && input.get_publickey() == self.get_publickey()
+                    {
+                        self.confirm_pending_transaction(input);
                         if input.get_slip_type() == SlipType::StakerDeposit
                             || input.get_slip_type() == SlipType::StakerOutput
                             || input.get_slip_type() == SlipType::StakerWithdrawalStaking
@@ -108,7 +308,17 @@ impl Wallet {
                     }
                 }
                 for output in tx.get_outputs() {
-                    if output.get_amount() > 0 && output.get_publickey() == self.get_publickey() {
+                    if output.get_amount() > Amount::ZERO
+                        && output.get_publickey() == self.get_publickey()
+                    {
+                        if tx.get_transaction_type() == TransactionType::ATR {
+                            self.replace_rebroadcast_slip(output);
+                        }
+                        if output.get_slip_type() == SlipType::StakerPayout {
+                            self.uncompounded_payouts = self
+                                .uncompounded_payouts
+                                .saturating_add(output.get_amount().into());
+                        }
                         self.add_slip(block, tx, output, true);
                     }
                 }
@@ -116,30 +326,161 @@ impl Wallet {
         } else {
             for tx in block.get_transactions() {
                 for input in tx.get_inputs() {
-                    if input.get_amount() > 0 && input.get_publickey() == self.get_publickey() {
+                    if input.get_amount() > Amount::ZERO
+                        && input.get_publickey() == self.get_publickey()
+                    {
                         self.add_slip(block, tx, input, true);
                     }
                 }
                 for output in tx.get_outputs() {
-                    if output.get_amount() > 0 && output.get_publickey() == self.get_publickey() {
-                        self.delete_slip(output);
+                    if output.get_amount() > Amount::ZERO
+                        && output.get_publickey() == self.get_publickey()
+                    {
+                        if output.get_slip_type() == SlipType::StakerPayout {
+                            self.uncompounded_payouts = self
+                                .uncompounded_payouts
+                                .saturating_sub(output.get_amount().into());
+                        }
+                        if tx.get_transaction_type() == TransactionType::ATR {
+                            self.restore_rebroadcast_slip(output);
+                        } else {
+                            self.delete_slip(output);
+                        }
                     }
                 }
             }
         }
     }
 
+    //
+    // an ATR transaction pays out an unspent slip falling off the chain again,
+    // less the ATR fee and under the uuid of the slip it replaces. we set the
+    // replaced slip aside so it can be put back if the block rebroadcasting it
+    // is unwound. slips reserved by a pending transaction are kept, as that
+    // transaction may still make it into a block.
+    //
+    fn replace_rebroadcast_slip(&mut self, output: &Slip) {
+        let replaced_amount = output.get_amount().checked_add(ATR_FEE.into());
+        if let Some(idx) = self.slips.iter().position(|slip| {
+            !slip.get_spent()
+                && slip.get_uuid() == output.get_uuid()
+                && Some(slip.get_amount()) == replaced_amount
+        }) {
+            let replaced_slip = self.slips.remove(idx);
+            self.replaced_slips
+                .push((output.get_utxoset_key(), replaced_slip));
+            self.has_unsaved_changes = true;
+        }
+    }
+
+    fn restore_rebroadcast_slip(&mut self, output: &Slip) {
+        let utxoset_key = output.get_utxoset_key();
+        self.delete_slip_by_utxoset_key(&utxoset_key);
+        if let Some(idx) = self
+            .replaced_slips
+            .iter()
+            .position(|(replacement_utxoset_key, _)| *replacement_utxoset_key == utxoset_key)
+        {
+            let (_, replaced_slip) = self.replaced_slips.remove(idx);
+            self.slips.push(replaced_slip);
+            self.has_unsaved_changes = true;
+        }
+    }
+
+    /// Rebuilds the slips from the longest chain starting at `from_block_id`. Slips from
+    /// those blocks are dropped and found again by replaying the blocks, loading them from
+    /// disk if they are no longer held in full in memory. Slips from earlier blocks are
+    /// kept but no longer marked spent, as any spend which made it into a block is removed
+    /// again by the replay. Slips reserved by our transactions in the mempool stay spent, so
+    /// that they cannot be spent a second time. Rescanning from block 0 rebuilds the wallet
+    /// from scratch. Fails, leaving the wallet as it was, if a block of the longest chain
+    /// cannot be loaded.
+    pub async fn rescan(
+        &mut self,
+        blockchain: &Blockchain,
+        from_block_id: u64,
+    ) -> crate::Result<()> {
+        info!(
+            "rescanning wallet {} from block {}",
+            self.get_publickey().to_base58(),
+            from_block_id
+        );
+
+        let mut wallet = self.clone();
+        wallet.rescan_blocks(blockchain, from_block_id)?;
+        *self = wallet;
+        self.save_if_changed();
+        Ok(())
+    }
+
+    fn rescan_blocks(&mut self, blockchain: &Blockchain, from_block_id: u64) -> crate::Result<()> {
+        let slips_count = self.slips.len() + self.staked_slips.len();
+        self.slips.retain(|x| x.get_block_id() < from_block_id);
+        self.staked_slips
+            .retain(|x| x.get_block_id() < from_block_id);
+        if self.slips.len() + self.staked_slips.len() != slips_count {
+            self.has_unsaved_changes = true;
+        }
+        let pending_transactions = &self.pending_transactions;
+        for slip in self.slips.iter_mut().filter(|x| x.get_spent()) {
+            if !is_reserved(pending_transactions, slip) {
+                slip.set_spent(false);
+                self.has_unsaved_changes = true;
+            }
+        }
+
+        for_each_longest_chain_block(blockchain, from_block_id, |block| {
+            self.on_chain_reorganization(block, true);
+        })?;
+
+        //
+        // the slips of the blocks replayed are found again unspent, but those
+        // our pending transactions spend are still reserved
+        //
+        let pending_transactions = &self.pending_transactions;
+        for slip in self.slips.iter_mut().filter(|x| !x.get_spent()) {
+            if is_reserved(pending_transactions, slip) {
+                slip.set_spent(true);
+                self.has_unsaved_changes = true;
+            }
+        }
+        Ok(())
+    }
+
+    /// The transactions in the longest chain which pay us or which we paid, oldest first.
+    /// Blocks which are no longer held in full in memory are loaded from disk.
+    pub fn get_history(&self, blockchain: &Blockchain) -> crate::Result<Vec<WalletTransaction>> {
+        let mut history = vec![];
+        for_each_longest_chain_block(blockchain, 1, |block| {
+            for transaction in block.get_transactions() {
+                if let Some(wallet_transaction) =
+                    WalletTransaction::new(block, transaction, &self.publickey)
+                {
+                    history.push(wallet_transaction);
+                }
+            }
+        })?;
+        Ok(history)
+    }
+
     //
     // removes all slips in block when pruned / deleted
     //
+    // outputs are matched on their utxoset key, as a slip rebroadcast by ATR
+    // keeps the uuid of the slip in the pruned block it replaces
+    //
     pub fn delete_block(&mut self, block: &Block) {
         for tx in block.get_transactions() {
             for input in tx.get_inputs() {
                 self.delete_slip(input);
             }
             for output in tx.get_outputs() {
-                if output.get_amount() > 0 {
-                    self.delete_slip(output);
+                if output.get_amount() > Amount::ZERO {
+                    let utxoset_key = output.get_utxoset_key();
+                    self.delete_slip_by_utxoset_key(&utxoset_key);
+                    self.replaced_slips.retain(|(replacement_utxoset_key, _)| {
+                        *replacement_utxoset_key != utxoset_key
+                    });
                 }
             }
         }
@@ -148,7 +489,15 @@ impl Wallet {
     pub fn add_slip(&mut self, block: &Block, transaction: &Transaction, slip: &Slip, lc: bool) {
         let mut wallet_slip = WalletSlip::new();
 
-        wallet_slip.set_uuid(transaction.get_hash_for_signature().unwrap());
+        //
+        // ATR slips keep the uuid of the slip they rebroadcast, which is what
+        // spending them needs, rather than taking that of their transaction
+        //
+        if slip.get_slip_type() == SlipType::ATR {
+            wallet_slip.set_uuid(slip.get_uuid());
+        } else {
+            wallet_slip.set_uuid(transaction.get_hash_for_signature().unwrap());
+        }
         wallet_slip.set_utxokey(slip.get_utxoset_key());
         wallet_slip.set_amount(slip.get_amount());
         wallet_slip.set_slip_ordinal(slip.get_slip_ordinal());
@@ -156,25 +505,149 @@ impl Wallet {
         wallet_slip.set_block_hash(block.get_hash());
         wallet_slip.set_lc(lc);
 
-        if slip.get_slip_type() == SlipType::StakerDeposit
+        let slips = if slip.get_slip_type() == SlipType::StakerDeposit
             || slip.get_slip_type() == SlipType::StakerOutput
         {
-            self.staked_slips.push(wallet_slip);
+            &mut self.staked_slips
         } else {
-            self.slips.push(wallet_slip);
+            &mut self.slips
+        };
+
+        //
+        // slips restored from disk are seen again as the blocks holding
+        // them are loaded, and keep the spent flag they were saved with.
+        //
+        if slips
+            .iter()
+            .any(|x| x.get_utxokey() == wallet_slip.get_utxokey())
+        {
+            return;
         }
+        slips.push(wallet_slip);
+        self.has_unsaved_changes = true;
     }
 
     pub fn delete_staked_slip(&mut self, slip: &Slip) {
+        let staked_slips_count = self.staked_slips.len();
         self.staked_slips.retain(|x| {
             x.get_uuid() != slip.get_uuid() || x.get_slip_ordinal() != slip.get_slip_ordinal()
         });
+        if self.staked_slips.len() != staked_slips_count {
+            self.has_unsaved_changes = true;
+        }
+    }
+
+    pub fn delete_slip_by_utxoset_key(&mut self, utxoset_key: &SaitoUTXOSetKey) {
+        let slips_count = self.slips.len();
+        self.slips.retain(|x| x.get_utxokey() != utxoset_key);
+        if self.slips.len() != slips_count {
+            self.has_unsaved_changes = true;
+        }
     }
 
     pub fn delete_slip(&mut self, slip: &Slip) {
+        let slips_count = self.slips.len();
         self.slips.retain(|x| {
             x.get_uuid() != slip.get_uuid() || x.get_slip_ordinal() != slip.get_slip_ordinal()
         });
+        if self.slips.len() != slips_count {
+            self.has_unsaved_changes = true;
+        }
+    }
+
+    //
+    // a pending transaction is confirmed once a block on the longest chain
+    // spends any of the slips it reserved.
+    //
+    fn confirm_pending_transaction(&mut self, slip: &Slip) {
+        let pending_transactions_count = self.pending_transactions.len();
+        self.pending_transactions.retain(|pending_transaction| {
+            !pending_transaction.is_spending(slip.get_uuid(), slip.get_slip_ordinal())
+        });
+        if self.pending_transactions.len() != pending_transactions_count {
+            self.has_unsaved_changes = true;
+        }
+    }
+
+    /// Called when one of our transactions is dropped from the mempool without making it into
+    /// a block. The slips it reserved can be spent again.
+    pub fn on_transaction_expired(&mut self, transaction: &Transaction) {
+        for input in transaction.get_inputs() {
+            if input.get_amount() == Amount::ZERO || input.get_publickey() != self.get_publickey() {
+                continue;
+            }
+            self.pending_transactions.retain(|pending_transaction| {
+                !pending_transaction.is_spending(input.get_uuid(), input.get_slip_ordinal())
+            });
+            self.release_slip(input.get_uuid(), input.get_slip_ordinal());
+        }
+    }
+
+    /// Pending transactions restored from disk are no longer in our mempool, which would tell
+    /// us when they expire. They are released once the chain is transaction_ttl blocks past
+    /// where it was when the mempool first asked, as a transaction it took then would be.
+    pub fn expire_restored_pending_transactions(
+        &mut self,
+        latest_block_id: u64,
+        transaction_ttl: u64,
+    ) {
+        if !self
+            .pending_transactions
+            .iter()
+            .any(|pending_transaction| pending_transaction.is_restored())
+        {
+            return;
+        }
+        let expiry_block_id = *self
+            .restored_pending_expiry_block_id
+            .get_or_insert(latest_block_id.saturating_add(transaction_ttl));
+        if latest_block_id < expiry_block_id {
+            return;
+        }
+        let (restored_pending_transactions, pending_transactions): (
+            Vec<PendingTransaction>,
+            Vec<PendingTransaction>,
+        ) = self
+            .pending_transactions
+            .drain(..)
+            .partition(|pending_transaction| pending_transaction.is_restored());
+        self.pending_transactions = pending_transactions;
+        for pending_transaction in restored_pending_transactions {
+            for (uuid, slip_ordinal) in pending_transaction.get_slip_keys() {
+                self.release_slip(*uuid, *slip_ordinal);
+            }
+        }
+        self.has_unsaved_changes = true;
+    }
+
+    //
+    // a slip reserved by a transaction which will not make it into a block
+    // can be spent again
+    //
+    fn release_slip(&mut self, uuid: SaitoHash, slip_ordinal: u8) {
+        for slip in self.slips.iter_mut() {
+            if slip.get_spent()
+                && slip.get_uuid() == uuid
+                && slip.get_slip_ordinal() == slip_ordinal
+            {
+                slip.set_spent(false);
+                self.has_unsaved_changes = true;
+            }
+        }
+    }
+
+    pub fn get_pending_transactions(&self) -> &Vec<PendingTransaction> {
+        &self.pending_transactions
+    }
+
+    /// The change and other payments to us from our own transactions which have not yet
+    /// been put in a block. It becomes part of the available balance once they are.
+    pub fn get_pending_balance(&self) -> u64 {
+        self.pending_transactions
+            .iter()
+            .map(|pending_transaction| Amount::from(pending_transaction.get_pending_amount()))
+            .sum::<Amount>()
+            .into()
     }
 
     pub fn get_privatekey(&self) -> SaitoPrivateKey {
@@ -201,6 +674,46 @@ impl Wallet {
         self.filepass = filepass;
     }
 
+    pub fn get_address_book(&self) -> &AddressBook {
+        &self.address_book
+    }
+
+    /// Labels the publickey in the address book, and saves the wallet if it is on disk.
+    pub fn add_address_book_entry(
+        &mut self,
+        label: &str,
+        publickey: SaitoPublicKey,
+    ) -> crate::Result<()> {
+        self.address_book.add(label, publickey)?;
+        self.has_unsaved_changes = true;
+        self.save_if_changed();
+        Ok(())
+    }
+
+    /// Removes the label from the address book, and saves the wallet if it is on disk.
+    pub fn remove_address_book_entry(&mut self, label: &str) -> Option<SaitoPublicKey> {
+        let publickey = self.address_book.remove(label)?;
+        self.has_unsaved_changes = true;
+        self.save_if_changed();
+        Some(publickey)
+    }
+
+    /// Sets how many nolan of staking payouts we collect before staking them again, 0 turns
+    /// this off.
+    pub fn set_auto_compound_threshold(&mut self, auto_compound_threshold: u64) {
+        self.auto_compound_threshold = auto_compound_threshold;
+    }
+
+    pub fn get_auto_compound_threshold(&self) -> u64 {
+        self.auto_compound_threshold
+    }
+
+    /// The StakerPayout nolan confirmed to us which have not been staked again. These are
+    /// counted from the blocks seen since the wallet was loaded.
+    pub fn get_uncompounded_payouts(&self) -> u64 {
+        self.uncompounded_payouts
+    }
+
     pub fn get_filename(&mut self) -> String {
         self.filename.clone()
     }
@@ -210,13 +723,151 @@ impl Wallet {
     }
 
     pub fn get_available_balance(&self) -> u64 {
-        let mut available_balance: u64 = 0;
-        for slip in &self.slips {
-            if !slip.get_spent() {
-                available_balance += slip.get_amount();
+        self.slips
+            .iter()
+            .filter(|slip| !slip.get_spent())
+            .map(|slip| slip.get_amount())
+            .sum::<Amount>()
+            .into()
+    }
+
+    /// The nolan in our unspent slips which are worth no more than ATR_FEE. These are not
+    /// rebroadcast, so they are lost as they fall off the chain.
+    pub fn get_dust_balance(&self) -> u64 {
+        self.slips
+            .iter()
+            .filter(|slip| !slip.get_spent() && slip.get_amount() <= Amount::from(ATR_FEE))
+            .map(|slip| slip.get_amount())
+            .sum::<Amount>()
+            .into()
+    }
+
+    //
+    // sweeps our dust slips into a single output paid to ourselves, so that the
+    // nolan are rebroadcast once they add up to more than the ATR fee. Returns None
+    // if there are fewer than two dust slips to sweep.
+    //
+    pub fn create_dust_consolidation_transaction(&mut self) -> Option<Transaction> {
+        let my_publickey = self.get_publickey();
+        let mut inputs: Vec<Slip> = vec![];
+        let mut nolan_in = Amount::ZERO;
+        for slip in self
+            .slips
+            .iter()
+            .filter(|slip| !slip.get_spent() && slip.get_amount() > Amount::ZERO)
+            .filter(|slip| slip.get_amount() <= Amount::from(ATR_FEE))
+        {
+            let mut input = Slip::new();
+            input.set_publickey(my_publickey);
+            input.set_amount(slip.get_amount());
+            input.set_uuid(slip.get_uuid());
+            input.set_slip_ordinal(slip.get_slip_ordinal());
+            inputs.push(input);
+            nolan_in = nolan_in.saturating_add(slip.get_amount());
+        }
+        if inputs.len() < 2 {
+            return None;
+        }
+
+        for slip in self.slips.iter_mut() {
+            if inputs.iter().any(|input| {
+                input.get_uuid() == slip.get_uuid()
+                    && input.get_slip_ordinal() == slip.get_slip_ordinal()
+            }) {
+                slip.set_spent(true);
             }
         }
-        available_balance
+        self.pending_transactions.push(PendingTransaction::new(
+            inputs
+                .iter()
+                .map(|input| (input.get_uuid(), input.get_slip_ordinal()))
+                .collect(),
+            nolan_in.into(),
+        ));
+        self.has_unsaved_changes = true;
+        self.save_if_changed();
+
+        let mut output = Slip::new();
+        output.set_publickey(my_publickey);
+        output.set_amount(nolan_in);
+
+        let mut transaction = Transaction::new();
+        transaction.set_timestamp(create_timestamp());
+        transaction.set_inputs(inputs);
+        transaction.add_output(output);
+        transaction.sign(self.get_privatekey());
+        Some(transaction)
+    }
+
+    //
+    // pays every recipient in one transaction, e.g. for payroll, so that the
+    // payments are made together or not at all. The fee is paid on top of the
+    // payments by default, or split evenly between the recipients, with the
+    // first recipients paying a nolan more where it does not divide. The
+    // change is paid back to us in the last output.
+    //
+    pub fn create_payment_transaction(
+        &mut self,
+        payments: &[(SaitoPublicKey, u64)],
+        fee: u64,
+        fee_payer: FeePayer,
+    ) -> crate::Result<Transaction> {
+        if payments.is_empty() {
+            return Err("a payment needs at least one recipient".into());
+        }
+        if payments.len() >= MAX_TRANSACTION_OUTPUTS {
+            return Err(format!(
+                "a payment can have at most {} recipients",
+                MAX_TRANSACTION_OUTPUTS - 1
+            )
+            .into());
+        }
+        let total_paid = Amount::checked_sum(payments.iter().map(|(_, amount)| (*amount).into()))
+            .ok_or("the payments overflow")?;
+        let total_requested = match fee_payer {
+            FeePayer::Sender => total_paid
+                .checked_add(fee.into())
+                .ok_or("the payments overflow")?,
+            FeePayer::Recipients => total_paid,
+        };
+
+        let mut outputs = vec![];
+        for (i, (publickey, amount)) in payments.iter().enumerate() {
+            let mut amount = *amount;
+            if fee_payer == FeePayer::Recipients {
+                let fee_share =
+                    fee / payments.len() as u64 + ((i as u64) < fee % payments.len() as u64) as u64;
+                amount = amount.checked_sub(fee_share).ok_or_else(|| {
+                    format!(
+                        "the payment to {} is smaller than its share of the fee",
+                        publickey.to_base58()
+                    )
+                })?;
+            }
+            let mut output = Slip::new();
+            output.set_publickey(*publickey);
+            output.set_amount(amount.into());
+            outputs.push(output);
+        }
+
+        if self.get_available_balance() < total_requested.as_nolan() {
+            return Err(format!(
+                "the payments need {} nolan but the wallet holds {}",
+                total_requested.as_nolan(),
+                self.get_available_balance()
+            )
+            .into());
+        }
+        let (inputs, change_outputs) = self.generate_slips(total_requested.as_nolan());
+
+        let mut transaction = Transaction::new();
+        transaction.set_timestamp(create_timestamp());
+        transaction.set_inputs(inputs);
+        for output in outputs.into_iter().chain(change_outputs) {
+            transaction.add_output(output);
+        }
+        transaction.sign(self.get_privatekey());
+        Ok(transaction)
     }
 
     // the nolan_requested is omitted from the slips created - only the change
@@ -235,7 +886,7 @@ impl Wallet {
         for slip in &mut self.slips {
             if !slip.get_spent() {
                 if nolan_in < nolan_requested {
-                    nolan_in += slip.get_amount();
+                    nolan_in += u64::from(slip.get_amount());
 
                     let mut input = Slip::new();
                     input.set_publickey(my_publickey);
@@ -245,6 +896,7 @@ impl Wallet {
                     inputs.push(input);
 
                     slip.set_spent(true);
+                    self.has_unsaved_changes = true;
                 }
             }
         }
@@ -261,23 +913,37 @@ impl Wallet {
         //
         let mut output = Slip::new();
         output.set_publickey(my_publickey);
-        output.set_amount(nolan_out);
+        output.set_amount(nolan_out.into());
         outputs.push(output);
 
+        //
+        // the inputs stay reserved until the transaction spending them is in a
+        // block, so later transactions cannot spend them a second time.
+        //
+        if !inputs.is_empty() {
+            self.pending_transactions.push(PendingTransaction::new(
+                inputs
+                    .iter()
+                    .map(|input| (input.get_uuid(), input.get_slip_ordinal()))
+                    .collect(),
+                nolan_out,
+            ));
+        }
+
         //
         // ensure not empty
         //
         if inputs.is_empty() {
             let mut input = Slip::new();
             input.set_publickey(my_publickey);
-            input.set_amount(0);
+            input.set_amount(Amount::from_nolan(0));
             input.set_uuid([0; 32]);
             inputs.push(input);
         }
         if outputs.is_empty() {
             let mut output = Slip::new();
             output.set_publickey(my_publickey);
-            output.set_amount(0);
+            output.set_amount(Amount::from_nolan(0));
             output.set_uuid([0; 32]);
             outputs.push(output);
         }
@@ -301,18 +967,18 @@ impl Wallet {
 
         let mut input1 = Slip::new();
         input1.set_publickey(self.get_publickey());
-        input1.set_amount(0);
+        input1.set_amount(Amount::from_nolan(0));
         input1.set_uuid([0; 32]);
 
         let mut output1 = Slip::new();
         output1.set_publickey(self.get_publickey());
-        output1.set_amount(0);
+        output1.set_amount(Amount::from_nolan(0));
         output1.set_uuid([0; 32]);
 
         transaction.add_input(input1);
         transaction.add_output(output1);
 
-        let hash_for_signature: SaitoHash = hash(&transaction.serialize_for_signature());
+        let hash_for_signature: SaitoHash = transaction.generate_hash_for_signature();
         transaction.set_hash_for_signature(hash_for_signature);
 
         transaction.sign(self.get_privatekey());
@@ -328,6 +994,27 @@ impl Wallet {
     pub async fn create_staking_deposit_transaction(
         &mut self,
         total_requested: u64,
+    ) -> Transaction {
+        self.build_staking_deposit_transaction(total_requested, None)
+    }
+
+    //
+    // creates a staking deposit whose payouts are collected by the delegate
+    // rather than by this wallet, so the stake can be held by a cold key while
+    // a hot key collects the return on it. only this wallet can withdraw it.
+    //
+    pub async fn create_delegated_staking_deposit_transaction(
+        &mut self,
+        total_requested: u64,
+        delegate: SaitoPublicKey,
+    ) -> Transaction {
+        self.build_staking_deposit_transaction(total_requested, Some(delegate))
+    }
+
+    fn build_staking_deposit_transaction(
+        &mut self,
+        total_requested: u64,
+        delegate: Option<SaitoPublicKey>,
     ) -> Transaction {
         let mut transaction = Transaction::new();
 
@@ -340,10 +1027,18 @@ impl Wallet {
         // add the staking deposit
         let mut output = Slip::new();
         output.set_publickey(self.get_publickey());
-        output.set_amount(total_requested);
+        output.set_amount(total_requested.into());
         output.set_slip_type(SlipType::StakerDeposit);
         transaction.add_output(output);
 
+        // and who collects its payouts, directly after it
+        if let Some(delegate) = delegate {
+            let mut output = Slip::new();
+            output.set_publickey(delegate);
+            output.set_slip_type(SlipType::StakerDelegation);
+            transaction.add_output(output);
+        }
+
         for _i in 0..input_len {
             transaction.add_input(input_slips.remove(0));
         }
@@ -351,13 +1046,31 @@ impl Wallet {
             transaction.add_output(output_slips.remove(0));
         }
 
-        let hash_for_signature: SaitoHash = hash(&transaction.serialize_for_signature());
+        let hash_for_signature: SaitoHash = transaction.generate_hash_for_signature();
         transaction.set_hash_for_signature(hash_for_signature);
         transaction.sign(self.get_privatekey());
 
         transaction
     }
 
+    //
+    // stakes the payouts confirmed to us again once they add up to more than
+    // the auto-compound threshold. Returns None below the threshold, or while
+    // we hold too little to pay for the deposit, e.g. as the payouts have
+    // been spent.
+    //
+    pub fn create_auto_compound_transaction(&mut self) -> Option<Transaction> {
+        if self.auto_compound_threshold == 0
+            || self.uncompounded_payouts <= self.auto_compound_threshold
+            || self.get_available_balance() < self.uncompounded_payouts
+        {
+            return None;
+        }
+        let amount = self.uncompounded_payouts;
+        self.uncompounded_payouts = 0;
+        Some(self.build_staking_deposit_transaction(amount, None))
+    }
+
     //
     // creates a staking withdrawal transaction if possible that removes a slip from
     // the staking table. this function is primarily used for testing and as a reference
@@ -400,12 +1113,18 @@ impl Wallet {
         transaction.add_input(input);
         transaction.add_output(output);
 
-        let hash_for_signature: SaitoHash = hash(&transaction.serialize_for_signature());
+        let hash_for_signature: SaitoHash = transaction.generate_hash_for_signature();
         transaction.set_hash_for_signature(hash_for_signature);
         transaction.sign(self.get_privatekey());
 
         // and remember it is spent!
         self.staked_slips[0].set_spent(true);
+        self.has_unsaved_changes = true;
+        self.pending_transactions.push(PendingTransaction::new(
+            vec![(slip.get_uuid(), slip.get_slip_ordinal())],
+            slip.get_amount().into(),
+        ));
+        self.save_if_changed();
 
         transaction
     }
@@ -422,11 +1141,11 @@ impl Wallet {
 /// are spent on one fork are not recaptured on chains, for instance, and once
 /// a slip is spent it is marked as spent.
 ///
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct WalletSlip {
     uuid: SaitoHash,
     utxokey: SaitoUTXOSetKey,
-    amount: u64,
+    amount: Amount,
     block_id: u64,
     block_hash: SaitoHash,
     lc: bool,
@@ -439,8 +1158,8 @@ impl WalletSlip {
     pub fn new() -> Self {
         WalletSlip {
             uuid: [0; 32],
-            utxokey: [0; 74],
-            amount: 0,
+            utxokey: [0; 75],
+            amount: Amount::ZERO,
             block_id: 0,
             block_hash: [0; 32],
             lc: true,
@@ -449,6 +1168,44 @@ impl WalletSlip {
         }
     }
 
+    /// [uuid - 32 bytes]
+    /// [utxokey - 75 bytes]
+    /// [amount - 8 bytes]
+    /// [block_id - 8 bytes]
+    /// [block_hash - 32 bytes]
+    /// [lc - 1 byte]
+    /// [slip_ordinal - 1 byte]
+    /// [spent - 1 byte]
+    pub fn serialize_for_disk(&self) -> Vec<u8> {
+        let mut vbytes: Vec<u8> = vec![];
+        vbytes.extend(&self.uuid);
+        vbytes.extend(&self.utxokey);
+        vbytes.extend(&self.amount.as_nolan().to_be_bytes());
+        vbytes.extend(&self.block_id.to_be_bytes());
+        vbytes.extend(&self.block_hash);
+        vbytes.push(self.lc as u8);
+        vbytes.push(self.slip_ordinal);
+        vbytes.push(self.spent as u8);
+        vbytes
+    }
+
+    /// Fails if the bytes are not those of one slip.
+    pub fn deserialize_for_disk(bytes: &[u8]) -> crate::Result<WalletSlip> {
+        if bytes.len() != WALLET_SLIP_SIZE {
+            return Err(format!("a wallet slip is not {} bytes", bytes.len()).into());
+        }
+        Ok(WalletSlip {
+            uuid: bytes[0..32].try_into().unwrap(),
+            utxokey: bytes[32..107].try_into().unwrap(),
+            amount: Amount::from_nolan(u64::from_be_bytes(bytes[107..115].try_into().unwrap())),
+            block_id: u64::from_be_bytes(bytes[115..123].try_into().unwrap()),
+            block_hash: bytes[123..155].try_into().unwrap(),
+            lc: bytes[155] != 0,
+            slip_ordinal: bytes[156],
+            spent: bytes[157] != 0,
+        })
+    }
+
     pub fn get_uuid(&self) -> SaitoHash {
         self.uuid
     }
@@ -457,7 +1214,7 @@ impl WalletSlip {
         &self.utxokey
     }
 
-    pub fn get_amount(&self) -> u64 {
+    pub fn get_amount(&self) -> Amount {
         self.amount
     }
 
@@ -493,7 +1250,7 @@ impl WalletSlip {
         self.utxokey = utxokey;
     }
 
-    pub fn set_amount(&mut self, amount: u64) {
+    pub fn set_amount(&mut self, amount: Amount) {
         self.amount = amount;
     }
 
@@ -514,17 +1271,360 @@ impl WalletSlip {
     }
 }
 
+/// Whether one of the pending transactions spends the slip.
+fn is_reserved(pending_transactions: &[PendingTransaction], slip: &WalletSlip) -> bool {
+    pending_transactions.iter().any(|pending_transaction| {
+        pending_transaction.is_spending(slip.get_uuid(), slip.get_slip_ordinal())
+    })
+}
+
+/// Calls f with the blocks of the longest chain from from_block_id to the latest block, loading
+/// the blocks which are no longer held in full in memory from disk. Fails on the first block
+/// which cannot be loaded.
+fn for_each_longest_chain_block<F: FnMut(&Block)>(
+    blockchain: &Blockchain,
+    from_block_id: u64,
+    mut f: F,
+) -> crate::Result<()> {
+    for block_id in from_block_id..=blockchain.get_latest_block_id() {
+        let block_hash = blockchain
+            .blockring
+            .get_longest_chain_block_hash_by_block_id(block_id);
+        match blockchain.get_block_sync(&block_hash) {
+            Some(block) if block.get_block_type() == BlockType::Full => {
+                f(block);
+            }
+            Some(block) => {
+                let mut full_block = blockchain
+                    .get_block_storage()
+                    .read_block(block.get_timestamp(), &block_hash)
+                    .map_err(|err| {
+                        format!("cannot load block {}: {}", hex::encode(block_hash), err)
+                    })?;
+                full_block.generate_metadata();
+                f(&full_block);
+            }
+            None => {}
+        }
+    }
+    Ok(())
+}
+
+/// Who pays the fee of a payment made with create_payment_transaction.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FeePayer {
+    /// the fee is paid on top of the payments
+    Sender,
+    /// the fee is taken out of the payments
+    Recipients,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WalletTransactionDirection {
+    Incoming,
+    Outgoing,
+}
+
+/// A transaction in the history of the wallet. The amount of an outgoing transaction is what
+/// it paid to others, and of an incoming one what it paid to us.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WalletTransaction {
+    block_id: u64,
+    timestamp: u64,
+    direction: WalletTransactionDirection,
+    amount: u64,
+    counterparty: Option<SaitoPublicKey>,
+}
+
+impl WalletTransaction {
+    /// None if the transaction neither pays us nor spends our slips. ATR transactions only
+    /// rebroadcast slips we were paid before, so they are not in the history either.
+    pub fn new(
+        block: &Block,
+        transaction: &Transaction,
+        publickey: &SaitoPublicKey,
+    ) -> Option<Self> {
+        if transaction.get_transaction_type() == TransactionType::ATR {
+            return None;
+        }
+        let (direction, amount, counterparty) = if transaction
+            .get_inputs()
+            .iter()
+            .any(|input| input.get_publickey() == *publickey)
+        {
+            let outputs: Vec<&Slip> = transaction
+                .get_outputs()
+                .iter()
+                .filter(|output| output.get_publickey() != *publickey)
+                .collect();
+            (
+                WalletTransactionDirection::Outgoing,
+                outputs
+                    .iter()
+                    .map(|output| output.get_amount())
+                    .sum::<Amount>()
+                    .into(),
+                outputs.first().map(|output| output.get_publickey()),
+            )
+        } else if transaction
+            .get_outputs()
+            .iter()
+            .any(|output| output.get_publickey() == *publickey)
+        {
+            (
+                WalletTransactionDirection::Incoming,
+                transaction
+                    .get_outputs()
+                    .iter()
+                    .filter(|output| output.get_publickey() == *publickey)
+                    .map(|output| output.get_amount())
+                    .sum::<Amount>()
+                    .into(),
+                transaction.get_sender(),
+            )
+        } else {
+            return None;
+        };
+        Some(WalletTransaction {
+            block_id: block.get_id(),
+            timestamp: block.get_timestamp(),
+            direction,
+            amount,
+            counterparty,
+        })
+    }
+
+    pub fn get_block_id(&self) -> u64 {
+        self.block_id
+    }
+
+    pub fn get_timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn get_direction(&self) -> WalletTransactionDirection {
+        self.direction
+    }
+
+    pub fn get_amount(&self) -> u64 {
+        self.amount
+    }
+
+    /// Whom we paid or were paid by. None for fee and golden ticket payouts, which have no
+    /// sender, and for payments to ourselves.
+    pub fn get_counterparty(&self) -> Option<SaitoPublicKey> {
+        self.counterparty
+    }
+}
+
+//
+// A merchant asks to be paid with a URI which any wallet can read, e.g.
+// from a link or a QR code, much like the bitcoin: URIs of BIP 21:
+//
+//   saito:gYsu1fVHjP6Z8CHCzti9K9xb5JPqpEL7zi7arvLiVANm?amount=1.5&message=order%2042
+//
+// The path is the base58 publickey to pay. The amount is in SAITO, with at
+// most 8 decimals, and the message is percent-encoded UTF-8. Parameters we
+// do not know are ignored, unless their names start with "req-", which
+// marks parameters a wallet must understand to pay the request correctly.
+//
+
+/// A request to be paid, which is encoded as a saito: URI.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PaymentRequest {
+    publickey: SaitoPublicKey,
+    amount: Option<Amount>,
+    message: Option<String>,
+}
+
+impl PaymentRequest {
+    pub fn new(publickey: SaitoPublicKey) -> Self {
+        PaymentRequest {
+            publickey,
+            amount: None,
+            message: None,
+        }
+    }
+
+    /// Parses a saito: URI. The scheme is matched ignoring case.
+    pub fn from_uri(uri: &str) -> crate::Result<Self> {
+        let url = Url::parse(uri.trim())?;
+        if url.scheme() != PAYMENT_URI_SCHEME {
+            return Err(format!("{} is not a {}: URI", uri, PAYMENT_URI_SCHEME).into());
+        }
+        let mut payment_request = PaymentRequest::new(publickey_from_base58(url.path())?);
+        for (name, value) in url.query_pairs() {
+            match name.as_ref() {
+                "amount" => {
+                    // only SAITO, so that "1 nolan" is not read as an amount by us alone
+                    if payment_request.amount.is_some()
+                        || !value.chars().all(|c| c.is_ascii_digit() || c == '.')
+                    {
+                        return Err(format!("invalid amount {} in {}", value, uri).into());
+                    }
+                    payment_request.amount = Some(value.parse()?);
+                }
+                "message" => {
+                    if payment_request.message.is_some() {
+                        return Err(format!("more than one message in {}", uri).into());
+                    }
+                    payment_request.message = Some(value.into_owned());
+                }
+                name if name.starts_with("req-") => {
+                    return Err(
+                        format!("unsupported required parameter {} in {}", name, uri).into(),
+                    );
+                }
+                _ => {}
+            }
+        }
+        Ok(payment_request)
+    }
+
+    pub fn to_uri(&self) -> String {
+        let mut query = form_urlencoded::Serializer::new(String::new());
+        if let Some(amount) = self.amount {
+            query.append_pair("amount", &amount.to_saito_string());
+        }
+        if let Some(message) = &self.message {
+            query.append_pair("message", message);
+        }
+        // form encoding turns spaces into +, which not every parser of URIs decodes
+        let query = query.finish().replace('+', "%20");
+        if query.is_empty() {
+            format!("{}:{}", PAYMENT_URI_SCHEME, self.publickey.to_base58())
+        } else {
+            format!(
+                "{}:{}?{}",
+                PAYMENT_URI_SCHEME,
+                self.publickey.to_base58(),
+                query
+            )
+        }
+    }
+
+    /// The URI drawn as a QR code in unicode blocks, two modules to a character, for
+    /// printing to a terminal with a dark background.
+    pub fn to_qr_code(&self) -> crate::Result<String> {
+        let qr_code = QrCode::new(self.to_uri().as_bytes())?;
+        Ok(qr_code
+            .render::<Dense1x2>()
+            .dark_color(Dense1x2::Light)
+            .light_color(Dense1x2::Dark)
+            .build())
+    }
+
+    pub fn get_publickey(&self) -> SaitoPublicKey {
+        self.publickey
+    }
+
+    pub fn get_amount(&self) -> Option<Amount> {
+        self.amount
+    }
+
+    pub fn get_message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+
+    pub fn set_amount(&mut self, amount: Option<Amount>) {
+        self.amount = amount;
+    }
+
+    pub fn set_message(&mut self, message: Option<String>) {
+        self.message = message;
+    }
+}
+
+/// A transaction we created which is not yet in a block. It holds the slips it reserved,
+/// which are marked spent in the wallet, and the amount it pays back to us.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PendingTransaction {
+    slip_keys: Vec<(SaitoHash, u8)>,
+    pending_amount: u64,
+    // loaded with the wallet, rather than created since
+    is_restored: bool,
+}
+
+impl PendingTransaction {
+    pub fn new(slip_keys: Vec<(SaitoHash, u8)>, pending_amount: u64) -> Self {
+        PendingTransaction {
+            slip_keys,
+            pending_amount,
+            is_restored: false,
+        }
+    }
+
+    /// [slip keys count - 4 bytes]
+    /// [pending amount - 8 bytes]
+    /// [slip keys - 33 bytes each, the uuid and slip ordinal]
+    pub fn serialize_for_disk(&self) -> Vec<u8> {
+        let mut vbytes: Vec<u8> = vec![];
+        vbytes.extend(&(self.slip_keys.len() as u32).to_be_bytes());
+        vbytes.extend(&self.pending_amount.to_be_bytes());
+        for (uuid, slip_ordinal) in self.slip_keys.iter() {
+            vbytes.extend(uuid);
+            vbytes.push(*slip_ordinal);
+        }
+        vbytes
+    }
+
+    /// The pending transaction at the start of the bytes and the number of bytes it takes, or
+    /// None if the bytes are cut short. It is marked restored, see
+    /// Wallet::expire_restored_pending_transactions.
+    pub fn deserialize_for_disk(bytes: &[u8]) -> Option<(PendingTransaction, usize)> {
+        let slip_keys_count = u32::from_be_bytes(bytes.get(0..4)?.try_into().ok()?) as usize;
+        let pending_amount = u64::from_be_bytes(bytes.get(4..12)?.try_into().ok()?);
+        let size = 12 + slip_keys_count * 33;
+        let slip_keys = bytes
+            .get(12..size)?
+            .chunks(33)
+            .map(|slip_key| (slip_key[0..32].try_into().unwrap(), slip_key[32]))
+            .collect();
+        Some((
+            PendingTransaction {
+                slip_keys,
+                pending_amount,
+                is_restored: true,
+            },
+            size,
+        ))
+    }
+
+    pub fn get_slip_keys(&self) -> &Vec<(SaitoHash, u8)> {
+        &self.slip_keys
+    }
+
+    pub fn get_pending_amount(&self) -> u64 {
+        self.pending_amount
+    }
+
+    pub fn is_spending(&self, uuid: SaitoHash, slip_ordinal: u8) -> bool {
+        self.slip_keys.contains(&(uuid, slip_ordinal))
+    }
+
+    pub fn is_restored(&self) -> bool {
+        self.is_restored
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
     use super::*;
+    use crate::blockchain::GENESIS_PERIOD;
+    use crate::storage::MemoryBlockStorage;
+    use crate::test_utilities::test_manager::TestManager;
+    use crate::time::create_timestamp;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
 
     #[test]
     fn wallet_new_test() {
         let wallet = Wallet::new();
         assert_ne!(wallet.get_publickey(), [0; 33]);
         assert_ne!(wallet.get_privatekey(), [0; 32]);
-        assert_eq!(wallet.serialize_for_disk().len(), WALLET_SIZE);
+        // no pending transactions and an empty address book
+        assert_eq!(wallet.serialize_for_disk().len(), WALLET_SIZE + 8);
     }
 
     #[test]
@@ -540,9 +1640,710 @@ mod tests {
         assert_ne!(wallet.get_publickey(), publickey1);
         assert_ne!(wallet.get_privatekey(), privatekey1);
 
-        wallet.load();
+        wallet.load().unwrap();
 
         assert_eq!(wallet.get_publickey(), publickey1);
         assert_eq!(wallet.get_privatekey(), privatekey1);
     }
+
+    #[test]
+    fn save_and_restore_wallet_slips_test() {
+        let mut wallet = Wallet::new();
+        wallet.set_filename("wallet_slips_test".to_string());
+
+        let block = Block::new();
+        let mut transaction = Transaction::new();
+        transaction.set_hash_for_signature([1; 32]);
+        for slip_ordinal in 0..3 {
+            let mut slip = Slip::new();
+            slip.set_publickey(wallet.get_publickey());
+            slip.set_amount(Amount::from_nolan(1000));
+            slip.set_slip_ordinal(slip_ordinal);
+            wallet.add_slip(&block, &transaction, &slip, true);
+        }
+        let mut staked_slip = Slip::new();
+        staked_slip.set_amount(Amount::from_nolan(5000));
+        staked_slip.set_slip_type(SlipType::StakerDeposit);
+        wallet.add_slip(&block, &transaction, &staked_slip, true);
+
+        wallet.save();
+
+        // spending marks a slip spent, which is saved with the next save
+        wallet.generate_slips(500);
+        assert_eq!(wallet.get_available_balance(), 2000);
+        let mut unsaved_wallet = Wallet::new();
+        unsaved_wallet.set_filename("wallet_slips_test".to_string());
+        unsaved_wallet.load().unwrap();
+        assert_eq!(unsaved_wallet.get_available_balance(), 3000);
+        wallet.save_if_changed();
+
+        let mut restored_wallet = Wallet::new();
+        restored_wallet.set_filename("wallet_slips_test".to_string());
+        restored_wallet.load().unwrap();
+
+        assert_eq!(restored_wallet.get_publickey(), wallet.get_publickey());
+        assert_eq!(restored_wallet.slips, wallet.slips);
+        assert_eq!(restored_wallet.staked_slips, wallet.staked_slips);
+        assert_eq!(restored_wallet.get_available_balance(), 2000);
+
+        // seeing the same slip again while loading blocks does not unspend it
+        let mut slip = Slip::new();
+        slip.set_publickey(wallet.get_publickey());
+        slip.set_amount(Amount::from_nolan(1000));
+        slip.set_slip_ordinal(0);
+        restored_wallet.add_slip(&block, &transaction, &slip, true);
+        assert_eq!(restored_wallet.slips.len(), 3);
+        assert_eq!(restored_wallet.get_available_balance(), 2000);
+
+        // the slip stays reserved by the pending transaction, which is no longer in the
+        // mempool, until the transaction would have expired from it
+        assert_eq!(restored_wallet.get_pending_transactions().len(), 1);
+        assert_eq!(restored_wallet.get_pending_balance(), 500);
+        assert!(restored_wallet.get_pending_transactions()[0].is_restored());
+        restored_wallet.expire_restored_pending_transactions(10, 5);
+        restored_wallet.expire_restored_pending_transactions(14, 5);
+        assert_eq!(restored_wallet.get_available_balance(), 2000);
+        restored_wallet.expire_restored_pending_transactions(15, 5);
+        assert!(restored_wallet.get_pending_transactions().is_empty());
+        assert_eq!(restored_wallet.get_available_balance(), 3000);
+
+        std::fs::remove_file("data/wallets/wallet_slips_test").unwrap();
+    }
+
+    #[test]
+    fn save_and_restore_wallet_address_book_test() {
+        let mut wallet = Wallet::new();
+        wallet.set_filename("wallet_address_book_test".to_string());
+        wallet.save();
+        wallet.add_address_book_entry("alice", [1; 33]).unwrap();
+        wallet.add_address_book_entry("bob", [2; 33]).unwrap();
+        assert!(wallet.add_address_book_entry("carol", [2; 33]).is_err());
+        assert_eq!(wallet.remove_address_book_entry("bob"), Some([2; 33]));
+
+        // the entries are saved as they change
+        let mut restored_wallet = Wallet::new();
+        restored_wallet.set_filename("wallet_address_book_test".to_string());
+        restored_wallet.load().unwrap();
+        assert_eq!(
+            restored_wallet.get_address_book(),
+            wallet.get_address_book()
+        );
+        assert_eq!(
+            restored_wallet.get_address_book().get_label(&[1; 33]),
+            Some("alice")
+        );
+        assert_eq!(restored_wallet.get_address_book().len(), 1);
+
+        std::fs::remove_file("data/wallets/wallet_address_book_test").unwrap();
+    }
+
+    #[test]
+    fn deserialize_truncated_wallet_test() {
+        let mut wallet = Wallet::new();
+        let block = Block::new();
+        let mut transaction = Transaction::new();
+        transaction.set_hash_for_signature([1; 32]);
+        let mut slip = Slip::new();
+        slip.set_publickey(wallet.get_publickey());
+        slip.set_amount(Amount::from_nolan(1000));
+        wallet.add_slip(&block, &transaction, &slip, true);
+        let vbytes = wallet.serialize_for_disk();
+
+        let mut restored_wallet = Wallet::new();
+        let publickey = restored_wallet.get_publickey();
+        for len in [0, WALLET_KEYS_SIZE - 1, WALLET_SIZE - 1, WALLET_SIZE + 1] {
+            assert!(restored_wallet
+                .deserialize_for_disk(&vbytes[..len])
+                .is_err());
+            assert_eq!(restored_wallet.get_publickey(), publickey);
+        }
+        assert!(WalletSlip::deserialize_for_disk(&vbytes[WALLET_SIZE..WALLET_SIZE + 1]).is_err());
+
+        // a wallet of version 0 holds only the keys
+        restored_wallet
+            .deserialize_for_disk(&vbytes[..WALLET_KEYS_SIZE])
+            .unwrap();
+        assert_eq!(restored_wallet.get_publickey(), wallet.get_publickey());
+    }
+
+    #[test]
+    fn deserialize_version_3_wallet_test() {
+        let mut wallet = Wallet::new();
+        let mut slip = Slip::new();
+        slip.set_publickey(wallet.get_publickey());
+        slip.set_amount(Amount::from_nolan(1000));
+        let mut wallet_slip = WalletSlip::new();
+        wallet_slip.set_utxokey(slip.get_utxoset_key());
+        wallet_slip.set_amount(slip.get_amount());
+        wallet.slips.push(wallet_slip);
+        let mut vbytes = wallet.serialize_for_disk();
+        // version 3 wallets have no slip type in the utxokeys of their slips
+        vbytes.remove(WALLET_SIZE + 32 + UTXOSET_KEY_SIZE - 1);
+        vbytes[65..69].copy_from_slice(&3_u32.to_be_bytes());
+
+        let mut restored_wallet = Wallet::new();
+        restored_wallet.deserialize_for_disk(&vbytes).unwrap();
+        assert_eq!(restored_wallet.slips, wallet.slips);
+    }
+
+    #[test]
+    fn deserialize_version_2_wallet_test() {
+        let mut wallet = Wallet::new();
+        wallet.add_address_book_entry("alice", [1; 33]).unwrap();
+        let mut vbytes = wallet.serialize_for_disk();
+        // version 2 wallets have no pending transactions count
+        vbytes.drain(WALLET_SIZE..WALLET_SIZE + 4);
+        vbytes[65..69].copy_from_slice(&2_u32.to_be_bytes());
+
+        let mut restored_wallet = Wallet::new();
+        restored_wallet.deserialize_for_disk(&vbytes).unwrap();
+        assert_eq!(restored_wallet.get_publickey(), wallet.get_publickey());
+        assert_eq!(
+            restored_wallet.get_address_book(),
+            wallet.get_address_book()
+        );
+    }
+
+    #[test]
+    fn deserialize_version_1_wallet_test() {
+        let mut wallet = Wallet::new();
+        wallet.add_address_book_entry("alice", [1; 33]).unwrap();
+        let mut vbytes = wallet.serialize_for_disk();
+        vbytes.truncate(WALLET_SIZE);
+        vbytes[65..69].copy_from_slice(&1_u32.to_be_bytes());
+
+        let mut restored_wallet = Wallet::new();
+        restored_wallet.deserialize_for_disk(&vbytes).unwrap();
+        assert_eq!(restored_wallet.get_publickey(), wallet.get_publickey());
+        assert!(restored_wallet.get_address_book().is_empty());
+    }
+
+    #[test]
+    fn wallet_transaction_test() {
+        let publickey = [1; 33];
+        let mut block = Block::new();
+        block.set_id(3);
+
+        let mut input = Slip::new();
+        input.set_publickey(publickey);
+        input.set_amount(Amount::from_nolan(1000));
+        let mut payment = Slip::new();
+        payment.set_publickey([2; 33]);
+        payment.set_amount(Amount::from_nolan(600));
+        let mut change = Slip::new();
+        change.set_publickey(publickey);
+        change.set_amount(Amount::from_nolan(300));
+        let mut transaction = Transaction::new();
+        transaction.add_input(input);
+        transaction.add_output(payment);
+        transaction.add_output(change);
+
+        // we pay 600 to [2; 33], and whom we pay sees a payment from us
+        let outgoing = WalletTransaction::new(&block, &transaction, &publickey).unwrap();
+        assert_eq!(outgoing.get_block_id(), 3);
+        assert_eq!(
+            outgoing.get_direction(),
+            WalletTransactionDirection::Outgoing
+        );
+        assert_eq!(outgoing.get_amount(), 600);
+        assert_eq!(outgoing.get_counterparty(), Some([2; 33]));
+        let incoming = WalletTransaction::new(&block, &transaction, &[2; 33]).unwrap();
+        assert_eq!(
+            incoming.get_direction(),
+            WalletTransactionDirection::Incoming
+        );
+        assert_eq!(incoming.get_amount(), 600);
+        assert_eq!(incoming.get_counterparty(), Some(publickey));
+        assert_eq!(WalletTransaction::new(&block, &transaction, &[3; 33]), None);
+    }
+
+    #[test]
+    fn payment_request_test() {
+        let publickey = Wallet::new().get_publickey();
+        let mut payment_request = PaymentRequest::new(publickey);
+        assert_eq!(
+            payment_request.to_uri(),
+            format!("saito:{}", publickey.to_base58())
+        );
+        assert_eq!(
+            PaymentRequest::from_uri(&payment_request.to_uri()).unwrap(),
+            payment_request
+        );
+
+        payment_request.set_amount(Some(Amount::from_nolan(150_000_000)));
+        payment_request.set_message(Some(String::from("order 42 & co")));
+        let uri = payment_request.to_uri();
+        assert_eq!(
+            uri,
+            format!(
+                "saito:{}?amount=1.5&message=order%2042%20%26%20co",
+                publickey.to_base58()
+            )
+        );
+        assert_eq!(PaymentRequest::from_uri(&uri).unwrap(), payment_request);
+        assert!(payment_request.to_qr_code().unwrap().contains('\u{2588}'));
+
+        // the scheme is read ignoring case, and parameters we do not know are ignored
+        let uri = format!("SAITO:{}?amount=2&label=shop", publickey.to_base58());
+        let payment_request = PaymentRequest::from_uri(&uri).unwrap();
+        assert_eq!(payment_request.get_publickey(), publickey);
+        assert_eq!(
+            payment_request.get_amount(),
+            Some(Amount::from_nolan(200_000_000))
+        );
+        assert_eq!(payment_request.get_message(), None);
+
+        for uri in [
+            format!("bitcoin:{}", publickey.to_base58()),
+            String::from("saito:notakey"),
+            format!("saito:{}?amount=1%20nolan", publickey.to_base58()),
+            format!("saito:{}?amount=0.000000001", publickey.to_base58()),
+            format!("saito:{}?amount=1&amount=2", publickey.to_base58()),
+            format!("saito:{}?req-expires=1", publickey.to_base58()),
+        ] {
+            assert!(PaymentRequest::from_uri(&uri).is_err(), "{}", uri);
+        }
+    }
+
+    #[test]
+    fn deserialize_version_0_wallet_test() {
+        let wallet = Wallet::new();
+        let mut vbytes: Vec<u8> = vec![];
+        vbytes.extend(&wallet.get_privatekey());
+        vbytes.extend(&wallet.get_publickey());
+        assert_eq!(vbytes.len(), WALLET_KEYS_SIZE);
+
+        let mut restored_wallet = Wallet::new();
+        restored_wallet.deserialize_for_disk(&vbytes).unwrap();
+        assert_eq!(restored_wallet.get_publickey(), wallet.get_publickey());
+        assert_eq!(restored_wallet.get_privatekey(), wallet.get_privatekey());
+        assert!(restored_wallet.slips.is_empty());
+    }
+
+    #[test]
+    fn wallet_slip_serialize_for_disk_test() {
+        let mut wallet_slip = WalletSlip::new();
+        wallet_slip.set_uuid([3; 32]);
+        wallet_slip.set_utxokey([4; 75]);
+        wallet_slip.set_amount(Amount::from_nolan(123456789));
+        wallet_slip.set_block_id(42);
+        wallet_slip.set_block_hash([5; 32]);
+        wallet_slip.set_lc(false);
+        wallet_slip.set_slip_ordinal(7);
+        wallet_slip.set_spent(true);
+
+        let serialized_wallet_slip = wallet_slip.serialize_for_disk();
+        assert_eq!(serialized_wallet_slip.len(), WALLET_SLIP_SIZE);
+        assert_eq!(
+            WalletSlip::deserialize_for_disk(&serialized_wallet_slip).unwrap(),
+            wallet_slip
+        );
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn wallet_rescan_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        test_manager.generate_blockchain(3, [0; 32]).await;
+
+        let blockchain = blockchain_lock.read().await;
+        let mut wallet = wallet_lock.write().await;
+        let balance = wallet.get_available_balance();
+        let slips = wallet.slips.clone();
+        assert!(balance > 0);
+
+        // losing track of our slips is repaired by a full rescan
+        wallet.slips.clear();
+        wallet.staked_slips.clear();
+        assert_eq!(wallet.get_available_balance(), 0);
+        wallet.rescan(&blockchain, 0).await.unwrap();
+        assert_eq!(wallet.get_available_balance(), balance);
+        assert_eq!(wallet.slips, slips);
+
+        // slips reserved by a transaction in the mempool stay spent, whether or not the
+        // blocks holding them are replayed
+        let (inputs, _outputs) = wallet.generate_slips(1);
+        let reserved_balance = wallet.get_available_balance();
+        assert!(reserved_balance < balance);
+        wallet
+            .rescan(&blockchain, blockchain.get_latest_block_id())
+            .await
+            .unwrap();
+        assert_eq!(wallet.get_available_balance(), reserved_balance);
+        wallet.rescan(&blockchain, 0).await.unwrap();
+        assert_eq!(wallet.get_available_balance(), reserved_balance);
+        assert_eq!(wallet.get_pending_transactions().len(), 1);
+
+        // slips marked spent by a transaction which is no longer pending are freed
+        let mut transaction = Transaction::new();
+        transaction.set_inputs(inputs);
+        wallet.on_transaction_expired(&transaction);
+        wallet
+            .slips
+            .iter_mut()
+            .for_each(|slip| slip.set_spent(true));
+        wallet
+            .rescan(&blockchain, blockchain.get_latest_block_id())
+            .await
+            .unwrap();
+        assert_eq!(wallet.get_available_balance(), balance);
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn wallet_rescan_missing_block_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        test_manager.generate_blockchain(3, [0; 32]).await;
+
+        //
+        // the second block is pruned in memory and cannot be loaded from storage
+        //
+        let mut blockchain = blockchain_lock.write().await;
+        let block_hash = blockchain
+            .blockring
+            .get_longest_chain_block_hash_by_block_id(2);
+        let mut block = (**blockchain.blocks.get(&block_hash).unwrap()).clone();
+        block.downgrade_block_to_block_type(BlockType::Pruned).await;
+        blockchain.blocks.insert(block_hash, Arc::new(block));
+        blockchain.set_block_storage(Arc::new(MemoryBlockStorage::new()));
+
+        let mut wallet = wallet_lock.write().await;
+        let balance = wallet.get_available_balance();
+        wallet.slips.clear();
+        let result = wallet.rescan(&blockchain, 0).await;
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("cannot load block"));
+        assert_eq!(wallet.get_available_balance(), 0);
+        assert!(balance > 0);
+        assert!(wallet.get_history(&blockchain).is_err());
+    }
+
+    //
+    // blocks need a transaction, so a second wallet funded in the first block
+    // spends one of its slips in each block the test needs to pass, leaving
+    // the first wallet with only the slips under test
+    //
+    async fn add_filler_block(
+        test_manager: &mut TestManager,
+        timestamp: u64,
+        filler_wallet: &Wallet,
+        filler_slips: &mut Vec<Slip>,
+    ) {
+        let mut transaction = Transaction::new();
+        let input = filler_slips.pop().unwrap();
+        let mut output = Slip::new();
+        output.set_publickey(filler_wallet.get_publickey());
+        output.set_amount(input.get_amount());
+        transaction.add_input(input);
+        transaction.add_output(output);
+        transaction.sign(filler_wallet.get_privatekey());
+        transaction.generate_metadata(filler_wallet.get_publickey());
+        test_manager
+            .add_block(timestamp, 0, 0, true, vec![transaction])
+            .await;
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn wallet_rebroadcast_slip_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        let (publickey, privatekey) = {
+            let wallet = wallet_lock.read().await;
+            (wallet.get_publickey(), wallet.get_privatekey())
+        };
+        let mut timestamp = create_timestamp();
+
+        let filler_wallet = Wallet::new();
+        let mut vip_outputs = vec![(publickey, 100 * ATR_FEE)];
+        vip_outputs.extend(vec![
+            (filler_wallet.get_publickey(), 1);
+            3 * GENESIS_PERIOD as usize
+        ]);
+        let vip_transaction = {
+            let wallet = wallet_lock.read().await;
+            let blockchain = blockchain_lock.read().await;
+            let mut transaction = blockchain
+                .get_vip_policy()
+                .create_vip_transaction(&wallet, &vip_outputs);
+            transaction.generate_metadata(publickey);
+            transaction
+        };
+        let mut filler_slips: Vec<Slip> = vip_transaction.get_outputs()[1..]
+            .iter()
+            .map(|output| {
+                let mut slip = output.clone();
+                slip.set_slip_type(SlipType::Normal);
+                slip
+            })
+            .collect();
+        test_manager
+            .add_block(timestamp, 0, 0, false, vec![vip_transaction])
+            .await;
+        for _ in 2..GENESIS_PERIOD - 1 {
+            timestamp += 120000;
+            add_filler_block(
+                &mut test_manager,
+                timestamp,
+                &filler_wallet,
+                &mut filler_slips,
+            )
+            .await;
+        }
+
+        //
+        // block 9 pays us a payment and change, which block 11 rebroadcasts
+        //
+        let mut transaction =
+            Transaction::generate_transaction(wallet_lock.clone(), publickey, 30 * ATR_FEE, 0)
+                .await;
+        transaction.sign(privatekey);
+        transaction.generate_metadata(publickey);
+        let slip_uuid = transaction.get_hash_for_signature().unwrap();
+        timestamp += 120000;
+        let payment_block_hash = test_manager
+            .add_block(timestamp, 0, 0, false, vec![transaction])
+            .await;
+        let original_utxokeys: Vec<SaitoUTXOSetKey> = wallet_lock
+            .read()
+            .await
+            .slips
+            .iter()
+            .map(|slip| *slip.get_utxokey())
+            .collect();
+        assert_eq!(original_utxokeys.len(), 2);
+
+        for _ in 0..2 {
+            timestamp += 120000;
+            add_filler_block(
+                &mut test_manager,
+                timestamp,
+                &filler_wallet,
+                &mut filler_slips,
+            )
+            .await;
+        }
+        {
+            let wallet = wallet_lock.read().await;
+            let blockchain = blockchain_lock.read().await;
+            assert_eq!(blockchain.get_latest_block_id(), GENESIS_PERIOD + 1);
+            assert_eq!(wallet.slips.len(), 2);
+            assert_eq!(wallet.get_available_balance(), 98 * ATR_FEE);
+            for slip in wallet.slips.iter() {
+                assert_eq!(slip.get_uuid(), slip_uuid);
+                assert!(!original_utxokeys.contains(slip.get_utxokey()));
+                assert_eq!(blockchain.utxoset.get(slip.get_utxokey()), Some(&1));
+            }
+        }
+
+        //
+        // unwinding the rebroadcasting block brings the originals back
+        //
+        {
+            let mut wallet = wallet_lock.write().await;
+            let blockchain = blockchain_lock.read().await;
+            let block = blockchain.get_latest_block().unwrap();
+            wallet.on_chain_reorganization(block, false);
+            assert_eq!(wallet.get_available_balance(), 100 * ATR_FEE);
+            let mut utxokeys: Vec<SaitoUTXOSetKey> = wallet
+                .slips
+                .iter()
+                .map(|slip| *slip.get_utxokey())
+                .collect();
+            utxokeys.sort();
+            let mut expected_utxokeys = original_utxokeys.clone();
+            expected_utxokeys.sort();
+            assert_eq!(utxokeys, expected_utxokeys);
+            wallet.on_chain_reorganization(block, true);
+            assert_eq!(wallet.get_available_balance(), 98 * ATR_FEE);
+        }
+
+        //
+        // the slips are rebroadcast again every other block, and stay in the
+        // wallet when block 9, which created them, is purged
+        //
+        while blockchain_lock.read().await.get_latest_block_id() < 3 * GENESIS_PERIOD - 1 {
+            timestamp += 120000;
+            add_filler_block(
+                &mut test_manager,
+                timestamp,
+                &filler_wallet,
+                &mut filler_slips,
+            )
+            .await;
+        }
+        {
+            let mut wallet = wallet_lock.write().await;
+            let blockchain = blockchain_lock.read().await;
+            wallet.delete_block(blockchain.get_block_sync(&payment_block_hash).unwrap());
+            assert_eq!(wallet.slips.len(), 2);
+            assert_eq!(wallet.get_available_balance(), 80 * ATR_FEE);
+            for slip in wallet.slips.iter() {
+                assert_eq!(slip.get_uuid(), slip_uuid);
+                assert_eq!(blockchain.utxoset.get(slip.get_utxokey()), Some(&1));
+            }
+        }
+
+        //
+        // and can be spent
+        //
+        let mut transaction =
+            Transaction::generate_transaction(wallet_lock.clone(), [1; 33], 70 * ATR_FEE, 0).await;
+        transaction.sign(privatekey);
+        transaction.generate_metadata(publickey);
+        {
+            let blockchain = blockchain_lock.read().await;
+            assert_eq!(transaction.get_inputs().len(), 2);
+            for input in transaction.get_inputs() {
+                assert!(input.validate(&blockchain.utxoset));
+            }
+        }
+        timestamp += 120000;
+        test_manager
+            .add_block(timestamp, 0, 0, false, vec![transaction])
+            .await;
+        let wallet = wallet_lock.read().await;
+        assert_eq!(wallet.get_available_balance(), 10 * ATR_FEE);
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn wallet_payment_transaction_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        test_manager.generate_blockchain(1, [0; 32]).await;
+
+        let mut wallet = wallet_lock.write().await;
+        let payments = [([1; 33], 1000), ([2; 33], 2000), ([3; 33], 3000)];
+        let total_nolan = |slips: &[Slip]| {
+            slips
+                .iter()
+                .map(|slip| u64::from(slip.get_amount()))
+                .sum::<u64>()
+        };
+
+        let transaction = wallet
+            .create_payment_transaction(&payments, 10, FeePayer::Sender)
+            .unwrap();
+        let outputs = transaction.get_outputs();
+        assert_eq!(outputs.len(), 4);
+        for (output, (publickey, amount)) in outputs.iter().zip(payments.iter()) {
+            assert_eq!(output.get_publickey(), *publickey);
+            assert_eq!(output.get_amount(), Amount::from(*amount));
+        }
+        assert_eq!(outputs[3].get_publickey(), wallet.get_publickey());
+        assert_eq!(
+            total_nolan(transaction.get_inputs()) - total_nolan(outputs),
+            10
+        );
+
+        // 10 nolan split three ways, the first recipient paying the odd nolan
+        let transaction = wallet
+            .create_payment_transaction(&payments, 10, FeePayer::Recipients)
+            .unwrap();
+        let outputs = transaction.get_outputs();
+        assert_eq!(outputs[0].get_amount(), Amount::from_nolan(996));
+        assert_eq!(outputs[1].get_amount(), Amount::from_nolan(1997));
+        assert_eq!(outputs[2].get_amount(), Amount::from_nolan(2997));
+        assert_eq!(
+            total_nolan(transaction.get_inputs()) - total_nolan(outputs),
+            10
+        );
+
+        assert!(wallet
+            .create_payment_transaction(&[], 10, FeePayer::Sender)
+            .is_err());
+        assert!(wallet
+            .create_payment_transaction(&[([1; 33], 1)], 2, FeePayer::Recipients)
+            .is_err());
+        assert!(wallet
+            .create_payment_transaction(
+                &vec![([1; 33], 1); MAX_TRANSACTION_OUTPUTS],
+                0,
+                FeePayer::Sender
+            )
+            .is_err());
+        let balance = wallet.get_available_balance();
+        assert!(wallet
+            .create_payment_transaction(&[([1; 33], balance)], 1, FeePayer::Sender)
+            .is_err());
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn wallet_pending_transaction_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        let block_hash = test_manager.generate_blockchain(1, [0; 32]).await;
+
+        let (balance, slip_amount) = {
+            let wallet = wallet_lock.read().await;
+            assert_eq!(wallet.get_pending_balance(), 0);
+            (
+                wallet.get_available_balance(),
+                u64::from(wallet.slips[0].get_amount()),
+            )
+        };
+
+        //
+        // spending one slip reserves it and leaves its change pending
+        //
+        let mut transaction =
+            Transaction::generate_transaction(wallet_lock.clone(), [1; 33], 1000, 0).await;
+        {
+            let wallet = wallet_lock.read().await;
+            assert_eq!(wallet.get_pending_transactions().len(), 1);
+            assert_eq!(wallet.get_available_balance(), balance - slip_amount);
+            assert_eq!(wallet.get_pending_balance(), slip_amount - 1000);
+        }
+
+        //
+        // the reserved slip is not used again by the next transaction
+        //
+        let second_transaction =
+            Transaction::generate_transaction(wallet_lock.clone(), [1; 33], 1000, 0).await;
+        let first_input = &transaction.get_inputs()[0];
+        let second_input = &second_transaction.get_inputs()[0];
+        assert_ne!(
+            (second_input.get_uuid(), second_input.get_slip_ordinal()),
+            (first_input.get_uuid(), first_input.get_slip_ordinal())
+        );
+
+        //
+        // once the first transaction is in a block its change is available
+        //
+        let privatekey = wallet_lock.read().await.get_privatekey();
+        transaction.sign(privatekey);
+        let block = test_manager
+            .generate_block(
+                block_hash,
+                create_timestamp() + 120000,
+                0,
+                0,
+                false,
+                vec![transaction],
+            )
+            .await;
+        wallet_lock
+            .write()
+            .await
+            .on_chain_reorganization(&block, true);
+
+        let wallet = wallet_lock.read().await;
+        assert_eq!(wallet.get_pending_transactions().len(), 1);
+        assert_eq!(wallet.get_pending_balance(), slip_amount - 1000);
+        assert_eq!(
+            wallet.get_available_balance(),
+            balance - 2 * slip_amount + (slip_amount - 1000)
+        );
+    }
 }
@@ -0,0 +1,176 @@
+use crate::crypto::SaitoPublicKey;
+use crate::wallet::Wallet;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+/// How often the wallets which changed are saved, in seconds.
+pub const WALLET_SAVE_INTERVAL: u64 = 5;
+
+/// The jobs a wallet can be given in a node. Payouts are made to the publickey which did
+/// the work, so the role a wallet holds decides which of the node's keys gets paid for it.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum WalletRole {
+    /// signs the blocks we produce and receives the block producer's share of the payout.
+    /// A block is only credited with the routing work of transactions routed to the key
+    /// which signs it, so it is always held together with the Routing role.
+    BlockProducer,
+    /// our identity on the network. Handshakes and routing hops are signed with it, so
+    /// routing payouts are made to it.
+    Routing,
+    /// creates staking deposits and so receives staking payouts.
+    Staking,
+    /// solves golden tickets and receives mining payouts.
+    Mining,
+}
+
+const WALLET_ROLES: [WalletRole; 4] = [
+    WalletRole::BlockProducer,
+    WalletRole::Routing,
+    WalletRole::Staking,
+    WalletRole::Mining,
+];
+
+/// The `WalletManager` holds every wallet loaded by the node and knows which of them
+/// performs each `WalletRole`. The first wallet holds every role which has not been
+/// given to another wallet.
+#[derive(Clone, Debug)]
+pub struct WalletManager {
+    wallet_locks: Vec<Arc<RwLock<Wallet>>>,
+    roles: HashMap<WalletRole, usize>,
+}
+
+impl WalletManager {
+    pub fn new(wallet_lock: Arc<RwLock<Wallet>>) -> WalletManager {
+        WalletManager {
+            wallet_locks: vec![wallet_lock],
+            roles: WALLET_ROLES.iter().map(|role| (*role, 0)).collect(),
+        }
+    }
+
+    /// Adds a wallet and moves the given roles to it. Fails if it would be given only one of
+    /// the BlockProducer and Routing roles.
+    pub fn add_wallet(
+        &mut self,
+        wallet_lock: Arc<RwLock<Wallet>>,
+        roles: &[WalletRole],
+    ) -> crate::Result<()> {
+        if roles.contains(&WalletRole::BlockProducer) != roles.contains(&WalletRole::Routing) {
+            return Err(
+                "the blockproducer and routing roles must be given to the same wallet".into(),
+            );
+        }
+        self.wallet_locks.push(wallet_lock);
+        for role in roles {
+            self.roles.insert(*role, self.wallet_locks.len() - 1);
+        }
+        Ok(())
+    }
+
+    pub fn get_wallet_lock(&self, role: WalletRole) -> Arc<RwLock<Wallet>> {
+        self.wallet_locks[self.roles[&role]].clone()
+    }
+
+    pub fn get_wallet_locks(&self) -> &Vec<Arc<RwLock<Wallet>>> {
+        &self.wallet_locks
+    }
+
+    pub async fn get_publickey(&self, role: WalletRole) -> SaitoPublicKey {
+        self.get_wallet_lock(role).read().await.get_publickey()
+    }
+
+    /// Periodically saves the wallets which changed since they were last saved. Their slips
+    /// change with every block, and saving them then would encrypt and write them out while
+    /// the blockchain waits on their locks.
+    pub fn start_saving(&self) {
+        let wallet_manager = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(WALLET_SAVE_INTERVAL));
+            loop {
+                interval.tick().await;
+                wallet_manager.save_if_changed().await;
+            }
+        });
+    }
+
+    /// Saves the wallets which changed since they were last saved.
+    pub async fn save_if_changed(&self) {
+        for wallet_lock in self.wallet_locks.iter() {
+            wallet_lock.write().await.save_if_changed();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn wallet_manager_roles_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let publickey = wallet_lock.read().await.get_publickey();
+        let mut wallet_manager = WalletManager::new(wallet_lock.clone());
+
+        for role in WALLET_ROLES {
+            assert_eq!(wallet_manager.get_publickey(role).await, publickey);
+        }
+
+        let producer_wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let producer_publickey = producer_wallet_lock.read().await.get_publickey();
+        wallet_manager
+            .add_wallet(
+                producer_wallet_lock,
+                &[WalletRole::BlockProducer, WalletRole::Routing],
+            )
+            .unwrap();
+
+        assert_eq!(wallet_manager.get_wallet_locks().len(), 2);
+        assert_eq!(
+            wallet_manager
+                .get_publickey(WalletRole::BlockProducer)
+                .await,
+            producer_publickey
+        );
+        assert_eq!(
+            wallet_manager.get_publickey(WalletRole::Routing).await,
+            producer_publickey
+        );
+        assert_eq!(
+            wallet_manager.get_publickey(WalletRole::Staking).await,
+            publickey
+        );
+        assert_eq!(
+            wallet_manager.get_publickey(WalletRole::Mining).await,
+            publickey
+        );
+    }
+
+    #[tokio::test]
+    async fn wallet_manager_split_roles_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let publickey = wallet_lock.read().await.get_publickey();
+        let mut wallet_manager = WalletManager::new(wallet_lock);
+
+        //
+        // blocks signed by one key would not be credited with the work routed to another
+        //
+        for roles in [[WalletRole::BlockProducer], [WalletRole::Routing]] {
+            assert!(wallet_manager
+                .add_wallet(Arc::new(RwLock::new(Wallet::new())), &roles)
+                .is_err());
+        }
+        assert_eq!(wallet_manager.get_wallet_locks().len(), 1);
+        assert_eq!(
+            wallet_manager
+                .get_publickey(WalletRole::BlockProducer)
+                .await,
+            publickey
+        );
+        assert_eq!(
+            wallet_manager.get_publickey(WalletRole::Routing).await,
+            publickey
+        );
+    }
+}
@@ -0,0 +1,408 @@
+use crate::amount::Amount;
+use crate::block::Block;
+use crate::configuration::WebhookSetting;
+use crate::crypto::{publickey_from_base58, SaitoPublicKey};
+use crate::transaction::Transaction;
+use ahash::AHashSet;
+use base58::ToBase58;
+use log::{error, info};
+use serde::Serialize;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::sleep;
+
+//
+// Webhooks let an operator learn about payments to and from a set of
+// addresses, e.g. deposits to an exchange, without running an indexer. The
+// blockchain hands every block it winds or unwinds to the webhooks, which
+// queue an event for each transaction touching a watched address, and a
+// background task POSTs the events as JSON in the order they were queued.
+//
+
+/// How often the delivery of an event is attempted before it is dropped.
+pub const WEBHOOK_DELIVERY_ATTEMPTS: u32 = 5;
+/// The wait before the first retry of a failed delivery, doubled for each retry after it.
+pub const WEBHOOK_RETRY_DELAY_MS: u64 = 1000;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEventType {
+    /// the transaction is in a block which was added to the longest chain
+    Confirmed,
+    /// the block holding the transaction was removed from the longest chain
+    ReorgedOut,
+}
+
+/// What a transaction did for one watched address, in nolan.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct WebhookAddressActivity {
+    pub address: String,
+    pub received: u64,
+    pub sent: u64,
+}
+
+/// The JSON body POSTed for a transaction touching watched addresses. Hashes are hex
+/// encoded and addresses base58 encoded.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct WebhookEvent {
+    pub event: WebhookEventType,
+    pub block_hash: String,
+    pub block_id: u64,
+    pub transaction_hash: String,
+    pub addresses: Vec<WebhookAddressActivity>,
+}
+
+/// An event waiting to be POSTed to the url of its webhook.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WebhookDelivery {
+    pub url: String,
+    pub event: WebhookEvent,
+}
+
+#[derive(Debug, Clone)]
+struct Webhook {
+    url: String,
+    publickeys: AHashSet<SaitoPublicKey>,
+}
+
+/// The webhooks registered in the configuration, see WebhookSetting.
+#[derive(Debug, Default)]
+pub struct Webhooks {
+    webhooks: Vec<Webhook>,
+    delivery_sender: Option<mpsc::UnboundedSender<WebhookDelivery>>,
+}
+
+impl Webhooks {
+    pub fn new() -> Self {
+        Webhooks::default()
+    }
+
+    pub fn from_settings(webhook_settings: &[WebhookSetting]) -> crate::Result<Webhooks> {
+        let mut webhooks = Webhooks::new();
+        for webhook_setting in webhook_settings {
+            url::Url::parse(&webhook_setting.url)
+                .map_err(|err| format!("invalid webhook url {}: {}", webhook_setting.url, err))?;
+            webhooks.webhooks.push(Webhook {
+                url: webhook_setting.url.clone(),
+                publickeys: webhook_setting
+                    .addresses
+                    .iter()
+                    .map(|address| publickey_from_base58(address))
+                    .collect::<crate::Result<_>>()?,
+            });
+        }
+        Ok(webhooks)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.webhooks.is_empty()
+    }
+
+    /// Events are queued on the channel once a sender is set, normally by start().
+    pub fn set_delivery_sender(&mut self, delivery_sender: mpsc::UnboundedSender<WebhookDelivery>) {
+        self.delivery_sender = Some(delivery_sender);
+    }
+
+    /// Spawns the task delivering the events, unless no webhooks are registered.
+    pub fn start(&mut self) {
+        if self.is_empty() {
+            return;
+        }
+        let (delivery_sender, delivery_receiver) = mpsc::unbounded_channel();
+        self.set_delivery_sender(delivery_sender);
+        tokio::spawn(run(delivery_receiver));
+        info!("delivering events to {} webhooks", self.webhooks.len());
+    }
+
+    /// Queues the events for the block being added to (or removed from) the longest chain.
+    pub fn on_chain_reorganization(&self, block: &Block, longest_chain: bool) {
+        let delivery_sender = match self.delivery_sender.as_ref() {
+            Some(delivery_sender) => delivery_sender,
+            None => return,
+        };
+        for delivery in self.generate_deliveries(block, longest_chain) {
+            if delivery_sender.send(delivery).is_err() {
+                error!("ERROR 340101: webhook delivery task has stopped");
+                return;
+            }
+        }
+    }
+
+    /// The events of the block for every webhook watching an address its transactions
+    /// pay or spend from, one per transaction and webhook.
+    pub fn generate_deliveries(&self, block: &Block, longest_chain: bool) -> Vec<WebhookDelivery> {
+        let event_type = if longest_chain {
+            WebhookEventType::Confirmed
+        } else {
+            WebhookEventType::ReorgedOut
+        };
+        let mut deliveries = vec![];
+        for transaction in block.get_transactions() {
+            for webhook in self.webhooks.iter() {
+                let addresses = get_address_activity(transaction, &webhook.publickeys);
+                if addresses.is_empty() {
+                    continue;
+                }
+                deliveries.push(WebhookDelivery {
+                    url: webhook.url.clone(),
+                    event: WebhookEvent {
+                        event: event_type,
+                        block_hash: hex::encode(block.get_hash()),
+                        block_id: block.get_id(),
+                        transaction_hash: hex::encode(
+                            transaction.get_hash_for_signature().unwrap_or([0; 32]),
+                        ),
+                        addresses,
+                    },
+                });
+            }
+        }
+        deliveries
+    }
+}
+
+/// The amounts the transaction pays to and spends from each of the publickeys it touches,
+/// in the order the publickeys first appear in its slips.
+fn get_address_activity(
+    transaction: &Transaction,
+    publickeys: &AHashSet<SaitoPublicKey>,
+) -> Vec<WebhookAddressActivity> {
+    let mut activity: Vec<(SaitoPublicKey, u64, u64)> = vec![];
+    let slips = transaction
+        .get_inputs()
+        .iter()
+        .map(|input| (input, false))
+        .chain(
+            transaction
+                .get_outputs()
+                .iter()
+                .map(|output| (output, true)),
+        );
+    for (slip, is_output) in slips {
+        if slip.get_amount() == Amount::ZERO || !publickeys.contains(&slip.get_publickey()) {
+            continue;
+        }
+        let idx = match activity
+            .iter()
+            .position(|(publickey, _, _)| *publickey == slip.get_publickey())
+        {
+            Some(idx) => idx,
+            None => {
+                activity.push((slip.get_publickey(), 0, 0));
+                activity.len() - 1
+            }
+        };
+        if is_output {
+            activity[idx].1 += u64::from(slip.get_amount());
+        } else {
+            activity[idx].2 += u64::from(slip.get_amount());
+        }
+    }
+    activity
+        .into_iter()
+        .map(|(publickey, received, sent)| WebhookAddressActivity {
+            address: publickey.to_base58(),
+            received,
+            sent,
+        })
+        .collect()
+}
+
+/// POSTs the event to the url of its webhook, retrying with a growing delay if the
+/// endpoint cannot be reached or does not answer with a success status.
+pub async fn deliver(client: &reqwest::Client, delivery: &WebhookDelivery) -> bool {
+    let body = serde_json::to_string(&delivery.event).unwrap();
+    for attempt in 0..WEBHOOK_DELIVERY_ATTEMPTS {
+        if attempt > 0 {
+            sleep(Duration::from_millis(
+                WEBHOOK_RETRY_DELAY_MS << (attempt - 1),
+            ))
+            .await;
+        }
+        let response = client
+            .post(&delivery.url)
+            .header("content-type", "application/json")
+            .body(body.clone())
+            .send()
+            .await;
+        match response {
+            Ok(response) if response.status().is_success() => return true,
+            Ok(response) => error!(
+                "ERROR 340102: webhook {} answered {}",
+                delivery.url,
+                response.status()
+            ),
+            Err(err) => error!(
+                "ERROR 340103: cannot reach webhook {}: {:?}",
+                delivery.url, err
+            ),
+        }
+    }
+    error!(
+        "ERROR 340104: dropping {:?} event for transaction {} after {} attempts to reach {}",
+        delivery.event.event,
+        delivery.event.transaction_hash,
+        WEBHOOK_DELIVERY_ATTEMPTS,
+        delivery.url
+    );
+    false
+}
+
+//
+// events are delivered one at a time, so a webhook sees them in the order
+// the chain changed even if its endpoint was briefly down
+//
+pub async fn run(mut delivery_receiver: mpsc::UnboundedReceiver<WebhookDelivery>) {
+    let client = reqwest::Client::new();
+    while let Some(delivery) = delivery_receiver.recv().await {
+        deliver(&client, &delivery).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::Blockchain;
+    use crate::test_utilities::test_manager::TestManager;
+    use crate::time::create_timestamp;
+    use crate::wallet::Wallet;
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::sync::RwLock;
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn webhooks_generate_deliveries_test() {
+        let wallet_lock = Arc::new(RwLock::new(Wallet::new()));
+        let blockchain_lock = Arc::new(RwLock::new(Blockchain::new(wallet_lock.clone())));
+        let mut test_manager = TestManager::new(blockchain_lock.clone(), wallet_lock.clone());
+        let publickey = wallet_lock.read().await.get_publickey();
+        let deposit_address = Wallet::new().get_publickey();
+        test_manager.generate_blockchain(1, [0; 32]).await;
+
+        let mut transaction =
+            Transaction::generate_transaction(wallet_lock.clone(), deposit_address, 1000, 0).await;
+        transaction.sign(wallet_lock.read().await.get_privatekey());
+        transaction.generate_metadata(publickey);
+        let sent: u64 = transaction
+            .get_inputs()
+            .iter()
+            .map(|input| u64::from(input.get_amount()))
+            .sum();
+        let block_hash = test_manager
+            .add_block(create_timestamp() + 120000, 0, 0, false, vec![transaction])
+            .await;
+
+        let webhooks = Webhooks::from_settings(&[
+            WebhookSetting {
+                url: String::from("http://127.0.0.1/deposits"),
+                addresses: vec![deposit_address.to_base58()],
+            },
+            WebhookSetting {
+                url: String::from("http://127.0.0.1/wallet"),
+                addresses: vec![publickey.to_base58(), deposit_address.to_base58()],
+            },
+            WebhookSetting {
+                url: String::from("http://127.0.0.1/other"),
+                addresses: vec![Wallet::new().get_publickey().to_base58()],
+            },
+        ])
+        .unwrap();
+        let blockchain = blockchain_lock.read().await;
+        let block = blockchain.get_block_sync(&block_hash).unwrap();
+
+        let deliveries = webhooks.generate_deliveries(block, true);
+        assert_eq!(deliveries.len(), 2);
+        assert_eq!(deliveries[0].url, "http://127.0.0.1/deposits");
+        assert_eq!(deliveries[0].event.event, WebhookEventType::Confirmed);
+        assert_eq!(deliveries[0].event.block_id, 2);
+        assert_eq!(deliveries[0].event.block_hash, hex::encode(block_hash));
+        assert_eq!(
+            deliveries[0].event.addresses,
+            vec![WebhookAddressActivity {
+                address: deposit_address.to_base58(),
+                received: 1000,
+                sent: 0,
+            }]
+        );
+        assert_eq!(deliveries[1].url, "http://127.0.0.1/wallet");
+        assert_eq!(
+            deliveries[1].event.addresses,
+            vec![
+                WebhookAddressActivity {
+                    address: publickey.to_base58(),
+                    received: sent - 1000,
+                    sent,
+                },
+                WebhookAddressActivity {
+                    address: deposit_address.to_base58(),
+                    received: 1000,
+                    sent: 0,
+                },
+            ]
+        );
+
+        let deliveries = webhooks.generate_deliveries(block, false);
+        assert_eq!(deliveries.len(), 2);
+        assert_eq!(deliveries[0].event.event, WebhookEventType::ReorgedOut);
+
+        assert!(Webhooks::from_settings(&[WebhookSetting {
+            url: String::from("not a url"),
+            addresses: vec![],
+        }])
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn webhooks_deliver_test() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        //
+        // an endpoint which fails the first request and accepts the second
+        //
+        let endpoint = tokio::spawn(async move {
+            let mut bodies = vec![];
+            for status in ["500 Internal Server Error", "200 OK"] {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = vec![];
+                let mut buffer = [0; 1024];
+                while !request.ends_with(b"}") {
+                    let len = stream.read(&mut buffer).await.unwrap();
+                    request.extend(&buffer[..len]);
+                }
+                let request = String::from_utf8(request).unwrap();
+                bodies.push(String::from(request.split("\r\n\r\n").nth(1).unwrap()));
+                stream
+                    .write_all(
+                        format!("HTTP/1.1 {}\r\ncontent-length: 0\r\n\r\n", status).as_bytes(),
+                    )
+                    .await
+                    .unwrap();
+            }
+            bodies
+        });
+
+        let delivery = WebhookDelivery {
+            url: format!("http://127.0.0.1:{}/deposits", port),
+            event: WebhookEvent {
+                event: WebhookEventType::Confirmed,
+                block_hash: hex::encode([1; 32]),
+                block_id: 1,
+                transaction_hash: hex::encode([3; 32]),
+                addresses: vec![WebhookAddressActivity {
+                    address: [2; 33].to_base58(),
+                    received: 10,
+                    sent: 0,
+                }],
+            },
+        };
+        assert!(deliver(&reqwest::Client::new(), &delivery).await);
+
+        let bodies = endpoint.await.unwrap();
+        let expected_body = serde_json::to_string(&delivery.event).unwrap();
+        assert_eq!(bodies, vec![expected_body.clone(), expected_body]);
+        assert!(bodies[0].starts_with("{\"event\":\"confirmed\""));
+    }
+}